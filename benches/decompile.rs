@@ -0,0 +1,219 @@
+//! Decompiling large synthetic chunks, to keep an eye on throughput.
+//!
+//! Run with `cargo bench`. Each chunk is decoded, parsed and written
+//! a few times, and the best time of each stage is reported, with the
+//! number and size of the allocations made along the way.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use lua_decompiler::lua40::{self, Decoder, Parser, Scribe, Timings};
+
+/// Allocator that counts allocations, to spot needless ones in hot paths.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+const RUNS: usize = 5;
+
+/// Opcodes of `lopcodes.h`, as used by the synthetic chunks.
+const CALL: u32 = 2;
+const PUSHINT: u32 = 6;
+const GETLOCAL: u32 = 11;
+const GETGLOBAL: u32 = 12;
+const SETGLOBAL: u32 = 19;
+const ADD: u32 = 23;
+const JMPF: u32 = 39;
+const CLOSURE: u32 = 48;
+const END: u32 = 0;
+
+const MAX_ARG_S: i32 = ((1 << 26) - 1) >> 1;
+#[allow(clippy::excessive_precision)]
+const TEST_NUMBER: f64 = 3.14159265358979323846E8;
+
+fn u(op: u32, u: u32) -> u32 {
+    op | u << 6
+}
+
+fn s(op: u32, s: i32) -> u32 {
+    op | ((s + MAX_ARG_S) as u32) << 6
+}
+
+fn ab(op: u32, a: u32, b: u32) -> u32 {
+    op | b << 6 | a << 15
+}
+
+/// Writer of a little endian chunk with 4 byte ints and `size_t`.
+struct ChunkWriter {
+    buf: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new() -> Self {
+        let mut buf = b"\x1bLua\x40\x01".to_vec();
+        buf.extend_from_slice(&[4, 4, 4, 32, 6, 9, 8]);
+        buf.extend_from_slice(&TEST_NUMBER.to_le_bytes());
+        Self { buf }
+    }
+
+    fn int(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.int(value.len() as u32 + 1);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Function without debug information.
+    fn function(&mut self, params: u32, strings: &[&str], protos: &[Vec<u32>], code: &[u32]) {
+        self.string("@bench.lua");
+        self.int(0);
+        self.int(params);
+        self.buf.push(0);
+        self.int(16);
+        // Locals and lines.
+        self.int(0);
+        self.int(0);
+        self.int(strings.len() as u32);
+        for string in strings {
+            self.string(string);
+        }
+        // Numbers.
+        self.int(0);
+        self.int(protos.len() as u32);
+        for proto in protos {
+            self.function(1, strings, &[], proto);
+        }
+        self.int(code.len() as u32);
+        for word in code {
+            self.int(*word);
+        }
+    }
+}
+
+/// Statements using the globals `x` and `print`, and the parameter of
+/// nested functions, cycling through assignments, calls and conditions.
+fn statements(count: usize, nested: bool) -> Vec<u32> {
+    const X: u32 = 0;
+    const PRINT: u32 = 1;
+    let mut code = vec![];
+    for i in 0..count {
+        let operand = if nested {
+            u(GETLOCAL, 0)
+        } else {
+            s(PUSHINT, i as i32)
+        };
+        match i % 3 {
+            // x = x + i
+            0 => code.extend([u(GETGLOBAL, X), operand, ADD, u(SETGLOBAL, X)]),
+            // print(x, i)
+            1 => code.extend([
+                u(GETGLOBAL, PRINT),
+                u(GETGLOBAL, X),
+                operand,
+                ab(CALL, nested as u32, 0),
+            ]),
+            // if x then print(x) end
+            _ => code.extend([
+                u(GETGLOBAL, X),
+                s(JMPF, 3),
+                u(GETGLOBAL, PRINT),
+                u(GETGLOBAL, X),
+                ab(CALL, nested as u32, 0),
+            ]),
+        }
+    }
+    code
+}
+
+/// Chunk with about `size` bytes of instructions, a tenth of them
+/// in nested functions assigned to globals.
+fn synthetic_chunk(size: usize) -> Vec<u8> {
+    let instructions = size / 4;
+    let functions = instructions / 4000;
+    let mut strings = vec!["x".to_string(), "print".to_string()];
+    strings.extend((0..functions).map(|index| format!("f{index}")));
+    let strings: Vec<_> = strings.iter().map(String::as_str).collect();
+
+    let protos: Vec<_> = (0..functions)
+        .map(|_| {
+            let mut code = statements(100, true);
+            code.push(END);
+            code
+        })
+        .collect();
+    let mut code = statements(instructions * 9 / 10 / 4, false);
+    for index in 0..functions {
+        code.extend([ab(CLOSURE, index as u32, 0), u(SETGLOBAL, 2 + index as u32)]);
+    }
+    code.push(END);
+
+    let mut writer = ChunkWriter::new();
+    writer.function(0, &strings, &protos, &code);
+    writer.buf
+}
+
+/// Allocations made, and bytes allocated, by the closure.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    let result = f();
+    (
+        result,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED.load(Ordering::Relaxed) - allocated,
+    )
+}
+
+fn bench(name: &str, code: &[u8]) {
+    let mut best = Timings::default();
+    let mut best_total = Duration::MAX;
+    for _ in 0..RUNS {
+        let (_, timings) = lua40::decompile_timed(code).expect("failed to decompile");
+        if timings.total() < best_total {
+            best_total = timings.total();
+            best = timings;
+        }
+    }
+
+    let (proto, decode_allocs, decode_bytes) =
+        count_allocations(|| Decoder::new(code).decode().expect("failed to decode"));
+    let (syntax, parse_allocs, parse_bytes) =
+        count_allocations(|| Parser::new(&proto).parse().expect("failed to parse"));
+    let (_, write_allocs, write_bytes) = count_allocations(|| {
+        let mut buf = String::new();
+        Scribe::default()
+            .fmt_syntax(&mut buf, &syntax)
+            .expect("scribe failed");
+    });
+
+    println!("{name}: {} bytes, {best}", code.len());
+    println!(
+        "{name}: allocations: decode {decode_allocs} ({decode_bytes} bytes), \
+         parse {parse_allocs} ({parse_bytes} bytes), write {write_allocs} ({write_bytes} bytes)"
+    );
+}
+
+fn main() {
+    for (name, size) in [("64K", 64 << 10), ("256K", 256 << 10), ("1M", 1 << 20)] {
+        bench(name, &synthetic_chunk(size));
+    }
+}
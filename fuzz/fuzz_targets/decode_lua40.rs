@@ -0,0 +1,18 @@
+//! Decode arbitrary bytes as a Lua 4.0 chunk, which must fail
+//! gracefully instead of panicking or exhausting memory.
+//!
+//! Run with `cargo fuzz run decode_lua40`, seeding the corpus
+//! with the chunks in `tests/fixtures` for a head start.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lua_decompiler::lua40::{Decoder, Limits};
+
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits {
+        max_constants: 1 << 12,
+        max_code: 1 << 16,
+        max_depth: 32,
+    };
+    let _ = Decoder::new(data).with_limits(limits).decode();
+});
@@ -0,0 +1,125 @@
+//! Decoding chunks of any supported Lua version.
+use std::fmt::{self, Formatter};
+
+use crate::errors::{Error, Result};
+use crate::options::{DecompileOptions, Output};
+use crate::trace::{NoTrace, Trace};
+use crate::{lua32, lua40, lua50, lua51};
+
+const ID_CHUNK: u8 = 27;
+const SIGNATURE: &str = "Lua";
+
+/// Lua version of a chunk, as declared by its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LuaVersion {
+    /// Lua 3.1 and 3.2 share a chunk format.
+    Lua32,
+    Lua40,
+    Lua50,
+    Lua51,
+}
+
+/// Top level function of a chunk, tagged with its Lua version.
+#[derive(Debug)]
+pub enum AnyProto {
+    Lua32(lua32::Proto),
+    Lua40(lua40::Proto),
+    Lua50(lua50::Proto),
+    Lua51(lua51::Proto),
+}
+
+/// Detect the Lua version of a chunk from the signature and version byte of its header.
+pub fn detect_version(code: &[u8]) -> Result<LuaVersion> {
+    if code.first() != Some(&ID_CHUNK) {
+        return Error::new_decoder("chunk bytemark must be 'Esc'(27)").into();
+    }
+    if code.get(1..1 + SIGNATURE.len()) != Some(SIGNATURE.as_bytes()) {
+        return Error::new_decoder("bad signature").into();
+    }
+
+    match code.get(1 + SIGNATURE.len()) {
+        Some(0x31 | 0x32) => Ok(LuaVersion::Lua32),
+        Some(0x40) => Ok(LuaVersion::Lua40),
+        Some(0x50) => Ok(LuaVersion::Lua50),
+        Some(0x51) => Ok(LuaVersion::Lua51),
+        Some(version) => {
+            Error::new_decoder(format!("unsupported Lua version: {version:02x}")).into()
+        }
+        None => Error::new_decoder("missing version").into(),
+    }
+}
+
+/// Decode a chunk of any supported Lua version, dispatching
+/// on the version declared in its header.
+pub fn decode_any(code: &[u8]) -> Result<AnyProto> {
+    decode_any_with_trace(code, &NoTrace)
+}
+
+/// Like [decode_any], sending diagnostic events to the given sink.
+pub fn decode_any_with_trace(code: &[u8], trace: &dyn Trace) -> Result<AnyProto> {
+    Ok(match detect_version(code)? {
+        LuaVersion::Lua32 => AnyProto::Lua32(lua32::Decoder::new(code).decode()?),
+        LuaVersion::Lua40 => AnyProto::Lua40(lua40::Decoder::new(code).with_trace(trace).decode()?),
+        LuaVersion::Lua50 => AnyProto::Lua50(lua50::Decoder::new(code).decode()?),
+        LuaVersion::Lua51 => AnyProto::Lua51(lua51::Decoder::new(code).decode()?),
+    })
+}
+
+/// Decompile a chunk of any version that can be decompiled into source,
+/// dispatching on the version declared in its header.
+///
+/// Lua 3.2 and 5.0 chunks can only be disassembled, see [crate::Disassembler].
+pub fn decompile(code: &[u8]) -> Result<String> {
+    match detect_version(code)? {
+        LuaVersion::Lua40 => lua40::decompile(code),
+        LuaVersion::Lua51 => lua51::decompile(code),
+        version @ (LuaVersion::Lua32 | LuaVersion::Lua50) => {
+            Error::new_unsupported(format!("decompiling {version} chunks")).into()
+        }
+    }
+}
+
+/// Decompile a chunk held in memory, for bindings to other languages
+/// like WebAssembly with `wasm-bindgen`.
+///
+/// The same as [decompile], kept as a stable entry point for bindings.
+/// Decompiling never touches the filesystem or prints, so it works on
+/// targets without them, like `wasm32-unknown-unknown`.
+pub fn decompile_bytes(code: &[u8]) -> Result<String> {
+    decompile(code)
+}
+
+/// Like [decompile], tolerating problems as per the options.
+///
+/// Diagnostics are only collected for Lua 4.0 chunks.
+pub fn decompile_with(code: &[u8], options: &DecompileOptions) -> Result<Output> {
+    match detect_version(code)? {
+        LuaVersion::Lua40 => lua40::decompile_with(code, options),
+        LuaVersion::Lua51 => lua51::decompile_with(code, options),
+        version @ (LuaVersion::Lua32 | LuaVersion::Lua50) => {
+            Error::new_unsupported(format!("decompiling {version} chunks")).into()
+        }
+    }
+}
+
+impl AnyProto {
+    pub fn version(&self) -> LuaVersion {
+        match self {
+            AnyProto::Lua32(_) => LuaVersion::Lua32,
+            AnyProto::Lua40(_) => LuaVersion::Lua40,
+            AnyProto::Lua50(_) => LuaVersion::Lua50,
+            AnyProto::Lua51(_) => LuaVersion::Lua51,
+        }
+    }
+}
+
+impl fmt::Display for LuaVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LuaVersion::Lua32 => write!(f, "Lua 3.2"),
+            LuaVersion::Lua40 => write!(f, "Lua 4.0"),
+            LuaVersion::Lua50 => write!(f, "Lua 5.0"),
+            LuaVersion::Lua51 => write!(f, "Lua 5.1"),
+        }
+    }
+}
@@ -1,25 +1,1519 @@
+use std::fmt;
 use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 
-use lua_decompiler::lua40;
+use lua_decompiler::diagnostics::Diagnostics;
+use lua_decompiler::errors::{Error, Location, Result};
+use lua_decompiler::options::Tolerance;
+use lua_decompiler::style::{Indent, LineEnding, NumberFormat, QuoteStyle, ScribeConfig};
+use lua_decompiler::trace::{Level, StderrTrace, Trace};
+use lua_decompiler::{
+    decode_any_with_trace, detect_version, find_chunk_start, lua32, lua40, lua50, lua51,
+    scan_chunks, AnyProto, Disassembler, LuaVersion, VERSION,
+};
 
+/// Decompiler and disassembler for compiled Lua chunks.
+///
+/// Exits with 1 when a file can't be read, decoded or decompiled,
+/// 2 when the arguments are invalid, and 3 when decompiled output
+/// fails validation or compared chunks differ.
 #[derive(Parser, Debug)]
+#[command(name = "luad", version)]
 struct Cli {
+    /// Print progress to stderr. Repeat for more detail.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Decompile a chunk into source.
+    Decompile(Box<DecompileArgs>),
+    /// Print a disassembly listing of a chunk.
+    Disasm(DisasmArgs),
+    /// Print the header of a chunk and a summary of its functions,
+    /// constants and instructions.
+    Info(InfoArgs),
+    /// List the chunks embedded in a file, like in a game archive.
+    Scan(ScanArgs),
+    /// Compare the functions, constants and instructions of two Lua 4.0 chunks,
+    /// like a script before and after a patch.
+    Diff(DiffArgs),
+    /// List the string constants of every function in a Lua 4.0 chunk,
+    /// without decompiling it.
+    Strings(StringsArgs),
+    /// Print the call graph of a Lua 4.0 chunk: the globals, locals and
+    /// functions of the chunk called by each of its functions.
+    Callgraph(CallgraphArgs),
+    /// List where each global is read and written in a Lua 4.0 chunk,
+    /// by function path and instruction.
+    Xrefs(XrefsArgs),
+    /// Inspect the functions of a Lua 4.0 chunk interactively, decoding it once
+    /// and reading commands to list, disassemble or decompile its functions.
+    Inspect(InspectArgs),
+    /// Export the constants, locals or line information of every function
+    /// in a Lua 4.0 chunk as a table, like for a spreadsheet.
+    Export(ExportArgs),
+    /// Report the opcodes used by the Lua 4.0 chunks in a directory,
+    /// and which of them the decompiler supports.
+    Coverage(CoverageArgs),
+}
+
+#[derive(Args, Debug)]
+struct DecompileArgs {
+    /// Chunk to decompile, `-` to read it from stdin,
+    /// or a directory of `.lub` and `.out` chunks to decompile in batch.
+    file: String,
+
+    /// Write the output to this file instead of stdout.
+    /// In batch mode, this is the directory the `.lua` files are written to.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+
+    /// Group local variable declarations at the top of their scope.
+    #[arg(long)]
+    group_locals: bool,
+
+    /// Follow each statement with a comment giving the instructions it was decoded from.
+    #[arg(long)]
+    annotate: bool,
+
+    /// Leave a blank line where statements weren't on adjacent lines in the original source.
+    #[arg(long)]
+    preserve_lines: bool,
+
+    /// Precede statements with a `-- line N` comment where the original line numbers jump.
+    #[arg(long)]
+    line_markers: bool,
+
+    /// Normalize whitespace and leave out annotations and line markers, so
+    /// decompiling different versions of a chunk gives output that diffs cleanly.
+    #[arg(long)]
+    canonical: bool,
+
+    /// Keep going when part of a function can't be decompiled,
+    /// writing its disassembly in a comment instead.
+    /// Shorthand for `--tolerance lenient`.
+    #[arg(long)]
+    lenient: bool,
+
+    /// How to handle problems in the chunk, like unknown opcodes: `strict` fails,
+    /// `lenient` writes the disassembly of what can't be decompiled, and
+    /// `best-effort` also patches over problems where possible.
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        default_value = "strict",
+        value_parser = ["strict", "lenient", "best-effort"]
+    )]
+    tolerance: String,
+
+    /// Rename globals as per this file, with one `old = new` pair of names per line.
+    #[arg(long, value_name = "FILE")]
+    rename_globals: Option<String>,
+
+    /// Name globals, and locals by function path and stack slot, as per
+    /// this JSON or TOML file, to annotate an obfuscated chunk.
+    #[arg(long, value_name = "FILE")]
+    rename_map: Option<String>,
+
+    /// How to make up the names of locals the chunk has no names for:
+    /// `alphabetic` like `a` and `b`, `indexed` like `local_1`, `typed`
+    /// by the type of their value like `str1`, or by stack `slot` like `slot3`.
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        default_value = "alphabetic",
+        value_parser = ["alphabetic", "indexed", "typed", "slot"]
+    )]
+    local_names: String,
+
+    /// Name parameters the chunk has no names for after the arguments of
+    /// the standard library functions they're passed to, like `s` for `strlen`.
+    #[arg(long)]
+    infer_params: bool,
+
+    /// Name parameters after the arguments in this file too, with one
+    /// `function position = name` argument per line. Implies `--infer-params`.
+    #[arg(long, value_name = "FILE")]
+    param_names: Option<String>,
+
+    /// Remove double negations from conditions, like `if not (not x)`.
+    #[arg(long)]
+    simplify: bool,
+
+    /// Flatten concatenations like `(a .. b) .. c` into `a .. b .. c`.
+    #[arg(long)]
+    flatten_concat: bool,
+
+    /// Only decompile this function of a Lua 4.0 chunk, by its path from the
+    /// main function, like `main.3`, as a standalone function definition.
+    #[arg(long, value_name = "PATH", conflicts_with = "validate")]
+    function: Option<String>,
+
+    /// Check arguments of calls to `format` against the format string.
+    #[arg(long)]
+    check_format: bool,
+
+    /// Number of spaces per indentation level.
+    #[arg(long, default_value_t = 4)]
+    indent: u8,
+
+    /// Indent with tabs instead of spaces.
+    #[arg(long)]
+    tabs: bool,
+
+    /// End lines with `\r\n` instead of `\n`.
+    #[arg(long)]
+    crlf: bool,
+
+    /// Leave out the spaces around binary operators.
+    #[arg(long)]
+    compact_operators: bool,
+
+    /// Delimit string literals with single quotes.
+    #[arg(long)]
+    single_quotes: bool,
+
+    /// Write strings that span lines as long strings, `[[...]]`.
+    #[arg(long)]
+    long_strings: bool,
+
+    /// Write numbers like `%.14g`, the way luac does, instead of with the
+    /// fewest digits that read back as the same number.
+    #[arg(long)]
+    luac_numbers: bool,
+
+    /// When to color keywords, strings, numbers and comments in the source
+    /// written to stdout: `auto` colors it when stdout is a terminal and
+    /// `NO_COLOR` isn't set.
+    #[arg(
+        long,
+        value_name = "WHEN",
+        default_value = "auto",
+        value_parser = ["auto", "always", "never"]
+    )]
+    color: String,
+
+    /// Print the syntax tree the parser built instead of source,
+    /// with the instructions each statement was decoded from.
+    #[arg(long)]
+    ast: bool,
+
+    /// Write an HTML page with the source beside the disassembly of a Lua 4.0
+    /// chunk, linking each statement to the instructions it was decoded from.
+    #[arg(long, conflicts_with_all = ["ast", "header"])]
+    html: bool,
+
+    /// How to print errors: `human` readable lines, or `json` with one object
+    /// per line giving the file, function, instruction and opcode of each error.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "human",
+        value_parser = ["human", "json"]
+    )]
+    error_format: String,
+
+    /// Skip up to this many bytes before the chunk's signature, like
+    /// a `#!` line or the header of a distribution format.
+    #[arg(long, value_name = "BYTES")]
+    preamble: Option<usize>,
+
+    /// Print the problems found in each file, like made up variable names,
+    /// and a count of them, after it's decompiled.
+    #[arg(long)]
+    warnings: bool,
+
+    /// Start the output with a comment recording the decompiler version and options.
+    #[arg(long)]
+    header: bool,
+
+    /// Write a JSON manifest describing the decompiler version and options to this file.
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<String>,
+
+    /// Recompile the decompiled source with this Lua 4.0 `luac` and compare
+    /// the bytecode against the input, exiting with an error on mismatch.
+    #[arg(long, value_name = "LUAC")]
+    validate: Option<String>,
+
+    /// Memory map each chunk instead of reading it into memory. Chunks must
+    /// not be changed while they're decompiled, and stdin is always read.
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    #[arg(long)]
+    mmap: bool,
+}
+
+#[derive(Args, Debug)]
+struct DisasmArgs {
+    /// Chunk to disassemble.
+    file: String,
+
+    /// Write the listing to this file instead of stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+
+    /// Only list this function of a Lua 4.0 chunk, and the functions nested
+    /// in it, by its path from the main function, like `main.2.0`.
+    #[arg(long, value_name = "PATH")]
+    function: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct InfoArgs {
+    /// Chunk to describe.
+    file: String,
+
+    /// Print a JSON object instead of a table.
+    #[arg(long, conflicts_with = "hex")]
+    json: bool,
+
+    /// Print the bytes of a Lua 4.0 header with the meaning of each field,
+    /// marking the first malformed field, instead of the summary.
+    #[arg(long)]
+    hex: bool,
+
+    /// Memory map the chunk instead of reading it into memory.
+    /// The chunk must not be changed while it's described.
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    #[arg(long)]
+    mmap: bool,
+}
+
+#[derive(Args, Debug)]
+struct ScanArgs {
+    /// File to search for chunks.
+    file: String,
+
+    /// Print a JSON array instead of one line per chunk.
+    #[arg(long)]
+    json: bool,
+
+    /// Memory map the file instead of reading it into memory,
+    /// for archives too large to read at once.
+    /// The file must not be changed while it's scanned.
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    #[arg(long)]
+    mmap: bool,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// Chunk before the change.
+    old: String,
+
+    /// Chunk after the change.
+    new: String,
+}
+
+#[derive(Args, Debug)]
+struct StringsArgs {
+    /// Chunk to list the constants of.
+    file: String,
+
+    /// List number constants too.
+    #[arg(long)]
+    numbers: bool,
+}
+
+#[derive(Args, Debug)]
+struct CallgraphArgs {
+    /// Chunk to build the call graph of.
+    file: String,
+
+    /// Write the graph to this file instead of stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+
+    /// Print a JSON object instead of a Graphviz DOT graph.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct XrefsArgs {
+    /// Chunk to cross reference.
+    file: String,
+
+    /// Only list references to this global.
+    #[arg(long, value_name = "NAME")]
+    global: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct InspectArgs {
+    /// Chunk to inspect.
     file: String,
 }
 
-fn main() {
-    let args = Cli::parse();
+#[derive(Args, Debug)]
+struct CoverageArgs {
+    /// Directory of `.lub` and `.out` chunks.
+    dir: String,
+
+    /// Print the coverage as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// Chunk to export the tables of.
+    file: String,
+
+    /// Table to export: string and number `constants` with the line each is
+    /// first used on, `locals` with the instructions they're live over,
+    /// or the source `lines` of the instructions.
+    #[arg(long, value_name = "TABLE", value_parser = ["constants", "locals", "lines"])]
+    what: String,
+
+    /// Format of the table.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "csv",
+        value_parser = ["csv"]
+    )]
+    format: String,
+
+    /// Write the table to this file instead of stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+}
+
+impl DecompileArgs {
+    /// Options that affect the output, in a stable order so
+    /// outputs from different runs can be compared.
+    ///
+    /// Values are JSON literals.
+    fn options(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("group_locals", self.group_locals.to_string()),
+            ("annotate", self.annotate.to_string()),
+            ("preserve_lines", self.preserve_lines.to_string()),
+            ("line_markers", self.line_markers.to_string()),
+            ("canonical", self.canonical.to_string()),
+            ("lenient", self.lenient.to_string()),
+            ("tolerance", json_string(&self.tolerance)),
+            (
+                "preamble",
+                match self.preamble {
+                    Some(max_len) => max_len.to_string(),
+                    None => "null".to_string(),
+                },
+            ),
+            (
+                "rename_globals",
+                match &self.rename_globals {
+                    Some(path) => json_string(path),
+                    None => "null".to_string(),
+                },
+            ),
+            (
+                "rename_map",
+                match &self.rename_map {
+                    Some(path) => json_string(path),
+                    None => "null".to_string(),
+                },
+            ),
+            ("local_names", json_string(&self.local_names)),
+            ("infer_params", self.infer_params.to_string()),
+            (
+                "param_names",
+                match &self.param_names {
+                    Some(path) => json_string(path),
+                    None => "null".to_string(),
+                },
+            ),
+            ("simplify", self.simplify.to_string()),
+            ("flatten_concat", self.flatten_concat.to_string()),
+            (
+                "function",
+                match &self.function {
+                    Some(path) => json_string(path),
+                    None => "null".to_string(),
+                },
+            ),
+            ("check_format", self.check_format.to_string()),
+            ("indent", self.indent.to_string()),
+            ("tabs", self.tabs.to_string()),
+            ("crlf", self.crlf.to_string()),
+            ("compact_operators", self.compact_operators.to_string()),
+            ("single_quotes", self.single_quotes.to_string()),
+            ("long_strings", self.long_strings.to_string()),
+            ("luac_numbers", self.luac_numbers.to_string()),
+            ("ast", self.ast.to_string()),
+            ("html", self.html.to_string()),
+        ]
+    }
+
+    /// Options given that only apply to Lua 4.0 chunks, by their flags.
+    fn lua40_options(&self) -> Vec<&'static str> {
+        let options = [
+            ("--group-locals", self.group_locals),
+            ("--annotate", self.annotate),
+            ("--preserve-lines", self.preserve_lines),
+            ("--line-markers", self.line_markers),
+            ("--rename-globals", self.rename_globals.is_some()),
+            ("--rename-map", self.rename_map.is_some()),
+            ("--local-names", self.local_names != "alphabetic"),
+            ("--infer-params", self.infer_params),
+            ("--param-names", self.param_names.is_some()),
+            ("--simplify", self.simplify),
+            ("--flatten-concat", self.flatten_concat),
+            ("--check-format", self.check_format),
+            ("--ast", self.ast),
+            ("--warnings", self.warnings),
+            ("--validate", self.validate.is_some()),
+        ];
+        options
+            .into_iter()
+            .filter(|(_, given)| *given)
+            .map(|(flag, _)| flag)
+            .collect()
+    }
+
+    fn naming(&self) -> Box<dyn lua40::NamingStrategy> {
+        match self.local_names.as_str() {
+            "indexed" => Box::new(lua40::IndexedNames::default()),
+            "typed" => Box::new(lua40::TypedNames::default()),
+            "slot" => Box::new(lua40::SlotNames),
+            _ => Box::new(lua40::AlphabeticNames::default()),
+        }
+    }
+
+    /// Whether to color the source written to stdout.
+    fn color(&self) -> bool {
+        if self.output.is_some() || self.ast || self.html {
+            return false;
+        }
+        match self.color.as_str() {
+            "always" => true,
+            "never" => false,
+            _ => io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    fn tolerance(&self) -> Tolerance {
+        match self.tolerance.as_str() {
+            "best-effort" => Tolerance::BestEffort,
+            "lenient" => Tolerance::Lenient,
+            _ if self.lenient => Tolerance::Lenient,
+            _ => Tolerance::Strict,
+        }
+    }
+
+    /// Names to give globals and locals, read from JSON or TOML by the file's
+    /// extension, along with the global names of `--rename-globals`.
+    fn rename_map(&self) -> Result<lua40::RenameMap> {
+        let mut map = lua40::RenameMap::new();
+        if let Some(path) = &self.rename_map {
+            let text = fs::read_to_string(path)?;
+            let renames = if path.ends_with(".toml") {
+                lua40::RenameMap::from_toml(&text)
+            } else {
+                lua40::RenameMap::from_json(&text)
+            };
+            map = map.merge(renames.map_err(|err| err.with_context(path))?);
+        }
+        if let Some(path) = &self.rename_globals {
+            let text = fs::read_to_string(path)?;
+            let renames = lua40::RenameMap::from_pairs(&text)
+                .map_err(|err| err.with_context(format!("global names {path}")))?;
+            map = map.merge(renames);
+        }
+        Ok(map)
+    }
+
+    /// Argument names that parameters are named after, if they're inferred.
+    fn param_names(&self) -> Result<Option<lua40::ParamNames>> {
+        let library = lua40::ParamNames::library();
+        match &self.param_names {
+            Some(path) => {
+                let text = fs::read_to_string(path)?;
+                let table =
+                    lua40::ParamNames::parse(&text).map_err(|err| err.with_context(path))?;
+                Ok(Some(library.with_table(table)))
+            }
+            None if self.infer_params => Ok(Some(library)),
+            None => Ok(None),
+        }
+    }
+
+    /// Passes to run over Lua 4.0 syntax trees, in the order of the options.
+    fn passes<'a>(&self, trace: &'a dyn Trace) -> Result<lua40::PassManager<'a>> {
+        let mut passes = lua40::PassManager::new().with_trace(trace);
+        if self.simplify {
+            passes = passes.with_pass(lua40::SimplifyConditions);
+        }
+        if self.flatten_concat {
+            passes = passes.with_pass(lua40::FlattenConcat);
+        }
+        Ok(passes)
+    }
+
+    fn scribe_config(&self) -> ScribeConfig {
+        ScribeConfig {
+            indent: if self.tabs {
+                Indent::Tabs
+            } else {
+                Indent::Spaces(self.indent)
+            },
+            line_ending: if self.crlf {
+                LineEnding::CrLf
+            } else {
+                LineEnding::Lf
+            },
+            operator_spaces: !self.compact_operators,
+            quote: if self.single_quotes {
+                QuoteStyle::Single
+            } else {
+                QuoteStyle::Double
+            },
+            long_strings: self.long_strings,
+            number_format: if self.luac_numbers {
+                NumberFormat::Luac
+            } else {
+                NumberFormat::Shortest
+            },
+        }
+    }
+
+    fn fingerprint(&self) -> String {
+        self.options()
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn manifest_json(&self) -> String {
+        let options = self
+            .options()
+            .iter()
+            .map(|(name, value)| format!("\"{name}\": {value}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{{\n  \"decompiler\": \"luad\",\n  \"version\": \"{VERSION}\",\n  \"input\": {},\n  \"options\": {{ {options} }},\n  \"fingerprint\": {}\n}}\n",
+            json_string(&self.file),
+            json_string(&self.fingerprint())
+        )
+    }
+}
+
+/// Quote a string for JSON output.
+fn json_string(value: &str) -> String {
+    let mut buf = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            c if c.is_control() => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+/// File argument that reads the chunk from stdin, for use in pipelines.
+const STDIN: &str = "-";
+
+/// Extensions of compiled chunks decompiled in batch mode.
+const CHUNK_EXTENSIONS: &[&str] = &["lub", "out"];
 
-    let code = fs::read(args.file).expect("failed to read file");
-    let mut decoder = lua40::Decoder::new(&code);
-    // TODO: Should decode return a chunk (with header info)?
-    let main_proto = decoder.decode().expect("failed to decode");
-    let mut parser = lua40::Parser::new(&main_proto);
-    let syntax = parser.parse().expect("failed to parse");
-    let mut scribe = lua40::Scribe::new();
+/// Exit code when a file can't be read, decoded or decompiled.
+const EXIT_FAILURE: u8 = 1;
+
+/// Exit code when decompiled output doesn't compile to the same bytecode,
+/// or compared chunks differ.
+const EXIT_MISMATCH: u8 = 3;
+
+/// Result of a command, failing with its exit code once the error has been reported.
+type Outcome = std::result::Result<(), u8>;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Warnings are printed with the rest of the diagnostics instead.
+    let quiet = matches!(&cli.command, Command::Decompile(args) if args.warnings);
+    let trace = StderrTrace::new(match cli.verbose {
+        0 if quiet => Level::Error,
+        0 => Level::Warn,
+        1 => Level::Info,
+        2 => Level::Debug,
+        _ => Level::Trace,
+    });
+
+    let result = match &cli.command {
+        Command::Decompile(args) => decompile(args, &trace),
+        Command::Disasm(args) => disasm(args, &trace),
+        Command::Info(args) => info(args, &trace),
+        Command::Scan(args) => scan(args),
+        Command::Diff(args) => diff(args, &trace),
+        Command::Strings(args) => strings(args, &trace),
+        Command::Callgraph(args) => callgraph(args, &trace),
+        Command::Xrefs(args) => xrefs(args, &trace),
+        Command::Inspect(args) => inspect(args, &trace),
+        Command::Export(args) => export(args, &trace),
+        Command::Coverage(args) => coverage(args, &trace),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => ExitCode::from(code),
+    }
+}
+
+/// Report an error about a file, returning the failure exit code.
+fn fail(path: impl AsRef<Path>, err: impl fmt::Display) -> u8 {
+    eprintln!("error: {}: {err}", path.as_ref().display());
+    EXIT_FAILURE
+}
+
+/// Report an error about a decompiled file in the format given by
+/// `--error-format`, returning the failure exit code.
+fn fail_decompile(path: &Path, err: &Error, args: &DecompileArgs) -> u8 {
+    if args.error_format != "json" {
+        return fail(path, err);
+    }
+    eprintln!(
+        "{}",
+        error_json(path, &err.kind().to_string(), err.location())
+    );
+    EXIT_FAILURE
+}
+
+/// Error as a JSON object on one line, with the instruction numbered
+/// from 1 like a disassembly listing.
+fn error_json(path: &Path, message: &str, location: Option<&Location>) -> String {
+    let (function, instruction, opcode) = match location {
+        Some(location) => (
+            json_string(&lua40::ProtoPath::from(location.path.clone()).to_string()),
+            location
+                .offset
+                .map(|offset| (offset + 1).to_string())
+                .unwrap_or_else(|| "null".to_string()),
+            location
+                .opcode
+                .map(json_string)
+                .unwrap_or_else(|| "null".to_string()),
+        ),
+        None => ("null".to_string(), "null".to_string(), "null".to_string()),
+    };
+    format!(
+        "{{\"file\": {}, \"function\": {function}, \"instruction\": {instruction}, \"opcode\": {opcode}, \"message\": {}}}",
+        json_string(&path.display().to_string()),
+        json_string(message)
+    )
+}
+
+/// Write to the output file when given, otherwise stdout.
+fn write_output(output: Option<&str>, buf: &str) -> Outcome {
+    match output {
+        Some(output) => fs::write(output, buf).map_err(|err| fail(output, err)),
+        // A pipeline closing stdout early, like `luad decompile - | head`, isn't an error.
+        None => match io::stdout().lock().write_all(buf.as_bytes()) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(fail("stdout", err)),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Memory map a chunk.
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+fn map_file(path: impl AsRef<Path>) -> Result<lua_decompiler::mmap::Mmap> {
+    // SAFETY: Changing a chunk while it's mapped is documented
+    // as unsupported by each command's `--mmap` option.
+    unsafe { lua_decompiler::mmap::Mmap::open(path) }
+}
+
+/// Read a chunk from the file, or from stdin when the path is `-`.
+fn read_input(path: &Path) -> io::Result<Vec<u8>> {
+    if path.as_os_str() == STDIN {
+        let mut data = vec![];
+        io::stdin().lock().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        fs::read(path)
+    }
+}
+
+fn decompile(args: &DecompileArgs, trace: &dyn Trace) -> Outcome {
+    let input = Path::new(&args.file);
+    let result = if input.is_dir() {
+        decompile_dir(input, args, trace)
+    } else {
+        decompile_single(input, args, trace)
+    };
+
+    if let Some(path) = &args.manifest {
+        fs::write(path, args.manifest_json()).map_err(|err| fail(path, err))?;
+    }
+
+    result
+}
+
+fn disasm(args: &DisasmArgs, trace: &dyn Trace) -> Outcome {
+    let buf = match &args.function {
+        Some(path) => {
+            let path: lua40::ProtoPath = path.parse().map_err(|err| fail(&args.file, err))?;
+            let main_proto = decode_lua40(&args.file, "selecting functions of", trace)?;
+            let proto = main_proto
+                .nested(&path)
+                .ok_or_else(|| fail(&args.file, format!("no function {path}")))?;
+            proto.dump().to_string()
+        }
+        None => {
+            let code = fs::read(&args.file).map_err(|err| fail(&args.file, err))?;
+            let main_proto =
+                decode_any_with_trace(&code, trace).map_err(|err| fail(&args.file, err))?;
+            Disassembler::new(&main_proto).to_string()
+        }
+    };
+    write_output(args.output.as_deref(), &buf)
+}
+
+/// Print the chunk's header, and statistics of its functions when it's Lua 4.0.
+fn info(args: &InfoArgs, trace: &dyn Trace) -> Outcome {
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    if args.mmap {
+        let code = map_file(&args.file).map_err(|err| fail(&args.file, err))?;
+        return info_code(args, &code, trace);
+    }
+    let code = fs::read(&args.file).map_err(|err| fail(&args.file, err))?;
+    info_code(args, &code, trace)
+}
+
+fn info_code(args: &InfoArgs, code: &[u8], trace: &dyn Trace) -> Outcome {
+    if args.hex {
+        return info_hex(&args.file, code);
+    }
+    let (version, header) = read_any_header(code).map_err(|err| fail(&args.file, err))?;
+    let main_proto = decode_any_with_trace(code, trace).map_err(|err| fail(&args.file, err))?;
+    let stats = match &main_proto {
+        AnyProto::Lua40(main_proto) => Some(main_proto.stats()),
+        _ => None,
+    };
+
+    if args.json {
+        let stats = match &stats {
+            Some(stats) => stats_json(stats),
+            None => "null".to_string(),
+        };
+        println!(
+            "{{\"version\": {}, \"header\": {}, \"stats\": {stats}}}",
+            json_string(&version.to_string()),
+            json_string(&header)
+        );
+    } else {
+        println!("{:<14}{version}", "version");
+        println!("{:<14}{header}", "header");
+        if let Some(stats) = &stats {
+            print!("{stats}");
+        }
+    }
+    Ok(())
+}
+
+/// Print the annotated header, failing when a field is malformed.
+///
+/// Chunks that aren't recognizably another version are annotated as Lua 4.0,
+/// since a damaged signature or version is what the listing should show.
+fn info_hex(path: &str, code: &[u8]) -> Outcome {
+    match detect_version(code) {
+        Ok(LuaVersion::Lua40) | Err(_) => {}
+        Ok(version) => {
+            return Err(fail(
+                path,
+                Error::new_unsupported(format!("annotating headers of {version} chunks")),
+            ))
+        }
+    }
+    let dump = lua40::annotate_header(code);
+    print!("{dump}");
+    match dump.malformed() {
+        Some(field) => Err(fail(path, format!("malformed {}", field.name))),
+        None => Ok(()),
+    }
+}
+
+/// Decode the header of a chunk of any version, formatted for display.
+fn read_any_header(code: &[u8]) -> Result<(LuaVersion, String)> {
+    let version = detect_version(code)?;
+    let header = match version {
+        LuaVersion::Lua32 => lua32::read_header(code)?.to_string(),
+        LuaVersion::Lua40 => lua40::read_header(code)?.to_string(),
+        LuaVersion::Lua50 => lua50::read_header(code)?.to_string(),
+        LuaVersion::Lua51 => lua51::read_header(code)?.to_string(),
+    };
+    Ok((version, header))
+}
+
+fn stats_json(stats: &lua40::Stats) -> String {
+    let lines = match stats.lines {
+        Some((first, last)) => format!("[{first}, {last}]"),
+        None => "null".to_string(),
+    };
+    let opcodes = stats
+        .opcodes
+        .iter()
+        .map(|(opcode, count)| format!("{}: {count}", json_string(opcode.name())))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{{\"functions\": {}, \"instructions\": {}, \"strings\": {}, \"numbers\": {}, \"max_stack\": {}, \"depth\": {}, \"lines\": {lines}, \"opcodes\": {{{opcodes}}}}}",
+        stats.functions,
+        stats.instructions,
+        stats.strings,
+        stats.numbers,
+        stats.max_stack,
+        stats.depth
+    )
+}
+
+/// List the byte ranges and versions of the chunks embedded in a file.
+fn scan(args: &ScanArgs) -> Outcome {
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    if args.mmap {
+        let data = map_file(&args.file).map_err(|err| fail(&args.file, err))?;
+        print_chunks(args, &data);
+        return Ok(());
+    }
+    let data = fs::read(&args.file).map_err(|err| fail(&args.file, err))?;
+    print_chunks(args, &data);
+    Ok(())
+}
+
+fn print_chunks(args: &ScanArgs, data: &[u8]) {
+    let chunks = scan_chunks(data);
+
+    if args.json {
+        let items = chunks
+            .iter()
+            .map(|chunk| {
+                format!(
+                    "{{\"start\": {}, \"end\": {}, \"version\": {}}}",
+                    chunk.range.start,
+                    chunk.range.end,
+                    json_string(&chunk.version.to_string())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("[{items}]");
+    } else {
+        for chunk in chunks {
+            let range = &chunk.range;
+            println!(
+                "{:#x}..{:#x}\t{}\t{} bytes",
+                range.start,
+                range.end,
+                chunk.version,
+                range.len()
+            );
+        }
+    }
+}
+
+/// Print the functions that differ between two chunks.
+fn diff(args: &DiffArgs, trace: &dyn Trace) -> Outcome {
+    let old = decode_lua40(&args.old, "comparing", trace)?;
+    let new = decode_lua40(&args.new, "comparing", trace)?;
+    let diff = lua40::diff(&old, &new);
+    print!("{diff}");
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(EXIT_MISMATCH)
+    }
+}
+
+/// Print the constants of each function, one per line, with the
+/// function's path and the constant's index in its pool.
+fn strings(args: &StringsArgs, trace: &dyn Trace) -> Outcome {
+    let main_proto = decode_lua40(&args.file, "listing constants of", trace)?;
+    for pooled in main_proto.constants() {
+        let lua40::PooledConstant {
+            path,
+            index,
+            constant,
+            ..
+        } = pooled;
+        match constant {
+            lua40::Constant::String(string) => println!("{path}\tstring {index}\t{string:?}"),
+            lua40::Constant::Number(number) if args.numbers => {
+                println!("{path}\tnumber {index}\t{number}")
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Decompile the chunk, and print the calls made by each of its functions.
+fn callgraph(args: &CallgraphArgs, trace: &dyn Trace) -> Outcome {
+    let main_proto = decode_lua40(&args.file, "building call graphs of", trace)?;
+    let syntax = lua40::Parser::new(&main_proto)
+        .with_trace(trace)
+        .parse()
+        .map_err(|err| fail(&args.file, err))?;
+    let graph = lua40::call_graph(&syntax);
+    let buf = if args.json {
+        call_graph_json(&graph)
+    } else {
+        graph.dot().to_string()
+    };
+    write_output(args.output.as_deref(), &buf)
+}
+
+fn call_graph_json(graph: &lua40::CallGraph) -> String {
+    let optional = |value: Option<String>| match value {
+        Some(value) => json_string(&value),
+        None => "null".to_string(),
+    };
+    let functions = graph
+        .functions
+        .iter()
+        .map(|node| {
+            format!(
+                "{{\"path\": {}, \"name\": {}}}",
+                json_string(&node.path.to_string()),
+                optional(node.name.clone())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n    ");
+    let calls = graph
+        .calls
+        .iter()
+        .map(|edge| {
+            let (kind, name) = match &edge.callee {
+                lua40::Callee::Global(name) => ("global", Some(name.clone())),
+                lua40::Callee::Local(name) => ("local", Some(name.clone())),
+                lua40::Callee::Upvalue(name) => ("upvalue", Some(name.clone())),
+                lua40::Callee::Dynamic => ("dynamic", None),
+            };
+            format!(
+                "{{\"caller\": {}, \"kind\": \"{kind}\", \"name\": {}, \"target\": {}, \"count\": {}}}",
+                json_string(&edge.caller.to_string()),
+                optional(name),
+                optional(edge.target.as_ref().map(ToString::to_string)),
+                edge.count
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n    ");
+    format!("{{\n  \"functions\": [\n    {functions}\n  ],\n  \"calls\": [\n    {calls}\n  ]\n}}\n")
+}
+
+/// Print where globals are read and written, one reference per line.
+fn xrefs(args: &XrefsArgs, trace: &dyn Trace) -> Outcome {
+    let main_proto = decode_lua40(&args.file, "cross referencing", trace)?;
+    let mut xrefs = main_proto.global_xrefs();
+    if let Some(global) = &args.global {
+        xrefs
+            .globals
+            .retain(|name, _| name.as_bytes() == global.as_bytes());
+    }
+    print!("{xrefs}");
+    Ok(())
+}
+
+fn export(args: &ExportArgs, trace: &dyn Trace) -> Outcome {
+    let main_proto = decode_lua40(&args.file, "exporting", trace)?;
+    let export = match args.what.as_str() {
+        "locals" => lua40::Export::Locals,
+        "lines" => lua40::Export::Lines,
+        _ => lua40::Export::Constants,
+    };
+    let buf = main_proto.export_csv(export).to_string();
+    write_output(args.output.as_deref(), &buf)
+}
+
+/// Count the opcodes used by every chunk in a directory. Chunks that fail to
+/// decode are reported and left out, without stopping the rest.
+fn coverage(args: &CoverageArgs, trace: &dyn Trace) -> Outcome {
+    let dir = Path::new(&args.dir);
+    let mut paths = chunk_paths(dir).map_err(|err| fail(dir, err))?;
+    paths.sort();
+
+    let mut coverage = lua40::Coverage::new();
+    let mut failed = 0;
+    for path in &paths {
+        // Failures are already reported by the decoding.
+        match decode_lua40(&path.to_string_lossy(), "checking coverage of", trace) {
+            Ok(main_proto) => coverage.add(&main_proto),
+            Err(_) => failed += 1,
+        }
+    }
+
+    if args.json {
+        println!("{}", coverage_json(&coverage));
+    } else {
+        print!("{coverage}");
+    }
+    if failed > 0 {
+        eprintln!("failed to decode {failed} of {} files", paths.len());
+        return Err(EXIT_FAILURE);
+    }
+    Ok(())
+}
+
+fn coverage_json(coverage: &lua40::Coverage) -> String {
+    let opcodes = coverage
+        .opcodes
+        .iter()
+        .map(|(opcode, used)| {
+            format!(
+                "{{\"opcode\": {}, \"instructions\": {}, \"chunks\": {}, \"supported\": {}}}",
+                json_string(opcode.name()),
+                used.instructions,
+                used.chunks,
+                opcode.is_supported()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{{\"chunks\": {}, \"supported_chunks\": {}, \"opcodes\": [{opcodes}]}}",
+        coverage.chunks, coverage.supported_chunks
+    )
+}
+
+/// Commands of `luad inspect`, printed by `help`.
+const INSPECT_HELP: &str = "\
+functions            list the functions of the chunk by path
+select PATH          select the function at the path, like main.2
+disasm [PATH]        disassemble the selected function, or the one at the path
+constants [PATH]     list the constants of the function
+decompile [PATH]     decompile the function, leaving what fails in comments
+help                 list the commands
+quit                 stop inspecting
+";
+
+/// Read commands about the chunk's functions from stdin until it ends,
+/// printing a prompt with the selected function when it's a terminal.
+fn inspect(args: &InspectArgs, trace: &dyn Trace) -> Outcome {
+    let main_proto = decode_lua40(&args.file, "inspecting", trace)?;
+    let mut inspector = Inspector {
+        main_proto: &main_proto,
+        selected: lua40::ProtoPath::main(),
+        trace,
+    };
+
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut line = String::new();
+    loop {
+        if interactive {
+            print!("{}> ", inspector.selected);
+            io::stdout().flush().map_err(|err| fail(&args.file, err))?;
+        }
+        line.clear();
+        if stdin
+            .read_line(&mut line)
+            .map_err(|err| fail(&args.file, err))?
+            == 0
+        {
+            break;
+        }
+        match inspector.run(line.trim()) {
+            Ok(Some(output)) => print!("{output}"),
+            Ok(None) => break,
+            Err(err) => eprintln!("error: {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// State of a `luad inspect` session.
+struct Inspector<'a> {
+    main_proto: &'a lua40::Proto,
+    /// Function the commands apply to when they aren't given a path.
+    selected: lua40::ProtoPath,
+    trace: &'a dyn Trace,
+}
+
+impl<'a> Inspector<'a> {
+    /// Run a command, returning its output, or `None` to stop inspecting.
+    fn run(&mut self, line: &str) -> std::result::Result<Option<String>, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(Some(String::new()));
+        };
+        let path = match words.next() {
+            Some(path) => path.parse().map_err(|err: Error| err.to_string())?,
+            None => self.selected.clone(),
+        };
+        if let Some(extra) = words.next() {
+            return Err(format!("unexpected argument {extra:?}"));
+        }
+
+        let output = match command {
+            "functions" | "ls" => self.functions(),
+            "select" | "cd" => {
+                self.proto(&path)?;
+                self.selected = path;
+                String::new()
+            }
+            "disasm" | "dis" => {
+                let proto = self.proto(&path)?;
+                proto.dump_range(0..proto.instrs().len()).to_string()
+            }
+            "constants" | "k" => Self::constants(&path, self.proto(&path)?),
+            "decompile" | "d" => self.decompile(&path).map_err(|err| err.to_string())?,
+            "help" | "?" => INSPECT_HELP.to_string(),
+            "quit" | "exit" | "q" => return Ok(None),
+            _ => return Err(format!("unknown command {command:?}, try help")),
+        };
+        Ok(Some(output))
+    }
+
+    fn proto(&self, path: &lua40::ProtoPath) -> std::result::Result<&'a lua40::Proto, String> {
+        self.main_proto
+            .nested(path)
+            .ok_or_else(|| format!("no function {path}"))
+    }
+
+    /// One line per function, with where it's defined and its size.
+    fn functions(&self) -> String {
+        let mut buf = String::new();
+        for (path, proto) in self.main_proto.iter_protos() {
+            buf.push_str(&format!(
+                "{path}\t{}:{}\t{} instructions\n",
+                proto.source(),
+                proto.line_defined(),
+                proto.instrs().len()
+            ));
+        }
+        buf
+    }
+
+    fn constants(path: &lua40::ProtoPath, proto: &lua40::Proto) -> String {
+        let mut buf = String::new();
+        for (index, string) in proto.strings().iter().enumerate() {
+            buf.push_str(&format!("string {index}\t{string:?}\n"));
+        }
+        for (index, number) in proto.numbers().iter().enumerate() {
+            buf.push_str(&format!("number {index}\t{number}\n"));
+        }
+        for index in 0..proto.protos().len() {
+            buf.push_str(&format!("function {index}\t{}\n", path.join(index)));
+        }
+        buf
+    }
+
+    /// Source of the function, followed by the problems found in it.
+    ///
+    /// Nested functions are decompiled as standalone definitions.
+    fn decompile(&self, path: &lua40::ProtoPath) -> Result<String> {
+        let parser = if path.is_main() {
+            lua40::Parser::new(self.main_proto)
+        } else {
+            lua40::Parser::for_function(self.main_proto, path)?
+        };
+        let mut parser = parser
+            .with_tolerance(Tolerance::Lenient)
+            .with_trace(self.trace);
+        let result = if path.is_main() {
+            parser.parse()
+        } else {
+            parser.parse_standalone()
+        };
+        let mut buf = String::new();
+        lua40::Scribe::default().fmt_syntax(&mut buf, &result?)?;
+        for diagnostic in parser.diagnostics() {
+            buf.push_str(&format!("-- {diagnostic}\n"));
+        }
+        Ok(buf)
+    }
+}
+
+/// Decode a chunk that must be Lua 4.0 for the purpose, like `comparing`.
+fn decode_lua40(
+    path: &str,
+    purpose: &str,
+    trace: &dyn Trace,
+) -> std::result::Result<lua40::Proto, u8> {
+    let code = fs::read(path).map_err(|err| fail(path, err))?;
+    match decode_any_with_trace(&code, trace).map_err(|err| fail(path, err))? {
+        AnyProto::Lua40(proto) => Ok(proto),
+        proto => Err(fail(
+            path,
+            Error::new_unsupported(format!("{purpose} {} chunks", proto.version())),
+        )),
+    }
+}
+
+/// Decompile one file to stdout, or to the output file when given.
+fn decompile_single(path: &Path, args: &DecompileArgs, trace: &dyn Trace) -> Outcome {
+    let (buf, valid) =
+        decompile_file(path, args, trace).map_err(|err| fail_decompile(path, &err, args))?;
+    let buf = if args.color() {
+        highlight(&buf).map_err(|err| fail(path, err))?
+    } else {
+        buf
+    };
+    write_output(args.output.as_deref(), &buf)?;
+    if valid {
+        Ok(())
+    } else {
+        Err(EXIT_MISMATCH)
+    }
+}
+
+/// Color the source for a terminal.
+fn highlight(source: &str) -> Result<String> {
+    let mut highlight = lua40::Highlight::new(String::new());
+    fmt::Write::write_str(&mut highlight, source)?;
+    highlight.finish()
+}
+
+/// Decompile every chunk in a directory, writing each to a `.lua` file of the
+/// same name in the output directory, or beside the chunk when not given.
+///
+/// Failures are reported per file, and don't stop the rest of the batch.
+fn decompile_dir(dir: &Path, args: &DecompileArgs, trace: &dyn Trace) -> Outcome {
+    let mut paths = chunk_paths(dir).map_err(|err| fail_decompile(dir, &err.into(), args))?;
+    paths.sort();
+
+    let output_dir = args.output.as_ref().map(PathBuf::from);
+    if let Some(output_dir) = &output_dir {
+        fs::create_dir_all(output_dir).map_err(|err| fail(output_dir, err))?;
+    }
+
+    let mut passed = 0;
+    let mut code = None;
+    for path in &paths {
+        let output = output_dir
+            .as_deref()
+            .unwrap_or(dir)
+            .join(path.file_name().unwrap_or_default())
+            .with_extension("lua");
+        let result = decompile_file(path, args, trace).and_then(|(buf, valid)| {
+            fs::write(&output, buf)?;
+            Ok(valid)
+        });
+        match result {
+            Ok(true) => passed += 1,
+            Ok(false) => {
+                if args.error_format == "json" {
+                    eprintln!("{}", error_json(path, "failed validation", None));
+                } else {
+                    eprintln!("error: {}: failed validation", path.display());
+                }
+                code.get_or_insert(EXIT_MISMATCH);
+            }
+            Err(err) => code = Some(fail_decompile(path, &err, args)),
+        }
+    }
+    eprintln!("decompiled {passed} of {} files", paths.len());
+
+    match code {
+        Some(code) => Err(code),
+        None => Ok(()),
+    }
+}
+
+/// Files in the directory with a chunk extension.
+fn chunk_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_chunk = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| CHUNK_EXTENSIONS.contains(&ext));
+        if is_chunk && path.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Decompile a file, returning the output and whether it passed validation.
+///
+/// With `--warnings`, the problems found are printed after, even when it fails.
+fn decompile_file(path: &Path, args: &DecompileArgs, trace: &dyn Trace) -> Result<(String, bool)> {
+    let mut diagnostics = Diagnostics::new();
+    let result = decompile_code(path, args, trace, &mut diagnostics);
+    if args.warnings {
+        for diagnostic in &diagnostics {
+            eprintln!("{}: {diagnostic}", path.display());
+        }
+        eprintln!("{}: {}", path.display(), diagnostics.summary());
+    }
+    result
+}
+
+fn decompile_code(
+    path: &Path,
+    args: &DecompileArgs,
+    trace: &dyn Trace,
+    diagnostics: &mut Diagnostics,
+) -> Result<(String, bool)> {
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    if args.mmap && path.as_os_str() != STDIN {
+        let data = map_file(path)?;
+        return decompile_data(&data, args, trace, diagnostics);
+    }
+    let data = read_input(path)?;
+    decompile_data(&data, args, trace, diagnostics)
+}
+
+fn decompile_data(
+    data: &[u8],
+    args: &DecompileArgs,
+    trace: &dyn Trace,
+    diagnostics: &mut Diagnostics,
+) -> Result<(String, bool)> {
+    let code = match args.preamble {
+        Some(max_len) => {
+            let start = find_chunk_start(data, max_len).ok_or_else(|| {
+                Error::new_decoder(format!(
+                    "no chunk signature after skipping up to {max_len} bytes"
+                ))
+            })?;
+            &data[start..]
+        }
+        None => data,
+    };
     let mut buf = String::new();
-    scribe.fmt_syntax(&mut buf, &syntax).expect("scribe failed");
-    println!("output:\n{buf}");
+    if args.header {
+        buf.push_str(&format!("-- decompiled by luad {VERSION}\n"));
+        buf.push_str(&format!("-- options: {}\n", args.fingerprint()));
+    }
+
+    let main_proto = match detect_version(code)? {
+        LuaVersion::Lua40 => {
+            let mut decoder = lua40::Decoder::new(code)
+                .with_trace(trace)
+                .with_tolerance(args.tolerance());
+            let result = decoder.decode();
+            diagnostics.extend(decoder.into_diagnostics());
+            AnyProto::Lua40(result?)
+        }
+        _ => decode_any_with_trace(code, trace)?,
+    };
+    if args.html && !matches!(main_proto, AnyProto::Lua40(_)) {
+        return Error::new_unsupported(format!("HTML output of {} chunks", main_proto.version()))
+            .into();
+    }
+    if args.function.is_some() && !matches!(main_proto, AnyProto::Lua40(_)) {
+        return Error::new_unsupported(format!(
+            "selecting functions of {} chunks",
+            main_proto.version()
+        ))
+        .into();
+    }
+    let mut valid = true;
+    match &main_proto {
+        AnyProto::Lua40(main_proto) => {
+            valid = decompile_lua40(main_proto, args, trace, diagnostics, &mut buf)?
+        }
+        AnyProto::Lua51(main_proto) => decompile_lua51(main_proto, args, &mut buf)?,
+        // Lua 3.2 and 5.0 chunks can only be disassembled for now.
+        AnyProto::Lua32(_) | AnyProto::Lua50(_) => {
+            return Error::new_unsupported(format!(
+                "decompiling {} chunks, use `luad disasm` to list their instructions",
+                main_proto.version()
+            ))
+            .into();
+        }
+    }
+    Ok((buf, valid))
+}
+
+/// Decompile into the buffer, returning whether the output passed validation.
+fn decompile_lua40(
+    main_proto: &lua40::Proto,
+    args: &DecompileArgs,
+    trace: &dyn Trace,
+    diagnostics: &mut Diagnostics,
+    buf: &mut String,
+) -> Result<bool> {
+    let renames = args.rename_map()?;
+    let param_names = args.param_names()?;
+    let parser = match &args.function {
+        Some(path) => lua40::Parser::for_function(main_proto, &path.parse()?)?,
+        None => lua40::Parser::new(main_proto),
+    };
+    let mut parser = parser
+        .with_tolerance(args.tolerance())
+        .with_renames(&renames)
+        .with_naming(args.naming())
+        .with_trace(trace);
+    if let Some(param_names) = &param_names {
+        parser = parser.with_param_names(param_names);
+    }
+    let result = if args.function.is_some() {
+        parser.parse_standalone()
+    } else {
+        parser.parse()
+    };
+    diagnostics.extend(parser.into_diagnostics());
+    let mut syntax = result?;
+    args.passes(trace)?.run(&mut syntax)?;
+    if args.ast {
+        buf.push_str(&syntax.dump_tree().to_string());
+        return Ok(true);
+    }
+    let mut scribe = lua40::Scribe::new(args.scribe_config())
+        .group_locals(args.group_locals)
+        .annotate(args.annotate)
+        .preserve_lines(args.preserve_lines)
+        .line_markers(args.line_markers)
+        .canonical(args.canonical);
+    let mut source = String::new();
+    scribe.fmt_syntax(&mut source, &syntax)?;
+    lua40::check_syntax(&source)?;
+    if args.html {
+        scribe.fmt_html(buf, &syntax, main_proto)?;
+    } else {
+        buf.push_str(&source);
+    }
+
+    if args.check_format {
+        for format_call in lua40::check_format_calls(&syntax) {
+            if !format_call.is_ok() {
+                eprintln!("warning: {format_call}");
+            }
+        }
+    }
+
+    match &args.validate {
+        Some(luac) => {
+            let report = lua40::Validator::new(luac).validate(main_proto, &source)?;
+            for mismatch in &report.mismatches {
+                eprintln!("mismatch: {mismatch}");
+            }
+            Ok(report.is_ok())
+        }
+        None => Ok(true),
+    }
+}
+
+fn decompile_lua51(
+    main_proto: &lua51::Proto,
+    args: &DecompileArgs,
+    buf: &mut String,
+) -> Result<()> {
+    if let Some(option) = args.lua40_options().first() {
+        return Error::new_unsupported(format!("{option} with {} chunks", LuaVersion::Lua51))
+            .into();
+    }
+    let mut parser = lua51::Parser::new(main_proto).lenient(args.tolerance().is_tolerant());
+    let syntax = parser.parse()?;
+    let mut scribe = lua51::Scribe::new(args.scribe_config());
+    scribe.fmt_syntax(buf, &syntax)
 }
@@ -0,0 +1,131 @@
+//! Problems found while decompiling, reported alongside the output.
+//!
+//! Unlike [crate::trace] events, which follow progress as it happens,
+//! diagnostics are kept so callers can report them once decompiling is done.
+use std::fmt::{self, Formatter};
+
+/// How much a diagnostic affects the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The output may be wrong or incomplete.
+    Warning,
+    /// The output is equivalent to the original, but may not read like it,
+    /// like when a variable name had to be made up.
+    Note,
+}
+
+/// Problem at a place in a chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Index of each nested function from the main function,
+    /// as per [crate::lua40::Proto::nested]. Empty for the main function.
+    pub path: Vec<usize>,
+    /// Index of the instruction, when the problem is at one.
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+/// Diagnostics in the order they were found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+/// Count of diagnostics of each severity.
+pub struct Summary<'a> {
+    diagnostics: &'a Diagnostics,
+}
+
+// ============================================================================
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    /// Append the diagnostics of another pass.
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.items.extend(other.items);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Number of diagnostics with the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+
+    /// One line count of the diagnostics by severity, like `2 warnings, 1 note`.
+    pub fn summary(&self) -> Summary<'_> {
+        Summary { diagnostics: self }
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        if self.path.is_empty() {
+            write!(f, "main function")?;
+        } else {
+            let path: Vec<_> = self.path.iter().map(|index| index.to_string()).collect();
+            write!(f, "function {}", path.join("."))?;
+        }
+        if let Some(offset) = self.offset {
+            // Numbered from 1, like the disassembly listing.
+            write!(f, ", instruction {}", offset + 1)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let counts = [
+            (self.diagnostics.count(Severity::Warning), "warning"),
+            (self.diagnostics.count(Severity::Note), "note"),
+        ];
+        let parts: Vec<_> = counts
+            .iter()
+            .map(|(count, name)| match count {
+                1 => format!("1 {name}"),
+                _ => format!("{count} {name}s"),
+            })
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
@@ -0,0 +1,31 @@
+//! Disassembly listings for chunks of any supported Lua version.
+use std::fmt::{self, Formatter};
+
+use crate::any::AnyProto;
+
+/// Instruction listing similar to `luac -l`, showing each function's
+/// instructions with their addresses, opcode names, arguments, line
+/// numbers, and the constants they refer to.
+///
+/// Only decoding is needed, so a listing is available even
+/// when the chunk can't be decompiled.
+pub struct Disassembler<'a> {
+    proto: &'a AnyProto,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(proto: &'a AnyProto) -> Self {
+        Self { proto }
+    }
+}
+
+impl<'a> fmt::Display for Disassembler<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.proto {
+            AnyProto::Lua32(proto) => write!(f, "{}", proto.dump()),
+            AnyProto::Lua40(proto) => write!(f, "{}", proto.dump()),
+            AnyProto::Lua50(proto) => write!(f, "{}", proto.dump()),
+            AnyProto::Lua51(proto) => write!(f, "{}", proto.dump()),
+        }
+    }
+}
@@ -5,12 +5,37 @@ pub type Result<T> = std::result::Result<T, self::Error>;
 #[derive(Debug)]
 pub struct Error {
     kind: ErrorKind,
+    /// Where the error happened, like the function and instruction being decoded.
+    context: Option<String>,
+    location: Option<Location>,
+}
+
+/// Function and instruction an error happened at, for tools that
+/// sort through the failures of many chunks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    /// Index of each nested function from the main function.
+    /// Empty for the main function.
+    pub path: Vec<usize>,
+    /// Index of the instruction in the function's code.
+    pub offset: Option<usize>,
+    /// Name of the instruction's opcode, like `CALL`.
+    pub opcode: Option<&'static str>,
 }
 
 #[derive(Debug)]
 pub enum ErrorKind {
     Decoder(String),
     Parser(String),
+    /// Valid bytecode that the decompiler doesn't handle yet.
+    Unsupported(String),
+    Encoder(String),
+    Patch(String),
+    Compiler(String),
+    /// Malformed map of names for globals and locals, given by the user.
+    RenameMap(String),
+    /// Bug in the decompiler, like writing source that doesn't parse.
+    Internal(String),
     Io(std::io::Error),
     Fmt(std::fmt::Error),
 }
@@ -19,23 +44,132 @@ impl Error {
     pub fn new_decoder(message: impl ToString) -> Self {
         Error {
             kind: ErrorKind::Decoder(message.to_string()),
+            context: None,
+            location: None,
         }
     }
 
     pub fn new_parser(message: impl ToString) -> Self {
         Error {
             kind: ErrorKind::Parser(message.to_string()),
+            context: None,
+            location: None,
+        }
+    }
+
+    pub fn new_unsupported(message: impl ToString) -> Self {
+        Error {
+            kind: ErrorKind::Unsupported(message.to_string()),
+            context: None,
+            location: None,
         }
     }
+
+    pub fn new_encoder(message: impl ToString) -> Self {
+        Error {
+            kind: ErrorKind::Encoder(message.to_string()),
+            context: None,
+            location: None,
+        }
+    }
+
+    pub fn new_patch(message: impl ToString) -> Self {
+        Error {
+            kind: ErrorKind::Patch(message.to_string()),
+            context: None,
+            location: None,
+        }
+    }
+
+    pub fn new_compiler(message: impl ToString) -> Self {
+        Error {
+            kind: ErrorKind::Compiler(message.to_string()),
+            context: None,
+            location: None,
+        }
+    }
+
+    pub fn new_rename_map(message: impl ToString) -> Self {
+        Error {
+            kind: ErrorKind::RenameMap(message.to_string()),
+            context: None,
+            location: None,
+        }
+    }
+
+    pub fn new_internal(message: impl ToString) -> Self {
+        Error {
+            kind: ErrorKind::Internal(message.to_string()),
+            context: None,
+            location: None,
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Whether the input ended before what was being read, like a chunk
+    /// cut off by a failed download.
+    pub fn is_truncated(&self) -> bool {
+        matches!(&self.kind, ErrorKind::Io(err) if err.kind() == std::io::ErrorKind::UnexpectedEof)
+    }
+
+    /// Where the error happened, when known.
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    /// Function and instruction the error happened at, when known.
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// Record the function and instruction the error happened at,
+    /// keeping an existing location like [Error::with_context].
+    pub fn with_location(mut self, location: Location) -> Self {
+        if self.location.is_none() {
+            self.location = Some(location);
+        }
+        self
+    }
+
+    /// Record where the error happened.
+    ///
+    /// Errors pass through the callers of the code that raised them,
+    /// so an existing context is kept as the most precise one.
+    pub fn with_context(mut self, context: impl ToString) -> Self {
+        if self.context.is_none() {
+            self.context = Some(context.to_string());
+        }
+        self
+    }
 }
 
 impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match &self.context {
+            Some(context) => write!(f, " ({context})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Message of the error, without where it happened.
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         use ErrorKind::*;
 
-        match &self.kind {
+        match self {
             Decoder(msg) => write!(f, "decoder error: {msg}"),
             Parser(msg) => write!(f, "parser error: {msg}"),
+            Unsupported(msg) => write!(f, "unsupported: {msg}"),
+            Encoder(msg) => write!(f, "encoder error: {msg}"),
+            Patch(msg) => write!(f, "patch error: {msg}"),
+            Compiler(msg) => write!(f, "compiler error: {msg}"),
+            RenameMap(msg) => write!(f, "rename map error: {msg}"),
+            Internal(msg) => write!(f, "internal error: {msg}"),
             Io(err) => fmt::Display::fmt(err, f),
             Fmt(err) => fmt::Display::fmt(err, f),
         }
@@ -46,6 +180,8 @@ impl From<std::io::Error> for self::Error {
     fn from(err: std::io::Error) -> Self {
         Error {
             kind: ErrorKind::Io(err),
+            context: None,
+            location: None,
         }
     }
 }
@@ -54,12 +190,14 @@ impl From<std::fmt::Error> for self::Error {
     fn from(err: std::fmt::Error) -> Self {
         Error {
             kind: ErrorKind::Fmt(err),
+            context: None,
+            location: None,
         }
     }
 }
 
-impl<T> Into<Result<T>> for Error {
-    fn into(self) -> Result<T> {
-        Err(self)
+impl<T> From<Error> for Result<T> {
+    fn from(err: Error) -> Self {
+        Err(err)
     }
 }
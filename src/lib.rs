@@ -1,3 +1,30 @@
+mod any;
+pub mod diagnostics;
+mod disasm;
 pub mod errors;
+pub mod lstring;
+pub mod lua32;
 pub mod lua40;
+pub mod lua50;
+pub mod lua51;
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+pub mod mmap;
+pub mod options;
 mod reader;
+mod scan;
+pub mod style;
+pub mod symbol;
+pub mod trace;
+mod writer;
+
+pub use any::{
+    decode_any, decode_any_with_trace, decompile, decompile_bytes, decompile_with, detect_version,
+    AnyProto, LuaVersion,
+};
+pub use disasm::Disassembler;
+pub use lstring::LuaString;
+pub use scan::{find_chunk_start, find_chunks, scan_chunks, FoundChunk};
+pub use symbol::{Symbol, SymbolTable};
+
+/// Version of the decompiler, recorded in outputs so they can be reproduced.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,140 @@
+//! Byte strings as stored in compiled chunks.
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+/// A Lua string, which is a sequence of arbitrary bytes.
+///
+/// Compiled chunks can embed binary data in string constants, so
+/// the contents are only decoded as UTF-8 when they're displayed.
+///
+/// Clones share the bytes, so constants can be copied into syntax
+/// trees as often as they're used.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LuaString(Arc<[u8]>);
+
+impl LuaString {
+    pub fn new(bytes: impl Into<Arc<[u8]>>) -> Self {
+        Self(bytes.into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The string as UTF-8, if it's valid.
+    pub fn to_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    /// The string as UTF-8, with invalid sequences replaced by `U+FFFD`.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+}
+
+impl From<&str> for LuaString {
+    fn from(value: &str) -> Self {
+        Self::new(value.as_bytes())
+    }
+}
+
+impl From<String> for LuaString {
+    fn from(value: String) -> Self {
+        Self::new(value.into_bytes())
+    }
+}
+
+impl From<Vec<u8>> for LuaString {
+    fn from(value: Vec<u8>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl fmt::Display for LuaString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_string_lossy())
+    }
+}
+
+/// Quoted, with bytes that aren't printable UTF-8 escaped as `\ddd`.
+impl fmt::Debug for LuaString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_escaped(f, &self.0, '"')
+    }
+}
+
+/// Text of a string that can be written as a long string, `[[...]]`,
+/// and read back as the same bytes.
+///
+/// Long strings have no escapes, so only valid UTF-8 without control
+/// characters other than line breaks and tabs can be written as one.
+/// Lua 4.0 has no `[==[` levels either, only nested `[[` and `]]` pairs,
+/// so the brackets in the text must leave the closing `]]` at the end.
+pub(crate) fn long_string_text(bytes: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    if text
+        .chars()
+        .any(|c| c.is_control() && c != '\n' && c != '\t')
+    {
+        return None;
+    }
+
+    // Read the text back with the closing brackets, like `read_long_string` in `llex.c`.
+    let mut bytes = text.bytes().chain(*b"]]").enumerate().peekable();
+    let mut depth = 0u32;
+    while let Some((offset, byte)) = bytes.next() {
+        match byte {
+            b'[' if bytes.next_if(|(_, next)| *next == b'[').is_some() => depth += 1,
+            b']' if bytes.peek().is_some_and(|(_, next)| *next == b']') => {
+                if depth == 0 {
+                    return (offset == text.len()).then_some(text);
+                }
+                bytes.next();
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Write a quoted string literal that reads back as the same bytes.
+///
+/// Valid UTF-8 is written as is, except for control characters.
+/// Everything else is written as a decimal `\ddd` escape, the only
+/// numeric escape understood by Lua before 5.2. Escapes are always
+/// three digits so a following digit can't be read as part of one.
+pub(crate) fn fmt_escaped(f: &mut impl fmt::Write, bytes: &[u8], quote: char) -> fmt::Result {
+    f.write_char(quote)?;
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                c if c == quote => write!(f, "\\{c}")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => {
+                    let mut buf = [0; 4];
+                    for byte in c.encode_utf8(&mut buf).bytes() {
+                        write!(f, "\\{byte:03}")?;
+                    }
+                }
+                c => f.write_char(c)?,
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(f, "\\{byte:03}")?;
+        }
+    }
+    f.write_char(quote)
+}
@@ -0,0 +1,735 @@
+//! Lua 3.2 Decoder.
+//!
+//! Also decodes Lua 3.1 chunks, which share the same format.
+//!
+//! # Opcodes
+//!
+//! Instructions are variable length. A one byte opcode is followed
+//! by zero or more arguments, stored in big-endian order.
+//!
+//! ```text
+//!  ____
+//! | Op |
+//!  ____ ___
+//! | Op | b |
+//!  ____ _______
+//! | Op |   w   |
+//!  ____ _______ ___
+//! | Op | b / w | c |
+//! ```
+//!
+//! Arguments larger than a word are prefixed with a `LONGARG` instruction
+//! holding the high bits.
+
+#![allow(dead_code)]
+use std::fmt::{self, Formatter};
+use std::io::Cursor;
+
+use crate::errors::{Error, Result};
+use crate::lstring::LuaString;
+use crate::reader::CodeReader;
+
+pub use crate::reader::Endian;
+
+const LUA_VERSION: u8 = 0x32;
+/// Oldest version with the same chunk format.
+const LUA_VERSION0: u8 = 0x31;
+const ID_CHUNK: u8 = 27;
+const SIGNATURE: &str = "Lua";
+#[allow(clippy::excessive_precision)]
+const TEST_NUMBER: f64 = 3.14159265358979323846E8;
+
+/// Number of list items to accumulate before a `SETLIST` instruction.
+const FIELDS_PER_FLUSH: u32 = 40;
+
+/// As per `lopcodes.h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    EndCode = 0,
+    PushNil,
+    Pop,
+    PushNumberW,
+    PushNumber,
+    PushNumberNegW,
+    PushNumberNeg,
+    PushConstantW,
+    PushConstant,
+    PushUpvalue,
+    PushLocal,
+    GetGlobalW,
+    GetGlobal,
+    GetTable,
+    GetDottedW,
+    GetDotted,
+    PushSelfW,
+    PushSelf,
+    CreateArrayW,
+    CreateArray,
+    SetLocal,
+    SetGlobalW,
+    SetGlobal,
+    SetTablePop,
+    SetTable,
+    SetListW,
+    SetList,
+    SetMap,
+    NeqOp,
+    EqOp,
+    LtOp,
+    LeOp,
+    GtOp,
+    GeOp,
+    AddOp,
+    SubOp,
+    MultOp,
+    DivOp,
+    PowOp,
+    ConcOp,
+    MinusOp,
+    NotOp,
+    OnTJmpW,
+    OnTJmp,
+    OnFJmpW,
+    OnFJmp,
+    JmpW,
+    Jmp,
+    IfFJmpW,
+    IfFJmp,
+    IfTUpJmpW,
+    IfTUpJmp,
+    IfFUpJmpW,
+    IfFUpJmp,
+    ClosureW,
+    Closure,
+    CallFunc,
+    RetCode,
+    SetLineW,
+    SetLine,
+    LongArgW,
+    LongArg,
+    CheckStack = 62,
+}
+
+/// Layout of an instruction's arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpMode {
+    /// No arguments.
+    None,
+    /// One byte argument.
+    B,
+    /// One word argument.
+    W,
+    /// Byte argument followed by a second byte.
+    BC,
+    /// Word argument followed by a second byte.
+    WC,
+}
+
+/// Decoded instruction.
+#[derive(Debug, Clone, Copy)]
+pub struct Instr {
+    /// Byte offset of the instruction in the function's code.
+    pub pc: u32,
+    pub opcode: Opcode,
+    /// First argument, including the high bits of a preceding `LONGARG`.
+    pub arg: u32,
+    /// Second argument.
+    pub arg2: u32,
+}
+
+/// Chunk header.
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Lua version, `0x32` for Lua 3.2 or `0x31` for Lua 3.1.
+    pub version: u8,
+    /// Size of numbers in bytes, or `0` when numbers are stored as text.
+    pub size_number: u8,
+    /// Byte order of numbers, which are stored in the native
+    /// order of the machine that compiled the chunk.
+    pub number_endian: Endian,
+}
+
+/// Function prototype.
+#[derive(Debug)]
+pub struct Proto {
+    code: Box<[u8]>,
+    instrs: Box<[Instr]>,
+    source: String,
+    line_defined: u32,
+    locals: Box<[Local]>,
+    constants: Box<[Constant]>,
+    protos: Box<[Proto]>,
+}
+
+/// Debug information for local variable.
+#[derive(Debug)]
+struct Local {
+    /// Source line where the variable is declared.
+    line: u32,
+    /// Name of the variable, or empty at the end of its scope.
+    varname: String,
+}
+
+/// Constant value referenced by instructions.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Nil,
+    Number(f64),
+    String(LuaString),
+    /// Nested function, by index into the prototype's functions.
+    Proto(usize),
+}
+
+/// Lua 3.2 bytecode chunk decoder.
+pub struct Decoder<'a> {
+    reader: CodeReader<Cursor<&'a [u8]>>,
+    header: Header,
+}
+
+/// Disassembly listing of a function and its nested functions.
+pub struct ProtoDump<'a> {
+    proto: &'a Proto,
+}
+
+// ============================================================================
+
+/// Type tags of constants, as per `lobject.h`. Tags
+/// are negative in memory, and stored negated.
+const LUA_T_NUMBER: u8 = 1;
+const LUA_T_STRING: u8 = 2;
+const LUA_T_PROTO: u8 = 4;
+const LUA_T_NIL: u8 = 6;
+
+// ============================================================================
+
+impl TryFrom<u8> for Opcode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        use Opcode::*;
+
+        Ok(match value {
+            0 => EndCode,
+            1 => PushNil,
+            2 => Pop,
+            3 => PushNumberW,
+            4 => PushNumber,
+            5 => PushNumberNegW,
+            6 => PushNumberNeg,
+            7 => PushConstantW,
+            8 => PushConstant,
+            9 => PushUpvalue,
+            10 => PushLocal,
+            11 => GetGlobalW,
+            12 => GetGlobal,
+            13 => GetTable,
+            14 => GetDottedW,
+            15 => GetDotted,
+            16 => PushSelfW,
+            17 => PushSelf,
+            18 => CreateArrayW,
+            19 => CreateArray,
+            20 => SetLocal,
+            21 => SetGlobalW,
+            22 => SetGlobal,
+            23 => SetTablePop,
+            24 => SetTable,
+            25 => SetListW,
+            26 => SetList,
+            27 => SetMap,
+            28 => NeqOp,
+            29 => EqOp,
+            30 => LtOp,
+            31 => LeOp,
+            32 => GtOp,
+            33 => GeOp,
+            34 => AddOp,
+            35 => SubOp,
+            36 => MultOp,
+            37 => DivOp,
+            38 => PowOp,
+            39 => ConcOp,
+            40 => MinusOp,
+            41 => NotOp,
+            42 => OnTJmpW,
+            43 => OnTJmp,
+            44 => OnFJmpW,
+            45 => OnFJmp,
+            46 => JmpW,
+            47 => Jmp,
+            48 => IfFJmpW,
+            49 => IfFJmp,
+            50 => IfTUpJmpW,
+            51 => IfTUpJmp,
+            52 => IfFUpJmpW,
+            53 => IfFUpJmp,
+            54 => ClosureW,
+            55 => Closure,
+            56 => CallFunc,
+            57 => RetCode,
+            58 => SetLineW,
+            59 => SetLine,
+            60 => LongArgW,
+            61 => LongArg,
+            62 => CheckStack,
+            _ => return Error::new_decoder(format!("unknown opcode: 0x{value:02x}")).into(),
+        })
+    }
+}
+
+impl Opcode {
+    /// Name as printed by `luac -l`.
+    pub fn name(self) -> &'static str {
+        use Opcode::*;
+
+        match self {
+            EndCode => "ENDCODE",
+            PushNil => "PUSHNIL",
+            Pop => "POP",
+            PushNumberW => "PUSHNUMBERW",
+            PushNumber => "PUSHNUMBER",
+            PushNumberNegW => "PUSHNUMBERNEGW",
+            PushNumberNeg => "PUSHNUMBERNEG",
+            PushConstantW => "PUSHCONSTANTW",
+            PushConstant => "PUSHCONSTANT",
+            PushUpvalue => "PUSHUPVALUE",
+            PushLocal => "PUSHLOCAL",
+            GetGlobalW => "GETGLOBALW",
+            GetGlobal => "GETGLOBAL",
+            GetTable => "GETTABLE",
+            GetDottedW => "GETDOTTEDW",
+            GetDotted => "GETDOTTED",
+            PushSelfW => "PUSHSELFW",
+            PushSelf => "PUSHSELF",
+            CreateArrayW => "CREATEARRAYW",
+            CreateArray => "CREATEARRAY",
+            SetLocal => "SETLOCAL",
+            SetGlobalW => "SETGLOBALW",
+            SetGlobal => "SETGLOBAL",
+            SetTablePop => "SETTABLEPOP",
+            SetTable => "SETTABLE",
+            SetListW => "SETLISTW",
+            SetList => "SETLIST",
+            SetMap => "SETMAP",
+            NeqOp => "NEQOP",
+            EqOp => "EQOP",
+            LtOp => "LTOP",
+            LeOp => "LEOP",
+            GtOp => "GTOP",
+            GeOp => "GEOP",
+            AddOp => "ADDOP",
+            SubOp => "SUBOP",
+            MultOp => "MULTOP",
+            DivOp => "DIVOP",
+            PowOp => "POWOP",
+            ConcOp => "CONCOP",
+            MinusOp => "MINUSOP",
+            NotOp => "NOTOP",
+            OnTJmpW => "ONTJMPW",
+            OnTJmp => "ONTJMP",
+            OnFJmpW => "ONFJMPW",
+            OnFJmp => "ONFJMP",
+            JmpW => "JMPW",
+            Jmp => "JMP",
+            IfFJmpW => "IFFJMPW",
+            IfFJmp => "IFFJMP",
+            IfTUpJmpW => "IFTUPJMPW",
+            IfTUpJmp => "IFTUPJMP",
+            IfFUpJmpW => "IFFUPJMPW",
+            IfFUpJmp => "IFFUPJMP",
+            ClosureW => "CLOSUREW",
+            Closure => "CLOSURE",
+            CallFunc => "CALLFUNC",
+            RetCode => "RETCODE",
+            SetLineW => "SETLINEW",
+            SetLine => "SETLINE",
+            LongArgW => "LONGARGW",
+            LongArg => "LONGARG",
+            CheckStack => "CHECKSTACK",
+        }
+    }
+
+    /// Argument layout, as per the comments in `lopcodes.h`.
+    pub fn mode(self) -> OpMode {
+        use Opcode::*;
+
+        match self {
+            EndCode | GetTable | SetTablePop | NeqOp | EqOp | LtOp | LeOp | GtOp | GeOp | AddOp
+            | SubOp | MultOp | DivOp | PowOp | ConcOp | MinusOp | NotOp => OpMode::None,
+            PushNumberW | PushNumberNegW | PushConstantW | GetGlobalW | GetDottedW | PushSelfW
+            | CreateArrayW | SetGlobalW | OnTJmpW | OnFJmpW | JmpW | IfFJmpW | IfTUpJmpW
+            | IfFUpJmpW | SetLineW | LongArgW => OpMode::W,
+            SetList | Closure | CallFunc => OpMode::BC,
+            SetListW | ClosureW => OpMode::WC,
+            _ => OpMode::B,
+        }
+    }
+
+    /// Whether the instruction jumps relative to the next instruction.
+    fn jump(self) -> Option<Jump> {
+        use Opcode::*;
+
+        match self {
+            OnTJmpW | OnTJmp | OnFJmpW | OnFJmp | JmpW | Jmp | IfFJmpW | IfFJmp => {
+                Some(Jump::Forward)
+            }
+            IfTUpJmpW | IfTUpJmp | IfFUpJmpW | IfFUpJmp => Some(Jump::Backward),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Jump {
+    Forward,
+    Backward,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self {
+            version: LUA_VERSION,
+            size_number: 8,
+            number_endian: Endian::Little,
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            version,
+            size_number,
+            number_endian,
+        } = self;
+        write!(
+            f,
+            "version: {version:02x}; Number: {size_number}B; Number endianess: {number_endian:?}"
+        )
+    }
+}
+
+impl Proto {
+    pub fn source(&self) -> &str {
+        self.source.as_str()
+    }
+
+    pub fn instrs(&self) -> &[Instr] {
+        &self.instrs
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    pub fn protos(&self) -> &[Proto] {
+        &self.protos
+    }
+
+    /// Disassembly listing similar to `luac -l`.
+    pub fn dump(&self) -> ProtoDump<'_> {
+        ProtoDump { proto: self }
+    }
+}
+
+/// Decode only the chunk header.
+pub fn read_header(code: &[u8]) -> Result<Header> {
+    let mut decoder = Decoder::new(code);
+    decoder.read_header()?;
+    Ok(decoder.header)
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(code: &'a [u8]) -> Self {
+        let mut reader = CodeReader::new(code);
+        // Words and longs are written a byte at a time, most significant first.
+        reader.set_endian(Endian::Big);
+        reader.set_size_int(2);
+        reader.set_size_t(4);
+
+        Self {
+            reader,
+            header: Header::default(),
+        }
+    }
+
+    pub fn decode(&mut self) -> Result<Proto> {
+        self.read_header()?;
+
+        // Top level function
+        self.read_function()
+    }
+
+    /// Number of bytes decoded so far, which after decoding is the size of the chunk.
+    pub fn position(&self) -> u64 {
+        self.reader.position()
+    }
+}
+
+impl<'a> Decoder<'a> {
+    fn read_header(&mut self) -> Result<()> {
+        if self.reader.read_u8()? != ID_CHUNK {
+            return Error::new_decoder("chunk bytemark must be 'Esc'(27)").into();
+        }
+
+        let mut signature = [0u8; SIGNATURE.len()];
+        self.reader.read_bytes(&mut signature)?;
+        if signature != SIGNATURE.as_bytes() {
+            return Error::new_decoder("bad signature").into();
+        }
+
+        let version = self.reader.read_u8()?;
+        if !(LUA_VERSION0..=LUA_VERSION).contains(&version) {
+            return Error::new_decoder(format!(
+                "expected Lua version 3.1(0x31) or 3.2(0x32), found: {version:02x}"
+            ))
+            .into();
+        }
+
+        self.header = Header {
+            version,
+            size_number: self.reader.read_u8()?,
+            number_endian: Endian::Little,
+        };
+
+        if self.header.size_number != 0 {
+            if self.header.size_number != 8 {
+                return Error::new_decoder(format!(
+                    "unknown number size: {}",
+                    self.header.size_number
+                ))
+                .into();
+            }
+
+            // The test number reveals the byte order of the compiling machine.
+            let mut buf = [0u8; 8];
+            self.reader.read_bytes(&mut buf)?;
+            self.header.number_endian = if f64::from_le_bytes(buf) as i64 == TEST_NUMBER as i64 {
+                Endian::Little
+            } else if f64::from_be_bytes(buf) as i64 == TEST_NUMBER as i64 {
+                Endian::Big
+            } else {
+                return Error::new_decoder("unknown number format").into();
+            };
+        }
+
+        Ok(())
+    }
+
+    fn read_function(&mut self) -> Result<Proto> {
+        let line_defined = self.reader.read_int()?;
+        let source = self.reader.read_string()?;
+        let code = self.read_code()?;
+        let locals = self.read_locals()?;
+
+        let mut protos = vec![];
+        let constants = self.read_constants(&mut protos)?;
+
+        let instrs = decode_instrs(&code)?;
+
+        Ok(Proto {
+            code,
+            instrs,
+            source,
+            line_defined,
+            locals,
+            constants,
+            protos: protos.into_boxed_slice(),
+        })
+    }
+
+    fn read_code(&mut self) -> Result<Box<[u8]>> {
+        let n = self.reader.read_size_t()?;
+        let code = self.reader.read_vec(n, "code size")?;
+        if code.last() != Some(&(Opcode::EndCode as u8)) {
+            return Error::new_decoder("code must end with ENDCODE").into();
+        }
+        Ok(code.into_boxed_slice())
+    }
+
+    fn read_locals(&mut self) -> Result<Box<[Local]>> {
+        let n = self.reader.read_int()?;
+        let mut locals = vec![];
+        for _ in 0..n {
+            locals.push(Local {
+                line: self.reader.read_int()?,
+                varname: self.reader.read_string()?,
+            });
+        }
+        Ok(locals.into_boxed_slice())
+    }
+
+    fn read_constants(&mut self, protos: &mut Vec<Proto>) -> Result<Box<[Constant]>> {
+        let n = self.reader.read_int()?;
+        let mut constants = vec![];
+        for _ in 0..n {
+            let constant = match self.reader.read_u8()? {
+                LUA_T_NIL => Constant::Nil,
+                LUA_T_NUMBER => Constant::Number(self.read_number()?),
+                LUA_T_STRING => Constant::String(self.reader.read_lua_string()?),
+                LUA_T_PROTO => {
+                    protos.push(self.read_function()?);
+                    Constant::Proto(protos.len() - 1)
+                }
+                tag => return Error::new_decoder(format!("unknown constant type: {tag}")).into(),
+            };
+            constants.push(constant);
+        }
+        Ok(constants.into_boxed_slice())
+    }
+
+    /// Read a number in the native format, or as text when the chunk is portable.
+    fn read_number(&mut self) -> Result<f64> {
+        if self.header.size_number == 0 {
+            let len = self.reader.read_u8()? as usize;
+            let mut buf = vec![0u8; len];
+            self.reader.read_bytes(&mut buf)?;
+            let text = String::from_utf8(buf)
+                .map_err(|err| Error::new_decoder(format!("bad number text: {err}")))?;
+            return text
+                .trim()
+                .parse()
+                .map_err(|err| Error::new_decoder(format!("bad number text '{text}': {err}")));
+        }
+
+        let mut buf = [0u8; 8];
+        self.reader.read_bytes(&mut buf)?;
+        Ok(match self.header.number_endian {
+            Endian::Little => f64::from_le_bytes(buf),
+            Endian::Big => f64::from_be_bytes(buf),
+        })
+    }
+}
+
+/// Split the byte code into instructions, folding `LONGARG`
+/// prefixes into the argument of the following instruction.
+fn decode_instrs(code: &[u8]) -> Result<Box<[Instr]>> {
+    let mut instrs = vec![];
+    let mut pc = 0;
+    let mut high = 0;
+
+    let arg_at = |offset: usize, size: usize| -> Result<u32> {
+        match code.get(offset..offset + size) {
+            Some(bytes) => Ok(bytes.iter().fold(0, |acc, b| acc << 8 | *b as u32)),
+            None => Error::new_decoder("instruction argument out of bounds").into(),
+        }
+    };
+
+    while pc < code.len() {
+        let opcode = Opcode::try_from(code[pc])?;
+        let (arg, arg2, size) = match opcode.mode() {
+            OpMode::None => (0, 0, 1),
+            OpMode::B => (arg_at(pc + 1, 1)?, 0, 2),
+            OpMode::W => (arg_at(pc + 1, 2)?, 0, 3),
+            OpMode::BC => (arg_at(pc + 1, 1)?, arg_at(pc + 2, 1)?, 3),
+            OpMode::WC => (arg_at(pc + 1, 2)?, arg_at(pc + 3, 1)?, 4),
+        };
+
+        if matches!(opcode, Opcode::LongArg | Opcode::LongArgW) {
+            high = arg << 16;
+        } else {
+            instrs.push(Instr {
+                pc: pc as u32,
+                opcode,
+                arg: arg | high,
+                arg2,
+            });
+            high = 0;
+        }
+        pc += size;
+    }
+
+    Ok(instrs.into_boxed_slice())
+}
+
+impl<'a> ProtoDump<'a> {
+    fn fmt_proto(&self, f: &mut Formatter, proto: &Proto) -> fmt::Result {
+        writeln!(
+            f,
+            "function <{}:{}> ({} bytes, {} instructions)",
+            proto.source,
+            proto.line_defined,
+            proto.code.len(),
+            proto.instrs.len()
+        )?;
+        writeln!(
+            f,
+            "{} locals, {} constants, {} functions",
+            proto
+                .locals
+                .iter()
+                .filter(|l| !l.varname.is_empty())
+                .count(),
+            proto.constants.len(),
+            proto.protos.len()
+        )?;
+
+        for (index, instr) in proto.instrs.iter().enumerate() {
+            write!(f, "\t{:>5}\t{}", instr.pc, instr.opcode.name())?;
+            match instr.opcode.mode() {
+                OpMode::None => {}
+                OpMode::B | OpMode::W => write!(f, "\t{}", instr.arg)?,
+                OpMode::BC | OpMode::WC => write!(f, "\t{} {}", instr.arg, instr.arg2)?,
+            }
+            self.fmt_comment(f, proto, index, instr)?;
+            writeln!(f)?;
+        }
+        writeln!(f)?;
+
+        for child in proto.protos.iter() {
+            self.fmt_proto(f, child)?;
+        }
+
+        Ok(())
+    }
+
+    fn fmt_comment(
+        &self,
+        f: &mut Formatter,
+        proto: &Proto,
+        index: usize,
+        instr: &Instr,
+    ) -> fmt::Result {
+        use Opcode::*;
+
+        match instr.opcode {
+            PushConstantW | PushConstant | GetGlobalW | GetGlobal | GetDottedW | GetDotted
+            | PushSelfW | PushSelf | SetGlobalW | SetGlobal | ClosureW | Closure => {
+                write!(f, "\t; ")?;
+                fmt_constant(f, proto, instr.arg)
+            }
+            _ => match instr.opcode.jump() {
+                Some(jump) => {
+                    // Jumps are relative to the end of the instruction.
+                    let next = proto
+                        .instrs
+                        .get(index + 1)
+                        .map(|next| next.pc)
+                        .unwrap_or(proto.code.len() as u32) as i64;
+                    let target = match jump {
+                        Jump::Forward => next + instr.arg as i64,
+                        Jump::Backward => next - instr.arg as i64,
+                    };
+                    write!(f, "\t; to {target}")
+                }
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+fn fmt_constant(f: &mut Formatter, proto: &Proto, index: u32) -> fmt::Result {
+    match proto.constants.get(index as usize) {
+        Some(Constant::Nil) => write!(f, "nil"),
+        Some(Constant::Number(n)) => write!(f, "{n}"),
+        Some(Constant::String(s)) => write!(f, "{s:?}"),
+        Some(Constant::Proto(index)) => write!(f, "function {index}"),
+        None => write!(f, "<bad constant {index}>"),
+    }
+}
+
+impl<'a> fmt::Display for ProtoDump<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_proto(f, self.proto)
+    }
+}
@@ -11,28 +11,88 @@
 //! ```
 
 #![allow(dead_code)]
-use byteorder::ReadBytesExt;
-use std::ffi::CString;
+use std::collections::VecDeque;
 use std::fmt::{self, Formatter};
 use std::io::{Cursor, Read};
-
-use crate::errors::{Error, Result};
-use crate::reader::{Endian, NumberType};
-
-mod ast;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity};
+use crate::errors::{Error, Location, Result};
+use crate::lstring::LuaString;
+use crate::options::{DecompileOptions, Output, Tolerance};
+use crate::reader::CodeReader;
+use crate::trace::{trace_event, Level, NoTrace, Trace};
+
+pub use crate::reader::{Endian, Limits, NumberType};
+
+mod analysis;
+pub mod ast;
+mod callgraph;
+mod cfg;
+mod check;
+mod diff;
+mod encoder;
+mod export;
+mod highlight;
+mod html;
+mod liveness;
+mod naming;
 mod parser;
+mod passes;
+mod path;
+mod pattern;
+mod rename;
 mod scribe;
-
+mod stats;
+mod tree;
+mod types;
+mod validate;
+mod verify;
+mod xref;
+
+pub use analysis::{check_format_calls, FormatCall};
+pub use ast::{Custom, Syntax};
+pub use callgraph::{call_graph, CallEdge, CallGraph, CallGraphDot, CallNode, Callee};
+pub use check::check_syntax;
+pub use diff::{diff, ChunkDiff, FunctionChange};
+pub use encoder::Encoder;
+pub use export::{CsvExport, Export, LineInfo, LocalInfo};
+pub use highlight::Highlight;
+pub use naming::{
+    AlphabeticNames, IndexedNames, LocalHint, NamingStrategy, ParamNames, SlotNames, TypedNames,
+};
 pub use parser::Parser;
+pub use passes::{FlattenConcat, Pass, PassManager, RenameGlobals, SimplifyConditions};
+pub use path::ProtoPath;
+pub use pattern::{Idiom, Pattern, Recognized};
+pub use rename::RenameMap;
 pub use scribe::Scribe;
+pub use stats::{Coverage, OpcodeUse, Stats};
+pub use tree::TreeDump;
+pub use types::Type;
+#[cfg(not(target_arch = "wasm32"))]
+pub use validate::Validator;
+pub use validate::{compare, Mismatch, Report};
+pub use xref::{Access, Xref, Xrefs};
 
 const LUA_VERSION: u8 = 0x40;
 const ID_CHUNK: u8 = 27;
 const SIGNATURE: &str = "Lua";
+#[allow(clippy::excessive_precision)]
 const TEST_NUMBER: f64 = 3.14159265358979323846E8;
+/// Marker for an open number of results in `OP_CALL`.
+const MULT_RET: u32 = 255;
+
+/// Number of array items set by each `OP_SETLIST` of a table constructor,
+/// as per `lopcodes.h`. Longer lists are flushed in batches of this size.
+const LFIELDS_PER_FLUSH: u32 = 64;
+
+/// Most parameters a function can have, as per `llimits.h`.
+const MAX_PARAMS: u32 = 100;
 
 /// As per `lopcode.h`
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Opcode {
     End = 0,
     Return,
@@ -48,7 +108,11 @@ pub enum Opcode {
     PushNum,
     PushNegNum,
 
-    PushValue,
+    /// Push the value of an upvalue, `PUSHUPVALUE` in `lopcodes.h`.
+    ///
+    /// Not to be mistaken for a stack duplication. Lua 4.0 has no such
+    /// instruction; the ones that reuse a value, like `PUSHSELF`, do so implicitly.
+    PushUpvalue,
 
     GetLocal,
     GetGlobal,
@@ -99,79 +163,278 @@ pub enum Opcode {
     LForLoop,
 
     Closure = 48,
+
+    /// Opcode outside of the instruction set, only decoded by a
+    /// tolerant [Decoder]. The word is kept in the function's code.
+    Unknown,
+}
+
+/// Layout of an instruction's arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpMode {
+    /// No arguments.
+    None,
+    /// Unsigned argument `U`.
+    U,
+    /// Signed argument `S`.
+    S,
+    /// Arguments `A` and `B`.
+    AB,
+}
+
+/// Instruction split into its opcode and every possible argument.
+///
+/// Only the arguments matching the opcode's [OpMode] are meaningful.
+#[derive(Debug, Clone, Copy)]
+pub struct Instr {
+    pub opcode: Opcode,
+    pub u: u32,
+    pub s: i32,
+    pub a: u32,
+    pub b: u32,
+}
+
+/// Decoded instruction with the constant and jump target it refers to.
+///
+/// Returned by [Proto::instructions], for tools that work with
+/// bytecode without decoding the instruction words themselves.
+#[derive(Debug, Clone)]
+pub struct Instruction<'a> {
+    /// Index of the instruction in the function's code.
+    pub offset: usize,
+    /// Instruction word as stored in the chunk, widened to 64 bits.
+    pub word: u64,
+    pub opcode: Opcode,
+    pub args: Args,
+    /// Constant used by the instruction, if any.
+    pub constant: Option<Constant<'a>>,
+    /// Index of the instruction jumped to, for jump instructions.
+    pub target: Option<usize>,
+}
+
+/// Arguments of an instruction, laid out as per its [OpMode].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Args {
+    None,
+    U(u32),
+    S(i32),
+    AB(u32, u32),
 }
 
+/// Entry of a function's constant pools.
+#[derive(Debug, Clone, Copy)]
+pub enum Constant<'a> {
+    String(&'a LuaString),
+    /// Number as stored, which [Opcode::PushNegNum] negates when pushing.
+    Number(f64),
+    /// Nested function, created by [Opcode::Closure].
+    Function(&'a Proto),
+}
+
+/// String or number constant of a function in a chunk.
+///
+/// Returned by [Chunk::constants], for tools that look for embedded
+/// strings without decompiling.
 #[derive(Debug, Clone)]
-enum Op {
+pub struct PooledConstant<'a> {
+    /// Path of the function the constant belongs to.
+    pub path: ProtoPath,
+    /// Index of the constant in the function's pool of strings or numbers.
+    pub index: usize,
+    pub constant: Constant<'a>,
+    /// Source line of the first instruction using the constant,
+    /// when it's used and debug information is present.
+    pub line: Option<u32>,
+}
+
+/// Instruction with its arguments named after what they mean for the opcode,
+/// as returned by [Instr::op] and [Proto::ops].
+///
+/// Opcodes the decompiler doesn't handle yet decode to [Op::Unsupported],
+/// and get variants of their own as support is added, so the enum is
+/// non-exhaustive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Op {
+    /// End of the function's code, returning no values.
     End,
     /// Return from the current activation frame.
     ///
-    /// Argument `U` is the numbeber of result values left on the stack.
-    Return {
-        results: u32,
-    },
+    /// Argument `U` is the stack offset of the first result value. All
+    /// values from there to the top of the stack are returned.
+    Return { stack_offset: u32 },
 
     /// Call Lua or C function.
     ///
     /// Argument `A` is the stack offset relative to the callee's stack base.
     ///
     /// Argument `B` is the number of result values left on the stack. When it's 255 (unsigned)
-    /// in bytecode it means the function has multiple returns.
-    Call {
+    /// in bytecode it means the function has multiple returns. See [MULT_RET].
+    Call { stack_offset: u32, results: u32 },
+    /// Call a function and return all its results, compiled for
+    /// a `return` whose last value is a call.
+    ///
+    /// Argument `A` is the stack offset of the callee, as with [Op::Call].
+    ///
+    /// Argument `B` is the stack offset of the first returned value, like the
+    /// argument of [Op::Return]. Values below the callee are returned before
+    /// the call's results.
+    TailCall {
         stack_offset: u32,
-        results: u32,
+        return_offset: u32,
     },
 
-    Pop {
-        n: u32,
-    },
+    /// Pop `U` values off the stack.
+    Pop { n: u32 },
 
     /// Push an integer constant onto the stack.
     ///
     /// Argument `S` is the inlined signed integer value.
-    PushInt {
-        value: i32,
-    },
+    PushInt { value: i32 },
+    /// Push a string constant onto the stack.
+    ///
+    /// Argument `U` is the index of the string constant.
+    PushString { string_id: u32 },
+    /// Push a number constant onto the stack.
+    ///
+    /// Argument `U` is the index of the number constant.
+    PushNum { number_id: u32 },
+    /// Push the negation of a number constant onto the stack,
+    /// which the compiler folds negative number literals into.
+    PushNegNum { number_id: u32 },
+    /// Push the value of upvalue `U` of the running closure onto the stack.
+    PushUpvalue { upvalue_id: u32 },
 
     /// Copy the local variable from stack index `U` to the top of the stack.
-    GetLocal {
-        stack_offset: u32,
-    },
+    GetLocal { stack_offset: u32 },
     /// Copy a global variable to the top of the stack.
     ///
     /// Argument `U` is the index of the string constant that acts as the key.
-    GetGlobal {
-        string_id: u32,
-    },
+    GetGlobal { string_id: u32 },
 
-    SetLocal {
-        stack_offset: u32,
-    },
+    /// Pop the top of the stack into the local variable at stack index `U`.
+    SetLocal { stack_offset: u32 },
+    /// Pop the top of the stack into a global variable.
+    ///
+    /// Argument `U` is the index of the string constant that acts as the key.
+    SetGlobal { string_id: u32 },
+
+    /// Push a new table.
+    ///
+    /// Argument `U` is the number of items in the constructor, as a size hint.
+    CreateTable { size: u32 },
+    /// Pop `B` values into the array items of the table below them.
+    ///
+    /// Argument `A` is the number of batches of [LFIELDS_PER_FLUSH]
+    /// items set by earlier instructions of the constructor.
+    SetList { batch: u32, n: u32 },
+    /// Pop `U` key and value pairs into the fields of the table below them.
+    SetMap { n: u32 },
 
+    /// Arithmetic, which pops two values and pushes the result.
     Add,
+    /// Add an integer to the top of the stack, compiled for `x + k` and `x - k`.
+    ///
+    /// Argument `S` is the inlined signed integer value.
+    AddI { value: i32 },
+    /// Subtraction, like [Op::Add].
+    Sub,
+    /// Multiplication, like [Op::Add].
+    Mult,
+    /// Division, like [Op::Add].
+    Div,
+    /// Exponentiation, like [Op::Add].
+    Pow,
+    /// Pop the number of values given by argument `U`, and push their concatenation.
+    Concat { n: u32 },
+    /// Negate the top of the stack.
+    Minus,
+    /// Logical `not` of the top of the stack.
+    Not,
 
-    JumpLe {
-        ip: i32,
-    },
+    /// Conditional jumps, which pop two values and compare them.
+    /// This one jumps if they aren't equal.
+    ///
+    /// Argument `S` is the jump offset, relative to the next instruction.
+    JumpNe { ip: i32 },
+    /// Jump if the values are equal, like [Op::JumpNe].
+    JumpEq { ip: i32 },
+    /// Jump if the lower value is less than the top one, like [Op::JumpNe].
+    JumpLt { ip: i32 },
+    /// Jump if the lower value is less than or equal to the top one, like [Op::JumpNe].
+    JumpLe { ip: i32 },
+    /// Jump if the lower value is greater than the top one, like [Op::JumpNe].
+    JumpGt { ip: i32 },
+    /// Jump if the lower value is greater than or equal to the top one, like [Op::JumpNe].
+    JumpGe { ip: i32 },
+
+    /// Pop a value and jump if it's not `nil`.
+    JumpTrue { ip: i32 },
+    /// Pop a value and jump if it's `nil`.
+    JumpFalse { ip: i32 },
+    /// Jump if the value on top of the stack is not `nil`, keeping it
+    /// as the value of an `or` expression, otherwise pop it.
+    JumpOnTrue { ip: i32 },
+    /// Jump if the value on top of the stack is `nil`, keeping it
+    /// as the value of an `and` expression, otherwise pop it.
+    JumpOnFalse { ip: i32 },
+    /// Unconditional jump.
+    Jump { ip: i32 },
+    /// Push `nil` and skip the next instruction, a `PUSHINT 1` that conditional
+    /// jumps land on instead, compiled for a comparison used as a value.
+    PushNilJump,
+
+    /// Create a closure from a nested function, popping the values
+    /// of its upvalues off the stack.
+    ///
+    /// Argument `A` is the index of the nested function,
+    /// and `B` is the number of upvalues.
+    Closure { proto_id: u32, upvalues: u32 },
+
+    /// Instruction that can be decoded, but not yet decompiled.
+    Unsupported { opcode: Opcode },
+
+    /// Instruction with an [Opcode::Unknown] opcode.
+    Unknown,
+}
+
+/// Chunk header.
+///
+/// Describes the platform the chunk was compiled on, which
+/// determines how the rest of the chunk must be decoded.
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Lua version, `0x40` for Lua 4.0.
+    pub version: u8,
+    pub endianess: Endian,
+    /// Size of C `int` in bytes.
+    pub size_int: u8,
+    /// Size of C `size_t` in bytes.
+    pub size_t: u8,
+    /// Size of an instruction in bytes.
+    pub size_instr: u8,
+    /// Size of an instruction in bits.
+    pub size_instr_arg: u8,
+    /// Size of the opcode field in bits.
+    pub size_op: u8,
+    /// Size of the instruction argument `B` in bits.
+    pub size_b: u8,
+    pub number_type: NumberType,
 }
 
+/// Decoded chunk, with the header describing the
+/// platform it was compiled on and its main function.
 #[derive(Debug)]
-struct Header {
-    version: u8,
-    endianess: Endian,
-    size_int: u8,
-    size_t: u8,
-    size_instr: u8,
-    size_instr_arg: u8,
-    size_op: u8,
-    size_b: u8,
-    number_type: NumberType,
+pub struct Chunk {
+    header: Header,
+    main: Proto,
 }
 
 /// Function prototype.
 #[derive(Debug)]
 pub struct Proto {
-    code: Box<[u32]>,
+    code: Box<[u64]>,
+    instrs: Box<[Instr]>,
     ops: Box<[Op]>,
     source: String,
     line_defined: u32,
@@ -181,39 +444,144 @@ pub struct Proto {
     locals: Box<[Local]>,
     constants: Constants,
     lines: Box<[u32]>,
+    /// Whether the chunk ended partway through the function.
+    truncated: bool,
 }
 
 /// Debug information for local variable.
 #[derive(Debug)]
-struct Local {
-    varname: String,
+pub struct Local {
+    pub varname: String,
     /// Point where variable is live.
-    startpc: u32,
+    pub startpc: u32,
     /// Point where variable is dead.
-    endpc: u32,
+    pub endpc: u32,
+}
+
+/// Parts of a function read so far, kept when the chunk ends partway through it.
+#[derive(Default)]
+struct ProtoParts {
+    source: String,
+    line_defined: u32,
+    num_params: u32,
+    is_vararg: bool,
+    max_stack: u32,
+    locals: Vec<Local>,
+    lines: Vec<u32>,
+    strings: Vec<LuaString>,
+    numbers: Vec<f64>,
+    protos: Vec<Proto>,
+    code: Vec<u64>,
 }
 
 #[derive(Debug)]
 struct Constants {
-    strings: Box<[String]>,
+    strings: Box<[LuaString]>,
     numbers: Box<[f64]>,
     protos: Box<[Proto]>,
 }
 
-/// Lua 4.0 bytecode chunk decoder.
-pub struct Decoder<'a> {
+/// Field of a chunk header, located by [annotate_header].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    pub name: &'static str,
+    /// Offsets of the field's bytes in the chunk.
+    pub range: Range<usize>,
+    /// Value of the field, or why it's malformed.
+    pub value: std::result::Result<String, String>,
+}
+
+/// Hex dump of a chunk header, with the meaning of each field.
+pub struct HeaderDump<'a> {
     code: &'a [u8],
-    cursor: Cursor<&'a [u8]>,
+    fields: Vec<HeaderField>,
+}
+
+/// Disassembly listing of a function and its nested functions.
+pub struct ProtoDump<'a> {
+    proto: &'a Proto,
+}
+
+/// Disassembly listing of a range of instructions in a function.
+pub struct RangeDump<'a> {
+    proto: &'a Proto,
+    range: Range<usize>,
+}
+
+/// Lua 4.0 bytecode chunk decoder.
+///
+/// Decodes from a byte slice by default, or from any [Read] with [Decoder::from_reader].
+pub struct Decoder<'a, R = Cursor<&'a [u8]>> {
+    reader: CodeReader<R>,
     header: Header,
+    trace: &'a dyn Trace,
+    limits: Limits,
+    tolerance: Tolerance,
+    /// Nesting depth of the function being read.
+    depth: u32,
+    /// Index of each nested function down to the one being read.
+    path: Vec<usize>,
+    diagnostics: Diagnostics,
+    transformer: Option<&'a dyn ConstantTransformer>,
+    profile: HeaderProfile,
+    /// Fields of the header read so far, for [annotate_header].
+    header_fields: Vec<HeaderField>,
+    /// Most bytes to skip before the bytemark, see [Decoder::with_preamble].
+    max_preamble: usize,
+}
+
+/// Rewrites constants as they're decoded, like decrypting the strings of
+/// a chunk that a game stores encrypted and decrypts at load time.
+///
+/// Each method is given the path of the function, as per [Proto::nested],
+/// and the index of the constant in its list. Both leave the constant as
+/// it is by default.
+pub trait ConstantTransformer {
+    fn transform_string(
+        &self,
+        path: &[usize],
+        index: usize,
+        string: LuaString,
+    ) -> Result<LuaString> {
+        let _ = (path, index);
+        Ok(string)
+    }
+
+    fn transform_number(&self, path: &[usize], index: usize, number: f64) -> Result<f64> {
+        let _ = (path, index);
+        Ok(number)
+    }
+}
+
+/// Header layout expected by the decoder, for chunks from engines
+/// that modified Lua, like with their own signature or instruction layout.
+///
+/// The default profile is the reference implementation's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderProfile {
+    /// Signature following the `Esc` byte, `Lua` in the reference implementation.
+    pub signature: Vec<u8>,
+    /// Accepted version bytes.
+    pub versions: Vec<u8>,
+    /// Size of C `int` in bytes, instead of the one in the header.
+    pub size_int: Option<u8>,
+    /// Size of C `size_t` in bytes, instead of the one in the header.
+    pub size_t: Option<u8>,
+    /// Size of an instruction in bits, instead of the one in the header.
+    pub size_instr_arg: Option<u8>,
+    /// Size of the opcode field in bits, instead of the one in the header.
+    pub size_op: Option<u8>,
+    /// Size of the instruction argument `B` in bits, instead of the one in the header.
+    pub size_b: Option<u8>,
+    /// Number stored after the header to check the number format.
+    pub test_number: f64,
 }
 
 // ============================================================================
 
-/// Creates a mask with `n` 1 bits at position `p`.
-macro_rules! mask1 {
-    ($n:expr, $p:expr) => {
-        (!(!0u32 << $n) << $p)
-    };
+/// Creates a mask of the low `n` bits.
+fn mask(n: u32) -> u64 {
+    u64::MAX.checked_shr(64 - n).unwrap_or(0)
 }
 
 // ============================================================================
@@ -235,7 +603,7 @@ impl TryFrom<u32> for Opcode {
             7 => PushString,
             8 => PushNum,
             9 => PushNegNum,
-            10 => PushValue,
+            10 => PushUpvalue,
             11 => GetLocal,
             12 => GetGlobal,
             13 => GetTable,
@@ -274,11 +642,152 @@ impl TryFrom<u32> for Opcode {
             46 => LForPrep,
             47 => LForLoop,
             48 => Closure,
-            _ => return Error::new_decoder("unknown opcode: 0x{value:02x}").into(),
+            _ => return Error::new_decoder(format!("unknown opcode: 0x{value:02x}")).into(),
         })
     }
 }
 
+impl Opcode {
+    /// Name as printed by `luac -l`.
+    pub fn name(self) -> &'static str {
+        use Opcode::*;
+
+        match self {
+            End => "END",
+            Return => "RETURN",
+            Call => "CALL",
+            TailCall => "TAILCALL",
+            PushNil => "PUSHNIL",
+            Pop => "POP",
+            PushInt => "PUSHINT",
+            PushString => "PUSHSTRING",
+            PushNum => "PUSHNUM",
+            PushNegNum => "PUSHNEGNUM",
+            PushUpvalue => "PUSHUPVALUE",
+            GetLocal => "GETLOCAL",
+            GetGlobal => "GETGLOBAL",
+            GetTable => "GETTABLE",
+            GetDotted => "GETDOTTED",
+            GetIndexed => "GETINDEXED",
+            PushSelf => "PUSHSELF",
+            CreateTable => "CREATETABLE",
+            SetLocal => "SETLOCAL",
+            SetGlobal => "SETGLOBAL",
+            SetTable => "SETTABLE",
+            SetList => "SETLIST",
+            SetMap => "SETMAP",
+            Add => "ADD",
+            AddI => "ADDI",
+            Sub => "SUB",
+            Mult => "MULT",
+            Div => "DIV",
+            Pow => "POW",
+            Concat => "CONCAT",
+            Minus => "MINUS",
+            Not => "NOT",
+            JumpNe => "JMPNE",
+            JumpEq => "JMPEQ",
+            JumpLt => "JMPLT",
+            JumpLe => "JMPLE",
+            JumpGt => "JMPGT",
+            JumpGe => "JMPGE",
+            JumpTrue => "JMPT",
+            JumpFalse => "JMPF",
+            JumpOnTrue => "JMPONT",
+            JumpOnFalse => "JMPONF",
+            Jump => "JMP",
+            PushNilJump => "PUSHNILJMP",
+            ForPrep => "FORPREP",
+            ForLoop => "FORLOOP",
+            LForPrep => "LFORPREP",
+            LForLoop => "LFORLOOP",
+            Closure => "CLOSURE",
+            Unknown => "UNKNOWN",
+        }
+    }
+
+    /// Argument layout, as per the comments in `lopcodes.h`.
+    pub fn mode(self) -> OpMode {
+        use Opcode::*;
+
+        match self {
+            End | GetTable | Add | Sub | Mult | Div | Pow | Minus | Not | PushNilJump | Unknown => {
+                OpMode::None
+            }
+            Call | TailCall | SetTable | SetList | Closure => OpMode::AB,
+            PushInt | AddI | JumpNe | JumpEq | JumpLt | JumpLe | JumpGt | JumpGe | JumpTrue
+            | JumpFalse | JumpOnTrue | JumpOnFalse | Jump | ForPrep | ForLoop | LForPrep
+            | LForLoop => OpMode::S,
+            _ => OpMode::U,
+        }
+    }
+
+    /// Whether argument `S` is a jump offset.
+    pub fn is_jump(self) -> bool {
+        use Opcode::*;
+
+        matches!(
+            self,
+            JumpNe
+                | JumpEq
+                | JumpLt
+                | JumpLe
+                | JumpGt
+                | JumpGe
+                | JumpTrue
+                | JumpFalse
+                | JumpOnTrue
+                | JumpOnFalse
+                | Jump
+                | ForPrep
+                | ForLoop
+                | LForPrep
+                | LForLoop
+        )
+    }
+
+    /// Whether the parser decompiles instructions with the opcode,
+    /// instead of failing on them as unsupported.
+    pub fn is_supported(self) -> bool {
+        let instr = Instr {
+            opcode: self,
+            u: 0,
+            s: 0,
+            a: 0,
+            b: 0,
+        };
+        !matches!(instr.op(), Op::Unsupported { .. } | Op::Unknown)
+    }
+}
+
+impl Op {
+    /// Jump offset of a jump instruction, relative to the next instruction.
+    fn jump_offset(&self) -> Option<i32> {
+        match *self {
+            Op::JumpNe { ip }
+            | Op::JumpEq { ip }
+            | Op::JumpLt { ip }
+            | Op::JumpLe { ip }
+            | Op::JumpGt { ip }
+            | Op::JumpGe { ip }
+            | Op::JumpTrue { ip }
+            | Op::JumpFalse { ip }
+            | Op::JumpOnTrue { ip }
+            | Op::JumpOnFalse { ip }
+            | Op::Jump { ip } => Some(ip),
+            _ => None,
+        }
+    }
+
+    /// Whether execution can continue to the next instruction.
+    fn falls_through(&self) -> bool {
+        !matches!(
+            self,
+            Op::End | Op::Return { .. } | Op::TailCall { .. } | Op::Jump { .. }
+        )
+    }
+}
+
 impl Header {
     /// Size of instruction argument `U` (unsigned int).
     fn size_u(&self) -> u32 {
@@ -286,14 +795,14 @@ impl Header {
     }
 
     /// Max value of instruction argument `U` (unsigned int).
-    fn max_arg_u(&self) -> u32 {
-        (1 << self.size_u()) - 1
+    fn max_arg_u(&self) -> u64 {
+        mask(self.size_u())
     }
 
     /// Max value of instruction argument `S` (signed int).
-    fn max_arg_s(&self) -> i32 {
+    fn max_arg_s(&self) -> i64 {
         // 1 bit taken up by sign.
-        self.max_arg_u() as i32 >> 1
+        (self.max_arg_u() >> 1) as i64
     }
 
     /// Position of instruction argument `A`.
@@ -312,13 +821,133 @@ impl Header {
     }
 
     /// Max value of instruction argument `A`,
-    fn max_arg_a(&self) -> u32 {
-        (1 << self.size_a()) - 1
+    fn max_arg_a(&self) -> u64 {
+        mask(self.size_a())
     }
 
     /// Max value of instruction argument `B`,
-    fn max_arg_b(&self) -> u32 {
-        (1 << self.size_b) - 1
+    fn max_arg_b(&self) -> u64 {
+        mask(self.size_b as u32)
+    }
+
+    /// Check that the instruction fields fit in the instruction size.
+    fn check_instr_layout(&self) -> Result<()> {
+        if !matches!(self.size_instr, 4 | 8) {
+            return Error::new_unsupported(format!(
+                "instruction size of {} bytes",
+                self.size_instr
+            ))
+            .into();
+        }
+        let size_fields = self.size_op as u32 + self.size_b as u32;
+        if self.size_instr_arg as u32 > self.size_instr as u32 * 8
+            || size_fields >= self.size_instr_arg as u32
+        {
+            return Error::new_decoder(format!(
+                "instruction fields don't fit in {} bits: opcode {} bits, B {} bits",
+                self.size_instr_arg, self.size_op, self.size_b
+            ))
+            .into();
+        }
+        Ok(())
+    }
+
+    /// Decode an instruction word, laid out as per the header.
+    ///
+    /// Words of 32 bit instructions are widened to 64 bits,
+    /// like the words of [Proto::code].
+    pub fn decode_instr(&self, word: u64) -> Result<Instr> {
+        self.check_instr_layout()?;
+        let opcode = Opcode::try_from((word & mask(self.size_op as u32)) as u32)?;
+        let instr = self.split_instr(word, opcode);
+
+        // Arguments of 64 bit instructions can be too wide for the decoder.
+        let (u, a, _) = self.split_args(word);
+        let s = u as i128 - self.max_arg_s() as i128;
+        let fits = match opcode.mode() {
+            OpMode::None => true,
+            OpMode::U => u32::try_from(u).is_ok(),
+            OpMode::S => i32::try_from(s).is_ok(),
+            OpMode::AB => u32::try_from(a).is_ok(),
+        };
+        if fits {
+            Ok(instr)
+        } else {
+            Error::new_unsupported(format!(
+                "argument of {opcode:?} instruction out of range: {word:016x}"
+            ))
+            .into()
+        }
+    }
+
+    /// Decode an instruction word into its opcode and the arguments it uses.
+    pub fn decode(&self, word: u64) -> Result<(Opcode, Args)> {
+        let instr = self.decode_instr(word)?;
+        Ok((instr.opcode, instr.args()))
+    }
+
+    /// Encode an instruction word, laid out as per the header.
+    ///
+    /// The arguments must match the opcode's [OpMode] and fit in their fields.
+    pub fn encode(&self, opcode: Opcode, args: Args) -> Result<u64> {
+        self.check_instr_layout()?;
+        if opcode == Opcode::Unknown || opcode as u64 > mask(self.size_op as u32) {
+            return Error::new_encoder(format!(
+                "opcode {} doesn't fit in {} bits",
+                opcode.name(),
+                self.size_op
+            ))
+            .into();
+        }
+
+        let arg = match (opcode.mode(), args) {
+            (OpMode::None, Args::None) => Some(0),
+            (OpMode::U, Args::U(u)) => Some(u as u64).filter(|u| *u <= self.max_arg_u()),
+            (OpMode::S, Args::S(s)) => u64::try_from(s as i128 + self.max_arg_s() as i128)
+                .ok()
+                .filter(|u| *u <= self.max_arg_u()),
+            (OpMode::AB, Args::AB(a, b)) => (a as u64 <= self.max_arg_a()
+                && b as u64 <= self.max_arg_b())
+            .then(|| (a as u64) << self.size_b | b as u64),
+            (mode, _) => {
+                return Error::new_encoder(format!(
+                    "arguments of {} instruction don't match its mode {mode:?}: {args:?}",
+                    opcode.name()
+                ))
+                .into()
+            }
+        };
+        match arg {
+            Some(arg) => Ok(opcode as u64 | arg << self.size_op),
+            None => Error::new_encoder(format!(
+                "argument of {} instruction out of range: {args:?}",
+                opcode.name()
+            ))
+            .into(),
+        }
+    }
+
+    /// Split the arguments out of an instruction word.
+    ///
+    /// Arguments too wide for their fields are truncated.
+    fn split_instr(&self, word: u64, opcode: Opcode) -> Instr {
+        let (u, a, b) = self.split_args(word);
+        Instr {
+            opcode,
+            u: u as u32,
+            s: (u as i128 - self.max_arg_s() as i128) as i32,
+            a: a as u32,
+            b: b as u32,
+        }
+    }
+
+    /// Arguments `U`, `A` and `B` of an instruction word.
+    fn split_args(&self, word: u64) -> (u64, u64, u64) {
+        (
+            (word >> self.size_op) & self.max_arg_u(),
+            (word >> self.pos_arg_a()) & self.max_arg_a(),
+            (word >> self.pos_arg_b()) & self.max_arg_b(),
+        )
     }
 }
 
@@ -338,6 +967,21 @@ impl Default for Header {
     }
 }
 
+impl Default for HeaderProfile {
+    fn default() -> Self {
+        Self {
+            signature: SIGNATURE.as_bytes().to_vec(),
+            versions: vec![LUA_VERSION],
+            size_int: None,
+            size_t: None,
+            size_instr_arg: None,
+            size_op: None,
+            size_b: None,
+            test_number: TEST_NUMBER,
+        }
+    }
+}
+
 impl fmt::Display for Header {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         let Self {
@@ -355,66 +999,725 @@ impl fmt::Display for Header {
     }
 }
 
+impl Chunk {
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Top level function of the chunk.
+    pub fn main(&self) -> &Proto {
+        &self.main
+    }
+
+    pub fn main_mut(&mut self) -> &mut Proto {
+        &mut self.main
+    }
+
+    pub fn into_main(self) -> Proto {
+        self.main
+    }
+
+    /// Replace a string constant, returning the old value.
+    ///
+    /// The function is found by following the indices of nested functions
+    /// in `path`, starting from the main function. See [Proto::nested].
+    pub fn patch_string(
+        &mut self,
+        path: &[usize],
+        index: usize,
+        value: impl Into<LuaString>,
+    ) -> Result<LuaString> {
+        let proto = self.nested_for_patch(path)?;
+        match proto.constants.strings.get_mut(index) {
+            Some(string) => Ok(std::mem::replace(string, value.into())),
+            None => Error::new_patch(format!(
+                "no string constant {index} in function {}",
+                path_name(path)
+            ))
+            .into(),
+        }
+    }
+
+    /// Replace a number constant, returning the old value.
+    ///
+    /// The function is found as in [Chunk::patch_string].
+    pub fn patch_number(&mut self, path: &[usize], index: usize, value: f64) -> Result<f64> {
+        let proto = self.nested_for_patch(path)?;
+        match proto.constants.numbers.get_mut(index) {
+            Some(number) => Ok(std::mem::replace(number, value)),
+            None => Error::new_patch(format!(
+                "no number constant {index} in function {}",
+                path_name(path)
+            ))
+            .into(),
+        }
+    }
+
+    fn nested_for_patch(&mut self, path: &[usize]) -> Result<&mut Proto> {
+        match self.main.nested_mut(path) {
+            Some(proto) => Ok(proto),
+            None => Error::new_patch(format!("no function {}", path_name(path))).into(),
+        }
+    }
+}
+
+impl Proto {
+    pub fn source(&self) -> &str {
+        self.source.as_str()
+    }
+
+    pub fn line_defined(&self) -> u32 {
+        self.line_defined
+    }
+
+    pub fn num_params(&self) -> u32 {
+        self.num_params
+    }
+
+    pub fn is_vararg(&self) -> bool {
+        self.is_vararg
+    }
+
+    pub fn max_stack(&self) -> u32 {
+        self.max_stack
+    }
+
+    /// Raw instruction words, widened to 64 bits.
+    ///
+    /// Instructions are 32 bits wide, unless the header's
+    /// [Header::size_instr] says the chunk uses 64 bit instructions.
+    pub fn code(&self) -> &[u64] {
+        &self.code
+    }
+
+    pub fn instrs(&self) -> &[Instr] {
+        &self.instrs
+    }
+
+    /// Instructions with their arguments named after what they mean for the opcode.
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Decoded instructions, with the constants and jump targets they refer to.
+    pub fn instructions(&self) -> impl Iterator<Item = Instruction<'_>> + '_ {
+        self.instrs
+            .iter()
+            .zip(self.code.iter())
+            .enumerate()
+            .map(|(offset, (instr, word))| Instruction {
+                offset,
+                word: *word,
+                opcode: instr.opcode,
+                args: instr.args(),
+                constant: instr.constant(self),
+                target: instr.jump_target(offset),
+            })
+    }
+
+    pub fn strings(&self) -> &[LuaString] {
+        &self.constants.strings
+    }
+
+    pub fn numbers(&self) -> &[f64] {
+        &self.constants.numbers
+    }
+
+    /// String constants, for patching. The number of constants can't change,
+    /// since instructions refer to them by index.
+    pub fn strings_mut(&mut self) -> &mut [LuaString] {
+        &mut self.constants.strings
+    }
+
+    /// Number constants, for patching. See [Proto::strings_mut].
+    pub fn numbers_mut(&mut self) -> &mut [f64] {
+        &mut self.constants.numbers
+    }
+
+    /// Nested functions.
+    pub fn protos(&self) -> &[Proto] {
+        &self.constants.protos
+    }
+
+    pub fn protos_mut(&mut self) -> &mut [Proto] {
+        &mut self.constants.protos
+    }
+
+    /// Nested function found by following the index of each
+    /// function in `path`. The empty path is this function.
+    pub fn nested(&self, path: &[usize]) -> Option<&Proto> {
+        path.iter()
+            .try_fold(self, |proto, index| proto.constants.protos.get(*index))
+    }
+
+    pub fn nested_mut(&mut self, path: &[usize]) -> Option<&mut Proto> {
+        path.iter()
+            .try_fold(self, |proto, index| proto.constants.protos.get_mut(*index))
+    }
+
+    /// Whether the chunk ended partway through the function, when decoded
+    /// with a tolerant [Decoder]. Only what was read before is present,
+    /// which for the code is the instructions before the end.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Whether the function has debug information, which `luac -s` strips.
+    ///
+    /// Without it, local variables have to be named and
+    /// their scopes inferred from the instructions.
+    pub fn has_debug_info(&self) -> bool {
+        !self.locals.is_empty() || !self.lines.is_empty()
+    }
+
+    /// Remove the debug information of the function and
+    /// its nested functions, like `luac -s`.
+    pub fn strip(&mut self) {
+        self.locals = Box::new([]);
+        self.lines = Box::new([]);
+        for child in self.constants.protos.iter_mut() {
+            child.strip();
+        }
+    }
+
+    /// Local variable debug information, empty when stripped.
+    pub fn locals(&self) -> &[Local] {
+        &self.locals
+    }
+
+    /// Encoded line information, empty when stripped. See [Proto::line_at].
+    pub fn lines(&self) -> &[u32] {
+        &self.lines
+    }
+
+    /// Disassembly listing similar to `luac -l`.
+    pub fn dump(&self) -> ProtoDump<'_> {
+        ProtoDump { proto: self }
+    }
+
+    /// Disassembly listing of the instructions in the range,
+    /// without the function header.
+    pub fn dump_range(&self, range: Range<usize>) -> RangeDump<'_> {
+        RangeDump { proto: self, range }
+    }
+
+    /// Source line of the instruction at index `pc`, when debug information is present.
+    ///
+    /// Line info is a sequence of instruction indices, each starting the next line.
+    /// Negative entries skip the given number of extra lines, as per `luaG_getline`.
+    /// Counting starts from line 1 in every function, like `currentline` in `ldebug.c`,
+    /// since the compiler starts each function's line info from line 0.
+    pub fn line_at(&self, pc: usize) -> Option<u32> {
+        let info = |index: usize| self.lines.get(index).map(|n| *n as i32);
+
+        let mut line = 1;
+        let mut index = 0;
+        if info(index)? < 0 {
+            line -= info(index)? as i64;
+            index += 1;
+        }
+        if info(index)? as i64 > pc as i64 {
+            return None;
+        }
+        loop {
+            let mut next_line = line + 1;
+            let mut next_index = index + 1;
+            match info(next_index) {
+                Some(skip) if skip < 0 => {
+                    next_line -= skip as i64;
+                    next_index += 1;
+                }
+                Some(_) => {}
+                None => break,
+            }
+            match info(next_index) {
+                Some(next_pc) if next_pc as i64 <= pc as i64 => {
+                    line = next_line;
+                    index = next_index;
+                }
+                _ => break,
+            }
+        }
+        Some(line as u32)
+    }
+
+    /// Describes an instruction for error messages.
+    fn instr_context(&self, pc: usize) -> String {
+        let mut context = format!(
+            "function {}:{}, instruction {}",
+            self.source,
+            self.line_defined,
+            pc + 1
+        );
+        if let Some(instr) = self.instrs.get(pc) {
+            context.push_str(&format!(" ({})", instr.opcode.name()));
+        }
+        context
+    }
+
+    /// Number of local variables live at the instruction,
+    /// or `None` when the debug information is stripped.
+    pub fn active_locals(&self, pc: usize) -> Option<u32> {
+        if self.locals.is_empty() {
+            return None;
+        }
+        let pc = pc as u32;
+        let count = self
+            .locals
+            .iter()
+            .filter(|local| local.startpc <= pc && pc < local.endpc)
+            .count();
+        Some(count as u32)
+    }
+
+    /// Name of the nth active local variable at the instruction, as per `luaF_getlocalname`.
+    pub fn local_name(&self, mut n: u32, pc: usize) -> Option<&str> {
+        let pc = pc as u32;
+        for local in self.locals.iter().take_while(|local| local.startpc <= pc) {
+            if pc < local.endpc {
+                if n == 0 {
+                    return Some(local.varname.as_str());
+                }
+                n -= 1;
+            }
+        }
+        None
+    }
+}
+
+/// Decompile a chunk into source, with the default style.
+///
+/// Shorthand for decoding with a [Decoder], parsing with a [Parser]
+/// and writing the syntax tree with a [Scribe]. The source is checked
+/// with [check_syntax] before it's returned.
+pub fn decompile(code: &[u8]) -> Result<String> {
+    let proto = Decoder::new(code).decode()?;
+    let syntax = Parser::new(&proto).parse()?;
+    let mut buf = String::new();
+    Scribe::default().fmt_syntax(&mut buf, &syntax)?;
+    check_syntax(&buf)?;
+    Ok(buf)
+}
+
+/// Decompile a chunk into source, with the default style, tolerating
+/// problems as per the options.
+///
+/// Diagnostics of the problems are returned with the source.
+pub fn decompile_with(code: &[u8], options: &DecompileOptions) -> Result<Output> {
+    let mut decoder = Decoder::new(code).with_tolerance(options.tolerance);
+    let proto = decoder.decode()?;
+    let mut diagnostics = decoder.into_diagnostics();
+
+    let mut parser = Parser::new(&proto).with_tolerance(options.tolerance);
+    let syntax = parser.parse()?;
+    diagnostics.extend(parser.into_diagnostics());
+
+    let mut source = String::new();
+    Scribe::default().fmt_syntax(&mut source, &syntax)?;
+    check_syntax(&source)?;
+    Ok(Output {
+        source,
+        diagnostics,
+    })
+}
+
+/// Time spent in each stage of decompiling a chunk, for profiling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub decode: Duration,
+    pub parse: Duration,
+    pub write: Duration,
+    /// Number of instructions in the chunk, including its nested functions.
+    pub instructions: usize,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.decode + self.parse + self.write
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "decode {:.2?}, parse {:.2?}, write {:.2?}, total {:.2?} for {} instructions",
+            self.decode,
+            self.parse,
+            self.write,
+            self.total(),
+            self.instructions
+        )
+    }
+}
+
+/// Decompile a chunk into source, with the default style,
+/// timing each stage like [decompile] would run it.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn decompile_timed(code: &[u8]) -> Result<(String, Timings)> {
+    use std::time::Instant;
+
+    let start = Instant::now();
+    let proto = Decoder::new(code).decode()?;
+    let decode = start.elapsed();
+
+    let start = Instant::now();
+    let syntax = Parser::new(&proto).parse()?;
+    let parse = start.elapsed();
+
+    let start = Instant::now();
+    let mut buf = String::new();
+    Scribe::default().fmt_syntax(&mut buf, &syntax)?;
+    check_syntax(&buf)?;
+    let write = start.elapsed();
+
+    let timings = Timings {
+        decode,
+        parse,
+        write,
+        instructions: proto.stats().instructions,
+    };
+    Ok((buf, timings))
+}
+
+/// Function parsed into a syntax tree, owning both.
+///
+/// A [Parser] borrows the function it parses, which gets in the way of
+/// keeping results around, like in a cache. The syntax tree doesn't borrow
+/// the function, so this keeps them together without a lifetime.
+#[derive(Debug)]
+pub struct Decompiled {
+    proto: Proto,
+    syntax: Syntax,
+}
+
+impl Decompiled {
+    /// Parse the function, failing on the first error.
+    pub fn parse(proto: Proto) -> Result<Self> {
+        Self::parse_impl(proto, false)
+    }
+
+    /// Parse the function, recovering from errors as per [Parser::lenient].
+    pub fn parse_lenient(proto: Proto) -> Result<Self> {
+        Self::parse_impl(proto, true)
+    }
+
+    fn parse_impl(proto: Proto, lenient: bool) -> Result<Self> {
+        let syntax = Parser::new(&proto).lenient(lenient).parse()?;
+        Ok(Self { proto, syntax })
+    }
+
+    pub fn proto(&self) -> &Proto {
+        &self.proto
+    }
+
+    pub fn syntax(&self) -> &Syntax {
+        &self.syntax
+    }
+
+    pub fn into_parts(self) -> (Proto, Syntax) {
+        (self.proto, self.syntax)
+    }
+}
+
+/// Decode only the chunk header.
+///
+/// This is much cheaper than decoding the whole chunk, and is
+/// useful for identifying files without reading constants and code.
+pub fn read_header(code: &[u8]) -> Result<Header> {
+    let mut decoder = Decoder::new(code);
+    decoder.read_header()?;
+    Ok(decoder.header)
+}
+
+/// Locate the fields of the chunk header, up to the first malformed one,
+/// so a corrupted or truncated chunk shows where decoding goes wrong.
+pub fn annotate_header(code: &[u8]) -> HeaderDump<'_> {
+    let mut decoder = Decoder::new(code);
+    // The error is recorded with the field it's in.
+    let _ = decoder.read_header();
+    let mut fields = decoder.header_fields;
+    // Reads that run out of bytes don't consume any,
+    // so the bytes that are left are shown instead.
+    if let Some(field) = fields.last_mut() {
+        if field.value.is_err() && field.range.is_empty() {
+            field.range.end = code.len().min(field.range.start + 8);
+        }
+    }
+    HeaderDump { code, fields }
+}
+
 impl<'a> Decoder<'a> {
     pub fn new(code: &'a [u8]) -> Self {
+        Self::from_reader(Cursor::new(code))
+    }
+}
+
+impl<'a, R: Read> Decoder<'a, R> {
+    /// Decoder that reads the chunk from its current position in the reader.
+    ///
+    /// The chunk doesn't need to be at the start, so a reader positioned
+    /// in an archive can decode an embedded chunk without copying it out.
+    /// Unbuffered readers, like files, should be wrapped in a [std::io::BufReader].
+    pub fn from_reader(reader: R) -> Self {
         Self {
-            code,
-            cursor: Cursor::new(code),
+            reader: CodeReader::from_reader(reader),
             header: Header::default(),
+            trace: &NoTrace,
+            limits: Limits::default(),
+            tolerance: Tolerance::default(),
+            depth: 0,
+            path: vec![],
+            diagnostics: Diagnostics::new(),
+            transformer: None,
+            profile: HeaderProfile::default(),
+            header_fields: vec![],
+            max_preamble: 0,
         }
     }
 
+    /// Send diagnostic events to the given sink.
+    pub fn with_trace(mut self, trace: &'a dyn Trace) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Fail to decode chunks that exceed the limits.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Expect the header layout of a modified engine.
+    pub fn with_profile(mut self, profile: HeaderProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Skip up to `max_len` bytes before the bytemark and signature, like
+    /// a `#!` line or the header of a distribution format, instead of
+    /// failing on the bytemark. The skipped bytes are searched for the
+    /// signature as they're read, so they can come from any reader.
+    pub fn with_preamble(mut self, max_len: usize) -> Self {
+        self.max_preamble = max_len;
+        self
+    }
+
+    /// Rewrite each string and number constant with the transformer after reading it.
+    pub fn with_transformer(mut self, transformer: &'a dyn ConstantTransformer) -> Self {
+        self.transformer = Some(transformer);
+        self
+    }
+
+    /// Decode instructions with unknown opcodes as [Opcode::Unknown], and keep
+    /// what was read of a chunk that ends partway through a function, with
+    /// a warning, unless the tolerance is strict.
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
     pub fn decode(&mut self) -> Result<Proto> {
-        self.read_bytemark()?;
-        self.read_signature()?;
-        self.header = Header {
-            version: self.read_version()?,
-            endianess: self.read_endianess()?,
-            size_int: self.read_u8()?,
-            size_t: self.read_u8()?,
-            size_instr: self.read_u8()?,
-            size_instr_arg: self.read_u8()?,
-            size_op: self.read_u8()?,
-            size_b: self.read_u8()?,
-            number_type: {
-                let size_number = self.read_u8()?;
-                match size_number {
-                    4 => NumberType::F32,
-                    8 => NumberType::F64,
-                    _ => return Error::new_decoder("unknown number size: {size_number}").into(),
-                }
-            },
-        };
+        Ok(self.decode_chunk()?.main)
+    }
+
+    /// Problems tolerated while decoding, like unknown opcodes.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
 
-        // println!("endianess: {endianess:?}; int: {size_int}B; size_t: {size_t}B; instruction: {size_instr1}B; args: {size_instr_args}b; op: {size_op}b; B: {size_b}b; Number: {size_number}B");
-        println!("{}", self.header);
+    pub fn into_diagnostics(self) -> Diagnostics {
+        self.diagnostics
+    }
 
-        self.check_number_format(self.header.number_type, self.header.endianess)?;
-        println!("number format check passed");
+    /// Number of bytes decoded so far, which after decoding is the size of the chunk.
+    pub fn position(&self) -> u64 {
+        self.reader.position()
+    }
+
+    /// Decode the whole chunk, keeping the header.
+    pub fn decode_chunk(&mut self) -> Result<Chunk> {
+        self.read_header()?;
+
+        trace_event!(self.trace, Level::Info, "{}", self.header);
 
         // Top level function
         let proto = self.read_function()?;
 
-        println!("{proto:#?}");
+        trace_event!(self.trace, Level::Trace, "{proto:#?}");
 
-        Ok(proto)
+        Ok(Chunk {
+            header: self.header.clone(),
+            main: proto,
+        })
     }
 }
 
-impl<'a> Decoder<'a> {
+impl<'a, R: Read> Decoder<'a, R> {
+    fn read_header(&mut self) -> Result<()> {
+        let bytes = |size: &u8| format!("{size} bytes");
+        let bits = |size: &u8| format!("{size} bits");
+
+        let signature = String::from_utf8_lossy(&self.profile.signature).into_owned();
+        if self.max_preamble > 0 {
+            self.skip_preamble(signature)?;
+        } else {
+            self.header_field("bytemark", Self::read_bytemark, |_| "ESC".to_string())?;
+            self.header_field("signature", Self::read_signature, |_| signature)?;
+        }
+        self.header.version = self.header_field("version", Self::read_version, |version| {
+            format!("{}.{}", version >> 4, version & 0xf)
+        })?;
+        self.header.endianess =
+            self.header_field("endianness", Self::read_endianess, |endian| {
+                format!("{endian:?}").to_lowercase()
+            })?;
+        self.header.size_int =
+            self.header_field("int size", |d| d.read_size(d.profile.size_int), bytes)?;
+        self.header.size_t =
+            self.header_field("size_t size", |d| d.read_size(d.profile.size_t), bytes)?;
+        self.header.size_instr =
+            self.header_field("instruction size", |d| d.reader.read_u8(), bytes)?;
+        self.header.size_instr_arg = self.header_field(
+            "instruction bits",
+            |d| d.read_size(d.profile.size_instr_arg),
+            bits,
+        )?;
+        self.header.size_op =
+            self.header_field("opcode bits", |d| d.read_size(d.profile.size_op), bits)?;
+        // Instructions are laid out once the last of their sizes is known.
+        self.header.size_b = self.header_field(
+            "B bits",
+            |d| {
+                d.header.size_b = d.read_size(d.profile.size_b)?;
+                d.header.check_instr_layout()?;
+                Ok(d.header.size_b)
+            },
+            bits,
+        )?;
+        self.header.number_type = self.header_field(
+            "number size",
+            |d| match d.reader.read_u8()? {
+                4 => Ok(NumberType::F32),
+                8 => Ok(NumberType::F64),
+                size => Error::new_decoder(format!("unknown number size: {size}")).into(),
+            },
+            |number_type| match number_type {
+                NumberType::F32 | NumberType::I32 => "4 bytes".to_string(),
+                NumberType::F64 | NumberType::I64 => "8 bytes".to_string(),
+            },
+        )?;
+        self.reader.set_endian(self.header.endianess);
+        self.reader.set_size_int(self.header.size_int as usize);
+        self.reader.set_size_t(self.header.size_t as usize);
+
+        let test_number = self.profile.test_number;
+        self.header.number_type = self.header_field(
+            "test number",
+            |d| d.check_number_format(d.header.number_type),
+            |number_type| format!("{test_number} as {number_type:?}"),
+        )?;
+        trace_event!(self.trace, Level::Debug, "number format check passed");
+
+        Ok(())
+    }
+
+    /// Read a field of the header, recording where it is and its value,
+    /// and locating errors at the field.
+    fn header_field<T>(
+        &mut self,
+        name: &'static str,
+        read: impl FnOnce(&mut Self) -> Result<T>,
+        describe: impl FnOnce(&T) -> String,
+    ) -> Result<T> {
+        let start = self.reader.position() as usize;
+        let result = read(self);
+        let end = self.reader.position() as usize;
+        self.header_fields.push(HeaderField {
+            name,
+            range: start..end,
+            value: match &result {
+                Ok(value) => Ok(describe(value)),
+                Err(err) => Err(err.to_string()),
+            },
+        });
+        result.map_err(|err| err.with_context(format!("chunk header {name}, at byte {start}")))
+    }
+
+    /// Read up to the end of the bytemark and signature, skipping the bytes
+    /// before them, which can't be put back once read from the reader.
+    fn skip_preamble(&mut self, signature: String) -> Result<()> {
+        let start = self.reader.position() as usize;
+        let mut expected = vec![ID_CHUNK];
+        expected.extend_from_slice(&self.profile.signature);
+        let mut window = VecDeque::with_capacity(expected.len());
+        while !window.iter().eq(expected.iter()) {
+            let read = self.reader.position() as usize - start;
+            let result = if read < self.max_preamble + expected.len() {
+                self.reader.read_u8()
+            } else {
+                Error::new_decoder(format!(
+                    "no chunk signature after skipping up to {} bytes",
+                    self.max_preamble
+                ))
+                .into()
+            };
+            match result {
+                Ok(byte) => {
+                    if window.len() == expected.len() {
+                        window.pop_front();
+                    }
+                    window.push_back(byte);
+                }
+                Err(err) => {
+                    self.header_fields.push(HeaderField {
+                        name: "preamble",
+                        range: start..start + read,
+                        value: Err(err.to_string()),
+                    });
+                    return Err(err);
+                }
+            }
+        }
+
+        let end = self.reader.position() as usize;
+        let bytemark = end - expected.len();
+        if bytemark > start {
+            self.header_fields.push(HeaderField {
+                name: "preamble",
+                range: start..bytemark,
+                value: Ok(format!("{} bytes skipped", bytemark - start)),
+            });
+        }
+        self.header_fields.push(HeaderField {
+            name: "bytemark",
+            range: bytemark..bytemark + 1,
+            value: Ok("ESC".to_string()),
+        });
+        self.header_fields.push(HeaderField {
+            name: "signature",
+            range: bytemark + 1..end,
+            value: Ok(signature),
+        });
+        Ok(())
+    }
+
     fn read_bytemark(&mut self) -> Result<()> {
-        let bytemark = self.read_u8()?;
+        let bytemark = self.reader.read_u8()?;
         if bytemark == ID_CHUNK {
             Ok(())
         } else {
-            Error::new_decoder("chunk bytemark must be 'Esc'(27), found: {bytemark}").into()
+            Error::new_decoder(format!(
+                "chunk bytemark must be 'Esc'(27), found: {bytemark}"
+            ))
+            .into()
         }
     }
 
     fn read_signature(&mut self) -> Result<()> {
-        let mut buf = [0u8; SIGNATURE.len()];
-        self.cursor.read_exact(&mut buf)?;
-        if buf == SIGNATURE.as_bytes() {
+        let mut buf = vec![0u8; self.profile.signature.len()];
+        self.reader.read_bytes(&mut buf)?;
+        if buf == self.profile.signature {
             Ok(())
         } else {
             Error::new_decoder("bad signature").into()
@@ -423,21 +1726,32 @@ impl<'a> Decoder<'a> {
 
     /// Returns version.
     fn read_version(&mut self) -> Result<u8> {
-        let version = self.read_u8()?;
-        if version == LUA_VERSION {
+        let version = self.reader.read_u8()?;
+        if self.profile.versions.contains(&version) {
             Ok(version)
+        } else if self.profile.versions == [LUA_VERSION] {
+            Error::new_decoder(format!(
+                "expected Lua version 4.0(0x40), found: {version:02x}"
+            ))
+            .into()
         } else {
-            Error::new_decoder("expected Lua version 4.0(0x40), found: {version:02x}").into()
+            Error::new_decoder(format!("unexpected version: {version:02x}")).into()
         }
     }
 
+    /// Read a size field of the header, which the profile may override.
+    fn read_size(&mut self, size: Option<u8>) -> Result<u8> {
+        let declared = self.reader.read_u8()?;
+        Ok(size.unwrap_or(declared))
+    }
+
     fn read_endianess(&mut self) -> Result<Endian> {
         // Endianess is determined in C by casting a 32-bit
         // integer to a 8-bit character.
         //
         //  int x = 1;
         //  char endian = *(char *)&x;
-        let mark = self.read_u8()?;
+        let mark = self.reader.read_u8()?;
         if mark == 0 {
             Ok(Endian::Big)
         } else {
@@ -445,284 +1759,623 @@ impl<'a> Decoder<'a> {
         }
     }
 
-    fn check_number_format(&mut self, number: NumberType, _endianess: Endian) -> Result<()> {
-        match number {
-            NumberType::F32 => {
-                if self.read_f32()? == TEST_NUMBER as f32 {
-                    Ok(())
+    /// Read the test number to confirm the number format.
+    ///
+    /// The header only gives the size of numbers. Luas patched to use
+    /// integers store the test number truncated, which tells them apart
+    /// from floating point numbers of the same size.
+    fn check_number_format(&mut self, number_type: NumberType) -> Result<NumberType> {
+        let test_number = self.profile.test_number;
+        match number_type {
+            NumberType::F32 | NumberType::I32 => {
+                let bits = self.reader.read_u32()?;
+                trace_event!(self.trace, Level::Trace, "test number: {bits:08x}");
+                if f32::from_bits(bits) == test_number as f32 {
+                    Ok(NumberType::F32)
+                } else if bits as i32 == test_number as i32 {
+                    Ok(NumberType::I32)
                 } else {
-                    Error::new_decoder("unknown f32 number format").into()
+                    Error::new_decoder("unknown 4 byte number format").into()
                 }
             }
-            NumberType::F64 => {
-                let f = self.read_f64()?;
-                println!("f: {f}");
-                if f == TEST_NUMBER {
-                    Ok(())
+            NumberType::F64 | NumberType::I64 => {
+                let bits = self.reader.read_u64()?;
+                trace_event!(self.trace, Level::Trace, "test number: {bits:016x}");
+                if f64::from_bits(bits) == test_number {
+                    Ok(NumberType::F64)
+                } else if bits as i64 == test_number as i64 {
+                    Ok(NumberType::I64)
                 } else {
-                    Error::new_decoder("unknown f64 number format").into()
+                    Error::new_decoder("unknown 8 byte number format").into()
                 }
             }
         }
     }
 
     fn read_function(&mut self) -> Result<Proto> {
-        let source = self.read_string()?;
-        let line_defined = self.read_u32()?;
-        let num_params = self.read_u32()?;
-        let is_vararg = self.read_u8()? != 0;
-        let max_stack = self.read_u32()?;
-
-        let locals = self.read_locals()?;
-        let lines = self.read_lines()?;
-        let constants = self.read_constants()?;
-        let code = self.read_code()?;
+        if self.depth >= self.limits.max_depth {
+            return Error::new_decoder(format!(
+                "functions nested deeper than the limit of {}",
+                self.limits.max_depth
+            ))
+            .into();
+        }
+        self.depth += 1;
+        let proto = self.read_function_body();
+        self.depth -= 1;
+        proto
+    }
 
-        let mut ops: Box<[Op]> = (0..code.len()).into_iter().map(|_| Op::End).collect();
-        for (index, instr) in code.iter().cloned().enumerate() {
-            ops[index] = self.decode_op(instr)?;
+    fn read_function_body(&mut self) -> Result<Proto> {
+        let mut parts = ProtoParts::default();
+        let truncated = match self.read_parts(&mut parts) {
+            Ok(()) => false,
+            // What was read before the chunk ended is kept,
+            // like the functions nested before the one it ended in.
+            Err(err) if err.is_truncated() && self.tolerance.is_tolerant() => {
+                let message = format!(
+                    "chunk ends partway through the function, after {} instructions",
+                    parts.code.len()
+                );
+                trace_event!(
+                    self.trace,
+                    Level::Warn,
+                    "{message} (function {})",
+                    path_name(&self.path)
+                );
+                self.diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    path: self.path.clone(),
+                    offset: None,
+                    message,
+                });
+                true
+            }
+            Err(err) => return Err(err),
+        };
+        let ProtoParts {
+            source,
+            line_defined,
+            num_params,
+            is_vararg,
+            max_stack,
+            locals,
+            lines,
+            strings,
+            numbers,
+            protos,
+            code,
+        } = parts;
+
+        let mut instrs = Vec::with_capacity(code.len());
+        for (pc, word) in code.iter().enumerate() {
+            let instr = match self.header.decode_instr(*word) {
+                Ok(instr) => instr,
+                Err(err) if self.tolerance.is_tolerant() => {
+                    trace_event!(
+                        self.trace,
+                        Level::Warn,
+                        "{err} (function {source}:{line_defined}, instruction {})",
+                        pc + 1
+                    );
+                    self.diagnostics.push(Diagnostic {
+                        severity: Severity::Warning,
+                        path: self.path.clone(),
+                        offset: Some(pc),
+                        message: err.to_string(),
+                    });
+                    self.header.split_instr(*word, Opcode::Unknown)
+                }
+                Err(err) => {
+                    return Err(err
+                        .with_context(format!(
+                            "function {source}:{line_defined}, instruction {}",
+                            pc + 1
+                        ))
+                        .with_location(Location {
+                            path: self.path.clone(),
+                            offset: Some(pc),
+                            opcode: None,
+                        }))
+                }
+            };
+            instrs.push(instr);
         }
+        let instrs = instrs.into_boxed_slice();
 
-        assert_eq!(code.len(), ops.len());
+        let ops = instrs.iter().map(Instr::op).collect();
 
         Ok(Proto {
-            code,
+            code: code.into_boxed_slice(),
+            instrs,
             ops,
             source,
             line_defined,
             num_params,
             is_vararg,
             max_stack,
-            locals,
-            constants,
-            lines,
+            locals: locals.into_boxed_slice(),
+            constants: Constants {
+                strings: strings.into_boxed_slice(),
+                numbers: numbers.into_boxed_slice(),
+                protos: protos.into_boxed_slice(),
+            },
+            lines: lines.into_boxed_slice(),
+            truncated,
         })
     }
 
-    fn read_string(&mut self) -> Result<String> {
-        // TODO: dynamic size_t and endianess
-        let len = self.read_size_t()?;
-        let mut buf = vec![0u8; len];
-        self.cursor.read_exact(&mut buf)?;
-        let c_string =
-            CString::from_vec_with_nul(buf).map_err(|err| Error::new_decoder(format!("{err}")))?;
-        let string = c_string
-            .into_string()
-            .map_err(|err| Error::new_decoder(format!("{err}")))?;
-        Ok(string)
+    /// Read the parts of a function in the order they're stored,
+    /// adding to the parts as they're read.
+    ///
+    /// Errors are located at the part of the function that failed to read.
+    fn read_parts(&mut self, parts: &mut ProtoParts) -> Result<()> {
+        let offset = self.reader.position();
+        self.read_function_header(parts)
+            .map_err(|err| self.part_context(err, "header", offset))?;
+        let offset = self.reader.position();
+        self.read_locals(&mut parts.locals)
+            .map_err(|err| self.part_context(err, "locals", offset))?;
+        let offset = self.reader.position();
+        self.read_lines(&mut parts.lines)
+            .map_err(|err| self.part_context(err, "line info", offset))?;
+        let offset = self.reader.position();
+        self.read_constants(parts)
+            .map_err(|err| self.part_context(err, "constants", offset))?;
+        let offset = self.reader.position();
+        self.read_code(&mut parts.code)
+            .map_err(|err| self.part_context(err, "code", offset))
     }
 
-    fn read_size_t(&mut self) -> Result<usize> {
-        match self.header.size_t {
-            2 => Ok(self.read_u16()? as usize),
-            4 => Ok(self.read_u32()? as usize),
-            8 => Ok(self.read_u64()? as usize),
-            _ => Error::new_decoder(format!("unknown size_t: {}", self.header.size_t)).into(),
+    fn read_function_header(&mut self, parts: &mut ProtoParts) -> Result<()> {
+        parts.source = self.reader.read_string()?;
+        parts.line_defined = self.reader.read_u32()?;
+        parts.num_params = self.reader.read_u32()?;
+        parts.is_vararg = self.reader.read_u8()? != 0;
+        parts.max_stack = self.reader.read_u32()?;
+        if parts.num_params > MAX_PARAMS {
+            return Error::new_decoder(format!(
+                "{} parameters exceed the limit of {MAX_PARAMS}",
+                parts.num_params
+            ))
+            .into();
         }
+        if parts.num_params > parts.max_stack {
+            return Error::new_decoder(format!(
+                "{} parameters don't fit in a stack of {}",
+                parts.num_params, parts.max_stack
+            ))
+            .into();
+        }
+        Ok(())
+    }
+
+    /// Locate an error in the part of the function starting at the byte offset.
+    ///
+    /// Errors in nested functions keep the context of the function they're in.
+    fn part_context(&self, err: Error, part: &str, offset: u64) -> Error {
+        err.with_context(format!(
+            "{part} of function {}, at byte {offset}",
+            path_name(&self.path)
+        ))
+        .with_location(Location {
+            path: self.path.clone(),
+            offset: None,
+            opcode: None,
+        })
     }
 
-    fn read_locals(&mut self) -> Result<Box<[Local]>> {
-        let n = self.read_u32()?;
-        let mut locals = vec![];
+    fn read_locals(&mut self, locals: &mut Vec<Local>) -> Result<()> {
+        let n = self.read_count("local", u32::MAX)?;
         for _ in 0..n {
             locals.push(Local {
-                varname: self.read_string()?,
-                startpc: self.read_u32()?,
-                endpc: self.read_u32()?,
+                varname: self.reader.read_string()?,
+                startpc: self.reader.read_u32()?,
+                endpc: self.reader.read_u32()?,
             });
         }
-        Ok(locals.into_boxed_slice())
+        Ok(())
     }
 
-    fn read_lines(&mut self) -> Result<Box<[u32]>> {
-        let n = self.read_u32()?;
-        let mut lines = vec![];
+    fn read_lines(&mut self, lines: &mut Vec<u32>) -> Result<()> {
+        let n = self.read_count("line info", u32::MAX)?;
         for _ in 0..n {
-            lines.push(self.read_u32()?);
+            lines.push(self.reader.read_u32()?);
         }
-        Ok(lines.into_boxed_slice())
+        Ok(())
     }
 
-    fn read_constants(&mut self) -> Result<Constants> {
-        let mut strings = vec![];
-        let mut numbers = vec![];
-        let mut protos = vec![];
+    fn read_constants(&mut self, parts: &mut ProtoParts) -> Result<()> {
+        let max = self.limits.max_constants;
+        for index in 0..self.read_count("string constant", max)? as usize {
+            let mut string = self.reader.read_lua_string()?;
+            if let Some(transformer) = self.transformer {
+                string = transformer
+                    .transform_string(&self.path, index, string)
+                    .map_err(|err| self.constant_context(err, "string", index))?;
+            }
+            parts.strings.push(string);
+        }
 
-        for _ in 0..self.read_u32()? {
-            strings.push(self.read_string()?);
+        for index in 0..self.read_count("number constant", max)? as usize {
+            let mut number = self.reader.read_number(self.header.number_type)?;
+            if let Some(transformer) = self.transformer {
+                number = transformer
+                    .transform_number(&self.path, index, number)
+                    .map_err(|err| self.constant_context(err, "number", index))?;
+            }
+            parts.numbers.push(number);
         }
 
-        for _ in 0..self.read_u32()? {
-            numbers.push(self.read_f64()?);
+        for index in 0..self.read_count("function", max)? {
+            self.path.push(index as usize);
+            let proto = self.read_function();
+            self.path.pop();
+            parts.protos.push(proto?);
         }
 
-        for _ in 0..self.read_u32()? {
-            protos.push(self.read_function()?);
+        Ok(())
+    }
+
+    /// Locate an error from the constant transformer.
+    fn constant_context(&self, err: Error, kind: &str, index: usize) -> Error {
+        err.with_context(format!(
+            "{kind} constant {index} of function {}",
+            path_name(&self.path)
+        ))
+    }
+
+    fn read_code(&mut self, code: &mut Vec<u64>) -> Result<()> {
+        for _ in 0..self.read_count("instruction", self.limits.max_code)? {
+            let word = match self.header.size_instr {
+                8 => self.reader.read_u64()?,
+                _ => self.reader.read_u32()? as u64,
+            };
+            code.push(word);
         }
+        Ok(())
+    }
+}
 
-        Ok(Constants {
-            strings: strings.into_boxed_slice(),
-            numbers: numbers.into_boxed_slice(),
-            protos: protos.into_boxed_slice(),
-        })
+impl<'a, R: Read> Decoder<'a, R> {
+    /// Read the number of elements in a list.
+    fn read_count(&mut self, what: &str, max: u32) -> Result<u32> {
+        let n = self.reader.read_u32()?;
+        if n > max {
+            return Error::new_decoder(format!("{n} {what}s exceed the limit of {max}")).into();
+        }
+        Ok(n)
     }
+}
 
-    fn read_code(&mut self) -> Result<Box<[u32]>> {
-        let mut code = vec![];
+impl<'a> ProtoDump<'a> {
+    fn fmt_proto(&self, f: &mut Formatter, proto: &Proto) -> fmt::Result {
+        writeln!(
+            f,
+            "function <{}:{}> ({} instructions{})",
+            proto.source,
+            proto.line_defined,
+            proto.instrs.len(),
+            if proto.truncated { ", truncated" } else { "" }
+        )?;
+        writeln!(
+            f,
+            "{}{} params, {} stack, {} locals, {} strings, {} numbers, {} functions",
+            proto.num_params,
+            if proto.is_vararg { "+" } else { "" },
+            proto.max_stack,
+            proto.locals.len(),
+            proto.constants.strings.len(),
+            proto.constants.numbers.len(),
+            proto.constants.protos.len()
+        )?;
+
+        for (pc, instr) in proto.instrs.iter().enumerate() {
+            fmt_instr(f, proto, pc, instr)?;
+            writeln!(f)?;
+        }
+        writeln!(f)?;
 
-        for _ in 0..self.read_u32()? {
-            code.push(self.read_u32()?);
+        for child in proto.constants.protos.iter() {
+            self.fmt_proto(f, child)?;
         }
 
-        Ok(code.into_boxed_slice())
+        Ok(())
     }
+}
 
-    fn decode_op(&self, op: u32) -> Result<Op> {
+impl Instr {
+    /// Instruction with its arguments named after what they mean for the opcode.
+    pub fn op(&self) -> Op {
         use Opcode::*;
 
-        let Header { size_op, .. } = self.header;
-        let opcode = Opcode::try_from(op & mask1!(size_op, 0))?;
-        let arg_u = op >> size_op;
-        let arg_s = arg_u as i32 - self.header.max_arg_s();
-        let arg_a = op >> self.header.pos_arg_a();
-        let arg_b = (op >> self.header.pos_arg_b()) & self.header.max_arg_b();
+        let Instr {
+            opcode,
+            u: arg_u,
+            s: arg_s,
+            a: arg_a,
+            b: arg_b,
+        } = *self;
 
-        let op = match opcode {
+        match opcode {
             End => Op::End,
-            Return => Op::Return { results: arg_u },
+            Return => Op::Return {
+                stack_offset: arg_u,
+            },
 
             Call => Op::Call {
                 stack_offset: arg_a,
                 results: arg_b,
             },
-            TailCall => todo!(),
+            TailCall => Op::TailCall {
+                stack_offset: arg_a,
+                return_offset: arg_b,
+            },
 
-            PushNil => todo!(),
+            PushNil => Op::Unsupported { opcode },
             Pop => Op::Pop { n: arg_u },
 
             PushInt => Op::PushInt { value: arg_s },
-            PushString => todo!(),
-            PushNum => todo!(),
-            PushNegNum => todo!(),
+            PushString => Op::PushString { string_id: arg_u },
+            PushNum => Op::PushNum { number_id: arg_u },
+            PushNegNum => Op::PushNegNum { number_id: arg_u },
 
-            PushValue => todo!(),
+            PushUpvalue => Op::PushUpvalue { upvalue_id: arg_u },
 
             GetLocal => Op::GetLocal {
                 stack_offset: arg_u,
             },
             GetGlobal => Op::GetGlobal { string_id: arg_u },
 
-            GetTable => todo!(),
-            GetDotted => todo!(),
-            GetIndexed => todo!(),
-            PushSelf => todo!(),
+            GetTable => Op::Unsupported { opcode },
+            GetDotted => Op::Unsupported { opcode },
+            GetIndexed => Op::Unsupported { opcode },
+            PushSelf => Op::Unsupported { opcode },
 
-            CreateTable => todo!(),
+            CreateTable => Op::CreateTable { size: arg_u },
 
             SetLocal => Op::SetLocal {
                 stack_offset: arg_u,
             },
-            SetGlobal => todo!(),
-            SetTable => todo!(),
+            SetGlobal => Op::SetGlobal { string_id: arg_u },
+            SetTable => Op::Unsupported { opcode },
 
-            SetList => todo!(),
-            SetMap => todo!(),
+            SetList => Op::SetList {
+                batch: arg_a,
+                n: arg_b,
+            },
+            SetMap => Op::SetMap { n: arg_u },
 
             Add => Op::Add,
-            AddI => todo!(),
-            Sub => todo!(),
-            Mult => todo!(),
-            Div => todo!(),
-            Pow => todo!(),
-            Concat => todo!(),
-            Minus => todo!(),
-            Not => todo!(),
-
-            JumpNe => todo!(),
-            JumpEq => todo!(),
-            JumpLt => todo!(),
+            AddI => Op::AddI { value: arg_s },
+            Sub => Op::Sub,
+            Mult => Op::Mult,
+            Div => Op::Div,
+            Pow => Op::Pow,
+            Concat => Op::Concat { n: arg_u },
+            Minus => Op::Minus,
+            Not => Op::Not,
+
+            JumpNe => Op::JumpNe { ip: arg_s },
+            JumpEq => Op::JumpEq { ip: arg_s },
+            JumpLt => Op::JumpLt { ip: arg_s },
             JumpLe => Op::JumpLe { ip: arg_s },
-            JumpGt => todo!(),
-            JumpGe => todo!(),
+            JumpGt => Op::JumpGt { ip: arg_s },
+            JumpGe => Op::JumpGe { ip: arg_s },
 
-            JumpTrue => todo!(),
-            JumpFalse => todo!(),
-            JumpOnTrue => todo!(),
-            JumpOnFalse => todo!(),
-            Jump => todo!(),
+            JumpTrue => Op::JumpTrue { ip: arg_s },
+            JumpFalse => Op::JumpFalse { ip: arg_s },
+            JumpOnTrue => Op::JumpOnTrue { ip: arg_s },
+            JumpOnFalse => Op::JumpOnFalse { ip: arg_s },
+            Jump => Op::Jump { ip: arg_s },
 
-            PushNilJump => todo!(),
+            PushNilJump => Op::PushNilJump,
 
-            ForPrep => todo!(),
-            ForLoop => todo!(),
+            ForPrep => Op::Unsupported { opcode },
+            ForLoop => Op::Unsupported { opcode },
 
-            LForPrep => todo!(),
-            LForLoop => todo!(),
+            LForPrep => Op::Unsupported { opcode },
+            LForLoop => Op::Unsupported { opcode },
 
-            Closure => todo!(),
-        };
+            Closure => Op::Closure {
+                proto_id: arg_a,
+                upvalues: arg_b,
+            },
+            Unknown => Op::Unknown,
+        }
+    }
 
-        Ok(op)
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
     }
-}
 
-impl<'a> Decoder<'a> {
-    fn read_u8(&mut self) -> Result<u8> {
-        Ok(self.cursor.read_u8()?)
+    /// Unsigned argument `U`, meaningful when the opcode's mode is [OpMode::U].
+    pub fn arg_u(&self) -> u32 {
+        self.u
+    }
+
+    /// Signed argument `S`, meaningful when the opcode's mode is [OpMode::S].
+    pub fn arg_s(&self) -> i32 {
+        self.s
+    }
+
+    /// Argument `A`, meaningful when the opcode's mode is [OpMode::AB].
+    pub fn arg_a(&self) -> u32 {
+        self.a
+    }
+
+    /// Argument `B`, meaningful when the opcode's mode is [OpMode::AB].
+    pub fn arg_b(&self) -> u32 {
+        self.b
     }
 
-    fn read_u16(&mut self) -> Result<u16> {
-        let mut buf = [0; std::mem::size_of::<u16>()];
-        self.cursor.read_exact(&mut buf)?;
-        match self.header.endianess {
-            Endian::Little => Ok(u16::from_le_bytes(buf)),
-            Endian::Big => Ok(u16::from_le_bytes(buf)),
+    /// Arguments used by the opcode.
+    pub fn args(&self) -> Args {
+        match self.opcode.mode() {
+            OpMode::None => Args::None,
+            OpMode::U => Args::U(self.u),
+            OpMode::S => Args::S(self.s),
+            OpMode::AB => Args::AB(self.a, self.b),
         }
     }
 
-    fn read_u32(&mut self) -> Result<u32> {
-        let mut buf = [0; std::mem::size_of::<u32>()];
-        self.cursor.read_exact(&mut buf)?;
-        match self.header.endianess {
-            Endian::Little => Ok(u32::from_le_bytes(buf)),
-            Endian::Big => Ok(u32::from_le_bytes(buf)),
+    /// Index of the instruction jumped to, when the instruction at `pc` is a jump.
+    ///
+    /// The offset is relative to the next instruction.
+    pub fn jump_target(&self, pc: usize) -> Option<usize> {
+        if self.opcode.is_jump() {
+            usize::try_from(pc as i64 + 1 + self.s as i64).ok()
+        } else {
+            None
         }
     }
 
-    fn read_u64(&mut self) -> Result<u64> {
-        let mut buf = [0; std::mem::size_of::<u64>()];
-        self.cursor.read_exact(&mut buf)?;
-        match self.header.endianess {
-            Endian::Little => Ok(u64::from_le_bytes(buf)),
-            Endian::Big => Ok(u64::from_le_bytes(buf)),
+    /// Constant of the function that the instruction uses, if it's in bounds.
+    fn constant<'a>(&self, proto: &'a Proto) -> Option<Constant<'a>> {
+        use Opcode::*;
+
+        match self.opcode {
+            PushString | GetGlobal | SetGlobal | GetDotted | PushSelf => proto
+                .constants
+                .strings
+                .get(self.u as usize)
+                .map(Constant::String),
+            PushNum | PushNegNum => proto
+                .constants
+                .numbers
+                .get(self.u as usize)
+                .copied()
+                .map(Constant::Number),
+            Closure => proto
+                .constants
+                .protos
+                .get(self.a as usize)
+                .map(Constant::Function),
+            _ => None,
         }
     }
+}
 
-    fn read_f32(&mut self) -> Result<f32> {
-        let mut buf = [0; std::mem::size_of::<f32>()];
-        self.cursor.read_exact(&mut buf)?;
-        match self.header.endianess {
-            Endian::Little => Ok(f32::from_le_bytes(buf)),
-            Endian::Big => Ok(f32::from_le_bytes(buf)),
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.name())?;
+        match self.opcode.mode() {
+            OpMode::None => Ok(()),
+            OpMode::U => write!(f, " {}", self.u),
+            OpMode::S => write!(f, " {}", self.s),
+            OpMode::AB => write!(f, " {} {}", self.a, self.b),
         }
     }
+}
+
+/// Name of a nested function, like `main.0.2` for the third function nested
+/// in the first function of the main function.
+fn path_name(path: &[usize]) -> String {
+    ProtoPath::from(path).to_string()
+}
+
+/// Write one line of a listing, without the line break.
+fn fmt_instr(f: &mut Formatter, proto: &Proto, pc: usize, instr: &Instr) -> fmt::Result {
+    write!(f, "\t{}\t", pc + 1)?;
+    match proto.line_at(pc) {
+        Some(line) => write!(f, "[{line}]\t")?,
+        None => write!(f, "[-]\t")?,
+    }
+    write!(f, "{}", instr.opcode.name())?;
+    match instr.opcode.mode() {
+        OpMode::None => {}
+        OpMode::U => write!(f, "\t{}", instr.u)?,
+        OpMode::S => write!(f, "\t{}", instr.s)?,
+        OpMode::AB => write!(f, "\t{} {}", instr.a, instr.b)?,
+    }
+    fmt_comment(f, proto, pc, instr)
+}
 
-    fn read_f64(&mut self) -> Result<f64> {
-        let mut buf = [0; std::mem::size_of::<f64>()];
-        self.cursor.read_exact(&mut buf)?;
-        match self.header.endianess {
-            Endian::Little => Ok(f64::from_le_bytes(buf)),
-            Endian::Big => Ok(f64::from_le_bytes(buf)),
+fn fmt_comment(f: &mut Formatter, proto: &Proto, pc: usize, instr: &Instr) -> fmt::Result {
+    use Opcode::*;
+
+    match instr.opcode {
+        PushString | GetGlobal | SetGlobal | GetDotted | PushSelf => {
+            match proto.constants.strings.get(instr.u as usize) {
+                Some(string) => write!(f, "\t; {string:?}"),
+                None => write!(f, "\t; <bad string {}>", instr.u),
+            }
         }
+        PushNum | PushNegNum => match proto.constants.numbers.get(instr.u as usize) {
+            Some(number) if instr.opcode == PushNegNum => write!(f, "\t; {}", -number),
+            Some(number) => write!(f, "\t; {number}"),
+            None => write!(f, "\t; <bad number {}>", instr.u),
+        },
+        GetLocal | SetLocal | GetIndexed => match proto.local_name(instr.u, pc) {
+            Some(name) => write!(f, "\t; {name}"),
+            None => Ok(()),
+        },
+        _ => match instr.jump_target(pc) {
+            Some(target) => write!(f, "\t; to {}", target + 1),
+            None => Ok(()),
+        },
     }
 }
 
-struct ProtoDump<'a> {
-    proto: &'a Proto,
+impl<'a> HeaderDump<'a> {
+    pub fn fields(&self) -> &[HeaderField] {
+        &self.fields
+    }
+
+    /// First field that's malformed, if any.
+    pub fn malformed(&self) -> Option<&HeaderField> {
+        self.fields.iter().find(|field| field.value.is_err())
+    }
+}
+
+/// One line per field, with its offset, bytes and value,
+/// and the malformed field marked with `!!`.
+///
+/// ```text
+/// 00000004  40                        version           4.0
+/// ```
+impl<'a> fmt::Display for HeaderDump<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for field in &self.fields {
+            let bytes = self
+                .code
+                .get(field.range.clone())
+                .unwrap_or_default()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            write!(
+                f,
+                "{:08x}  {bytes:<24}  {:<16}  ",
+                field.range.start, field.name
+            )?;
+            match &field.value {
+                Ok(value) => writeln!(f, "{value}")?,
+                Err(err) => writeln!(f, "!! {err}")?,
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a> fmt::Display for ProtoDump<'a> {
-    fn fmt(&self, _f: &mut Formatter) -> fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_proto(f, self.proto)
+    }
+}
+
+impl<'a> fmt::Display for RangeDump<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let instrs = self
+            .proto
+            .instrs
+            .get(self.range.clone())
+            .unwrap_or_default();
+        for (offset, instr) in instrs.iter().enumerate() {
+            fmt_instr(f, self.proto, self.range.start + offset, instr)?;
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,261 @@
+//! Static analysis over the syntax tree.
+use std::fmt::{self, Formatter};
+
+use super::ast::{Block, Call, Expr, Lit, Node, Span, Stmt, Syntax};
+use super::ProtoPath;
+
+/// Global functions that take a `printf` style format string
+/// as their first argument.
+const FORMAT_FUNCTIONS: &[&str] = &["format"];
+
+/// Call to a function taking a format string constant.
+#[derive(Debug)]
+pub struct FormatCall {
+    /// Name of the called function.
+    pub callee: String,
+    /// The format string constant.
+    pub format: String,
+    /// Conversion specifications found in the format string, like `%5.2f`.
+    pub specs: Vec<String>,
+    /// Number of arguments following the format string.
+    pub num_args: usize,
+    /// Problems found when matching arguments to specifications.
+    pub issues: Vec<String>,
+    /// Function the call is in.
+    pub path: ProtoPath,
+    /// Span of the statement the call is in.
+    pub span: Option<Span>,
+}
+
+/// Statement a visited call is in.
+#[derive(Clone, Copy)]
+struct Site<'a> {
+    path: &'a ProtoPath,
+    span: Option<&'a Span>,
+}
+
+/// Find calls to format functions with a constant format string, and
+/// check their arguments against the conversion specifications.
+///
+/// Mismatched argument counts in the output often reveal decompilation errors.
+pub fn check_format_calls(syntax: &Syntax) -> Vec<FormatCall> {
+    let mut calls = vec![];
+    visit_block(&syntax.root, &syntax.path, &mut |call, site| {
+        if let Some(format_call) = check_call(call, site) {
+            calls.push(format_call);
+        }
+    });
+    calls
+}
+
+impl FormatCall {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for FormatCall {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}({:?}): {} specs, {} args",
+            self.callee,
+            self.format,
+            self.specs.len(),
+            self.num_args
+        )?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}:{}", self.path, span.start + 1)?;
+        }
+        for issue in &self.issues {
+            write!(f, "; {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+fn check_call(call: &Call, site: Site) -> Option<FormatCall> {
+    let callee = match &call.name {
+        Expr::Access(ident) if FORMAT_FUNCTIONS.contains(&ident.as_str()) => ident.as_str(),
+        _ => return None,
+    };
+    let format = match call.args.first() {
+        Some(Expr::Literal(Lit::Str(format))) => format.to_string_lossy(),
+        _ => return None,
+    };
+    let args = &call.args[1..];
+
+    let mut issues = vec![];
+    let specs = match parse_specs(&format) {
+        Ok(specs) => specs,
+        Err(err) => {
+            issues.push(err);
+            vec![]
+        }
+    };
+
+    // The last argument may be a call expanding to multiple values,
+    // so the count can only be checked when it's a single value.
+    let open_ended = matches!(args.last(), Some(Expr::Call(_)));
+    if args.len() > specs.len() || (args.len() < specs.len() && !open_ended) {
+        issues.push(format!(
+            "expected {} arguments, found {}",
+            specs.len(),
+            args.len()
+        ));
+    }
+
+    for (index, (spec, arg)) in specs.iter().zip(args).enumerate() {
+        let conversion = spec.chars().last().unwrap_or('%');
+        let is_numeric = matches!(
+            conversion,
+            'c' | 'd' | 'i' | 'o' | 'u' | 'x' | 'X' | 'e' | 'E' | 'f' | 'g' | 'G'
+        );
+        if let Expr::Literal(Lit::Str(value)) = arg {
+            let is_number = value
+                .to_str()
+                .is_some_and(|s| s.trim().parse::<f64>().is_ok());
+            if is_numeric && !is_number {
+                issues.push(format!(
+                    "argument {} is a string for numeric conversion {spec}",
+                    index + 1
+                ));
+            }
+        }
+    }
+
+    Some(FormatCall {
+        callee: callee.to_string(),
+        format: format.into_owned(),
+        specs,
+        num_args: args.len(),
+        issues,
+        path: site.path.clone(),
+        span: site.span.copied(),
+    })
+}
+
+/// Parse the conversion specifications in a format string, as per `str_format` in `lstrlib.c`.
+///
+/// ```text
+/// %[flags][width][.precision]conversion
+/// ```
+fn parse_specs(format: &str) -> Result<Vec<String>, String> {
+    let mut specs = vec![];
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            continue;
+        }
+
+        let mut spec = String::from("%");
+        while let Some(&c) = chars.peek() {
+            if "-+ #0".contains(c) {
+                spec.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_digit() || c == '.' {
+                spec.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        match chars.next() {
+            Some(c) if "cdiouxXeEfgGqs".contains(c) => {
+                spec.push(c);
+                specs.push(spec);
+            }
+            Some(c) => return Err(format!("invalid conversion '{spec}{c}'")),
+            None => return Err(format!("incomplete conversion '{spec}'")),
+        }
+    }
+
+    Ok(specs)
+}
+
+fn visit_block(block: &Block, path: &ProtoPath, visit: &mut impl FnMut(&Call, Site)) {
+    for (node, span) in block.iter_spanned() {
+        let site = Site { path, span };
+        match node {
+            Node::Stmt(stmt) => visit_stmt(stmt, site, visit),
+            Node::Expr(expr) => visit_expr(expr, site, visit),
+            Node::Partial(_) => {}
+        }
+    }
+}
+
+fn visit_stmt(stmt: &Stmt, site: Site, visit: &mut impl FnMut(&Call, Site)) {
+    match stmt {
+        Stmt::LocalVar(local_var) => local_var
+            .rhs
+            .iter()
+            .for_each(|e| visit_expr(e, site, visit)),
+        Stmt::Assign(assign) => assign.rhs.iter().for_each(|e| visit_expr(e, site, visit)),
+        Stmt::Call(call) => visit_call(call, site, visit),
+        Stmt::Block(block) => visit_block(block, site.path, visit),
+        Stmt::If(if_block) => {
+            visit_expr(&if_block.head, site, visit);
+            visit_block(&if_block.then, site.path, visit);
+            if let Some(else_) = &if_block.else_ {
+                visit_block(else_, site.path, visit);
+            }
+        }
+        Stmt::While(while_block) => {
+            visit_expr(&while_block.head, site, visit);
+            visit_block(&while_block.body, site.path, visit);
+        }
+        Stmt::Repeat(repeat_block) => {
+            visit_block(&repeat_block.body, site.path, visit);
+            visit_expr(&repeat_block.cond, site, visit);
+        }
+        Stmt::Return(ret) => ret.values.iter().for_each(|e| visit_expr(e, site, visit)),
+        Stmt::Goto(goto) => {
+            if let Some(cond) = &goto.cond {
+                visit_expr(cond, site, visit);
+            }
+        }
+        Stmt::Break | Stmt::Label(_) | Stmt::Failed(_) | Stmt::Custom(_) | Stmt::Comment(_) => {}
+    }
+}
+
+fn visit_expr(expr: &Expr, site: Site, visit: &mut impl FnMut(&Call, Site)) {
+    match expr {
+        Expr::Access(_) | Expr::Upvalue(_) | Expr::Literal(_) | Expr::Custom(_) => {}
+        Expr::Binary(bin_expr) => {
+            visit_expr(&bin_expr.lhs, site, visit);
+            visit_expr(&bin_expr.rhs, site, visit);
+        }
+        Expr::Unary(unary_expr) => visit_expr(&unary_expr.rhs, site, visit),
+        Expr::Call(call) => visit_call(call, site, visit),
+        Expr::Function(function) => visit_block(&function.body, &function.path, visit),
+        Expr::Table(table) => {
+            table
+                .items
+                .iter()
+                .for_each(|item| visit_expr(item, site, visit));
+            for field in table.fields.iter() {
+                visit_expr(&field.key, site, visit);
+                visit_expr(&field.value, site, visit);
+            }
+        }
+    }
+}
+
+fn visit_call(call: &Call, site: Site, visit: &mut impl FnMut(&Call, Site)) {
+    visit(call, site);
+    visit_expr(&call.name, site, visit);
+    call.args
+        .iter()
+        .for_each(|arg| visit_expr(arg, site, visit));
+}
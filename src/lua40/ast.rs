@@ -1,18 +1,63 @@
 //! Abstract syntax tree.
 use std::fmt::{self, Formatter};
 
+use super::ProtoPath;
+use crate::lstring::LuaString;
+use std::sync::Arc;
+
+use crate::symbol::{Symbol, SymbolTable};
+
+/// Reserved words of Lua 4.0, which can't be used as names.
+pub const KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "for", "function", "if", "in", "local", "nil",
+    "not", "or", "repeat", "return", "then", "until", "while",
+];
+
+/// Whether the text can be written as a name, like a variable.
+pub fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&text)
+}
+
 /// Abstract syntax tree.
 #[derive(Debug)]
 pub struct Syntax {
     pub root: Block,
-    pub debug: (),
+    /// Function the spans of the root block's nodes refer to.
+    pub path: ProtoPath,
+    /// Names interned by the parser, which the tree's identifiers share.
+    pub symbols: SymbolTable,
 }
 
 /// Block of statements.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Block {
     // FIXME: Should this be statements?
     pub nodes: Vec<Node>,
+    /// Instructions each node was decoded from, in the same order as `nodes`.
+    pub spans: Vec<Span>,
+}
+
+/// Range of instructions that a syntax node was decoded from.
+///
+/// Spans are kept for each node of a [Block], and the expressions
+/// in a statement share its span. Instructions are indexed in the
+/// function the block belongs to, as per [Syntax::path] and
+/// [Function::path].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// Index of the first instruction.
+    pub start: u32,
+    /// Index of the last instruction, inclusive.
+    pub end: u32,
+    /// Source line of the first instruction, when debug information is present.
+    pub line: Option<u32>,
+    /// Source line of the last instruction, when debug information is present.
+    pub end_line: Option<u32>,
 }
 
 /// Syntax Node.
@@ -23,9 +68,13 @@ pub enum Node {
     Partial(Partial),
 }
 
-#[derive(Debug)]
+/// Name of a variable.
+///
+/// Identifiers made by the parser share their text with the name
+/// interned in its [SymbolTable], so repeated names are stored once.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Ident {
-    text: String,
+    name: Arc<str>,
 }
 
 // ----------------------------------------------------------------------------
@@ -38,49 +87,136 @@ pub enum Stmt {
     Assign(Box<Assign>),
     Call(Box<Call>),
     Block(Block),
-    If(IfBlock),
+    If(Box<IfBlock>),
+    While(Box<WhileBlock>),
+    Repeat(Box<RepeatBlock>),
+    Break,
+    Return(Return),
+    Goto(Box<Goto>),
+    /// Destination of a [Goto], kept as a comment.
+    ///
+    /// Holds the index of the instruction jumped to.
+    Label(u32),
+    Failed(Failed),
+    Custom(Custom),
+    Comment(Comment),
 }
 
 /// Local variable declaration.
 ///
 /// ```lua
-/// local {name} = {rhs}
+/// local {names} = {rhs}
 /// ```
+///
+/// There may be fewer values than names when the last
+/// value is a call returning multiple results.
 #[derive(Debug)]
 pub struct LocalVar {
-    pub name: Ident,
-    pub rhs: Expr,
+    pub names: Vec<Ident>,
+    pub rhs: Vec<Expr>,
 }
 
+/// Assignment to one or more variables.
+///
+/// ```lua
+/// {targets} = {rhs}
+/// ```
 #[derive(Debug)]
 pub struct Assign {
-    pub name: Ident,
-    pub rhs: Expr,
+    pub targets: Vec<Ident>,
+    pub rhs: Vec<Expr>,
+}
+
+/// Return statement.
+///
+/// ```lua
+/// return {values}
+/// ```
+#[derive(Debug)]
+pub struct Return {
+    pub values: Vec<Expr>,
+}
+
+/// Instructions that couldn't be decompiled.
+///
+/// Only produced by a lenient parser, which keeps the disassembly
+/// in place of the statements the instructions held.
+#[derive(Debug)]
+pub struct Failed {
+    pub message: String,
+    /// Index of the first instruction.
+    pub start: u32,
+    /// Index of the last instruction, inclusive.
+    pub end: u32,
+    /// Disassembly listing of the instructions.
+    pub listing: String,
+}
+
+/// Statement or expression built by an [super::Idiom] in place of
+/// the instructions it recognized, written out as the source it gave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Custom {
+    /// Name of the idiom, shown in the syntax tree.
+    pub kind: String,
+    pub source: String,
+}
+
+/// Explanatory comment, like a note left by a pass about
+/// something it couldn't work out.
+///
+/// ```lua
+/// -- {text}
+/// ```
+///
+/// A trailing comment is written at the end of the first line
+/// of the node before it instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// Text of the comment, written with `--` before each line.
+    pub text: String,
+    pub trailing: bool,
+}
+
+/// Jump that doesn't fit any statement, kept as a comment.
+///
+/// ```lua
+/// -- if {cond} then goto {target} end
+/// ```
+#[derive(Debug)]
+pub struct Goto {
+    pub cond: Option<Expr>,
+    /// Index of the instruction jumped to.
+    pub target: u32,
 }
 
 /// `if` conditional block statement.
 #[derive(Debug)]
 pub struct IfBlock {
-    pub head: CondExpr,
+    pub head: Expr,
     pub then: Block,
     pub else_: Option<Block>,
 }
 
+/// `while` loop statement.
+///
+/// ```lua
+/// while {head} do {body} end
+/// ```
 #[derive(Debug)]
-pub enum CondExpr {
-    Unary { op: (), rhs: Expr },
-    Binary { op: CondOp, lhs: Expr, rhs: Expr },
+pub struct WhileBlock {
+    pub head: Expr,
+    pub body: Block,
 }
 
-/// Conditional operators.
-#[derive(Debug, Clone, Copy)]
-pub enum CondOp {
-    Ne, // ~=
-    Eq, // ==
-    Lt, // <
-    Le, // <=
-    Gt, // >
-    Ge, // >=
+/// `repeat` loop statement.
+///
+/// ```lua
+/// repeat {body} until {cond}
+/// ```
+#[derive(Debug)]
+pub struct RepeatBlock {
+    pub body: Block,
+    pub cond: Expr,
 }
 
 // ----------------------------------------------------------------------------
@@ -89,16 +225,27 @@ pub enum CondOp {
 
 /// A partially built statement.
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Partial {
     IfHead(Box<IfHead>),
-    WhileHead,
+    WhileHead(Box<WhileHead>),
     ForHead,
+    /// Condition at the end of a `repeat` loop, waiting for its body.
+    Until(Box<Expr>),
 }
 
 /// Header for an `if` conditional statement.
 #[derive(Debug)]
 pub struct IfHead {
-    pub expr: CondExpr,
+    pub expr: Expr,
+    /// The `then` block, once it's built and the `else` block is next.
+    pub then: Option<Block>,
+}
+
+/// Header for a `while` loop statement.
+#[derive(Debug)]
+pub struct WhileHead {
+    pub expr: Expr,
 }
 
 // ----------------------------------------------------------------------------
@@ -109,17 +256,24 @@ pub struct IfHead {
 pub enum Expr {
     /// Variable access by name.
     Access(Ident),
+    /// Value of an enclosing function's variable, copied when
+    /// the closure was created, written as `%name`.
+    Upvalue(Ident),
     Literal(Lit),
     Binary(Box<BinExpr>),
+    Unary(Box<UnaryExpr>),
     Call(Box<Call>),
+    Function(Box<Function>),
+    Table(Box<Table>),
+    Custom(Custom),
 }
 
 /// Literal value.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Lit {
     Int(i32),
     Num(f64),
-    Str(String),
+    Str(LuaString),
 }
 
 #[derive(Debug)]
@@ -129,9 +283,37 @@ pub struct BinExpr {
     pub rhs: Expr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Concat,
+    Ne,
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Both operands are true, from conditional jumps where the
+    /// first one jumps past the second.
+    And,
+    /// Either operand is true, from conditional jumps to the same place.
+    Or,
+}
+
+#[derive(Debug)]
+pub struct UnaryExpr {
+    pub op: UnaryOp,
+    pub rhs: Expr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
 }
 
 #[derive(Debug)]
@@ -140,6 +322,43 @@ pub struct Call {
     pub args: Vec<Expr>,
 }
 
+/// Table constructor.
+///
+/// ```lua
+/// { {items}; {fields} }
+/// ```
+#[derive(Debug, Default)]
+pub struct Table {
+    /// Values of the list part, from index 1.
+    pub items: Vec<Expr>,
+    /// Keyed values of the record part.
+    pub fields: Vec<Field>,
+    /// Whether the record part is written before the list part.
+    pub fields_first: bool,
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub key: Expr,
+    pub value: Expr,
+}
+
+/// Function constructor, decompiled from a nested prototype.
+///
+/// ```lua
+/// function({params}, ...) {body} end
+/// ```
+#[derive(Debug)]
+pub struct Function {
+    pub params: Vec<Ident>,
+    /// Takes a variable number of arguments, which the body
+    /// accesses through the implicit `arg` table.
+    pub is_vararg: bool,
+    pub body: Block,
+    /// Nested function the body was decompiled from.
+    pub path: ProtoPath,
+}
+
 // ============================================================================
 // Functions
 // ============================================================================
@@ -166,21 +385,55 @@ impl Node {
     }
 }
 
-impl Ident {
+impl Comment {
     pub fn new(text: impl ToString) -> Self {
         Self {
             text: text.to_string(),
+            trailing: false,
+        }
+    }
+
+    /// Comment at the end of the line of the node before it.
+    pub fn trailing(text: impl ToString) -> Self {
+        Self {
+            text: text.to_string(),
+            trailing: true,
+        }
+    }
+}
+
+impl Ident {
+    pub fn new(text: impl AsRef<str>) -> Self {
+        Self {
+            name: text.as_ref().into(),
         }
     }
 
+    /// Identifier sharing its text with the name interned in the table.
+    pub fn interned(text: impl AsRef<str>, symbols: &mut SymbolTable) -> Self {
+        let (_, name) = symbols.intern_text(text.as_ref());
+        Self { name }
+    }
+
     pub fn as_str(&self) -> &str {
-        self.text.as_str()
+        &self.name
+    }
+
+    /// Symbol of the name in the table, if it was interned there.
+    pub fn symbol(&self, symbols: &SymbolTable) -> Option<Symbol> {
+        symbols.get(&self.name)
+    }
+}
+
+impl fmt::Debug for Ident {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Ident({:?})", self.as_str())
     }
 }
 
 impl fmt::Display for Ident {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        fmt::Display::fmt(self.text.as_str(), f)
+        fmt::Display::fmt(self.as_str(), f)
     }
 }
 
@@ -196,6 +449,12 @@ impl From<IfHead> for Node {
     }
 }
 
+impl From<WhileHead> for Node {
+    fn from(while_head: WhileHead) -> Self {
+        Node::Partial(Partial::WhileHead(Box::new(while_head)))
+    }
+}
+
 impl From<Lit> for Node {
     fn from(lit: Lit) -> Self {
         Node::Expr(Expr::Literal(lit))
@@ -208,12 +467,30 @@ impl From<BinExpr> for Node {
     }
 }
 
+impl From<UnaryExpr> for Node {
+    fn from(unary_expr: UnaryExpr) -> Self {
+        Node::Expr(Expr::Unary(Box::new(unary_expr)))
+    }
+}
+
 impl From<Call> for Node {
     fn from(call: Call) -> Self {
         Node::Expr(Expr::Call(Box::new(call)))
     }
 }
 
+impl From<Table> for Node {
+    fn from(table: Table) -> Self {
+        Node::Expr(Expr::Table(Box::new(table)))
+    }
+}
+
+impl From<Function> for Node {
+    fn from(function: Function) -> Self {
+        Node::Expr(Expr::Function(Box::new(function)))
+    }
+}
+
 impl Node {
     /// Checks whether the statement is partially built.
     #[inline(always)]
@@ -228,15 +505,284 @@ impl Node {
     }
 }
 
-impl CondOp {
+impl Node {
+    /// Visit every identifier referenced by the node,
+    /// including those in nested blocks.
+    pub fn for_each_ident(&self, visit: &mut impl FnMut(&Ident)) {
+        match self {
+            Node::Stmt(stmt) => stmt.for_each_ident(visit),
+            Node::Expr(expr) => expr.for_each_ident(visit),
+            Node::Partial(Partial::IfHead(if_head)) => {
+                if_head.expr.for_each_ident(visit);
+                if let Some(then) = &if_head.then {
+                    then.for_each_ident(visit);
+                }
+            }
+            Node::Partial(Partial::WhileHead(while_head)) => while_head.expr.for_each_ident(visit),
+            Node::Partial(Partial::Until(cond)) => cond.for_each_ident(visit),
+            Node::Partial(Partial::ForHead) => {}
+        }
+    }
+}
+
+impl Span {
+    /// Whether the instruction at the index is in the span.
+    pub fn contains(&self, index: u32) -> bool {
+        (self.start..=self.end).contains(&index)
+    }
+
+    /// Source lines of the first and last instruction,
+    /// when debug information is present.
+    pub fn lines(&self) -> Option<(u32, u32)> {
+        Some((self.line?, self.end_line.unwrap_or(self.line?)))
+    }
+}
+
+impl Block {
+    /// Span of the node at the index.
+    pub fn span(&self, index: usize) -> Option<&Span> {
+        self.spans.get(index)
+    }
+
+    /// Insert a comment before the node at the index, or after the last node.
+    ///
+    /// The comment takes the span of the node it's written with, which
+    /// is the one before it for a trailing comment.
+    pub fn insert_comment(&mut self, index: usize, comment: Comment) {
+        let neighbour = if comment.trailing {
+            index.checked_sub(1)
+        } else {
+            Some(index.min(self.nodes.len().saturating_sub(1)))
+        };
+        if self.spans.len() == self.nodes.len() {
+            if let Some(span) = neighbour.and_then(|index| self.spans.get(index)) {
+                self.spans.insert(index, *span);
+            }
+        }
+        self.nodes.insert(index, Node::Stmt(Stmt::Comment(comment)));
+    }
+
+    /// Each node with its span, when it has one.
+    pub fn iter_spanned(&self) -> impl Iterator<Item = (&Node, Option<&Span>)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node, self.spans.get(index)))
+    }
+
+    /// Visit every identifier referenced in the block.
+    pub fn for_each_ident(&self, visit: &mut impl FnMut(&Ident)) {
+        for node in &self.nodes {
+            node.for_each_ident(visit);
+        }
+    }
+}
+
+impl Stmt {
+    /// Visit every identifier referenced by the statement,
+    /// including declared and assigned names.
+    pub fn for_each_ident(&self, visit: &mut impl FnMut(&Ident)) {
+        match self {
+            Stmt::LocalVar(local_var) => {
+                local_var.names.iter().for_each(&mut *visit);
+                local_var
+                    .rhs
+                    .iter()
+                    .for_each(|expr| expr.for_each_ident(visit));
+            }
+            Stmt::Assign(assign) => {
+                assign.targets.iter().for_each(&mut *visit);
+                assign
+                    .rhs
+                    .iter()
+                    .for_each(|expr| expr.for_each_ident(visit));
+            }
+            Stmt::Call(call) => call.for_each_ident(visit),
+            Stmt::Block(block) => block.for_each_ident(visit),
+            Stmt::If(if_block) => {
+                if_block.head.for_each_ident(visit);
+                if_block.then.for_each_ident(visit);
+                if let Some(else_) = &if_block.else_ {
+                    else_.for_each_ident(visit);
+                }
+            }
+            Stmt::While(while_block) => {
+                while_block.head.for_each_ident(visit);
+                while_block.body.for_each_ident(visit);
+            }
+            Stmt::Repeat(repeat_block) => {
+                repeat_block.body.for_each_ident(visit);
+                repeat_block.cond.for_each_ident(visit);
+            }
+            Stmt::Return(ret) => ret
+                .values
+                .iter()
+                .for_each(|expr| expr.for_each_ident(visit)),
+            Stmt::Goto(goto) => {
+                if let Some(cond) = &goto.cond {
+                    cond.for_each_ident(visit);
+                }
+            }
+            Stmt::Break | Stmt::Label(_) | Stmt::Failed(_) | Stmt::Custom(_) | Stmt::Comment(_) => {
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// The condition that holds when this one doesn't.
     pub fn invert(self) -> Self {
         match self {
-            CondOp::Ne => todo!(),
-            CondOp::Eq => todo!(),
-            CondOp::Lt => todo!(),
-            CondOp::Le => CondOp::Gt,
-            CondOp::Gt => todo!(),
-            CondOp::Ge => todo!(),
+            Expr::Unary(unary_expr) if matches!(unary_expr.op, UnaryOp::Not) => unary_expr.rhs,
+            Expr::Binary(bin_expr) => {
+                let BinExpr { op, lhs, rhs } = *bin_expr;
+                let (op, lhs, rhs) = match op {
+                    // De Morgan's laws.
+                    BinOp::And => (BinOp::Or, lhs.invert(), rhs.invert()),
+                    BinOp::Or => (BinOp::And, lhs.invert(), rhs.invert()),
+                    op => match op.invert() {
+                        Some(inverse) => (inverse, lhs, rhs),
+                        None => {
+                            let bin_expr = BinExpr { op, lhs, rhs };
+                            return Expr::logical_not(Expr::Binary(Box::new(bin_expr)));
+                        }
+                    },
+                };
+                Expr::Binary(Box::new(BinExpr { op, lhs, rhs }))
+            }
+            expr => Expr::logical_not(expr),
+        }
+    }
+
+    /// Logical `not` of the expression.
+    pub fn logical_not(rhs: Expr) -> Self {
+        Expr::Unary(Box::new(UnaryExpr {
+            op: UnaryOp::Not,
+            rhs,
+        }))
+    }
+
+    /// Visit every identifier referenced by the expression.
+    pub fn for_each_ident(&self, visit: &mut impl FnMut(&Ident)) {
+        match self {
+            Expr::Access(ident) => visit(ident),
+            // Names a variable of the enclosing function, not this one.
+            Expr::Upvalue(_) => {}
+            // The names in custom source aren't known.
+            Expr::Literal(_) | Expr::Custom(_) => {}
+            Expr::Binary(bin_expr) => {
+                bin_expr.lhs.for_each_ident(visit);
+                bin_expr.rhs.for_each_ident(visit);
+            }
+            Expr::Unary(unary_expr) => unary_expr.rhs.for_each_ident(visit),
+            Expr::Call(call) => call.for_each_ident(visit),
+            // The body is a separate scope, which only sees the
+            // enclosing function's variables through upvalues.
+            Expr::Function(_) => {}
+            Expr::Table(table) => {
+                table
+                    .items
+                    .iter()
+                    .for_each(|item| item.for_each_ident(visit));
+                for field in table.fields.iter() {
+                    field.key.for_each_ident(visit);
+                    field.value.for_each_ident(visit);
+                }
+            }
+        }
+    }
+}
+
+impl Call {
+    /// Visit every identifier referenced by the callee and arguments.
+    pub fn for_each_ident(&self, visit: &mut impl FnMut(&Ident)) {
+        self.name.for_each_ident(visit);
+        self.args.iter().for_each(|arg| arg.for_each_ident(visit));
+    }
+}
+
+impl Lit {
+    /// Whether the literal is written with a leading minus.
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Lit::Int(value) => *value < 0,
+            Lit::Num(value) => *value < 0.0 && value.is_finite(),
+            Lit::Str(_) => false,
+        }
+    }
+
+    /// String that's a valid name, so it can be written
+    /// as one where a string key is expected.
+    pub fn as_name(&self) -> Option<&str> {
+        let Lit::Str(string) = self else {
+            return None;
+        };
+        let name = std::str::from_utf8(string.as_bytes()).ok()?;
+        is_name(name).then_some(name)
+    }
+}
+
+impl BinOp {
+    /// Left and right binding power, as per `priority` in `lparser.c`.
+    pub fn priority(self) -> (u32, u32) {
+        match self {
+            BinOp::Add | BinOp::Sub => (5, 5),
+            BinOp::Mul | BinOp::Div => (6, 6),
+            // Right associative.
+            BinOp::Pow => (9, 8),
+            BinOp::Concat => (4, 3),
+            BinOp::Ne | BinOp::Eq | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => (2, 2),
+            BinOp::And | BinOp::Or => (1, 1),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Pow => "^",
+            BinOp::Concat => "..",
+            BinOp::Ne => "~=",
+            BinOp::Eq => "==",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+        }
+    }
+
+    /// Whether the operator is a keyword, which is spaced whatever the style.
+    pub fn is_keyword(self) -> bool {
+        matches!(self, BinOp::And | BinOp::Or)
+    }
+
+    /// The comparison that holds when this one doesn't.
+    pub fn invert(self) -> Option<Self> {
+        let op = match self {
+            BinOp::Ne => BinOp::Eq,
+            BinOp::Eq => BinOp::Ne,
+            BinOp::Lt => BinOp::Ge,
+            BinOp::Le => BinOp::Gt,
+            BinOp::Gt => BinOp::Le,
+            BinOp::Ge => BinOp::Lt,
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+impl UnaryOp {
+    /// Priority of unary operators, as per `UNARY_PRIORITY` in `lparser.c`.
+    pub const PRIORITY: u32 = 7;
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "not ",
         }
     }
 }
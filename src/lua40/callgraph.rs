@@ -0,0 +1,412 @@
+//! Call graph of a chunk, from its syntax tree.
+//!
+//! Calls are found by what's called, like `print(x)` calling the global
+//! `print`. Where the chunk assigns a function to the called variable, like
+//! `function f() end` assigning to the global `f`, the call is resolved to
+//! that function, so calls between the chunk's functions can be followed.
+//!
+//! Indexing isn't decompiled yet, so calls to methods and fields of tables
+//! don't show up.
+use std::collections::HashMap;
+use std::fmt::{self, Formatter};
+
+use super::ast::{Block, Expr, Function, Ident, Node, Stmt, Syntax};
+use super::ProtoPath;
+
+/// Functions of a chunk, and the calls made by each.
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    pub functions: Vec<CallNode>,
+    pub calls: Vec<CallEdge>,
+}
+
+/// Function of the chunk.
+#[derive(Debug, Clone)]
+pub struct CallNode {
+    pub path: ProtoPath,
+    /// Variable the function is assigned to where it's defined, if any.
+    pub name: Option<String>,
+}
+
+/// Calls from a function to a callee.
+#[derive(Debug, Clone)]
+pub struct CallEdge {
+    pub caller: ProtoPath,
+    pub callee: Callee,
+    /// Function of the chunk assigned to the callee, if it was found.
+    pub target: Option<ProtoPath>,
+    /// Number of calls made in the caller's body.
+    pub count: usize,
+}
+
+/// What a call calls.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callee {
+    Global(String),
+    Local(String),
+    /// Variable of the enclosing function, or global, captured as an upvalue.
+    Upvalue(String),
+    /// Anything else, like the result of another call.
+    Dynamic,
+}
+
+/// DOT view of a call graph, for Graphviz.
+pub struct CallGraphDot<'a> {
+    graph: &'a CallGraph,
+}
+
+/// Local in scope, with the function it was defined as.
+struct Binding {
+    name: String,
+    function: Option<ProtoPath>,
+}
+
+#[derive(Default)]
+struct Walker {
+    graph: CallGraph,
+    /// Names of functions assigned where they're defined, by path.
+    names: HashMap<ProtoPath, String>,
+    /// Functions assigned to globals, resolved once the whole tree is walked
+    /// since the assignment may come after the call.
+    globals: HashMap<String, ProtoPath>,
+    /// Global each call is to, including through an upvalue, by edge index.
+    unresolved: Vec<(usize, String)>,
+}
+
+// ============================================================================
+
+/// Build the call graph of the syntax tree, from its root function down.
+pub fn call_graph(syntax: &Syntax) -> CallGraph {
+    let mut walker = Walker::default();
+    walker.graph.functions.push(CallNode {
+        path: syntax.path.clone(),
+        name: None,
+    });
+    walker.block(&syntax.root, &syntax.path, &mut vec![], &[]);
+
+    let Walker {
+        mut graph,
+        globals,
+        unresolved,
+        ..
+    } = walker;
+    for (index, name) in unresolved {
+        graph.calls[index].target = globals.get(&name).cloned();
+    }
+    graph
+}
+
+impl CallGraph {
+    /// Function of the chunk at the path, if it's in the graph.
+    pub fn function(&self, path: &ProtoPath) -> Option<&CallNode> {
+        self.functions.iter().find(|node| &node.path == path)
+    }
+
+    /// Calls made by the function at the path.
+    pub fn calls_from<'a>(&'a self, path: &'a ProtoPath) -> impl Iterator<Item = &'a CallEdge> {
+        self.calls.iter().filter(move |edge| &edge.caller == path)
+    }
+
+    /// DOT view of the graph, with a node for each function of the chunk
+    /// and each callee that isn't one of them.
+    ///
+    /// ```text
+    /// digraph calls {
+    ///     "main" [label="main"];
+    ///     "main.0" [label="add\nmain.0"];
+    ///     "main" -> "main.0" [label="add"];
+    ///     "print" [shape=box];
+    ///     "main.0" -> "print";
+    /// }
+    /// ```
+    pub fn dot(&self) -> CallGraphDot<'_> {
+        CallGraphDot { graph: self }
+    }
+}
+
+impl CallNode {
+    /// Name of the function, or its path when it isn't named.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{name}\\n{}", self.path),
+            None => self.path.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Callee {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Callee::Global(name) => write!(f, "{name}"),
+            Callee::Local(name) => write!(f, "local {name}"),
+            Callee::Upvalue(name) => write!(f, "%{name}"),
+            Callee::Dynamic => write!(f, "?"),
+        }
+    }
+}
+
+impl fmt::Display for CallGraphDot<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "digraph calls {{")?;
+        for node in &self.graph.functions {
+            writeln!(f, "    \"{}\" [label=\"{}\"];", node.path, node.label())?;
+        }
+        let mut externals = vec![];
+        for edge in &self.graph.calls {
+            let callee = match &edge.target {
+                Some(target) => target.to_string(),
+                None => {
+                    let callee = edge.callee.to_string();
+                    if !externals.contains(&callee) {
+                        writeln!(f, "    \"{callee}\" [shape=box];")?;
+                        externals.push(callee.clone());
+                    }
+                    callee
+                }
+            };
+            write!(f, "    \"{}\" -> \"{callee}\"", edge.caller)?;
+            match (&edge.target, edge.count) {
+                (Some(_), 1) => writeln!(f, " [label=\"{}\"];", edge.callee)?,
+                (Some(_), count) => writeln!(f, " [label=\"{} x{count}\"];", edge.callee)?,
+                (None, 1) => writeln!(f, ";")?,
+                (None, count) => writeln!(f, " [label=\"x{count}\"];")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Walker {
+    /// Record the call, counting it with earlier calls from the caller to the callee.
+    ///
+    /// Calls to globals are resolved once the whole tree is walked.
+    fn call(
+        &mut self,
+        caller: &ProtoPath,
+        callee: Callee,
+        target: Option<ProtoPath>,
+        is_global: bool,
+    ) {
+        let existing =
+            self.graph.calls.iter_mut().find(|edge| {
+                &edge.caller == caller && edge.callee == callee && edge.target == target
+            });
+        if let Some(edge) = existing {
+            edge.count += 1;
+            return;
+        }
+
+        if let (true, Callee::Global(name) | Callee::Upvalue(name)) = (is_global, &callee) {
+            self.unresolved.push((self.graph.calls.len(), name.clone()));
+        }
+        self.graph.calls.push(CallEdge {
+            caller: caller.clone(),
+            callee,
+            target,
+            count: 1,
+        });
+    }
+
+    /// Name the function assigned to the variable, returning its path.
+    fn name_function(&mut self, name: &Ident, value: &Expr) -> Option<ProtoPath> {
+        let Expr::Function(function) = value else {
+            return None;
+        };
+        self.names
+            .entry(function.path.clone())
+            .or_insert_with(|| name.to_string());
+        Some(function.path.clone())
+    }
+
+    /// Walk the block, declaring its locals in the current
+    /// function's scope until the block ends.
+    ///
+    /// `outer` holds the locals of the enclosing function, which upvalues refer to.
+    fn block(
+        &mut self,
+        block: &Block,
+        path: &ProtoPath,
+        locals: &mut Vec<Binding>,
+        outer: &[Binding],
+    ) {
+        let depth = locals.len();
+        for node in &block.nodes {
+            match node {
+                Node::Stmt(stmt) => self.stmt(stmt, path, locals, outer),
+                Node::Expr(expr) => self.expr(expr, path, locals, outer),
+                Node::Partial(_) => {}
+            }
+        }
+        locals.truncate(depth);
+    }
+
+    fn stmt(
+        &mut self,
+        stmt: &Stmt,
+        path: &ProtoPath,
+        locals: &mut Vec<Binding>,
+        outer: &[Binding],
+    ) {
+        match stmt {
+            Stmt::LocalVar(local_var) => {
+                let functions: Vec<_> = (local_var.names.iter().zip(&local_var.rhs))
+                    .map(|(name, value)| self.name_function(name, value))
+                    .collect();
+                self.exprs(&local_var.rhs, path, locals, outer);
+                locals.extend(
+                    local_var
+                        .names
+                        .iter()
+                        .enumerate()
+                        .map(|(index, name)| Binding {
+                            name: name.to_string(),
+                            function: functions.get(index).cloned().flatten(),
+                        }),
+                );
+            }
+            Stmt::Assign(assign) => {
+                for (target, value) in assign.targets.iter().zip(&assign.rhs) {
+                    let Some(function) = self.name_function(target, value) else {
+                        continue;
+                    };
+                    match locals
+                        .iter_mut()
+                        .rev()
+                        .find(|local| local.name == target.as_str())
+                    {
+                        Some(local) => local.function = Some(function),
+                        None => {
+                            self.globals.entry(target.to_string()).or_insert(function);
+                        }
+                    }
+                }
+                self.exprs(&assign.rhs, path, locals, outer);
+            }
+            Stmt::Call(call) => {
+                self.callee(&call.name, path, locals, outer);
+                self.expr(&call.name, path, locals, outer);
+                self.exprs(&call.args, path, locals, outer);
+            }
+            Stmt::Block(block) => self.block(block, path, locals, outer),
+            Stmt::If(if_block) => {
+                self.expr(&if_block.head, path, locals, outer);
+                self.block(&if_block.then, path, locals, outer);
+                if let Some(else_) = &if_block.else_ {
+                    self.block(else_, path, locals, outer);
+                }
+            }
+            Stmt::While(while_block) => {
+                self.expr(&while_block.head, path, locals, outer);
+                self.block(&while_block.body, path, locals, outer);
+            }
+            Stmt::Repeat(repeat_block) => {
+                self.block(&repeat_block.body, path, locals, outer);
+                self.expr(&repeat_block.cond, path, locals, outer);
+            }
+            Stmt::Return(ret) => self.exprs(&ret.values, path, locals, outer),
+            Stmt::Goto(goto) => {
+                if let Some(cond) = &goto.cond {
+                    self.expr(cond, path, locals, outer);
+                }
+            }
+            Stmt::Break | Stmt::Label(_) | Stmt::Failed(_) | Stmt::Custom(_) | Stmt::Comment(_) => {
+            }
+        }
+    }
+
+    /// Record a call to the expression, resolving locals and
+    /// upvalues to the functions they were defined as.
+    fn callee(&mut self, name: &Expr, path: &ProtoPath, locals: &[Binding], outer: &[Binding]) {
+        let find = |scope: &[Binding], ident: &Ident| {
+            scope
+                .iter()
+                .rev()
+                .find(|local| local.name == ident.as_str())
+                .map(|local| local.function.clone())
+        };
+        let (callee, target, is_global) = match name {
+            Expr::Access(ident) => match find(locals, ident) {
+                Some(target) => (Callee::Local(ident.to_string()), target, false),
+                None => (Callee::Global(ident.to_string()), None, true),
+            },
+            // Upvalues that aren't locals of the enclosing function capture a global.
+            Expr::Upvalue(ident) => {
+                let target = find(outer, ident);
+                let is_global = target.is_none();
+                (
+                    Callee::Upvalue(ident.to_string()),
+                    target.flatten(),
+                    is_global,
+                )
+            }
+            _ => (Callee::Dynamic, None, false),
+        };
+        self.call(path, callee, target, is_global);
+    }
+
+    fn exprs(
+        &mut self,
+        exprs: &[Expr],
+        path: &ProtoPath,
+        locals: &mut Vec<Binding>,
+        outer: &[Binding],
+    ) {
+        exprs
+            .iter()
+            .for_each(|expr| self.expr(expr, path, locals, outer));
+    }
+
+    fn expr(
+        &mut self,
+        expr: &Expr,
+        path: &ProtoPath,
+        locals: &mut Vec<Binding>,
+        outer: &[Binding],
+    ) {
+        match expr {
+            Expr::Access(_) | Expr::Upvalue(_) | Expr::Literal(_) | Expr::Custom(_) => {}
+            Expr::Binary(bin_expr) => {
+                self.expr(&bin_expr.lhs, path, locals, outer);
+                self.expr(&bin_expr.rhs, path, locals, outer);
+            }
+            Expr::Unary(unary_expr) => self.expr(&unary_expr.rhs, path, locals, outer),
+            Expr::Call(call) => {
+                self.callee(&call.name, path, locals, outer);
+                self.expr(&call.name, path, locals, outer);
+                self.exprs(&call.args, path, locals, outer);
+            }
+            Expr::Function(function) => self.function(function, locals),
+            Expr::Table(table) => {
+                self.exprs(&table.items, path, locals, outer);
+                for field in &table.fields {
+                    self.expr(&field.key, path, locals, outer);
+                    self.expr(&field.value, path, locals, outer);
+                }
+            }
+        }
+    }
+
+    /// Walk a nested function, which only sees its own locals,
+    /// and the enclosing function's through upvalues.
+    fn function(&mut self, function: &Function, enclosing: &[Binding]) {
+        self.graph.functions.push(CallNode {
+            path: function.path.clone(),
+            name: self.names.get(&function.path).cloned(),
+        });
+        let mut params: Vec<_> = function
+            .params
+            .iter()
+            .map(|param| Binding {
+                name: param.to_string(),
+                function: None,
+            })
+            .collect();
+        if function.is_vararg {
+            params.push(Binding {
+                name: "arg".to_string(),
+                function: None,
+            });
+        }
+        self.block(&function.body, &function.path, &mut params, enclosing);
+    }
+}
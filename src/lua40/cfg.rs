@@ -0,0 +1,926 @@
+//! Control flow graph.
+//!
+//! Recovers the statements that a function's jumps were compiled from.
+//! Loops are found from the back edges in the dominator tree, and the
+//! remaining conditional jumps are matched to `if` statements when the
+//! blocks they skip can only be entered from the top.
+//!
+//! Conditions with `and` and `or` compile to a chain of conditional jumps,
+//! which are found first, so only the last jump of each chain is matched
+//! to a statement. Comparisons used as values are found before them, since
+//! their jumps land on the `nil` and `1` pushed for the result, and so are
+//! `and` and `or` expressions used as values, which keep the value they test
+//! when they jump.
+//!
+//! Jumps that don't fit any statement are left as gotos, which
+//! the parser turns into comments.
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use super::{Op, MULT_RET};
+use crate::errors::{Error, Result};
+
+/// Control flow graph of a function's basic blocks.
+pub(super) struct Cfg {
+    blocks: Vec<BasicBlock>,
+    /// Basic block that each instruction belongs to.
+    block_of: Box<[usize]>,
+    /// Destination of each jump instruction.
+    targets: Box<[Option<usize>]>,
+    /// Immediate dominator of each basic block.
+    ///
+    /// The entry block is its own dominator, and
+    /// blocks that can't be reached have none.
+    idom: Vec<Option<usize>>,
+}
+
+/// Sequence of instructions that is only entered at the
+/// first instruction, and only left after the last.
+#[derive(Debug)]
+struct BasicBlock {
+    /// Index of the first instruction.
+    start: usize,
+    /// Index of the instruction after the last.
+    end: usize,
+    succs: Vec<usize>,
+    preds: Vec<usize>,
+}
+
+/// How a jump instruction fits into the structured statements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Control {
+    /// Conditional jump over the `then` block of an `if` statement,
+    /// to the start of the `else` block when there is one.
+    If {
+        else_start: Option<usize>,
+        end: usize,
+    },
+    /// Jump at the end of a `then` block, over the `else` block.
+    Else,
+    /// Conditional jump out of a `while` loop, ahead of its body.
+    While { end: usize },
+    /// Jump at the end of a `while` loop, back to its start.
+    Continue,
+    /// Conditional jump at the end of a `repeat` loop, back to its start.
+    Until,
+    /// Jump out of the innermost loop.
+    Break,
+    /// Jump to the next instruction, which has no effect.
+    Implied,
+    /// Jump that doesn't fit any statement.
+    Goto { target: usize },
+    /// Conditional jump that's part of the condition of the jump at `head`,
+    /// joined to it with `and` or `or`.
+    Cond { head: usize },
+    /// Last conditional jump of a comparison used as a value, and the
+    /// `PUSHNILJMP` after it.
+    Value,
+    /// Conditional jump in an `and` or `or` expression used as a value.
+    Logic,
+}
+
+/// Conditional jump in a chain compiled from `and` and `or`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ChainJump<T> {
+    /// Condition under which the jump is taken.
+    pub cond: T,
+    pub target: usize,
+    /// Instruction after the jump, run when it isn't taken.
+    pub next: usize,
+}
+
+/// How two neighbouring jumps in a chain are joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Join {
+    /// Both jumps go to the same place, so either condition takes it.
+    Or,
+    /// The first jump skips the second, so the second is only
+    /// taken when the first isn't.
+    AndNot,
+}
+
+/// Range of instructions forming a block, which doesn't start
+/// with a jump instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Span {
+    pub start: usize,
+    /// Index of the instruction after the last.
+    pub end: usize,
+    pub kind: SpanKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SpanKind {
+    /// Body of a `repeat` loop, ending with its condition.
+    Repeat,
+    /// Body of a loop without a condition.
+    Loop,
+    /// Instructions skipped or repeated by a goto.
+    Do,
+}
+
+/// Structured statements recovered from the control flow graph.
+#[derive(Debug, Default)]
+pub(super) struct Structure {
+    /// Role of each jump instruction.
+    controls: Vec<Option<Control>>,
+    /// Blocks that don't start with a jump, sorted by start
+    /// with the outermost block first.
+    spans: Vec<Span>,
+    /// Instructions targeted by gotos.
+    labels: BTreeSet<usize>,
+}
+
+/// Working state while recovering the structure.
+struct Structurer<'a> {
+    ops: &'a [Op],
+    cfg: &'a Cfg,
+    controls: Vec<Option<Control>>,
+    spans: Vec<Span>,
+    labels: BTreeSet<usize>,
+    /// Instruction ranges of the accepted statements and their blocks,
+    /// which later statements must nest within.
+    ranges: Ranges,
+    /// Ranges of the accepted loops, from their first instruction
+    /// to the instruction after the loop.
+    loops: Vec<Range<usize>>,
+}
+
+/// Set of ranges, indexed by both ends to find
+/// the ones crossing a range without a linear scan.
+#[derive(Default)]
+struct Ranges {
+    /// Start and end of each range.
+    by_start: BTreeSet<(usize, usize)>,
+    /// End and start of each range.
+    by_end: BTreeSet<(usize, usize)>,
+}
+
+// ============================================================================
+
+/// Merge a chain of conditional jumps into a single jump, when they
+/// were compiled from one condition joined with `and` and `or`.
+///
+/// Neighbouring jumps are merged from the left, as long as the first goes
+/// where the second goes or right past it. The merged jump goes where the
+/// second goes, and is taken under the condition built by `join`.
+pub(super) fn merge_chain<T>(
+    mut jumps: Vec<ChainJump<T>>,
+    mut join: impl FnMut(Join, T, T) -> T,
+) -> Option<ChainJump<T>> {
+    while jumps.len() > 1 {
+        let (index, kind) = jumps.windows(2).enumerate().find_map(|(index, pair)| {
+            if pair[0].target == pair[1].target {
+                Some((index, Join::Or))
+            } else if pair[0].target == pair[1].next {
+                Some((index, Join::AndNot))
+            } else {
+                None
+            }
+        })?;
+        let first = jumps.remove(index);
+        let second = jumps.remove(index);
+        jumps.insert(
+            index,
+            ChainJump {
+                cond: join(kind, first.cond, second.cond),
+                target: second.target,
+                next: second.next,
+            },
+        );
+    }
+    jumps.pop()
+}
+
+/// Checks whether the instructions only push the operands popped by the
+/// conditional jump right after them, so they can be part of its condition.
+fn pushes_operands(ops: &[Op], operands: usize) -> bool {
+    // Calls address the stack from the function's base, which isn't known
+    // here, so each base that puts the first callee on the stack is tried.
+    let first_call = ops.iter().enumerate().find_map(|(index, op)| match op {
+        Op::Call { stack_offset, .. } => Some((index, *stack_offset as usize)),
+        _ => None,
+    });
+    match first_call {
+        Some((index, stack_offset)) => match stack_effect(&ops[..index], None) {
+            Some(height) => (0..height)
+                .filter_map(|callee| stack_offset.checked_sub(callee))
+                .any(|base| stack_effect(ops, Some(base)) == Some(operands)),
+            None => false,
+        },
+        None => stack_effect(ops, None) == Some(operands),
+    }
+}
+
+/// Number of values left on the stack by instructions that only push values,
+/// without popping any they didn't push.
+fn stack_effect(ops: &[Op], base: Option<usize>) -> Option<usize> {
+    let mut height = 0usize;
+    for op in ops {
+        let (pops, pushes) = match *op {
+            Op::PushInt { .. }
+            | Op::PushString { .. }
+            | Op::PushNum { .. }
+            | Op::PushNegNum { .. }
+            | Op::PushUpvalue { .. }
+            | Op::GetLocal { .. }
+            | Op::GetGlobal { .. } => (0, 1),
+            Op::Add | Op::Sub | Op::Mult | Op::Div | Op::Pow => (2, 1),
+            Op::AddI { .. } | Op::Minus | Op::Not => (1, 1),
+            Op::Concat { n } => (n as usize, 1),
+            Op::Call {
+                stack_offset,
+                results,
+            } if results != MULT_RET => {
+                let callee = (stack_offset as usize).checked_sub(base?)?;
+                (height.checked_sub(callee)?, results as usize)
+            }
+            _ => return None,
+        };
+        height = height.checked_sub(pops)? + pushes;
+    }
+    Some(height)
+}
+
+/// Number of values popped by a conditional jump.
+fn cond_operands(op: &Op) -> usize {
+    match op {
+        Op::JumpTrue { .. }
+        | Op::JumpFalse { .. }
+        | Op::JumpOnTrue { .. }
+        | Op::JumpOnFalse { .. } => 1,
+        _ => 2,
+    }
+}
+
+/// Destination of a jump instruction.
+fn jump_target(pc: usize, op: &Op, len: usize) -> Result<Option<usize>> {
+    let offset = match op.jump_offset() {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+
+    // Destination address is relative to the instruction following the current one.
+    let target = pc as i64 + 1 + offset as i64;
+    if target < 0 || target >= len as i64 {
+        return Error::new_decoder("jump destination out of bounds").into();
+    }
+
+    Ok(Some(target as usize))
+}
+
+fn is_cond_jump(op: &Op) -> bool {
+    op.jump_offset().is_some() && !matches!(op, Op::Jump { .. })
+}
+
+/// Checks whether the conditional jump keeps the value it tests when taken.
+fn is_keep_jump(op: &Op) -> bool {
+    matches!(op, Op::JumpOnTrue { .. } | Op::JumpOnFalse { .. })
+}
+
+/// Checks whether the instruction ends a statement, so it can't be
+/// part of an expression.
+pub(super) fn ends_statement(op: &Op) -> bool {
+    matches!(
+        op,
+        Op::End
+            | Op::Return { .. }
+            | Op::TailCall { .. }
+            | Op::Pop { .. }
+            | Op::SetLocal { .. }
+            | Op::SetGlobal { .. }
+            | Op::SetList { .. }
+            | Op::SetMap { .. }
+            | Op::Call { results: 0, .. }
+    )
+}
+
+impl Ranges {
+    fn insert(&mut self, range: Range<usize>) {
+        self.by_start.insert((range.start, range.end));
+        self.by_end.insert((range.end, range.start));
+    }
+
+    fn contains(&self, range: &Range<usize>) -> bool {
+        self.by_start.contains(&(range.start, range.end))
+    }
+
+    /// Checks whether any of the ranges partially overlaps the range,
+    /// starting or ending strictly inside it and sticking out.
+    fn crosses(&self, range: &Range<usize>) -> bool {
+        if range.len() < 2 {
+            return false;
+        }
+        let inside = (range.start + 1, 0)..(range.end, 0);
+        self.by_start
+            .range(inside.clone())
+            .any(|&(_, end)| end > range.end)
+            || self
+                .by_end
+                .range(inside)
+                .any(|&(_, start)| start < range.start)
+    }
+}
+
+impl Extend<Range<usize>> for Ranges {
+    fn extend<T: IntoIterator<Item = Range<usize>>>(&mut self, ranges: T) {
+        for range in ranges {
+            self.insert(range);
+        }
+    }
+}
+
+// ============================================================================
+
+impl Cfg {
+    pub(super) fn new(ops: &[Op]) -> Result<Self> {
+        let len = ops.len();
+        let targets = ops
+            .iter()
+            .enumerate()
+            .map(|(pc, op)| jump_target(pc, op, len))
+            .collect::<Result<Box<[_]>>>()?;
+
+        // Blocks start at the entry, at jump destinations,
+        // and after instructions that leave the block.
+        let mut leaders = vec![false; len];
+        if let Some(first) = leaders.first_mut() {
+            *first = true;
+        }
+        for (pc, op) in ops.iter().enumerate() {
+            if let Some(target) = targets[pc] {
+                leaders[target] = true;
+            }
+            if (targets[pc].is_some() || !op.falls_through()) && pc + 1 < len {
+                leaders[pc + 1] = true;
+            }
+        }
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        let mut block_of = vec![0; len].into_boxed_slice();
+        for pc in 0..len {
+            if leaders[pc] {
+                blocks.push(BasicBlock {
+                    start: pc,
+                    end: pc,
+                    succs: vec![],
+                    preds: vec![],
+                });
+            }
+            let last = blocks.len() - 1;
+            blocks[last].end = pc + 1;
+            block_of[pc] = last;
+        }
+
+        for index in 0..blocks.len() {
+            let last = blocks[index].end - 1;
+            let mut succs = vec![];
+            if ops[last].falls_through() && last + 1 < len {
+                succs.push(block_of[last + 1]);
+            }
+            if let Some(target) = targets[last] {
+                if !succs.contains(&block_of[target]) {
+                    succs.push(block_of[target]);
+                }
+            }
+            for succ in &succs {
+                blocks[*succ].preds.push(index);
+            }
+            blocks[index].succs = succs;
+        }
+
+        let idom = dominators(&blocks);
+
+        Ok(Self {
+            blocks,
+            block_of,
+            targets,
+            idom,
+        })
+    }
+
+    /// Destination of the jump instruction.
+    pub(super) fn target(&self, pc: usize) -> Option<usize> {
+        self.targets.get(pc).copied().flatten()
+    }
+
+    /// Checks whether every path from the entry to block `b` passes through block `a`.
+    ///
+    /// Blocks that can't be reached are dominated by every block.
+    fn dominates(&self, a: usize, b: usize) -> bool {
+        if self.idom[b].is_none() {
+            return true;
+        }
+
+        let mut node = b;
+        loop {
+            if node == a {
+                return true;
+            }
+            match self.idom[node] {
+                Some(parent) if parent != node => node = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Checks whether the instructions in the range can only
+    /// be entered through the first one.
+    fn is_single_entry(&self, range: Range<usize>) -> bool {
+        if range.is_empty() {
+            return true;
+        }
+        let entry = self.block_of[range.start];
+        if self.blocks[entry].start != range.start {
+            return false;
+        }
+        range
+            .map(|pc| self.block_of[pc])
+            .all(|block| self.dominates(entry, block))
+    }
+
+    /// Index of the last instruction in the basic block holding the instruction.
+    fn block_last(&self, pc: usize) -> usize {
+        self.blocks[self.block_of[pc]].end - 1
+    }
+}
+
+/// Find the immediate dominator of each block, using the iterative algorithm from
+/// "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy.
+fn dominators(blocks: &[BasicBlock]) -> Vec<Option<usize>> {
+    let count = blocks.len();
+    let mut idom = vec![None; count];
+    if count == 0 {
+        return idom;
+    }
+
+    // Depth first search from the entry.
+    let mut postorder = vec![];
+    let mut visited = vec![false; count];
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.last_mut() {
+        match blocks[*block].succs.get(*next) {
+            Some(&succ) => {
+                *next += 1;
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            }
+            None => {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+    }
+
+    let mut order = vec![usize::MAX; count];
+    for (index, block) in postorder.iter().rev().enumerate() {
+        order[*block] = index;
+    }
+
+    let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+        while a != b {
+            while order[a] > order[b] {
+                a = idom[a].expect("processed block has a dominator");
+            }
+            while order[b] > order[a] {
+                b = idom[b].expect("processed block has a dominator");
+            }
+        }
+        a
+    };
+
+    idom[0] = Some(0);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in postorder.iter().rev().skip(1) {
+            let mut new_idom = None;
+            for pred in &blocks[*block].preds {
+                if idom[*pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    Some(other) => intersect(&idom, *pred, other),
+                    None => *pred,
+                });
+            }
+            if new_idom != idom[*block] {
+                idom[*block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    idom
+}
+
+// ============================================================================
+
+impl Structure {
+    pub(super) fn new(ops: &[Op]) -> Result<Self> {
+        let cfg = Cfg::new(ops)?;
+        let mut structurer = Structurer {
+            ops,
+            cfg: &cfg,
+            controls: vec![None; ops.len()],
+            spans: vec![],
+            labels: BTreeSet::new(),
+            ranges: Ranges::default(),
+            loops: vec![],
+        };
+
+        structurer.find_values();
+        structurer.find_logic();
+        structurer.find_conditions();
+        structurer.find_loops();
+        structurer.find_breaks();
+        structurer.find_ifs();
+        structurer.find_gotos();
+
+        let Structurer {
+            controls,
+            mut spans,
+            labels,
+            ..
+        } = structurer;
+        spans.sort_by_key(|span| (span.start, std::cmp::Reverse(span.end)));
+
+        Ok(Self {
+            controls,
+            spans,
+            labels,
+        })
+    }
+
+    /// Role of the jump instruction.
+    pub(super) fn control(&self, pc: usize) -> Option<Control> {
+        self.controls.get(pc).copied().flatten()
+    }
+
+    /// Blocks starting at the instruction, outermost first.
+    pub(super) fn spans_at(&self, pc: usize) -> impl Iterator<Item = &Span> {
+        self.spans.iter().filter(move |span| span.start == pc)
+    }
+
+    /// Remove the goto targets in the range.
+    pub(super) fn take_labels(&mut self, range: Range<usize>) -> Vec<usize> {
+        let labels: Vec<usize> = self.labels.range(range).copied().collect();
+        for label in &labels {
+            self.labels.remove(label);
+        }
+        labels
+    }
+}
+
+impl<'a> Structurer<'a> {
+    /// Comparisons used as values push `nil` when their jumps aren't taken,
+    /// then skip the `PUSHINT 1` that the jumps land on.
+    ///
+    /// ```text
+    /// JMPLT 2, PUSHNILJMP, PUSHINT 1
+    /// ```
+    fn find_values(&mut self) {
+        for pc in 1..self.ops.len().saturating_sub(1) {
+            let is_value = matches!(self.ops[pc], Op::PushNilJump)
+                && matches!(self.ops[pc + 1], Op::PushInt { value: 1 })
+                && is_cond_jump(&self.ops[pc - 1])
+                && self.cfg.target(pc - 1) == Some(pc + 1);
+            if !is_value {
+                continue;
+            }
+            let head = pc - 1;
+            for part in self.chain(head) {
+                self.controls[part] = Some(Control::Cond { head });
+            }
+            self.controls[head] = Some(Control::Value);
+            self.controls[pc] = Some(Control::Value);
+        }
+    }
+
+    /// Expressions with `and` and `or` used as values jump to the instruction
+    /// after them with the value that decides them, skipping the operands
+    /// after it.
+    ///
+    /// ```text
+    /// GETGLOBAL a, JMPONT 1, GETGLOBAL b
+    /// ```
+    ///
+    /// An operand joined to the next with the other operator is tested and
+    /// popped by a plain conditional jump instead, which lands on the operand
+    /// after the next, like the `JMPF` of `a and b or c`.
+    fn find_logic(&mut self) {
+        for pc in 0..self.ops.len() {
+            if self.controls[pc].is_some() || !is_keep_jump(&self.ops[pc]) {
+                continue;
+            }
+            let Some(end) = self.cfg.target(pc).filter(|end| *end > pc) else {
+                continue;
+            };
+
+            let mut first = pc;
+            for part in (0..end).rev() {
+                let op = &self.ops[part];
+                let Some(target) = self.cfg.target(part).filter(|_| is_cond_jump(op)) else {
+                    if part < first && (ends_statement(op) || self.cfg.target(part).is_some()) {
+                        break;
+                    }
+                    continue;
+                };
+                let joined = self.controls[part].is_none()
+                    && if is_keep_jump(op) {
+                        target == end
+                    } else {
+                        target > part.max(first) && target < end
+                    };
+                if joined {
+                    self.controls[part] = Some(Control::Logic);
+                    first = first.min(part);
+                } else if part < first && !(part < target && target <= first) {
+                    // Only jumps within the operands, like those of
+                    // a comparison used as a value, come before it.
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Conditional jumps with only the operands of the next one between them
+    /// are joined into one condition, when the chain merges into a single jump.
+    ///
+    /// The jump ending the chain is left for the statements to match.
+    fn find_conditions(&mut self) {
+        for head in (0..self.ops.len()).rev() {
+            if self.controls[head].is_some() || !is_cond_jump(&self.ops[head]) {
+                continue;
+            }
+            for part in self.chain(head) {
+                self.controls[part] = Some(Control::Cond { head });
+            }
+        }
+    }
+
+    /// Conditional jumps joined to the one at `head`, taking
+    /// the longest chain that merges into one jump.
+    fn chain(&self, head: usize) -> Vec<usize> {
+        let mut jumps = vec![head];
+        let mut first = head;
+        loop {
+            let mut start = first;
+            while start > 0 && self.cfg.block_of[start - 1] == self.cfg.block_of[first] {
+                start -= 1;
+            }
+            if start == 0
+                || !pushes_operands(&self.ops[start..first], cond_operands(&self.ops[first]))
+            {
+                break;
+            }
+            let prev = start - 1;
+            if self.controls[prev].is_some() || !is_cond_jump(&self.ops[prev]) {
+                break;
+            }
+            jumps.push(prev);
+            first = prev;
+        }
+        jumps.reverse();
+
+        // The operands of each jump after the first may only be entered
+        // from the jumps before them, by falling through or jumping.
+        let entered_within = |parts: &[usize]| {
+            parts[1..].iter().all(|part| {
+                let entry = self.cfg.block_of[*part];
+                self.cfg.blocks[entry].preds.iter().all(|pred| {
+                    let last = self.cfg.blocks[*pred].end - 1;
+                    parts.contains(&last) && last < *part
+                })
+            })
+        };
+        let merges = |parts: &[usize]| {
+            let jumps = parts
+                .iter()
+                .map(|pc| ChainJump {
+                    cond: (),
+                    target: self.cfg.target(*pc).unwrap_or(*pc),
+                    next: pc + 1,
+                })
+                .collect();
+            merge_chain(jumps, |_, _, _| ()).is_some()
+        };
+        let len = jumps.len();
+        (0..len - 1)
+            .find(|index| entered_within(&jumps[*index..]) && merges(&jumps[*index..]))
+            .map(|index| jumps[index..len - 1].to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Loops are closed by a jump back to their first instruction, which
+    /// must dominate the whole body.
+    ///
+    /// A `while` loop tests its condition in the first block, and jumps back
+    /// unconditionally. A `repeat` loop jumps back when its condition fails.
+    fn find_loops(&mut self) {
+        let mut back_edges: Vec<(usize, usize)> = (0..self.ops.len())
+            .filter_map(|pc| match self.cfg.target(pc) {
+                Some(target) if target <= pc => Some((target, pc)),
+                _ => None,
+            })
+            .collect();
+        // Outer loops first, so nested loops sharing a start are accepted too.
+        back_edges.sort_by_key(|(start, latch)| (*start, std::cmp::Reverse(*latch)));
+
+        for (start, latch) in back_edges {
+            // Part of the condition of a `repeat` loop, ending at a later jump.
+            if self.controls[latch].is_some() {
+                continue;
+            }
+            let range = start..latch + 1;
+            if !self.cfg.is_single_entry(range.clone()) || !self.nests(&range) {
+                continue;
+            }
+
+            match self.ops[latch] {
+                Op::Jump { .. } => {
+                    // The condition may be a chain, ending at the head.
+                    let head = match self.controls[self.cfg.block_last(start)] {
+                        Some(Control::Cond { head }) => head,
+                        _ => self.cfg.block_last(start),
+                    };
+                    let has_cond = head < latch
+                        && is_cond_jump(&self.ops[head])
+                        && self.cfg.target(head) == Some(range.end);
+                    if has_cond {
+                        self.controls[head] = Some(Control::While { end: range.end });
+                        self.ranges.insert(head..range.end);
+                    } else {
+                        self.add_span(range.clone(), SpanKind::Loop);
+                    }
+                    self.controls[latch] = Some(Control::Continue);
+                }
+                ref op if is_cond_jump(op) => {
+                    self.controls[latch] = Some(Control::Until);
+                    self.add_span(range.clone(), SpanKind::Repeat);
+                }
+                _ => continue,
+            }
+
+            self.ranges.insert(range.clone());
+            self.loops.push(range);
+        }
+    }
+
+    /// Jumps to the instruction after the innermost loop are breaks,
+    /// conditional ones being breaks wrapped in an `if` statement.
+    fn find_breaks(&mut self) {
+        for pc in 0..self.ops.len() {
+            if self.controls[pc].is_some() {
+                continue;
+            }
+            let target = match self.cfg.target(pc) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let innermost = self
+                .loops
+                .iter()
+                .filter(|range| range.contains(&pc))
+                .min_by_key(|range| range.len());
+            if innermost.is_some_and(|range| range.end == target) {
+                self.controls[pc] = Some(Control::Break);
+            }
+        }
+    }
+
+    /// Forward conditional jumps skip the `then` block of an `if` statement.
+    ///
+    /// When the `then` block ends in a forward jump, that jump skips the
+    /// `else` block. Both blocks can only be left at their end, or by breaking
+    /// out of an enclosing loop.
+    fn find_ifs(&mut self) {
+        for head in 0..self.ops.len() {
+            if self.controls[head].is_some() || !is_cond_jump(&self.ops[head]) {
+                continue;
+            }
+            let target = match self.cfg.target(head) {
+                Some(target) if target > head => target,
+                _ => continue,
+            };
+
+            // Jump at the end of the `then` block, over the `else` block.
+            let else_jump = target - 1;
+            let else_end = match self.cfg.target(else_jump) {
+                Some(end)
+                    if else_jump > head
+                        && end > target
+                        && self.controls[else_jump].is_none()
+                        && matches!(self.ops[else_jump], Op::Jump { .. }) =>
+                {
+                    Some(end)
+                }
+                _ => None,
+            };
+
+            if let Some(end) = else_end {
+                if self.is_if(head, target, Some(end)) {
+                    self.controls[head] = Some(Control::If {
+                        else_start: Some(target),
+                        end,
+                    });
+                    self.controls[else_jump] = Some(Control::Else);
+                    self.ranges.extend([head..end, head..target, target..end]);
+                    continue;
+                }
+            }
+
+            if self.is_if(head, target, None) {
+                self.controls[head] = Some(Control::If {
+                    else_start: None,
+                    end: target,
+                });
+                self.ranges.insert(head..target);
+            }
+        }
+    }
+
+    /// The remaining jumps are gotos.
+    ///
+    /// The instructions they skip or repeat are wrapped in a `do` block,
+    /// when it doesn't cross other statements, to make them stand out.
+    fn find_gotos(&mut self) {
+        for pc in 0..self.ops.len() {
+            if self.controls[pc].is_some() {
+                continue;
+            }
+            let target = match self.cfg.target(pc) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            if target == pc + 1 && matches!(self.ops[pc], Op::Jump { .. }) {
+                self.controls[pc] = Some(Control::Implied);
+                continue;
+            }
+
+            self.controls[pc] = Some(Control::Goto { target });
+            self.labels.insert(target);
+
+            let range = if target > pc {
+                pc + 1..target
+            } else {
+                target..pc + 1
+            };
+            if !range.is_empty() && self.nests(&range) && !self.ranges.contains(&range) {
+                self.add_span(range.clone(), SpanKind::Do);
+                self.ranges.insert(range);
+            }
+        }
+    }
+
+    /// Checks whether the conditional jump at `head` to `target`
+    /// is an `if` statement, with an `else` block ending at `else_end`.
+    fn is_if(&self, head: usize, target: usize, else_end: Option<usize>) -> bool {
+        let end = else_end.unwrap_or(target);
+        if !self.nests(&(head..end)) {
+            return false;
+        }
+
+        match else_end {
+            Some(else_end) => {
+                // The jump over the `else` block is left out of the `then` block.
+                self.is_region(head + 1..target - 1) && self.is_region(target..else_end)
+            }
+            None => self.is_region(head + 1..target),
+        }
+    }
+
+    /// Checks whether the instructions can be a block, which is only
+    /// entered at the start and only left at the end or with a break.
+    fn is_region(&self, range: Range<usize>) -> bool {
+        if !self.cfg.is_single_entry(range.clone()) {
+            return false;
+        }
+
+        range.clone().all(|pc| {
+            let leaves = |dest: usize| dest != range.end && !range.contains(&dest);
+            let falls_out = self.ops[pc].falls_through() && leaves(pc + 1);
+            let jumps_out = self.cfg.target(pc).is_some_and(leaves)
+                && self.controls[pc] != Some(Control::Break);
+            !falls_out && !jumps_out
+        })
+    }
+
+    /// Checks whether the range doesn't partially overlap any accepted statement.
+    fn nests(&self, range: &Range<usize>) -> bool {
+        !self.ranges.crosses(range)
+    }
+
+    fn add_span(&mut self, range: Range<usize>, kind: SpanKind) {
+        self.spans.push(Span {
+            start: range.start,
+            end: range.end,
+            kind,
+        });
+    }
+}
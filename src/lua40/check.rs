@@ -0,0 +1,652 @@
+//! Syntax check of decompiled source against the Lua 4.0 grammar.
+//!
+//! The [super::Scribe] should only ever write valid source, but a bug may
+//! leave out an `end`, or write an operator that Lua 4.0 doesn't have.
+//! Reading the source back with the grammar of `lparser.c`, without
+//! building anything, catches those before the source is handed out,
+//! as an internal error pointing at the line.
+use super::ast::KEYWORDS;
+use crate::errors::{Error, Result};
+
+/// Symbols, longest first so the lexer takes the longest match.
+const SYMBOLS: &[&str] = &[
+    "...", "..", "==", "~=", "<=", ">=", "=", "<", ">", "+", "-", "*", "/", "^", "(", ")", "{",
+    "}", "[", "]", ";", ":", ",", ".", "%",
+];
+
+const BINARY_OPS: &[&str] = &[
+    "+", "-", "*", "/", "^", "..", "==", "~=", "<", "<=", ">", ">=", "and", "or",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Name,
+    Keyword,
+    Number,
+    String,
+    Symbol,
+    Eof,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Token<'a> {
+    pub(super) kind: Kind,
+    pub(super) text: &'a str,
+    line: u32,
+}
+
+/// Lexer of Lua 4.0 source, also used to highlight it.
+pub(super) struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    line: u32,
+}
+
+/// What a suffixed expression ends with, which decides
+/// whether it can be assigned to or stand as a statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Suffixed {
+    Var,
+    Call,
+    Other,
+}
+
+struct Checker<'a> {
+    source: &'a str,
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+// ============================================================================
+
+/// Check that the source parses as a Lua 4.0 chunk.
+///
+/// Fails with an internal error on the first syntax error,
+/// with the line it's on as the context.
+pub fn check_syntax(source: &str) -> Result<()> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = vec![];
+    loop {
+        let token = lexer
+            .next_token()
+            .map_err(|message| err_syntax(source, lexer.line, message))?;
+        tokens.push(token);
+        if token.kind == Kind::Eof {
+            break;
+        }
+    }
+
+    let mut checker = Checker {
+        source,
+        tokens,
+        pos: 0,
+    };
+    checker.block()?;
+    checker.expect("<eof>")
+}
+
+fn err_syntax(source: &str, line: u32, message: impl std::fmt::Display) -> Error {
+    let text = source
+        .lines()
+        .nth(line as usize - 1)
+        .unwrap_or_default()
+        .trim();
+    Error::new_internal(format!("decompiled source doesn't parse: {message}"))
+        .with_context(format!("line {line}: {text}"))
+}
+
+impl<'a> Lexer<'a> {
+    pub(super) fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    /// Byte offset in the source of the end of the last token.
+    pub(super) fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    /// Next token, skipping the whitespace and comments before it.
+    pub(super) fn next_token(&mut self) -> std::result::Result<Token<'a>, String> {
+        self.skip_space();
+        let rest = self.rest();
+        let start = self.pos;
+        let line = self.line;
+        let Some(c) = rest.chars().next() else {
+            return Ok(Token {
+                kind: Kind::Eof,
+                text: "",
+                line,
+            });
+        };
+
+        let kind = if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            self.pos += len;
+            if KEYWORDS.contains(&&rest[..len]) {
+                Kind::Keyword
+            } else {
+                Kind::Name
+            }
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            self.number()?;
+            Kind::Number
+        } else if c == '"' || c == '\'' {
+            self.string(c)?;
+            Kind::String
+        } else if rest.starts_with("[[") {
+            self.long_string()?;
+            Kind::String
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| format!("unexpected character `{c}`"))?;
+            self.pos += symbol.len();
+            Kind::Symbol
+        };
+
+        Ok(Token {
+            kind,
+            text: &self.source[start..self.pos],
+            line,
+        })
+    }
+
+    /// Skip whitespace and comments, which run to the end of the line.
+    fn skip_space(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.line += rest[..rest.len() - trimmed.len()].matches('\n').count() as u32;
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with("--") {
+                return;
+            }
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn number(&mut self) -> std::result::Result<(), String> {
+        let rest = self.rest();
+        let bytes = rest.as_bytes();
+        let mut len = 0;
+        while len < bytes.len() && (bytes[len].is_ascii_digit() || bytes[len] == b'.') {
+            len += 1;
+        }
+        if len < bytes.len() && matches!(bytes[len], b'e' | b'E') {
+            len += 1;
+            if len < bytes.len() && matches!(bytes[len], b'+' | b'-') {
+                len += 1;
+            }
+            while len < bytes.len() && bytes[len].is_ascii_digit() {
+                len += 1;
+            }
+        }
+        let text = &rest[..len];
+        let trailing = bytes
+            .get(len)
+            .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_');
+        if trailing || text.parse::<f64>().is_err() {
+            return Err(format!("malformed number near `{text}`"));
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    fn string(&mut self, quote: char) -> std::result::Result<(), String> {
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    // An escaped newline continues the string on the next line.
+                    if let Some((_, '\n')) = chars.next() {
+                        self.line += 1;
+                    }
+                }
+                '\n' => break,
+                c if c == quote => {
+                    self.pos += offset + c.len_utf8();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        Err("unfinished string".to_string())
+    }
+
+    /// Long strings, which nest in Lua 4.0.
+    fn long_string(&mut self) -> std::result::Result<(), String> {
+        let rest = self.rest();
+        let mut depth = 0;
+        let mut offset = 0;
+        while offset < rest.len() {
+            let tail = &rest[offset..];
+            if tail.starts_with("[[") {
+                depth += 1;
+                offset += 2;
+            } else if tail.starts_with("]]") {
+                depth -= 1;
+                offset += 2;
+                if depth == 0 {
+                    self.line += rest[..offset].matches('\n').count() as u32;
+                    self.pos += offset;
+                    return Ok(());
+                }
+            } else {
+                offset += tail.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        Err("unfinished long string".to_string())
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn peek(&self) -> Token<'a> {
+        self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn peek_next(&self) -> Token<'a> {
+        self.tokens[(self.pos + 1).min(self.tokens.len() - 1)]
+    }
+
+    fn advance(&mut self) -> Token<'a> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    /// Whether the next token is the keyword or symbol.
+    fn check(&self, text: &str) -> bool {
+        let token = self.peek();
+        matches!(token.kind, Kind::Keyword | Kind::Symbol) && token.text == text
+    }
+
+    fn accept(&mut self, text: &str) -> bool {
+        let found = self.check(text);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> Error {
+        let token = self.peek();
+        let (near, line) = match token.kind {
+            // Pointing at the last line with anything on it, rather than
+            // the empty one after the final newline.
+            Kind::Eof => {
+                let line = self
+                    .tokens
+                    .iter()
+                    .rev()
+                    .find(|token| token.kind != Kind::Eof)
+                    .map_or(1, |token| token.line);
+                ("the end of the source".to_string(), line)
+            }
+            _ => (format!("`{}`", token.text), token.line),
+        };
+        err_syntax(self.source, line, format!("{message} near {near}"))
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        if text == "<eof>" && self.peek().kind == Kind::Eof {
+            return Ok(());
+        }
+        if self.accept(text) {
+            return Ok(());
+        }
+        match text {
+            "<eof>" => Err(self.error("end of the source expected")),
+            _ => Err(self.error(format!("`{text}` expected"))),
+        }
+    }
+
+    /// Expect the token closing a construct, mentioning where it was opened.
+    fn expect_match(&mut self, close: &str, open: &str, line: u32) -> Result<()> {
+        if self.accept(close) {
+            return Ok(());
+        }
+        if line == self.peek().line {
+            Err(self.error(format!("`{close}` expected")))
+        } else {
+            Err(self.error(format!(
+                "`{close}` expected to close `{open}` at line {line}"
+            )))
+        }
+    }
+
+    fn name(&mut self) -> Result<()> {
+        if self.peek().kind == Kind::Name {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error("name expected"))
+        }
+    }
+
+    fn block_follows(&self) -> bool {
+        self.peek().kind == Kind::Eof
+            || ["else", "elseif", "end", "until"]
+                .iter()
+                .any(|text| self.check(text))
+    }
+
+    /// Statements up to the end of the block, where `return`
+    /// and `break` can only be the last one.
+    fn block(&mut self) -> Result<()> {
+        while !self.block_follows() {
+            let is_last = self.statement()?;
+            self.accept(";");
+            if is_last {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the statement must be the last of its block.
+    fn statement(&mut self) -> Result<bool> {
+        let token = self.peek();
+        if token.kind != Kind::Keyword {
+            self.expr_statement()?;
+            return Ok(false);
+        }
+
+        let line = token.line;
+        match token.text {
+            "if" => {
+                self.advance();
+                self.expr()?;
+                self.expect("then")?;
+                self.block()?;
+                while self.accept("elseif") {
+                    self.expr()?;
+                    self.expect("then")?;
+                    self.block()?;
+                }
+                if self.accept("else") {
+                    self.block()?;
+                }
+                self.expect_match("end", "if", line)?;
+            }
+            "while" => {
+                self.advance();
+                self.expr()?;
+                self.expect("do")?;
+                self.block()?;
+                self.expect_match("end", "while", line)?;
+            }
+            "do" => {
+                self.advance();
+                self.block()?;
+                self.expect_match("end", "do", line)?;
+            }
+            "for" => {
+                self.advance();
+                self.name()?;
+                if self.accept("=") {
+                    self.expr()?;
+                    self.expect(",")?;
+                    self.expr()?;
+                    if self.accept(",") {
+                        self.expr()?;
+                    }
+                } else if self.accept(",") {
+                    self.name()?;
+                    self.expect("in")?;
+                    self.expr()?;
+                } else {
+                    return Err(self.error("`=` or `,` expected"));
+                }
+                self.expect("do")?;
+                self.block()?;
+                self.expect_match("end", "for", line)?;
+            }
+            "repeat" => {
+                self.advance();
+                self.block()?;
+                self.expect_match("until", "repeat", line)?;
+                self.expr()?;
+            }
+            "function" => {
+                self.advance();
+                self.name()?;
+                while self.accept(".") {
+                    self.name()?;
+                }
+                if self.accept(":") {
+                    self.name()?;
+                }
+                self.body(line)?;
+            }
+            "local" => {
+                self.advance();
+                self.name()?;
+                while self.accept(",") {
+                    self.name()?;
+                }
+                if self.accept("=") {
+                    self.expr_list()?;
+                }
+            }
+            "return" => {
+                self.advance();
+                if !self.block_follows() && !self.check(";") {
+                    self.expr_list()?;
+                }
+                return Ok(true);
+            }
+            "break" => {
+                self.advance();
+                return Ok(true);
+            }
+            _ => self.expr_statement()?,
+        }
+        Ok(false)
+    }
+
+    /// A call, or an assignment to variables.
+    fn expr_statement(&mut self) -> Result<()> {
+        let first = self.suffixed_expr()?;
+        if self.check("=") || self.check(",") {
+            if first != Suffixed::Var {
+                return Err(self.error("syntax error"));
+            }
+            while self.accept(",") {
+                if self.suffixed_expr()? != Suffixed::Var {
+                    return Err(self.error("syntax error"));
+                }
+            }
+            self.expect("=")?;
+            self.expr_list()
+        } else if first == Suffixed::Call {
+            Ok(())
+        } else {
+            Err(self.error("syntax error"))
+        }
+    }
+
+    fn suffixed_expr(&mut self) -> Result<Suffixed> {
+        let token = self.peek();
+        let mut kind = match (token.kind, token.text) {
+            (Kind::Name, _) => {
+                self.advance();
+                Suffixed::Var
+            }
+            // Upvalues can only be read.
+            (Kind::Symbol, "%") => {
+                self.advance();
+                self.name()?;
+                Suffixed::Other
+            }
+            (Kind::Symbol, "(") => {
+                self.advance();
+                self.expr()?;
+                self.expect_match(")", "(", token.line)?;
+                Suffixed::Other
+            }
+            _ => return Err(self.error("unexpected symbol")),
+        };
+
+        loop {
+            let token = self.peek();
+            match (token.kind, token.text) {
+                (Kind::Symbol, ".") => {
+                    self.advance();
+                    self.name()?;
+                    kind = Suffixed::Var;
+                }
+                (Kind::Symbol, "[") => {
+                    self.advance();
+                    self.expr()?;
+                    self.expect("]")?;
+                    kind = Suffixed::Var;
+                }
+                (Kind::Symbol, ":") => {
+                    self.advance();
+                    self.name()?;
+                    self.args()?;
+                    kind = Suffixed::Call;
+                }
+                (Kind::Symbol, "(" | "{") | (Kind::String, _) => {
+                    self.args()?;
+                    kind = Suffixed::Call;
+                }
+                _ => return Ok(kind),
+            }
+        }
+    }
+
+    fn args(&mut self) -> Result<()> {
+        let token = self.peek();
+        match (token.kind, token.text) {
+            (Kind::String, _) => {
+                self.advance();
+                Ok(())
+            }
+            (Kind::Symbol, "{") => self.constructor(),
+            (Kind::Symbol, "(") => {
+                self.advance();
+                if !self.check(")") {
+                    self.expr_list()?;
+                }
+                self.expect_match(")", "(", token.line)
+            }
+            _ => Err(self.error("function arguments expected")),
+        }
+    }
+
+    fn expr_list(&mut self) -> Result<()> {
+        self.expr()?;
+        while self.accept(",") {
+            self.expr()?;
+        }
+        Ok(())
+    }
+
+    /// Operands joined by binary operators, each with any number of unary
+    /// operators, since the priorities don't change what parses.
+    fn expr(&mut self) -> Result<()> {
+        loop {
+            while self.accept("not") || self.accept("-") {}
+            self.simple_expr()?;
+            let token = self.peek();
+            let is_binary = matches!(token.kind, Kind::Keyword | Kind::Symbol)
+                && BINARY_OPS.contains(&token.text);
+            if !is_binary {
+                return Ok(());
+            }
+            self.advance();
+        }
+    }
+
+    fn simple_expr(&mut self) -> Result<()> {
+        let token = self.peek();
+        match (token.kind, token.text) {
+            (Kind::Number | Kind::String, _) | (Kind::Keyword, "nil") => {
+                self.advance();
+                Ok(())
+            }
+            (Kind::Symbol, "{") => self.constructor(),
+            (Kind::Keyword, "function") => {
+                self.advance();
+                self.body(token.line)
+            }
+            _ => self.suffixed_expr().map(|_| ()),
+        }
+    }
+
+    /// Parameters and body of a function, up to its `end`.
+    fn body(&mut self, line: u32) -> Result<()> {
+        self.expect("(")?;
+        if !self.check(")") {
+            loop {
+                if self.accept("...") {
+                    break;
+                }
+                self.name()?;
+                if !self.accept(",") {
+                    break;
+                }
+            }
+        }
+        self.expect(")")?;
+        self.block()?;
+        self.expect_match("end", "function", line)
+    }
+
+    /// Table constructor, with a list part and a record part in
+    /// either order, separated by `;` when there are both.
+    fn constructor(&mut self) -> Result<()> {
+        let line = self.peek().line;
+        self.expect("{")?;
+        let first = self.constructor_part()?;
+        if self.accept(";") {
+            let second = self.constructor_part()?;
+            if first == second {
+                return Err(self.error("invalid constructor syntax"));
+            }
+        }
+        self.expect_match("}", "{", line)
+    }
+
+    /// Returns the kind of the part, or the token after it when it's empty.
+    fn constructor_part(&mut self) -> Result<&'a str> {
+        let token = self.peek();
+        if self.check(";") || self.check("}") {
+            return Ok(token.text);
+        }
+        let is_record = self.check("[")
+            || (token.kind == Kind::Name
+                && self.peek_next().kind == Kind::Symbol
+                && self.peek_next().text == "=");
+        loop {
+            if is_record {
+                if self.accept("[") {
+                    self.expr()?;
+                    self.expect("]")?;
+                } else {
+                    self.name()?;
+                }
+                self.expect("=")?;
+            }
+            self.expr()?;
+            if !self.accept(",") || self.check(";") || self.check("}") {
+                break;
+            }
+        }
+        Ok(if is_record { "record" } else { "list" })
+    }
+}
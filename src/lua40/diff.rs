@@ -0,0 +1,279 @@
+//! Differences between two chunks, like a game script before and after a patch.
+//!
+//! Nested functions are matched up between the chunks around the ones that are
+//! identical in both, so a function inserted in the middle shows up as added
+//! instead of every function after it showing up as changed. The rest are
+//! matched by their position among their siblings.
+//!
+//! Like [super::validate], debug information isn't compared.
+use std::fmt::{self, Formatter};
+
+use super::validate::{compare, same_instr};
+use super::{path_name, Proto};
+use crate::lstring::LuaString;
+
+/// Functions that differ between two chunks.
+#[derive(Debug, Default)]
+pub struct ChunkDiff {
+    pub changes: Vec<FunctionChange>,
+}
+
+/// Function added, removed or changed between two chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionChange {
+    /// Function only in the new chunk, with its nested functions.
+    Added { path: Vec<usize> },
+    /// Function only in the old chunk, with its nested functions.
+    Removed { path: Vec<usize> },
+    /// Function in both chunks, which may have moved among its siblings.
+    Changed {
+        old_path: Vec<usize>,
+        new_path: Vec<usize>,
+        /// Description of each difference, like `string constants added: "x"`.
+        differences: Vec<String>,
+    },
+}
+
+/// Compare two chunks by their main functions.
+pub fn diff(old: &Proto, new: &Proto) -> ChunkDiff {
+    let mut changes = vec![];
+    diff_at(&mut vec![], &mut vec![], old, new, &mut changes);
+    ChunkDiff { changes }
+}
+
+fn diff_at(
+    old_path: &mut Vec<usize>,
+    new_path: &mut Vec<usize>,
+    old: &Proto,
+    new: &Proto,
+    changes: &mut Vec<FunctionChange>,
+) {
+    let differences = diff_function(old, new);
+    if !differences.is_empty() {
+        changes.push(FunctionChange::Changed {
+            old_path: old_path.clone(),
+            new_path: new_path.clone(),
+            differences,
+        });
+    }
+
+    let (old_protos, new_protos) = (old.protos(), new.protos());
+    let mut anchors = identical_protos(old_protos, new_protos);
+    // Closes the gap after the last identical pair.
+    anchors.push((old_protos.len(), new_protos.len()));
+
+    let (mut i, mut j) = (0, 0);
+    for (anchor_i, anchor_j) in anchors {
+        // Functions in the same position of the gap are taken to be the same function.
+        while i < anchor_i && j < anchor_j {
+            old_path.push(i);
+            new_path.push(j);
+            diff_at(old_path, new_path, &old_protos[i], &new_protos[j], changes);
+            old_path.pop();
+            new_path.pop();
+            i += 1;
+            j += 1;
+        }
+        for index in i..anchor_i {
+            changes.push(FunctionChange::Removed {
+                path: child_path(old_path, index),
+            });
+        }
+        for index in j..anchor_j {
+            changes.push(FunctionChange::Added {
+                path: child_path(new_path, index),
+            });
+        }
+        (i, j) = (anchor_i + 1, anchor_j + 1);
+    }
+}
+
+/// Indices of the longest sequence of pairs of identical functions, in order.
+fn identical_protos(old: &[Proto], new: &[Proto]) -> Vec<(usize, usize)> {
+    // Length of the longest common subsequence of the suffixes starting at each pair.
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if compare(&old[i], &new[j]).is_empty() {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if lengths[i][j] == lengths[i + 1][j + 1] + 1 && compare(&old[i], &new[j]).is_empty() {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Differences between two functions, leaving out their nested functions.
+fn diff_function(old: &Proto, new: &Proto) -> Vec<String> {
+    let mut differences = vec![];
+
+    if old.num_params != new.num_params {
+        differences.push(format!(
+            "{} parameters, was {}",
+            new.num_params, old.num_params
+        ));
+    }
+    if old.is_vararg != new.is_vararg {
+        differences.push(format!(
+            "vararg is {}, was {}",
+            new.is_vararg, old.is_vararg
+        ));
+    }
+    if old.max_stack != new.max_stack {
+        differences.push(format!(
+            "maximum stack size {}, was {}",
+            new.max_stack, old.max_stack
+        ));
+    }
+
+    differences.extend(diff_instrs(old, new));
+
+    let (added, removed) = diff_constants(old.strings(), new.strings(), |a, b| a == b);
+    let quote = |strings: Vec<&LuaString>| {
+        let quoted: Vec<_> = strings
+            .iter()
+            .map(|string| format!("{:?}", string.to_string_lossy()))
+            .collect();
+        quoted.join(", ")
+    };
+    if !added.is_empty() {
+        differences.push(format!("string constants added: {}", quote(added)));
+    }
+    if !removed.is_empty() {
+        differences.push(format!("string constants removed: {}", quote(removed)));
+    }
+
+    // Compared by bits, so a NaN constant matches itself.
+    let (added, removed) = diff_constants(old.numbers(), new.numbers(), |a, b| {
+        a.to_bits() == b.to_bits()
+    });
+    let join = |numbers: Vec<&f64>| {
+        let numbers: Vec<_> = numbers.iter().map(|number| number.to_string()).collect();
+        numbers.join(", ")
+    };
+    if !added.is_empty() {
+        differences.push(format!("number constants added: {}", join(added)));
+    }
+    if !removed.is_empty() {
+        differences.push(format!("number constants removed: {}", join(removed)));
+    }
+
+    differences
+}
+
+/// Range of instructions that differ, between the instructions
+/// both functions start with and the ones both end with.
+fn diff_instrs(old: &Proto, new: &Proto) -> Option<String> {
+    let (old, new) = (old.instrs(), new.instrs());
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(a, b)| same_instr(a, b))
+        .count();
+    if prefix == old.len() && prefix == new.len() {
+        return None;
+    }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| same_instr(a, b))
+        .count();
+
+    // Numbered from 1, like the disassembly listing.
+    let range = |len: usize| match len - suffix - prefix {
+        1 => format!("instruction {}", prefix + 1),
+        _ => format!("instructions {}..{}", prefix + 1, len - suffix),
+    };
+    Some(
+        match (old.len() - suffix - prefix, new.len() - suffix - prefix) {
+            (0, _) => format!("{} inserted", range(new.len())),
+            (_, 0) => format!("{} removed", range(old.len())),
+            _ => format!("{} replaced with {}", range(old.len()), range(new.len())),
+        },
+    )
+}
+
+/// Constants only in the new function, and only in the old function.
+///
+/// Constants are compared as a set, since inserting one shifts the indices of the rest.
+fn diff_constants<'a, T>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: impl Fn(&T, &T) -> bool,
+) -> (Vec<&'a T>, Vec<&'a T>) {
+    let added = new
+        .iter()
+        .filter(|value| !old.iter().any(|other| eq(value, other)))
+        .collect();
+    let removed = old
+        .iter()
+        .filter(|value| !new.iter().any(|other| eq(value, other)))
+        .collect();
+    (added, removed)
+}
+
+fn child_path(path: &[usize], index: usize) -> Vec<usize> {
+    let mut path = path.to_vec();
+    path.push(index);
+    path
+}
+
+impl ChunkDiff {
+    /// Checks whether the chunks have the same functions.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for ChunkDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no differences");
+        }
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FunctionChange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FunctionChange::Added { path } => write!(f, "added function {}", path_name(path)),
+            FunctionChange::Removed { path } => {
+                write!(f, "removed function {}", path_name(path))
+            }
+            FunctionChange::Changed {
+                old_path,
+                new_path,
+                differences,
+            } => {
+                write!(f, "changed function {}", path_name(new_path))?;
+                if old_path != new_path {
+                    write!(f, " (was {})", path_name(old_path))?;
+                }
+                for difference in differences {
+                    write!(f, "\n  {difference}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Lua 4.0 bytecode chunk encoder.
+//!
+//! Writes a function back into a binary chunk, laid out as `ldump.c` does.
+//! Instructions are written as their raw words, so a decoded chunk encodes
+//! back to the same bytes when the header is kept.
+use super::{Chunk, Constants, Header, Local, Proto, ID_CHUNK, SIGNATURE, TEST_NUMBER};
+use crate::errors::{Error, Result};
+use crate::lstring::LuaString;
+use crate::reader::{Endian, NumberType};
+
+/// Lua 4.0 bytecode chunk encoder.
+pub struct Encoder {
+    header: Header,
+    buf: Vec<u8>,
+}
+
+impl Chunk {
+    /// Encode the chunk for the platform described by its header.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Encoder::new(self.header.clone()).encode(&self.main)
+    }
+}
+
+impl Encoder {
+    /// Encoder for the platform described by the header.
+    pub fn new(header: Header) -> Self {
+        Self {
+            header,
+            buf: vec![],
+        }
+    }
+
+    /// Encode a chunk with the given main function.
+    pub fn encode(mut self, main: &Proto) -> Result<Vec<u8>> {
+        self.write_header()?;
+        self.write_function(main)?;
+        Ok(self.buf)
+    }
+}
+
+impl Encoder {
+    fn write_header(&mut self) -> Result<()> {
+        let Header {
+            version,
+            endianess,
+            size_int,
+            size_t,
+            size_instr,
+            size_instr_arg,
+            size_op,
+            size_b,
+            number_type,
+        } = self.header;
+
+        self.write_u8(ID_CHUNK);
+        self.buf.extend_from_slice(SIGNATURE.as_bytes());
+        self.write_u8(version);
+        self.write_u8(match endianess {
+            Endian::Little => 1,
+            Endian::Big => 0,
+        });
+        self.write_u8(size_int);
+        self.write_u8(size_t);
+        self.write_u8(size_instr);
+        self.write_u8(size_instr_arg);
+        self.write_u8(size_op);
+        self.write_u8(size_b);
+
+        // Integer formats are only told apart by the test number.
+        match number_type {
+            NumberType::F32 => {
+                self.write_u8(4);
+                self.write_u32((TEST_NUMBER as f32).to_bits());
+            }
+            NumberType::F64 => {
+                self.write_u8(8);
+                self.write_u64(TEST_NUMBER.to_bits());
+            }
+            NumberType::I32 => {
+                self.write_u8(4);
+                self.write_u32(TEST_NUMBER as i32 as u32);
+            }
+            NumberType::I64 => {
+                self.write_u8(8);
+                self.write_u64(TEST_NUMBER as i64 as u64);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_function(&mut self, proto: &Proto) -> Result<()> {
+        self.write_lua_string(&LuaString::from(proto.source.as_str()))?;
+        self.write_u32(proto.line_defined);
+        self.write_u32(proto.num_params);
+        self.write_u8(proto.is_vararg as u8);
+        self.write_u32(proto.max_stack);
+
+        self.write_locals(&proto.locals)?;
+        self.write_lines(&proto.lines)?;
+        self.write_constants(&proto.constants)?;
+        self.write_code(&proto.code)
+    }
+
+    fn write_lua_string(&mut self, string: &LuaString) -> Result<()> {
+        // Length includes the nul terminator.
+        self.write_size_t(string.len() + 1)?;
+        self.buf.extend_from_slice(string.as_bytes());
+        self.write_u8(0);
+        Ok(())
+    }
+
+    fn write_size_t(&mut self, size: usize) -> Result<()> {
+        let overflow = |_| Error::new_encoder(format!("size {size} overflows size_t"));
+        match self.header.size_t {
+            2 => self.write_u16(u16::try_from(size).map_err(overflow)?),
+            4 => self.write_u32(u32::try_from(size).map_err(overflow)?),
+            8 => self.write_u64(size as u64),
+            _ => {
+                return Error::new_encoder(format!("unknown size_t: {}", self.header.size_t)).into()
+            }
+        }
+        Ok(())
+    }
+
+    fn write_locals(&mut self, locals: &[Local]) -> Result<()> {
+        self.write_len(locals.len())?;
+        for local in locals {
+            self.write_lua_string(&LuaString::from(local.varname.as_str()))?;
+            self.write_u32(local.startpc);
+            self.write_u32(local.endpc);
+        }
+        Ok(())
+    }
+
+    fn write_lines(&mut self, lines: &[u32]) -> Result<()> {
+        self.write_len(lines.len())?;
+        for line in lines {
+            self.write_u32(*line);
+        }
+        Ok(())
+    }
+
+    fn write_constants(&mut self, constants: &Constants) -> Result<()> {
+        self.write_len(constants.strings.len())?;
+        for string in constants.strings.iter() {
+            self.write_lua_string(string)?;
+        }
+
+        self.write_len(constants.numbers.len())?;
+        for number in constants.numbers.iter() {
+            self.write_number(*number)?;
+        }
+
+        self.write_len(constants.protos.len())?;
+        for proto in constants.protos.iter() {
+            self.write_function(proto)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_code(&mut self, code: &[u64]) -> Result<()> {
+        self.write_len(code.len())?;
+        for word in code {
+            match self.header.size_instr {
+                4 => self.write_u32(u32::try_from(*word).map_err(|_| {
+                    Error::new_encoder(format!("instruction {word:016x} exceeds 32 bits"))
+                })?),
+                8 => self.write_u64(*word),
+                size => {
+                    return Error::new_encoder(format!("unknown instruction size: {size}")).into()
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the number of elements in a list.
+    fn write_len(&mut self, len: usize) -> Result<()> {
+        match u32::try_from(len) {
+            Ok(len) => {
+                self.write_u32(len);
+                Ok(())
+            }
+            Err(_) => Error::new_encoder(format!("too many elements: {len}")).into(),
+        }
+    }
+
+    /// Write a number in the chunk's format.
+    ///
+    /// Integer formats can't hold fractions, so those are an error rather than truncated.
+    fn write_number(&mut self, number: f64) -> Result<()> {
+        match self.header.number_type {
+            NumberType::F32 => self.write_u32((number as f32).to_bits()),
+            NumberType::F64 => self.write_u64(number.to_bits()),
+            NumberType::I32 if number as i32 as f64 == number => {
+                self.write_u32(number as i32 as u32)
+            }
+            NumberType::I64 if number as i64 as f64 == number => {
+                self.write_u64(number as i64 as u64)
+            }
+            NumberType::I32 | NumberType::I64 => {
+                return Error::new_encoder(format!(
+                    "number {number} can't be represented as {:?}",
+                    self.header.number_type
+                ))
+                .into()
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Encoder {
+    fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        match self.header.endianess {
+            Endian::Little => self.buf.extend_from_slice(&value.to_le_bytes()),
+            Endian::Big => self.buf.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        match self.header.endianess {
+            Endian::Little => self.buf.extend_from_slice(&value.to_le_bytes()),
+            Endian::Big => self.buf.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        match self.header.endianess {
+            Endian::Little => self.buf.extend_from_slice(&value.to_le_bytes()),
+            Endian::Big => self.buf.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+}
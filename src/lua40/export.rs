@@ -0,0 +1,157 @@
+//! Tables of a function's constants, locals and lines, for analysis
+//! outside of the decompiler, like a localizer collecting every string
+//! with where it's used.
+use std::fmt::{self, Formatter};
+
+use super::{Constant, Local, Opcode, Proto, ProtoPath};
+
+/// Table of a chunk to export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Export {
+    /// String and number constants, as per [Proto::constants].
+    Constants,
+    /// Local variable debug information, as per [Proto::iter_locals].
+    Locals,
+    /// Source line of every instruction, as per [Proto::iter_lines].
+    Lines,
+}
+
+/// Local variable of a function or a function nested in it.
+///
+/// Returned by [Proto::iter_locals].
+#[derive(Debug, Clone)]
+pub struct LocalInfo<'a> {
+    /// Path of the function the local belongs to.
+    pub path: ProtoPath,
+    /// Index of the local in the function's debug information.
+    pub index: usize,
+    pub local: &'a Local,
+}
+
+/// Source line of an instruction of a function or a function nested in it.
+///
+/// Returned by [Proto::iter_lines].
+#[derive(Debug, Clone)]
+pub struct LineInfo {
+    /// Path of the function the instruction is in.
+    pub path: ProtoPath,
+    /// Index of the instruction in the function's code.
+    pub offset: usize,
+    pub opcode: Opcode,
+    pub line: u32,
+}
+
+/// CSV view of a table of a function, with a header row.
+///
+/// Instructions are counted from 1, like in disassembly listings.
+pub struct CsvExport<'a> {
+    proto: &'a Proto,
+    export: Export,
+}
+
+impl Proto {
+    /// Local variables of the function and every function nested in it,
+    /// depth first, with their paths relative to this function.
+    ///
+    /// Functions without debug information have none.
+    pub fn iter_locals(&self) -> impl Iterator<Item = LocalInfo<'_>> + '_ {
+        self.iter_protos().flat_map(|(path, proto)| {
+            proto
+                .locals()
+                .iter()
+                .enumerate()
+                .map(move |(index, local)| LocalInfo {
+                    path: path.clone(),
+                    index,
+                    local,
+                })
+        })
+    }
+
+    /// Source line of every instruction of the function and every function
+    /// nested in it, depth first, with their paths relative to this function.
+    ///
+    /// Functions without debug information have none.
+    pub fn iter_lines(&self) -> impl Iterator<Item = LineInfo> + '_ {
+        self.iter_protos().flat_map(|(path, proto)| {
+            proto
+                .instrs()
+                .iter()
+                .enumerate()
+                .filter_map(move |(offset, instr)| {
+                    Some(LineInfo {
+                        path: path.clone(),
+                        offset,
+                        opcode: instr.opcode,
+                        line: proto.line_at(offset)?,
+                    })
+                })
+        })
+    }
+
+    /// The table as CSV, like for a spreadsheet.
+    ///
+    /// ```csv
+    /// function,kind,index,value,line
+    /// main,string,0,hello,1
+    /// ```
+    pub fn export_csv(&self, export: Export) -> CsvExport<'_> {
+        CsvExport {
+            proto: self,
+            export,
+        }
+    }
+}
+
+impl fmt::Display for CsvExport<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let optional = |line: Option<u32>| line.map(|line| line.to_string()).unwrap_or_default();
+        match self.export {
+            Export::Constants => {
+                writeln!(f, "function,kind,index,value,line")?;
+                for pooled in self.proto.constants() {
+                    let (kind, value) = match pooled.constant {
+                        Constant::String(string) => ("string", string.to_string_lossy()),
+                        Constant::Number(number) => ("number", number.to_string().into()),
+                        Constant::Function(_) => continue,
+                    };
+                    write!(f, "{},{kind},{},", pooled.path, pooled.index)?;
+                    fmt_field(f, &value)?;
+                    writeln!(f, ",{}", optional(pooled.line))?;
+                }
+            }
+            Export::Locals => {
+                writeln!(f, "function,index,name,first_instruction,last_instruction")?;
+                for info in self.proto.iter_locals() {
+                    write!(f, "{},{},", info.path, info.index)?;
+                    fmt_field(f, &info.local.varname)?;
+                    // Live from `startpc` up to but not including `endpc`.
+                    writeln!(f, ",{},{}", info.local.startpc + 1, info.local.endpc)?;
+                }
+            }
+            Export::Lines => {
+                writeln!(f, "function,instruction,opcode,line")?;
+                for info in self.proto.iter_lines() {
+                    writeln!(
+                        f,
+                        "{},{},{},{}",
+                        info.path,
+                        info.offset + 1,
+                        info.opcode.name(),
+                        info.line
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a field, quoted if it contains a separator, quote or line break.
+fn fmt_field(f: &mut Formatter, value: &str) -> fmt::Result {
+    if value.contains([',', '"', '\n', '\r']) {
+        write!(f, "\"{}\"", value.replace('"', "\"\""))
+    } else {
+        f.write_str(value)
+    }
+}
@@ -0,0 +1,104 @@
+//! Syntax highlighting of decompiled source for terminals.
+//!
+//! [Highlight] is a sink for the [super::Scribe] that colors keywords,
+//! strings, numbers and comments with ANSI escape codes. Names and
+//! symbols are left in the terminal's own color.
+use std::fmt::{self, Write as FmtWrite};
+
+use super::check::{Kind, Lexer};
+use crate::errors::Result;
+
+const KEYWORD: &str = "\x1b[35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const COMMENT: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+/// Colors Lua 4.0 source written to it with ANSI escape codes.
+///
+/// A token can be split over several writes, so the source is kept
+/// until [Highlight::finish] colors it and writes it to the inner sink.
+pub struct Highlight<W: FmtWrite> {
+    inner: W,
+    source: String,
+}
+
+impl<W: FmtWrite> Highlight<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            source: String::new(),
+        }
+    }
+
+    /// Write the colored source to the inner sink, and return it.
+    ///
+    /// Source that doesn't lex as Lua 4.0, like the output for another
+    /// version, is written without color from where the lexer gave up.
+    pub fn finish(mut self) -> Result<W> {
+        fmt_highlighted(&mut self.inner, &self.source)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: FmtWrite> FmtWrite for Highlight<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.source.push_str(s);
+        Ok(())
+    }
+}
+
+fn fmt_highlighted(f: &mut impl FmtWrite, source: &str) -> fmt::Result {
+    let mut lexer = Lexer::new(source);
+    let mut gap_start = 0;
+    loop {
+        let Ok(token) = lexer.next_token() else {
+            return f.write_str(&source[gap_start..]);
+        };
+        let end = lexer.position();
+        let start = end - token.text.len();
+        fmt_gap(f, &source[gap_start..start])?;
+
+        let color = match token.kind {
+            Kind::Keyword => KEYWORD,
+            Kind::String => STRING,
+            Kind::Number => NUMBER,
+            Kind::Name | Kind::Symbol => "",
+            Kind::Eof => return Ok(()),
+        };
+        fmt_colored(f, color, token.text)?;
+        gap_start = end;
+    }
+}
+
+/// Write the whitespace and comments between tokens, where a comment
+/// runs from `--` to the end of the line.
+fn fmt_gap(f: &mut impl FmtWrite, gap: &str) -> fmt::Result {
+    let mut rest = gap;
+    while let Some(start) = rest.find("--") {
+        f.write_str(&rest[..start])?;
+        let len = rest[start..].find('\n').unwrap_or(rest.len() - start);
+        fmt_colored(f, COMMENT, &rest[start..start + len])?;
+        rest = &rest[start + len..];
+    }
+    f.write_str(rest)
+}
+
+/// Write text in a color, or as is without one.
+///
+/// The color is reset at the end of each line of a multi-line token,
+/// so it doesn't run into anything a pager puts at the end of lines.
+fn fmt_colored(f: &mut impl FmtWrite, color: &str, text: &str) -> fmt::Result {
+    if color.is_empty() {
+        return f.write_str(text);
+    }
+    for (index, line) in text.split('\n').enumerate() {
+        if index != 0 {
+            f.write_char('\n')?;
+        }
+        if !line.is_empty() {
+            write!(f, "{color}{line}{RESET}")?;
+        }
+    }
+    Ok(())
+}
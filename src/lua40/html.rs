@@ -0,0 +1,179 @@
+//! HTML page of decompiled source beside the disassembly it was decompiled from.
+//!
+//! Each statement links to the range of instructions it was decoded from,
+//! as per the spans of the syntax tree, and each instruction links back to
+//! the statement it belongs to, for auditing tricky decompilations.
+use std::collections::HashMap;
+use std::fmt::{self, Formatter, Write as FmtWrite};
+
+use super::ast::{Span, Syntax};
+use super::{fmt_instr, Instr, Proto, ProtoPath, Scribe};
+use crate::errors::{Error, Result};
+
+const STYLE: &str = "\
+body { margin: 0; font-family: monospace; }
+main { display: flex; height: 100vh; }
+section { flex: 1; overflow: auto; border-right: 1px solid #ccc; }
+table { border-collapse: collapse; width: 100%; }
+td { padding: 0 0.5em; vertical-align: top; }
+td pre { margin: 0; tab-size: 4; }
+td.ln { text-align: right; color: #888; user-select: none; }
+th { text-align: left; padding: 0.5em; background: #eee; }
+tr:target, tr.hl { background: #ffe9a8; }
+";
+
+/// Highlights the instructions of a statement when its link is followed,
+/// since `:target` only reaches the first of them.
+const SCRIPT: &str = "\
+function highlight() {
+  document.querySelectorAll('tr.hl').forEach(row => row.classList.remove('hl'));
+  const link = document.querySelector('a[href=\"' + location.hash + '\"][data-end]');
+  if (!link) return;
+  const [, path, start] = location.hash.match(/^#i-(.*)-(\\d+)$/);
+  for (let pc = +start; pc <= +link.dataset.end; pc++) {
+    const row = document.getElementById('i-' + path + '-' + pc);
+    if (row) row.classList.add('hl');
+  }
+}
+window.addEventListener('hashchange', highlight);
+window.addEventListener('load', highlight);
+";
+
+/// Instruction as a line of the disassembly listing.
+struct InstrLine<'a> {
+    proto: &'a Proto,
+    pc: usize,
+    instr: &'a Instr,
+}
+
+/// Text escaped for HTML.
+struct Escape<'a>(&'a str);
+
+// ============================================================================
+
+impl Scribe {
+    /// Write an HTML page with the source on the left, and the disassembly of
+    /// the function it was decompiled from and its nested functions on the right.
+    ///
+    /// Statements link to the instructions they were decoded from, and
+    /// instructions link back to their statements. Instructions are numbered
+    /// from 1, like the disassembly listing.
+    pub fn fmt_html(&mut self, f: &mut impl FmtWrite, syntax: &Syntax, main: &Proto) -> Result<()> {
+        let proto = main
+            .nested(&syntax.path)
+            .ok_or_else(|| Error::new_parser(format!("no function {}", syntax.path)))?;
+        let lines = self.fmt_spanned_lines(syntax)?;
+
+        // Inner statements come after the statements they're nested in,
+        // so each instruction ends up with the innermost statement.
+        let mut owners: HashMap<(&ProtoPath, u32), usize> = HashMap::new();
+        for (index, (_, spanned)) in lines.iter().enumerate() {
+            if let Some((path, span)) = spanned {
+                for pc in span.start..=span.end {
+                    owners.insert((path, pc), index + 1);
+                }
+            }
+        }
+
+        writeln!(f, "<!DOCTYPE html>")?;
+        writeln!(f, "<html>")?;
+        writeln!(f, "<head>")?;
+        writeln!(f, "<meta charset=\"utf-8\">")?;
+        writeln!(f, "<title>{}</title>", Escape(&proto.source))?;
+        writeln!(f, "<style>\n{STYLE}</style>")?;
+        writeln!(f, "<script>\n{SCRIPT}</script>")?;
+        writeln!(f, "</head>")?;
+        writeln!(f, "<body>")?;
+        writeln!(f, "<main>")?;
+
+        writeln!(f, "<section>")?;
+        writeln!(f, "<table>")?;
+        for (index, (text, spanned)) in lines.iter().enumerate() {
+            let number = index + 1;
+            write!(f, "<tr id=\"L{number}\"><td class=\"ln\">")?;
+            match spanned {
+                Some((path, span)) => fmt_span_link(f, number, path, span)?,
+                None => write!(f, "{number}")?,
+            }
+            writeln!(f, "</td><td><pre>{}</pre></td></tr>", Escape(text))?;
+        }
+        writeln!(f, "</table>")?;
+        writeln!(f, "</section>")?;
+
+        writeln!(f, "<section>")?;
+        writeln!(f, "<table>")?;
+        for (relative, nested) in proto.iter_protos() {
+            let path = ProtoPath::from([&syntax.path[..], &relative[..]].concat());
+            writeln!(
+                f,
+                "<tr><th colspan=\"2\">function {path} &lt;{}:{}&gt;</th></tr>",
+                Escape(&nested.source),
+                nested.line_defined
+            )?;
+            for (pc, instr) in nested.instrs.iter().enumerate() {
+                write!(f, "<tr id=\"i-{path}-{}\"><td class=\"ln\">", pc + 1)?;
+                if let Some(line) = owners.get(&(&path, pc as u32)) {
+                    write!(f, "<a href=\"#L{line}\">{line}</a>")?;
+                }
+                let line = InstrLine {
+                    proto: nested,
+                    pc,
+                    instr,
+                };
+                let line = line.to_string();
+                writeln!(
+                    f,
+                    "</td><td><pre>{}</pre></td></tr>",
+                    Escape(line.trim_start())
+                )?;
+            }
+        }
+        writeln!(f, "</table>")?;
+        writeln!(f, "</section>")?;
+
+        writeln!(f, "</main>")?;
+        writeln!(f, "</body>")?;
+        writeln!(f, "</html>")?;
+        Ok(())
+    }
+}
+
+/// Link from a source line to the first instruction of its statement.
+fn fmt_span_link(
+    f: &mut impl FmtWrite,
+    number: usize,
+    path: &ProtoPath,
+    span: &Span,
+) -> fmt::Result {
+    let (start, end) = (span.start + 1, span.end + 1);
+    let range = if start == end {
+        format!("instruction {start}")
+    } else {
+        format!("instructions {start}-{end}")
+    };
+    write!(
+        f,
+        "<a href=\"#i-{path}-{start}\" data-end=\"{end}\" title=\"{path} {range}\">{number}</a>"
+    )
+}
+
+impl fmt::Display for InstrLine<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_instr(f, self.proto, self.pc, self.instr)
+    }
+}
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
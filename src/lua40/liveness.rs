@@ -0,0 +1,71 @@
+//! Def-use analysis of the stack slots read and written by local variable instructions.
+//!
+//! Locals live at the bottom of the stack, below the values of the statement
+//! being evaluated. Bytecode that wasn't compiled from source, or was patched,
+//! can also read back a value pushed earlier in the same expression with
+//! `GETLOCAL`, which makes the slot a temporary rather than a local.
+//!
+//! The debug information tells which slots are locals where it's kept. In
+//! stripped chunks the uses of the value are followed instead: a temporary is
+//! consumed as the operand of a later instruction, while a local stays on the
+//! stack until its scope ends and it's popped, or the function returns.
+use super::{Opcode, Proto};
+
+/// Which stack slots hold local variables where they're accessed.
+pub(crate) struct Liveness {
+    /// Number of values on the stack before each instruction, for
+    /// stripped functions whose stack balances.
+    heights: Option<Vec<Option<u32>>>,
+}
+
+impl Liveness {
+    pub fn new(proto: &Proto) -> Self {
+        let heights = if proto.locals.is_empty() {
+            proto.stack_heights().ok()
+        } else {
+            None
+        };
+        Self { heights }
+    }
+
+    /// Whether the stack slot accessed by the instruction at `pc` holds a local variable.
+    ///
+    /// When the stack of a stripped function doesn't balance, the uses of
+    /// values can't be followed, so every slot is assumed to be a local.
+    pub fn is_local(&self, proto: &Proto, pc: usize, stack_offset: u32) -> bool {
+        if let Some(count) = proto.active_locals(pc) {
+            return stack_offset < count;
+        }
+        let Some(heights) = &self.heights else {
+            return true;
+        };
+
+        for (offset, instr) in proto.instrs.iter().enumerate().skip(pc + 1) {
+            let Some(before) = heights[offset] else {
+                continue;
+            };
+            // Reached by a jump from outside the slot's scope.
+            if before <= stack_offset {
+                return true;
+            }
+            let after = match instr.opcode {
+                Opcode::End => return true,
+                Opcode::Return => return stack_offset < instr.u,
+                Opcode::TailCall => return stack_offset < instr.b,
+                // Jumps keep the values on the stack, even when the next
+                // instruction is only reached from elsewhere.
+                Opcode::Jump => before,
+                _ => heights.get(offset + 1).copied().flatten().unwrap_or(before),
+            };
+            if after <= stack_offset {
+                // Scopes end by popping their locals, and loops pop their
+                // control variables when they're done.
+                return matches!(
+                    instr.opcode,
+                    Opcode::Pop | Opcode::ForLoop | Opcode::LForLoop
+                );
+            }
+        }
+        true
+    }
+}
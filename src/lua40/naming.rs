@@ -0,0 +1,344 @@
+//! Names made up for locals that the chunk has no names for.
+//!
+//! Stripped chunks keep no local names, so the parser asks a
+//! [NamingStrategy] for one whenever it declares a local. Parameters can
+//! instead be named after the arguments they're passed as, from a table
+//! of [ParamNames].
+use std::collections::HashMap;
+
+use super::ast::is_name;
+use super::{Constant, Opcode, Proto, Type};
+use crate::errors::{Error, Result};
+
+/// Makes up names for locals without debug information.
+///
+/// The same strategy names the locals of a function and the functions
+/// nested in it, so their names can carry on from each other.
+pub trait NamingStrategy {
+    /// Name for the local, which must be a valid Lua name.
+    ///
+    /// Names that are taken, like by globals, are asked for again. The
+    /// strategy may give another name, and when it gives the same one,
+    /// a number is added to it instead.
+    fn name(&mut self, hint: &LocalHint) -> String;
+}
+
+/// What's known about a local that needs a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalHint {
+    /// Stack slot of the local in its function.
+    pub slot: u32,
+    /// Type of the value the local is declared with.
+    pub ty: Type,
+    /// Whether the local is a parameter of its function.
+    pub is_param: bool,
+}
+
+/// Names of one letter, then two and so on: `a`, `b`, ..., `z`, `ab`, `bc`.
+#[derive(Debug, Clone, Default)]
+pub struct AlphabeticNames {
+    count: usize,
+}
+
+/// Numbered names, like `local_1` and `local_2`, and `param_1` for parameters.
+#[derive(Debug, Clone, Default)]
+pub struct IndexedNames {
+    locals: usize,
+    params: usize,
+}
+
+/// Names numbered by the type of the value the local is declared with,
+/// like `str1`, `num1` and `tbl2`, or `var1` when the type isn't known.
+#[derive(Debug, Clone, Default)]
+pub struct TypedNames {
+    counts: HashMap<&'static str, usize>,
+}
+
+/// Names after the local's stack slot, like `slot3`, the way the
+/// virtual machine refers to them. Locals in the same slot at different
+/// times share the name.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlotNames;
+
+/// Names of the arguments of global functions, which parameters that are
+/// passed straight to them are named after.
+///
+/// A parameter only passed to `strlen` is named `s`, like the argument
+/// of `strlen` is in the reference manual.
+#[derive(Debug, Clone, Default)]
+pub struct ParamNames {
+    /// Keyed by the function and the position of the argument, from 1.
+    arguments: HashMap<(String, u32), String>,
+}
+
+const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+/// Strategy picked at runtime, like from a command line option.
+impl<T: NamingStrategy + ?Sized> NamingStrategy for Box<T> {
+    fn name(&mut self, hint: &LocalHint) -> String {
+        (**self).name(hint)
+    }
+}
+
+impl NamingStrategy for AlphabeticNames {
+    fn name(&mut self, _hint: &LocalHint) -> String {
+        // Names get a letter longer each time the letters wrap around.
+        let len = self.count / LETTERS.len();
+        let name = (0..len + 1)
+            .map(|i| LETTERS[(self.count + i) % LETTERS.len()] as char)
+            .collect();
+        self.count += 1;
+        name
+    }
+}
+
+impl NamingStrategy for IndexedNames {
+    fn name(&mut self, hint: &LocalHint) -> String {
+        if hint.is_param {
+            self.params += 1;
+            format!("param_{}", self.params)
+        } else {
+            self.locals += 1;
+            format!("local_{}", self.locals)
+        }
+    }
+}
+
+impl NamingStrategy for TypedNames {
+    fn name(&mut self, hint: &LocalHint) -> String {
+        let prefix = match hint.ty {
+            Type::Number => "num",
+            Type::String => "str",
+            Type::Table => "tbl",
+            Type::Function => "fn",
+            Type::Bool => "flag",
+            Type::Unknown if hint.is_param => "param",
+            Type::Unknown => "var",
+        };
+        let count = self.counts.entry(prefix).or_default();
+        *count += 1;
+        format!("{prefix}{count}")
+    }
+}
+
+impl NamingStrategy for SlotNames {
+    fn name(&mut self, hint: &LocalHint) -> String {
+        format!("slot{}", hint.slot)
+    }
+}
+
+/// Arguments of the Lua 4.0 standard library, by function and position.
+const LIBRARY: &[(&str, u32, &str)] = &[
+    ("assert", 2, "message"),
+    ("call", 1, "f"),
+    ("call", 2, "args"),
+    ("dofile", 1, "filename"),
+    ("dostring", 1, "code"),
+    ("error", 1, "message"),
+    ("foreach", 1, "t"),
+    ("foreach", 2, "f"),
+    ("foreachi", 1, "t"),
+    ("foreachi", 2, "f"),
+    ("getglobal", 1, "name"),
+    ("getn", 1, "t"),
+    ("next", 1, "t"),
+    ("rawget", 1, "t"),
+    ("rawset", 1, "t"),
+    ("setglobal", 1, "name"),
+    ("settag", 1, "t"),
+    ("settag", 2, "tag"),
+    ("sort", 1, "t"),
+    ("sort", 2, "comp"),
+    ("tinsert", 1, "t"),
+    ("tremove", 1, "t"),
+    ("tonumber", 2, "base"),
+    // String library.
+    ("format", 1, "fmt"),
+    ("gsub", 1, "s"),
+    ("gsub", 2, "pattern"),
+    ("gsub", 3, "repl"),
+    ("strbyte", 1, "s"),
+    ("strfind", 1, "s"),
+    ("strfind", 2, "pattern"),
+    ("strlen", 1, "s"),
+    ("strlower", 1, "s"),
+    ("strrep", 1, "s"),
+    ("strrep", 2, "n"),
+    ("strsub", 1, "s"),
+    ("strsub", 2, "i"),
+    ("strsub", 3, "j"),
+    ("strupper", 1, "s"),
+    // Math library.
+    ("abs", 1, "x"),
+    ("ceil", 1, "x"),
+    ("cos", 1, "x"),
+    ("exp", 1, "x"),
+    ("floor", 1, "x"),
+    ("log", 1, "x"),
+    ("log10", 1, "x"),
+    ("sin", 1, "x"),
+    ("sqrt", 1, "x"),
+    ("tan", 1, "x"),
+    // I/O library.
+    ("appendto", 1, "filename"),
+    ("openfile", 1, "filename"),
+    ("openfile", 2, "mode"),
+    ("readfrom", 1, "filename"),
+    ("remove", 1, "filename"),
+    ("writeto", 1, "filename"),
+];
+
+impl ParamNames {
+    /// Empty table, which names no parameters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Arguments of the functions in the Lua 4.0 standard library.
+    pub fn library() -> Self {
+        LIBRARY
+            .iter()
+            .fold(Self::new(), |names, (function, position, name)| {
+                names.with_argument(function, *position, name)
+            })
+    }
+
+    /// Name the argument of the global function at the position, from 1.
+    pub fn with_argument(
+        mut self,
+        function: impl ToString,
+        position: u32,
+        name: impl ToString,
+    ) -> Self {
+        self.arguments
+            .insert((function.to_string(), position), name.to_string());
+        self
+    }
+
+    /// Add the arguments of the other table, replacing the ones named by both.
+    pub fn with_table(mut self, other: ParamNames) -> Self {
+        self.arguments.extend(other.arguments);
+        self
+    }
+
+    /// Parse a table with one `function position = name` argument per line,
+    /// like `strlen 1 = s`.
+    ///
+    /// Blank lines, and lines starting with `--`, are skipped.
+    pub fn parse(table: &str) -> Result<Self> {
+        let mut names = Self::new();
+        for (index, line) in table.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("--") {
+                continue;
+            }
+            let argument = line.split_once('=').and_then(|(key, name)| {
+                let (function, position) = key.trim().split_once(char::is_whitespace)?;
+                let position = position.trim().parse::<u32>().ok()?;
+                let name = name.trim();
+                (is_name(function) && is_name(name) && position > 0)
+                    .then_some((function, position, name))
+            });
+            match argument {
+                Some((function, position, name)) => {
+                    names = names.with_argument(function, position, name);
+                }
+                None => {
+                    return Error::new_parser(format!(
+                        "line {}: expected `function position = name`, found `{line}`",
+                        index + 1
+                    ))
+                    .into()
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Name of the argument of the global function at the position, if it's named.
+    pub fn argument(&self, function: &str, position: u32) -> Option<&str> {
+        self.arguments
+            .get(&(function.to_string(), position))
+            .map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arguments.is_empty()
+    }
+
+    /// Names for the parameters of the function, from the first call each
+    /// is passed straight to as an argument of a named global function.
+    ///
+    /// Functions whose stack doesn't verify get no names.
+    pub(crate) fn infer(&self, proto: &Proto) -> Vec<Option<&str>> {
+        let mut names = vec![None; proto.num_params as usize];
+        let Ok(heights) = proto.stack_heights() else {
+            return names;
+        };
+        let height = |pc: usize| heights.get(pc).copied().flatten();
+
+        for (pc, instr) in proto.instrs.iter().enumerate() {
+            if instr.opcode != Opcode::GetLocal || instr.u >= proto.num_params {
+                continue;
+            }
+            let param = instr.u as usize;
+            if names[param].is_some() {
+                continue;
+            }
+            let Some(slot) = height(pc) else {
+                continue;
+            };
+            names[param] = self.passed_to(proto, &height, pc, slot);
+        }
+        names
+    }
+
+    /// Name of the argument that the value pushed at `pc` into the stack
+    /// slot is passed as, when it's left untouched until a call to a
+    /// global function.
+    fn passed_to(
+        &self,
+        proto: &Proto,
+        height: &impl Fn(usize) -> Option<u32>,
+        pc: usize,
+        slot: u32,
+    ) -> Option<&str> {
+        use Opcode::*;
+
+        let mut call = None;
+        for (offset, instr) in proto.instrs.iter().enumerate().skip(pc + 1) {
+            if instr.opcode == Call && instr.a <= slot {
+                call = Some(instr.a).filter(|function| *function < slot);
+                break;
+            }
+            if instr.opcode.is_jump() {
+                return None;
+            }
+            // The value is gone once something else is pushed into its slot,
+            // or the stack is popped below it.
+            let after = height(offset + 1)?;
+            let pops_only = matches!(
+                instr.opcode,
+                Pop | SetLocal | SetGlobal | SetTable | SetList | SetMap
+            ) || (instr.opcode == Call && instr.b == 0);
+            if after < slot + 1 || (after == slot + 1 && !pops_only) {
+                return None;
+            }
+        }
+        let function = call?;
+
+        // The function is pushed by the last instruction to run
+        // with the stack no higher than its slot.
+        let pusher = (0..pc)
+            .rev()
+            .find(|&offset| height(offset).is_some_and(|before| before <= function))?;
+        let instr = &proto.instrs[pusher];
+        if instr.opcode != GetGlobal || height(pusher) != Some(function) {
+            return None;
+        }
+        let Some(Constant::String(name)) = instr.constant(proto) else {
+            return None;
+        };
+        self.argument(name.to_str()?, slot - function)
+    }
+}
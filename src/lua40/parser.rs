@@ -1,30 +1,39 @@
 //! Bytecode parser.
 //!
 //! Analyzes bytecode instructions to generate an abstract syntax tree.
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fmt::{self, Formatter};
+use std::ops::Range;
 
 use super::ast::{
-    Assign, BinExpr, BinOp, Call, CondExpr, CondOp, Expr, Ident, IfHead, Lit, LocalVar, Node, Stmt,
+    is_name, Assign, BinExpr, BinOp, Call, Comment, Expr, Failed, Field, Function, Goto, Ident,
+    IfHead, Lit, LocalVar, Node, RepeatBlock, Return, Span, Stmt, Table, UnaryExpr, UnaryOp,
+    WhileBlock, WhileHead,
 };
-use super::{Op, Proto};
-use crate::errors::{Error, Result};
+use super::cfg::{ends_statement, merge_chain, ChainJump, Control, Join, SpanKind, Structure};
+use super::liveness::Liveness;
+use super::naming::{AlphabeticNames, LocalHint, NamingStrategy, ParamNames};
+use super::pattern::{Idiom, Recognized};
+use super::rename::RenameMap;
+use super::types::Type;
+use super::{Op, Opcode, Proto, ProtoPath, LFIELDS_PER_FLUSH, MULT_RET};
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity};
+use crate::errors::{Error, Location, Result};
+use crate::lstring::LuaString;
 use crate::lua40::ast::{Block, IfBlock, Partial, Syntax};
-
-const ASCII_CHARS: [u8; 26] = [
-    'a' as u8, 'b' as u8, 'c' as u8, 'd' as u8, 'e' as u8, 'f' as u8, 'g' as u8, 'h' as u8,
-    'i' as u8, 'j' as u8, 'k' as u8, 'l' as u8, 'm' as u8, 'n' as u8, 'o' as u8, 'p' as u8,
-    'q' as u8, 'r' as u8, 's' as u8, 't' as u8, 'u' as u8, 'v' as u8, 'w' as u8, 'x' as u8,
-    'y' as u8, 'z' as u8,
-];
+use crate::options::Tolerance;
+use crate::symbol::SymbolTable;
+use crate::trace::{trace_event, Level, NoTrace, Trace};
 
 pub struct Parser<'a> {
     proto: &'a Proto,
 
     /// Stack that mimics the operand stack used in the virtual machine.
     ///
-    /// The [Ip] points to the bytecode instruction that pushed the
+    /// Each [Slot] points to the bytecode instruction that pushed the
     /// slot item onto the stack.
-    stack: Vec<Ip>,
+    stack: Vec<Slot>,
 
     /// Space for the syntax tree nodes that are being built.
     ///
@@ -32,9 +41,19 @@ pub struct Parser<'a> {
     /// bytecode buffer. Each node corresponds to an instruction.
     nodes: Box<[Option<Node>]>,
 
+    /// Last instruction of the statements placed at the header of their
+    /// blocks, whose span extends over the blocks.
+    block_ends: Box<[Option<usize>]>,
+
     /// Stack of block spans.
     blocks: Vec<BlockSpan>,
 
+    /// Statements recovered from the function's control flow.
+    structure: Structure,
+
+    /// Which stack slots accessed by the function hold local variables.
+    liveness: Liveness,
+
     /// Stack offset where local variables end.
     local_end: u32,
 
@@ -46,6 +65,61 @@ pub struct Parser<'a> {
 
     /// namer for local variables.
     local_namer: Namer,
+
+    /// Names of the function's fixed parameters.
+    params: Vec<Ident>,
+
+    /// Names of the enclosing function's variables captured by the
+    /// closure, in the order of their upvalue indices.
+    upvalues: Vec<Ident>,
+
+    /// Flags for the nested functions that have been placed
+    /// at their closure instruction.
+    attached: Vec<bool>,
+
+    /// Index of each nested function from the main function down to this one.
+    path: Vec<usize>,
+
+    /// Problems found in the function and its nested functions.
+    diagnostics: Diagnostics,
+
+    /// Whether to keep going after an error, leaving the disassembly
+    /// of the failed instructions in the output.
+    tolerance: Tolerance,
+
+    /// Names given to globals and locals instead of the ones in the chunk.
+    renames: Option<&'a RenameMap>,
+
+    /// Names of arguments that parameters without names are named after.
+    param_names: Option<&'a ParamNames>,
+
+    /// Recognizers tried at each instruction before the generic parsing.
+    idioms: Vec<&'a dyn Idiom>,
+
+    /// Instruction after the last one recognized as an idiom,
+    /// which parsing resumes from.
+    resume: usize,
+
+    /// Function this one is nested in and its index there, when
+    /// it's decompiled on its own with [Parser::for_function].
+    enclosing: Option<(&'a Proto, usize)>,
+
+    /// Comments on what was skipped over in a tolerant mode, and their
+    /// instruction, placed before the first node at or after it.
+    comments: Vec<(usize, Comment)>,
+
+    /// Conditional jumps waiting for the last jump of their chain,
+    /// to be joined into one condition with it.
+    chain: Vec<ChainJump<Expr>>,
+
+    /// Jumps of the `and` and `or` expressions used as values,
+    /// waiting for the instructions they go to.
+    logic: Vec<LogicJump>,
+
+    /// Names of the identifiers, handed over with the syntax tree.
+    symbols: SymbolTable,
+
+    trace: &'a dyn Trace,
 }
 
 /// Instruction pointer.
@@ -54,14 +128,53 @@ pub struct Parser<'a> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Ip(u32);
 
+/// Item on the symbolic operand stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    /// Instruction that pushed the value.
+    ip: Ip,
+    /// Index of the value when the instruction pushed multiple
+    /// results, like a call with more than one result.
+    nth: u32,
+    /// Every result of a call with [MULT_RET], however many there are at
+    /// runtime. Only the last value of a call's arguments or of a `return`
+    /// can be open, where the results expand in place.
+    open: bool,
+}
+
 #[derive(Debug)]
 struct BlockSpan {
     /// Instruction where the block started.
     start: Ip,
     /// Instruction right after the last instruction in the block.
     end: Ip,
+    kind: BlockKind,
+    /// Operand stack when the block started.
+    ///
+    /// Every path through a block leaves the stack as it found it, but
+    /// a `break` pops the locals before jumping so it's restored at the end.
+    stack: Vec<Slot>,
+    locals: Vec<Local>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    /// Block after the header of an `if` statement, followed by
+    /// an `else` block when it has an end.
+    Then { else_end: Option<Ip> },
+    /// Block after the `then` block of the `if` statement with the given header.
+    Else { head: Ip },
+    /// Body after the header of a `while` loop.
+    While,
+    /// Body of a loop without a condition.
+    Loop,
+    /// Body of a `repeat` loop, ending with its condition.
+    Repeat,
+    /// Instructions skipped or repeated by a goto.
+    Do,
 }
 
+#[derive(Debug, Clone)]
 struct Local {
     name: String,
     stack_offset: u32,
@@ -70,11 +183,43 @@ struct Local {
     is_declared: bool,
 }
 
+/// Jump in an `and` or `or` expression used as a value.
+#[derive(Debug)]
+enum LogicJump {
+    /// Jump that keeps the value it tests, which is the value of the whole
+    /// expression when taken, joined to the operands after it with `op`.
+    Keep {
+        ip: Ip,
+        op: BinOp,
+        value: Expr,
+        target: usize,
+    },
+    /// Jump that pops the value it tests, skipping the next operand.
+    Test(ChainJump<Expr>),
+}
+
 struct Namer {
-    /// Set of characters that can be used to generate names.
-    chars: Box<[u8]>,
-    cursor: usize,
-    count: usize,
+    strategy: Box<dyn NamingStrategy>,
+    /// Names that must not be generated, because they're
+    /// already used for something else in the function.
+    reserved: HashSet<String>,
+}
+
+// ============================================================================
+
+/// Join the operands with `and` or `or`, associating to the left like the
+/// source does, since `a or (b or c)` compiles the same as `a or b or c`.
+fn join_logic(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    match rhs {
+        Expr::Binary(bin_expr) if bin_expr.op == op => {
+            let BinExpr {
+                lhs: inner, rhs, ..
+            } = *bin_expr;
+            let lhs = join_logic(op, lhs, inner);
+            Expr::Binary(Box::new(BinExpr { op, lhs, rhs }))
+        }
+        rhs => Expr::Binary(Box::new(BinExpr { op, lhs, rhs })),
+    }
 }
 
 // ============================================================================
@@ -95,23 +240,188 @@ fn err_node_none() -> Error {
     Error::new_parser("no syntax node for bytecode")
 }
 
+fn err_unstructured_jump() -> Error {
+    Error::new_parser("jump is not part of a statement")
+}
+
+fn err_unsupported(opcode: Opcode) -> Error {
+    Error::new_unsupported(format!("instruction {}", opcode.name()))
+}
+
 // ============================================================================
 
 impl<'a> Parser<'a> {
     pub fn new(root: &'a Proto) -> Self {
+        Self::with_namer(root, Namer::new(used_names(root)))
+    }
+
+    fn with_namer(root: &'a Proto, local_namer: Namer) -> Self {
         Self {
             proto: root,
             stack: vec![],
-            nodes: (0..root.code.len()).into_iter().map(|_| None).collect(),
+            nodes: (0..root.code.len()).map(|_| None).collect(),
+            block_ends: vec![None; root.code.len()].into_boxed_slice(),
             blocks: vec![],
+            structure: Structure::default(),
+            liveness: Liveness::new(root),
             local_end: 0,
             locals: vec![],
-            local_namer: Namer::new(&ASCII_CHARS),
+            local_namer,
+            params: vec![],
+            upvalues: vec![],
+            attached: vec![false; root.protos().len()],
+            path: vec![],
+            diagnostics: Diagnostics::new(),
+            tolerance: Tolerance::Strict,
+            renames: None,
+            param_names: None,
+            idioms: vec![],
+            resume: 0,
+            enclosing: None,
+            comments: vec![],
+            chain: vec![],
+            logic: vec![],
+            symbols: SymbolTable::new(),
+            trace: &NoTrace,
+        }
+    }
+
+    /// Parser for only the function at the path, like one event handler of
+    /// a large script, without decompiling the functions it's nested in.
+    ///
+    /// Its upvalues are named after the variables pushed before its closure.
+    pub fn for_function(root: &'a Proto, path: &ProtoPath) -> Result<Self> {
+        let proto = root
+            .nested(path)
+            .ok_or_else(|| Error::new_parser(format!("no function {path}")))?;
+        let mut parser = Self::new(proto);
+        parser.path = path.to_vec();
+        if let Some((proto_id, parent)) = path.split_last() {
+            // The parent exists, since the function nested in it does.
+            parser.enclosing = root.nested(parent).map(|parent| (parent, *proto_id));
         }
+        Ok(parser)
+    }
+
+    /// Recover from errors by replacing the instructions that failed
+    /// with a comment, instead of failing the whole chunk.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.tolerance = if lenient {
+            Tolerance::Lenient
+        } else {
+            Tolerance::Strict
+        };
+        self
+    }
+
+    /// Recover from errors unless the tolerance is strict, like [Parser::lenient].
+    ///
+    /// With [Tolerance::BestEffort], instructions with unknown opcodes are
+    /// skipped instead of failing.
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Intern names into the table, to share it with the syntax trees of
+    /// other parsers in the same pipeline. The table is handed over with
+    /// the syntax tree, in [Syntax::symbols].
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Name globals and locals as per the map, in this function and its
+    /// nested functions, instead of using their names in the chunk or
+    /// making them up.
+    pub fn with_renames(mut self, renames: &'a RenameMap) -> Self {
+        self.local_namer
+            .reserved
+            .extend(renames.names().map(str::to_string));
+        self.renames = Some(renames);
+        self
+    }
+
+    /// Make up the names of locals without debug information with the
+    /// strategy, in this function and its nested functions, instead of
+    /// with [AlphabeticNames].
+    pub fn with_naming(mut self, strategy: impl NamingStrategy + 'static) -> Self {
+        self.local_namer.strategy = Box::new(strategy);
+        self
+    }
+
+    /// Name parameters that the chunk has no names for after the arguments
+    /// they're passed as, like `s` for one passed to `strlen`, in this
+    /// function and its nested functions.
+    pub fn with_param_names(mut self, param_names: &'a ParamNames) -> Self {
+        self.param_names = Some(param_names);
+        self
+    }
+
+    /// Recognize the idiom wherever its pattern matches, in this
+    /// function and its nested functions, before the generic parsing.
+    ///
+    /// Idioms are tried in the order they were added.
+    pub fn with_idiom(mut self, idiom: &'a dyn Idiom) -> Self {
+        self.idioms.push(idiom);
+        self
+    }
+
+    /// Send diagnostic events to the given sink.
+    pub fn with_trace(mut self, trace: &'a dyn Trace) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Problems found so far in the function and its nested functions,
+    /// like instructions that couldn't be decompiled or made up names.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn into_diagnostics(self) -> Diagnostics {
+        self.diagnostics
     }
 
     pub fn parse(&mut self) -> Result<Syntax> {
-        println!("parse");
+        trace_event!(self.trace, Level::Debug, "parse");
+
+        self.structure = Structure::new(&self.proto.ops).map_err(|err| {
+            err.with_context(format!(
+                "function {}:{}",
+                self.proto.source, self.proto.line_defined
+            ))
+            .with_location(self.location(None))
+        })?;
+
+        // Imbalanced code is still parsed when tolerant, since the
+        // instructions around the imbalance may decompile.
+        if let Some(imbalance) = self.proto.stack_imbalance() {
+            if !self.tolerance.is_tolerant() {
+                let location = self.location(Some(imbalance.offset));
+                self.proto
+                    .verify_stack()
+                    .map_err(|err| err.with_location(location))?;
+            }
+            self.diagnose(
+                Severity::Warning,
+                Some(Ip(imbalance.offset as u32)),
+                format!("stack imbalance: {}", imbalance.message),
+            );
+        }
+
+        if !self.proto.has_debug_info() {
+            trace_event!(
+                self.trace,
+                Level::Info,
+                "function {}:{} has no debug information",
+                self.proto.source,
+                self.proto.line_defined
+            );
+        }
+
+        self.declare_params();
+        self.name_upvalues()?;
 
         let iter = self
             .proto
@@ -121,71 +431,503 @@ impl<'a> Parser<'a> {
             .map(|(i, o)| (Ip(i as u32), o));
 
         for (ip, op) in iter {
-            println!("[{}] op: {op:?}", ip.as_usize() + 1);
+            // Already recognized as part of an idiom.
+            if ip.as_usize() < self.resume {
+                continue;
+            }
 
-            // If we reached the end marker of the block, wrap up
-            // by collecting all the nodes in the block into a single node.
-            if let Some(block) = self.blocks.last() {
-                if ip == block.end {
-                    self.end_block()?;
+            trace_event!(
+                self.trace,
+                Level::Trace,
+                "[{}] op: {op:?}",
+                ip.as_usize() + 1
+            );
+
+            match self.parse_op(ip, op) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) if self.tolerance.is_tolerant() => self.recover(ip, err),
+                Err(err) => {
+                    return Err(err
+                        .with_context(self.proto.instr_context(ip.as_usize()))
+                        .with_location(self.location(Some(ip.as_usize()))))
                 }
             }
 
-            match op {
-                Op::End => break,
-                Op::Return { .. } => { /* todo */ }
-                Op::Call {
-                    stack_offset,
-                    results,
-                } => self.parse_call(ip, *stack_offset, *results)?,
-                Op::Pop { n } => self.parse_pop(*n)?,
-                Op::PushInt { value } => self.parse_push_int(ip, *value)?,
-                Op::GetLocal { stack_offset } => self.parse_get_local(ip, *stack_offset)?,
-                Op::GetGlobal { string_id } => self.parse_get_global(ip, *string_id)?,
-                Op::SetLocal { stack_offset } => self.parse_set_local(ip, *stack_offset)?,
-                Op::Add => self.parse_binary_op(ip, BinOp::Add)?,
-                Op::JumpLe { ip: dest_ip } => self.parse_jump_le(ip, *dest_ip)?,
-            }
-
-            println!("stack: {:?}", self.stack);
-            println!("nodes: {:?}", self.nodes);
-            println!("-------------")
-        }
-
-        let block = Block {
-            nodes: self
-                .nodes
-                .iter_mut()
-                .filter_map(|node| node.take())
-                .collect(),
-        };
+            trace_event!(self.trace, Level::Trace, "stack: {:?}", self.stack);
+            trace_event!(self.trace, Level::Trace, "nodes: {:?}", self.nodes);
+        }
+
+        // The statement the chunk ended in leaves the values it pushed.
+        if self.proto.is_truncated() {
+            for slot in std::mem::take(&mut self.stack) {
+                if let Some(Some(Node::Expr(_))) = self.nodes.get(slot.ip.as_usize()) {
+                    self.nodes[slot.ip.as_usize()] = None;
+                }
+            }
+            if let Some(last) = self.nodes.len().checked_sub(1) {
+                self.comment(Ip(last as u32), "the chunk ends here");
+            }
+        }
+
+        let mut block = self.collect_block(0, self.nodes.len());
+        if self.tolerance.is_tolerant() {
+            self.append_detached(&mut block)?;
+        }
 
         Ok(Syntax {
             root: block,
-            debug: (),
+            path: ProtoPath::from(self.path.clone()),
+            symbols: std::mem::take(&mut self.symbols),
+        })
+    }
+
+    /// Decompile the function as a standalone definition, like
+    /// `local function_3 = function(a) ... end`, named after its index
+    /// like the functions that aren't attached to a closure.
+    ///
+    /// The main function is decompiled as usual.
+    pub fn parse_standalone(&mut self) -> Result<Syntax> {
+        let mut syntax = self.parse()?;
+        let Some(&proto_id) = self.path.last() else {
+            return Ok(syntax);
+        };
+        let function = Function {
+            params: self.params.clone(),
+            is_vararg: self.proto.is_vararg,
+            body: syntax.root,
+            path: syntax.path.clone(),
+        };
+        let site = match self.enclosing {
+            Some((parent, _)) => closure_site(parent, proto_id),
+            None => None,
+        };
+        // The definition is placed at the closure in the enclosing function.
+        let path = match site {
+            Some(_) => syntax.path.parent().unwrap_or_default(),
+            None => syntax.path,
+        };
+        Ok(Syntax {
+            root: Block {
+                nodes: vec![Node::Stmt(Stmt::LocalVar(LocalVar {
+                    names: vec![Ident::interned(
+                        format!("function_{proto_id}"),
+                        &mut syntax.symbols,
+                    )],
+                    rhs: vec![Expr::Function(Box::new(function))],
+                }))],
+                spans: match (self.enclosing, site) {
+                    (Some((parent, _)), Some(site)) => vec![span(parent, site, site)],
+                    _ => vec![span(self.proto, 0, 0)],
+                },
+            },
+            path,
+            symbols: syntax.symbols,
         })
     }
 }
 
 impl<'a> Parser<'a> {
+    /// Declare the locals that the caller's arguments are placed in.
+    ///
+    /// A vararg function collects the extra arguments into a table,
+    /// in the implicit local `arg` after the fixed parameters.
+    ///
+    /// Parameters are live from the first instruction in the debug
+    /// information, so their names are kept when it's there and they're
+    /// valid names. Without it, they're made up like other locals.
+    fn declare_params(&mut self) {
+        let inferred = match self.param_names {
+            Some(param_names) => param_names.infer(self.proto),
+            None => vec![],
+        };
+        let mut in_scope = self.names_in_scope();
+        for stack_offset in 0..self.proto.num_params {
+            let debug_name = self
+                .proto
+                .local_name(stack_offset, 0)
+                .filter(|name| is_name(name))
+                .map(str::to_string);
+            let usage_name = inferred.get(stack_offset as usize).copied().flatten();
+            let name = match (self.renamed_local(stack_offset).or(debug_name), usage_name) {
+                (Some(name), _) => name,
+                (None, Some(base)) => {
+                    let name = self.local_namer.claim(base, &in_scope);
+                    self.diagnose(
+                        Severity::Note,
+                        None,
+                        format!("named a parameter `{name}` by how it's used"),
+                    );
+                    name
+                }
+                (None, None) => {
+                    let name = self.local_namer.next(
+                        &LocalHint {
+                            slot: stack_offset,
+                            ty: Type::Unknown,
+                            is_param: true,
+                        },
+                        &in_scope,
+                    );
+                    self.diagnose(
+                        Severity::Note,
+                        None,
+                        format!("made up the name `{name}` for a parameter"),
+                    );
+                    name
+                }
+            };
+            self.stack.push(Slot::PARAM);
+            let param = self.ident(&name);
+            self.params.push(param);
+            in_scope.insert(name.clone());
+            self.declare_local(name, stack_offset);
+        }
+
+        if self.proto.is_vararg {
+            let stack_offset = self.proto.num_params;
+            self.stack.push(Slot::PARAM);
+            self.declare_local("arg", stack_offset);
+        }
+    }
+
+    /// Parse one instruction, closing the current block first
+    /// when the instruction is its end marker.
+    ///
+    /// Returns `false` at the end of the function.
+    fn parse_op(&mut self, ip: Ip, op: &Op) -> Result<bool> {
+        // If we reached the end marker of blocks, wrap up
+        // by collecting all the nodes in each block into a single node.
+        while self.blocks.last().is_some_and(|block| block.end == ip) {
+            self.end_block()?;
+        }
+
+        // Expressions with `and` and `or` end where their jumps go.
+        self.resolve_logic(ip)?;
+
+        // Values become locals when their scope starts, even if never read.
+        if let Some(count) = self.proto.active_locals(ip.as_usize()) {
+            self.declare_live_locals(count)?;
+        }
+
+        // Blocks that don't start with a jump, like loop bodies.
+        let spans: Vec<_> = self.structure.spans_at(ip.as_usize()).copied().collect();
+        for span in spans {
+            let kind = match span.kind {
+                SpanKind::Repeat => BlockKind::Repeat,
+                SpanKind::Loop => BlockKind::Loop,
+                SpanKind::Do => BlockKind::Do,
+            };
+            self.start_block(ip, Ip(span.end as u32), kind);
+        }
+
+        if self.parse_idiom(ip) {
+            return Ok(true);
+        }
+
+        match op {
+            Op::End => {
+                // Values still on the stack at the end were never read,
+                // but only locals outlive their statement.
+                self.declare_live_locals(self.stack.len() as u32)?;
+                return Ok(false);
+            }
+            Op::Return { stack_offset } => self.parse_return(ip, *stack_offset)?,
+            Op::TailCall {
+                stack_offset,
+                return_offset,
+            } => self.parse_tail_call(ip, *stack_offset, *return_offset)?,
+            Op::Call {
+                stack_offset,
+                results,
+            } => self.parse_call(ip, *stack_offset, *results)?,
+            Op::Pop { n } => self.parse_pop(ip, *n)?,
+            Op::PushInt { value } => self.parse_push_int(ip, *value)?,
+            Op::PushString { string_id } => self.parse_push_string(ip, *string_id)?,
+            Op::PushNum { number_id } => self.parse_push_num(ip, *number_id, false)?,
+            Op::PushNegNum { number_id } => self.parse_push_num(ip, *number_id, true)?,
+            Op::PushUpvalue { upvalue_id } => self.parse_push_upvalue(ip, *upvalue_id)?,
+            Op::GetLocal { stack_offset } => self.parse_get_local(ip, *stack_offset)?,
+            Op::GetGlobal { string_id } => self.parse_get_global(ip, *string_id)?,
+            Op::SetLocal { stack_offset } => self.parse_set_local(ip, *stack_offset)?,
+            Op::SetGlobal { string_id } => self.parse_set_global(ip, *string_id)?,
+            Op::CreateTable { .. } => self.parse_create_table(ip)?,
+            Op::SetList { batch, n } => self.parse_set_list(*batch, *n)?,
+            Op::SetMap { n } => self.parse_set_map(*n)?,
+            Op::Add => self.parse_binary_op(ip, BinOp::Add)?,
+            Op::AddI { value } => self.parse_add_int(ip, *value)?,
+            Op::Sub => self.parse_binary_op(ip, BinOp::Sub)?,
+            Op::Mult => self.parse_binary_op(ip, BinOp::Mul)?,
+            Op::Div => self.parse_binary_op(ip, BinOp::Div)?,
+            Op::Pow => self.parse_binary_op(ip, BinOp::Pow)?,
+            Op::Concat { n } => self.parse_concat(ip, *n)?,
+            Op::Minus => self.parse_unary_op(ip, UnaryOp::Neg)?,
+            Op::Not => self.parse_unary_op(ip, UnaryOp::Not)?,
+            Op::JumpNe { .. } => self.parse_compare_jump(ip, BinOp::Ne)?,
+            Op::JumpEq { .. } => self.parse_compare_jump(ip, BinOp::Eq)?,
+            Op::JumpLt { .. } => self.parse_compare_jump(ip, BinOp::Lt)?,
+            Op::JumpLe { .. } => self.parse_compare_jump(ip, BinOp::Le)?,
+            Op::JumpGt { .. } => self.parse_compare_jump(ip, BinOp::Gt)?,
+            Op::JumpGe { .. } => self.parse_compare_jump(ip, BinOp::Ge)?,
+            Op::JumpTrue { .. } => self.parse_test_jump(ip, false)?,
+            Op::JumpFalse { .. } => self.parse_test_jump(ip, true)?,
+            Op::JumpOnTrue { .. } => self.parse_logic_jump(ip, BinOp::Or)?,
+            Op::JumpOnFalse { .. } => self.parse_logic_jump(ip, BinOp::And)?,
+            Op::Jump { .. } => self.parse_jump(ip)?,
+            Op::PushNilJump => self.parse_push_nil_jump(ip)?,
+            Op::Closure { proto_id, upvalues } => self.parse_closure(ip, *proto_id, *upvalues)?,
+            Op::Unsupported { opcode } => return Err(err_unsupported(*opcode)),
+            Op::Unknown => self.parse_unknown(ip)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Try the idioms at the instruction, placing the node built by the
+    /// first one that recognizes the instructions its pattern matches.
+    ///
+    /// Returns `true` when an idiom was recognized, and the
+    /// instructions it's made of don't need parsing.
+    fn parse_idiom(&mut self, ip: Ip) -> bool {
+        for index in 0..self.idioms.len() {
+            let idiom = self.idioms[index];
+            let Some(len) = idiom.pattern().match_at(&self.proto.instrs, ip.as_usize()) else {
+                continue;
+            };
+            let range = ip.as_usize()..ip.as_usize() + len;
+            let Some(height) = self.idiom_stack_height(range.clone()) else {
+                trace_event!(
+                    self.trace,
+                    Level::Debug,
+                    "[{}] idiom pattern matched across a block or stack boundary",
+                    ip.as_usize() + 1
+                );
+                continue;
+            };
+
+            let instructions: Vec<_> = self
+                .proto
+                .instructions()
+                .skip(range.start)
+                .take(len)
+                .collect();
+            let last = Ip(range.end as u32 - 1);
+            match idiom.recognize(self.proto, &instructions) {
+                Some(Recognized::Stmt(custom)) if height == self.stack.len() => {
+                    self.nodes[last.as_usize()] = Some(Node::Stmt(Stmt::Custom(custom)));
+                }
+                Some(Recognized::Expr(custom)) if height == self.stack.len() + 1 => {
+                    self.nodes[last.as_usize()] = Some(Node::Expr(Expr::Custom(custom)));
+                    self.push_slot(last);
+                }
+                Some(_) => {
+                    self.diagnose(
+                        Severity::Warning,
+                        Some(ip),
+                        "idiom doesn't fit the values its instructions leave on the stack",
+                    );
+                    continue;
+                }
+                None => continue,
+            }
+
+            self.resume = range.end;
+            return true;
+        }
+        false
+    }
+
+    /// Height of the operand stack after the instructions,
+    /// when they can be replaced by a single node.
+    ///
+    /// They can't when control enters or leaves in the middle, when
+    /// a local's scope starts within them, or when they use values
+    /// pushed before them.
+    fn idiom_stack_height(&self, range: Range<usize>) -> Option<usize> {
+        let floor = self.stack.len();
+        let locals = self.proto.active_locals(range.start);
+        let mut height = floor;
+        for pc in range.clone() {
+            if pc > range.start
+                && (self
+                    .blocks
+                    .last()
+                    .is_some_and(|block| block.end.as_usize() == pc)
+                    || self.structure.spans_at(pc).next().is_some()
+                    || self.proto.active_locals(pc) != locals
+                    || self.is_jump_target(pc))
+            {
+                return None;
+            }
+
+            height = match self.proto.ops[pc] {
+                Op::Pop { n } => height.checked_sub(n as usize)?,
+                Op::PushInt { .. }
+                | Op::PushString { .. }
+                | Op::PushNum { .. }
+                | Op::PushNegNum { .. }
+                | Op::PushUpvalue { .. }
+                | Op::GetLocal { .. }
+                | Op::GetGlobal { .. }
+                | Op::CreateTable { .. } => height + 1,
+                Op::AddI { .. } | Op::Minus | Op::Not => height,
+                Op::SetLocal { .. }
+                | Op::SetGlobal { .. }
+                | Op::Add
+                | Op::Sub
+                | Op::Mult
+                | Op::Div
+                | Op::Pow => height.checked_sub(1)?,
+                Op::Concat { n } => height.checked_sub((n as usize).checked_sub(1)?)?,
+                Op::SetList { n, .. } => height.checked_sub(n as usize)?,
+                Op::SetMap { n } => height.checked_sub(n as usize * 2)?,
+                Op::Call {
+                    stack_offset,
+                    results,
+                } if results != MULT_RET && stack_offset as usize >= floor => {
+                    stack_offset as usize + results as usize
+                }
+                // Idioms may be made of instructions the generic parser doesn't support.
+                Op::Unsupported { opcode } => {
+                    let instr = &self.proto.instrs[pc];
+                    match opcode {
+                        Opcode::PushNil => height + instr.u as usize,
+                        Opcode::GetTable => height.checked_sub(1)?,
+                        Opcode::GetDotted | Opcode::GetIndexed => height,
+                        Opcode::PushSelf => height + 1,
+                        Opcode::SetTable => height.checked_sub(instr.b as usize)?,
+                        _ => return None,
+                    }
+                }
+                // Control flow, nested functions, and instructions
+                // that leave a variable number of values.
+                _ => return None,
+            };
+            if height < floor {
+                return None;
+            }
+        }
+        Some(height)
+    }
+
+    /// Whether any jump in the function lands on the instruction.
+    fn is_jump_target(&self, pc: usize) -> bool {
+        self.proto
+            .instrs
+            .iter()
+            .enumerate()
+            .any(|(from, instr)| instr.jump_target(from) == Some(pc))
+    }
+
+    /// Replace the nodes since the last statement in the current block, up to
+    /// and including the instruction that failed, with a [Failed] statement.
+    ///
+    /// Values pushed in that range are lost, so instructions using them fail
+    /// in turn and are merged into the same statement until the code recovers.
+    fn recover(&mut self, ip: Ip, err: Error) {
+        self.diagnose(Severity::Warning, Some(ip), &err);
+
+        let floor = self.blocks.last().map(BlockSpan::floor).unwrap_or(0);
+        let mut start = floor;
+        let mut message = err.to_string();
+        for index in (floor..ip.as_usize()).rev() {
+            match &mut self.nodes[index] {
+                // Nothing was decompiled since the last failure, so the
+                // first error is kept as the cause.
+                Some(Node::Stmt(Stmt::Failed(failed))) => {
+                    start = failed.start as usize;
+                    message = std::mem::take(&mut failed.message);
+                    break;
+                }
+                Some(Node::Stmt(_)) => {
+                    start = index + 1;
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        for index in start..=ip.as_usize() {
+            self.nodes[index] = None;
+            self.block_ends[index] = None;
+
+            // Function bodies in the failed range are lost with their closure.
+            if let Op::Closure { proto_id, .. } = self.proto.ops[index] {
+                if let Some(attached) = self.attached.get_mut(proto_id as usize) {
+                    *attached = false;
+                }
+            }
+        }
+        self.nodes[ip.as_usize()] = Some(Node::Stmt(Stmt::Failed(Failed {
+            message,
+            start: start as u32,
+            end: ip.0,
+            listing: self.proto.dump_range(start..ip.as_usize() + 1).to_string(),
+        })));
+    }
+
+    fn parse_return(&mut self, ip: Ip, stack_offset: u32) -> Result<()> {
+        // The returned values are pushed after the live locals.
+        self.declare_live_locals(stack_offset)?;
+
+        // All values from the offset to the top of the stack are returned.
+        let value_slots = self.split_stack(stack_offset)?;
+        let values = self.take_list(value_slots)?;
+        self.nodes[ip.as_usize()] = Some(Node::Stmt(Stmt::Return(Return { values })));
+
+        Ok(())
+    }
+
+    /// Parse a [Op::TailCall], which is a call with all its results
+    /// followed by a return of the values from the return offset.
+    fn parse_tail_call(&mut self, ip: Ip, stack_offset: u32, return_offset: u32) -> Result<()> {
+        if stack_offset < return_offset {
+            return Error::new_parser(format!(
+                "tail call at stack offset {stack_offset} below returned values at {return_offset}"
+            ))
+            .into();
+        }
+        // The call's node is taken by the return, which takes its place.
+        self.parse_call(ip, stack_offset, MULT_RET)?;
+        self.parse_return(ip, return_offset)
+    }
+
     fn parse_call(&mut self, ip: Ip, stack_offset: u32, results: u32) -> Result<()> {
         // TODO: All the call semantics and how it interacts with the stack.
 
         // Truncate stack and leave results.
-        let mut arg_ips = self.stack.split_off(stack_offset as usize);
-        let name_ip = arg_ips.remove(0);
-
-        // TODO: Multi return semantics (even possible for C calls?)
-        for _ in 0..results {
-            self.stack.push(ip);
+        let mut arg_slots = self.split_stack(stack_offset)?;
+        if arg_slots.is_empty() {
+            return Err(err_stack_underflow());
         }
+        let name_slot = arg_slots.remove(0);
+        self.check_type(ip, name_slot, "call", Type::is_callable);
 
-        let name = self.take_expr(name_ip)?;
-        let mut args = vec![];
-        for arg_ip in arg_ips {
-            args.push(self.take_expr(arg_ip)?);
+        // Each result gets its own slot, so multiple results can be
+        // distributed over several variables.
+        //
+        // When the number of results is open-ended it's only known at runtime, so
+        // the call gets a single open slot. It can only be consumed by the last
+        // argument of a call or value of a return statement, where it expands.
+        if results == MULT_RET {
+            self.stack.push(Slot {
+                ip,
+                nth: 0,
+                open: true,
+            });
+        } else {
+            for nth in 0..results {
+                self.stack.push(Slot {
+                    ip,
+                    nth,
+                    open: false,
+                });
+            }
         }
 
+        let name = self.take_value(name_slot)?.ok_or_else(err_expr_expected)?;
+        let args = self.take_list(arg_slots)?;
+
         let node: Node = if results == 0 {
             // When the call returns 0 results, it implies the function
             // was called as a statement.
@@ -199,21 +941,85 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn parse_pop(&mut self, n: u32) -> Result<()> {
+    fn parse_pop(&mut self, ip: Ip, n: u32) -> Result<()> {
+        // Locals popped anywhere but at the end of a statement's block were
+        // declared in a `do` block, which the pop ends.
+        let height = self.stack.len().saturating_sub(n as usize);
+
+        // Without debug information, values popped right above the locals
+        // are locals too, declared but never read.
+        let inferred = self.proto.active_locals(ip.as_usize()).is_none()
+            && (0..height as u32).all(|offset| self.has_local(offset));
+        if inferred {
+            self.declare_live_locals(self.stack.len() as u32)?;
+        }
+
+        let scoped = self
+            .locals
+            .iter()
+            .any(|local| local.stack_offset as usize >= height);
+        if scoped && !self.closes_block(ip) {
+            self.wrap_do_block(ip, height)?;
+        }
+
         // Removes 'n' slots from the stack.
         for _ in 0..n {
             self.stack.pop();
         }
 
+        // Local variables that lived in the popped slots go out of scope.
+        let height = self.stack.len() as u32;
+        self.locals.retain(|local| local.stack_offset < height);
+
         // Pop is implicit to remove locals at the end of a block,
         // so doesn't have any syntax to generate.
 
         Ok(())
     }
 
+    /// Checks whether the pop removes the locals of the innermost block
+    /// at its end, leaving only the jump out of it, or the condition of
+    /// a `repeat` loop.
+    fn closes_block(&self, ip: Ip) -> bool {
+        let Some(block) = self.blocks.last() else {
+            return false;
+        };
+        let rest = self
+            .proto
+            .ops
+            .get(ip.as_usize() + 1..block.end.as_usize())
+            .unwrap_or_default();
+        match block.kind {
+            BlockKind::Do => false,
+            BlockKind::Repeat => rest.iter().all(|op| !ends_statement(op)),
+            _ => rest.iter().all(|op| matches!(op, Op::Jump { .. })),
+        }
+    }
+
+    /// Wrap the statements from the one declaring the local in the stack
+    /// slot at `height` up to the instruction in a `do` block.
+    fn wrap_do_block(&mut self, ip: Ip, height: usize) -> Result<()> {
+        let slot = *self.stack.get(height).ok_or_else(err_stack_underflow)?;
+        let floor = self.blocks.last().map(BlockSpan::floor).unwrap_or(0);
+        let declared = slot.ip.as_usize().max(floor);
+
+        // The block starts right after the statement before the declaration.
+        let start = (floor..declared)
+            .rev()
+            .find_map(|index| {
+                self.nodes[index]
+                    .as_ref()
+                    .map(|_| self.block_ends[index].unwrap_or(index) + 1)
+            })
+            .unwrap_or(floor);
+        let body = self.collect_block(start, ip.as_usize() + 1);
+        self.nodes[ip.as_usize()] = Some(Node::Stmt(Stmt::Block(body)));
+        Ok(())
+    }
+
     fn parse_push_int(&mut self, ip: Ip, value: i32) -> Result<()> {
         // Pushes a constant integer into the stack top.
-        self.stack.push(ip);
+        self.push_slot(ip);
 
         // Integer literal in code.
         self.nodes[ip.as_usize()] = Some(Lit::Int(value).into());
@@ -221,233 +1027,1338 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    /// Parse a [Op::GetLocal] instruction.
-    fn parse_get_local(&mut self, ip: Ip, stack_offset: u32) -> Result<()> {
-        // Because the stack slot is now being treated as a local variable, we
-        // can check how it was written and possibly promote that syntax from
-        // an expression into a local variable declaration statement.
-        let node_ip = self.stack[stack_offset as usize];
-        self.promote_local_var(node_ip)?;
-
-        // Copies the value from the local variable's slot onto the stack top.
-        self.stack.push(ip);
+    fn parse_push_string(&mut self, ip: Ip, string_id: u32) -> Result<()> {
+        self.push_slot(ip);
 
-        let local_name = self.get_local_var_name(stack_offset)?;
-        self.nodes[ip.as_usize()] = Some(Ident::new(local_name).into());
+        let value = self.get_string_constant(string_id)?.clone();
+        self.nodes[ip.as_usize()] = Some(Lit::Str(value).into());
 
         Ok(())
     }
 
-    fn parse_get_global(&mut self, ip: Ip, string_id: u32) -> Result<()> {
-        self.stack.push(ip);
+    /// Parse a [Op::PushNum], or a [Op::PushNegNum] which
+    /// pushes the negative of the constant.
+    fn parse_push_num(&mut self, ip: Ip, number_id: u32, negate: bool) -> Result<()> {
+        self.push_slot(ip);
 
-        let global_name = self.get_global_var_name(string_id);
-        self.nodes[ip.as_usize()] = Some(Ident::new(global_name).into());
+        let value = self
+            .proto
+            .constants
+            .numbers
+            .get(number_id as usize)
+            .ok_or_else(|| {
+                Error::new_parser(format!("number constant {number_id} out of bounds"))
+            })?;
+        let value = if negate { -value } else { *value };
+        self.nodes[ip.as_usize()] = Some(Lit::Num(value).into());
 
         Ok(())
     }
 
-    fn parse_set_local(&mut self, ip: Ip, stack_offset: u32) -> Result<()> {
-        // An existing node that wrote the variable may be promoted to a variable declaration.
-        let node_ip = self.stack[stack_offset as usize];
-        self.promote_local_var(node_ip)?;
-
-        // Value is 'moved' into the variable.
-        let rhs_ip = self.stack.pop().ok_or_else(err_stack_underflow)?;
-        let rhs_node = self.take_expr(rhs_ip)?;
-
-        let name = Ident::new(self.get_local_var_name(stack_offset)?);
-        self.nodes[ip.as_usize()] = Some(Node::Stmt(Stmt::Assign(Box::new(Assign {
-            name,
-            rhs: rhs_node,
-        }))));
-
+    /// Name the upvalues of a function decompiled on its own, after the
+    /// variables its enclosing function pushed before the closure.
+    fn name_upvalues(&mut self) -> Result<()> {
+        let Some((parent, proto_id)) = self.enclosing else {
+            return Ok(());
+        };
+        let Some(site) = closure_site(parent, proto_id) else {
+            return Ok(());
+        };
+        let Op::Closure { upvalues, .. } = parent.ops[site] else {
+            return Ok(());
+        };
+        let start = site
+            .checked_sub(upvalues as usize)
+            .ok_or_else(err_stack_underflow)?;
+        let parent_path = self.path[..self.path.len() - 1].to_vec();
+
+        self.upvalues.clear();
+        for (index, pc) in (start..site).enumerate() {
+            let name = match parent.ops[pc] {
+                Op::GetGlobal { string_id } => {
+                    let name = parent
+                        .strings()
+                        .get(string_id as usize)
+                        .ok_or_else(|| {
+                            Error::new_parser(format!("string constant {string_id} out of bounds"))
+                        })?
+                        .to_string_lossy();
+                    match self.renames.and_then(|renames| renames.global(&name)) {
+                        Some(new_name) => new_name.to_string(),
+                        None => name.into_owned(),
+                    }
+                }
+                Op::GetLocal { stack_offset } => {
+                    let renamed = self
+                        .renames
+                        .and_then(|renames| renames.local(&parent_path, stack_offset));
+                    match renamed.or_else(|| parent.local_name(stack_offset, pc)) {
+                        Some(name) => name.to_string(),
+                        None => {
+                            let name = format!("upvalue_{index}");
+                            self.diagnose(
+                                Severity::Note,
+                                None,
+                                format!("made up the name `{name}` for an upvalue"),
+                            );
+                            name
+                        }
+                    }
+                }
+                _ => return Err(Error::new_parser("upvalue is not a variable")),
+            };
+            self.local_namer.reserved.insert(name.clone());
+            let upvalue = self.ident(name);
+            self.upvalues.push(upvalue);
+        }
         Ok(())
     }
 
-    fn parse_binary_op(&mut self, ip: Ip, op: BinOp) -> Result<()> {
-        let rhs_ip = self.stack.pop().ok_or_else(err_stack_underflow)?;
-        let lhs_ip = self.stack.pop().ok_or_else(err_stack_underflow)?;
-
-        let rhs = self.take_expr(rhs_ip)?;
-        let lhs = self.take_expr(lhs_ip)?;
+    /// Parse a [Op::PushUpvalue], naming the variable the
+    /// enclosing function captured for it.
+    fn parse_push_upvalue(&mut self, ip: Ip, upvalue_id: u32) -> Result<()> {
+        let name = self
+            .upvalues
+            .get(upvalue_id as usize)
+            .cloned()
+            .ok_or_else(|| Error::new_parser(format!("upvalue {upvalue_id} out of bounds")))?;
 
-        self.nodes[ip.as_usize()] = Some(BinExpr { op, lhs, rhs }.into());
-
-        self.stack.push(ip);
+        self.push_slot(ip);
+        self.nodes[ip.as_usize()] = Some(Node::Expr(Expr::Upvalue(name)));
 
         Ok(())
     }
 
-    fn parse_jump_le(&mut self, ip: Ip, dest_ip: i32) -> Result<()> {
-        // Destination address is relative to the instruction following the current one.
-        let end = (ip.0 as i32 + 1)
-            .checked_add(dest_ip)
-            .ok_or_else(|| Error::new_decoder("jump address overflow"))?;
-        if end < 0 || end >= self.proto.code.len() as i32 {
-            return Error::new_decoder("jump destination out of bounds").into();
+    /// Parse a [Op::GetLocal] instruction.
+    fn parse_get_local(&mut self, ip: Ip, stack_offset: u32) -> Result<()> {
+        if !self.is_local(ip, stack_offset) {
+            return self.parse_copy_temporary(ip, stack_offset);
         }
-        self.start_block(ip, Ip(end as u32));
 
-        // NOTE: Jump relative to the next ip
-        // TODO: Generate if conditional statement and block nodes.
-        let rhs_ip = self.stack.pop().ok_or_else(err_stack_underflow)?;
-        let lhs_ip = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        // Because the stack slot is now being treated as a local variable, we
+        // can check how it was written and possibly promote that syntax from
+        // an expression into a local variable declaration statement.
+        self.promote_local_var(stack_offset)?;
 
-        let lhs = self.take_expr(lhs_ip)?;
-        let rhs = self.take_expr(rhs_ip)?;
+        // Copies the value from the local variable's slot onto the stack top.
+        self.push_slot(ip);
 
-        self.nodes[ip.as_usize()] = Some(
-            IfHead {
-                expr: CondExpr::Binary {
-                    op: CondOp::Le.invert(),
-                    lhs,
-                    rhs,
-                },
-            }
-            .into(),
-        );
+        let local_name = self.get_local_var_name(stack_offset)?.to_string();
+        self.nodes[ip.as_usize()] = Some(self.ident(local_name).into());
 
         Ok(())
     }
-}
-
-impl<'a> Parser<'a> {
-    /// Start a new block.
-    fn start_block(&mut self, start: Ip, end: Ip) {
-        self.blocks.push(BlockSpan { start, end })
-    }
 
-    fn end_block(&mut self) -> Result<()> {
-        if let Some(BlockSpan { start, end }) = self.blocks.pop() {
-            println!("end block ({start}, {end})");
+    /// Read back a temporary value pushed earlier in the same expression,
+    /// by repeating the expression that pushed it.
+    ///
+    /// Only values that are the same however often they're evaluated can be
+    /// repeated, since the instructions in between may have changed the rest.
+    fn parse_copy_temporary(&mut self, ip: Ip, stack_offset: u32) -> Result<()> {
+        let slot = *self
+            .stack
+            .get(stack_offset as usize)
+            .ok_or_else(err_stack_underflow)?;
+        let is_constant = !slot.open
+            && matches!(
+                self.proto.ops.get(slot.ip.as_usize()),
+                Some(
+                    Op::PushInt { .. }
+                        | Op::PushString { .. }
+                        | Op::PushNum { .. }
+                        | Op::PushNegNum { .. }
+                        | Op::PushUpvalue { .. }
+                        | Op::GetLocal { .. }
+                )
+            );
+        let copy = match self.nodes.get(slot.ip.as_usize()) {
+            Some(Some(Node::Expr(Expr::Literal(lit)))) if is_constant => Expr::Literal(lit.clone()),
+            Some(Some(Node::Expr(Expr::Access(ident)))) if is_constant => {
+                Expr::Access(ident.clone())
+            }
+            Some(Some(Node::Expr(Expr::Upvalue(ident)))) if is_constant => {
+                Expr::Upvalue(ident.clone())
+            }
+            _ => {
+                return Error::new_parser(format!(
+                    "temporary value at stack offset {stack_offset} is read again, \
+                     but the expression that pushed it can't be repeated"
+                ))
+                .into()
+            }
+        };
 
-            // TODO: if, while, for, do...
-            // TODO: Conditional header
-            // let _header = self.nodes[start.as_usize()].take().ok_or_else(err_node_none)?;
+        self.push_slot(ip);
+        self.nodes[ip.as_usize()] = Some(Node::Expr(copy));
 
-            let mut nodes = vec![];
+        Ok(())
+    }
 
-            // Note that the ending instruction is exclusive.
-            // The jump destination is the previous instruction.
-            for maybe_node in &mut self.nodes[start.as_usize() + 1..end.as_usize()] {
-                if let Some(node) = maybe_node.take() {
-                    nodes.push(node);
-                }
-            }
-            let body = Block { nodes };
+    fn parse_get_global(&mut self, ip: Ip, string_id: u32) -> Result<()> {
+        self.push_slot(ip);
 
-            // head
-            match self.take_partial(start)? {
-                Partial::IfHead(if_head) => {
-                    let IfHead { expr } = *if_head;
-                    let node = Node::Stmt(Stmt::If(IfBlock {
-                        head: expr,
-                        then: body,
-                        else_: None,
-                    }));
+        let global_name = self.get_global_var_name(ip, string_id)?.into_owned();
+        self.nodes[ip.as_usize()] = Some(self.ident(global_name).into());
 
-                    // Place the new node into the header instruction.
-                    self.nodes[start.as_usize()] = Some(node);
-                }
-                Partial::WhileHead => todo!(),
-                Partial::ForHead => todo!(),
-            }
+        Ok(())
+    }
 
-            println!("stack: {:?}", self.stack);
-            println!("nodes: {:?}", self.nodes);
-            println!("-------------")
+    fn parse_set_local(&mut self, ip: Ip, stack_offset: u32) -> Result<()> {
+        if !self.is_local(ip, stack_offset) {
+            return Error::new_parser(format!(
+                "stack offset {stack_offset} is not a live local variable"
+            ))
+            .into();
         }
 
-        Ok(())
+        // An existing node that wrote the variable may be promoted to a variable declaration.
+        self.promote_local_var(stack_offset)?;
+
+        let name = self.get_local_var_name(stack_offset)?.to_string();
+        let name = self.ident(name);
+        self.parse_assign(ip, name)
     }
 
-    /// Promotes the syntax node the given instruction into a local variable declaration.
-    ///
+    fn parse_set_global(&mut self, ip: Ip, string_id: u32) -> Result<()> {
+        let name = self.get_global_var_name(ip, string_id)?.into_owned();
+        let name = self.ident(name);
+        self.parse_assign(ip, name)
+    }
+
+    /// Pop the top of the stack into the given variable.
+    ///
+    /// A multiple assignment pushes all its values first, then pops them
+    /// into the targets in reverse order. Consecutive assignments are thus
+    /// merged into the statement built by the previous instruction.
+    fn parse_assign(&mut self, ip: Ip, target: Ident) -> Result<()> {
+        // Value is 'moved' into the variable.
+        let rhs_slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        let rhs = self.take_value(rhs_slot)?;
+
+        let mut assign = match self.take_prev_assign(ip) {
+            Some(assign) => assign,
+            None => Assign {
+                targets: vec![],
+                rhs: vec![],
+            },
+        };
+        assign.targets.insert(0, target);
+        if let Some(rhs) = rhs {
+            assign.rhs.insert(0, rhs);
+        }
+
+        self.nodes[ip.as_usize()] = Some(Node::Stmt(Stmt::Assign(Box::new(assign))));
+
+        Ok(())
+    }
+
+    fn parse_closure(&mut self, ip: Ip, proto_id: u32, upvalues: u32) -> Result<()> {
+        let proto = self
+            .proto
+            .protos()
+            .get(proto_id as usize)
+            .ok_or_else(|| Error::new_parser(format!("function {proto_id} out of bounds")))?;
+
+        // The values of the upvalues are pushed before the closure, which
+        // copies them. The nested function refers to them by name.
+        let offset = (self.stack.len() as u32)
+            .checked_sub(upvalues)
+            .ok_or_else(err_stack_underflow)?;
+        let mut names = vec![];
+        for slot in self.split_stack(offset)? {
+            match self.take_value(slot)? {
+                Some(Expr::Access(name)) => names.push(name),
+                _ => return Err(Error::new_parser("upvalue is not a variable")),
+            }
+        }
+
+        let function = self.parse_nested(proto_id as usize, proto, names)?;
+        self.attached[proto_id as usize] = true;
+
+        self.push_slot(ip);
+        self.nodes[ip.as_usize()] = Some(function.into());
+
+        Ok(())
+    }
+
+    /// Decompile a nested function.
+    fn parse_nested(
+        &mut self,
+        proto_id: usize,
+        proto: &'a Proto,
+        upvalues: Vec<Ident>,
+    ) -> Result<Function> {
+        // The namer is swapped for the enclosing function's below.
+        let namer = Namer::new(HashSet::new());
+        let mut parser = Parser::with_namer(proto, namer)
+            .with_tolerance(self.tolerance)
+            .with_trace(self.trace);
+        parser.renames = self.renames;
+        parser.param_names = self.param_names;
+        parser.idioms = self.idioms.clone();
+        parser.upvalues = upvalues;
+        parser.path = self.path.clone();
+        parser.path.push(proto_id);
+
+        // Names continue from the enclosing function, so locals
+        // of nested functions are told apart from its own.
+        std::mem::swap(&mut parser.local_namer, &mut self.local_namer);
+        parser.symbols = std::mem::take(&mut self.symbols);
+        let mut result = parser.parse();
+        std::mem::swap(&mut parser.local_namer, &mut self.local_namer);
+        self.symbols = match &mut result {
+            Ok(syntax) => std::mem::take(&mut syntax.symbols),
+            Err(_) => std::mem::take(&mut parser.symbols),
+        };
+        self.diagnostics.extend(parser.diagnostics);
+
+        let syntax = result?;
+        Ok(Function {
+            params: parser.params,
+            is_vararg: proto.is_vararg,
+            body: syntax.root,
+            path: syntax.path,
+        })
+    }
+
+    /// Append the nested functions that weren't placed at a closure,
+    /// because it failed to decompile, as standalone definitions.
+    fn append_detached(&mut self, block: &mut Block) -> Result<()> {
+        let protos = self.proto.protos();
+        for (proto_id, proto) in protos.iter().enumerate() {
+            if self.attached[proto_id] {
+                continue;
+            }
+            self.diagnose(
+                Severity::Warning,
+                None,
+                format!("function {proto_id} is not attached to a closure"),
+            );
+
+            let site = closure_site(self.proto, proto_id)
+                .unwrap_or(self.proto.ops.len().saturating_sub(1));
+            // Without the closure, the upvalues can't be named.
+            let function = self.parse_nested(proto_id, proto, vec![])?;
+
+            block.nodes.push(Node::Stmt(Stmt::LocalVar(LocalVar {
+                names: vec![self.ident(format!("function_{proto_id}"))],
+                rhs: vec![Expr::Function(Box::new(function))],
+            })));
+            block.spans.push(span(self.proto, site, site));
+        }
+        Ok(())
+    }
+
+    fn parse_create_table(&mut self, ip: Ip) -> Result<()> {
+        self.push_slot(ip);
+        self.nodes[ip.as_usize()] = Some(Table::default().into());
+        Ok(())
+    }
+
+    /// Parse a [Op::SetList], appending the values to the list part
+    /// of the table constructor below them.
+    ///
+    /// Long lists are set in batches, which must follow each other.
+    fn parse_set_list(&mut self, batch: u32, n: u32) -> Result<()> {
+        let items = self.take_values(n)?;
+        let table = self.table_at_top()?;
+        if table.items.len() != (batch * LFIELDS_PER_FLUSH) as usize {
+            return Error::new_parser(format!(
+                "list batch {batch} set after {} items",
+                table.items.len()
+            ))
+            .into();
+        }
+        if table.items.is_empty() && !table.fields.is_empty() {
+            table.fields_first = true;
+        }
+        table.items.extend(items);
+        Ok(())
+    }
+
+    /// Parse a [Op::SetMap], appending the key and value pairs to the
+    /// record part of the table constructor below them.
+    fn parse_set_map(&mut self, n: u32) -> Result<()> {
+        let mut values = self.take_values(n * 2)?.into_iter();
+        let table = self.table_at_top()?;
+        while let (Some(key), Some(value)) = (values.next(), values.next()) {
+            table.fields.push(Field { key, value });
+        }
+        Ok(())
+    }
+
+    /// Pop the given number of values, in the order they were pushed.
+    fn take_values(&mut self, n: u32) -> Result<Vec<Expr>> {
+        let offset = (self.stack.len() as u32)
+            .checked_sub(n)
+            .ok_or_else(err_stack_underflow)?;
+        let mut values = vec![];
+        for slot in self.split_stack(offset)? {
+            values.push(self.take_value(slot)?.ok_or_else(err_expr_expected)?);
+        }
+        Ok(values)
+    }
+
+    /// Table constructor that pushed the value at the top of the stack.
+    fn table_at_top(&mut self) -> Result<&mut Table> {
+        let slot = *self.stack.last().ok_or_else(err_stack_underflow)?;
+        match self.nodes.get_mut(slot.ip.as_usize()) {
+            Some(Some(Node::Expr(Expr::Table(table)))) => Ok(table),
+            _ => Error::new_parser("expected table constructor").into(),
+        }
+    }
+
+    fn parse_binary_op(&mut self, ip: Ip, op: BinOp) -> Result<()> {
+        let rhs_slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        let lhs_slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+
+        let accept = match op {
+            BinOp::Concat => Type::is_concat_operand,
+            _ => Type::is_arith_operand,
+        };
+        self.check_type(ip, lhs_slot, op.as_str(), accept);
+        self.check_type(ip, rhs_slot, op.as_str(), accept);
+
+        let rhs = self.take_expr(rhs_slot.ip)?;
+        let lhs = self.take_expr(lhs_slot.ip)?;
+
+        self.nodes[ip.as_usize()] = Some(BinExpr { op, lhs, rhs }.into());
+
+        self.push_slot(ip);
+
+        Ok(())
+    }
+
+    fn parse_unary_op(&mut self, ip: Ip, op: UnaryOp) -> Result<()> {
+        let rhs_slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        if let UnaryOp::Neg = op {
+            self.check_type(ip, rhs_slot, op.as_str(), Type::is_arith_operand);
+        }
+        let rhs = self.take_expr(rhs_slot.ip)?;
+
+        self.nodes[ip.as_usize()] = Some(UnaryExpr { op, rhs }.into());
+
+        self.push_slot(ip);
+
+        Ok(())
+    }
+
+    /// Parse an [Op::AddI], which the compiler emits for adding or
+    /// subtracting an integer constant.
+    fn parse_add_int(&mut self, ip: Ip, value: i32) -> Result<()> {
+        let lhs_slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        self.check_type(ip, lhs_slot, "+", Type::is_arith_operand);
+        let lhs = self.take_expr(lhs_slot.ip)?;
+
+        let (op, value) = match value.checked_neg() {
+            Some(negated) if value < 0 => (BinOp::Sub, negated),
+            _ => (BinOp::Add, value),
+        };
+        let rhs = Expr::Literal(Lit::Int(value));
+        self.nodes[ip.as_usize()] = Some(BinExpr { op, lhs, rhs }.into());
+
+        self.push_slot(ip);
+
+        Ok(())
+    }
+
+    /// Parse an [Op::Concat] of the values on top of the stack.
+    ///
+    /// Concatenation is right associative, so the chain
+    /// is built from the last operand backwards.
+    fn parse_concat(&mut self, ip: Ip, n: u32) -> Result<()> {
+        if n == 0 {
+            return Error::new_parser("concatenation without operands").into();
+        }
+        let offset = (self.stack.len() as u32)
+            .checked_sub(n)
+            .ok_or_else(err_stack_underflow)?;
+        let mut operands = vec![];
+        for slot in self.split_stack(offset)? {
+            self.check_type(ip, slot, "..", Type::is_concat_operand);
+            operands.push(self.take_expr(slot.ip)?);
+        }
+
+        let mut expr = operands.pop().ok_or_else(err_stack_underflow)?;
+        while let Some(lhs) = operands.pop() {
+            expr = Expr::Binary(Box::new(BinExpr {
+                op: BinOp::Concat,
+                lhs,
+                rhs: expr,
+            }));
+        }
+        self.nodes[ip.as_usize()] = Some(Node::Expr(expr));
+
+        self.push_slot(ip);
+
+        Ok(())
+    }
+
+    /// Parse a jump that compares the two values on top of the stack.
+    fn parse_compare_jump(&mut self, ip: Ip, op: BinOp) -> Result<()> {
+        let rhs_slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        let lhs_slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+
+        if !matches!(op, BinOp::Eq | BinOp::Ne) {
+            let (lhs_type, rhs_type) = (self.slot_type(lhs_slot), self.slot_type(rhs_slot));
+            if !lhs_type.is_ordered_with(rhs_type) {
+                self.diagnose(
+                    Severity::Warning,
+                    Some(ip),
+                    format!("comparing {} with {}", lhs_type.name(), rhs_type.name()),
+                );
+            }
+        }
+
+        let lhs = self.take_expr(lhs_slot.ip)?;
+        let rhs = self.take_expr(rhs_slot.ip)?;
+
+        self.parse_cond_jump(ip, Expr::Binary(Box::new(BinExpr { op, lhs, rhs })))
+    }
+
+    /// Parse a jump that tests the value on top of the stack.
+    ///
+    /// The jump is taken when the value is true, or when it's false with `negate`.
+    fn parse_test_jump(&mut self, ip: Ip, negate: bool) -> Result<()> {
+        let slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        let rhs = self.take_expr(slot.ip)?;
+
+        self.parse_cond_jump(ip, if negate { Expr::logical_not(rhs) } else { rhs })
+    }
+
+    /// Build the statement for a conditional jump, given
+    /// the condition under which the jump is taken.
+    fn parse_cond_jump(&mut self, ip: Ip, cond: Expr) -> Result<()> {
+        let pc = ip.as_usize();
+        let offset = self.proto.ops[pc].jump_offset().unwrap_or_default();
+        let jump = ChainJump {
+            cond,
+            target: (pc as i64 + 1 + offset as i64) as usize,
+            next: pc + 1,
+        };
+        let control = self.structure.control(pc);
+        if control == Some(Control::Logic) {
+            self.logic.push(LogicJump::Test(jump));
+            return Ok(());
+        }
+        self.chain.push(jump);
+        if let Some(Control::Cond { .. } | Control::Value) = control {
+            return Ok(());
+        }
+        let cond = self.take_chain()?;
+
+        let node: Node = match control {
+            // The blocks run when the jump isn't taken, so the
+            // source condition is the inverse of the jump's.
+            Some(Control::If { else_start, end }) => {
+                let kind = match else_start {
+                    Some(_) => BlockKind::Then {
+                        else_end: Some(Ip(end as u32)),
+                    },
+                    None => BlockKind::Then { else_end: None },
+                };
+                let then_end = else_start.unwrap_or(end);
+                self.start_block(ip, Ip(then_end as u32), kind);
+                IfHead {
+                    expr: cond.invert(),
+                    then: None,
+                }
+                .into()
+            }
+            Some(Control::While { end }) => {
+                self.start_block(ip, Ip(end as u32), BlockKind::While);
+                WhileHead {
+                    expr: cond.invert(),
+                }
+                .into()
+            }
+            Some(Control::Until) => Node::Partial(Partial::Until(Box::new(cond.invert()))),
+            Some(Control::Break) => Node::Stmt(Stmt::If(Box::new(IfBlock {
+                head: cond,
+                then: Block {
+                    nodes: vec![Node::Stmt(Stmt::Break)],
+                    spans: vec![span(self.proto, ip.as_usize(), ip.as_usize())],
+                },
+                else_: None,
+            }))),
+            Some(Control::Goto { target }) => {
+                self.diagnose_goto(ip, target);
+                Node::Stmt(Stmt::Goto(Box::new(Goto {
+                    cond: Some(cond),
+                    target: target as u32,
+                })))
+            }
+            _ => return Err(err_unstructured_jump()),
+        };
+        self.nodes[ip.as_usize()] = Some(node);
+
+        Ok(())
+    }
+
+    /// Parse a jump that keeps the value on top of the stack when taken,
+    /// as the value of the `or` or `and` expression it's part of.
+    fn parse_logic_jump(&mut self, ip: Ip, op: BinOp) -> Result<()> {
+        let pc = ip.as_usize();
+        if self.structure.control(pc) != Some(Control::Logic) {
+            return Err(err_unsupported(self.proto.instrs[pc].opcode));
+        }
+        let slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        let value = self.take_expr(slot.ip)?;
+        let offset = self.proto.ops[pc].jump_offset().unwrap_or_default();
+        self.logic.push(LogicJump::Keep {
+            ip,
+            op,
+            value,
+            target: (pc as i64 + 1 + offset as i64) as usize,
+        });
+        Ok(())
+    }
+
+    /// Join the jumps of `and` and `or` expressions that go to the instruction
+    /// with the operands they skip, and replace the value on top of the stack
+    /// with the whole expression once all its jumps have landed.
+    fn resolve_logic(&mut self, ip: Ip) -> Result<()> {
+        let pc = ip.as_usize();
+        if !self.logic.iter().any(|jump| jump.target() == pc) {
+            return Ok(());
+        }
+
+        loop {
+            let len = self.logic.len();
+            // A test that skips the operand of a keeping jump to land here joins
+            // them into one operand, `a and b` in `a and b or c`.
+            let joins = matches!(
+                &self.logic[..],
+                [.., LogicJump::Test(test), LogicJump::Keep { target, .. }]
+                    if test.target == pc && *target > pc
+            );
+            if joins {
+                if let (
+                    Some(LogicJump::Keep {
+                        ip,
+                        op,
+                        value,
+                        target,
+                    }),
+                    Some(LogicJump::Test(test)),
+                ) = (self.logic.pop(), self.logic.pop())
+                {
+                    let operand = match op {
+                        BinOp::Or => join_logic(BinOp::And, test.cond.invert(), value),
+                        _ => join_logic(BinOp::Or, test.cond, value),
+                    };
+                    self.logic.push(LogicJump::Keep {
+                        ip,
+                        op,
+                        value: operand,
+                        target,
+                    });
+                    continue;
+                }
+            }
+
+            // Tests landing here are joined with the ones after them
+            // into the condition of one test, like `a or b` in `(a or b) and c`.
+            let tests = self
+                .logic
+                .iter()
+                .rev()
+                .take_while(|jump| matches!(jump, LogicJump::Test(_)))
+                .count();
+            let lands = self.logic[len - tests..]
+                .iter()
+                .any(|jump| jump.target() == pc);
+            if tests < 2 || !lands {
+                break;
+            }
+            let chain = self
+                .logic
+                .split_off(len - tests)
+                .into_iter()
+                .filter_map(|jump| match jump {
+                    LogicJump::Test(test) => Some(test),
+                    LogicJump::Keep { .. } => None,
+                })
+                .collect();
+            let merged = merge_chain(chain, |join, first, second| match join {
+                Join::Or => join_logic(BinOp::Or, first, second),
+                Join::AndNot => join_logic(BinOp::And, first.invert(), second),
+            });
+            self.logic
+                .push(LogicJump::Test(merged.ok_or_else(err_unstructured_jump)?));
+        }
+
+        // The keeping jumps landing here end the expression, which is
+        // the value left by the operands after them otherwise.
+        let mut ends = vec![];
+        while let Some(LogicJump::Keep { target, .. }) = self.logic.last() {
+            if *target != pc {
+                break;
+            }
+            ends.extend(self.logic.pop());
+        }
+        if self.logic.iter().any(|jump| jump.target() == pc) {
+            return Err(err_unstructured_jump());
+        }
+        let Some(LogicJump::Keep { ip: last, .. }) = ends.first() else {
+            return Ok(());
+        };
+        let last = *last;
+
+        let slot = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        let mut expr = self.take_expr(slot.ip)?;
+        for jump in ends {
+            if let LogicJump::Keep { op, value, .. } = jump {
+                expr = join_logic(op, value, expr);
+            }
+        }
+        self.nodes[last.as_usize()] = Some(Node::Expr(expr));
+        self.push_slot(last);
+        Ok(())
+    }
+
+    /// Join the conditional jumps of a chain into the
+    /// condition under which the last one is taken.
+    fn take_chain(&mut self) -> Result<Expr> {
+        let chain = std::mem::take(&mut self.chain);
+        let merged = merge_chain(chain, |join, first, second| match join {
+            Join::Or => Expr::Binary(Box::new(BinExpr {
+                op: BinOp::Or,
+                lhs: first,
+                rhs: second,
+            })),
+            Join::AndNot => Expr::Binary(Box::new(BinExpr {
+                op: BinOp::And,
+                lhs: first.invert(),
+                rhs: second,
+            })),
+        });
+        Ok(merged.ok_or_else(err_unstructured_jump)?.cond)
+    }
+
+    /// Parse the end of a comparison used as a value, which is true when
+    /// its jumps are taken to the `PUSHINT 1` after this instruction.
+    fn parse_push_nil_jump(&mut self, ip: Ip) -> Result<()> {
+        if self.structure.control(ip.as_usize()) != Some(Control::Value) {
+            return Err(err_unsupported(Opcode::PushNilJump));
+        }
+        let value = self.take_chain()?;
+        self.nodes[ip.as_usize()] = Some(Node::Expr(value));
+        self.push_slot(ip);
+
+        // The `PUSHINT 1` is part of the value.
+        self.resume = ip.as_usize() + 2;
+        Ok(())
+    }
+
+    /// Skip an instruction with an unknown opcode, with [Tolerance::BestEffort].
+    ///
+    /// Its effect on the stack isn't known, so this only works out
+    /// when it's a no-op, like padding in a modified compiler's output.
+    fn parse_unknown(&mut self, ip: Ip) -> Result<()> {
+        if self.tolerance != Tolerance::BestEffort {
+            return Error::new_parser("unknown opcode").into();
+        }
+        self.diagnose(Severity::Warning, Some(ip), "skipped unknown instruction");
+        self.comment(
+            ip,
+            format!("skipped unknown instruction {}", ip.as_usize() + 1),
+        );
+        Ok(())
+    }
+
+    fn parse_jump(&mut self, ip: Ip) -> Result<()> {
+        let node = match self.structure.control(ip.as_usize()) {
+            // Part of the enclosing statement, which is built when its block ends.
+            Some(Control::Else | Control::Continue | Control::Implied) => return Ok(()),
+            Some(Control::Break) => Stmt::Break,
+            Some(Control::Goto { target }) => {
+                self.diagnose_goto(ip, target);
+                Stmt::Goto(Box::new(Goto {
+                    cond: None,
+                    target: target as u32,
+                }))
+            }
+            _ => return Err(err_unstructured_jump()),
+        };
+        self.nodes[ip.as_usize()] = Some(Node::Stmt(node));
+
+        Ok(())
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// Take the nodes placed in the range of instructions into a block.
+    ///
+    /// Operands are pushed right before the instruction that consumes them, so
+    /// each node is attributed all instructions since the node before it.
+    ///
+    /// Goto destinations in the range are placed before the first node at or
+    /// after them, unless they were already placed in a nested block.
+    fn collect_block(&mut self, start: usize, end: usize) -> Block {
+        let mut nodes = vec![];
+        let mut spans = vec![];
+        let mut next_start = start;
+        let mut labels = self
+            .structure
+            .take_labels(start..end)
+            .into_iter()
+            .peekable();
+        let mut comments = self.take_comments(start..end).into_iter().peekable();
+
+        for (index, maybe_node) in self.nodes[start..end].iter_mut().enumerate() {
+            if let Some(node) = maybe_node.take() {
+                let ip = start + index;
+                let last = self.block_ends[ip].take().unwrap_or(ip);
+                while let Some(label) = labels.next_if(|label| *label <= ip) {
+                    nodes.push(Node::Stmt(Stmt::Label(label as u32)));
+                    spans.push(span(self.proto, label, label));
+                }
+                while let Some((at, comment)) = comments.next_if(|(at, _)| *at <= ip) {
+                    nodes.push(Node::Stmt(Stmt::Comment(comment)));
+                    spans.push(span(self.proto, at, at));
+                }
+                nodes.push(node);
+                spans.push(span(self.proto, next_start, last));
+                next_start = last + 1;
+            }
+        }
+        for label in labels {
+            nodes.push(Node::Stmt(Stmt::Label(label as u32)));
+            spans.push(span(self.proto, label, label));
+        }
+        for (at, comment) in comments {
+            nodes.push(Node::Stmt(Stmt::Comment(comment)));
+            spans.push(span(self.proto, at, at));
+        }
+
+        Block { nodes, spans }
+    }
+
+    /// Start a new block.
+    fn start_block(&mut self, start: Ip, end: Ip, kind: BlockKind) {
+        self.blocks.push(BlockSpan {
+            start,
+            end,
+            kind,
+            stack: self.stack.clone(),
+            locals: self.locals.clone(),
+        })
+    }
+
+    fn end_block(&mut self) -> Result<()> {
+        if let Some(span) = self.blocks.pop() {
+            let BlockSpan {
+                start, end, kind, ..
+            } = span;
+            trace_event!(
+                self.trace,
+                Level::Debug,
+                "end block {kind:?} ({start}, {end})"
+            );
+
+            // Note that the ending instruction is exclusive.
+            // The jump destination is the previous instruction.
+            match kind {
+                BlockKind::Then { else_end } => {
+                    let then = self.collect_block(start.as_usize() + 1, end.as_usize());
+                    let mut if_head = self.take_if_head(start)?;
+
+                    match else_end {
+                        Some(else_end) => {
+                            // The statement is built once the `else` block is done.
+                            if_head.then = Some(then);
+                            self.nodes[start.as_usize()] =
+                                Some(Node::Partial(Partial::IfHead(Box::new(if_head))));
+                            self.blocks.push(BlockSpan {
+                                start: end,
+                                end: else_end,
+                                kind: BlockKind::Else { head: start },
+                                stack: span.stack.clone(),
+                                locals: span.locals.clone(),
+                            });
+                        }
+                        None => {
+                            let node = Node::Stmt(Stmt::If(Box::new(IfBlock {
+                                head: if_head.expr,
+                                then,
+                                else_: None,
+                            })));
+
+                            // Place the new node into the header instruction.
+                            self.nodes[start.as_usize()] = Some(node);
+                            self.block_ends[start.as_usize()] = Some(end.as_usize() - 1);
+                        }
+                    }
+                }
+                BlockKind::Else { head } => {
+                    let else_ = self.collect_block(start.as_usize(), end.as_usize());
+                    let if_head = self.take_if_head(head)?;
+                    let node = Node::Stmt(Stmt::If(Box::new(IfBlock {
+                        head: if_head.expr,
+                        then: if_head.then.ok_or_else(err_partial_expected)?,
+                        else_: Some(else_),
+                    })));
+                    self.nodes[head.as_usize()] = Some(node);
+                    self.block_ends[head.as_usize()] = Some(end.as_usize() - 1);
+                }
+                BlockKind::While => {
+                    let body = self.collect_block(start.as_usize() + 1, end.as_usize());
+                    let head = match self.take_partial(start)? {
+                        Partial::WhileHead(while_head) => while_head.expr,
+                        _ => return Err(err_partial_expected()),
+                    };
+                    let node = Node::Stmt(Stmt::While(Box::new(WhileBlock { head, body })));
+                    self.nodes[start.as_usize()] = Some(node);
+                    self.block_ends[start.as_usize()] = Some(end.as_usize() - 1);
+                }
+                BlockKind::Loop => {
+                    // Without a condition the loop runs until a break,
+                    // which Lua 4.0 writes with a constant condition.
+                    let body = self.collect_block(start.as_usize(), end.as_usize());
+                    let head = Expr::Literal(Lit::Int(1));
+                    let node = Node::Stmt(Stmt::While(Box::new(WhileBlock { head, body })));
+                    self.nodes[end.as_usize() - 1] = Some(node);
+                }
+                BlockKind::Repeat => {
+                    let last = Ip(end.0 - 1);
+                    let cond = match self.take_partial(last)? {
+                        Partial::Until(cond) => *cond,
+                        _ => return Err(err_partial_expected()),
+                    };
+                    let body = self.collect_block(start.as_usize(), last.as_usize());
+                    let node = Node::Stmt(Stmt::Repeat(Box::new(RepeatBlock { body, cond })));
+                    self.nodes[last.as_usize()] = Some(node);
+                }
+                BlockKind::Do => {
+                    let body = self.collect_block(start.as_usize(), end.as_usize());
+                    self.nodes[end.as_usize() - 1] = Some(Node::Stmt(Stmt::Block(body)));
+                }
+            }
+
+            // The range skipped by a goto isn't a block in the source.
+            if kind != BlockKind::Do {
+                self.check_block_stack(end, &span.stack)?;
+                self.stack = span.stack;
+                self.locals = span.locals;
+            }
+
+            trace_event!(self.trace, Level::Trace, "stack: {:?}", self.stack);
+            trace_event!(self.trace, Level::Trace, "nodes: {:?}", self.nodes);
+        }
+
+        Ok(())
+    }
+
+    /// Every path through a block leaves the stack as it found it,
+    /// so a different height at its end means the stack was tracked wrong.
+    ///
+    /// Unless the tolerance is strict, the stack is restored with a warning.
+    fn check_block_stack(&mut self, end: Ip, stack: &[Slot]) -> Result<()> {
+        if self.stack.len() == stack.len() {
+            return Ok(());
+        }
+        let err = Error::new_parser(format!(
+            "stack height {} at the end of the block, expected {}",
+            self.stack.len(),
+            stack.len()
+        ));
+        if !self.tolerance.is_tolerant() {
+            return Err(err);
+        }
+        self.diagnose(Severity::Warning, Some(end), &err);
+        self.comment(end, err.to_string());
+        Ok(())
+    }
+
+    /// Whether the stack slot accessed by the instruction holds a local variable,
+    /// rather than a temporary value of the expression being evaluated.
+    ///
+    /// Slots already declared as locals are taken at their word, so the uses
+    /// of a local's value are only followed the first time it's accessed.
+    fn is_local(&self, ip: Ip, stack_offset: u32) -> bool {
+        (self.proto.locals.is_empty() && self.has_local(stack_offset))
+            || self
+                .liveness
+                .is_local(self.proto, ip.as_usize(), stack_offset)
+    }
+
+    /// Promote the nodes that wrote the bottom stack slots into local variable declarations.
+    fn declare_live_locals(&mut self, count: u32) -> Result<()> {
+        for stack_offset in 0..count {
+            self.promote_slot(stack_offset)?;
+        }
+        Ok(())
+    }
+
+    /// Promotes the syntax node that wrote the given stack slot into a local variable declaration.
+    ///
+    /// Locals are always at the bottom of the stack, so the slots below it
+    /// that aren't locals yet are promoted first, in their own statements.
+    fn promote_local_var(&mut self, stack_offset: u32) -> Result<()> {
+        self.declare_live_locals(stack_offset + 1)
+    }
+
+    /// Promotes the syntax node that wrote a single stack slot into a local variable declaration.
+    ///
     /// Returns `true` if the node was promoted.
-    fn promote_local_var(&mut self, ip: Ip) -> Result<bool> {
+    fn promote_slot(&mut self, stack_offset: u32) -> Result<bool> {
+        // Slot is already known to be a local variable.
+        if self.has_local(stack_offset) {
+            return Ok(false);
+        }
+
         // If the stack slot is not a local variable declaration,
         // then promote it.
         //
         // Local variable declarations at the start of the function
         // may have their OP_SETLOCAL instructions removed as an
         // optimsation.
-        if let Some(node) = &self.nodes[ip.as_usize()] {
-            // TODO: Consider the case where an expression assigned after declaration.
-            if !node.is_local_var() {
-                let node = self.nodes[ip.as_usize()].take().unwrap();
-
-                match node {
-                    Node::Expr(rhs) => {
-                        // Generate a new name for the local variable.
-                        // TODO: Detect conflict with globals or up-values.
-                        let name = Ident::new(self.local_namer.next());
-                        let new_node = Node::Stmt(Stmt::LocalVar(LocalVar { name, rhs }));
-                        self.nodes[ip.as_usize()] = Some(new_node);
-                        self.local_end += 1;
-                        return Ok(true);
-                    }
-                    Node::Stmt(_) => {
-                        return Error::new_parser(
-                            "a statement cannot be turned into a local variable declaration",
-                        )
-                        .into()
-                    }
-                    Node::Partial(_) => {
-                        return Error::new_parser(
-                            "a partially built statement cannot be turned into a local variable declaration",
-                        )
-                        .into()
-                    }
+        let slot = *self
+            .stack
+            .get(stack_offset as usize)
+            .ok_or_else(err_stack_underflow)?;
+
+        // Values of a multiple result expression are declared
+        // together in the same statement.
+        let offsets: Vec<u32> = self
+            .stack
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| other.ip == slot.ip)
+            .map(|(offset, _)| offset as u32)
+            .collect();
+        let types: Vec<Type> = offsets
+            .iter()
+            .map(|offset| self.slot_type(self.stack[*offset as usize]))
+            .collect();
+
+        match self
+            .nodes
+            .get_mut(slot.ip.as_usize())
+            .and_then(Option::take)
+        {
+            Some(Node::Expr(rhs)) => {
+                let mut names = vec![];
+                let mut in_scope = self.names_in_scope();
+                for (offset, ty) in offsets.into_iter().zip(types) {
+                    // Keep the name in the debug information, unless the rename map
+                    // names the local. Stripped chunks have their names made up.
+                    let name = match self
+                        .renamed_local(offset)
+                        .or_else(|| self.debug_local_name(slot.ip, offset))
+                    {
+                        Some(name) => name,
+                        None => {
+                            let name = self.local_namer.next(
+                                &LocalHint {
+                                    slot: offset,
+                                    ty,
+                                    is_param: false,
+                                },
+                                &in_scope,
+                            );
+                            self.diagnose(
+                                Severity::Note,
+                                Some(slot.ip),
+                                format!("made up the name `{name}` for a local variable"),
+                            );
+                            name
+                        }
+                    };
+                    names.push(self.ident(&name));
+                    in_scope.insert(name.clone());
+                    self.declare_local(name, offset);
+                    self.local_end += 1;
                 }
+
+                let new_node = Node::Stmt(Stmt::LocalVar(LocalVar {
+                    names,
+                    rhs: vec![rhs],
+                }));
+                self.nodes[slot.ip.as_usize()] = Some(new_node);
             }
+            Some(Node::Stmt(_)) => {
+                return Error::new_parser(
+                    "a statement cannot be turned into a local variable declaration",
+                )
+                .into()
+            }
+            Some(Node::Partial(_)) => return Error::new_parser(
+                "a partially built statement cannot be turned into a local variable declaration",
+            )
+            .into(),
+            None => return Err(err_node_none()),
         }
 
-        Ok(false)
+        Ok(true)
     }
 
-    fn get_local_var_name(&self, local_id: u32) -> Result<&str> {
-        // TODO: Tracking local variables may require a dedicated Vec<Local> because this node migh tbe overwritten.
-        let node_ip = self.stack[local_id as usize];
-        match self.nodes[node_ip.as_usize()]
-            .as_ref()
-            .ok_or_else(err_node_none)?
-        {
-            Node::Stmt(stmt) => match stmt {
-                Stmt::LocalVar(local_var) => Ok(local_var.name.as_str()),
-                _ => Error::new_parser("unexpected statement in local variable node").into(),
-            },
-            Node::Expr(_) => {
-                Error::new_parser("unexpected expression in local variable node").into()
-            }
-            Node::Partial(_) => {
-                Error::new_parser("unexpected partial statement in local variable node").into()
+    fn get_local_var_name(&self, stack_offset: u32) -> Result<&str> {
+        self.locals
+            .iter()
+            .rev()
+            .find(|local| local.stack_offset == stack_offset)
+            .map(|local| local.name.as_str())
+            .ok_or_else(|| {
+                Error::new_parser(format!("no local variable at stack offset {stack_offset}"))
+            })
+    }
+
+    fn get_string_constant(&self, string_id: u32) -> Result<&LuaString> {
+        self.proto
+            .constants
+            .strings
+            .get(string_id as usize)
+            .ok_or_else(|| Error::new_parser(format!("string constant {string_id} out of bounds")))
+    }
+
+    /// Name of a global variable, which only needs to be
+    /// UTF-8 when the tolerance is strict.
+    fn get_global_var_name(&mut self, ip: Ip, string_id: u32) -> Result<Cow<'_, str>> {
+        let name = self.get_string_constant(string_id)?;
+        if name.to_str().is_none() {
+            let err = Error::new_parser(format!(
+                "global name {:?} is not valid UTF-8",
+                name.to_string_lossy()
+            ));
+            if !self.tolerance.is_tolerant() {
+                return Err(err);
             }
+            self.diagnose(Severity::Warning, Some(ip), &err);
+        }
+        let name = self.get_string_constant(string_id)?.to_string_lossy();
+        match self.renames.and_then(|renames| renames.global(&name)) {
+            Some(new_name) => Ok(Cow::Borrowed(new_name)),
+            None => Ok(name),
         }
     }
 
-    fn get_global_var_name(&self, string_id: u32) -> &str {
-        self.proto.constants.strings[string_id as usize].as_str()
+    /// Name given to the locals in the stack slot by the rename map.
+    fn renamed_local(&self, stack_offset: u32) -> Option<String> {
+        self.renames?
+            .local(&self.path, stack_offset)
+            .map(str::to_string)
+    }
+
+    /// Name of the local declared in the stack slot by the statement
+    /// that wrote its value at `ip`, from the debug information.
+    ///
+    /// Locals are live from the instruction after the statement that
+    /// declares them, which is where the next locals in the list start.
+    fn debug_local_name(&self, ip: Ip, stack_offset: u32) -> Option<String> {
+        let startpc = self
+            .proto
+            .locals
+            .iter()
+            .map(|local| local.startpc as usize)
+            .find(|startpc| *startpc > ip.as_usize())?;
+        self.proto
+            .local_name(stack_offset, startpc)
+            .filter(|name| is_name(name))
+            .map(str::to_string)
     }
 
     /// Checks whether we have a record of the local variable
     /// at the given stack slot.
     fn has_local(&self, stack_offset: u32) -> bool {
-        stack_offset as usize >= self.locals.len()
+        self.locals
+            .iter()
+            .any(|local| local.stack_offset == stack_offset)
+    }
+
+    /// Names of the local variables in scope, for the namer to avoid.
+    fn names_in_scope(&self) -> HashSet<String> {
+        self.locals.iter().map(|local| local.name.clone()).collect()
+    }
+
+    /// Identifier of the name, interned in the parser's table.
+    fn ident(&mut self, name: impl AsRef<str>) -> Ident {
+        Ident::interned(name, &mut self.symbols)
+    }
+
+    fn declare_local(&mut self, name: impl ToString, stack_offset: u32) {
+        self.locals.push(Local {
+            name: name.to_string(),
+            stack_offset,
+            is_declared: true,
+        });
+    }
+
+    /// Type of the value in the slot, inferred from the instruction that pushed it.
+    fn slot_type(&self, slot: Slot) -> Type {
+        match self.proto.ops.get(slot.ip.as_usize()) {
+            Some(op) if slot.nth == 0 => Type::of_op(op),
+            _ => Type::Unknown,
+        }
+    }
+
+    /// Warn when the value in the slot is of a type that the operation would
+    /// fail on at runtime, which hints that the stack was tracked wrong.
+    fn check_type(&mut self, ip: Ip, slot: Slot, op: &str, accept: fn(Type) -> bool) {
+        let ty = self.slot_type(slot);
+        if !accept(ty) {
+            self.diagnose(
+                Severity::Warning,
+                Some(ip),
+                format!("{} operand of {op}", ty.name()),
+            );
+        }
+    }
+
+    /// A jump that isn't part of any statement is written as a goto, which
+    /// Lua 4.0 doesn't have, so the output has to be restructured by hand.
+    fn diagnose_goto(&mut self, ip: Ip, target: usize) {
+        self.diagnose(
+            Severity::Warning,
+            Some(ip),
+            format!(
+                "jump to instruction {} written as a goto comment",
+                target + 1
+            ),
+        );
+    }
+
+    /// Function and instruction to locate an error at.
+    fn location(&self, offset: Option<usize>) -> Location {
+        Location {
+            path: self.path.clone(),
+            offset,
+            opcode: offset
+                .and_then(|offset| self.proto.instrs().get(offset))
+                .map(|instr| instr.opcode.name()),
+        }
     }
 
-    fn declare_local(&self, name: impl ToString, stack_offset: u32) -> Result<()> {
-        todo!("declare local")
+    /// Leave a comment in the output at the instruction, about something
+    /// that was skipped over, which would otherwise only be a diagnostic.
+    fn comment(&mut self, ip: Ip, text: impl ToString) {
+        self.comments.push((ip.as_usize(), Comment::new(text)));
+    }
+
+    /// Take the comments on the instructions in the range, in order.
+    fn take_comments(&mut self, range: Range<usize>) -> Vec<(usize, Comment)> {
+        if self.comments.is_empty() {
+            return vec![];
+        }
+        let (mut taken, kept): (Vec<_>, _) = std::mem::take(&mut self.comments)
+            .into_iter()
+            .partition(|(ip, _)| range.contains(ip));
+        self.comments = kept;
+        taken.sort_by_key(|(ip, _)| *ip);
+        taken
+    }
+
+    /// Record a problem, and send it to the trace as it happens.
+    fn diagnose(&mut self, severity: Severity, ip: Option<Ip>, message: impl ToString) {
+        let message = message.to_string();
+        let level = match severity {
+            Severity::Warning => Level::Warn,
+            Severity::Note => Level::Info,
+        };
+        match ip {
+            Some(ip) => trace_event!(
+                self.trace,
+                level,
+                "{message} at instruction {}",
+                ip.as_usize() + 1
+            ),
+            None => trace_event!(self.trace, level, "{message}"),
+        }
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path: self.path.clone(),
+            offset: ip.map(Ip::as_usize),
+            message,
+        });
+    }
+
+    fn push_slot(&mut self, ip: Ip) {
+        self.stack.push(Slot {
+            ip,
+            nth: 0,
+            open: false,
+        });
+    }
+
+    /// Remove all the slots from the given offset to the top of the stack.
+    fn split_stack(&mut self, stack_offset: u32) -> Result<Vec<Slot>> {
+        if stack_offset as usize > self.stack.len() {
+            return Err(err_stack_underflow());
+        }
+        Ok(self.stack.split_off(stack_offset as usize))
+    }
+
+    /// Take the expression that produced the value in the given slot.
+    ///
+    /// Values of a multiple result expression share a single node, which is
+    /// only taken with its first value. Because the values are consumed from the
+    /// top of the stack down, the other values return `None`.
+    ///
+    /// The value must be a single one, unlike the open results of a call.
+    fn take_value(&mut self, slot: Slot) -> Result<Option<Expr>> {
+        if slot.open {
+            return Error::new_parser("all results of a call used as a single value").into();
+        }
+        if slot.nth == 0 {
+            self.take_expr(slot.ip).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Take the values of a list of arguments or returned values, where
+    /// the last value may be all the results of a call, expanded in place.
+    fn take_list(&mut self, mut slots: Vec<Slot>) -> Result<Vec<Expr>> {
+        let last = slots.pop_if(|slot| slot.open);
+        let mut values = vec![];
+        for slot in slots {
+            if let Some(value) = self.take_value(slot)? {
+                values.push(value);
+            }
+        }
+        if let Some(slot) = last {
+            values.push(self.take_expr(slot.ip)?);
+        }
+        Ok(values)
+    }
+
+    /// Take the assignment statement built by the previous instruction,
+    /// if it was an assignment.
+    fn take_prev_assign(&mut self, ip: Ip) -> Option<Assign> {
+        let prev = ip.as_usize().checked_sub(1)?;
+        if !matches!(
+            self.proto.ops[prev],
+            Op::SetLocal { .. } | Op::SetGlobal { .. }
+        ) {
+            return None;
+        }
+
+        match self.nodes[prev].take() {
+            Some(Node::Stmt(Stmt::Assign(assign))) => Some(*assign),
+            node => {
+                self.nodes[prev] = node;
+                None
+            }
+        }
     }
 
     fn take_expr(&mut self, ip: Ip) -> Result<Expr> {
-        self.nodes[ip.as_usize()]
-            .take()
+        self.nodes
+            .get_mut(ip.as_usize())
+            .and_then(Option::take)
             .ok_or_else(err_node_none)?
             .into_expr()
             .ok_or_else(err_expr_expected)
     }
 
+    fn take_if_head(&mut self, ip: Ip) -> Result<IfHead> {
+        match self.take_partial(ip)? {
+            Partial::IfHead(if_head) => Ok(*if_head),
+            _ => Err(err_partial_expected()),
+        }
+    }
+
     fn take_partial(&mut self, ip: Ip) -> Result<Partial> {
         self.nodes[ip.as_usize()]
             .take()
@@ -457,6 +2368,28 @@ impl<'a> Parser<'a> {
     }
 }
 
+impl BlockSpan {
+    /// First instruction that belongs to the block's statements.
+    fn floor(&self) -> usize {
+        match self.kind {
+            // The header instruction holds the partial statement.
+            BlockKind::Then { .. } | BlockKind::While => self.start.as_usize() + 1,
+            _ => self.start.as_usize(),
+        }
+    }
+}
+
+impl Slot {
+    /// Slot of an argument, which the caller pushed before the first instruction.
+    ///
+    /// Doesn't refer to any instruction, so there's no node to take for its value.
+    const PARAM: Slot = Slot {
+        ip: Ip(u32::MAX),
+        nth: 0,
+        open: false,
+    };
+}
+
 impl Ip {
     fn as_usize(self) -> usize {
         self.0 as usize
@@ -469,32 +2402,114 @@ impl fmt::Display for Ip {
     }
 }
 
+impl LogicJump {
+    /// Instruction the jump goes to.
+    fn target(&self) -> usize {
+        match self {
+            LogicJump::Keep { target, .. } => *target,
+            LogicJump::Test(test) => test.target,
+        }
+    }
+}
+
 impl Namer {
-    fn new(char_set: &[u8]) -> Self {
+    fn new(reserved: HashSet<String>) -> Self {
         Self {
-            chars: char_set
-                .iter()
-                .cloned()
-                .collect::<Vec<_>>()
-                .into_boxed_slice(),
-            cursor: 0,
-            count: 0,
+            strategy: Box::new(AlphabeticNames::default()),
+            reserved,
         }
     }
 
-    fn next(&mut self) -> String {
-        // Determine the length of the name to generate,
-        // depending on whether we've wrapped the available character set.
-        let len = self.count / self.chars.len();
-        let mut buf = String::new();
+    /// Generate the next name that doesn't collide with a reserved name,
+    /// keyword or one of the locals in scope.
+    ///
+    /// The strategy is asked again while its names are taken, and when
+    /// it gives the same name twice, a number is added to that instead.
+    fn next(&mut self, hint: &LocalHint, in_scope: &HashSet<String>) -> String {
+        let mut previous = None;
+        let base = loop {
+            let name = self.strategy.name(hint);
+            if self.is_free(&name, in_scope) {
+                return name;
+            }
+            if previous.as_ref() == Some(&name) {
+                break name;
+            }
+            previous = Some(name);
+        };
+        self.claim(&base, in_scope)
+    }
 
-        for i in 0..len + 1 {
-            let c = self.chars[(self.count + i) % self.chars.len()];
-            buf.push(c as char);
+    /// The name, or the name with the lowest number added to it that
+    /// doesn't collide with a reserved name, keyword or local in scope.
+    fn claim(&self, base: &str, in_scope: &HashSet<String>) -> String {
+        if self.is_free(base, in_scope) {
+            return base.to_string();
         }
+        let base = if is_name(base) { base } else { "local" };
+        let mut n = 2;
+        loop {
+            let name = format!("{base}_{n}");
+            if self.is_free(&name, in_scope) {
+                return name;
+            }
+            n += 1;
+        }
+    }
+
+    fn is_free(&self, name: &str, in_scope: &HashSet<String>) -> bool {
+        is_name(name) && !self.reserved.contains(name) && !in_scope.contains(name)
+    }
+}
+
+/// Instruction of the closure that creates the nested function.
+fn closure_site(proto: &Proto, proto_id: usize) -> Option<usize> {
+    proto
+        .ops
+        .iter()
+        .position(|op| matches!(op, Op::Closure { proto_id: id, .. } if *id as usize == proto_id))
+}
 
-        self.count += 1;
+/// Span of a node decoded from the instructions from `start` to `end`, inclusive.
+fn span(proto: &Proto, start: usize, end: usize) -> Span {
+    Span {
+        start: start as u32,
+        end: end as u32,
+        line: proto.line_at(start),
+        end_line: proto.line_at(end),
+    }
+}
 
-        buf
+/// Collect the names of globals accessed by the function and its nested
+/// functions, and the local names from debug information or implicit `arg`.
+///
+/// Generated local names must avoid these, otherwise a local
+/// could shadow a global that's accessed in its scope.
+fn used_names(proto: &Proto) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut protos = vec![proto];
+
+    while let Some(proto) = protos.pop() {
+        // Globals are accessed many times over, so each name is only copied once.
+        let mut is_global = vec![false; proto.strings().len()];
+        for instr in proto.instrs() {
+            if matches!(instr.opcode, Opcode::GetGlobal | Opcode::SetGlobal) {
+                if let Some(flag) = is_global.get_mut(instr.u as usize) {
+                    *flag = true;
+                }
+            }
+        }
+        for (name, _) in proto.strings().iter().zip(is_global).filter(|(_, g)| *g) {
+            names.insert(name.to_string());
+        }
+        for local in proto.locals() {
+            names.insert(local.varname.clone());
+        }
+        if proto.is_vararg {
+            names.insert("arg".to_string());
+        }
+        protos.extend(proto.protos());
     }
+
+    names
 }
@@ -0,0 +1,460 @@
+//! Transformations of the syntax tree, between parsing and writing the source.
+//!
+//! A [Pass] rewrites the [Syntax] the parser built, like giving globals
+//! readable names or tidying up conditions. The [PassManager] runs
+//! the registered passes in order.
+use super::ast::{BinExpr, BinOp, Block, Expr, Ident, Lit, Node, Stmt, Syntax, UnaryOp};
+use super::RenameMap;
+use crate::errors::{Error, Result};
+use crate::trace::{trace_event, Level, NoTrace, Trace};
+
+/// Transformation of a syntax tree.
+pub trait Pass {
+    /// Name of the pass, shown in trace events.
+    fn name(&self) -> &str;
+
+    fn run(&mut self, syntax: &mut Syntax) -> Result<()>;
+}
+
+/// Runs passes over syntax trees in the order they were added.
+pub struct PassManager<'a> {
+    passes: Vec<Box<dyn Pass + 'a>>,
+    trace: &'a dyn Trace,
+}
+
+/// Renames globals as per a [RenameMap], like one recovered from
+/// a game's other scripts or its documentation.
+///
+/// This is for syntax trees that were parsed without the map, as
+/// [super::Parser::with_renames] names globals as it builds the tree.
+/// Locals with the same name as a global are left alone.
+#[derive(Debug, Default)]
+pub struct RenameGlobals {
+    renames: RenameMap,
+}
+
+/// Removes double negations from conditions, like `if not (not x)`,
+/// which only test whether the value is `nil`.
+///
+/// Lua 4.0 has no comparison expressions, only comparisons in conditions,
+/// so `not (a == b)` already decompiles to `a ~= b`.
+#[derive(Debug, Default)]
+pub struct SimplifyConditions;
+
+/// Flattens concatenations nested on the left, like `(a .. b) .. c`,
+/// into a single `a .. b .. c` chain.
+///
+/// Lua 4.0 merges right nested concatenations into one `CONCAT`, so left
+/// nested ones are what's left of parentheses in the source. The value is
+/// the same, but the chain compiles to a single `CONCAT`, and tag methods
+/// for `concat` are called on the operands in a different order.
+#[derive(Debug, Default)]
+pub struct FlattenConcat;
+
+// ============================================================================
+
+impl<'a> PassManager<'a> {
+    pub fn new() -> Self {
+        Self {
+            passes: vec![],
+            trace: &NoTrace,
+        }
+    }
+
+    pub fn with_pass(mut self, pass: impl Pass + 'a) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Send diagnostic events to the given sink.
+    pub fn with_trace(mut self, trace: &'a dyn Trace) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Run every pass over the syntax tree, stopping at the first that fails.
+    pub fn run(&mut self, syntax: &mut Syntax) -> Result<()> {
+        for pass in &mut self.passes {
+            trace_event!(self.trace, Level::Debug, "pass: {}", pass.name());
+            pass.run(syntax)
+                .map_err(|err| err.with_context(format!("pass {}", pass.name())))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for PassManager<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenameGlobals {
+    pub fn new(renames: RenameMap) -> Self {
+        Self { renames }
+    }
+
+    /// Parse a mapping with one `old = new` pair of names per
+    /// line, as per [RenameMap::from_pairs].
+    pub fn parse(mapping: &str) -> Result<Self> {
+        RenameMap::from_pairs(mapping).map(Self::new)
+    }
+
+    /// Rename the global, unless a local of the same name is in scope.
+    fn rename(&self, ident: &mut Ident, locals: &[String]) -> Result<()> {
+        if locals.iter().any(|local| local == ident.as_str()) {
+            return Ok(());
+        }
+        if let Some(new) = self.renames.global(ident.as_str()) {
+            if locals.iter().any(|local| local == new) {
+                return Error::new_rename_map(format!(
+                    "renaming global `{ident}` to `{new}` would refer to a local"
+                ))
+                .into();
+            }
+            *ident = Ident::new(new);
+        }
+        Ok(())
+    }
+
+    /// Rename the globals in the block, declaring its locals in
+    /// the current function's scope until the block ends.
+    ///
+    /// `outer` holds the locals of the enclosing function, which upvalues refer to.
+    fn block(&self, block: &mut Block, locals: &mut Vec<String>, outer: &[String]) -> Result<()> {
+        let depth = locals.len();
+        for node in &mut block.nodes {
+            match node {
+                Node::Stmt(stmt) => self.stmt(stmt, locals, outer)?,
+                Node::Expr(expr) => self.expr(expr, locals, outer)?,
+                Node::Partial(_) => {}
+            }
+        }
+        locals.truncate(depth);
+        Ok(())
+    }
+
+    fn stmt(&self, stmt: &mut Stmt, locals: &mut Vec<String>, outer: &[String]) -> Result<()> {
+        match stmt {
+            Stmt::LocalVar(local_var) => {
+                self.exprs(&mut local_var.rhs, locals, outer)?;
+                locals.extend(local_var.names.iter().map(|name| name.to_string()));
+            }
+            Stmt::Assign(assign) => {
+                for target in &mut assign.targets {
+                    self.rename(target, locals)?;
+                }
+                self.exprs(&mut assign.rhs, locals, outer)?;
+            }
+            Stmt::Call(call) => {
+                self.expr(&mut call.name, locals, outer)?;
+                self.exprs(&mut call.args, locals, outer)?;
+            }
+            Stmt::Block(block) => self.block(block, locals, outer)?,
+            Stmt::If(if_block) => {
+                self.expr(&mut if_block.head, locals, outer)?;
+                self.block(&mut if_block.then, locals, outer)?;
+                if let Some(else_) = &mut if_block.else_ {
+                    self.block(else_, locals, outer)?;
+                }
+            }
+            Stmt::While(while_block) => {
+                self.expr(&mut while_block.head, locals, outer)?;
+                self.block(&mut while_block.body, locals, outer)?;
+            }
+            Stmt::Repeat(repeat_block) => {
+                self.block(&mut repeat_block.body, locals, outer)?;
+                self.expr(&mut repeat_block.cond, locals, outer)?;
+            }
+            Stmt::Return(ret) => self.exprs(&mut ret.values, locals, outer)?,
+            Stmt::Goto(goto) => {
+                if let Some(cond) = &mut goto.cond {
+                    self.expr(cond, locals, outer)?;
+                }
+            }
+            Stmt::Break | Stmt::Label(_) | Stmt::Failed(_) | Stmt::Custom(_) | Stmt::Comment(_) => {
+            }
+        }
+        Ok(())
+    }
+
+    fn exprs(&self, exprs: &mut [Expr], locals: &mut Vec<String>, outer: &[String]) -> Result<()> {
+        exprs
+            .iter_mut()
+            .try_for_each(|expr| self.expr(expr, locals, outer))
+    }
+
+    fn expr(&self, expr: &mut Expr, locals: &mut Vec<String>, outer: &[String]) -> Result<()> {
+        match expr {
+            Expr::Access(ident) => self.rename(ident, locals)?,
+            // Upvalues may capture a global of the enclosing function, like `%print`.
+            Expr::Upvalue(ident) => self.rename(ident, outer)?,
+            Expr::Literal(_) | Expr::Custom(_) => {}
+            Expr::Binary(bin_expr) => {
+                self.expr(&mut bin_expr.lhs, locals, outer)?;
+                self.expr(&mut bin_expr.rhs, locals, outer)?;
+            }
+            Expr::Unary(unary_expr) => self.expr(&mut unary_expr.rhs, locals, outer)?,
+            Expr::Call(call) => {
+                self.expr(&mut call.name, locals, outer)?;
+                self.exprs(&mut call.args, locals, outer)?;
+            }
+            Expr::Function(function) => {
+                // Nested functions only see their own locals, and the
+                // enclosing function's through upvalues.
+                let mut params: Vec<_> = function.params.iter().map(Ident::to_string).collect();
+                if function.is_vararg {
+                    params.push("arg".to_string());
+                }
+                self.block(&mut function.body, &mut params, locals)?;
+            }
+            Expr::Table(table) => {
+                self.exprs(&mut table.items, locals, outer)?;
+                for field in &mut table.fields {
+                    self.expr(&mut field.key, locals, outer)?;
+                    self.expr(&mut field.value, locals, outer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Pass for RenameGlobals {
+    fn name(&self) -> &str {
+        "rename-globals"
+    }
+
+    fn run(&mut self, syntax: &mut Syntax) -> Result<()> {
+        self.block(&mut syntax.root, &mut vec![], &[])
+    }
+}
+
+impl Pass for SimplifyConditions {
+    fn name(&self) -> &str {
+        "simplify-conditions"
+    }
+
+    fn run(&mut self, syntax: &mut Syntax) -> Result<()> {
+        simplify_block(&mut syntax.root);
+        Ok(())
+    }
+}
+
+fn simplify_block(block: &mut Block) {
+    for node in &mut block.nodes {
+        match node {
+            Node::Stmt(stmt) => simplify_stmt(stmt),
+            Node::Expr(expr) => simplify_expr(expr),
+            Node::Partial(_) => {}
+        }
+    }
+}
+
+fn simplify_stmt(stmt: &mut Stmt) {
+    match stmt {
+        Stmt::LocalVar(local_var) => local_var.rhs.iter_mut().for_each(simplify_expr),
+        Stmt::Assign(assign) => assign.rhs.iter_mut().for_each(simplify_expr),
+        Stmt::Call(call) => {
+            simplify_expr(&mut call.name);
+            call.args.iter_mut().for_each(simplify_expr);
+        }
+        Stmt::Block(block) => simplify_block(block),
+        Stmt::If(if_block) => {
+            simplify_cond(&mut if_block.head);
+            simplify_block(&mut if_block.then);
+            if let Some(else_) = &mut if_block.else_ {
+                simplify_block(else_);
+            }
+        }
+        Stmt::While(while_block) => {
+            simplify_cond(&mut while_block.head);
+            simplify_block(&mut while_block.body);
+        }
+        Stmt::Repeat(repeat_block) => {
+            simplify_block(&mut repeat_block.body);
+            simplify_cond(&mut repeat_block.cond);
+        }
+        Stmt::Return(ret) => ret.values.iter_mut().for_each(simplify_expr),
+        Stmt::Goto(goto) => {
+            if let Some(cond) = &mut goto.cond {
+                simplify_cond(cond);
+            }
+        }
+        Stmt::Break | Stmt::Label(_) | Stmt::Failed(_) | Stmt::Custom(_) | Stmt::Comment(_) => {}
+    }
+}
+
+/// Remove double negations of the tested value, since only
+/// whether the value is `nil` matters, not the value itself.
+fn simplify_cond(cond: &mut Expr) {
+    while let Some(value) = take_double_negation(cond) {
+        *cond = value;
+    }
+    match cond {
+        // The operands of `and`, `or` and `not` are only tested too.
+        Expr::Binary(bin_expr) if matches!(bin_expr.op, BinOp::And | BinOp::Or) => {
+            simplify_cond(&mut bin_expr.lhs);
+            simplify_cond(&mut bin_expr.rhs);
+        }
+        Expr::Unary(unary_expr) if matches!(unary_expr.op, UnaryOp::Not) => {
+            simplify_cond(&mut unary_expr.rhs)
+        }
+        expr => simplify_expr(expr),
+    }
+}
+
+/// Take the value out of `not (not value)`.
+fn take_double_negation(expr: &mut Expr) -> Option<Expr> {
+    let Expr::Unary(outer) = expr else {
+        return None;
+    };
+    let Expr::Unary(inner) = &mut outer.rhs else {
+        return None;
+    };
+    if !matches!((outer.op, inner.op), (UnaryOp::Not, UnaryOp::Not)) {
+        return None;
+    }
+    Some(std::mem::replace(
+        &mut inner.rhs,
+        Expr::Literal(Lit::Int(0)),
+    ))
+}
+
+/// Simplify the conditions in the functions nested in the expression.
+///
+/// Outside of conditions `not` is kept, since it turns values into `1`.
+fn simplify_expr(expr: &mut Expr) {
+    match expr {
+        Expr::Access(_) | Expr::Upvalue(_) | Expr::Literal(_) | Expr::Custom(_) => {}
+        Expr::Binary(bin_expr) => {
+            simplify_expr(&mut bin_expr.lhs);
+            simplify_expr(&mut bin_expr.rhs);
+        }
+        Expr::Unary(unary_expr) => simplify_expr(&mut unary_expr.rhs),
+        Expr::Call(call) => {
+            simplify_expr(&mut call.name);
+            call.args.iter_mut().for_each(simplify_expr);
+        }
+        Expr::Function(function) => simplify_block(&mut function.body),
+        Expr::Table(table) => {
+            table.items.iter_mut().for_each(simplify_expr);
+            for field in &mut table.fields {
+                simplify_expr(&mut field.key);
+                simplify_expr(&mut field.value);
+            }
+        }
+    }
+}
+
+impl Pass for FlattenConcat {
+    fn name(&self) -> &str {
+        "flatten-concat"
+    }
+
+    fn run(&mut self, syntax: &mut Syntax) -> Result<()> {
+        flatten_block(&mut syntax.root);
+        Ok(())
+    }
+}
+
+fn flatten_block(block: &mut Block) {
+    for node in &mut block.nodes {
+        match node {
+            Node::Stmt(stmt) => flatten_stmt(stmt),
+            Node::Expr(expr) => flatten_expr(expr),
+            Node::Partial(_) => {}
+        }
+    }
+}
+
+fn flatten_stmt(stmt: &mut Stmt) {
+    match stmt {
+        Stmt::LocalVar(local_var) => local_var.rhs.iter_mut().for_each(flatten_expr),
+        Stmt::Assign(assign) => assign.rhs.iter_mut().for_each(flatten_expr),
+        Stmt::Call(call) => {
+            flatten_expr(&mut call.name);
+            call.args.iter_mut().for_each(flatten_expr);
+        }
+        Stmt::Block(block) => flatten_block(block),
+        Stmt::If(if_block) => {
+            flatten_expr(&mut if_block.head);
+            flatten_block(&mut if_block.then);
+            if let Some(else_) = &mut if_block.else_ {
+                flatten_block(else_);
+            }
+        }
+        Stmt::While(while_block) => {
+            flatten_expr(&mut while_block.head);
+            flatten_block(&mut while_block.body);
+        }
+        Stmt::Repeat(repeat_block) => {
+            flatten_block(&mut repeat_block.body);
+            flatten_expr(&mut repeat_block.cond);
+        }
+        Stmt::Return(ret) => ret.values.iter_mut().for_each(flatten_expr),
+        Stmt::Goto(goto) => {
+            if let Some(cond) = &mut goto.cond {
+                flatten_expr(cond);
+            }
+        }
+        Stmt::Break | Stmt::Label(_) | Stmt::Failed(_) | Stmt::Custom(_) | Stmt::Comment(_) => {}
+    }
+}
+
+fn flatten_expr(expr: &mut Expr) {
+    match expr {
+        Expr::Access(_) | Expr::Upvalue(_) | Expr::Literal(_) | Expr::Custom(_) => {}
+        Expr::Binary(bin_expr) => {
+            flatten_expr(&mut bin_expr.lhs);
+            flatten_expr(&mut bin_expr.rhs);
+        }
+        Expr::Unary(unary_expr) => flatten_expr(&mut unary_expr.rhs),
+        Expr::Call(call) => {
+            flatten_expr(&mut call.name);
+            call.args.iter_mut().for_each(flatten_expr);
+        }
+        Expr::Function(function) => flatten_block(&mut function.body),
+        Expr::Table(table) => {
+            table.items.iter_mut().for_each(flatten_expr);
+            for field in &mut table.fields {
+                flatten_expr(&mut field.key);
+                flatten_expr(&mut field.value);
+            }
+        }
+    }
+    rotate_concat(expr);
+}
+
+/// Rotate `(a .. b) .. c` into `a .. (b .. c)`, which is written without
+/// parentheses, until the left operand isn't a concatenation.
+///
+/// The operands are expected to be flattened already.
+fn rotate_concat(expr: &mut Expr) {
+    let Expr::Binary(outer) = expr else {
+        return;
+    };
+    if !matches!(outer.op, BinOp::Concat) {
+        return;
+    }
+    while let Expr::Binary(inner) = &mut outer.lhs {
+        if !matches!(inner.op, BinOp::Concat) {
+            break;
+        }
+        let a = std::mem::replace(&mut inner.lhs, Expr::Literal(Lit::Int(0)));
+        let b = std::mem::replace(&mut inner.rhs, Expr::Literal(Lit::Int(0)));
+        let c = std::mem::replace(&mut outer.rhs, Expr::Literal(Lit::Int(0)));
+        outer.lhs = a;
+        outer.rhs = Expr::Binary(Box::new(BinExpr {
+            op: BinOp::Concat,
+            lhs: b,
+            rhs: c,
+        }));
+        // `b` may be a chain of its own, like in `(a .. (b .. c)) .. d`.
+        rotate_concat(&mut outer.rhs);
+    }
+}
@@ -0,0 +1,178 @@
+//! Addressing nested functions by the index of each function on the way down.
+//!
+//! Paths are written from the main function, like `main.2.0` for the first
+//! function nested in the third function of the main function. Slashes are
+//! accepted as separators too, like `main/2/0`, and `main` may be left out.
+use std::fmt::{self, Formatter};
+use std::ops::Deref;
+use std::str::FromStr;
+
+use super::{Chunk, Constant, PooledConstant, Proto};
+use crate::errors::{Error, Result};
+
+/// Path of a function in a chunk, as per [Proto::nested].
+///
+/// The empty path is the main function.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProtoPath(Vec<usize>);
+
+// ============================================================================
+
+impl ProtoPath {
+    /// Path of the main function.
+    pub fn main() -> Self {
+        Self::default()
+    }
+
+    /// Path of the function nested in this one at the index.
+    pub fn join(&self, index: usize) -> Self {
+        let mut path = self.0.clone();
+        path.push(index);
+        Self(path)
+    }
+
+    /// Path of the function this one is nested in, unless it's the main function.
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.0.split_last()?;
+        Some(Self(parent.to_vec()))
+    }
+
+    pub fn is_main(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Levels of nesting, zero for the main function.
+    pub fn depth(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl Deref for ProtoPath {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl From<Vec<usize>> for ProtoPath {
+    fn from(path: Vec<usize>) -> Self {
+        Self(path)
+    }
+}
+
+impl From<&[usize]> for ProtoPath {
+    fn from(path: &[usize]) -> Self {
+        Self(path.to_vec())
+    }
+}
+
+impl From<ProtoPath> for Vec<usize> {
+    fn from(path: ProtoPath) -> Self {
+        path.0
+    }
+}
+
+impl FromStr for ProtoPath {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let err = || Error::new_parser(format!("bad function path `{text}`"));
+        let indices = match text.strip_prefix("main") {
+            Some("") => return Ok(Self::main()),
+            Some(rest) => rest.strip_prefix(['.', '/']).ok_or_else(err)?,
+            // Without `main`, the path starts with an index.
+            None => text,
+        };
+        indices
+            .split(['.', '/'])
+            .map(|index| index.parse().map_err(|_| err()))
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for ProtoPath {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "main")?;
+        for index in &self.0 {
+            write!(f, ".{index}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Proto {
+    /// The function and every function nested in it, depth first,
+    /// with their paths relative to this function.
+    pub fn iter_protos(&self) -> impl Iterator<Item = (ProtoPath, &Proto)> + '_ {
+        let mut stack = vec![(ProtoPath::main(), self)];
+        std::iter::from_fn(move || {
+            let (path, proto) = stack.pop()?;
+            // Pushed in reverse, so they're visited in order.
+            for (index, nested) in proto.protos().iter().enumerate().rev() {
+                stack.push((path.join(index), nested));
+            }
+            Some((path, proto))
+        })
+    }
+
+    /// String and number constants of the function and every function
+    /// nested in it, depth first, with the strings of each function
+    /// before its numbers.
+    pub fn constants(&self) -> impl Iterator<Item = PooledConstant<'_>> + '_ {
+        self.iter_protos().flat_map(|(path, proto)| {
+            let (string_lines, number_lines) = proto.first_uses();
+            let strings = proto
+                .strings()
+                .iter()
+                .map(Constant::String)
+                .zip(string_lines);
+            let numbers = (proto.numbers().iter().copied().map(Constant::Number)).zip(number_lines);
+            strings
+                .enumerate()
+                .chain(numbers.enumerate())
+                .map(move |(index, (constant, line))| PooledConstant {
+                    path: path.clone(),
+                    index,
+                    constant,
+                    line,
+                })
+        })
+    }
+
+    /// Source line of the first instruction using each string
+    /// and each number constant of the function.
+    fn first_uses(&self) -> (Vec<Option<u32>>, Vec<Option<u32>>) {
+        let mut strings = vec![None; self.strings().len()];
+        let mut numbers = vec![None; self.numbers().len()];
+        for (pc, instr) in self.instrs.iter().enumerate() {
+            let lines = match instr.constant(self) {
+                Some(Constant::String(_)) => &mut strings,
+                Some(Constant::Number(_)) => &mut numbers,
+                _ => continue,
+            };
+            if let Some(line @ None) = lines.get_mut(instr.u as usize) {
+                *line = self.line_at(pc);
+            }
+        }
+        (strings, numbers)
+    }
+}
+
+impl Chunk {
+    /// Function in the chunk at the path.
+    pub fn get_proto(&self, path: &ProtoPath) -> Option<&Proto> {
+        self.main.nested(path)
+    }
+
+    pub fn get_proto_mut(&mut self, path: &ProtoPath) -> Option<&mut Proto> {
+        self.main.nested_mut(path)
+    }
+
+    /// String and number constants of every function in the chunk,
+    /// as per [Proto::constants].
+    pub fn constants(&self) -> impl Iterator<Item = PooledConstant<'_>> + '_ {
+        self.main.constants()
+    }
+}
@@ -0,0 +1,143 @@
+//! Patterns over instruction sequences, for recognizing idioms.
+//!
+//! Some chunks are compiled from code that a generic decompiler can't
+//! recover the way it was written, like macros expanded by an engine's
+//! build tools, or helpers the compiler inlined. An [Idiom] recognizes
+//! such a sequence of instructions by a [Pattern], and gives the source
+//! to write in its place, before the generic parser sees them.
+//!
+//! ```text
+//! GETGLOBAL, PUSH*+, CALL
+//! ```
+use super::ast::Custom;
+use super::{Instr, Instruction, Opcode, Proto};
+use crate::errors::{Error, Result};
+
+/// Sequence of opcodes to look for, parsed from a small pattern language.
+///
+/// Elements are separated by spaces or commas. Each element is an opcode
+/// name as printed by `luac -l`, like `GETGLOBAL`, a name ending in `*`
+/// to match every opcode starting with it, like `PUSH*`, or `_` to match
+/// any opcode. An element followed by `+` matches one or more instructions,
+/// and followed by `?` matches one or none.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    elements: Vec<Element>,
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    opcodes: Vec<Opcode>,
+    repeat: Repeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repeat {
+    One,
+    OneOrMore,
+    Optional,
+}
+
+/// Recognizer of a sequence of instructions that the generic parser
+/// wouldn't decompile the way it was written.
+///
+/// Registered with [super::Parser::with_idiom].
+pub trait Idiom {
+    /// Instructions the idiom is made of.
+    fn pattern(&self) -> &Pattern;
+
+    /// Build the statement or expression to write in place of
+    /// the matched instructions, or `None` when they aren't the
+    /// idiom after all, leaving them to the generic parser.
+    fn recognize(&self, proto: &Proto, instructions: &[Instruction]) -> Option<Recognized>;
+}
+
+/// Source built by an [Idiom].
+///
+/// The instructions must leave the operand stack as they found it for
+/// a statement, and push exactly one value for an expression, otherwise
+/// they're left to the generic parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recognized {
+    Stmt(Custom),
+    Expr(Custom),
+}
+
+// ============================================================================
+
+impl Pattern {
+    /// Parse a pattern, like `GETGLOBAL, PUSH*+, CALL`.
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let elements = pattern
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .map(Element::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if elements.is_empty() {
+            return Error::new_parser("empty instruction pattern").into();
+        }
+        Ok(Self { elements })
+    }
+
+    /// Number of instructions matched from the offset, if any match.
+    ///
+    /// Repeated elements match as many instructions as they can,
+    /// giving some back when the rest of the pattern needs them.
+    pub fn match_at(&self, instrs: &[Instr], offset: usize) -> Option<usize> {
+        let instrs = instrs.get(offset..)?;
+        match_elements(&self.elements, instrs, 0).filter(|len| *len > 0)
+    }
+}
+
+fn match_elements(elements: &[Element], instrs: &[Instr], pos: usize) -> Option<usize> {
+    let Some((element, rest)) = elements.split_first() else {
+        return Some(pos);
+    };
+
+    // Longest run of instructions the element could match from here.
+    let run = instrs[pos..]
+        .iter()
+        .take_while(|instr| element.opcodes.contains(&instr.opcode))
+        .count();
+    let (min, max) = match element.repeat {
+        Repeat::One => (1, 1),
+        Repeat::OneOrMore => (1, run),
+        Repeat::Optional => (0, 1),
+    };
+
+    (min..=max.min(run))
+        .rev()
+        .find_map(|count| match_elements(rest, instrs, pos + count))
+}
+
+impl Element {
+    fn parse(token: &str) -> Result<Self> {
+        let (name, repeat) = match token.as_bytes().last() {
+            Some(b'+') => (&token[..token.len() - 1], Repeat::OneOrMore),
+            Some(b'?') => (&token[..token.len() - 1], Repeat::Optional),
+            _ => (token, Repeat::One),
+        };
+
+        let name = name.to_ascii_uppercase();
+        let opcodes: Vec<_> = all_opcodes()
+            .filter(|opcode| match name.as_str() {
+                "_" => true,
+                _ => match name.strip_suffix('*') {
+                    Some(prefix) => opcode.name().starts_with(prefix),
+                    None => opcode.name() == name,
+                },
+            })
+            .collect();
+        if opcodes.is_empty() {
+            return Error::new_parser(format!("no opcode matches `{token}` in pattern")).into();
+        }
+
+        Ok(Self { opcodes, repeat })
+    }
+}
+
+fn all_opcodes() -> impl Iterator<Item = Opcode> {
+    (0..=Opcode::Closure as u32)
+        .filter_map(|value| Opcode::try_from(value).ok())
+        .chain([Opcode::Unknown])
+}
@@ -0,0 +1,495 @@
+//! Names given by an analyst to globals and locals, for deobfuscation.
+//!
+//! Obfuscated scripts use meaningless names, and stripped ones have none
+//! for their locals. A [RenameMap] gives them names as the syntax tree is
+//! built, so a decompilation can be annotated bit by bit, decompiling
+//! again after each name is worked out.
+//!
+//! Maps are read from JSON:
+//!
+//! ```json
+//! {
+//!     "globals": { "a1": "player" },
+//!     "locals": { "main.0": { "0": "self", "1": "damage" } }
+//! }
+//! ```
+//!
+//! Or from TOML:
+//!
+//! ```toml
+//! [globals]
+//! a1 = "player"
+//!
+//! [locals."main.0"]
+//! 0 = "self"
+//! 1 = "damage"
+//! ```
+//!
+//! Or, for globals only, from one `old = new` pair of names per line:
+//!
+//! ```text
+//! -- from the manual
+//! a1 = player
+//! ```
+//!
+//! Locals are keyed by the path of their function, as per [super::Proto::nested],
+//! and their stack slot. Each local in the slot gets the name.
+//!
+//! Any JSON document is read, but the values in the map must be strings. Only
+//! the part of TOML that maps are written in is read: `[globals]` and
+//! `[locals."path"]` tables of bare or quoted keys, with basic string values
+//! and `#` comments. Names have no need for escapes, so strings are read
+//! without them, and other values, like literal strings, are rejected.
+use std::collections::HashMap;
+
+use super::ast::is_name;
+use super::ProtoPath;
+use crate::errors::{Error, Result};
+
+/// Names to use instead of the ones in the chunk, or the made up ones.
+#[derive(Debug, Clone, Default)]
+pub struct RenameMap {
+    globals: HashMap<String, String>,
+    locals: HashMap<(Vec<usize>, u32), String>,
+}
+
+/// Value in a JSON rename map, which only holds strings in objects.
+enum Json {
+    String(String),
+    Object(Vec<(String, Json)>),
+    /// Numbers, booleans, `null` and arrays, which are read to report
+    /// what was found, but have no place in a map.
+    Other(&'static str),
+}
+
+struct JsonReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+// ============================================================================
+
+impl RenameMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rename the global everywhere it's used.
+    pub fn with_global(mut self, name: impl ToString, new_name: impl ToString) -> Self {
+        self.globals.insert(name.to_string(), new_name.to_string());
+        self
+    }
+
+    /// Name the locals in the stack slot of the function at the path.
+    pub fn with_local(mut self, path: &[usize], stack_offset: u32, name: impl ToString) -> Self {
+        self.locals
+            .insert((path.to_vec(), stack_offset), name.to_string());
+        self
+    }
+
+    /// New name of the global, if it's renamed.
+    pub fn global(&self, name: &str) -> Option<&str> {
+        self.globals.get(name).map(String::as_str)
+    }
+
+    /// Name of the locals in the stack slot of the function at the path, if they're named.
+    pub fn local(&self, path: &[usize], stack_offset: u32) -> Option<&str> {
+        self.locals
+            .get(&(path.to_vec(), stack_offset))
+            .map(String::as_str)
+    }
+
+    /// Every name given by the map, which made up names must not clash with.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.globals
+            .values()
+            .chain(self.locals.values())
+            .map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.globals.is_empty() && self.locals.is_empty()
+    }
+
+    /// Add the names of another map, which replace the
+    /// names of the same globals and stack slots.
+    pub fn merge(mut self, other: RenameMap) -> Self {
+        self.globals.extend(other.globals);
+        self.locals.extend(other.locals);
+        self
+    }
+
+    /// Read a map of globals with one `old = new` pair of names per line.
+    ///
+    /// Blank lines, and lines starting with `--`, are skipped.
+    pub fn from_pairs(text: &str) -> Result<Self> {
+        let mut map = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("--") {
+                continue;
+            }
+            let pair = line
+                .split_once('=')
+                .map(|(old, new)| (old.trim(), new.trim()))
+                .filter(|(old, new)| is_name(old) && is_name(new));
+            match pair {
+                Some((old, new)) => map.insert_global(old.to_string(), new.to_string())?,
+                None => {
+                    return Err(Error::new_rename_map(format!(
+                        "line {}: expected `old = new` names, found `{line}`",
+                        index + 1
+                    )))
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Read a map from JSON, with `globals` and `locals` objects.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let mut reader = JsonReader { text, pos: 0 };
+        let root = reader.value()?;
+        reader.skip_whitespace();
+        if reader.pos != text.len() {
+            return Err(reader.error("trailing characters"));
+        }
+
+        let mut map = Self::new();
+        for (section, value) in expect_object(root, "the map")? {
+            match section.as_str() {
+                "globals" => {
+                    for (name, new_name) in expect_object(value, "globals")? {
+                        let new_name = expect_string(new_name, &name)?;
+                        map.insert_global(name, new_name)?;
+                    }
+                }
+                "locals" => {
+                    for (path, slots) in expect_object(value, "locals")? {
+                        for (slot, name) in expect_object(slots, &path)? {
+                            let name = expect_string(name, &slot)?;
+                            map.insert_local(&path, &slot, name)?;
+                        }
+                    }
+                }
+                _ => {
+                    return Err(Error::new_rename_map(format!(
+                        "unknown section `{section}`"
+                    )))
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Read a map from TOML, with a `[globals]` table, and a
+    /// `[locals."path"]` table for each function.
+    ///
+    /// Only tables of basic strings without escapes are supported,
+    /// see the [module documentation](self).
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let mut map = Self::new();
+        // Section of the keys that follow: `None` for globals, or the function path.
+        let mut section: Option<Option<String>> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let err_line =
+                |message: &str| Error::new_rename_map(format!("line {}: {message}", index + 1));
+            let line = strip_toml_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let header = header.trim();
+                section = if header == "globals" {
+                    Some(None)
+                } else if let Some(path) = header.strip_prefix("locals.") {
+                    Some(Some(
+                        unquote_toml(path.trim()).ok_or_else(|| err_line("bad path"))?,
+                    ))
+                } else {
+                    return Err(err_line(&format!("unknown section `{header}`")));
+                };
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err_line("expected `key = \"value\"`"))?;
+            let key = unquote_toml(key.trim()).ok_or_else(|| err_line("bad key"))?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .filter(|v| !v.contains(['"', '\\']))
+                .ok_or_else(|| err_line("expected a basic string value without escapes"))?;
+
+            match &section {
+                Some(None) => map.insert_global(key, value.to_string())?,
+                Some(Some(path)) => map.insert_local(path, &key, value.to_string())?,
+                None => return Err(err_line("key outside of a section")),
+            }
+        }
+        Ok(map)
+    }
+
+    fn insert_global(&mut self, name: String, new_name: String) -> Result<()> {
+        check_name(&new_name)?;
+        self.globals.insert(name, new_name);
+        Ok(())
+    }
+
+    fn insert_local(&mut self, path: &str, slot: &str, name: String) -> Result<()> {
+        check_name(&name)?;
+        let path = parse_path(path)?;
+        let slot = slot
+            .parse()
+            .map_err(|_| Error::new_rename_map(format!("bad stack slot `{slot}`")))?;
+        self.locals.insert((path, slot), name);
+        Ok(())
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if is_name(name) {
+        Ok(())
+    } else {
+        Err(Error::new_rename_map(format!(
+            "`{name}` is not a valid name"
+        )))
+    }
+}
+
+/// Parse a function path, like `main.0.1`, where `main` is optional.
+fn parse_path(text: &str) -> Result<Vec<usize>> {
+    text.parse::<ProtoPath>()
+        .map(Vec::from)
+        .map_err(|_| Error::new_rename_map(format!("bad function path `{text}`")))
+}
+
+/// The line up to a `#` comment outside of a quoted string.
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (offset, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..offset],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Bare or quoted TOML key.
+fn unquote_toml(key: &str) -> Option<String> {
+    match key.strip_prefix('"') {
+        Some(rest) => rest
+            .strip_suffix('"')
+            .filter(|key| !key.contains('"'))
+            .map(str::to_string),
+        None => key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            .then(|| key.to_string()),
+    }
+}
+
+fn expect_object(value: Json, what: &str) -> Result<Vec<(String, Json)>> {
+    match value {
+        Json::Object(members) => Ok(members),
+        value => Err(Error::new_rename_map(format!(
+            "expected an object for {what}, found {}",
+            value.kind()
+        ))),
+    }
+}
+
+fn expect_string(value: Json, what: &str) -> Result<String> {
+    match value {
+        Json::String(string) => Ok(string),
+        value => Err(Error::new_rename_map(format!(
+            "expected a string for `{what}`, found {}",
+            value.kind()
+        ))),
+    }
+}
+
+impl Json {
+    /// Kind of value, for error messages.
+    fn kind(&self) -> &'static str {
+        match self {
+            Json::String(_) => "a string",
+            Json::Object(_) => "an object",
+            Json::Other(kind) => kind,
+        }
+    }
+}
+
+impl JsonReader<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::new_rename_map(format!("{message} at byte {}", self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.pos..].chars().next()
+    }
+
+    /// Next character, without skipping whitespace.
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.text[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{c}`")))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(),
+            Some('-' | '0'..='9') => self.number(),
+            _ => {
+                for (literal, kind) in [
+                    ("true", "a boolean"),
+                    ("false", "a boolean"),
+                    ("null", "null"),
+                ] {
+                    if self.text[self.pos..].starts_with(literal) {
+                        self.pos += literal.len();
+                        return Ok(Json::Other(kind));
+                    }
+                }
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json> {
+        self.expect('{')?;
+        let mut members = vec![];
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    /// Read an array, only to skip over it.
+    fn array(&mut self) -> Result<Json> {
+        self.expect('[')?;
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Other("an array"));
+        }
+        loop {
+            self.value()?;
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Other("an array"));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    /// Read a number, only to skip over it.
+    fn number(&mut self) -> Result<Json> {
+        let rest = &self.text[self.pos..];
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        if rest[..len].parse::<f64>().is_err() {
+            return Err(self.error("bad number"));
+        }
+        self.pos += len;
+        Ok(Json::Other("a number"))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        while let Some(c) = self.next_char() {
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = match self.next_char() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("bad escape")),
+                    };
+                    string.push(escaped);
+                }
+                c if c.is_control() => return Err(self.error("control character in string")),
+                c => string.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    /// Character of a `\u` escape, after the `u`, joining the
+    /// surrogate pairs that encode characters beyond the BMP.
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xd800..=0xdbff => {
+                if !self.text[self.pos..].starts_with("\\u") {
+                    return Err(self.error("unpaired surrogate in unicode escape"));
+                }
+                self.pos += 2;
+                let low = self.hex4()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(self.error("unpaired surrogate in unicode escape"));
+                }
+                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+            }
+            0xdc00..=0xdfff => return Err(self.error("unpaired surrogate in unicode escape")),
+            code => code,
+        };
+        char::from_u32(code).ok_or_else(|| self.error("bad unicode escape"))
+    }
+
+    /// Four hex digits of a `\u` escape.
+    fn hex4(&mut self) -> Result<u32> {
+        let hex = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("bad unicode escape"))?;
+        self.pos += 4;
+        u32::from_str_radix(hex, 16).map_err(|_| self.error("bad unicode escape"))
+    }
+}
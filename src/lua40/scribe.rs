@@ -1,23 +1,226 @@
 //! Code generator for Lua syntax.
+use std::collections::HashSet;
 use std::fmt::Write as FmtWrite;
+use std::io;
 
 use super::ast::{
-    Assign, BinExpr, BinOp, Block, Call, CondExpr, CondOp, Expr, Ident, IfBlock, Lit, LocalVar,
-    Node, Stmt, Syntax,
+    Assign, BinExpr, Block, Call, Comment, Expr, Failed, Field, Function, Goto, Ident, IfBlock,
+    Lit, LocalVar, Node, RepeatBlock, Return, Span, Stmt, Syntax, Table, UnaryExpr, UnaryOp,
+    WhileBlock,
 };
-use crate::errors::Result;
+use super::ProtoPath;
+use crate::errors::{Error, Result};
+use crate::style::ScribeConfig;
+use crate::writer::IoFmt;
 
 pub struct Scribe {
+    config: ScribeConfig,
     level: u32,
+    /// Hoist local variable declarations to the top of their block.
+    group_locals: bool,
+    /// Annotate statements with the instructions they were decoded from.
+    annotate: bool,
+    /// Separate statements that weren't on adjacent source lines with a blank line.
+    preserve_lines: bool,
+    /// Mark statements that don't follow on from the previous source line with their line.
+    line_markers: bool,
+    /// Normalize whitespace, and leave out what depends on where code fell in the chunk.
+    canonical: bool,
+    /// Function the block being written was decompiled from.
+    path: ProtoPath,
+    /// Spans of the statements written so far, when marking where each starts.
+    marks: Option<Vec<(ProtoPath, Span)>>,
+}
+
+/// Delimits the index of a span in [Scribe::marks] in marked source.
+///
+/// Control characters are always escaped in strings, so they
+/// can't be confused with anything the source itself contains.
+const MARK_START: char = '\u{1}';
+const MARK_END: char = '\u{2}';
+
+/// Line of source, with the function and span of the statement starting on it.
+pub(super) type SpannedLine = (String, Option<(ProtoPath, Span)>);
+
+impl Default for Scribe {
+    fn default() -> Self {
+        Self::new(ScribeConfig::default())
+    }
 }
 
 impl Scribe {
-    pub fn new() -> Self {
-        Self { level: 0 }
+    pub fn new(config: ScribeConfig) -> Self {
+        Self {
+            config,
+            level: 0,
+            group_locals: false,
+            annotate: false,
+            preserve_lines: false,
+            line_markers: false,
+            canonical: false,
+            path: ProtoPath::main(),
+            marks: None,
+        }
+    }
+
+    /// Follow each statement with a comment giving the range of
+    /// instructions it was decoded from, and their source line.
+    ///
+    /// ```lua
+    /// local a = 1  -- [3-4] line 12
+    /// ```
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    /// Emit all local variable declarations grouped at the top of their
+    /// block, with assignments left in their place.
+    ///
+    /// Blocks where the reordering would change which variable a name
+    /// refers to are emitted as is.
+    pub fn group_locals(mut self, group_locals: bool) -> Self {
+        self.group_locals = group_locals;
+        self
+    }
+
+    /// Leave a blank line between statements that weren't on adjacent
+    /// lines in the original source, so the output keeps its paragraphs.
+    ///
+    /// Needs the line information from the chunk's debug information.
+    pub fn preserve_lines(mut self, preserve_lines: bool) -> Self {
+        self.preserve_lines = preserve_lines;
+        self
+    }
+
+    /// Precede statements with a comment giving their source line,
+    /// where it doesn't follow on from the previous statement.
+    ///
+    /// ```lua
+    /// -- line 12
+    /// ```
+    pub fn line_markers(mut self, line_markers: bool) -> Self {
+        self.line_markers = line_markers;
+        self
+    }
+
+    /// Write source that's the same for the same syntax tree, with
+    /// normalized whitespace, for diffing decompilations of different
+    /// versions of a chunk.
+    ///
+    /// Trailing whitespace is removed, runs of blank lines are collapsed,
+    /// and the source ends with a single line break. Annotations, line
+    /// markers and preserved blank lines are left out, since they change
+    /// whenever code moves around in the chunk, and strings are quoted.
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
     }
 
     pub fn fmt_syntax(&mut self, f: &mut impl FmtWrite, syntax: &Syntax) -> Result<()> {
-        self.fmt_block(f, &syntax.root)
+        self.path = syntax.path.clone();
+        if !self.canonical {
+            return self.fmt_block(f, &syntax.root);
+        }
+
+        // Normalizing whitespace would change the text of long strings.
+        let options = (
+            self.annotate,
+            self.preserve_lines,
+            self.line_markers,
+            self.config.long_strings,
+        );
+        self.annotate = false;
+        self.preserve_lines = false;
+        self.line_markers = false;
+        self.config.long_strings = false;
+        let mut buf = String::new();
+        let result = self.fmt_block(&mut buf, &syntax.root);
+        (
+            self.annotate,
+            self.preserve_lines,
+            self.line_markers,
+            self.config.long_strings,
+        ) = options;
+        result?;
+        self.fmt_normalized(f, &buf)
+    }
+
+    /// Write the source, split into lines, with the span of each
+    /// statement on the line it starts on.
+    pub(super) fn fmt_spanned_lines(&mut self, syntax: &Syntax) -> Result<Vec<SpannedLine>> {
+        self.marks = Some(vec![]);
+        let mut source = String::new();
+        let result = self.fmt_syntax(&mut source, syntax);
+        let marks = self.marks.take().unwrap_or_default();
+        result?;
+
+        let lines = source
+            .lines()
+            .map(|line| {
+                let mut text = String::with_capacity(line.len());
+                let mut span = None;
+                let mut rest = line;
+                while let Some(start) = rest.find(MARK_START) {
+                    text.push_str(&rest[..start]);
+                    rest = &rest[start + MARK_START.len_utf8()..];
+                    let end = rest.find(MARK_END).unwrap_or(rest.len());
+                    let mark = rest[..end]
+                        .parse()
+                        .ok()
+                        .and_then(|index: usize| marks.get(index));
+                    span = span.or_else(|| mark.cloned());
+                    rest = rest.get(end + MARK_END.len_utf8()..).unwrap_or_default();
+                }
+                text.push_str(rest);
+                (text, span)
+            })
+            .collect();
+        Ok(lines)
+    }
+
+    /// Mark where a statement starts, when marking them.
+    fn fmt_mark(&mut self, f: &mut impl FmtWrite, span: Option<&Span>) -> Result<()> {
+        if let (Some(marks), Some(span)) = (&mut self.marks, span) {
+            write!(f, "{MARK_START}{}{MARK_END}", marks.len())?;
+            marks.push((self.path.clone(), *span));
+        }
+        Ok(())
+    }
+
+    /// Write the source to a byte stream as it's generated,
+    /// without building the whole source in memory.
+    pub fn write_syntax(&mut self, w: impl io::Write, syntax: &Syntax) -> Result<()> {
+        let mut f = IoFmt::new(w);
+        let result = self.fmt_syntax(&mut f, syntax);
+        f.finish(result)
+    }
+
+    /// Write the source to a file, replacing it if it exists.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_file(&mut self, path: impl AsRef<std::path::Path>, syntax: &Syntax) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_syntax(io::BufWriter::new(file), syntax)
+    }
+
+    /// Write the source with trailing whitespace and extra blank lines removed.
+    fn fmt_normalized(&mut self, f: &mut impl FmtWrite, source: &str) -> Result<()> {
+        let mut blank = false;
+        let mut lines = source.lines().map(str::trim_end).peekable();
+        while lines.next_if(|line| line.is_empty()).is_some() {}
+        for line in lines {
+            if line.is_empty() {
+                blank = true;
+                continue;
+            }
+            if blank {
+                self.config.fmt_newline(f)?;
+                blank = false;
+            }
+            f.write_str(line)?;
+            self.config.fmt_newline(f)?;
+        }
+        Ok(())
     }
 
     fn with_indent<F>(&mut self, func: F) -> Result<()>
@@ -31,16 +234,139 @@ impl Scribe {
     }
 
     fn fmt_indent(&mut self, f: &mut impl FmtWrite) -> Result<()> {
-        for _ in 0..self.level {
-            write!(f, "    ")?;
+        self.config.fmt_indent(f, self.level)
+    }
+
+    fn fmt_block(&mut self, f: &mut impl FmtWrite, block: &Block) -> Result<()> {
+        if self.group_locals && can_group_locals(block) {
+            return self.fmt_grouped_block(f, block);
+        }
+
+        for (index, node) in block.nodes.iter().enumerate() {
+            if is_attached(block, index) {
+                continue;
+            }
+            self.fmt_line_break(f, block, index)?;
+            self.fmt_indent(f)?;
+            self.fmt_mark(f, block.spans.get(index))?;
+            let trailing = trailing_comments(block, index);
+            self.fmt_annotated_node(f, node, block.spans.get(index), trailing)?;
         }
+
         Ok(())
     }
 
-    fn fmt_block(&mut self, f: &mut impl FmtWrite, block: &Block) -> Result<()> {
-        for node in &block.nodes {
+    /// Separate the statement at the index from the previous one,
+    /// when the source lines between them were skipped.
+    fn fmt_line_break(&mut self, f: &mut impl FmtWrite, block: &Block, index: usize) -> Result<()> {
+        let prev_line = index
+            .checked_sub(1)
+            .and_then(|prev| block.spans.get(prev))
+            .and_then(|span| span.end_line);
+        let line = block.spans.get(index).and_then(|span| span.line);
+        let (Some(prev_line), Some(line)) = (prev_line, line) else {
+            return Ok(());
+        };
+
+        if self.preserve_lines && line > prev_line + 1 {
+            self.config.fmt_newline(f)?;
+        }
+        if self.line_markers && line != prev_line && line != prev_line + 1 {
             self.fmt_indent(f)?;
-            self.fmt_node(f, node)?;
+            write!(f, "-- line {line}")?;
+            self.config.fmt_newline(f)?;
+        }
+        Ok(())
+    }
+
+    /// Format a node, with its trailing comments and the span
+    /// comment at the end of its first line.
+    fn fmt_annotated_node(
+        &mut self,
+        f: &mut impl FmtWrite,
+        node: &Node,
+        span: Option<&Span>,
+        trailing: &[Node],
+    ) -> Result<()> {
+        let span = span.filter(|_| self.annotate);
+        if span.is_none() && trailing.is_empty() {
+            return self.fmt_node(f, node);
+        }
+
+        let mut buf = String::new();
+        self.fmt_node(&mut buf, node)?;
+        let (first, rest) = match buf.find('\n') {
+            Some(index) => buf.split_at(index),
+            None => (buf.as_str(), ""),
+        };
+        let (first, carriage) = match first.strip_suffix('\r') {
+            Some(first) => (first, "\r"),
+            None => (first, ""),
+        };
+
+        write!(f, "{first}")?;
+        fmt_trailing_comments(f, trailing)?;
+        if let Some(span) = span {
+            fmt_span(f, span)?;
+        }
+        write!(f, "{carriage}{rest}")?;
+        Ok(())
+    }
+
+    fn fmt_grouped_block(&mut self, f: &mut impl FmtWrite, block: &Block) -> Result<()> {
+        let names: Vec<Ident> = block
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Stmt(Stmt::LocalVar(local_var)) => Some(local_var.names.iter()),
+                _ => None,
+            })
+            .flatten()
+            .cloned()
+            .collect();
+
+        if !names.is_empty() {
+            self.fmt_indent(f)?;
+            write!(f, "local ")?;
+            self.fmt_names(f, &names)?;
+            self.config.fmt_newline(f)?;
+        }
+
+        // Whether the trailing comments at the index were written with the node before them.
+        let mut attached = false;
+        for (index, node) in block.nodes.iter().enumerate() {
+            if attached && is_attached(block, index) {
+                continue;
+            }
+            let trailing = trailing_comments(block, index);
+            attached = true;
+            match node {
+                Node::Stmt(Stmt::LocalVar(local_var)) => {
+                    // Declarations without values are covered by the grouped
+                    // declaration, which already initialises them to nil.
+                    if local_var.rhs.is_empty() {
+                        attached = false;
+                        continue;
+                    }
+                    self.fmt_line_break(f, block, index)?;
+                    self.fmt_indent(f)?;
+                    self.fmt_mark(f, block.spans.get(index))?;
+                    self.fmt_names(f, &local_var.names)?;
+                    write!(f, " = ")?;
+                    self.fmt_expr_list(f, &local_var.rhs)?;
+                    fmt_trailing_comments(f, trailing)?;
+                    if let Some(span) = block.spans.get(index).filter(|_| self.annotate) {
+                        fmt_span(f, span)?;
+                    }
+                    self.config.fmt_newline(f)?;
+                }
+                _ => {
+                    self.fmt_line_break(f, block, index)?;
+                    self.fmt_indent(f)?;
+                    self.fmt_mark(f, block.spans.get(index))?;
+                    self.fmt_annotated_node(f, node, block.spans.get(index), trailing)?;
+                }
+            }
         }
 
         Ok(())
@@ -51,34 +377,111 @@ impl Scribe {
             Node::Stmt(stmt) => self.fmt_stmt(f, stmt),
             // FIXME: Some expressions are valid statements, like Call. Can we detect this and wrap them in stmt?
             Node::Expr(expr) => self.fmt_expr(f, expr),
-            Node::Partial(_) => panic!("partially built statement"),
+            Node::Partial(_) => Error::new_parser("partially built statement").into(),
         }
     }
 
     fn fmt_stmt(&mut self, f: &mut impl FmtWrite, stmt: &Stmt) -> Result<()> {
         match stmt {
             Stmt::LocalVar(local_var) => self.fmt_local_var(f, local_var),
-            Stmt::Call(call) => self.fmt_call(f, call),
+            Stmt::Call(call) => {
+                self.fmt_call(f, call)?;
+                self.config.fmt_newline(f)?;
+                Ok(())
+            }
             Stmt::Assign(assign) => self.fmt_assign(f, assign),
             Stmt::Block(block) => self.fmt_block_stmt(f, block),
             Stmt::If(if_block) => self.fmt_if_block(f, if_block),
+            Stmt::While(while_block) => self.fmt_while_block(f, while_block),
+            Stmt::Repeat(repeat_block) => self.fmt_repeat_block(f, repeat_block),
+            Stmt::Break => {
+                write!(f, "break")?;
+                self.config.fmt_newline(f)?;
+                Ok(())
+            }
+            Stmt::Return(ret) => self.fmt_return(f, ret),
+            Stmt::Goto(goto) => self.fmt_goto(f, goto),
+            Stmt::Label(target) => {
+                write!(f, "-- ::{}::", label_name(*target))?;
+                self.config.fmt_newline(f)?;
+                Ok(())
+            }
+            Stmt::Failed(failed) => self.fmt_failed(f, failed),
+            Stmt::Custom(custom) => {
+                write!(f, "{}", custom.source)?;
+                self.config.fmt_newline(f)?;
+                Ok(())
+            }
+            Stmt::Comment(comment) => self.fmt_comment(f, comment),
         }
     }
 
     fn fmt_local_var(&mut self, f: &mut impl FmtWrite, local_var: &LocalVar) -> Result<()> {
-        let LocalVar { name, rhs } = local_var;
-        write!(f, "local {name} = ")?;
-        self.fmt_expr(f, rhs)?;
-        writeln!(f)?;
+        let LocalVar { names, rhs } = local_var;
+        write!(f, "local ")?;
+        self.fmt_names(f, names)?;
+        if !rhs.is_empty() {
+            write!(f, " = ")?;
+            self.fmt_expr_list(f, rhs)?;
+        }
+        self.config.fmt_newline(f)?;
+        Ok(())
+    }
+
+    fn fmt_names(&mut self, f: &mut impl FmtWrite, names: &[Ident]) -> Result<()> {
+        for (i, name) in names.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}")?;
+        }
+        Ok(())
+    }
+
+    fn fmt_expr_list(&mut self, f: &mut impl FmtWrite, exprs: &[Expr]) -> Result<()> {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            self.fmt_expr(f, expr)?;
+        }
         Ok(())
     }
 
     fn fmt_expr(&mut self, f: &mut impl FmtWrite, expr: &Expr) -> Result<()> {
+        self.fmt_subexpr(f, expr, 0)
+    }
+
+    /// Format an expression, wrapping it in parentheses when its operator
+    /// binds weaker than the given priority.
+    fn fmt_subexpr(&mut self, f: &mut impl FmtWrite, expr: &Expr, limit: u32) -> Result<()> {
         match expr {
             Expr::Access(ident) => self.fmt_access(f, ident),
-            Expr::Literal(lit) => self.fmt_lit(f, lit),
-            Expr::Binary(bin_expr) => self.fmt_binary_expr(f, bin_expr),
-            Expr::Call(call) => self.fmt_call(f, &*call),
+            Expr::Upvalue(ident) => {
+                write!(f, "%{}", ident)?;
+                Ok(())
+            }
+            Expr::Literal(lit) => {
+                // A negative literal is negated by a unary minus when read back.
+                let wrap = lit.is_negative() && UnaryOp::PRIORITY <= limit;
+                if wrap {
+                    write!(f, "(")?;
+                }
+                self.fmt_lit(f, lit)?;
+                if wrap {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Expr::Binary(bin_expr) => self.fmt_binary_expr(f, bin_expr, limit),
+            Expr::Unary(unary_expr) => self.fmt_unary_expr(f, unary_expr, limit),
+            Expr::Call(call) => self.fmt_call(f, call),
+            Expr::Function(function) => self.fmt_function(f, function),
+            Expr::Table(table) => self.fmt_table(f, table),
+            Expr::Custom(custom) => {
+                write!(f, "{}", custom.source)?;
+                Ok(())
+            }
         }
     }
 
@@ -90,92 +493,358 @@ impl Scribe {
     fn fmt_lit(&self, f: &mut impl FmtWrite, lit: &Lit) -> Result<()> {
         match lit {
             Lit::Int(value) => write!(f, "{}", value)?,
-            Lit::Num(_) => todo!(),
-            Lit::Str(_) => todo!(),
+            Lit::Num(value) => self.config.fmt_number(f, *value)?,
+            Lit::Str(value) => self.config.fmt_string(f, value.as_bytes())?,
         }
         Ok(())
     }
 
-    fn fmt_binary_expr(&mut self, f: &mut impl FmtWrite, bin_expr: &BinExpr) -> Result<()> {
-        self.fmt_expr(f, &bin_expr.lhs)?;
-        write!(f, " ")?;
+    fn fmt_binary_expr(
+        &mut self,
+        f: &mut impl FmtWrite,
+        bin_expr: &BinExpr,
+        limit: u32,
+    ) -> Result<()> {
+        let (left, right) = bin_expr.op.priority();
+        let wrap = left <= limit;
+        if wrap {
+            write!(f, "(")?;
+        }
 
-        match bin_expr.op {
-            BinOp::Add => write!(f, "+")?,
+        // An operand binds tighter than the operator when its own priority is
+        // greater than the operator's priority on that side.
+        self.fmt_subexpr(f, &bin_expr.lhs, left)?;
+        if bin_expr.op.is_keyword() {
+            write!(f, " {} ", bin_expr.op.as_str())?;
+            self.fmt_subexpr(f, &bin_expr.rhs, right)?;
+        } else {
+            let mut rhs = self.config.fmt_operator(f, bin_expr.op.as_str())?;
+            self.fmt_subexpr(&mut rhs, &bin_expr.rhs, right)?;
         }
 
-        write!(f, " ")?;
-        self.fmt_expr(f, &bin_expr.rhs)?;
+        if wrap {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
 
+    fn fmt_unary_expr(
+        &mut self,
+        f: &mut impl FmtWrite,
+        unary_expr: &UnaryExpr,
+        limit: u32,
+    ) -> Result<()> {
+        let wrap = UnaryOp::PRIORITY <= limit;
+        if wrap {
+            write!(f, "(")?;
+        }
+        write!(f, "{}", unary_expr.op.as_str())?;
+        self.fmt_subexpr(f, &unary_expr.rhs, UnaryOp::PRIORITY)?;
+        if wrap {
+            write!(f, ")")?;
+        }
         Ok(())
     }
 
     fn fmt_call(&mut self, f: &mut impl FmtWrite, call: &Call) -> Result<()> {
         self.fmt_expr(f, &call.name)?;
         write!(f, "(")?;
-        for (i, arg) in call.args.iter().enumerate() {
+        self.fmt_expr_list(f, &call.args)?;
+        write!(f, ")")?;
+        Ok(())
+    }
+
+    fn fmt_table(&mut self, f: &mut impl FmtWrite, table: &Table) -> Result<()> {
+        write!(f, "{{")?;
+        if table.fields_first {
+            self.fmt_fields(f, &table.fields)?;
+            if !table.items.is_empty() {
+                write!(f, "; ")?;
+                self.fmt_expr_list(f, &table.items)?;
+            }
+        } else {
+            self.fmt_expr_list(f, &table.items)?;
+            if !table.items.is_empty() && !table.fields.is_empty() {
+                write!(f, "; ")?;
+            }
+            self.fmt_fields(f, &table.fields)?;
+        }
+        write!(f, "}}")?;
+        Ok(())
+    }
+
+    fn fmt_fields(&mut self, f: &mut impl FmtWrite, fields: &[Field]) -> Result<()> {
+        for (i, field) in fields.iter().enumerate() {
             if i != 0 {
                 write!(f, ", ")?;
             }
-            self.fmt_expr(f, arg)?;
+            let name = match &field.key {
+                Expr::Literal(lit) => lit.as_name(),
+                _ => None,
+            };
+            match name {
+                Some(name) => write!(f, "{name}")?,
+                None => {
+                    write!(f, "[")?;
+                    self.fmt_expr(f, &field.key)?;
+                    write!(f, "]")?;
+                }
+            }
+            write!(f, " = ")?;
+            self.fmt_expr(f, &field.value)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_function(&mut self, f: &mut impl FmtWrite, function: &Function) -> Result<()> {
+        write!(f, "function(")?;
+        self.fmt_names(f, &function.params)?;
+        if function.is_vararg {
+            if !function.params.is_empty() {
+                write!(f, ", ")?;
+            }
+            write!(f, "...")?;
         }
         write!(f, ")")?;
+        self.config.fmt_newline(f)?;
+        let outer = std::mem::replace(&mut self.path, function.path.clone());
+        let result = self.with_indent(|scribe| scribe.fmt_block(f, &function.body));
+        self.path = outer;
+        result?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
         Ok(())
     }
 
     fn fmt_assign(&mut self, f: &mut impl FmtWrite, assign: &Assign) -> Result<()> {
-        let Assign { name, rhs } = assign;
-        write!(f, "{name} = ")?;
-        self.fmt_expr(f, rhs)?;
-        writeln!(f)?;
+        let Assign { targets, rhs } = assign;
+        self.fmt_names(f, targets)?;
+        write!(f, " = ")?;
+        self.fmt_expr_list(f, rhs)?;
+        self.config.fmt_newline(f)?;
+        Ok(())
+    }
+
+    fn fmt_return(&mut self, f: &mut impl FmtWrite, ret: &Return) -> Result<()> {
+        write!(f, "return")?;
+        if !ret.values.is_empty() {
+            write!(f, " ")?;
+            self.fmt_expr_list(f, &ret.values)?;
+        }
+        self.config.fmt_newline(f)?;
+        Ok(())
+    }
+
+    /// Write the disassembly of instructions that couldn't be decompiled.
+    ///
+    /// Lua 4.0 has no block comments, so every line is commented out.
+    fn fmt_failed(&mut self, f: &mut impl FmtWrite, failed: &Failed) -> Result<()> {
+        write!(f, "-- DECOMPILE FAILED: {}", failed.message)?;
+        self.config.fmt_newline(f)?;
+        for line in failed.listing.lines() {
+            self.fmt_indent(f)?;
+            write!(f, "--{line}")?;
+            self.config.fmt_newline(f)?;
+        }
+        Ok(())
+    }
+
+    /// Write a comment on lines of its own, which is also
+    /// how a trailing comment without a node before it is written.
+    fn fmt_comment(&mut self, f: &mut impl FmtWrite, comment: &Comment) -> Result<()> {
+        let mut lines = comment.text.lines();
+        let first = lines.next().unwrap_or_default();
+        for (index, line) in std::iter::once(first).chain(lines).enumerate() {
+            if index != 0 {
+                self.fmt_indent(f)?;
+            }
+            write!(f, "--")?;
+            if !line.is_empty() {
+                write!(f, " {line}")?;
+            }
+            self.config.fmt_newline(f)?;
+        }
+        Ok(())
+    }
+
+    /// Write a jump that doesn't fit any statement.
+    ///
+    /// Lua 4.0 has no `goto`, so it's commented out.
+    fn fmt_goto(&mut self, f: &mut impl FmtWrite, goto: &Goto) -> Result<()> {
+        write!(f, "-- ")?;
+        match &goto.cond {
+            Some(cond) => {
+                write!(f, "if ")?;
+                self.fmt_expr(f, cond)?;
+                write!(f, " then goto {} end", label_name(goto.target))?;
+            }
+            None => write!(f, "goto {}", label_name(goto.target))?,
+        }
+        self.config.fmt_newline(f)?;
         Ok(())
     }
 
     fn fmt_block_stmt(&mut self, f: &mut impl FmtWrite, block: &Block) -> Result<()> {
-        writeln!(f, "do")?;
+        write!(f, "do")?;
+        self.config.fmt_newline(f)?;
         self.with_indent(|scribe| scribe.fmt_block(f, block))?;
-        writeln!(f, "end")?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        self.config.fmt_newline(f)?;
         Ok(())
     }
 
     fn fmt_if_block(&mut self, f: &mut impl FmtWrite, if_block: &IfBlock) -> Result<()> {
         //  head
         write!(f, "if ")?;
-        self.fmt_cond_expr(f, &if_block.head)?;
-        writeln!(f, " then")?;
+        self.fmt_expr(f, &if_block.head)?;
+        write!(f, " then")?;
+        self.config.fmt_newline(f)?;
 
         // body
         self.with_indent(|scribe| scribe.fmt_block(f, &if_block.then))?;
-        if let Some(else_) = &if_block.else_ {
-            writeln!(f, "else")?;
-            self.with_indent(|scribe| scribe.fmt_block(f, else_))?;
+
+        // An `else` block holding only another `if` is an `elseif`.
+        let mut else_ = if_block.else_.as_ref();
+        while let Some(block) = else_ {
+            match block.nodes.as_slice() {
+                [Node::Stmt(Stmt::If(elseif))] => {
+                    self.fmt_indent(f)?;
+                    write!(f, "elseif ")?;
+                    self.fmt_expr(f, &elseif.head)?;
+                    write!(f, " then")?;
+                    self.config.fmt_newline(f)?;
+                    self.with_indent(|scribe| scribe.fmt_block(f, &elseif.then))?;
+                    else_ = elseif.else_.as_ref();
+                }
+                _ => {
+                    self.fmt_indent(f)?;
+                    write!(f, "else")?;
+                    self.config.fmt_newline(f)?;
+                    self.with_indent(|scribe| scribe.fmt_block(f, block))?;
+                    else_ = None;
+                }
+            }
         }
 
-        writeln!(f, "end")?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        self.config.fmt_newline(f)?;
         Ok(())
     }
 
-    fn fmt_cond_expr(&mut self, f: &mut impl FmtWrite, expr: &CondExpr) -> Result<()> {
-        match expr {
-            CondExpr::Unary { .. } => todo!("unary expression"),
-            CondExpr::Binary { op, lhs, rhs } => {
-                self.fmt_expr(f, lhs)?;
-                write!(f, " ")?;
-
-                match op {
-                    CondOp::Ne => write!(f, "~=")?,
-                    CondOp::Eq => write!(f, "==")?,
-                    CondOp::Lt => write!(f, "<")?,
-                    CondOp::Le => write!(f, "<=")?,
-                    CondOp::Gt => write!(f, ">")?,
-                    CondOp::Ge => write!(f, ">=")?,
-                }
+    fn fmt_while_block(&mut self, f: &mut impl FmtWrite, while_block: &WhileBlock) -> Result<()> {
+        write!(f, "while ")?;
+        self.fmt_expr(f, &while_block.head)?;
+        write!(f, " do")?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &while_block.body))?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        self.config.fmt_newline(f)?;
+        Ok(())
+    }
+
+    fn fmt_repeat_block(
+        &mut self,
+        f: &mut impl FmtWrite,
+        repeat_block: &RepeatBlock,
+    ) -> Result<()> {
+        write!(f, "repeat")?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &repeat_block.body))?;
+        self.fmt_indent(f)?;
+        write!(f, "until ")?;
+        self.fmt_expr(f, &repeat_block.cond)?;
+        self.config.fmt_newline(f)?;
+        Ok(())
+    }
+}
+
+/// Write a trailing comment with the instruction range, numbered from 1 like
+/// the disassembly listing.
+fn fmt_span(f: &mut impl FmtWrite, span: &Span) -> Result<()> {
+    write!(f, "  -- [{}", span.start + 1)?;
+    if span.end != span.start {
+        write!(f, "-{}", span.end + 1)?;
+    }
+    write!(f, "]")?;
+    if let Some(line) = span.line {
+        write!(f, " line {line}")?;
+    }
+    Ok(())
+}
 
-                write!(f, " ")?;
-                self.fmt_expr(f, rhs)?;
+/// Write comments at the end of a line, with each of their lines run together.
+fn fmt_trailing_comments(f: &mut impl FmtWrite, trailing: &[Node]) -> Result<()> {
+    for node in trailing {
+        if let Node::Stmt(Stmt::Comment(comment)) = node {
+            write!(f, "  -- ")?;
+            for (index, line) in comment.text.lines().enumerate() {
+                if index != 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{line}")?;
             }
         }
+    }
+    Ok(())
+}
 
-        Ok(())
+/// Whether the node is a trailing comment written with a node before it.
+fn is_attached(block: &Block, index: usize) -> bool {
+    index > 0 && is_trailing_comment(&block.nodes[index])
+}
+
+/// Trailing comments following the node at the index.
+fn trailing_comments(block: &Block, index: usize) -> &[Node] {
+    let rest = &block.nodes[index + 1..];
+    let len = rest
+        .iter()
+        .take_while(|node| is_trailing_comment(node))
+        .count();
+    &rest[..len]
+}
+
+fn is_trailing_comment(node: &Node) -> bool {
+    matches!(node, Node::Stmt(Stmt::Comment(comment)) if comment.trailing)
+}
+
+/// Name of a goto destination, numbered from 1 like the disassembly listing.
+fn label_name(target: u32) -> String {
+    format!("label_{}", target + 1)
+}
+
+/// Checks whether hoisting the local variable declarations in the block
+/// preserves semantics.
+///
+/// Moving a declaration up changes the meaning of any reference to the same
+/// name between the top of the block and the original declaration, including
+/// the declaration's own initialiser, since those referred to an outer
+/// variable. Redeclaring a name in the same block can't be grouped either.
+fn can_group_locals(block: &Block) -> bool {
+    let mut declared: HashSet<&str> = HashSet::new();
+    let mut referenced: HashSet<String> = HashSet::new();
+
+    for node in &block.nodes {
+        if let Node::Stmt(Stmt::LocalVar(local_var)) = node {
+            for expr in &local_var.rhs {
+                expr.for_each_ident(&mut |ident| {
+                    referenced.insert(ident.as_str().to_string());
+                });
+            }
+
+            for name in &local_var.names {
+                if referenced.contains(name.as_str()) || !declared.insert(name.as_str()) {
+                    return false;
+                }
+            }
+        } else {
+            node.for_each_ident(&mut |ident| {
+                referenced.insert(ident.as_str().to_string());
+            });
+        }
     }
+
+    true
 }
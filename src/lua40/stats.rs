@@ -0,0 +1,169 @@
+//! Summary statistics of a function and its nested functions.
+//!
+//! Cheap to gather compared to decompiling, so large dumps of scripts
+//! can be triaged to find the functions worth looking at first, and
+//! checked for opcodes the parser doesn't support yet with [Coverage].
+use std::collections::BTreeMap;
+use std::fmt::{self, Formatter};
+
+use super::{Opcode, Proto};
+
+/// Totals over a function and all the functions nested in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of functions, including the function itself.
+    pub functions: usize,
+    pub instructions: usize,
+    /// Number of instructions of each opcode that occurs.
+    pub opcodes: BTreeMap<Opcode, usize>,
+    pub strings: usize,
+    pub numbers: usize,
+    /// Largest stack size needed by any of the functions.
+    pub max_stack: u32,
+    /// Levels of nested functions, zero when the function has none.
+    pub depth: usize,
+    /// First and last source line of the instructions, when debug information is present.
+    ///
+    /// Only the first and last instruction of each function are considered,
+    /// since resolving the line of every instruction is quadratic.
+    pub lines: Option<(u32, u32)>,
+}
+
+/// Opcodes used by a set of chunks, like the scripts of a game,
+/// and whether the parser supports each of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub chunks: usize,
+    /// Number of chunks that only use opcodes the parser supports.
+    pub supported_chunks: usize,
+    /// Use of each opcode that occurs.
+    pub opcodes: BTreeMap<Opcode, OpcodeUse>,
+}
+
+/// How much an opcode is used across the chunks of a [Coverage].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpcodeUse {
+    pub instructions: usize,
+    /// Number of chunks with at least one instruction of the opcode.
+    pub chunks: usize,
+}
+
+impl Proto {
+    /// Gather statistics for the function and its nested functions.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        stats.add(self, 0);
+        stats
+    }
+}
+
+impl Stats {
+    fn add(&mut self, proto: &Proto, depth: usize) {
+        self.functions += 1;
+        self.instructions += proto.instrs.len();
+        for instr in proto.instrs.iter() {
+            *self.opcodes.entry(instr.opcode).or_default() += 1;
+        }
+        self.strings += proto.constants.strings.len();
+        self.numbers += proto.constants.numbers.len();
+        self.max_stack = self.max_stack.max(proto.max_stack);
+        self.depth = self.depth.max(depth);
+
+        let last = proto.instrs.len().saturating_sub(1);
+        for line in [proto.line_at(0), proto.line_at(last)]
+            .into_iter()
+            .flatten()
+        {
+            self.lines = match self.lines {
+                Some((first, last)) => Some((first.min(line), last.max(line))),
+                None => Some((line, line)),
+            };
+        }
+
+        for child in proto.constants.protos.iter() {
+            self.add(child, depth + 1);
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the opcodes of the chunk's main function and its nested functions.
+    pub fn add(&mut self, main: &Proto) {
+        let stats = main.stats();
+        self.chunks += 1;
+        if stats.opcodes.keys().all(|opcode| opcode.is_supported()) {
+            self.supported_chunks += 1;
+        }
+        for (opcode, count) in stats.opcodes {
+            let used = self.opcodes.entry(opcode).or_default();
+            used.instructions += count;
+            used.chunks += 1;
+        }
+    }
+
+    /// Opcodes used by the chunks that the parser doesn't support.
+    pub fn unsupported(&self) -> impl Iterator<Item = (Opcode, &OpcodeUse)> {
+        self.opcodes
+            .iter()
+            .filter(|(opcode, _)| !opcode.is_supported())
+            .map(|(opcode, used)| (*opcode, used))
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let lines = match self.lines {
+            Some((first, last)) => format!("{first}-{last}"),
+            None => "-".to_string(),
+        };
+        let rows = [
+            ("functions", self.functions.to_string()),
+            ("instructions", self.instructions.to_string()),
+            ("strings", self.strings.to_string()),
+            ("numbers", self.numbers.to_string()),
+            ("max stack", self.max_stack.to_string()),
+            ("depth", self.depth.to_string()),
+            ("lines", lines),
+        ];
+        for (name, value) in rows {
+            writeln!(f, "{name:<14}{value}")?;
+        }
+
+        writeln!(f)?;
+        for (opcode, count) in self.opcodes.iter() {
+            writeln!(f, "{:<14}{count}", opcode.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<14}{:>14}{:>8}  supported",
+            "opcode", "instructions", "chunks"
+        )?;
+        for (opcode, used) in self.opcodes.iter() {
+            let supported = if opcode.is_supported() { "yes" } else { "no" };
+            writeln!(
+                f,
+                "{:<14}{:>14}{:>8}  {supported}",
+                opcode.name(),
+                used.instructions,
+                used.chunks
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} of {} chunks only use supported opcodes",
+            self.supported_chunks, self.chunks
+        )
+    }
+}
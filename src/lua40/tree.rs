@@ -0,0 +1,234 @@
+//! Indented tree view of a syntax tree.
+//!
+//! Shows what the parser built, with the instructions each statement was
+//! decoded from, to tell parser bugs apart from scribe bugs.
+use std::fmt::{self, Formatter};
+
+use super::ast::{Block, Custom, Expr, Function, Ident, Lit, Node, Partial, Span, Stmt, Syntax};
+
+/// Tree view of a syntax tree, one node per line.
+pub struct TreeDump<'a> {
+    syntax: &'a Syntax,
+}
+
+struct TreeWriter<'a, 'b> {
+    f: &'a mut Formatter<'b>,
+    depth: usize,
+}
+
+// ============================================================================
+
+impl Syntax {
+    /// Indented tree view of the syntax tree, with the range of instructions
+    /// and source lines of each statement.
+    ///
+    /// ```text
+    /// Block
+    ///   Local a  [1..1]
+    ///     Int 7
+    /// ```
+    pub fn dump_tree(&self) -> TreeDump<'_> {
+        TreeDump { syntax: self }
+    }
+}
+
+impl fmt::Display for TreeDump<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut writer = TreeWriter { f, depth: 0 };
+        writer.block("Block", &self.syntax.root)
+    }
+}
+
+impl TreeWriter<'_, '_> {
+    fn line(&mut self, label: impl fmt::Display) -> fmt::Result {
+        writeln!(self.f, "{:indent$}{label}", "", indent = self.depth * 2)
+    }
+
+    /// Write a line, and the children written by `nest` indented below it.
+    fn nest(
+        &mut self,
+        label: impl fmt::Display,
+        nest: impl FnOnce(&mut Self) -> fmt::Result,
+    ) -> fmt::Result {
+        self.line(label)?;
+        self.depth += 1;
+        let result = nest(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn block(&mut self, label: &str, block: &Block) -> fmt::Result {
+        self.nest(label, |w| {
+            for (index, node) in block.nodes.iter().enumerate() {
+                w.node(node, block.spans.get(index))?;
+            }
+            Ok(())
+        })
+    }
+
+    fn node(&mut self, node: &Node, span: Option<&Span>) -> fmt::Result {
+        let span = match span {
+            Some(span) => span_label(span),
+            None => String::new(),
+        };
+        match node {
+            Node::Stmt(stmt) => self.stmt(stmt, &span),
+            Node::Expr(expr) => self.expr_with(expr, &span),
+            Node::Partial(partial) => self.partial(partial, &span),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, span: &str) -> fmt::Result {
+        match stmt {
+            Stmt::LocalVar(local_var) => {
+                let names = names(local_var.names.iter());
+                self.nest(format!("Local {names}{span}"), |w| w.exprs(&local_var.rhs))
+            }
+            Stmt::Assign(assign) => {
+                let names = names(assign.targets.iter());
+                self.nest(format!("Assign {names}{span}"), |w| w.exprs(&assign.rhs))
+            }
+            Stmt::Call(call) => self.nest(format!("Call{span}"), |w| {
+                w.expr(&call.name)?;
+                w.exprs(&call.args)
+            }),
+            Stmt::Block(block) => self.block(&format!("Do{span}"), block),
+            Stmt::If(if_block) => self.nest(format!("If{span}"), |w| {
+                w.cond(&if_block.head)?;
+                w.block("Then", &if_block.then)?;
+                match &if_block.else_ {
+                    Some(else_) => w.block("Else", else_),
+                    None => Ok(()),
+                }
+            }),
+            Stmt::While(while_block) => self.nest(format!("While{span}"), |w| {
+                w.cond(&while_block.head)?;
+                w.block("Body", &while_block.body)
+            }),
+            Stmt::Repeat(repeat_block) => self.nest(format!("Repeat{span}"), |w| {
+                w.block("Body", &repeat_block.body)?;
+                w.cond(&repeat_block.cond)
+            }),
+            Stmt::Break => self.line(format!("Break{span}")),
+            Stmt::Return(ret) => self.nest(format!("Return{span}"), |w| w.exprs(&ret.values)),
+            Stmt::Goto(goto) => {
+                self.nest(format!("Goto {}{span}", goto.target + 1), |w| {
+                    match &goto.cond {
+                        Some(cond) => w.cond(cond),
+                        None => Ok(()),
+                    }
+                })
+            }
+            Stmt::Label(target) => self.line(format!("Label {}{span}", target + 1)),
+            Stmt::Failed(failed) => self.line(format!("Failed {:?}{span}", failed.message)),
+            Stmt::Custom(custom) => self.custom(custom, span),
+            Stmt::Comment(comment) => {
+                let kind = if comment.trailing { "Trailing " } else { "" };
+                self.line(format!("{kind}Comment {:?}{span}", comment.text))
+            }
+        }
+    }
+
+    fn partial(&mut self, partial: &Partial, span: &str) -> fmt::Result {
+        match partial {
+            Partial::IfHead(if_head) => self.nest(format!("Partial IfHead{span}"), |w| {
+                w.cond(&if_head.expr)?;
+                match &if_head.then {
+                    Some(then) => w.block("Then", then),
+                    None => Ok(()),
+                }
+            }),
+            Partial::WhileHead(while_head) => self.nest(format!("Partial WhileHead{span}"), |w| {
+                w.cond(&while_head.expr)
+            }),
+            Partial::ForHead => self.line(format!("Partial ForHead{span}")),
+            Partial::Until(cond) => self.nest(format!("Partial Until{span}"), |w| w.cond(cond)),
+        }
+    }
+
+    /// Condition of a statement, tested for whether it's true.
+    fn cond(&mut self, cond: &Expr) -> fmt::Result {
+        self.nest("Test", |w| w.expr(cond))
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> fmt::Result {
+        exprs.iter().try_for_each(|expr| self.expr(expr))
+    }
+
+    fn expr(&mut self, expr: &Expr) -> fmt::Result {
+        self.expr_with(expr, "")
+    }
+
+    fn expr_with(&mut self, expr: &Expr, span: &str) -> fmt::Result {
+        match expr {
+            Expr::Access(name) => self.line(format!("Name {name}{span}")),
+            Expr::Upvalue(name) => self.line(format!("Upvalue {name}{span}")),
+            Expr::Literal(Lit::Int(value)) => self.line(format!("Int {value}{span}")),
+            Expr::Literal(Lit::Num(value)) => self.line(format!("Num {value}{span}")),
+            Expr::Literal(Lit::Str(value)) => {
+                self.line(format!("Str {:?}{span}", value.to_string_lossy()))
+            }
+            Expr::Binary(bin) => self.nest(format!("Binary {}{span}", bin.op.as_str()), |w| {
+                w.expr(&bin.lhs)?;
+                w.expr(&bin.rhs)
+            }),
+            Expr::Unary(unary) => {
+                let op = unary.op.as_str().trim_end();
+                self.nest(format!("Unary {op}{span}"), |w| w.expr(&unary.rhs))
+            }
+            Expr::Call(call) => self.nest(format!("Call{span}"), |w| {
+                w.expr(&call.name)?;
+                w.exprs(&call.args)
+            }),
+            Expr::Function(function) => self.function(function, span),
+            Expr::Custom(custom) => self.custom(custom, span),
+            Expr::Table(table) => self.nest(format!("Table{span}"), |w| {
+                w.exprs(&table.items)?;
+                for field in &table.fields {
+                    w.nest("Field", |w| {
+                        w.expr(&field.key)?;
+                        w.expr(&field.value)
+                    })?;
+                }
+                Ok(())
+            }),
+        }
+    }
+
+    fn custom(&mut self, custom: &Custom, span: &str) -> fmt::Result {
+        self.line(format!("Custom {} {:?}{span}", custom.kind, custom.source))
+    }
+
+    fn function(&mut self, function: &Function, span: &str) -> fmt::Result {
+        let mut params = names(function.params.iter());
+        if function.is_vararg {
+            params.push_str(if function.params.is_empty() {
+                "..."
+            } else {
+                ", ..."
+            });
+        }
+        self.block(&format!("Function ({params}){span}"), &function.body)
+    }
+}
+
+/// Instructions and source lines a statement was decoded from,
+/// numbered from 1 like the disassembly listing.
+fn span_label(span: &Span) -> String {
+    let mut label = format!("  [{}..{}]", span.start + 1, span.end + 1);
+    match (span.line, span.end_line) {
+        (Some(line), Some(end_line)) if line != end_line => {
+            label.push_str(&format!(" lines {line}..{end_line}"))
+        }
+        (Some(line), _) => label.push_str(&format!(" line {line}")),
+        _ => {}
+    }
+    label
+}
+
+fn names<'a>(names: impl Iterator<Item = &'a Ident>) -> String {
+    names
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,87 @@
+//! Inferred types of the values on the operand stack.
+//!
+//! Lua is dynamically typed, but many instructions push values of a
+//! known type, like literals and the results of arithmetic. When such a
+//! value is used where it would fail at runtime, the bytecode is unusual
+//! enough that it's more likely the parser tracked the stack wrong.
+use super::Op;
+
+/// Type of a value, as far as it's known from the instruction that pushed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Unknown,
+    Number,
+    String,
+    Table,
+    Function,
+    /// Result of `not`, which is `nil` or `1`.
+    Bool,
+}
+
+impl Type {
+    /// Type of the value pushed by the instruction.
+    pub(super) fn of_op(op: &Op) -> Type {
+        match op {
+            Op::PushInt { .. }
+            | Op::PushNum { .. }
+            | Op::PushNegNum { .. }
+            | Op::Add
+            | Op::AddI { .. }
+            | Op::Sub
+            | Op::Mult
+            | Op::Div
+            | Op::Pow
+            | Op::Minus => Type::Number,
+            Op::PushString { .. } | Op::Concat { .. } => Type::String,
+            Op::CreateTable { .. } => Type::Table,
+            Op::Closure { .. } => Type::Function,
+            Op::Not | Op::PushNilJump => Type::Bool,
+            _ => Type::Unknown,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Unknown => "unknown",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Table => "table",
+            Type::Function => "function",
+            Type::Bool => "boolean",
+        }
+    }
+
+    /// Whether the value can be an operand of arithmetic.
+    ///
+    /// Strings are converted to numbers, and tables may have tag methods.
+    pub fn is_arith_operand(self) -> bool {
+        !matches!(self, Type::Function | Type::Bool)
+    }
+
+    /// Whether the value can be an operand of a concatenation.
+    ///
+    /// Numbers are converted to strings, and tables may have tag methods.
+    pub fn is_concat_operand(self) -> bool {
+        !matches!(self, Type::Function | Type::Bool)
+    }
+
+    /// Whether the value can be called.
+    ///
+    /// Tables may have a `function` tag method.
+    pub fn is_callable(self) -> bool {
+        matches!(self, Type::Unknown | Type::Function | Type::Table)
+    }
+
+    /// Whether two values can be compared for order.
+    ///
+    /// Numbers and strings can only be compared with their own type.
+    pub fn is_ordered_with(self, other: Type) -> bool {
+        !matches!(
+            (self, other),
+            (Type::Number, Type::String)
+                | (Type::String, Type::Number)
+                | (Type::Function | Type::Bool, _)
+                | (_, Type::Function | Type::Bool)
+        )
+    }
+}
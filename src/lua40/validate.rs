@@ -0,0 +1,223 @@
+//! Round-trip validation.
+//!
+//! Recompiles decompiled source with a Lua 4.0 compiler, and compares the
+//! bytecode it produces against the original chunk. Faithful source compiles
+//! back to the same instructions and constants in every function.
+//!
+//! Debug information isn't compared, since the decompiled source is laid out
+//! differently and local variable names may have been generated.
+use std::fmt::{self, Formatter};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::Command;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::Decoder;
+use super::{path_name, Instr, OpMode, Proto};
+use crate::errors::{Error, Result};
+
+/// Validates decompiled source by recompiling it with an external `luac`.
+///
+/// Not available on WebAssembly, which can't run processes.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Validator {
+    luac: PathBuf,
+}
+
+/// Differences found between the original and recompiled bytecode.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Difference in one function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Index of each nested function on the way from the main function.
+    pub path: Vec<usize>,
+    pub message: String,
+}
+
+/// Counter that keeps the temporary files of concurrent validations apart.
+#[cfg(not(target_arch = "wasm32"))]
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Validator {
+    /// Validator that compiles with the Lua 4.0 `luac` binary at the given path.
+    pub fn new(luac: impl Into<PathBuf>) -> Self {
+        Self { luac: luac.into() }
+    }
+
+    /// Compile the source and compare it against the original main function.
+    pub fn validate(&self, original: &Proto, source: &str) -> Result<Report> {
+        let code = self.compile(source)?;
+        let recompiled = Decoder::new(&code).decode()?;
+        Ok(Report {
+            mismatches: compare(original, &recompiled),
+        })
+    }
+
+    /// Compile the source into a chunk.
+    pub fn compile(&self, source: &str) -> Result<Vec<u8>> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let base = std::env::temp_dir().join(format!("luad-{}-{id}", std::process::id()));
+        let source_path = base.with_extension("lua");
+        let chunk_path = base.with_extension("out");
+
+        std::fs::write(&source_path, source)?;
+        let output = Command::new(&self.luac)
+            .arg("-o")
+            .arg(&chunk_path)
+            .arg(&source_path)
+            .output();
+        let code = match output {
+            Ok(output) if output.status.success() => {
+                std::fs::read(&chunk_path).map_err(Error::from)
+            }
+            Ok(output) => Error::new_compiler(format!(
+                "{} failed: {}",
+                self.luac.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into(),
+            Err(err) => Err(err.into()),
+        };
+
+        // Clean up regardless of the outcome; the files may not exist.
+        let _ = std::fs::remove_file(&source_path);
+        let _ = std::fs::remove_file(&chunk_path);
+
+        code
+    }
+}
+
+impl Report {
+    /// Checks whether the recompiled bytecode matches the original.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return writeln!(f, "bytecode matches");
+        }
+        for mismatch in &self.mismatches {
+            writeln!(f, "{mismatch}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "function {}: {}", path_name(&self.path), self.message)
+    }
+}
+
+/// Compare two functions and their nested functions.
+pub fn compare(original: &Proto, recompiled: &Proto) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    compare_at(&mut vec![], original, recompiled, &mut mismatches);
+    mismatches
+}
+
+fn compare_at(
+    path: &mut Vec<usize>,
+    original: &Proto,
+    recompiled: &Proto,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    let mut report = |message: String| {
+        mismatches.push(Mismatch {
+            path: path.clone(),
+            message,
+        })
+    };
+
+    if original.num_params != recompiled.num_params {
+        report(format!(
+            "{} parameters, recompiled with {}",
+            original.num_params, recompiled.num_params
+        ));
+    }
+    if original.is_vararg != recompiled.is_vararg {
+        report(format!(
+            "vararg is {}, recompiled as {}",
+            original.is_vararg, recompiled.is_vararg
+        ));
+    }
+    if original.max_stack != recompiled.max_stack {
+        report(format!(
+            "maximum stack size {}, recompiled with {}",
+            original.max_stack, recompiled.max_stack
+        ));
+    }
+
+    // Later instructions usually differ as a consequence of the first
+    // difference, so only that one is reported.
+    let instrs = original.instrs.iter().zip(recompiled.instrs.iter());
+    if let Some((pc, (expected, actual))) = instrs
+        .enumerate()
+        .find(|(_, (expected, actual))| !same_instr(expected, actual))
+    {
+        report(format!(
+            "instruction {} is {expected}, recompiled as {actual}",
+            pc + 1
+        ));
+    } else if original.instrs.len() != recompiled.instrs.len() {
+        report(format!(
+            "{} instructions, recompiled with {}",
+            original.instrs.len(),
+            recompiled.instrs.len()
+        ));
+    }
+
+    if original.constants.strings != recompiled.constants.strings {
+        report(format!(
+            "string constants {:?}, recompiled as {:?}",
+            original.constants.strings, recompiled.constants.strings
+        ));
+    }
+    // Compared by bits, so a NaN constant matches itself.
+    let bits = |numbers: &[f64]| numbers.iter().map(|n| n.to_bits()).collect::<Vec<_>>();
+    if bits(&original.constants.numbers) != bits(&recompiled.constants.numbers) {
+        report(format!(
+            "number constants {:?}, recompiled as {:?}",
+            original.constants.numbers, recompiled.constants.numbers
+        ));
+    }
+
+    let (expected, actual) = (original.protos(), recompiled.protos());
+    if expected.len() != actual.len() {
+        report(format!(
+            "{} nested functions, recompiled with {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        path.push(index);
+        compare_at(path, expected, actual, mismatches);
+        path.pop();
+    }
+}
+
+/// Compare the opcode and the arguments it uses.
+///
+/// The chunks may have been compiled with different instruction
+/// layouts, so the encoded instructions can't be compared directly.
+pub(super) fn same_instr(a: &Instr, b: &Instr) -> bool {
+    a.opcode == b.opcode
+        && match a.opcode.mode() {
+            OpMode::None => true,
+            OpMode::U => a.u == b.u,
+            OpMode::S => a.s == b.s,
+            OpMode::AB => a.a == b.a && a.b == b.b,
+        }
+}
@@ -0,0 +1,199 @@
+//! Stack verification of a function's code.
+//!
+//! Simulates the values each instruction pops and pushes along every path
+//! through the code, as the virtual machine would. Bytecode that was patched
+//! or damaged usually throws the stack off long before the parser trips over
+//! it, so the imbalance is reported at the instruction where it starts.
+use super::{Instr, Opcode, Proto, MULT_RET};
+use crate::errors::{Error, Result};
+
+/// Instruction where the stack goes wrong, and what went wrong.
+pub(crate) struct Imbalance {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Proto {
+    /// Checks that no path through the function's code pops values that aren't
+    /// on the stack, or pushes more than [Proto::max_stack], and that every path
+    /// into an instruction reaches it with the same number of values.
+    ///
+    /// Nested functions aren't verified.
+    pub fn verify_stack(&self) -> Result<()> {
+        match self.stack_imbalance() {
+            Some(imbalance) => Error::new_decoder(format!(
+                "stack imbalance at instruction {}: {}",
+                imbalance.offset + 1,
+                imbalance.message
+            ))
+            .with_context(format!("function {}:{}", self.source, self.line_defined))
+            .into(),
+            None => Ok(()),
+        }
+    }
+
+    /// First instruction found to throw the stack off, if any.
+    pub(crate) fn stack_imbalance(&self) -> Option<Imbalance> {
+        self.stack_heights().err()
+    }
+
+    /// Number of values on the stack before each instruction, or `None` for
+    /// the instructions that can't be reached.
+    pub(crate) fn stack_heights(&self) -> std::result::Result<Vec<Option<u32>>, Imbalance> {
+        let mut heights: Vec<Option<u32>> = vec![None; self.instrs.len()];
+        let mut pending = Vec::new();
+        if let Some(first) = heights.first_mut() {
+            // The `arg` table of a vararg function sits above its parameters.
+            *first = Some(self.num_params + self.is_vararg as u32);
+            pending.push(0);
+        }
+
+        while let Some(pc) = pending.pop() {
+            let Some(height) = heights[pc] else {
+                continue;
+            };
+            let successors = match successors(pc, &self.instrs[pc], height) {
+                Ok(successors) => successors,
+                Err(message) => {
+                    return Err(Imbalance {
+                        offset: pc,
+                        message,
+                    })
+                }
+            };
+
+            for (next, after) in successors {
+                if after > self.max_stack {
+                    return Err(Imbalance {
+                        offset: pc,
+                        message: format!("leaves {after} values on a stack of {}", self.max_stack),
+                    });
+                }
+                // Jumps out of bounds are reported by the control flow analysis.
+                match heights.get_mut(next) {
+                    Some(Some(before)) if *before != after => {
+                        return Err(Imbalance {
+                            offset: next,
+                            message: format!(
+                                "reached with {before} values on one path and {after} on another"
+                            ),
+                        });
+                    }
+                    Some(Some(_)) | None => {}
+                    Some(slot) => {
+                        *slot = Some(after);
+                        pending.push(next);
+                    }
+                }
+            }
+        }
+
+        Ok(heights)
+    }
+}
+
+/// Instructions that can run after the one at `pc`, with the number
+/// of values on the stack each is reached with.
+fn successors(
+    pc: usize,
+    instr: &Instr,
+    values: u32,
+) -> std::result::Result<Vec<(usize, u32)>, String> {
+    use Opcode::*;
+
+    let pop = |n: u32| {
+        values
+            .checked_sub(n)
+            .ok_or_else(|| format!("pops {n} values with {values} on the stack"))
+    };
+    let callee = |offset: u32| {
+        if offset < values {
+            Ok(())
+        } else {
+            Err(format!(
+                "calls the function at stack offset {offset} with {values} values on the stack"
+            ))
+        }
+    };
+    let next = pc + 1;
+    let target = instr.jump_target(pc).unwrap_or(next);
+
+    let successors = match instr.opcode {
+        End => vec![],
+        Return if instr.u > values => {
+            return Err(format!(
+                "returns from stack offset {} with {values} values on the stack",
+                instr.u
+            ))
+        }
+        Return => vec![],
+        Call => {
+            callee(instr.a)?;
+            // All the results of a call count as one value until they're
+            // passed on by the call or return that uses them.
+            let results = if instr.b == MULT_RET { 1 } else { instr.b };
+            vec![(next, instr.a + results)]
+        }
+        TailCall => {
+            callee(instr.a)?;
+            vec![]
+        }
+
+        PushNil => vec![(next, values + instr.u)],
+        Pop => vec![(next, pop(instr.u)?)],
+
+        PushInt | PushString | PushNum | PushNegNum | PushUpvalue | GetLocal | GetGlobal
+        | CreateTable => vec![(next, values + 1)],
+        GetTable => vec![(next, pop(2)? + 1)],
+        GetDotted | GetIndexed => vec![(next, pop(1)? + 1)],
+        PushSelf => vec![(next, pop(1)? + 2)],
+
+        SetLocal | SetGlobal => vec![(next, pop(1)?)],
+        SetTable | SetList => vec![(next, pop(instr.b)?)],
+        SetMap => vec![(next, pop(instr.u.saturating_mul(2))?)],
+
+        Add | Sub | Mult | Div | Pow => vec![(next, pop(2)? + 1)],
+        AddI | Minus | Not => vec![(next, pop(1)? + 1)],
+        Concat => vec![(next, pop(instr.u)? + 1)],
+
+        JumpNe | JumpEq | JumpLt | JumpLe | JumpGt | JumpGe => {
+            let after = pop(2)?;
+            vec![(next, after), (target, after)]
+        }
+        JumpTrue | JumpFalse => {
+            let after = pop(1)?;
+            vec![(next, after), (target, after)]
+        }
+        // The value is only popped when the jump isn't taken.
+        JumpOnTrue | JumpOnFalse => vec![(next, pop(1)?), (target, values)],
+        Jump => vec![(target, values)],
+        // Skips the `PUSHINT 1` after it.
+        PushNilJump => vec![(pc + 2, values + 1)],
+
+        // Empty loops jump past the end with the control values removed.
+        ForPrep => {
+            let outside = pop(3)?;
+            vec![(next, values), (target, outside)]
+        }
+        ForLoop => {
+            let outside = pop(3)?;
+            vec![(next, outside), (target, values)]
+        }
+        // The table's first key and value are pushed above it.
+        LForPrep => {
+            let outside = pop(1)?;
+            vec![(next, values + 2), (target, outside)]
+        }
+        LForLoop => {
+            let outside = pop(3)?;
+            vec![(next, outside), (target, values)]
+        }
+
+        Closure => vec![(next, pop(instr.b)? + 1)],
+
+        // What the instruction does to the stack isn't known.
+        Unknown => vec![],
+    };
+
+    Ok(successors)
+}
@@ -0,0 +1,120 @@
+//! Cross references of the globals read and written by a function
+//! and its nested functions.
+//!
+//! Found from the bytecode alone, so they're cheap to gather and don't
+//! depend on the function decompiling.
+use std::collections::BTreeMap;
+use std::fmt::{self, Formatter};
+
+use super::{Constant, Opcode, Proto, ProtoPath};
+use crate::LuaString;
+
+/// Where each global is read and written, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Xrefs {
+    pub globals: BTreeMap<LuaString, Vec<Xref>>,
+}
+
+/// Instruction reading or writing a global.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xref {
+    /// Path of the function the instruction is in.
+    pub path: ProtoPath,
+    /// Index of the instruction in the function's code.
+    pub offset: usize,
+    pub access: Access,
+    /// Source line of the instruction, when debug information is present.
+    pub line: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Read by [Opcode::GetGlobal].
+    Read,
+    /// Written by [Opcode::SetGlobal].
+    Write,
+}
+
+impl Proto {
+    /// Where the function and its nested functions read and write each global,
+    /// with paths relative to this function.
+    pub fn global_xrefs(&self) -> Xrefs {
+        let mut xrefs = Xrefs::default();
+        for (path, proto) in self.iter_protos() {
+            for instruction in proto.instructions() {
+                let access = match instruction.opcode {
+                    Opcode::GetGlobal => Access::Read,
+                    Opcode::SetGlobal => Access::Write,
+                    _ => continue,
+                };
+                let Some(Constant::String(name)) = instruction.constant else {
+                    continue;
+                };
+                xrefs.globals.entry(name.clone()).or_default().push(Xref {
+                    path: path.clone(),
+                    offset: instruction.offset,
+                    access,
+                    line: proto.line_at(instruction.offset),
+                });
+            }
+        }
+        xrefs
+    }
+}
+
+impl Xrefs {
+    /// References to the global, in the order of the functions and instructions.
+    pub fn get(&self, name: impl Into<LuaString>) -> &[Xref] {
+        self.globals
+            .get(&name.into())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Globals that are read, but never written by the function.
+    ///
+    /// These come from the host or other scripts, like library functions
+    /// or configuration.
+    pub fn inputs(&self) -> impl Iterator<Item = &LuaString> {
+        self.globals
+            .iter()
+            .filter(|(_, xrefs)| xrefs.iter().all(|xref| xref.access == Access::Read))
+            .map(|(name, _)| name)
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// One line per reference, with tab separated fields, numbering
+/// instructions from 1 like a disassembly listing.
+///
+/// ```text
+/// print    read    main.0:1    line 2
+/// ```
+impl fmt::Display for Xrefs {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (name, xrefs) in &self.globals {
+            for xref in xrefs {
+                write!(
+                    f,
+                    "{name}\t{}\t{}:{}",
+                    xref.access,
+                    xref.path,
+                    xref.offset + 1
+                )?;
+                if let Some(line) = xref.line {
+                    write!(f, "\tline {line}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,741 @@
+//! Lua 5.0 Decoder.
+//!
+//! Lua 5.0 replaced the stack machine with a register based virtual machine.
+//!
+//! # Opcodes
+//!
+//! ```text
+//!     8      9       9      6
+//!  ______ _______ _______ ____
+//! |  A   |   B   |   C   | Op |
+//! |  A   |      Bx       | Op |
+//! |  A   |      sBx      | Op |
+//! ```
+
+#![allow(dead_code)]
+use std::fmt::{self, Formatter};
+use std::io::Cursor;
+
+use crate::errors::{Error, Result};
+use crate::lstring::LuaString;
+use crate::reader::CodeReader;
+
+pub use crate::reader::{Endian, Limits, NumberType};
+
+const LUA_VERSION: u8 = 0x50;
+const ID_CHUNK: u8 = 27;
+const SIGNATURE: &str = "Lua";
+#[allow(clippy::excessive_precision)]
+const TEST_NUMBER: f64 = 3.14159265358979323846E7;
+
+/// Register and constant arguments `B` and `C` greater or equal to
+/// this value are indices into the constant table.
+const MAX_STACK: u32 = 250;
+
+/// As per `lopcodes.h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Move = 0,
+    LoadK,
+    LoadBool,
+    LoadNil,
+    GetUpval,
+
+    GetGlobal,
+    GetTable,
+
+    SetGlobal,
+    SetUpval,
+    SetTable,
+
+    NewTable,
+
+    SelfOp,
+
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Unm,
+    Not,
+
+    Concat,
+
+    Jmp,
+
+    Eq,
+    Lt,
+    Le,
+
+    Test,
+
+    Call,
+    TailCall,
+    Return,
+
+    ForLoop,
+
+    TForLoop,
+    TForPrep,
+
+    SetList,
+    SetListO,
+
+    Close,
+    Closure = 34,
+}
+
+/// Layout of an instruction's arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpMode {
+    ABC,
+    ABx,
+    AsBx,
+}
+
+/// Decoded instruction.
+#[derive(Debug, Clone, Copy)]
+pub struct Instr {
+    pub opcode: Opcode,
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    /// Arguments `B` and `C` combined into one unsigned value.
+    pub bx: u32,
+    /// Arguments `B` and `C` combined into one signed value.
+    pub sbx: i32,
+}
+
+/// Chunk header.
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Lua version, `0x50` for Lua 5.0.
+    pub version: u8,
+    pub endianess: Endian,
+    /// Size of C `int` in bytes.
+    pub size_int: u8,
+    /// Size of C `size_t` in bytes.
+    pub size_t: u8,
+    /// Size of an instruction in bytes.
+    pub size_instr: u8,
+    /// Size of the opcode field in bits.
+    pub size_op: u8,
+    /// Size of the instruction argument `A` in bits.
+    pub size_a: u8,
+    /// Size of the instruction argument `B` in bits.
+    pub size_b: u8,
+    /// Size of the instruction argument `C` in bits.
+    pub size_c: u8,
+    pub number_type: NumberType,
+}
+
+/// Function prototype.
+#[derive(Debug)]
+pub struct Proto {
+    code: Box<[u32]>,
+    instrs: Box<[Instr]>,
+    source: String,
+    line_defined: u32,
+    num_upvalues: u8,
+    num_params: u8,
+    is_vararg: bool,
+    max_stack: u8,
+    lines: Box<[u32]>,
+    locals: Box<[Local]>,
+    upvalues: Box<[String]>,
+    constants: Box<[Constant]>,
+    protos: Box<[Proto]>,
+}
+
+/// Debug information for local variable.
+#[derive(Debug)]
+struct Local {
+    varname: String,
+    /// Point where variable is live.
+    startpc: u32,
+    /// Point where variable is dead.
+    endpc: u32,
+}
+
+/// Constant value referenced by instructions.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Nil,
+    Number(f64),
+    String(LuaString),
+}
+
+/// Lua 5.0 bytecode chunk decoder.
+pub struct Decoder<'a> {
+    reader: CodeReader<Cursor<&'a [u8]>>,
+    header: Header,
+    limits: Limits,
+    /// Nesting depth of the function being read.
+    depth: u32,
+}
+
+/// Disassembly listing of a function and its nested functions.
+pub struct ProtoDump<'a> {
+    proto: &'a Proto,
+}
+
+// ============================================================================
+
+/// Type tags of constants, as per `lua.h`.
+const LUA_TNIL: u8 = 0;
+const LUA_TNUMBER: u8 = 3;
+const LUA_TSTRING: u8 = 4;
+
+/// Creates a mask with `n` 1 bits at position `p`.
+macro_rules! mask1 {
+    ($n:expr, $p:expr) => {
+        (!(!0u32 << $n) << $p)
+    };
+}
+
+// ============================================================================
+
+impl TryFrom<u32> for Opcode {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        use Opcode::*;
+
+        Ok(match value {
+            0 => Move,
+            1 => LoadK,
+            2 => LoadBool,
+            3 => LoadNil,
+            4 => GetUpval,
+            5 => GetGlobal,
+            6 => GetTable,
+            7 => SetGlobal,
+            8 => SetUpval,
+            9 => SetTable,
+            10 => NewTable,
+            11 => SelfOp,
+            12 => Add,
+            13 => Sub,
+            14 => Mul,
+            15 => Div,
+            16 => Pow,
+            17 => Unm,
+            18 => Not,
+            19 => Concat,
+            20 => Jmp,
+            21 => Eq,
+            22 => Lt,
+            23 => Le,
+            24 => Test,
+            25 => Call,
+            26 => TailCall,
+            27 => Return,
+            28 => ForLoop,
+            29 => TForLoop,
+            30 => TForPrep,
+            31 => SetList,
+            32 => SetListO,
+            33 => Close,
+            34 => Closure,
+            _ => return Error::new_decoder(format!("unknown opcode: 0x{value:02x}")).into(),
+        })
+    }
+}
+
+impl Opcode {
+    /// Name as printed by `luac -l`.
+    pub fn name(self) -> &'static str {
+        use Opcode::*;
+
+        match self {
+            Move => "MOVE",
+            LoadK => "LOADK",
+            LoadBool => "LOADBOOL",
+            LoadNil => "LOADNIL",
+            GetUpval => "GETUPVAL",
+            GetGlobal => "GETGLOBAL",
+            GetTable => "GETTABLE",
+            SetGlobal => "SETGLOBAL",
+            SetUpval => "SETUPVAL",
+            SetTable => "SETTABLE",
+            NewTable => "NEWTABLE",
+            SelfOp => "SELF",
+            Add => "ADD",
+            Sub => "SUB",
+            Mul => "MUL",
+            Div => "DIV",
+            Pow => "POW",
+            Unm => "UNM",
+            Not => "NOT",
+            Concat => "CONCAT",
+            Jmp => "JMP",
+            Eq => "EQ",
+            Lt => "LT",
+            Le => "LE",
+            Test => "TEST",
+            Call => "CALL",
+            TailCall => "TAILCALL",
+            Return => "RETURN",
+            ForLoop => "FORLOOP",
+            TForLoop => "TFORLOOP",
+            TForPrep => "TFORPREP",
+            SetList => "SETLIST",
+            SetListO => "SETLISTO",
+            Close => "CLOSE",
+            Closure => "CLOSURE",
+        }
+    }
+
+    /// Argument layout, as per `luaP_opmodes` in `lopcodes.c`.
+    pub fn mode(self) -> OpMode {
+        use Opcode::*;
+
+        match self {
+            LoadK | GetGlobal | SetGlobal | SetList | SetListO | Closure => OpMode::ABx,
+            Jmp | ForLoop | TForPrep => OpMode::AsBx,
+            _ => OpMode::ABC,
+        }
+    }
+}
+
+impl Header {
+    /// Position of instruction argument `C`.
+    fn pos_c(&self) -> u32 {
+        self.size_op as u32
+    }
+
+    /// Position of instruction argument `B`.
+    fn pos_b(&self) -> u32 {
+        self.pos_c() + self.size_c as u32
+    }
+
+    /// Position of instruction argument `A`.
+    fn pos_a(&self) -> u32 {
+        self.pos_b() + self.size_b as u32
+    }
+
+    /// Size of instruction argument `Bx`.
+    fn size_bx(&self) -> u32 {
+        self.size_c as u32 + self.size_b as u32
+    }
+
+    /// Max value of instruction argument `sBx` (signed int).
+    fn max_arg_sbx(&self) -> i32 {
+        // 1 bit taken up by sign.
+        (mask1!(self.size_bx(), 0) >> 1) as i32
+    }
+
+    /// Decode an instruction word.
+    pub fn decode_instr(&self, word: u32) -> Result<Instr> {
+        let opcode = Opcode::try_from(word & mask1!(self.size_op, 0))?;
+        let bx = (word >> self.pos_c()) & mask1!(self.size_bx(), 0);
+        Ok(Instr {
+            opcode,
+            a: (word >> self.pos_a()) & mask1!(self.size_a, 0),
+            b: (word >> self.pos_b()) & mask1!(self.size_b, 0),
+            c: (word >> self.pos_c()) & mask1!(self.size_c, 0),
+            bx,
+            sbx: bx as i32 - self.max_arg_sbx(),
+        })
+    }
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self {
+            version: LUA_VERSION,
+            endianess: Endian::Little,
+            size_int: 4,
+            size_t: 4,
+            size_instr: 4,
+            size_op: 6,
+            size_a: 8,
+            size_b: 9,
+            size_c: 9,
+            number_type: NumberType::F64,
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            version,
+            endianess,
+            size_int,
+            size_t,
+            size_instr,
+            size_op,
+            size_a,
+            size_b,
+            size_c,
+            number_type,
+        } = self;
+        write!(f, "version: {version:02x}, endianess: {endianess:?}; int: {size_int}B; size_t: {size_t}B; instruction: {size_instr}B; opcode: {size_op}bits; A: {size_a}bits; B: {size_b}bits; C: {size_c}bits; Number: {number_type:?}")
+    }
+}
+
+impl Proto {
+    pub fn source(&self) -> &str {
+        self.source.as_str()
+    }
+
+    pub fn instrs(&self) -> &[Instr] {
+        &self.instrs
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    pub fn protos(&self) -> &[Proto] {
+        &self.protos
+    }
+
+    /// Disassembly listing similar to `luac -l`.
+    pub fn dump(&self) -> ProtoDump<'_> {
+        ProtoDump { proto: self }
+    }
+
+    /// Source line of the instruction, when debug information is present.
+    fn line_at(&self, pc: usize) -> Option<u32> {
+        self.lines.get(pc).cloned()
+    }
+}
+
+/// Decode only the chunk header.
+pub fn read_header(code: &[u8]) -> Result<Header> {
+    let mut decoder = Decoder::new(code);
+    decoder.read_header()?;
+    Ok(decoder.header)
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(code: &'a [u8]) -> Self {
+        Self {
+            reader: CodeReader::new(code),
+            header: Header::default(),
+            limits: Limits::default(),
+            depth: 0,
+        }
+    }
+
+    /// Fail to decode chunks that exceed the limits.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn decode(&mut self) -> Result<Proto> {
+        self.read_header()?;
+
+        // Top level function
+        self.read_function("=?")
+    }
+
+    /// Number of bytes decoded so far, which after decoding is the size of the chunk.
+    pub fn position(&self) -> u64 {
+        self.reader.position()
+    }
+}
+
+impl<'a> Decoder<'a> {
+    fn read_header(&mut self) -> Result<()> {
+        if self.reader.read_u8()? != ID_CHUNK {
+            return Error::new_decoder("chunk bytemark must be 'Esc'(27)").into();
+        }
+
+        let mut signature = [0u8; SIGNATURE.len()];
+        self.reader.read_bytes(&mut signature)?;
+        if signature != SIGNATURE.as_bytes() {
+            return Error::new_decoder("bad signature").into();
+        }
+
+        let version = self.reader.read_u8()?;
+        if version != LUA_VERSION {
+            return Error::new_decoder(format!(
+                "expected Lua version 5.0(0x50), found: {version:02x}"
+            ))
+            .into();
+        }
+
+        let endianess = if self.reader.read_u8()? == 0 {
+            Endian::Big
+        } else {
+            Endian::Little
+        };
+
+        self.header = Header {
+            version,
+            endianess,
+            size_int: self.reader.read_u8()?,
+            size_t: self.reader.read_u8()?,
+            size_instr: self.reader.read_u8()?,
+            size_op: self.reader.read_u8()?,
+            size_a: self.reader.read_u8()?,
+            size_b: self.reader.read_u8()?,
+            size_c: self.reader.read_u8()?,
+            number_type: {
+                let size_number = self.reader.read_u8()?;
+                match size_number {
+                    4 => NumberType::F32,
+                    8 => NumberType::F64,
+                    _ => {
+                        return Error::new_decoder(format!("unknown number size: {size_number}"))
+                            .into()
+                    }
+                }
+            },
+        };
+
+        if self.header.size_instr != 4 {
+            return Error::new_decoder(format!(
+                "unsupported instruction size: {}",
+                self.header.size_instr
+            ))
+            .into();
+        }
+
+        self.reader.set_endian(self.header.endianess);
+        self.reader.set_size_int(self.header.size_int as usize);
+        self.reader.set_size_t(self.header.size_t as usize);
+
+        let test_number = self.reader.read_number(self.header.number_type)?;
+        let expected = match self.header.number_type {
+            NumberType::F32 => TEST_NUMBER as f32 as f64,
+            NumberType::F64 => TEST_NUMBER,
+            NumberType::I32 | NumberType::I64 => TEST_NUMBER.trunc(),
+        };
+        if test_number != expected {
+            return Error::new_decoder("unknown number format").into();
+        }
+
+        Ok(())
+    }
+
+    fn read_function(&mut self, parent_source: &str) -> Result<Proto> {
+        if self.depth >= self.limits.max_depth {
+            return Error::new_decoder(format!(
+                "functions nested deeper than the limit of {}",
+                self.limits.max_depth
+            ))
+            .into();
+        }
+        self.depth += 1;
+        let proto = self.read_function_body(parent_source);
+        self.depth -= 1;
+        proto
+    }
+
+    fn read_function_body(&mut self, parent_source: &str) -> Result<Proto> {
+        // Nested functions leave out the source name of their parent.
+        let source = match self.reader.read_string()? {
+            source if source.is_empty() => parent_source.to_string(),
+            source => source,
+        };
+        let line_defined = self.reader.read_int()?;
+        let num_upvalues = self.reader.read_u8()?;
+        let num_params = self.reader.read_u8()?;
+        let is_vararg = self.reader.read_u8()? != 0;
+        let max_stack = self.reader.read_u8()?;
+
+        let lines = self.read_lines()?;
+        let locals = self.read_locals()?;
+        let upvalues = self.read_upvalues()?;
+        let constants = self.read_constants()?;
+        let protos = self.read_protos(&source)?;
+        let code = self.read_code()?;
+
+        let instrs = code
+            .iter()
+            .map(|word| self.header.decode_instr(*word))
+            .collect::<Result<Box<[Instr]>>>()?;
+
+        Ok(Proto {
+            code,
+            instrs,
+            source,
+            line_defined,
+            num_upvalues,
+            num_params,
+            is_vararg,
+            max_stack,
+            lines,
+            locals,
+            upvalues,
+            constants,
+            protos,
+        })
+    }
+
+    fn read_lines(&mut self) -> Result<Box<[u32]>> {
+        let n = self.read_count("line info", u32::MAX)?;
+        let mut lines = vec![];
+        for _ in 0..n {
+            lines.push(self.reader.read_int()?);
+        }
+        Ok(lines.into_boxed_slice())
+    }
+
+    fn read_locals(&mut self) -> Result<Box<[Local]>> {
+        let n = self.read_count("local", u32::MAX)?;
+        let mut locals = vec![];
+        for _ in 0..n {
+            locals.push(Local {
+                varname: self.reader.read_string()?,
+                startpc: self.reader.read_int()?,
+                endpc: self.reader.read_int()?,
+            });
+        }
+        Ok(locals.into_boxed_slice())
+    }
+
+    fn read_upvalues(&mut self) -> Result<Box<[String]>> {
+        let n = self.read_count("upvalue name", u32::MAX)?;
+        let mut upvalues = vec![];
+        for _ in 0..n {
+            upvalues.push(self.reader.read_string()?);
+        }
+        Ok(upvalues.into_boxed_slice())
+    }
+
+    fn read_constants(&mut self) -> Result<Box<[Constant]>> {
+        let n = self.read_count("constant", self.limits.max_constants)?;
+        let mut constants = vec![];
+        for _ in 0..n {
+            let constant = match self.reader.read_u8()? {
+                LUA_TNIL => Constant::Nil,
+                LUA_TNUMBER => Constant::Number(self.reader.read_number(self.header.number_type)?),
+                LUA_TSTRING => Constant::String(self.reader.read_lua_string()?),
+                tag => return Error::new_decoder(format!("unknown constant type: {tag}")).into(),
+            };
+            constants.push(constant);
+        }
+        Ok(constants.into_boxed_slice())
+    }
+
+    fn read_protos(&mut self, source: &str) -> Result<Box<[Proto]>> {
+        let n = self.read_count("function", self.limits.max_constants)?;
+        let mut protos = vec![];
+        for _ in 0..n {
+            protos.push(self.read_function(source)?);
+        }
+        Ok(protos.into_boxed_slice())
+    }
+
+    fn read_code(&mut self) -> Result<Box<[u32]>> {
+        let n = self.read_count("instruction", self.limits.max_code)?;
+        let mut code = vec![];
+        for _ in 0..n {
+            code.push(self.reader.read_u32()?);
+        }
+        Ok(code.into_boxed_slice())
+    }
+
+    /// Read the number of elements in a list.
+    fn read_count(&mut self, what: &str, max: u32) -> Result<u32> {
+        let n = self.reader.read_int()?;
+        if n > max {
+            return Error::new_decoder(format!("{n} {what}s exceed the limit of {max}")).into();
+        }
+        Ok(n)
+    }
+}
+
+impl<'a> ProtoDump<'a> {
+    fn fmt_proto(&self, f: &mut Formatter, proto: &Proto) -> fmt::Result {
+        writeln!(
+            f,
+            "function <{}:{}> ({} instructions)",
+            proto.source,
+            proto.line_defined,
+            proto.instrs.len()
+        )?;
+        writeln!(
+            f,
+            "{}{} params, {} stack, {} upvalues, {} locals, {} constants, {} functions",
+            proto.num_params,
+            if proto.is_vararg { "+" } else { "" },
+            proto.max_stack,
+            proto.num_upvalues,
+            proto.locals.len(),
+            proto.constants.len(),
+            proto.protos.len()
+        )?;
+
+        for (pc, instr) in proto.instrs.iter().enumerate() {
+            write!(f, "\t{}\t", pc + 1)?;
+            match proto.line_at(pc) {
+                Some(line) => write!(f, "[{line}]\t")?,
+                None => write!(f, "[-]\t")?,
+            }
+            write!(f, "{:<9}\t", instr.opcode.name())?;
+            match instr.opcode.mode() {
+                OpMode::ABC => write!(f, "{} {} {}", instr.a, instr.b, instr.c)?,
+                OpMode::ABx => write!(f, "{} {}", instr.a, instr.bx)?,
+                OpMode::AsBx => write!(f, "{} {}", instr.a, instr.sbx)?,
+            }
+            self.fmt_comment(f, proto, pc, instr)?;
+            writeln!(f)?;
+        }
+        writeln!(f)?;
+
+        for child in proto.protos.iter() {
+            self.fmt_proto(f, child)?;
+        }
+
+        Ok(())
+    }
+
+    fn fmt_comment(
+        &self,
+        f: &mut Formatter,
+        proto: &Proto,
+        pc: usize,
+        instr: &Instr,
+    ) -> fmt::Result {
+        use Opcode::*;
+
+        match instr.opcode {
+            LoadK | GetGlobal | SetGlobal => {
+                write!(f, "\t; ")?;
+                fmt_constant(f, proto, instr.bx)
+            }
+            GetTable | SelfOp if instr.c >= MAX_STACK => {
+                write!(f, "\t; ")?;
+                fmt_constant(f, proto, instr.c - MAX_STACK)
+            }
+            SetTable | Add | Sub | Mul | Div | Pow | Eq | Lt | Le => {
+                let mut sep = "\t; ";
+                for arg in [instr.b, instr.c] {
+                    if arg >= MAX_STACK {
+                        write!(f, "{sep}")?;
+                        fmt_constant(f, proto, arg - MAX_STACK)?;
+                        sep = " ";
+                    }
+                }
+                Ok(())
+            }
+            Jmp | ForLoop | TForPrep => {
+                write!(f, "\t; to {}", pc as i64 + 2 + instr.sbx as i64)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn fmt_constant(f: &mut Formatter, proto: &Proto, index: u32) -> fmt::Result {
+    match proto.constants.get(index as usize) {
+        Some(Constant::Nil) => write!(f, "nil"),
+        Some(Constant::Number(n)) => write!(f, "{n}"),
+        Some(Constant::String(s)) => write!(f, "{s:?}"),
+        None => write!(f, "<bad constant {index}>"),
+    }
+}
+
+impl<'a> fmt::Display for ProtoDump<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_proto(f, self.proto)
+    }
+}
@@ -0,0 +1,857 @@
+//! Lua 5.1 Decompiler.
+//!
+//! # Opcodes
+//!
+//! ```text
+//!      9       9      8     6
+//!  _______ _______ ______ ____
+//! |   B   |   C   |  A   | Op |
+//! |      Bx       |  A   | Op |
+//! |      sBx      |  A   | Op |
+//! ```
+
+#![allow(dead_code)]
+use std::fmt::{self, Formatter};
+use std::io::Cursor;
+use std::ops::Range;
+
+use crate::diagnostics::Diagnostics;
+use crate::errors::{Error, Result};
+use crate::lstring::LuaString;
+use crate::options::{DecompileOptions, Output};
+use crate::reader::CodeReader;
+
+pub mod ast;
+mod flow;
+mod locals;
+mod parser;
+mod scribe;
+
+pub use crate::reader::{Endian, Limits, NumberType};
+pub use ast::Syntax;
+pub use parser::Parser;
+pub use scribe::Scribe;
+
+const LUA_VERSION: u8 = 0x51;
+const LUAC_FORMAT: u8 = 0;
+const ID_CHUNK: u8 = 27;
+const SIGNATURE: &str = "Lua";
+
+/// Arguments `B` and `C` with this bit set are indices into
+/// the constant table instead of registers.
+const BIT_RK: u32 = 1 << 8;
+
+/// Number of list items to accumulate before a `SETLIST` instruction.
+const FIELDS_PER_FLUSH: u32 = 50;
+
+/// As per `lopcodes.h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Move = 0,
+    LoadK,
+    LoadBool,
+    LoadNil,
+    GetUpval,
+
+    GetGlobal,
+    GetTable,
+
+    SetGlobal,
+    SetUpval,
+    SetTable,
+
+    NewTable,
+
+    SelfOp,
+
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Unm,
+    Not,
+    Len,
+
+    Concat,
+
+    Jmp,
+
+    Eq,
+    Lt,
+    Le,
+
+    Test,
+    TestSet,
+
+    Call,
+    TailCall,
+    Return,
+
+    ForLoop,
+    ForPrep,
+
+    TForLoop,
+    SetList,
+
+    Close,
+    Closure,
+
+    VarArg = 37,
+}
+
+/// Layout of an instruction's arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpMode {
+    ABC,
+    ABx,
+    AsBx,
+}
+
+/// Decoded instruction.
+#[derive(Debug, Clone, Copy)]
+pub struct Instr {
+    pub opcode: Opcode,
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    /// Arguments `B` and `C` combined into one unsigned value.
+    pub bx: u32,
+    /// Arguments `B` and `C` combined into one signed value.
+    pub sbx: i32,
+}
+
+/// Chunk header.
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Lua version, `0x51` for Lua 5.1.
+    pub version: u8,
+    /// Format version, `0` for the official format.
+    pub format: u8,
+    pub endianess: Endian,
+    /// Size of C `int` in bytes.
+    pub size_int: u8,
+    /// Size of C `size_t` in bytes.
+    pub size_t: u8,
+    /// Size of an instruction in bytes.
+    pub size_instr: u8,
+    /// Size of `lua_Number` in bytes.
+    pub size_number: u8,
+    /// Numbers are integers instead of floating point.
+    pub integral: bool,
+}
+
+/// Function prototype.
+#[derive(Debug)]
+pub struct Proto {
+    code: Box<[u32]>,
+    instrs: Box<[Instr]>,
+    source: String,
+    line_defined: u32,
+    last_line_defined: u32,
+    num_upvalues: u8,
+    num_params: u8,
+    is_vararg: u8,
+    max_stack: u8,
+    constants: Box<[Constant]>,
+    protos: Box<[Proto]>,
+    lines: Box<[u32]>,
+    locals: Box<[Local]>,
+    upvalues: Box<[String]>,
+}
+
+/// Debug information for local variable.
+#[derive(Debug)]
+struct Local {
+    varname: String,
+    /// Point where variable is live.
+    startpc: u32,
+    /// Point where variable is dead.
+    endpc: u32,
+}
+
+/// Constant value referenced by instructions.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(LuaString),
+}
+
+/// Lua 5.1 bytecode chunk decoder.
+pub struct Decoder<'a> {
+    reader: CodeReader<Cursor<&'a [u8]>>,
+    header: Header,
+    limits: Limits,
+    /// Nesting depth of the function being read.
+    depth: u32,
+}
+
+/// Disassembly listing of a function and its nested functions.
+pub struct ProtoDump<'a> {
+    proto: &'a Proto,
+}
+
+/// Disassembly listing of a range of instructions in a function.
+pub struct RangeDump<'a> {
+    proto: &'a Proto,
+    range: Range<usize>,
+}
+
+// ============================================================================
+
+/// Type tags of constants, as per `lua.h`.
+const LUA_TNIL: u8 = 0;
+const LUA_TBOOLEAN: u8 = 1;
+const LUA_TNUMBER: u8 = 3;
+const LUA_TSTRING: u8 = 4;
+
+/// Flag in `is_vararg` marking functions that use `...`.
+const VARARG_ISVARARG: u8 = 2;
+
+/// Creates a mask with `n` 1 bits at position `p`.
+macro_rules! mask1 {
+    ($n:expr, $p:expr) => {
+        (!(!0u32 << $n) << $p)
+    };
+}
+
+const SIZE_OP: u32 = 6;
+const SIZE_A: u32 = 8;
+const SIZE_B: u32 = 9;
+const SIZE_C: u32 = 9;
+const SIZE_BX: u32 = SIZE_B + SIZE_C;
+const POS_A: u32 = SIZE_OP;
+const POS_C: u32 = POS_A + SIZE_A;
+const POS_B: u32 = POS_C + SIZE_C;
+const POS_BX: u32 = POS_C;
+const MAX_ARG_SBX: i32 = (mask1!(SIZE_BX, 0) >> 1) as i32;
+
+// ============================================================================
+
+impl TryFrom<u32> for Opcode {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        use Opcode::*;
+
+        Ok(match value {
+            0 => Move,
+            1 => LoadK,
+            2 => LoadBool,
+            3 => LoadNil,
+            4 => GetUpval,
+            5 => GetGlobal,
+            6 => GetTable,
+            7 => SetGlobal,
+            8 => SetUpval,
+            9 => SetTable,
+            10 => NewTable,
+            11 => SelfOp,
+            12 => Add,
+            13 => Sub,
+            14 => Mul,
+            15 => Div,
+            16 => Mod,
+            17 => Pow,
+            18 => Unm,
+            19 => Not,
+            20 => Len,
+            21 => Concat,
+            22 => Jmp,
+            23 => Eq,
+            24 => Lt,
+            25 => Le,
+            26 => Test,
+            27 => TestSet,
+            28 => Call,
+            29 => TailCall,
+            30 => Return,
+            31 => ForLoop,
+            32 => ForPrep,
+            33 => TForLoop,
+            34 => SetList,
+            35 => Close,
+            36 => Closure,
+            37 => VarArg,
+            _ => return Error::new_decoder(format!("unknown opcode: 0x{value:02x}")).into(),
+        })
+    }
+}
+
+impl Opcode {
+    /// Name as printed by `luac -l`.
+    pub fn name(self) -> &'static str {
+        use Opcode::*;
+
+        match self {
+            Move => "MOVE",
+            LoadK => "LOADK",
+            LoadBool => "LOADBOOL",
+            LoadNil => "LOADNIL",
+            GetUpval => "GETUPVAL",
+            GetGlobal => "GETGLOBAL",
+            GetTable => "GETTABLE",
+            SetGlobal => "SETGLOBAL",
+            SetUpval => "SETUPVAL",
+            SetTable => "SETTABLE",
+            NewTable => "NEWTABLE",
+            SelfOp => "SELF",
+            Add => "ADD",
+            Sub => "SUB",
+            Mul => "MUL",
+            Div => "DIV",
+            Mod => "MOD",
+            Pow => "POW",
+            Unm => "UNM",
+            Not => "NOT",
+            Len => "LEN",
+            Concat => "CONCAT",
+            Jmp => "JMP",
+            Eq => "EQ",
+            Lt => "LT",
+            Le => "LE",
+            Test => "TEST",
+            TestSet => "TESTSET",
+            Call => "CALL",
+            TailCall => "TAILCALL",
+            Return => "RETURN",
+            ForLoop => "FORLOOP",
+            ForPrep => "FORPREP",
+            TForLoop => "TFORLOOP",
+            SetList => "SETLIST",
+            Close => "CLOSE",
+            Closure => "CLOSURE",
+            VarArg => "VARARG",
+        }
+    }
+
+    /// Argument layout, as per `luaP_opmodes` in `lopcodes.c`.
+    pub fn mode(self) -> OpMode {
+        use Opcode::*;
+
+        match self {
+            LoadK | GetGlobal | SetGlobal | Closure => OpMode::ABx,
+            Jmp | ForLoop | ForPrep => OpMode::AsBx,
+            _ => OpMode::ABC,
+        }
+    }
+
+    /// Whether arguments `B` and `C` may refer to constants,
+    /// as per `OpArgK` in `luaP_opmodes`.
+    fn rk_args(self) -> (bool, bool) {
+        use Opcode::*;
+
+        match self {
+            GetTable | SelfOp => (false, true),
+            SetTable | Add | Sub | Mul | Div | Mod | Pow | Eq | Lt | Le => (true, true),
+            _ => (false, false),
+        }
+    }
+}
+
+impl Instr {
+    /// Decode an instruction word.
+    pub fn decode(word: u32) -> Result<Self> {
+        let opcode = Opcode::try_from(word & mask1!(SIZE_OP, 0))?;
+        let bx = (word >> POS_BX) & mask1!(SIZE_BX, 0);
+        Ok(Instr {
+            opcode,
+            a: (word >> POS_A) & mask1!(SIZE_A, 0),
+            b: (word >> POS_B) & mask1!(SIZE_B, 0),
+            c: (word >> POS_C) & mask1!(SIZE_C, 0),
+            bx,
+            sbx: bx as i32 - MAX_ARG_SBX,
+        })
+    }
+}
+
+/// Checks whether a `B` or `C` argument refers to a constant.
+fn is_k(arg: u32) -> bool {
+    arg & BIT_RK != 0
+}
+
+/// Constant index of a `B` or `C` argument.
+fn index_k(arg: u32) -> u32 {
+    arg & !BIT_RK
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self {
+            version: LUA_VERSION,
+            format: LUAC_FORMAT,
+            endianess: Endian::Little,
+            size_int: 4,
+            size_t: 4,
+            size_instr: 4,
+            size_number: 8,
+            integral: false,
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            version,
+            format,
+            endianess,
+            size_int,
+            size_t,
+            size_instr,
+            size_number,
+            integral,
+        } = self;
+        write!(f, "version: {version:02x}, format: {format}, endianess: {endianess:?}; int: {size_int}B; size_t: {size_t}B; instruction: {size_instr}B; Number: {size_number}B; integral: {integral}")
+    }
+}
+
+impl Proto {
+    pub fn source(&self) -> &str {
+        self.source.as_str()
+    }
+
+    pub fn instrs(&self) -> &[Instr] {
+        &self.instrs
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    pub fn protos(&self) -> &[Proto] {
+        &self.protos
+    }
+
+    /// Disassembly listing similar to `luac -l`.
+    pub fn dump(&self) -> ProtoDump<'_> {
+        ProtoDump { proto: self }
+    }
+
+    /// Disassembly listing of the instructions in the range,
+    /// without the function header.
+    pub fn dump_range(&self, range: Range<usize>) -> RangeDump<'_> {
+        RangeDump { proto: self, range }
+    }
+
+    /// Remove the debug information of the function and
+    /// its nested functions, like `luac -s`.
+    pub fn strip(&mut self) {
+        self.lines = Box::new([]);
+        self.locals = Box::new([]);
+        self.upvalues = Box::new([]);
+        for child in self.protos.iter_mut() {
+            child.strip();
+        }
+    }
+
+    fn is_vararg(&self) -> bool {
+        self.is_vararg & VARARG_ISVARARG != 0
+    }
+
+    /// Source line of the instruction, when debug information is present.
+    fn line_at(&self, pc: usize) -> Option<u32> {
+        self.lines.get(pc).cloned()
+    }
+}
+
+/// Decompile a chunk into source, with the default style.
+///
+/// Shorthand for decoding with a [Decoder], parsing with a [Parser]
+/// and writing the syntax tree with a [Scribe].
+pub fn decompile(code: &[u8]) -> Result<String> {
+    let proto = Decoder::new(code).decode()?;
+    let syntax = Parser::new(&proto).parse()?;
+    let mut buf = String::new();
+    Scribe::default().fmt_syntax(&mut buf, &syntax)?;
+    Ok(buf)
+}
+
+/// Decompile a chunk into source, with the default style, recovering
+/// from errors unless the tolerance is strict.
+///
+/// The parser doesn't report diagnostics yet, so none are returned.
+pub fn decompile_with(code: &[u8], options: &DecompileOptions) -> Result<Output> {
+    let proto = Decoder::new(code).decode()?;
+    let syntax = Parser::new(&proto)
+        .lenient(options.tolerance.is_tolerant())
+        .parse()?;
+    let mut source = String::new();
+    Scribe::default().fmt_syntax(&mut source, &syntax)?;
+    Ok(Output {
+        source,
+        diagnostics: Diagnostics::new(),
+    })
+}
+
+/// Decode only the chunk header.
+pub fn read_header(code: &[u8]) -> Result<Header> {
+    let mut decoder = Decoder::new(code);
+    decoder.read_header()?;
+    Ok(decoder.header)
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(code: &'a [u8]) -> Self {
+        Self {
+            reader: CodeReader::new(code),
+            header: Header::default(),
+            limits: Limits::default(),
+            depth: 0,
+        }
+    }
+
+    /// Fail to decode chunks that exceed the limits.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn decode(&mut self) -> Result<Proto> {
+        self.read_header()?;
+
+        // Top level function
+        self.read_function("=?")
+    }
+
+    /// Number of bytes decoded so far, which after decoding is the size of the chunk.
+    pub fn position(&self) -> u64 {
+        self.reader.position()
+    }
+}
+
+impl<'a> Decoder<'a> {
+    fn read_header(&mut self) -> Result<()> {
+        if self.reader.read_u8()? != ID_CHUNK {
+            return Error::new_decoder("chunk bytemark must be 'Esc'(27)").into();
+        }
+
+        let mut signature = [0u8; SIGNATURE.len()];
+        self.reader.read_bytes(&mut signature)?;
+        if signature != SIGNATURE.as_bytes() {
+            return Error::new_decoder("bad signature").into();
+        }
+
+        let version = self.reader.read_u8()?;
+        if version != LUA_VERSION {
+            return Error::new_decoder(format!(
+                "expected Lua version 5.1(0x51), found: {version:02x}"
+            ))
+            .into();
+        }
+
+        let format = self.reader.read_u8()?;
+        if format != LUAC_FORMAT {
+            return Error::new_decoder(format!("unknown chunk format: {format}")).into();
+        }
+
+        self.header = Header {
+            version,
+            format,
+            endianess: if self.reader.read_u8()? == 0 {
+                Endian::Big
+            } else {
+                Endian::Little
+            },
+            size_int: self.reader.read_u8()?,
+            size_t: self.reader.read_u8()?,
+            size_instr: self.reader.read_u8()?,
+            size_number: self.reader.read_u8()?,
+            integral: self.reader.read_u8()? != 0,
+        };
+
+        if self.header.size_instr != 4 {
+            return Error::new_decoder(format!(
+                "unsupported instruction size: {}",
+                self.header.size_instr
+            ))
+            .into();
+        }
+
+        self.reader.set_endian(self.header.endianess);
+        self.reader.set_size_int(self.header.size_int as usize);
+        self.reader.set_size_t(self.header.size_t as usize);
+
+        Ok(())
+    }
+
+    fn read_function(&mut self, parent_source: &str) -> Result<Proto> {
+        if self.depth >= self.limits.max_depth {
+            return Error::new_decoder(format!(
+                "functions nested deeper than the limit of {}",
+                self.limits.max_depth
+            ))
+            .into();
+        }
+        self.depth += 1;
+        let proto = self.read_function_body(parent_source);
+        self.depth -= 1;
+        proto
+    }
+
+    fn read_function_body(&mut self, parent_source: &str) -> Result<Proto> {
+        // Nested functions leave out the source name of their parent.
+        let source = match self.reader.read_string()? {
+            source if source.is_empty() => parent_source.to_string(),
+            source => source,
+        };
+        let line_defined = self.reader.read_int()?;
+        let last_line_defined = self.reader.read_int()?;
+        let num_upvalues = self.reader.read_u8()?;
+        let num_params = self.reader.read_u8()?;
+        let is_vararg = self.reader.read_u8()?;
+        let max_stack = self.reader.read_u8()?;
+
+        let code = self.read_code()?;
+        let constants = self.read_constants()?;
+        let protos = self.read_protos(&source)?;
+        let lines = self.read_lines()?;
+        let locals = self.read_locals()?;
+        let upvalues = self.read_upvalues()?;
+
+        let instrs = code
+            .iter()
+            .map(|word| Instr::decode(*word))
+            .collect::<Result<Box<[Instr]>>>()?;
+
+        Ok(Proto {
+            code,
+            instrs,
+            source,
+            line_defined,
+            last_line_defined,
+            num_upvalues,
+            num_params,
+            is_vararg,
+            max_stack,
+            constants,
+            protos,
+            lines,
+            locals,
+            upvalues,
+        })
+    }
+
+    fn read_code(&mut self) -> Result<Box<[u32]>> {
+        let n = self.read_count("instruction", self.limits.max_code)?;
+        let mut code = vec![];
+        for _ in 0..n {
+            code.push(self.reader.read_u32()?);
+        }
+        Ok(code.into_boxed_slice())
+    }
+
+    fn read_number(&mut self) -> Result<f64> {
+        match (self.header.integral, self.header.size_number) {
+            (false, 4) => Ok(self.reader.read_f32()? as f64),
+            (false, 8) => self.reader.read_f64(),
+            (true, 4) => Ok(self.reader.read_u32()? as i32 as f64),
+            (true, 8) => Ok(self.reader.read_u64()? as i64 as f64),
+            (_, size) => Error::new_decoder(format!("unknown number size: {size}")).into(),
+        }
+    }
+
+    fn read_constants(&mut self) -> Result<Box<[Constant]>> {
+        let n = self.read_count("constant", self.limits.max_constants)?;
+        let mut constants = vec![];
+        for _ in 0..n {
+            let constant = match self.reader.read_u8()? {
+                LUA_TNIL => Constant::Nil,
+                LUA_TBOOLEAN => Constant::Bool(self.reader.read_u8()? != 0),
+                LUA_TNUMBER => Constant::Number(self.read_number()?),
+                LUA_TSTRING => Constant::String(self.reader.read_lua_string()?),
+                tag => return Error::new_decoder(format!("unknown constant type: {tag}")).into(),
+            };
+            constants.push(constant);
+        }
+        Ok(constants.into_boxed_slice())
+    }
+
+    fn read_protos(&mut self, source: &str) -> Result<Box<[Proto]>> {
+        let n = self.read_count("function", self.limits.max_constants)?;
+        let mut protos = vec![];
+        for _ in 0..n {
+            protos.push(self.read_function(source)?);
+        }
+        Ok(protos.into_boxed_slice())
+    }
+
+    fn read_lines(&mut self) -> Result<Box<[u32]>> {
+        let n = self.read_count("line info", u32::MAX)?;
+        let mut lines = vec![];
+        for _ in 0..n {
+            lines.push(self.reader.read_int()?);
+        }
+        Ok(lines.into_boxed_slice())
+    }
+
+    fn read_locals(&mut self) -> Result<Box<[Local]>> {
+        let n = self.read_count("local", u32::MAX)?;
+        let mut locals = vec![];
+        for _ in 0..n {
+            locals.push(Local {
+                varname: self.reader.read_string()?,
+                startpc: self.reader.read_int()?,
+                endpc: self.reader.read_int()?,
+            });
+        }
+        Ok(locals.into_boxed_slice())
+    }
+
+    fn read_upvalues(&mut self) -> Result<Box<[String]>> {
+        let n = self.read_count("upvalue name", u32::MAX)?;
+        let mut upvalues = vec![];
+        for _ in 0..n {
+            upvalues.push(self.reader.read_string()?);
+        }
+        Ok(upvalues.into_boxed_slice())
+    }
+
+    /// Read the number of elements in a list.
+    fn read_count(&mut self, what: &str, max: u32) -> Result<u32> {
+        let n = self.reader.read_int()?;
+        if n > max {
+            return Error::new_decoder(format!("{n} {what}s exceed the limit of {max}")).into();
+        }
+        Ok(n)
+    }
+}
+
+impl<'a> ProtoDump<'a> {
+    fn fmt_proto(&self, f: &mut Formatter, proto: &Proto) -> fmt::Result {
+        writeln!(
+            f,
+            "function <{}:{},{}> ({} instructions)",
+            proto.source,
+            proto.line_defined,
+            proto.last_line_defined,
+            proto.instrs.len()
+        )?;
+        writeln!(
+            f,
+            "{}{} params, {} slots, {} upvalues, {} locals, {} constants, {} functions",
+            proto.num_params,
+            if proto.is_vararg() { "+" } else { "" },
+            proto.max_stack,
+            proto.num_upvalues,
+            proto.locals.len(),
+            proto.constants.len(),
+            proto.protos.len()
+        )?;
+
+        for (pc, instr) in proto.instrs.iter().enumerate() {
+            fmt_instr(f, proto, pc, instr)?;
+            writeln!(f)?;
+        }
+        writeln!(f)?;
+
+        for child in proto.protos.iter() {
+            self.fmt_proto(f, child)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write one line of a listing, without the line break.
+fn fmt_instr(f: &mut Formatter, proto: &Proto, pc: usize, instr: &Instr) -> fmt::Result {
+    write!(f, "\t{}\t", pc + 1)?;
+    match proto.line_at(pc) {
+        Some(line) => write!(f, "[{line}]\t")?,
+        None => write!(f, "[-]\t")?,
+    }
+    write!(f, "{:<9}\t", instr.opcode.name())?;
+    match instr.opcode.mode() {
+        OpMode::ABC => {
+            let (b_k, c_k) = instr.opcode.rk_args();
+            write!(
+                f,
+                "{} {} {}",
+                instr.a,
+                fmt_rk(instr.b, b_k),
+                fmt_rk(instr.c, c_k)
+            )?
+        }
+        OpMode::ABx => write!(f, "{} {}", instr.a, instr.bx)?,
+        OpMode::AsBx => write!(f, "{} {}", instr.a, instr.sbx)?,
+    }
+    fmt_comment(f, proto, pc, instr)
+}
+
+fn fmt_comment(f: &mut Formatter, proto: &Proto, pc: usize, instr: &Instr) -> fmt::Result {
+    use Opcode::*;
+
+    match instr.opcode {
+        LoadK | GetGlobal | SetGlobal => {
+            write!(f, "\t; ")?;
+            fmt_constant(f, proto, instr.bx)
+        }
+        GetUpval | SetUpval => match proto.upvalues.get(instr.b as usize) {
+            Some(name) => write!(f, "\t; {name}"),
+            None => write!(f, "\t; -"),
+        },
+        GetTable | SelfOp if is_k(instr.c) => {
+            write!(f, "\t; ")?;
+            fmt_constant(f, proto, index_k(instr.c))
+        }
+        SetTable | Add | Sub | Mul | Div | Mod | Pow | Eq | Lt | Le => {
+            let mut sep = "\t; ";
+            for arg in [instr.b, instr.c] {
+                if is_k(arg) {
+                    write!(f, "{sep}")?;
+                    fmt_constant(f, proto, index_k(arg))?;
+                    sep = " ";
+                }
+            }
+            Ok(())
+        }
+        Jmp | ForLoop | ForPrep => {
+            write!(f, "\t; to {}", pc as i64 + 2 + instr.sbx as i64)
+        }
+        Closure => write!(f, "\t; function {}", instr.bx),
+        _ => Ok(()),
+    }
+}
+
+/// Constant arguments are printed negative, like `luac -l` does.
+fn fmt_rk(arg: u32, rk: bool) -> i64 {
+    if rk && is_k(arg) {
+        -1 - index_k(arg) as i64
+    } else {
+        arg as i64
+    }
+}
+
+fn fmt_constant(f: &mut Formatter, proto: &Proto, index: u32) -> fmt::Result {
+    match proto.constants.get(index as usize) {
+        Some(Constant::Nil) => write!(f, "nil"),
+        Some(Constant::Bool(b)) => write!(f, "{b}"),
+        Some(Constant::Number(n)) => write!(f, "{n}"),
+        Some(Constant::String(s)) => write!(f, "{s:?}"),
+        None => write!(f, "<bad constant {index}>"),
+    }
+}
+
+impl<'a> fmt::Display for ProtoDump<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_proto(f, self.proto)
+    }
+}
+
+impl<'a> fmt::Display for RangeDump<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let instrs = self
+            .proto
+            .instrs
+            .get(self.range.clone())
+            .unwrap_or_default();
+        for (offset, instr) in instrs.iter().enumerate() {
+            fmt_instr(f, self.proto, self.range.start + offset, instr)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+//! Abstract syntax tree.
+use crate::lstring::LuaString;
+
+/// Abstract syntax tree.
+#[derive(Debug)]
+pub struct Syntax {
+    pub root: Block,
+}
+
+/// Block of statements.
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+}
+
+// ----------------------------------------------------------------------------
+// Statements
+// ----------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    LocalVar(LocalVar),
+    Assign(Assign),
+    Call(Call),
+    Return(Return),
+    If(If),
+    While(While),
+    Repeat(Repeat),
+    NumericFor(NumericFor),
+    GenericFor(GenericFor),
+    Break,
+    Failed(Failed),
+}
+
+/// Local variable declaration.
+///
+/// ```lua
+/// local {names} = {rhs}
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocalVar {
+    pub names: Vec<String>,
+    pub rhs: Vec<Expr>,
+}
+
+/// Assignment to one or more variables or table fields.
+///
+/// ```lua
+/// {targets} = {rhs}
+/// ```
+#[derive(Debug, Clone)]
+pub struct Assign {
+    pub targets: Vec<Expr>,
+    pub rhs: Vec<Expr>,
+}
+
+/// Return statement.
+///
+/// ```lua
+/// return {values}
+/// ```
+#[derive(Debug, Clone)]
+pub struct Return {
+    pub values: Vec<Expr>,
+}
+
+/// Conditional statement.
+///
+/// ```lua
+/// if {cond} then {then} else {else_} end
+/// ```
+///
+/// An `else` block holding only another `if` is written as `elseif`.
+#[derive(Debug, Clone)]
+pub struct If {
+    pub cond: Expr,
+    pub then: Block,
+    pub else_: Option<Block>,
+}
+
+/// Loop that tests its condition before the body.
+///
+/// ```lua
+/// while {cond} do {body} end
+/// ```
+#[derive(Debug, Clone)]
+pub struct While {
+    pub cond: Expr,
+    pub body: Block,
+}
+
+/// Loop that tests its condition after the body.
+///
+/// ```lua
+/// repeat {body} until {cond}
+/// ```
+#[derive(Debug, Clone)]
+pub struct Repeat {
+    pub body: Block,
+    pub cond: Expr,
+}
+
+/// Loop counting a variable from a start to a limit.
+///
+/// ```lua
+/// for {var} = {start}, {limit}, {step} do {body} end
+/// ```
+#[derive(Debug, Clone)]
+pub struct NumericFor {
+    pub var: String,
+    pub start: Expr,
+    pub limit: Expr,
+    /// Left out when it's 1.
+    pub step: Option<Expr>,
+    pub body: Block,
+}
+
+/// Loop calling an iterator function.
+///
+/// ```lua
+/// for {names} in {exprs} do {body} end
+/// ```
+#[derive(Debug, Clone)]
+pub struct GenericFor {
+    pub names: Vec<String>,
+    pub exprs: Vec<Expr>,
+    pub body: Block,
+}
+
+/// Instructions that couldn't be decompiled.
+///
+/// Only produced by a lenient parser, which keeps the disassembly
+/// in place of the statements the instructions held.
+#[derive(Debug, Clone)]
+pub struct Failed {
+    pub message: String,
+    /// Index of the first instruction.
+    pub start: u32,
+    /// Index of the last instruction, inclusive.
+    pub end: u32,
+    /// Disassembly listing of the instructions.
+    pub listing: String,
+}
+
+// ----------------------------------------------------------------------------
+// Expressions
+// ----------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(LuaString),
+    /// Variable arguments `...`.
+    VarArg,
+    /// Variable access by name.
+    Name(String),
+    /// Table field access `{table}[{key}]`.
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Call>),
+    Binary(Box<BinExpr>),
+    Unary(Box<UnaryExpr>),
+    Table(Table),
+    Function(Box<Function>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub name: Expr,
+    /// Method name when called with `:`, which passes
+    /// the callee as the implicit `self` argument.
+    pub method: Option<String>,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BinExpr {
+    pub op: BinOp,
+    pub lhs: Expr,
+    pub rhs: Expr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnaryExpr {
+    pub op: UnaryOp,
+    pub rhs: Expr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    Len,
+}
+
+/// Table constructor.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    /// Positional items.
+    pub items: Vec<Expr>,
+    /// Keyed fields.
+    pub fields: Vec<(Expr, Expr)>,
+}
+
+/// Function constructor.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub is_vararg: bool,
+    pub body: Block,
+}
+
+// ============================================================================
+// Functions
+// ============================================================================
+
+impl Expr {
+    /// Logical `not` of the expression, simplified where
+    /// the meaning as a condition stays the same.
+    pub fn invert(self) -> Self {
+        match self {
+            Expr::Unary(unary_expr) if matches!(unary_expr.op, UnaryOp::Not) => unary_expr.rhs,
+            Expr::Bool(value) => Expr::Bool(!value),
+            Expr::Binary(bin_expr) => {
+                let BinExpr { op, lhs, rhs } = *bin_expr;
+                let (op, lhs, rhs) = match op {
+                    BinOp::Eq => (BinOp::Ne, lhs, rhs),
+                    BinOp::Ne => (BinOp::Eq, lhs, rhs),
+                    // De Morgan's laws.
+                    BinOp::And => (BinOp::Or, lhs.invert(), rhs.invert()),
+                    BinOp::Or => (BinOp::And, lhs.invert(), rhs.invert()),
+                    op => {
+                        let bin_expr = BinExpr { op, lhs, rhs };
+                        return Expr::logical_not(Expr::Binary(Box::new(bin_expr)));
+                    }
+                };
+                Expr::Binary(Box::new(BinExpr { op, lhs, rhs }))
+            }
+            expr => Expr::logical_not(expr),
+        }
+    }
+
+    /// Logical `not` of the expression.
+    pub fn logical_not(rhs: Expr) -> Self {
+        Expr::Unary(Box::new(UnaryExpr {
+            op: UnaryOp::Not,
+            rhs,
+        }))
+    }
+}
+
+impl BinOp {
+    /// Left and right binding power, as per `priority` in `lparser.c`.
+    pub fn priority(self) -> (u32, u32) {
+        match self {
+            BinOp::Add | BinOp::Sub => (6, 6),
+            BinOp::Mul | BinOp::Div | BinOp::Mod => (7, 7),
+            // Right associative.
+            BinOp::Pow => (10, 9),
+            BinOp::Concat => (5, 4),
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => (3, 3),
+            BinOp::And => (2, 2),
+            BinOp::Or => (1, 1),
+        }
+    }
+
+    /// Whether the operator is a keyword, which is spaced whatever the style.
+    pub fn is_keyword(self) -> bool {
+        matches!(self, BinOp::And | BinOp::Or)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Pow => "^",
+            BinOp::Concat => "..",
+            BinOp::Eq => "==",
+            BinOp::Ne => "~=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+        }
+    }
+}
+
+impl UnaryOp {
+    /// Priority of unary operators, as per `UNARY_PRIORITY` in `lparser.c`.
+    pub const PRIORITY: u32 = 8;
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "not ",
+            UnaryOp::Len => "#",
+        }
+    }
+}
@@ -0,0 +1,508 @@
+//! Jumps of a function, matched to the statements and operators they were compiled from.
+//!
+//! The Lua 5.1 compiler only jumps for a few constructs, each with its own
+//! pattern of instructions:
+//!
+//! - A conditional jump is a test, like `EQ` or `TEST`, followed by a `JMP`
+//!   that the test skips when it fails. Conditions with `and` and `or`
+//!   compile to a chain of them.
+//! - `and` and `or` used as values test a register and jump over the rest of
+//!   the expression, keeping the value they tested as the result.
+//! - Comparisons used as values jump to one of a pair of `LOADBOOL`s.
+//! - `while` loops end with a jump back to their condition,
+//!   and `repeat` loops with a conditional one.
+//! - `for` loops have instructions of their own, which jump
+//!   between the start and the end of the body.
+//!
+//! A jump emitted right where earlier jumps were headed takes them along, so
+//! those jump straight to its target. [Flow::resolve] and [Flow::redirect]
+//! undo this for the jumps that end up past the statement they belong to.
+use super::{Instr, Opcode as Op, Proto};
+
+/// How a test instruction, and the jump after it, are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Test {
+    /// Condition of a statement, like `if`, `while` or `until`.
+    Cond,
+    /// Operand of `and` or `or` used as a value.
+    Value,
+    /// Comparison used as a value, jumping to the `LOADBOOL` of its result.
+    Bool,
+}
+
+/// Conditional jump in a chain compiled from `and` and `or`.
+#[derive(Debug, Clone)]
+pub(super) struct ChainJump<T> {
+    /// Condition under which the jump is taken.
+    pub cond: T,
+    pub target: usize,
+    /// Instruction after the jump, run when it isn't taken.
+    pub next: usize,
+}
+
+/// How two neighbouring jumps in a chain are joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Join {
+    /// Both jumps go to the same place, so either condition takes it.
+    Or,
+    /// The first jump skips the second, so the second is only
+    /// taken when the first isn't.
+    AndNot,
+}
+
+/// Jumps of a function.
+pub(super) struct Flow<'a> {
+    proto: &'a Proto,
+    /// `repeat` loops, as the index of their first instruction
+    /// and of the jump back to it.
+    repeats: Vec<(usize, usize)>,
+    /// `while` loops, as the index of their first instruction
+    /// and of the jump back to it.
+    whiles: Vec<(usize, usize)>,
+    /// Use of each test instruction followed by a jump.
+    tests: Vec<Option<Test>>,
+}
+
+// ============================================================================
+
+/// Merge a chain of conditional jumps into a single jump, when they
+/// were compiled from one condition joined with `and` and `or`.
+///
+/// Neighbouring jumps are merged from the left, as long as the first goes
+/// where the second goes or right past it. The merged jump goes where the
+/// second goes, and is taken under the condition built by `join`.
+pub(super) fn merge_chain<T>(
+    mut jumps: Vec<ChainJump<T>>,
+    mut join: impl FnMut(Join, T, T) -> T,
+) -> Option<ChainJump<T>> {
+    while jumps.len() > 1 {
+        let (index, kind) = jumps.windows(2).enumerate().find_map(|(index, pair)| {
+            if pair[0].target == pair[1].target {
+                Some((index, Join::Or))
+            } else if pair[0].target == pair[1].next {
+                Some((index, Join::AndNot))
+            } else {
+                None
+            }
+        })?;
+        let first = jumps.remove(index);
+        let second = jumps.remove(index);
+        jumps.insert(
+            index,
+            ChainJump {
+                cond: join(kind, first.cond, second.cond),
+                target: second.target,
+                next: second.next,
+            },
+        );
+    }
+    jumps.pop()
+}
+
+/// Destination of a jump instruction.
+pub(super) fn jump_target(instrs: &[Instr], pc: usize) -> Option<usize> {
+    let instr = instrs.get(pc)?;
+    if !matches!(instr.opcode, Op::Jmp | Op::ForLoop | Op::ForPrep) {
+        return None;
+    }
+    let target = pc as i64 + 1 + instr.sbx as i64;
+    (0..=instrs.len() as i64)
+        .contains(&target)
+        .then_some(target as usize)
+}
+
+fn is_test(op: Op) -> bool {
+    matches!(op, Op::Eq | Op::Lt | Op::Le | Op::Test | Op::TestSet)
+}
+
+/// Destination of the jump after a test instruction.
+pub(super) fn test_target(instrs: &[Instr], pc: usize) -> Option<usize> {
+    if !is_test(instrs.get(pc)?.opcode) {
+        return None;
+    }
+    jump_target(instrs, pc + 1).filter(|_| instrs[pc + 1].opcode == Op::Jmp)
+}
+
+/// Checks whether the instruction at `pc` is a `JMP` of its own,
+/// rather than the second half of a conditional jump.
+pub(super) fn is_plain_jump(instrs: &[Instr], pc: usize) -> bool {
+    instrs.get(pc).is_some_and(|instr| instr.opcode == Op::Jmp)
+        && !(pc > 0 && is_test(instrs[pc - 1].opcode))
+}
+
+/// Checks whether the instructions at `pc` are the `LOADBOOL`s
+/// that load the result of a comparison used as a value.
+pub(super) fn is_bool_pair(instrs: &[Instr], pc: usize) -> bool {
+    match (instrs.get(pc), instrs.get(pc + 1)) {
+        (Some(first), Some(second)) => {
+            first.opcode == Op::LoadBool
+                && second.opcode == Op::LoadBool
+                && first.a == second.a
+                && (first.b, first.c) == (0, 1)
+                && (second.b, second.c) == (1, 0)
+        }
+        _ => false,
+    }
+}
+
+/// Register written by an instruction that computes a value into it.
+fn result_reg(instr: &Instr) -> Option<u32> {
+    use Op::*;
+
+    match instr.opcode {
+        Move | LoadK | LoadBool | LoadNil | GetUpval | GetGlobal | GetTable | NewTable | Add
+        | Sub | Mul | Div | Mod | Pow | Unm | Not | Len | Concat | VarArg | Closure => {
+            Some(instr.a)
+        }
+        Call if instr.c != 1 => Some(instr.a),
+        _ => None,
+    }
+}
+
+impl<'a> Flow<'a> {
+    pub fn new(proto: &'a Proto) -> Self {
+        let mut flow = Self {
+            proto,
+            repeats: vec![],
+            whiles: vec![],
+            tests: vec![None; proto.instrs.len()],
+        };
+        flow.whiles = flow.find_whiles();
+        flow.repeats = flow.find_repeats();
+
+        // Tests nested in the operands of a value are classified first.
+        for pc in (0..proto.instrs.len()).rev() {
+            flow.tests[pc] = flow.classify(pc);
+        }
+        flow
+    }
+
+    pub fn test(&self, pc: usize) -> Option<Test> {
+        self.tests.get(pc).copied().flatten()
+    }
+
+    /// `repeat` loops starting at the instruction, outermost first,
+    /// as the index of their jump back.
+    pub fn repeats_at(&self, pc: usize) -> impl Iterator<Item = usize> + '_ {
+        self.repeats
+            .iter()
+            .filter(move |(start, _)| *start == pc)
+            .map(|(_, jump)| *jump)
+    }
+
+    /// `while` loops starting at the instruction, outermost first,
+    /// as the index of their jump back.
+    pub fn whiles_at(&self, pc: usize) -> impl Iterator<Item = usize> + '_ {
+        self.whiles
+            .iter()
+            .filter(move |(start, _)| *start == pc)
+            .map(|(_, jump)| *jump)
+    }
+
+    /// First instruction of each loop body, which is jumped back to.
+    pub fn loop_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        let instrs = &self.proto.instrs;
+        let for_loops = (0..instrs.len()).filter_map(|pc| match instrs[pc].opcode {
+            Op::ForPrep => Some(pc + 1),
+            Op::Jmp => self.generic_for_at(pc).map(|_| pc + 1),
+            _ => None,
+        });
+        self.repeats
+            .iter()
+            .chain(&self.whiles)
+            .map(|(start, _)| *start)
+            .chain(for_loops)
+    }
+
+    /// Generic `for` loop whose jump to the `TFORLOOP` is at `pc`,
+    /// as the index of the `TFORLOOP`.
+    pub fn generic_for_at(&self, pc: usize) -> Option<usize> {
+        let instrs = &self.proto.instrs;
+        if !is_plain_jump(instrs, pc) {
+            return None;
+        }
+        let target = jump_target(instrs, pc)?;
+        let is_loop = target > pc
+            && instrs.get(target)?.opcode == Op::TForLoop
+            && instrs.get(target + 1)?.opcode == Op::Jmp
+            && jump_target(instrs, target + 1) == Some(pc + 1);
+        is_loop.then_some(target)
+    }
+
+    /// Number of instructions at `pc` that form part of an expression,
+    /// or zero when it's part of a statement.
+    pub fn expr_len(&self, pc: usize) -> usize {
+        let instrs = &self.proto.instrs;
+        let Some(instr) = instrs.get(pc) else {
+            return 0;
+        };
+        match instr.opcode {
+            Op::Move
+            | Op::LoadK
+            | Op::LoadNil
+            | Op::GetUpval
+            | Op::GetGlobal
+            | Op::GetTable
+            | Op::NewTable
+            | Op::SelfOp
+            | Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Mod
+            | Op::Pow
+            | Op::Unm
+            | Op::Not
+            | Op::Len
+            | Op::Concat
+            | Op::VarArg
+            | Op::SetList => 1,
+            Op::Call if instr.c != 1 => 1,
+            Op::LoadBool if instr.c == 0 => 1,
+            Op::LoadBool if is_bool_pair(instrs, pc) => 2,
+            Op::Closure => {
+                // Followed by a pseudo instruction for each upvalue.
+                let child = self.proto.protos.get(instr.bx as usize);
+                1 + child.map_or(0, |child| child.num_upvalues as usize)
+            }
+            Op::Eq | Op::Lt | Op::Le | Op::Test | Op::TestSet => match self.test(pc) {
+                Some(Test::Value | Test::Bool) => 2,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    /// Tests of the condition starting with the test at `pc`, which are joined
+    /// into one jump, and only have the operands of the next test between them.
+    ///
+    /// The longest run of tests whose jumps fit together is taken.
+    pub fn cond_chain(&self, pc: usize, end: usize) -> Vec<usize> {
+        let mut tests = vec![pc];
+        let mut next = pc + 2;
+        loop {
+            while next < end && self.expr_len(next) > 0 {
+                next += self.expr_len(next);
+            }
+            if next + 1 < end && self.test(next) == Some(Test::Cond) {
+                tests.push(next);
+                next += 2;
+            } else {
+                break;
+            }
+        }
+
+        while tests.len() > 1 && self.chain_target(&tests, true).is_none() {
+            tests.pop();
+        }
+        tests
+    }
+
+    /// Tests of the condition of a `repeat` loop, ending with the test
+    /// before the jump back at `jump`.
+    ///
+    /// The longest run of tests whose jumps fit together is taken.
+    pub fn until_chain(&self, start: usize, jump: usize) -> Vec<usize> {
+        let instrs = &self.proto.instrs;
+        let mut tests = vec![jump - 1];
+
+        // Earlier tests jump back to the start too, or further into the condition.
+        let mut next = jump - 1;
+        for pc in (start..next.saturating_sub(1)).rev() {
+            let is_member = self.test(pc) == Some(Test::Cond)
+                && test_target(instrs, pc)
+                    .is_some_and(|target| target == start || (pc + 2..=jump + 1).contains(&target))
+                && self.is_operands(pc + 2, next);
+            if is_member {
+                tests.insert(0, pc);
+                next = pc;
+            }
+        }
+
+        while tests.len() > 1 && self.chain_target(&tests, false).is_none() {
+            tests.remove(0);
+        }
+        tests
+    }
+
+    /// Tests of a comparison used as a value, starting with the test at `pc`,
+    /// and the index of the `LOADBOOL` pair they jump to.
+    pub fn bool_chain(&self, pc: usize) -> Option<(Vec<usize>, usize)> {
+        let instrs = &self.proto.instrs;
+        let target = test_target(instrs, pc)?;
+        let pair = if is_bool_pair(instrs, target) {
+            target
+        } else {
+            target.checked_sub(1)?
+        };
+        let tests = (pc..pair)
+            .filter(|&test| {
+                self.test(test) == Some(Test::Bool)
+                    && test_target(instrs, test).is_some_and(|t| t == pair || t == pair + 1)
+            })
+            .collect();
+        Some((tests, pair))
+    }
+
+    /// Where the jumps of the tests go once joined into one jump,
+    /// if they fit together.
+    pub fn chain_target(&self, tests: &[usize], resolve: bool) -> Option<usize> {
+        let jumps = tests
+            .iter()
+            .map(|&test| {
+                let target = test_target(&self.proto.instrs, test)?;
+                Some(ChainJump {
+                    cond: (),
+                    target: match resolve {
+                        true => self.resolve(target, test),
+                        false => target,
+                    },
+                    next: test + 2,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        merge_chain(jumps, |_, _, _| ()).map(|jump| jump.target)
+    }
+
+    /// Checks whether the instructions in the range only compute values.
+    fn is_operands(&self, start: usize, end: usize) -> bool {
+        let mut pc = start;
+        while pc < end {
+            match self.expr_len(pc) {
+                0 => return false,
+                len => pc += len,
+            }
+        }
+        pc == end
+    }
+
+    /// Where a jump to `target` from `from` leads, taking a jump back to
+    /// the start of a `while` loop from inside it to the end of its body.
+    ///
+    /// Those were jumps to the end of the body, which the loop's
+    /// own jump back took along.
+    pub fn resolve(&self, target: usize, from: usize) -> usize {
+        self.whiles
+            .iter()
+            .filter(|(start, jump)| *start == target && target <= from && from < *jump)
+            .map(|(_, jump)| *jump)
+            .min()
+            .unwrap_or(target)
+    }
+
+    /// Where a jump to `target` from the block starting at `start` was
+    /// headed, before a `break` right after the block took it along.
+    ///
+    /// Otherwise, a target past the `end` of the enclosing block is the
+    /// end of an enclosing `if` statement, which the `JMP` at `end` over
+    /// its `else` block took along.
+    pub fn redirect(&self, target: usize, start: usize, end: usize, exit: Option<usize>) -> usize {
+        let instrs = &self.proto.instrs;
+        let is_break = |pc: usize| {
+            is_plain_jump(instrs, pc)
+                && Some(target) == exit
+                && jump_target(instrs, pc).map(|t| self.resolve(t, pc)) == Some(target)
+        };
+        match (start..end.min(target)).find(|&pc| is_break(pc)) {
+            Some(pc) => pc,
+            None => target.min(end),
+        }
+    }
+
+    /// Checks whether a jump to `target` lands at `pc`, directly or
+    /// through a `JMP` there that took it along.
+    pub fn lands(&self, target: usize, pc: usize) -> bool {
+        let instrs = &self.proto.instrs;
+        target == pc
+            || (is_plain_jump(instrs, pc)
+                && jump_target(instrs, pc).map(|t| self.resolve(t, pc)) == Some(target))
+    }
+
+    fn classify(&self, pc: usize) -> Option<Test> {
+        let instrs = &self.proto.instrs;
+        let target = test_target(instrs, pc)?;
+        let instr = &instrs[pc];
+
+        if target > pc + 2 && (is_bool_pair(instrs, target) || is_bool_pair(instrs, target - 1)) {
+            return Some(Test::Bool);
+        }
+        match instr.opcode {
+            // Conditions test without setting, since the register isn't used.
+            Op::TestSet => Some(Test::Value),
+            Op::Test if self.is_value(pc, target) => Some(Test::Value),
+            _ => Some(Test::Cond),
+        }
+    }
+
+    /// Checks whether the instructions a `TEST` jumps over only compute the
+    /// other operand of `and` or `or` into the register it tests.
+    ///
+    /// An `if` statement assigning a local variable compiles the same, and
+    /// is taken for `and` or `or`, unless the local is declared in it.
+    fn is_value(&self, pc: usize, target: usize) -> bool {
+        let instrs = &self.proto.instrs;
+        let reg = instrs[pc].a;
+        if target <= pc + 2 {
+            return false;
+        }
+        // The other operand can end with an `and` or `or` of its own.
+        let writes = instrs.get(target - 1).and_then(result_reg) == Some(reg)
+            || (self.test(target - 2) == Some(Test::Value) && instrs[target - 2].a == reg);
+
+        let declares = self.proto.locals.iter().any(|local| {
+            let (start, end) = (local.startpc as usize, local.endpc as usize);
+            pc + 2 < start && start <= target && end <= target
+        });
+        writes && self.is_operands(pc + 2, target) && !declares
+    }
+
+    /// Find the `while` loops, which end with a jump back to the condition.
+    fn find_whiles(&self) -> Vec<(usize, usize)> {
+        let instrs = &self.proto.instrs;
+        let mut loops: Vec<(usize, usize)> = (0..instrs.len())
+            .filter(|&pc| {
+                is_plain_jump(instrs, pc)
+                    && !(pc > 0 && instrs[pc - 1].opcode == Op::TForLoop)
+                    && self.generic_for_at(pc).is_none()
+            })
+            .filter_map(|pc| {
+                let target = jump_target(instrs, pc)?;
+                (target <= pc).then_some((target, pc))
+            })
+            .collect();
+        loops.sort_by_key(|(start, jump)| (*start, std::cmp::Reverse(*jump)));
+        loops
+    }
+
+    /// Find the `repeat` loops, which end with a test that skips
+    /// the jump back to the start of the loop.
+    ///
+    /// Unlike a `while` loop, which jumps back unconditionally, the
+    /// condition is only tested once per iteration at the end.
+    fn find_repeats(&self) -> Vec<(usize, usize)> {
+        let instrs = &self.proto.instrs;
+        let mut loops: Vec<(usize, usize)> = (1..instrs.len())
+            .filter(|pc| matches!(instrs[pc - 1].opcode, Op::Eq | Op::Lt | Op::Le | Op::Test))
+            .filter_map(|pc| {
+                let start = test_target(instrs, pc - 1)?;
+                (start < pc).then_some((start, pc))
+            })
+            // Jumps back to the start of a `while` loop from inside it
+            // are jumps to the end of its body.
+            .filter(|(start, jump)| !self.whiles.iter().any(|(s, j)| s == start && jump < j))
+            .collect();
+
+        // Outer loops first, and only loops that nest properly.
+        loops.sort_by_key(|(start, jump)| (*start, std::cmp::Reverse(*jump)));
+        let mut nested: Vec<(usize, usize)> = vec![];
+        for (start, jump) in loops {
+            let crosses = nested
+                .iter()
+                .any(|(s, j)| *s < start && start <= *j && *j < jump);
+            if !crosses {
+                nested.push((start, jump));
+            }
+        }
+        nested
+    }
+}
@@ -0,0 +1,343 @@
+//! Local variables of functions stripped of debug information.
+//!
+//! The compiler allocates registers like a stack: local variables at the
+//! bottom, and the temporary values of the statement being compiled above
+//! them. A temporary is read once, by the instruction it was computed for,
+//! and before anything below it. A value that breaks this discipline must
+//! be a local variable, which is what [find_locals] looks for.
+use std::collections::HashSet;
+
+use super::flow::{is_bool_pair, jump_target, Flow, Test};
+use super::{is_k, Instr, Opcode as Op, Proto};
+
+/// Instructions writing the values of local variables, and the register they
+/// write, as found by following the registers through the function.
+pub(super) type LocalDefs = HashSet<(usize, u32)>;
+
+/// Value written to a register.
+#[derive(Debug, Clone, Copy)]
+struct Def {
+    /// Instruction that wrote it.
+    pc: usize,
+    /// Not read yet.
+    live: bool,
+    /// Found to be a local variable.
+    local: bool,
+}
+
+struct Stack {
+    defs: Vec<Option<Def>>,
+    /// Top of the stack set by the last instruction with an open number
+    /// of results, like a call or `...`.
+    top: Option<u32>,
+    found: LocalDefs,
+}
+
+// ============================================================================
+
+/// Find the values that are kept in local variables, rather than consumed
+/// as temporaries by the statement they were computed for.
+///
+/// A value is a local variable when:
+///
+/// - it's read more than once,
+/// - it's read while a temporary above it waits for another instruction,
+/// - a nested function captures it as an upvalue,
+/// - it's overwritten before being read,
+/// - or it's still unread at the end of a statement, or the start of a loop.
+///
+/// Locals that are only read once, by the next statement, pass for
+/// temporaries, which decompiles to equivalent code.
+pub(super) fn find_locals(proto: &Proto, flow: &Flow) -> LocalDefs {
+    let size = proto.max_stack.max(proto.num_params) as usize;
+    let mut stack = Stack {
+        defs: vec![None; size],
+        top: None,
+        found: LocalDefs::new(),
+    };
+    let loop_starts: HashSet<usize> = flow.loop_starts().collect();
+
+    let instrs = &proto.instrs;
+    let mut pc = 0;
+    while pc < instrs.len() {
+        if loop_starts.contains(&pc) {
+            stack.end_statement();
+        }
+        pc += stack.step(proto, flow, pc, &instrs[pc]);
+    }
+    stack.found
+}
+
+impl Stack {
+    /// Follow the registers through one instruction.
+    ///
+    /// Returns the number of instructions consumed.
+    fn step(&mut self, proto: &Proto, flow: &Flow, pc: usize, instr: &Instr) -> usize {
+        let Instr { a, b, c, .. } = *instr;
+        let rk = |arg: u32| (!is_k(arg)).then_some(arg);
+
+        match instr.opcode {
+            Op::Move => {
+                // Temporaries are never moved up the stack,
+                // so only locals are copied into a higher register.
+                if b < a {
+                    self.capture(b);
+                }
+                self.read(&[b]);
+                self.write(pc, a);
+            }
+            Op::LoadK | Op::GetUpval | Op::GetGlobal | Op::NewTable => self.write(pc, a),
+            Op::LoadBool => {
+                self.write(pc, a);
+                if is_bool_pair(&proto.instrs, pc) {
+                    return 2;
+                }
+            }
+            Op::LoadNil => {
+                for reg in a..=b {
+                    self.write(pc, reg);
+                }
+            }
+            Op::GetTable => {
+                self.read(&[Some(b), rk(c)].into_iter().flatten().collect::<Vec<_>>());
+                self.write(pc, a);
+            }
+            Op::SetGlobal | Op::SetUpval => {
+                self.read(&[a]);
+                self.end_statement();
+            }
+            Op::SetTable => {
+                let args: Vec<u32> = [rk(b), rk(c)].into_iter().flatten().collect();
+                if self.is_constructor(proto, a) {
+                    self.read(&args);
+                } else {
+                    self.read(&[&[a][..], &args].concat());
+                    self.end_statement();
+                }
+            }
+            Op::SelfOp => {
+                self.read(&[Some(b), rk(c)].into_iter().flatten().collect::<Vec<_>>());
+                self.write(pc, a);
+                self.write(pc, a + 1);
+            }
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod | Op::Pow => {
+                self.read(&[rk(b), rk(c)].into_iter().flatten().collect::<Vec<_>>());
+                self.write(pc, a);
+            }
+            Op::Unm | Op::Not | Op::Len => {
+                self.read(&[b]);
+                self.write(pc, a);
+            }
+            Op::Concat => {
+                self.read(&(b..=c).collect::<Vec<_>>());
+                self.write(pc, a);
+            }
+            Op::Jmp => {
+                if let Some(tfl) = flow.generic_for_at(pc) {
+                    // The iterator, its state and control variable,
+                    // followed by the loop variables.
+                    let Instr {
+                        a: base, c: vars, ..
+                    } = proto.instrs[tfl];
+                    self.read(&[base, base + 1, base + 2]);
+                    self.clear(base..base + 3 + vars);
+                }
+                if jump_target(&proto.instrs, pc) != Some(pc + 1) {
+                    self.end_statement();
+                }
+            }
+            Op::Eq | Op::Lt | Op::Le => {
+                self.read(&[rk(b), rk(c)].into_iter().flatten().collect::<Vec<_>>());
+                if flow.test(pc) == Some(Test::Cond) {
+                    self.end_statement();
+                }
+                return 2;
+            }
+            Op::Test => {
+                match flow.test(pc) {
+                    // The operand of `and` or `or` is overwritten
+                    // with the other one when it's not the result.
+                    Some(Test::Value) => self.consume(a),
+                    _ => {
+                        self.read(&[a]);
+                        self.end_statement();
+                    }
+                }
+                return 2;
+            }
+            Op::TestSet => {
+                self.read(&[b]);
+                return 2;
+            }
+            Op::Call => {
+                let args = self.open_list(a, b);
+                self.read(&[&[a][..], &args].concat());
+                match c {
+                    0 => {
+                        self.write(pc, a);
+                        self.top = Some(a + 1);
+                    }
+                    1 => self.end_statement(),
+                    _ => {
+                        for reg in a..a + c - 1 {
+                            self.write(pc, reg);
+                        }
+                    }
+                }
+            }
+            Op::TailCall => {
+                let args = self.open_list(a, b);
+                self.read(&[&[a][..], &args].concat());
+                self.end_statement();
+            }
+            Op::Return => {
+                let values = self.open_list(a.wrapping_sub(1), b);
+                self.read(&values);
+                self.end_statement();
+            }
+            Op::ForPrep => {
+                self.read(&[a, a + 1, a + 2]);
+                self.end_statement();
+                self.clear(a..a + 4);
+            }
+            Op::ForLoop => {
+                self.end_statement();
+                self.clear(a..a + 4);
+            }
+            Op::TForLoop => {
+                self.end_statement();
+                self.clear(a..a + 3 + c);
+            }
+            Op::SetList => {
+                // Unlike calls and returns, `B` is the exact number of items.
+                let items = match b {
+                    0 => self.open_list(a, 0),
+                    _ => (a + 1..=a + b).collect(),
+                };
+                self.read(&items);
+                if c == 0 {
+                    // Batch number is stored in the next instruction.
+                    return 2;
+                }
+            }
+            Op::Close => {}
+            Op::Closure => {
+                self.write(pc, a);
+                let len = flow.expr_len(pc);
+                for pseudo in proto.instrs.iter().skip(pc + 1).take(len - 1) {
+                    if pseudo.opcode == Op::Move {
+                        self.capture(pseudo.b);
+                    }
+                }
+                return len;
+            }
+            Op::VarArg => {
+                if b == 0 {
+                    self.write(pc, a);
+                    self.top = Some(a + 1);
+                } else {
+                    for reg in a..a + b - 1 {
+                        self.write(pc, reg);
+                    }
+                }
+            }
+        }
+
+        1
+    }
+
+    /// Registers after `base` read by an instruction taking `count - 1`
+    /// values, or up to the top of the stack when `count` is zero.
+    fn open_list(&mut self, base: u32, count: u32) -> Vec<u32> {
+        let start = base.wrapping_add(1);
+        let end = match count {
+            0 => self.top.take().unwrap_or(start),
+            _ => start + count - 1,
+        };
+        (start..end).collect()
+    }
+
+    /// Read the registers, in order, for the same instruction.
+    fn read(&mut self, regs: &[u32]) {
+        for &reg in regs {
+            let Some(def) = self.def(reg) else {
+                continue;
+            };
+            let waiting = (reg + 1..self.defs.len() as u32)
+                .any(|above| !regs.contains(&above) && self.def(above).is_some_and(|d| d.live));
+            if !def.live || waiting {
+                self.found_local(reg);
+            }
+            self.consume(reg);
+        }
+    }
+
+    fn write(&mut self, pc: usize, reg: u32) {
+        // Temporaries are read before they're overwritten.
+        if self.def(reg).is_some_and(|def| def.live) {
+            self.found_local(reg);
+        }
+        if let Some(slot) = self.defs.get_mut(reg as usize) {
+            *slot = Some(Def {
+                pc,
+                live: true,
+                local: false,
+            });
+        }
+    }
+
+    /// Mark the value in a register as a local, when a nested function
+    /// keeps it as an upvalue.
+    fn capture(&mut self, reg: u32) {
+        if self.def(reg).is_some() {
+            self.found_local(reg);
+        }
+    }
+
+    fn consume(&mut self, reg: u32) {
+        if let Some(Some(def)) = self.defs.get_mut(reg as usize) {
+            def.live = false;
+        }
+    }
+
+    /// Values left unread at the end of a statement are locals.
+    fn end_statement(&mut self) {
+        for reg in 0..self.defs.len() as u32 {
+            if self.def(reg).is_some_and(|def| def.live) {
+                self.found_local(reg);
+            }
+        }
+    }
+
+    fn found_local(&mut self, reg: u32) {
+        if let Some(Some(def)) = self.defs.get_mut(reg as usize) {
+            def.live = false;
+            if !def.local {
+                def.local = true;
+                self.found.insert((def.pc, reg));
+            }
+        }
+    }
+
+    /// Forget the registers of a loop's control variables, which
+    /// the loop instructions read and write implicitly.
+    fn clear(&mut self, regs: std::ops::Range<u32>) {
+        for reg in regs {
+            if let Some(slot) = self.defs.get_mut(reg as usize) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn def(&self, reg: u32) -> Option<Def> {
+        self.defs.get(reg as usize).copied().flatten()
+    }
+
+    /// Checks whether the register holds a table constructor
+    /// that's still being filled.
+    fn is_constructor(&self, proto: &Proto, reg: u32) -> bool {
+        self.def(reg).is_some_and(|def| {
+            def.live && !def.local && proto.instrs[def.pc].opcode == Op::NewTable
+        })
+    }
+}
@@ -0,0 +1,1394 @@
+//! Bytecode parser.
+//!
+//! Analyzes the register based instructions to generate an abstract syntax tree.
+use super::ast::{
+    Assign, BinExpr, BinOp, Block, Call, Expr, Failed, Function, GenericFor, If, LocalVar,
+    NumericFor, Repeat, Return, Stmt, Syntax, Table, UnaryExpr, UnaryOp, While,
+};
+use super::flow::{
+    is_plain_jump, jump_target, merge_chain, test_target, ChainJump, Flow, Join, Test,
+};
+use super::locals::{find_locals, LocalDefs};
+use super::{index_k, is_k, Constant, Instr, Opcode as Op, Proto, FIELDS_PER_FLUSH};
+use crate::errors::{Error, Result};
+
+pub struct Parser<'a> {
+    proto: &'a Proto,
+
+    /// Jumps of the function, matched to the statements
+    /// and operators they were compiled from.
+    flow: Flow<'a>,
+
+    /// Symbolic registers.
+    ///
+    /// Mimics the registers of the virtual machine, holding the expression
+    /// that was last written into each one until it's consumed.
+    regs: Vec<Reg>,
+
+    /// Names of the local variables that currently live in each register.
+    locals: Vec<Option<String>>,
+
+    /// Top of the stack set by the last instruction with an open number
+    /// of results, like a call or `...`.
+    top: Option<u32>,
+
+    /// Statements of the block being parsed.
+    stmts: Vec<Stmt>,
+
+    /// Loops being parsed, innermost last.
+    loops: Vec<Loop>,
+
+    /// `and` and `or` operators waiting for their right operand, innermost last.
+    logic: Vec<Logic>,
+
+    /// Values kept in local variables, when debug information is stripped.
+    local_defs: LocalDefs,
+
+    /// Registers holding values of local variables that aren't declared yet.
+    pending: Vec<u32>,
+
+    /// Upvalue names passed down by the enclosing function,
+    /// when debug information is stripped.
+    upvalue_names: Vec<String>,
+
+    /// Index of the instruction being parsed.
+    pc: usize,
+
+    /// First instruction that isn't part of an emitted statement yet.
+    region_start: usize,
+
+    /// Keep going after an error, leaving the disassembly of
+    /// the failed instructions in the output.
+    lenient: bool,
+}
+
+/// Symbolic register content.
+#[derive(Debug, Clone)]
+enum Reg {
+    Empty,
+    Expr(Expr),
+    /// Method callee loaded by `SELF`, expected to be called
+    /// with the object in the next register.
+    Method(Expr, String),
+    /// The object passed as `self` to a method call.
+    SelfArg,
+    /// One of the trailing values of a multiple result expression
+    /// stored in a lower register.
+    Rest,
+}
+
+/// Loop whose body is being parsed.
+struct Loop {
+    /// Index of the jump back to the start, or of the loop instruction.
+    jump: usize,
+    /// Index of the first instruction after the loop, where `break` goes.
+    exit: usize,
+}
+
+/// Operand of `and` or `or`, waiting for the other operand
+/// to be computed into the same register.
+struct Logic {
+    reg: u32,
+    op: BinOp,
+    lhs: Expr,
+    /// Index of the jump over the other operand.
+    jump: usize,
+    /// Index of the instruction after the other operand.
+    target: usize,
+}
+
+/// State of the enclosing block, kept while a nested block is parsed.
+struct Outer {
+    stmts: Vec<Stmt>,
+    locals: Vec<Option<String>>,
+    region_start: usize,
+}
+
+// ============================================================================
+
+fn err_unsupported(instr: &Instr) -> Error {
+    Error::new_parser(format!("unsupported instruction: {}", instr.opcode.name()))
+}
+
+fn into_expr(reg: u32, value: Reg) -> Result<Expr> {
+    match value {
+        Reg::Expr(expr) => Ok(expr),
+        Reg::Method(object, method) => Ok(Expr::Index(
+            Box::new(object),
+            Box::new(Expr::Str(method.into())),
+        )),
+        Reg::SelfArg => Error::new_parser("method object used outside of call").into(),
+        Reg::Rest => {
+            Error::new_parser(format!("register {reg} holds one of multiple results")).into()
+        }
+        Reg::Empty => Error::new_parser(format!("register {reg} read before write")).into(),
+    }
+}
+
+fn binary(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::Binary(Box::new(BinExpr { op, lhs, rhs }))
+}
+
+/// Join operands with `and` or `or`.
+///
+/// Both are associative, so a chain is nested to the left,
+/// which reads without parentheses.
+fn logic_expr(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    match rhs {
+        Expr::Binary(bin_expr)
+            if bin_expr.op.is_keyword() && bin_expr.op.as_str() == op.as_str() =>
+        {
+            let BinExpr {
+                lhs: middle,
+                rhs: last,
+                ..
+            } = *bin_expr;
+            binary(op, logic_expr(op, lhs, middle), last)
+        }
+        rhs => binary(op, lhs, rhs),
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Nil | Expr::Bool(_) | Expr::Number(_) | Expr::Str(_)
+    )
+}
+
+// ============================================================================
+
+impl<'a> Parser<'a> {
+    pub fn new(proto: &'a Proto) -> Self {
+        let size = proto.max_stack.max(proto.num_params) as usize;
+        let flow = Flow::new(proto);
+        let local_defs = if proto.locals.is_empty() {
+            find_locals(proto, &flow)
+        } else {
+            LocalDefs::new()
+        };
+        Self {
+            proto,
+            flow,
+            regs: vec![Reg::Empty; size],
+            locals: vec![None; size],
+            top: None,
+            stmts: vec![],
+            loops: vec![],
+            logic: vec![],
+            local_defs,
+            pending: vec![],
+            upvalue_names: vec![],
+            pc: 0,
+            region_start: 0,
+            lenient: false,
+        }
+    }
+
+    /// Recover from errors by replacing the instructions that failed
+    /// with a comment, instead of failing the whole chunk.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    pub fn parse(&mut self) -> Result<Syntax> {
+        let root = self.parse_body()?;
+        Ok(Syntax { root })
+    }
+
+    /// Parameter names of the function.
+    fn params(&self) -> Vec<String> {
+        (0..self.proto.num_params as u32)
+            .map(|reg| self.local_name(reg))
+            .collect()
+    }
+
+    fn parse_body(&mut self) -> Result<Block> {
+        // Parameters occupy the first registers.
+        for reg in 0..self.proto.num_params as u32 {
+            self.locals[reg as usize] = Some(self.local_name(reg));
+        }
+        self.parse_block(0, self.proto.instrs.len())
+    }
+
+    /// Parse the instructions in the range into a block of their own,
+    /// with its own local variables.
+    fn parse_block(&mut self, start: usize, end: usize) -> Result<Block> {
+        let outer = self.enter_block(start);
+        let result = self
+            .parse_range(start, end)
+            .and_then(|()| self.declare_pending());
+        let block = self.leave_block(outer);
+        result.map(|()| block)
+    }
+
+    fn enter_block(&mut self, start: usize) -> Outer {
+        Outer {
+            stmts: std::mem::take(&mut self.stmts),
+            locals: self.locals.clone(),
+            region_start: std::mem::replace(&mut self.region_start, start),
+        }
+    }
+
+    fn leave_block(&mut self, outer: Outer) -> Block {
+        let stmts = std::mem::replace(&mut self.stmts, outer.stmts);
+        self.locals = outer.locals;
+        self.region_start = outer.region_start;
+
+        // Values left in the block's registers can't be used after it.
+        for reg in 0..self.regs.len() {
+            if self.locals[reg].is_none() {
+                self.regs[reg] = Reg::Empty;
+            }
+        }
+        Block { stmts }
+    }
+
+    /// Parse the instructions in the range into the current block.
+    fn parse_range(&mut self, start: usize, end: usize) -> Result<()> {
+        let mut pc = start;
+        while pc < end {
+            let count = self.stmts.len();
+            match self.parse_step(pc, end) {
+                Ok(next) => pc = next,
+                Err(err) if self.lenient => {
+                    self.recover(pc, err);
+                    pc += 1;
+                }
+                Err(err) => return Err(err),
+            }
+            if self.stmts.len() != count {
+                self.region_start = pc;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the instruction at `pc`, or the statement or operator starting
+    /// there, with the local variables coming in and out of scope around it.
+    ///
+    /// Returns the index of the next instruction.
+    fn parse_step(&mut self, pc: usize, end: usize) -> Result<usize> {
+        self.end_locals(pc as u32);
+        self.pc = pc;
+
+        let next = match self.parse_structure(pc, end)? {
+            Some(next) => next,
+            None => {
+                let instr = self.proto.instrs[pc];
+                pc + self.parse_instr(pc, &instr)?
+            }
+        };
+
+        self.join_logic(next)?;
+        self.start_locals(next as u32)?;
+        Ok(next)
+    }
+
+    /// Parse the statement or operator starting at `pc`, made up of jumps
+    /// and the instructions between them.
+    ///
+    /// Returns the index of the next instruction, or `None` when
+    /// the instruction stands on its own.
+    fn parse_structure(&mut self, pc: usize, end: usize) -> Result<Option<usize>> {
+        // A loop can start where another starts, like an inner `repeat`
+        // at the start of an outer one, so the outermost goes first.
+        let repeat = self
+            .flow
+            .repeats_at(pc)
+            .find(|&jump| jump < end && !self.in_loop(jump));
+        let while_ = self
+            .flow
+            .whiles_at(pc)
+            .find(|&jump| jump < end && !self.in_loop(jump));
+        match (repeat, while_) {
+            (Some(repeat), Some(while_)) if while_ > repeat => {
+                return self.parse_while(pc, while_).map(Some)
+            }
+            (Some(repeat), _) => return self.parse_repeat(pc, repeat).map(Some),
+            (None, Some(while_)) => return self.parse_while(pc, while_).map(Some),
+            (None, None) => {}
+        }
+
+        let instr = self.proto.instrs[pc];
+        match instr.opcode {
+            Op::ForPrep => self.parse_numeric_for(pc, end).map(Some),
+            Op::Jmp => self.parse_jump(pc, end).map(Some),
+            Op::Eq | Op::Lt | Op::Le | Op::Test | Op::TestSet => match self.flow.test(pc) {
+                Some(Test::Cond) => self.parse_if(pc, end).map(Some),
+                Some(Test::Bool) => self.parse_bool(pc).map(Some),
+                Some(Test::Value) => self.parse_logic(pc).map(Some),
+                None => Err(err_unsupported(&instr)),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Replace the instructions since the last statement, up to and
+    /// including the one that failed, with a [Failed] statement.
+    ///
+    /// Temporaries are dropped, so instructions reading them fail in turn
+    /// and are merged into the same statement until the code recovers.
+    fn recover(&mut self, pc: usize, err: Error) {
+        for reg in 0..self.regs.len() as u32 {
+            if self.local(reg).is_none() {
+                self.regs[reg as usize] = Reg::Empty;
+            }
+        }
+        self.top = None;
+        self.logic.clear();
+
+        // Nothing was decompiled since the last failure, so the first
+        // error is kept as the cause.
+        let mut message = err.to_string();
+        if let Some(Stmt::Failed(last)) = self.stmts.last_mut() {
+            self.region_start = last.start as usize;
+            message = std::mem::take(&mut last.message);
+            self.stmts.pop();
+        }
+
+        let start = self.region_start;
+        self.stmts.push(Stmt::Failed(Failed {
+            message,
+            start: start as u32,
+            end: pc as u32,
+            listing: self.proto.dump_range(start..pc + 1).to_string(),
+        }));
+    }
+
+    /// Parse one instruction.
+    ///
+    /// Returns the number of instructions consumed.
+    fn parse_instr(&mut self, pc: usize, instr: &Instr) -> Result<usize> {
+        let Instr { a, b, c, bx, .. } = *instr;
+
+        match instr.opcode {
+            Op::Move => {
+                let value = self.get(b)?;
+                self.set(a, value)?;
+            }
+            Op::LoadK => {
+                let value = self.constant(bx)?;
+                self.set(a, value)?;
+            }
+            Op::LoadBool => {
+                if c != 0 {
+                    // Skips the next instruction, which is only
+                    // done for comparisons used as values.
+                    return Err(err_unsupported(instr));
+                }
+                self.set(a, Expr::Bool(b != 0))?;
+            }
+            Op::LoadNil => {
+                for reg in a..=b {
+                    self.set(reg, Expr::Nil)?;
+                }
+            }
+            Op::GetUpval => {
+                let name = self.upvalue_name(b);
+                self.set(a, Expr::Name(name))?;
+            }
+            Op::GetGlobal => {
+                let name = self.constant_string(bx)?;
+                self.set(a, Expr::Name(name))?;
+            }
+            Op::GetTable => {
+                let table = self.get(b)?;
+                let key = self.get_rk(c)?;
+                self.set(a, Expr::Index(Box::new(table), Box::new(key)))?;
+            }
+            Op::SetGlobal => {
+                let name = self.constant_string(bx)?;
+                let value = self.get(a)?;
+                self.emit_assign(Expr::Name(name), value)?;
+            }
+            Op::SetUpval => {
+                let name = self.upvalue_name(b);
+                let value = self.get(a)?;
+                self.emit_assign(Expr::Name(name), value)?;
+            }
+            Op::SetTable => {
+                let key = self.get_rk(b)?;
+                let value = self.get_rk(c)?;
+                match self.pending_table(a) {
+                    // Field of a table constructor still being built.
+                    Some(table) => table.fields.push((key, value)),
+                    None => {
+                        let table = self.get(a)?;
+                        self.emit_assign(Expr::Index(Box::new(table), Box::new(key)), value)?;
+                    }
+                }
+            }
+            Op::NewTable => self.set(a, Expr::Table(Table::default()))?,
+            Op::SelfOp => {
+                let object = self.get(b)?;
+                let method = match self.get_rk(c)? {
+                    Expr::Str(name) => name.to_string(),
+                    _ => return Error::new_parser("method name must be a string constant").into(),
+                };
+                self.set_reg(a, Reg::Method(object, method))?;
+                self.set_reg(a + 1, Reg::SelfArg)?;
+            }
+            Op::Add => self.parse_binary(a, b, c, BinOp::Add)?,
+            Op::Sub => self.parse_binary(a, b, c, BinOp::Sub)?,
+            Op::Mul => self.parse_binary(a, b, c, BinOp::Mul)?,
+            Op::Div => self.parse_binary(a, b, c, BinOp::Div)?,
+            Op::Mod => self.parse_binary(a, b, c, BinOp::Mod)?,
+            Op::Pow => self.parse_binary(a, b, c, BinOp::Pow)?,
+            Op::Unm => self.parse_unary(a, b, UnaryOp::Neg)?,
+            Op::Not => self.parse_unary(a, b, UnaryOp::Not)?,
+            Op::Len => self.parse_unary(a, b, UnaryOp::Len)?,
+            Op::Concat => {
+                // Right associative, so the chain is built from the end.
+                let mut values = vec![];
+                for reg in b..=c {
+                    values.push(self.get(reg)?);
+                }
+                let mut rhs = values
+                    .pop()
+                    .ok_or_else(|| Error::new_parser("empty concat"))?;
+                while let Some(lhs) = values.pop() {
+                    rhs = binary(BinOp::Concat, lhs, rhs);
+                }
+                self.set(a, rhs)?;
+            }
+            Op::Call => self.parse_call(a, b, c)?,
+            Op::TailCall => {
+                let call = self.build_call(a, b)?;
+                self.emit(Stmt::Return(Return {
+                    values: vec![Expr::Call(Box::new(call))],
+                }))?;
+                // The tail call is followed by a return that's never executed.
+                if let Some(Op::Return) = self.proto.instrs.get(pc + 1).map(|i| i.opcode) {
+                    return Ok(2);
+                }
+            }
+            Op::Return => {
+                let values = self.get_list(a, b)?;
+                // Every function ends with an implicit return.
+                let is_implicit = values.is_empty() && pc + 1 == self.proto.instrs.len();
+                if !is_implicit {
+                    self.emit(Stmt::Return(Return { values }))?;
+                }
+            }
+            Op::SetList => {
+                // Unlike calls and returns, `B` is the exact number of items.
+                let count = if b == 0 { 0 } else { b + 1 };
+                let values = self.get_list(a + 1, count)?;
+                // A batch number too big for `C` is stored in the next instruction.
+                let (batch, len) = match c {
+                    0 => match self.proto.code.get(pc + 1) {
+                        Some(word) => (*word, 2),
+                        None => return Error::new_parser("missing list batch number").into(),
+                    },
+                    c => (c, 1),
+                };
+                let offset = batch.saturating_sub(1).saturating_mul(FIELDS_PER_FLUSH);
+                match self.pending_table(a) {
+                    Some(table) if table.items.len() as u32 == offset => table.items.extend(values),
+                    _ => return Error::new_parser("list items without table constructor").into(),
+                }
+                return Ok(len);
+            }
+            Op::Close => {}
+            Op::Closure => return self.parse_closure(pc, a, bx),
+            Op::VarArg => {
+                if b == 0 {
+                    self.set(a, Expr::VarArg)?;
+                    self.top = Some(a + 1);
+                } else {
+                    self.set_multiple(a, b - 1, Expr::VarArg)?;
+                }
+            }
+            // Only parsed as part of the statements and operators they were compiled from.
+            Op::Jmp
+            | Op::Eq
+            | Op::Lt
+            | Op::Le
+            | Op::Test
+            | Op::TestSet
+            | Op::ForLoop
+            | Op::ForPrep
+            | Op::TForLoop => return Err(err_unsupported(instr)),
+        }
+
+        Ok(1)
+    }
+
+    /// Parse a jump that isn't part of a test: the start of a generic `for`
+    /// loop, a `break`, or a jump to the next instruction.
+    fn parse_jump(&mut self, pc: usize, end: usize) -> Result<usize> {
+        if let Some(tfl) = self.flow.generic_for_at(pc) {
+            return self.parse_generic_for(pc, tfl, end);
+        }
+
+        let instr = self.proto.instrs[pc];
+        let target = jump_target(&self.proto.instrs, pc)
+            .ok_or_else(|| Error::new_parser(format!("jump out of bounds at {}", pc + 1)))?;
+        if target == pc + 1 {
+            return Ok(pc + 1);
+        }
+        if self.is_break(pc, target) {
+            self.emit(Stmt::Break)?;
+            return Ok(pc + 1);
+        }
+        Err(err_unsupported(&instr))
+    }
+
+    /// Checks whether a jump leaves the innermost loop.
+    fn is_break(&self, pc: usize, target: usize) -> bool {
+        let target = self.flow.resolve(target, pc);
+        self.loops
+            .last()
+            .is_some_and(|innermost| self.flow.lands(target, innermost.exit))
+    }
+
+    /// Parse an `if` statement, starting with the first test of its condition.
+    ///
+    /// The condition jumps past the `then` block when it fails. When the
+    /// statement has an `else` block, the `then` block ends with a jump over it.
+    fn parse_if(&mut self, pc: usize, end: usize) -> Result<usize> {
+        let tests = self.flow.cond_chain(pc, end);
+        let then_start = tests.last().copied().unwrap_or(pc) + 2;
+        let jump = self.parse_cond(&tests, true)?;
+        let exit = self.loops.last().map(|innermost| innermost.exit);
+
+        let mut target = jump.target;
+        if target > end {
+            target = self.flow.redirect(target, then_start, end, exit);
+        }
+        if target < then_start {
+            return Error::new_parser(format!("if statement jumps back to {}", target + 1)).into();
+        }
+        let cond = jump.cond.invert();
+        self.declare_pending()?;
+
+        let else_jump = (target > then_start)
+            .then(|| target - 1)
+            .filter(|&jump| self.is_else_jump(jump));
+        let (then_end, else_end) = match else_jump {
+            Some(jump) => {
+                let mut else_end = jump_target(&self.proto.instrs, jump)
+                    .map(|target| self.flow.resolve(target, jump))
+                    .unwrap_or(target);
+                if else_end > end {
+                    else_end = self.flow.redirect(else_end, target, end, exit);
+                }
+                (jump, else_end)
+            }
+            None => (target, target),
+        };
+
+        let then = self.parse_block(then_start, then_end)?;
+        let else_ = if else_end > target {
+            Some(self.parse_block(target, else_end)?)
+        } else {
+            None
+        };
+        self.emit(Stmt::If(If { cond, then, else_ }))?;
+
+        Ok(else_end)
+    }
+
+    /// Checks whether the instruction is a `JMP` at the end of a `then` block
+    /// over an `else` block, rather than a `break`.
+    fn is_else_jump(&self, pc: usize) -> bool {
+        let instrs = &self.proto.instrs;
+        let Some(target) = jump_target(instrs, pc) else {
+            return false;
+        };
+        is_plain_jump(instrs, pc)
+            && self.flow.generic_for_at(pc).is_none()
+            && self.flow.resolve(target, pc) > pc
+            && !self.is_break(pc, target)
+    }
+
+    /// Parse a `while` loop, whose condition jumps out of the loop when it fails.
+    ///
+    /// Without one that does, it's a `while true` loop, which stops with `break`.
+    fn parse_while(&mut self, pc: usize, jump: usize) -> Result<usize> {
+        self.declare_pending()?;
+        let exit = jump + 1;
+
+        let mut first = pc;
+        while first < jump && self.flow.expr_len(first) > 0 {
+            first += self.flow.expr_len(first);
+        }
+        let tests = Some(first)
+            .filter(|&first| first < jump && self.flow.test(first) == Some(Test::Cond))
+            .map(|first| self.flow.cond_chain(first, jump))
+            .filter(|tests| {
+                self.flow
+                    .chain_target(tests, true)
+                    .is_some_and(|target| self.flow.lands(target, exit))
+            });
+
+        self.loops.push(Loop { jump, exit });
+        let result = match &tests {
+            Some(tests) => self
+                .parse_range(pc, first)
+                .and_then(|()| self.parse_cond(tests, true))
+                .map(|jump| (jump.cond.invert(), tests[tests.len() - 1] + 2)),
+            None => Ok((Expr::Bool(true), pc)),
+        }
+        .and_then(|(cond, body_start)| Ok((cond, self.parse_block(body_start, jump)?)));
+        self.loops.pop();
+
+        let (cond, body) = result?;
+        self.emit(Stmt::While(While { cond, body }))?;
+        Ok(exit)
+    }
+
+    /// Parse a `repeat` loop, whose condition is tested at the end of the body,
+    /// and jumps back to the start when it fails.
+    ///
+    /// The condition is in the scope of the body's local variables.
+    fn parse_repeat(&mut self, pc: usize, jump: usize) -> Result<usize> {
+        self.declare_pending()?;
+        let tests = self.flow.until_chain(pc, jump);
+        let first = tests.first().copied().unwrap_or(jump - 1);
+
+        self.loops.push(Loop {
+            jump,
+            exit: jump + 1,
+        });
+        let outer = self.enter_block(pc);
+        let result = self
+            .parse_range(pc, first)
+            .and_then(|()| self.parse_cond(&tests, false))
+            .and_then(|cond| self.declare_pending().map(|()| cond));
+        let body = self.leave_block(outer);
+        self.loops.pop();
+
+        let jump_back = result?;
+        let cond = if jump_back.target == pc {
+            jump_back.cond.invert()
+        } else if jump_back.target == jump + 1 {
+            jump_back.cond
+        } else {
+            return Error::new_parser("repeat condition doesn't jump back to the start").into();
+        };
+        self.emit(Stmt::Repeat(Repeat { body, cond }))?;
+
+        Ok(jump + 1)
+    }
+
+    /// Parse a numeric `for` loop, from the `FORPREP` that jumps
+    /// to the `FORLOOP` at the end of the body.
+    fn parse_numeric_for(&mut self, pc: usize, end: usize) -> Result<usize> {
+        let instrs = &self.proto.instrs;
+        let a = instrs[pc].a;
+        let forloop = jump_target(instrs, pc)
+            .filter(|&target| target < end && instrs[target].opcode == Op::ForLoop)
+            .ok_or_else(|| Error::new_parser("for loop without end"))?;
+
+        let start = self.get(a)?;
+        let limit = self.get(a + 1)?;
+        let step = match self.get(a + 2)? {
+            Expr::Number(1.0) => None,
+            step => Some(step),
+        };
+        self.declare_pending()?;
+
+        let var = self.loop_var_name(pc + 1, a + 3);
+        let body = self.parse_loop_body(
+            pc + 1,
+            Loop {
+                jump: forloop,
+                exit: forloop + 1,
+            },
+            &[(a + 3, var.clone())],
+        )?;
+        self.emit(Stmt::NumericFor(NumericFor {
+            var,
+            start,
+            limit,
+            step,
+            body,
+        }))?;
+
+        Ok(forloop + 1)
+    }
+
+    /// Parse a generic `for` loop, from the jump to the `TFORLOOP` that ends
+    /// the body, which is followed by the jump back to the start.
+    fn parse_generic_for(&mut self, pc: usize, tforloop: usize, end: usize) -> Result<usize> {
+        if tforloop + 1 >= end {
+            return Error::new_parser("for loop without end").into();
+        }
+        let Instr { a, c, .. } = self.proto.instrs[tforloop];
+
+        // The iterator function, its state and the control variable.
+        let mut exprs = vec![];
+        for reg in a..a + 3 {
+            match self.take_reg(reg)? {
+                Reg::Rest => {}
+                value => exprs.push(into_expr(reg, value)?),
+            }
+        }
+        // Missing values are filled in with `nil`.
+        while exprs.len() > 1 && matches!(exprs.last(), Some(Expr::Nil)) {
+            exprs.pop();
+        }
+        self.declare_pending()?;
+
+        let vars: Vec<(u32, String)> = (a + 3..a + 3 + c.max(1))
+            .map(|reg| (reg, self.loop_var_name(pc + 1, reg)))
+            .collect();
+        let body = self.parse_loop_body(
+            pc + 1,
+            Loop {
+                jump: tforloop,
+                exit: tforloop + 2,
+            },
+            &vars,
+        )?;
+        self.emit(Stmt::GenericFor(GenericFor {
+            names: vars.into_iter().map(|(_, name)| name).collect(),
+            exprs,
+            body,
+        }))?;
+
+        Ok(tforloop + 2)
+    }
+
+    /// Parse the body of a `for` loop, up to the loop instruction,
+    /// with its variables in scope.
+    fn parse_loop_body(
+        &mut self,
+        start: usize,
+        body: Loop,
+        vars: &[(u32, String)],
+    ) -> Result<Block> {
+        let locals = self.locals.clone();
+        for (reg, name) in vars {
+            if let Some(slot) = self.locals.get_mut(*reg as usize) {
+                *slot = Some(name.clone());
+            }
+        }
+
+        let end = body.jump;
+        self.loops.push(body);
+        let result = self.parse_block(start, end);
+        self.loops.pop();
+        self.locals = locals;
+        result
+    }
+
+    /// Name of a `for` loop variable, which comes into scope
+    /// at the start of the body.
+    fn loop_var_name(&self, startpc: usize, reg: u32) -> String {
+        self.local_regs()
+            .find(|(r, local)| *r == reg && local.startpc as usize == startpc)
+            .map(|(_, local)| local.varname.clone())
+            .unwrap_or_else(|| self.local_name(reg))
+    }
+
+    /// Parse a comparison used as a value, which jumps to one of the
+    /// `LOADBOOL`s that load its result.
+    fn parse_bool(&mut self, pc: usize) -> Result<usize> {
+        let (tests, pair) = self
+            .flow
+            .bool_chain(pc)
+            .filter(|(tests, _)| tests.first() == Some(&pc))
+            .ok_or_else(|| Error::new_parser("comparison without result"))?;
+
+        // The second `LOADBOOL` loads `true`.
+        let jump = self.parse_cond(&tests, false)?;
+        let value = if jump.target == pair + 1 {
+            jump.cond
+        } else if jump.target == pair {
+            jump.cond.invert()
+        } else {
+            return Error::new_parser("comparison jumps past its result").into();
+        };
+
+        self.pc = pair;
+        self.set(self.proto.instrs[pair].a, value)?;
+        Ok(pair + 2)
+    }
+
+    /// Parse the first operand of `and` or `or` used as a value, which
+    /// jumps over the second when it's the result.
+    fn parse_logic(&mut self, pc: usize) -> Result<usize> {
+        let Instr {
+            opcode, a, b, c, ..
+        } = self.proto.instrs[pc];
+        let target = test_target(&self.proto.instrs, pc)
+            .ok_or_else(|| Error::new_parser("test without jump"))?;
+
+        // `TESTSET` copies the operand into the result when it jumps.
+        let lhs = match opcode {
+            Op::TestSet => self.get(b)?,
+            _ => self.get(a)?,
+        };
+        // Jumps when the truth of the operand is `C`.
+        let op = if c != 0 { BinOp::Or } else { BinOp::And };
+        self.logic.push(Logic {
+            reg: a,
+            op,
+            lhs,
+            jump: pc + 1,
+            target,
+        });
+
+        Ok(pc + 2)
+    }
+
+    /// Join the `and` and `or` operators whose second operand ends at `next`.
+    fn join_logic(&mut self, next: usize) -> Result<()> {
+        loop {
+            let len = self.logic.len();
+            let Some(top) = self.logic.last() else {
+                return Ok(());
+            };
+
+            if top.target == next {
+                let Some(Logic { reg, op, lhs, .. }) = self.logic.pop() else {
+                    return Ok(());
+                };
+                let rhs = self.take_logic(reg)?;
+                let value = logic_expr(op, lhs, rhs);
+
+                if self.has_logic(reg) {
+                    self.set_reg(reg, Reg::Expr(value))?;
+                } else if let Some(name) = self.local(reg) {
+                    let target = Expr::Name(name.to_string());
+                    self.emit_assign(target, value)?;
+                } else {
+                    self.set_reg(reg, Reg::Expr(value))?;
+                    self.mark(next - 1, reg);
+                }
+                continue;
+            }
+
+            // In `a and b or c`, `a` jumps to `c` when it fails, so the
+            // first operand of `or` is `a and b`. Likewise for `a or b and c`
+            // in parentheses.
+            let nested = len >= 2
+                && top.jump + 1 == next
+                && self.logic[len - 2].target == next
+                && self.logic[len - 2].reg == top.reg;
+            if !nested {
+                return Ok(());
+            }
+            if let (Some(mut outer), Some(inner)) = (self.logic.pop(), self.logic.pop()) {
+                outer.lhs = logic_expr(inner.op, inner.lhs, outer.lhs);
+                self.logic.push(outer);
+            }
+        }
+    }
+
+    /// Take the second operand of `and` or `or` out of the register,
+    /// even when it's a local variable's.
+    fn take_logic(&mut self, reg: u32) -> Result<Expr> {
+        let slot = self
+            .regs
+            .get_mut(reg as usize)
+            .ok_or_else(|| Error::new_parser(format!("register {reg} out of bounds")))?;
+        into_expr(reg, std::mem::replace(slot, Reg::Empty))
+    }
+
+    fn has_logic(&self, reg: u32) -> bool {
+        self.logic.iter().any(|logic| logic.reg == reg)
+    }
+
+    /// Parse the tests of a condition, and the operands of all but the first,
+    /// into the one jump they're joined into.
+    ///
+    /// Jumps back to the start of a `while` loop are taken as jumps to the
+    /// end of its body when `resolve` is set.
+    fn parse_cond(&mut self, tests: &[usize], resolve: bool) -> Result<ChainJump<Expr>> {
+        let mut jumps = vec![];
+        for (index, &test) in tests.iter().enumerate() {
+            if index > 0 {
+                self.parse_range(tests[index - 1] + 2, test)?;
+            }
+            self.pc = test;
+            let cond = self.test_cond(test)?;
+            let target = test_target(&self.proto.instrs, test)
+                .ok_or_else(|| Error::new_parser("test without jump"))?;
+            let target = match resolve {
+                true => self.flow.resolve(target, test),
+                false => target,
+            };
+            jumps.push(ChainJump {
+                cond,
+                target,
+                next: test + 2,
+            });
+        }
+
+        merge_chain(jumps, |join, first, second| match join {
+            Join::Or => binary(BinOp::Or, first, second),
+            Join::AndNot => binary(BinOp::And, first.invert(), second),
+        })
+        .ok_or_else(|| Error::new_parser("condition jumps don't fit together"))
+    }
+
+    /// Condition under which the jump after a test is taken.
+    fn test_cond(&mut self, pc: usize) -> Result<Expr> {
+        let instr = self.proto.instrs[pc];
+        let Instr { a, b, c, .. } = instr;
+
+        // Comparisons jump when the result is `A`, and `TEST` when
+        // the truth of the register is `C`.
+        match instr.opcode {
+            Op::Eq | Op::Lt | Op::Le => {
+                let op = match instr.opcode {
+                    Op::Eq => BinOp::Eq,
+                    Op::Lt => BinOp::Lt,
+                    _ => BinOp::Le,
+                };
+                let cond = self.comparison(op, b, c)?;
+                Ok(if a != 0 { cond } else { cond.invert() })
+            }
+            Op::Test => {
+                let value = self.get(a)?;
+                Ok(if c != 0 { value } else { value.invert() })
+            }
+            _ => Err(err_unsupported(&instr)),
+        }
+    }
+
+    fn comparison(&mut self, op: BinOp, b: u32, c: u32) -> Result<Expr> {
+        let lhs = self.get_rk(b)?;
+        let rhs = self.get_rk(c)?;
+
+        // `a > b` compiles to `b < a`, which puts constants on the left.
+        if is_literal(&lhs) && !is_literal(&rhs) {
+            let op = match op {
+                BinOp::Lt => BinOp::Gt,
+                BinOp::Le => BinOp::Ge,
+                op => op,
+            };
+            return Ok(binary(op, rhs, lhs));
+        }
+        Ok(binary(op, lhs, rhs))
+    }
+
+    fn parse_binary(&mut self, a: u32, b: u32, c: u32, op: BinOp) -> Result<()> {
+        let lhs = self.get_rk(b)?;
+        let rhs = self.get_rk(c)?;
+        self.set(a, binary(op, lhs, rhs))
+    }
+
+    fn parse_unary(&mut self, a: u32, b: u32, op: UnaryOp) -> Result<()> {
+        let rhs = self.get(b)?;
+        self.set(a, Expr::Unary(Box::new(UnaryExpr { op, rhs })))
+    }
+
+    fn parse_call(&mut self, a: u32, b: u32, c: u32) -> Result<()> {
+        let call = self.build_call(a, b)?;
+
+        match c {
+            // Open number of results.
+            0 => {
+                self.set(a, Expr::Call(Box::new(call)))?;
+                self.top = Some(a + 1);
+            }
+            // Called as a statement.
+            1 => self.emit(Stmt::Call(call))?,
+            _ => self.set_multiple(a, c - 1, Expr::Call(Box::new(call)))?,
+        }
+
+        Ok(())
+    }
+
+    fn build_call(&mut self, a: u32, b: u32) -> Result<Call> {
+        let args = self.get_list(a + 1, b)?;
+
+        match self.take_reg(a)? {
+            Reg::Method(object, method) => Ok(Call {
+                name: object,
+                method: Some(method),
+                args,
+            }),
+            Reg::Expr(name) => Ok(Call {
+                name,
+                method: None,
+                args,
+            }),
+            _ => Error::new_parser(format!("register {a} is not callable")).into(),
+        }
+    }
+
+    fn parse_closure(&mut self, pc: usize, a: u32, bx: u32) -> Result<usize> {
+        let proto = self
+            .proto
+            .protos
+            .get(bx as usize)
+            .ok_or_else(|| Error::new_parser(format!("function {bx} out of bounds")))?;
+
+        // The closure is followed by a pseudo instruction for each upvalue,
+        // telling the virtual machine where to find it.
+        let num_upvalues = proto.num_upvalues as usize;
+        if pc + num_upvalues >= self.proto.instrs.len() {
+            return Error::new_parser("missing closure upvalue instructions").into();
+        }
+        let upvalue_names = if proto.upvalues.is_empty() {
+            self.upvalue_sources(pc, a, num_upvalues)?
+        } else {
+            vec![]
+        };
+
+        let mut parser = Parser::new(proto).lenient(self.lenient);
+        parser.upvalue_names = upvalue_names;
+        let body = parser.parse_body()?;
+        let function = Function {
+            params: parser.params(),
+            is_vararg: proto.is_vararg(),
+            body,
+        };
+        self.set(a, Expr::Function(Box::new(function)))?;
+
+        Ok(1 + num_upvalues)
+    }
+
+    /// Names of the variables a closure captures, from the pseudo
+    /// instructions after it, for functions without upvalue names.
+    fn upvalue_sources(&mut self, pc: usize, a: u32, num_upvalues: usize) -> Result<Vec<String>> {
+        let mut names = vec![];
+        for pseudo in &self.proto.instrs[pc + 1..=pc + num_upvalues] {
+            match pseudo.opcode {
+                Op::Move => {
+                    let reg = pseudo.b;
+                    if self.pending.contains(&reg) {
+                        self.declare_pending()?;
+                    }
+                    // A function that refers to itself is declared before it's
+                    // assigned, like `local function` does.
+                    if reg == a && self.local(a).is_none() {
+                        let name = self.local_name(a);
+                        self.emit(Stmt::LocalVar(LocalVar {
+                            names: vec![name.clone()],
+                            rhs: vec![],
+                        }))?;
+                        self.locals[a as usize] = Some(name);
+                    }
+                    let name = match self.local(reg) {
+                        Some(name) => name.to_string(),
+                        None => self.local_name(reg),
+                    };
+                    names.push(name);
+                }
+                Op::GetUpval => names.push(self.upvalue_name(pseudo.b)),
+                _ => return Error::new_parser("bad closure upvalue instruction").into(),
+            }
+        }
+        Ok(names)
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn in_loop(&self, jump: usize) -> bool {
+        self.loops.iter().any(|l| l.jump == jump)
+    }
+
+    /// Read the expression in a register.
+    ///
+    /// Temporary values are moved out of the register.
+    fn get(&mut self, reg: u32) -> Result<Expr> {
+        let value = self.take_reg(reg)?;
+        into_expr(reg, value)
+    }
+
+    fn take_reg(&mut self, reg: u32) -> Result<Reg> {
+        if self.pending.contains(&reg) {
+            self.declare_pending()?;
+        }
+        if let Some(name) = self.local(reg) {
+            return Ok(Reg::Expr(Expr::Name(name.to_string())));
+        }
+        let slot = self
+            .regs
+            .get_mut(reg as usize)
+            .ok_or_else(|| Error::new_parser(format!("register {reg} out of bounds")))?;
+        Ok(std::mem::replace(slot, Reg::Empty))
+    }
+
+    /// Read a register or constant argument.
+    fn get_rk(&mut self, arg: u32) -> Result<Expr> {
+        if is_k(arg) {
+            self.constant(index_k(arg))
+        } else {
+            self.get(arg)
+        }
+    }
+
+    /// Read the `count - 1` registers starting at `start`, or all of them
+    /// up to the top of the stack when `count` is zero.
+    fn get_list(&mut self, start: u32, count: u32) -> Result<Vec<Expr>> {
+        let end = if count == 0 {
+            self.top
+                .take()
+                .ok_or_else(|| Error::new_parser("open list without stack top"))?
+        } else {
+            start + count - 1
+        };
+
+        let mut values = vec![];
+        for reg in start..end {
+            match self.take_reg(reg)? {
+                // Trailing values of multiple results, and the method object
+                // that's passed implicitly.
+                Reg::Rest | Reg::SelfArg => {}
+                value => values.push(into_expr(reg, value)?),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Write an expression into a register.
+    ///
+    /// Writing into a local variable is an assignment statement, unless it's
+    /// the second operand of `and` or `or`, which is assigned once joined.
+    fn set(&mut self, reg: u32, value: Expr) -> Result<()> {
+        if self.has_logic(reg) {
+            return self.set_reg(reg, Reg::Expr(value));
+        }
+        // A local variable that isn't declared yet is assigned a new value.
+        if self.pending.contains(&reg) {
+            self.declare_pending()?;
+        }
+        match self.local(reg) {
+            Some(name) => {
+                let target = Expr::Name(name.to_string());
+                self.emit_assign(target, value)
+            }
+            None => {
+                self.set_reg(reg, Reg::Expr(value))?;
+                self.mark(self.pc, reg);
+                Ok(())
+            }
+        }
+    }
+
+    /// Write a multiple result expression into `count` registers.
+    fn set_multiple(&mut self, reg: u32, count: u32, value: Expr) -> Result<()> {
+        if (reg..reg + count).any(|r| self.pending.contains(&r)) {
+            self.declare_pending()?;
+        }
+        let targets: Vec<Option<String>> = (reg..reg + count)
+            .map(|r| self.local(r).map(str::to_string))
+            .collect();
+
+        if targets.iter().all(Option::is_some) && count > 0 {
+            let targets = targets.into_iter().flatten().map(Expr::Name).collect();
+            return self.emit(Stmt::Assign(Assign {
+                targets,
+                rhs: vec![value],
+            }));
+        }
+
+        self.set_reg(reg, Reg::Expr(value))?;
+        for r in reg + 1..reg + count {
+            self.set_reg(r, Reg::Rest)?;
+        }
+        // The values are declared together.
+        if (reg..reg + count).any(|r| self.local_defs.contains(&(self.pc, r))) {
+            for r in reg..reg + count {
+                if !self.pending.contains(&r) {
+                    self.pending.push(r);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn set_reg(&mut self, reg: u32, value: Reg) -> Result<()> {
+        let slot = self
+            .regs
+            .get_mut(reg as usize)
+            .ok_or_else(|| Error::new_parser(format!("register {reg} out of bounds")))?;
+        *slot = value;
+        Ok(())
+    }
+
+    /// Keep the value written into the register by the instruction for
+    /// a local variable declaration, when it was found to be one.
+    fn mark(&mut self, pc: usize, reg: u32) {
+        if self.local_defs.contains(&(pc, reg)) && !self.pending.contains(&reg) {
+            self.pending.push(reg);
+        }
+    }
+
+    /// Table constructor waiting in a temporary register.
+    fn pending_table(&mut self, reg: u32) -> Option<&mut Table> {
+        if self.local(reg).is_some() {
+            return None;
+        }
+        match self.regs.get_mut(reg as usize) {
+            Some(Reg::Expr(Expr::Table(table))) => Some(table),
+            _ => None,
+        }
+    }
+
+    fn emit_assign(&mut self, target: Expr, value: Expr) -> Result<()> {
+        self.emit(Stmt::Assign(Assign {
+            targets: vec![target],
+            rhs: vec![value],
+        }))
+    }
+
+    /// Add a statement to the current block, after the declarations
+    /// of the local variables written before it.
+    fn emit(&mut self, stmt: Stmt) -> Result<()> {
+        self.declare_pending()?;
+        self.stmts.push(stmt);
+        Ok(())
+    }
+
+    /// Declare the local variables whose values are waiting in registers.
+    ///
+    /// When debug information is stripped, there is no record of where local
+    /// variables are declared, so they're declared when first used, with the
+    /// values found to be theirs. See [find_locals].
+    fn declare_pending(&mut self) -> Result<()> {
+        let mut regs = std::mem::take(&mut self.pending);
+        regs.sort_unstable();
+
+        let mut names = vec![];
+        let mut rhs = vec![];
+        let mut after_rest = false;
+        for reg in regs {
+            let value = match std::mem::replace(&mut self.regs[reg as usize], Reg::Empty) {
+                Reg::Expr(value) => Some(value),
+                Reg::Rest | Reg::Empty => None,
+                Reg::Method(..) | Reg::SelfArg => {
+                    return Error::new_parser("method call parts stored in local").into()
+                }
+            };
+            // Only the last value of a declaration has multiple results.
+            if value.is_some() && after_rest {
+                self.push_local_var(std::mem::take(&mut names), std::mem::take(&mut rhs));
+            }
+            after_rest = value.is_none();
+            rhs.extend(value);
+
+            let name = self.local_name(reg);
+            self.locals[reg as usize] = Some(name.clone());
+            names.push(name);
+        }
+        if !names.is_empty() {
+            self.push_local_var(names, rhs);
+        }
+        Ok(())
+    }
+
+    fn push_local_var(&mut self, names: Vec<String>, mut rhs: Vec<Expr>) {
+        // Declarations without values are initialised with `LOADNIL`.
+        if rhs.iter().all(|value| matches!(value, Expr::Nil)) {
+            rhs.clear();
+        }
+        self.stmts.push(Stmt::LocalVar(LocalVar { names, rhs }));
+    }
+
+    fn local(&self, reg: u32) -> Option<&str> {
+        self.locals.get(reg as usize)?.as_deref()
+    }
+
+    /// Declare the local variables that come into scope at the given instruction.
+    ///
+    /// Their initial values are the temporaries left in their registers.
+    /// The hidden control variables of `for` loops, whose names start with
+    /// `(`, are left to the loop.
+    fn start_locals(&mut self, pc: u32) -> Result<()> {
+        let regs: Vec<u32> = self
+            .local_regs()
+            .filter(|(_, local)| local.startpc == pc && !local.varname.starts_with('('))
+            .map(|(reg, _)| reg)
+            .collect();
+        if regs.is_empty() {
+            return Ok(());
+        }
+
+        let mut names = vec![];
+        let mut rhs = vec![];
+        for reg in regs {
+            match self.take_reg(reg)? {
+                Reg::Expr(value) => rhs.push(value),
+                Reg::Rest | Reg::Empty => {}
+                Reg::Method(..) | Reg::SelfArg => {
+                    return Error::new_parser("method call parts stored in local").into()
+                }
+            }
+            let name = self.local_name(reg);
+            self.locals[reg as usize] = Some(name.clone());
+            names.push(name);
+        }
+        self.push_local_var(names, rhs);
+
+        Ok(())
+    }
+
+    /// Remove the local variables that go out of scope at the given instruction.
+    fn end_locals(&mut self, pc: u32) {
+        let regs: Vec<u32> = self
+            .local_regs()
+            .filter(|(_, local)| local.endpc == pc)
+            .map(|(reg, _)| reg)
+            .collect();
+        for reg in regs {
+            self.locals[reg as usize] = None;
+        }
+    }
+
+    /// Debug information of local variables, paired with their register.
+    ///
+    /// Locals are allocated on the stack, so the register is the number
+    /// of locals still in scope when the variable was declared.
+    fn local_regs(&self) -> impl Iterator<Item = (u32, &super::Local)> + '_ {
+        let locals = &self.proto.locals;
+        locals.iter().enumerate().map(move |(index, local)| {
+            let reg = locals[..index]
+                .iter()
+                .filter(|other| other.startpc <= local.startpc && local.startpc < other.endpc)
+                .count() as u32;
+            (reg, local)
+        })
+    }
+
+    /// Name of the local variable in the register, from debug
+    /// information when available.
+    fn local_name(&self, reg: u32) -> String {
+        self.local_regs()
+            .filter(|(r, _)| *r == reg)
+            .map(|(_, local)| local.varname.clone())
+            .last()
+            .unwrap_or_else(|| {
+                if reg < self.proto.num_params as u32 {
+                    format!("p{}", reg + 1)
+                } else {
+                    format!("l{reg}")
+                }
+            })
+    }
+
+    fn upvalue_name(&self, index: u32) -> String {
+        self.proto
+            .upvalues
+            .get(index as usize)
+            .or_else(|| self.upvalue_names.get(index as usize))
+            .cloned()
+            .unwrap_or_else(|| format!("u{index}"))
+    }
+
+    fn constant(&self, index: u32) -> Result<Expr> {
+        match self.proto.constants.get(index as usize) {
+            Some(Constant::Nil) => Ok(Expr::Nil),
+            Some(Constant::Bool(value)) => Ok(Expr::Bool(*value)),
+            Some(Constant::Number(value)) => Ok(Expr::Number(*value)),
+            Some(Constant::String(value)) => Ok(Expr::Str(value.clone())),
+            None => Error::new_parser(format!("constant {index} out of bounds")).into(),
+        }
+    }
+
+    fn constant_string(&self, index: u32) -> Result<String> {
+        match self.constant(index)? {
+            Expr::Str(value) => Ok(value.to_string()),
+            _ => Error::new_parser(format!("constant {index} is not a string")).into(),
+        }
+    }
+}
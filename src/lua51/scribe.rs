@@ -0,0 +1,389 @@
+//! Code generator for Lua syntax.
+use std::fmt::Write as FmtWrite;
+use std::io;
+
+use super::ast::{
+    Assign, BinExpr, Block, Call, Expr, Failed, Function, GenericFor, If, LocalVar, NumericFor,
+    Repeat, Return, Stmt, Syntax, Table, UnaryExpr, UnaryOp, While,
+};
+use crate::errors::Result;
+use crate::style::ScribeConfig;
+use crate::writer::IoFmt;
+
+pub struct Scribe {
+    config: ScribeConfig,
+    level: u32,
+}
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in", "local",
+    "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+impl Default for Scribe {
+    fn default() -> Self {
+        Self::new(ScribeConfig::default())
+    }
+}
+
+impl Scribe {
+    pub fn new(config: ScribeConfig) -> Self {
+        Self { config, level: 0 }
+    }
+
+    pub fn fmt_syntax(&mut self, f: &mut impl FmtWrite, syntax: &Syntax) -> Result<()> {
+        self.fmt_block(f, &syntax.root)
+    }
+
+    /// Write the source to a byte stream as it's generated,
+    /// without building the whole source in memory.
+    pub fn write_syntax(&mut self, w: impl io::Write, syntax: &Syntax) -> Result<()> {
+        let mut f = IoFmt::new(w);
+        let result = self.fmt_syntax(&mut f, syntax);
+        f.finish(result)
+    }
+
+    /// Write the source to a file, replacing it if it exists.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_file(&mut self, path: impl AsRef<std::path::Path>, syntax: &Syntax) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_syntax(io::BufWriter::new(file), syntax)
+    }
+
+    fn with_indent<F>(&mut self, func: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        self.level += 1;
+        func(self)?;
+        self.level -= 1;
+        Ok(())
+    }
+
+    fn fmt_indent(&mut self, f: &mut impl FmtWrite) -> Result<()> {
+        self.config.fmt_indent(f, self.level)
+    }
+
+    fn fmt_block(&mut self, f: &mut impl FmtWrite, block: &Block) -> Result<()> {
+        for stmt in &block.stmts {
+            self.fmt_indent(f)?;
+            self.fmt_stmt(f, stmt)?;
+            self.config.fmt_newline(f)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_stmt(&mut self, f: &mut impl FmtWrite, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::LocalVar(local_var) => self.fmt_local_var(f, local_var),
+            Stmt::Assign(assign) => self.fmt_assign(f, assign),
+            Stmt::Call(call) => self.fmt_call(f, call),
+            Stmt::Return(ret) => self.fmt_return(f, ret),
+            Stmt::If(if_) => self.fmt_if(f, if_),
+            Stmt::While(while_) => self.fmt_while(f, while_),
+            Stmt::Repeat(repeat) => self.fmt_repeat(f, repeat),
+            Stmt::NumericFor(numeric_for) => self.fmt_numeric_for(f, numeric_for),
+            Stmt::GenericFor(generic_for) => self.fmt_generic_for(f, generic_for),
+            Stmt::Break => {
+                write!(f, "break")?;
+                Ok(())
+            }
+            Stmt::Failed(failed) => self.fmt_failed(f, failed),
+        }
+    }
+
+    fn fmt_local_var(&mut self, f: &mut impl FmtWrite, local_var: &LocalVar) -> Result<()> {
+        let LocalVar { names, rhs } = local_var;
+        write!(f, "local {}", names.join(", "))?;
+        if !rhs.is_empty() {
+            write!(f, " = ")?;
+            self.fmt_expr_list(f, rhs)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_assign(&mut self, f: &mut impl FmtWrite, assign: &Assign) -> Result<()> {
+        self.fmt_expr_list(f, &assign.targets)?;
+        write!(f, " = ")?;
+        self.fmt_expr_list(f, &assign.rhs)
+    }
+
+    fn fmt_return(&mut self, f: &mut impl FmtWrite, ret: &Return) -> Result<()> {
+        write!(f, "return")?;
+        if !ret.values.is_empty() {
+            write!(f, " ")?;
+            self.fmt_expr_list(f, &ret.values)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_if(&mut self, f: &mut impl FmtWrite, if_: &If) -> Result<()> {
+        write!(f, "if ")?;
+        self.fmt_expr(f, &if_.cond)?;
+        write!(f, " then")?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &if_.then))?;
+
+        // An `else` block holding only another `if` is an `elseif`.
+        let mut else_ = if_.else_.as_ref();
+        while let Some(block) = else_ {
+            match block.stmts.as_slice() {
+                [Stmt::If(elseif)] => {
+                    self.fmt_indent(f)?;
+                    write!(f, "elseif ")?;
+                    self.fmt_expr(f, &elseif.cond)?;
+                    write!(f, " then")?;
+                    self.config.fmt_newline(f)?;
+                    self.with_indent(|scribe| scribe.fmt_block(f, &elseif.then))?;
+                    else_ = elseif.else_.as_ref();
+                }
+                _ => {
+                    self.fmt_indent(f)?;
+                    write!(f, "else")?;
+                    self.config.fmt_newline(f)?;
+                    self.with_indent(|scribe| scribe.fmt_block(f, block))?;
+                    else_ = None;
+                }
+            }
+        }
+
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        Ok(())
+    }
+
+    fn fmt_while(&mut self, f: &mut impl FmtWrite, while_: &While) -> Result<()> {
+        write!(f, "while ")?;
+        self.fmt_expr(f, &while_.cond)?;
+        write!(f, " do")?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &while_.body))?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        Ok(())
+    }
+
+    fn fmt_numeric_for(&mut self, f: &mut impl FmtWrite, numeric_for: &NumericFor) -> Result<()> {
+        write!(f, "for {} = ", numeric_for.var)?;
+        self.fmt_expr(f, &numeric_for.start)?;
+        write!(f, ", ")?;
+        self.fmt_expr(f, &numeric_for.limit)?;
+        if let Some(step) = &numeric_for.step {
+            write!(f, ", ")?;
+            self.fmt_expr(f, step)?;
+        }
+        write!(f, " do")?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &numeric_for.body))?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        Ok(())
+    }
+
+    fn fmt_generic_for(&mut self, f: &mut impl FmtWrite, generic_for: &GenericFor) -> Result<()> {
+        write!(f, "for {} in ", generic_for.names.join(", "))?;
+        self.fmt_expr_list(f, &generic_for.exprs)?;
+        write!(f, " do")?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &generic_for.body))?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        Ok(())
+    }
+
+    fn fmt_repeat(&mut self, f: &mut impl FmtWrite, repeat: &Repeat) -> Result<()> {
+        write!(f, "repeat")?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &repeat.body))?;
+        self.fmt_indent(f)?;
+        write!(f, "until ")?;
+        self.fmt_expr(f, &repeat.cond)
+    }
+
+    /// Write the disassembly of instructions that couldn't be
+    /// decompiled in a block comment.
+    fn fmt_failed(&mut self, f: &mut impl FmtWrite, failed: &Failed) -> Result<()> {
+        // The comment is closed by the first matching bracket,
+        // so its level must not appear in the listing.
+        let mut level = String::new();
+        while failed.listing.contains(&format!("]{level}]"))
+            || failed.message.contains(&format!("]{level}]"))
+        {
+            level.push('=');
+        }
+
+        write!(f, "--[{level}[ DECOMPILE FAILED: {}", failed.message)?;
+        self.config.fmt_newline(f)?;
+        for line in failed.listing.lines() {
+            write!(f, "{line}")?;
+            self.config.fmt_newline(f)?;
+        }
+        self.fmt_indent(f)?;
+        write!(f, "]{level}]")?;
+        Ok(())
+    }
+
+    fn fmt_expr_list(&mut self, f: &mut impl FmtWrite, exprs: &[Expr]) -> Result<()> {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            self.fmt_expr(f, expr)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_expr(&mut self, f: &mut impl FmtWrite, expr: &Expr) -> Result<()> {
+        self.fmt_subexpr(f, expr, 0)
+    }
+
+    /// Format an expression, wrapping it in parentheses when its operator
+    /// binds weaker than the given priority.
+    fn fmt_subexpr(&mut self, f: &mut impl FmtWrite, expr: &Expr, limit: u32) -> Result<()> {
+        match expr {
+            Expr::Nil => write!(f, "nil")?,
+            Expr::Bool(value) => write!(f, "{value}")?,
+            Expr::Number(value) => self.config.fmt_number(f, *value)?,
+            Expr::Str(value) => self.config.fmt_string(f, value.as_bytes())?,
+            Expr::VarArg => write!(f, "...")?,
+            Expr::Name(name) => write!(f, "{name}")?,
+            Expr::Index(table, key) => {
+                self.fmt_prefix_expr(f, table)?;
+                match key.as_ref() {
+                    Expr::Str(name) if name.to_str().is_some_and(is_name) => write!(f, ".{name}")?,
+                    _ => {
+                        write!(f, "[")?;
+                        self.fmt_expr(f, key)?;
+                        write!(f, "]")?;
+                    }
+                }
+            }
+            Expr::Call(call) => self.fmt_call(f, call)?,
+            Expr::Binary(bin_expr) => self.fmt_binary_expr(f, bin_expr, limit)?,
+            Expr::Unary(unary_expr) => self.fmt_unary_expr(f, unary_expr, limit)?,
+            Expr::Table(table) => self.fmt_table(f, table)?,
+            Expr::Function(function) => self.fmt_function(f, function)?,
+        }
+        Ok(())
+    }
+
+    /// Format an expression that is indexed or called, which must
+    /// be parenthesized unless it's a variable or call.
+    fn fmt_prefix_expr(&mut self, f: &mut impl FmtWrite, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Name(_) | Expr::Index(..) | Expr::Call(_) => self.fmt_expr(f, expr),
+            _ => {
+                write!(f, "(")?;
+                self.fmt_expr(f, expr)?;
+                write!(f, ")")?;
+                Ok(())
+            }
+        }
+    }
+
+    fn fmt_binary_expr(
+        &mut self,
+        f: &mut impl FmtWrite,
+        bin_expr: &BinExpr,
+        limit: u32,
+    ) -> Result<()> {
+        let (left, right) = bin_expr.op.priority();
+        let wrap = left <= limit;
+        if wrap {
+            write!(f, "(")?;
+        }
+
+        // An operand binds tighter than the operator when its own priority is
+        // greater than the operator's priority on that side.
+        self.fmt_subexpr(f, &bin_expr.lhs, left)?;
+        if bin_expr.op.is_keyword() {
+            write!(f, " {} ", bin_expr.op.as_str())?;
+            self.fmt_subexpr(f, &bin_expr.rhs, right)?;
+        } else {
+            let mut rhs = self.config.fmt_operator(f, bin_expr.op.as_str())?;
+            self.fmt_subexpr(&mut rhs, &bin_expr.rhs, right)?;
+        }
+
+        if wrap {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+
+    fn fmt_unary_expr(
+        &mut self,
+        f: &mut impl FmtWrite,
+        unary_expr: &UnaryExpr,
+        limit: u32,
+    ) -> Result<()> {
+        let wrap = UnaryOp::PRIORITY <= limit;
+        if wrap {
+            write!(f, "(")?;
+        }
+        write!(f, "{}", unary_expr.op.as_str())?;
+        self.fmt_subexpr(f, &unary_expr.rhs, UnaryOp::PRIORITY)?;
+        if wrap {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+
+    fn fmt_call(&mut self, f: &mut impl FmtWrite, call: &Call) -> Result<()> {
+        self.fmt_prefix_expr(f, &call.name)?;
+        if let Some(method) = &call.method {
+            write!(f, ":{method}")?;
+        }
+        write!(f, "(")?;
+        self.fmt_expr_list(f, &call.args)?;
+        write!(f, ")")?;
+        Ok(())
+    }
+
+    fn fmt_table(&mut self, f: &mut impl FmtWrite, table: &Table) -> Result<()> {
+        if table.items.is_empty() && table.fields.is_empty() {
+            write!(f, "{{}}")?;
+            return Ok(());
+        }
+
+        write!(f, "{{ ")?;
+        self.fmt_expr_list(f, &table.items)?;
+        for (i, (key, value)) in table.fields.iter().enumerate() {
+            if i != 0 || !table.items.is_empty() {
+                write!(f, ", ")?;
+            }
+            match key {
+                Expr::Str(name) if name.to_str().is_some_and(is_name) => write!(f, "{name}")?,
+                _ => {
+                    write!(f, "[")?;
+                    self.fmt_expr(f, key)?;
+                    write!(f, "]")?;
+                }
+            }
+            write!(f, " = ")?;
+            self.fmt_expr(f, value)?;
+        }
+        write!(f, " }}")?;
+        Ok(())
+    }
+
+    fn fmt_function(&mut self, f: &mut impl FmtWrite, function: &Function) -> Result<()> {
+        let mut params = function.params.clone();
+        if function.is_vararg {
+            params.push("...".to_string());
+        }
+        write!(f, "function({})", params.join(", "))?;
+        self.config.fmt_newline(f)?;
+        self.with_indent(|scribe| scribe.fmt_block(f, &function.body))?;
+        self.fmt_indent(f)?;
+        write!(f, "end")?;
+        Ok(())
+    }
+}
+
+/// Checks whether the string is a valid identifier.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
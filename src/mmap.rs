@@ -0,0 +1,105 @@
+//! Read-only memory maps of files, so archives of hundreds of megabytes
+//! can be scanned and decoded without reading them into memory.
+//!
+//! Maps with `mmap` from the C library that the standard library already
+//! links against, so only 64 bit Unix platforms are supported, where the
+//! file offset argument is 64 bits wide.
+use std::ffi::c_void;
+use std::fs::File;
+use std::ops::Deref;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::ptr::NonNull;
+
+use crate::errors::Result;
+
+const PROT_READ: i32 = 1;
+const MAP_PRIVATE: i32 = 2;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+/// File mapped into memory read only, dereferencing to its bytes.
+///
+/// Pages are read from the file as they're used, and can be evicted again
+/// under memory pressure.
+pub struct Mmap {
+    /// Start of the mapping, or `None` for an empty file, which can't be mapped.
+    ptr: Option<NonNull<u8>>,
+    len: usize,
+}
+
+// The mapping is read only and owned by the value.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Map the whole file.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or written to, by this process or any
+    /// other, until the map is dropped. Truncating it faults on access to the
+    /// pages past its new end, and writing to it may change bytes that are
+    /// borrowed as immutable.
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len()).map_err(std::io::Error::other)?;
+        if len == 0 {
+            return Ok(Self { ptr: None, len });
+        }
+
+        // SAFETY: A fresh private mapping of the open file, which stays valid
+        // after the file is closed, and is checked for failure before use.
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ,
+                MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        // `MAP_FAILED` is all bits set.
+        if ptr as usize == usize::MAX {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self {
+            ptr: NonNull::new(ptr.cast()),
+            len,
+        })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.ptr {
+            // SAFETY: The mapping is `len` bytes long, and lives as long as `self`.
+            Some(ptr) => unsafe { std::slice::from_raw_parts(ptr.as_ptr(), self.len) },
+            None => &[],
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if let Some(ptr) = self.ptr {
+            // SAFETY: The mapping was made by `open`, and no slices of it outlive `self`.
+            unsafe {
+                munmap(ptr.as_ptr().cast(), self.len);
+            }
+        }
+    }
+}
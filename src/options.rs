@@ -0,0 +1,52 @@
+//! Options for decompiling whole chunks.
+//!
+//! Chunks found in the wild aren't always what a stock compiler produces.
+//! They may be damaged, or come from a modified Lua with extra opcodes.
+//! The [Tolerance] decides how much of that is accepted to get source out.
+use crate::diagnostics::Diagnostics;
+
+/// How to handle problems in a chunk, like unknown opcodes, global
+/// names that aren't UTF-8, or an operand stack that doesn't balance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tolerance {
+    /// Fail on the first problem.
+    #[default]
+    Strict,
+    /// Warn about problems, and keep the disassembly of the
+    /// instructions that can't be decompiled in a comment.
+    Lenient,
+    /// Warn about problems, and patch over them where possible,
+    /// like skipping instructions with unknown opcodes.
+    BestEffort,
+}
+
+/// Options for [crate::decompile_with].
+#[derive(Debug, Clone, Default)]
+pub struct DecompileOptions {
+    pub tolerance: Tolerance,
+}
+
+/// Decompiled source, with the diagnostics of the problems that were tolerated.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub source: String,
+    pub diagnostics: Diagnostics,
+}
+
+impl Tolerance {
+    /// Whether decompiling goes on after a problem.
+    pub fn is_tolerant(self) -> bool {
+        self != Tolerance::Strict
+    }
+}
+
+impl DecompileOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
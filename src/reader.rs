@@ -1,21 +1,227 @@
 #![allow(dead_code)]
-use std::io::Cursor;
+use std::io::{self, Cursor, Read};
+
+use crate::errors::{Error, Result};
+use crate::lstring::LuaString;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum Endian {
+pub enum Endian {
     Little,
     Big,
 }
 
+/// Representation of `lua_Number` on the platform a chunk was compiled for.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum NumberType {
+pub enum NumberType {
     F32,
     F64,
+    /// Integers, used by Luas patched for machines without floating point.
+    I32,
+    I64,
+}
+
+/// Resource limits for decoding untrusted chunks.
+///
+/// Chunks declare the sizes of their lists up front, so
+/// malformed chunks could otherwise make the decoder exhaust
+/// memory, or the stack when functions are nested deeply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of constants in each list of them in a function,
+    /// including its nested functions.
+    pub max_constants: u32,
+    /// Maximum number of instructions in a function.
+    pub max_code: u32,
+    /// Maximum nesting depth of functions, the main function being depth 1.
+    pub max_depth: u32,
 }
 
-pub struct CodeReader<'a> {
-    code: &'a [u8],
-    cursor: Cursor<&'a [u8]>,
+impl Default for Limits {
+    /// Limits well beyond what `luac` produces for real scripts.
+    fn default() -> Self {
+        Self {
+            max_constants: 1 << 18,
+            max_code: 1 << 24,
+            max_depth: 200,
+        }
+    }
+}
+
+/// Reader for the primitive types in a binary chunk.
+///
+/// Sizes and byte order are dictated by the chunk header, so
+/// they must be configured once the header has been read.
+///
+/// Reads from any [Read], so chunks can be decoded from files or
+/// archives without loading them into memory first. Unbuffered
+/// readers should be wrapped in an [std::io::BufReader].
+pub struct CodeReader<R> {
+    reader: R,
+    /// Bytes read so far.
+    position: u64,
+    endian: Endian,
     size_int: usize,
     size_t: usize,
 }
+
+impl<'a> CodeReader<Cursor<&'a [u8]>> {
+    pub fn new(code: &'a [u8]) -> Self {
+        Self::from_reader(Cursor::new(code))
+    }
+}
+
+impl<R: Read> CodeReader<R> {
+    pub fn from_reader(reader: R) -> Self {
+        Self {
+            reader,
+            position: 0,
+            endian: Endian::Little,
+            size_int: 4,
+            size_t: 4,
+        }
+    }
+
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian;
+    }
+
+    /// Set the size of C `int` in bytes.
+    pub fn set_size_int(&mut self, size_int: usize) {
+        self.size_int = size_int;
+    }
+
+    /// Set the size of C `size_t` in bytes.
+    pub fn set_size_t(&mut self, size_t: usize) {
+        self.size_t = size_t;
+    }
+
+    /// Byte offset into the chunk, from where the reader started.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        let [byte] = self.read_array::<1>()?;
+        Ok(byte)
+    }
+
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.reader.read_exact(buf)?;
+        self.position += buf.len() as u64;
+        Ok(())
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        let buf = self.read_array()?;
+        match self.endian {
+            Endian::Little => Ok(u16::from_le_bytes(buf)),
+            Endian::Big => Ok(u16::from_be_bytes(buf)),
+        }
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        let buf = self.read_array()?;
+        match self.endian {
+            Endian::Little => Ok(u32::from_le_bytes(buf)),
+            Endian::Big => Ok(u32::from_be_bytes(buf)),
+        }
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        let buf = self.read_array()?;
+        match self.endian {
+            Endian::Little => Ok(u64::from_le_bytes(buf)),
+            Endian::Big => Ok(u64::from_be_bytes(buf)),
+        }
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32> {
+        let buf = self.read_array()?;
+        match self.endian {
+            Endian::Little => Ok(f32::from_le_bytes(buf)),
+            Endian::Big => Ok(f32::from_be_bytes(buf)),
+        }
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64> {
+        let buf = self.read_array()?;
+        match self.endian {
+            Endian::Little => Ok(f64::from_le_bytes(buf)),
+            Endian::Big => Ok(f64::from_be_bytes(buf)),
+        }
+    }
+
+    /// Read a number of the given type, widened to `f64`.
+    pub fn read_number(&mut self, number_type: NumberType) -> Result<f64> {
+        match number_type {
+            NumberType::F32 => Ok(self.read_f32()? as f64),
+            NumberType::F64 => self.read_f64(),
+            NumberType::I32 => Ok(self.read_u32()? as i32 as f64),
+            NumberType::I64 => Ok(self.read_u64()? as i64 as f64),
+        }
+    }
+
+    /// Read a C `int`.
+    pub fn read_int(&mut self) -> Result<u32> {
+        match self.size_int {
+            2 => Ok(self.read_u16()? as u32),
+            4 => self.read_u32(),
+            8 => u32::try_from(self.read_u64()?)
+                .map_err(|_| Error::new_decoder("integer out of range")),
+            _ => Error::new_decoder(format!("unknown int size: {}", self.size_int)).into(),
+        }
+    }
+
+    /// Read a C `size_t`.
+    pub fn read_size_t(&mut self) -> Result<usize> {
+        match self.size_t {
+            2 => Ok(self.read_u16()? as usize),
+            4 => Ok(self.read_u32()? as usize),
+            8 => Ok(self.read_u64()? as usize),
+            _ => Error::new_decoder(format!("unknown size_t: {}", self.size_t)).into(),
+        }
+    }
+
+    /// Read a string prefixed with its `size_t` length.
+    ///
+    /// The length includes the nul terminator. A length of zero
+    /// encodes a `NULL` string, which is returned as empty.
+    pub fn read_lua_string(&mut self) -> Result<LuaString> {
+        let len = self.read_size_t()?;
+        if len == 0 {
+            return Ok(LuaString::default());
+        }
+        let mut buf = self.read_vec(len, "string length")?;
+        if buf.pop() != Some(0) {
+            return Error::new_decoder("string is not nul terminated").into();
+        }
+        Ok(LuaString::from(buf))
+    }
+
+    /// Read `len` bytes, where `what` describes the length in errors.
+    ///
+    /// The length can't be trusted, so the buffer only grows
+    /// as the data is read, rather than being allocated up front.
+    pub fn read_vec(&mut self, len: usize, what: &str) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        (&mut self.reader).take(len as u64).read_to_end(&mut buf)?;
+        self.position += buf.len() as u64;
+        if buf.len() != len {
+            let message = format!("{what} {len} exceeds chunk size");
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message).into());
+        }
+        Ok(buf)
+    }
+
+    /// Read a string that names something, like a source file or
+    /// local variable, decoding it as UTF-8.
+    pub fn read_string(&mut self) -> Result<String> {
+        Ok(self.read_lua_string()?.to_string_lossy().into_owned())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.read_bytes(&mut buf)?;
+        Ok(buf)
+    }
+}
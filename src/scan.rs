@@ -0,0 +1,96 @@
+//! Finding chunks embedded in larger files, like game archives.
+use std::ops::Range;
+
+use crate::any::{detect_version, LuaVersion};
+use crate::{lua32, lua40, lua50, lua51};
+
+/// Start of every chunk, the bytemark `Esc` followed by the signature.
+const CHUNK_START: &[u8] = b"\x1bLua";
+
+/// Chunk found embedded in a larger file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundChunk {
+    pub version: LuaVersion,
+    /// Byte range of the chunk in the file.
+    pub range: Range<usize>,
+}
+
+/// Find the byte ranges of the chunks embedded in the data.
+///
+/// See [scan_chunks].
+pub fn find_chunks(data: &[u8]) -> Vec<Range<usize>> {
+    scan_chunks(data)
+        .into_iter()
+        .map(|chunk| chunk.range)
+        .collect()
+}
+
+/// Find the chunks embedded in the data, with their Lua versions.
+///
+/// Candidates are found by their signature, and must decode completely to
+/// count, which also gives their size. The signature can occur by chance in
+/// other data, so a candidate that fails to decode is skipped. Each chunk
+/// can then be decoded from the slice of the data in its range.
+pub fn scan_chunks(data: &[u8]) -> Vec<FoundChunk> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    while let Some(offset) = find(&data[start..], CHUNK_START) {
+        let chunk_start = start + offset;
+        match chunk_size(&data[chunk_start..]) {
+            Some((version, size)) => {
+                chunks.push(FoundChunk {
+                    version,
+                    range: chunk_start..chunk_start + size,
+                });
+                start = chunk_start + size;
+            }
+            None => start = chunk_start + 1,
+        }
+    }
+    chunks
+}
+
+/// Offset of the chunk at the start of the data, after up to `max_len`
+/// bytes before its bytemark and signature, like a `#!` line or the
+/// header of a distribution format.
+///
+/// Only the signature is looked for, so the chunk may still fail to decode.
+pub fn find_chunk_start(data: &[u8], max_len: usize) -> Option<usize> {
+    let end = data.len().min(max_len + CHUNK_START.len());
+    find(&data[..end], CHUNK_START)
+}
+
+/// Version and size of the chunk at the start of the data,
+/// or `None` when it doesn't decode.
+fn chunk_size(code: &[u8]) -> Option<(LuaVersion, usize)> {
+    let version = detect_version(code).ok()?;
+    let size = match version {
+        LuaVersion::Lua32 => {
+            let mut decoder = lua32::Decoder::new(code);
+            decoder.decode().ok()?;
+            decoder.position()
+        }
+        LuaVersion::Lua40 => {
+            let mut decoder = lua40::Decoder::new(code);
+            decoder.decode().ok()?;
+            decoder.position()
+        }
+        LuaVersion::Lua50 => {
+            let mut decoder = lua50::Decoder::new(code);
+            decoder.decode().ok()?;
+            decoder.position()
+        }
+        LuaVersion::Lua51 => {
+            let mut decoder = lua51::Decoder::new(code);
+            decoder.decode().ok()?;
+            decoder.position()
+        }
+    };
+    Some((version, size as usize))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
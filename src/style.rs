@@ -0,0 +1,216 @@
+//! Code style options for generated source.
+use std::fmt::Write as FmtWrite;
+
+use crate::errors::Result;
+use crate::lstring::{fmt_escaped, long_string_text};
+
+/// Formatting conventions for the source written by a `Scribe`.
+#[derive(Debug, Clone)]
+pub struct ScribeConfig {
+    pub indent: Indent,
+    pub line_ending: LineEnding,
+    /// Surround binary operators with spaces, like `a + b` instead of `a+b`.
+    pub operator_spaces: bool,
+    pub quote: QuoteStyle,
+    /// Write strings that span lines as long strings, `[[...]]`, where
+    /// they read back the same, instead of quoted with `\n` escapes.
+    pub long_strings: bool,
+    pub number_format: NumberFormat,
+}
+
+/// Writes the right operand of a binary operator, spacing it from
+/// the operator when it would run into it, see [ScribeConfig::fmt_operator].
+pub(crate) struct OperandWriter<'a> {
+    inner: &'a mut dyn FmtWrite,
+    /// Put a space before the operand if it starts with a minus.
+    space_minus: bool,
+}
+
+impl FmtWrite for OperandWriter<'_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        if self.space_minus && !s.is_empty() {
+            self.space_minus = false;
+            if s.starts_with('-') {
+                self.inner.write_char(' ')?;
+            }
+        }
+        self.inner.write_str(s)
+    }
+}
+
+/// Characters written for each level of indentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    Spaces(u8),
+    Tabs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Unix style `\n`.
+    Lf,
+    /// Windows style `\r\n`.
+    CrLf,
+}
+
+/// Delimiter of string literals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteStyle {
+    Double,
+    Single,
+}
+
+/// How number literals are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// The fewest digits that read back as the same number, so recompiling
+    /// the source gives the same constants. Integers are written without
+    /// a fraction or exponent.
+    Shortest,
+    /// Like C's `%.14g`, the way `luac` writes numbers, which rounds
+    /// numbers that need more digits.
+    Luac,
+}
+
+impl Default for ScribeConfig {
+    fn default() -> Self {
+        Self {
+            indent: Indent::Spaces(4),
+            line_ending: LineEnding::Lf,
+            operator_spaces: true,
+            quote: QuoteStyle::Double,
+            long_strings: false,
+            number_format: NumberFormat::Shortest,
+        }
+    }
+}
+
+impl ScribeConfig {
+    pub(crate) fn fmt_indent(&self, f: &mut impl FmtWrite, level: u32) -> Result<()> {
+        for _ in 0..level {
+            match self.indent {
+                Indent::Spaces(width) => write!(f, "{:width$}", "", width = width as usize)?,
+                Indent::Tabs => write!(f, "\t")?,
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn fmt_newline(&self, f: &mut impl FmtWrite) -> Result<()> {
+        match self.line_ending {
+            LineEnding::Lf => writeln!(f)?,
+            LineEnding::CrLf => write!(f, "\r\n")?,
+        }
+        Ok(())
+    }
+
+    /// Write a binary operator, with the operand spacing, returning
+    /// the writer to write the right operand to.
+    ///
+    /// Without spaces, `..` would read as part of a number before it, so it's
+    /// always spaced, and `-` is spaced from a right operand that starts with
+    /// a minus, as `--` starts a comment.
+    pub(crate) fn fmt_operator<'a>(
+        &self,
+        f: &'a mut dyn FmtWrite,
+        op: &str,
+    ) -> Result<OperandWriter<'a>> {
+        let spaced = self.operator_spaces || op == "..";
+        if spaced {
+            write!(f, " {op} ")?;
+        } else {
+            write!(f, "{op}")?;
+        }
+        Ok(OperandWriter {
+            inner: f,
+            space_minus: !spaced && op == "-",
+        })
+    }
+
+    /// Write a number literal.
+    ///
+    /// Lua has no literals for infinity and NaN, so those are written as
+    /// the divisions that give them, in parentheses.
+    pub(crate) fn fmt_number(&self, f: &mut impl FmtWrite, value: f64) -> Result<()> {
+        if value.is_nan() {
+            write!(f, "(0/0)")?;
+        } else if value.is_infinite() {
+            let sign = if value < 0.0 { "-" } else { "" };
+            write!(f, "({sign}1/0)")?;
+        } else {
+            match self.number_format {
+                NumberFormat::Shortest => fmt_shortest(f, value)?,
+                NumberFormat::Luac => fmt_general(f, value, 14)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a quoted string literal, escaping bytes that
+    /// can't appear literally in Lua source.
+    pub(crate) fn fmt_string(&self, f: &mut impl FmtWrite, value: &[u8]) -> Result<()> {
+        if self.long_strings && value.contains(&b'\n') {
+            if let Some(text) = long_string_text(value) {
+                // The lexer skips a line break right after the opening brackets.
+                let skipped = if text.starts_with('\n') { "\n" } else { "" };
+                write!(f, "[[{skipped}{text}]]")?;
+                return Ok(());
+            }
+        }
+        let quote = match self.quote {
+            QuoteStyle::Double => '"',
+            QuoteStyle::Single => '\'',
+        };
+        fmt_escaped(f, value, quote)?;
+        Ok(())
+    }
+}
+
+/// Largest magnitude below which every integer is exactly representable.
+const MAX_EXACT_INTEGER: f64 = (1u64 << f64::MANTISSA_DIGITS) as f64;
+
+/// Write a finite number with the fewest digits that read back as the same number.
+fn fmt_shortest(f: &mut impl FmtWrite, value: f64) -> Result<()> {
+    if value.fract() == 0.0 && value.abs() < MAX_EXACT_INTEGER {
+        write!(f, "{}", value as i64)?;
+        return Ok(());
+    }
+    // Both are the shortest digits that round trip, but positional notation
+    // spells out every zero of very large and very small numbers.
+    let positional = value.to_string();
+    let scientific = format!("{value:e}");
+    if scientific.len() < positional.len() {
+        f.write_str(&scientific)?;
+    } else {
+        f.write_str(&positional)?;
+    }
+    Ok(())
+}
+
+/// Write a finite number like C's `%.{precision}g`.
+fn fmt_general(f: &mut impl FmtWrite, value: f64, precision: usize) -> Result<()> {
+    let scientific = format!("{value:.*e}", precision - 1);
+    // Formatting always gives an exponent, like `1.5e-7`.
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+
+    if exponent < -4 || exponent >= precision as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        let mantissa = trim_fraction(mantissa);
+        write!(f, "{mantissa}e{sign}{:02}", exponent.unsigned_abs())?;
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        let positional = format!("{value:.decimals$}");
+        f.write_str(trim_fraction(&positional))?;
+    }
+    Ok(())
+}
+
+/// Remove trailing zeros from the fraction, and the point if nothing's left of it.
+fn trim_fraction(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
@@ -0,0 +1,87 @@
+//! Interned names, shared by the syntax trees of a pipeline.
+//!
+//! Chunks use the same few names over and over, like `self` or the globals
+//! of a game's API, so names are stored once in a [SymbolTable] and referred
+//! to by a [Symbol] handle. Identifiers share the text of the table, so each
+//! distinct name is allocated once however often it occurs.
+//!
+//! A table belongs to the pipeline that fills it: the parser interns names
+//! into it, and hands it over with the syntax tree. Names are freed once the
+//! table and the trees referring to them are dropped.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Handle of a name interned in a [SymbolTable].
+///
+/// Symbols of the same table are equal when their names are,
+/// but aren't ordered by them. Symbols of different tables
+/// can't be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// Table of interned names.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    symbols: HashMap<Arc<str>, Symbol>,
+    names: Vec<Arc<str>>,
+}
+
+// ============================================================================
+
+impl Symbol {
+    /// Index of the symbol in its table, in the order names were interned.
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Symbol of the name, interning it the first time it's seen.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        self.intern_text(name).0
+    }
+
+    /// Symbol the name was interned as, if it was.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Name the symbol was interned from, or `None` for
+    /// a symbol of another table.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.names.get(symbol.0 as usize).map(|name| &**name)
+    }
+
+    /// Number of distinct names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Interned names, in the order of their symbols.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> + '_ {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (Symbol(index as u32), &**name))
+    }
+
+    /// Symbol of the name and the text it shares with the table.
+    pub(crate) fn intern_text(&mut self, name: &str) -> (Symbol, Arc<str>) {
+        if let Some((text, symbol)) = self.symbols.get_key_value(name) {
+            return (*symbol, text.clone());
+        }
+        let text: Arc<str> = name.into();
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(text.clone());
+        self.symbols.insert(text.clone(), symbol);
+        (symbol, text)
+    }
+}
@@ -0,0 +1,121 @@
+//! Diagnostic events emitted while decoding and parsing.
+//!
+//! The library never prints on its own. Callers that want to follow
+//! progress pass a [Trace] implementation to the decoder or parser.
+use std::cell::RefCell;
+use std::fmt;
+
+/// Verbosity of an event, from most to least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Receiver of diagnostic events.
+pub trait Trace {
+    /// Whether events of the given level are wanted.
+    ///
+    /// Checked before an event is formatted, so disabled
+    /// events cost nothing.
+    fn enabled(&self, level: Level) -> bool;
+
+    fn event(&self, level: Level, args: fmt::Arguments);
+}
+
+/// Discards all events.
+pub struct NoTrace;
+
+/// Writes events up to a maximum level to stderr.
+///
+/// Not available on WebAssembly, which has no stderr.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct StderrTrace {
+    max_level: Level,
+}
+
+/// Keeps the messages of events up to a maximum level, for callers
+/// that return them with a result instead of reporting them as they happen.
+pub struct CollectTrace {
+    max_level: Level,
+    messages: RefCell<Vec<String>>,
+}
+
+// ============================================================================
+
+/// Emit an event to a [Trace], formatting the message only when the level is enabled.
+macro_rules! trace_event {
+    ($trace:expr, $level:expr, $($arg:tt)+) => {
+        if $trace.enabled($level) {
+            $trace.event($level, format_args!($($arg)+))
+        }
+    };
+}
+
+pub(crate) use trace_event;
+
+impl Trace for NoTrace {
+    fn enabled(&self, _level: Level) -> bool {
+        false
+    }
+
+    fn event(&self, _level: Level, _args: fmt::Arguments) {}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StderrTrace {
+    pub fn new(max_level: Level) -> Self {
+        Self { max_level }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Trace for StderrTrace {
+    fn enabled(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+
+    fn event(&self, level: Level, args: fmt::Arguments) {
+        eprintln!("[{level}] {args}");
+    }
+}
+
+impl CollectTrace {
+    pub fn new(max_level: Level) -> Self {
+        Self {
+            max_level,
+            messages: RefCell::new(vec![]),
+        }
+    }
+
+    /// Messages of the events received so far, in order.
+    pub fn into_messages(self) -> Vec<String> {
+        self.messages.into_inner()
+    }
+}
+
+impl Trace for CollectTrace {
+    fn enabled(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+
+    fn event(&self, _level: Level, args: fmt::Arguments) {
+        self.messages.borrow_mut().push(args.to_string());
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        write!(f, "{name}")
+    }
+}
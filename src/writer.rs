@@ -0,0 +1,39 @@
+//! Adapters between formatting and byte output.
+use std::fmt;
+use std::io;
+
+use crate::errors::Result;
+
+/// Forwards formatted text to an [io::Write].
+///
+/// [fmt::Write] can't carry the cause of a failure, so the I/O
+/// error is kept aside until [IoFmt::finish] is called.
+pub(crate) struct IoFmt<W: io::Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> IoFmt<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
+
+    /// Flush the output, and recover the I/O error that interrupted formatting.
+    pub(crate) fn finish(mut self, result: Result<()>) -> Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+        result?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: io::Write> fmt::Write for IoFmt<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
@@ -0,0 +1,63 @@
+//! Trailing comments with the instructions and lines each statement came from.
+use lua_decompiler::lua40::{Decoder, Parser, Scribe};
+
+const LINES: &[u8] = include_bytes!("fixtures/lines.lua4");
+const IFELSE: &[u8] = include_bytes!("fixtures/ifelse.lua4");
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+
+fn annotate(code: &[u8], strip: bool) -> String {
+    let mut proto = Decoder::new(code).decode().expect("failed to decode");
+    if strip {
+        proto.strip();
+    }
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    Scribe::default()
+        .annotate(true)
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    buf
+}
+
+#[test]
+fn test_lines() {
+    assert_eq!(
+        annotate(LINES, false),
+        "a = 1  -- [1-2] line 1\nb = 2  -- [3-4] line 2\nc = 3  -- [5-6] line 4\nd = 4  -- [7-8] line 10\n"
+    );
+    // Without debug information, only the instructions are known.
+    assert_eq!(
+        annotate(LINES, true),
+        "a = 1  -- [1-2]\nb = 2  -- [3-4]\nc = 3  -- [5-6]\nd = 4  -- [7-8]\n"
+    );
+}
+
+#[test]
+fn test_blocks() {
+    assert_eq!(
+        annotate(IFELSE, false),
+        "if x <= 1 then  -- [1-10]\n    print(\"a\")  -- [4-6]\nelse\n    print(\"b\")  -- [8-10]\nend\n"
+    );
+}
+
+#[test]
+fn test_nested_functions() {
+    // Instructions of nested functions are numbered within their function.
+    assert_eq!(
+        annotate(UPVALUE, false),
+        "local a = 1  -- [1]\nf = function()  -- [2-4]\n    print(%a)  -- [1-3]\nend\ng = function()  -- [5-7]\n    print(%print)  -- [1-3]\nend\n"
+    );
+}
+
+#[test]
+fn test_disabled() {
+    let proto = Decoder::new(LINES).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    Scribe::default()
+        .annotate(true)
+        .annotate(false)
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(buf, "a = 1\nb = 2\nc = 3\nd = 4\n");
+}
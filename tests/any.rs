@@ -0,0 +1,57 @@
+//! Detecting the version of a chunk and dispatching to its decoder.
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::{decode_any, decompile, detect_version, AnyProto, LuaVersion};
+
+const LUA32: &[u8] = include_bytes!("fixtures/lua32/hello.lua32");
+const LUA40: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const LUA50: &[u8] = include_bytes!("fixtures/lua50/hello.lua50");
+const LUA51: &[u8] = include_bytes!("fixtures/lua51/loops.lua51");
+
+#[test]
+fn test_detect_version() {
+    let cases = [
+        (LUA32, LuaVersion::Lua32),
+        (LUA40, LuaVersion::Lua40),
+        (LUA50, LuaVersion::Lua50),
+        (LUA51, LuaVersion::Lua51),
+    ];
+    for (code, version) in cases {
+        assert_eq!(detect_version(code).expect("unknown version"), version);
+        let proto = decode_any(code).expect("failed to decode");
+        assert_eq!(proto.version(), version);
+    }
+    assert_eq!(LuaVersion::Lua32.to_string(), "Lua 3.2");
+    assert_eq!(LuaVersion::Lua51.to_string(), "Lua 5.1");
+}
+
+#[test]
+fn test_dispatch() {
+    match decode_any(LUA40).expect("failed to decode") {
+        AnyProto::Lua40(proto) => assert_eq!(proto.source(), "@test.lua"),
+        proto => panic!("decoded as {}", proto.version()),
+    }
+}
+
+#[test]
+fn test_bad_header() {
+    let decoder_error = |code: &[u8]| {
+        let err = detect_version(code).expect_err("detected a version");
+        assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+        err.to_string()
+    };
+    assert!(decoder_error(b"").contains("bytemark"));
+    assert!(decoder_error(b"\x1bLuc\x40").contains("signature"));
+    assert!(decoder_error(b"\x1bLua").contains("missing version"));
+    assert!(decoder_error(b"\x1bLua\x52").contains("unsupported Lua version: 52"));
+    assert!(decode_any(b"\x1bLua\x52").is_err());
+}
+
+#[test]
+fn test_decompile_versions() {
+    assert!(decompile(LUA40).is_ok());
+    assert!(decompile(LUA51).is_ok());
+    for code in [LUA32, LUA50] {
+        let err = decompile(code).expect_err("decompiled a legacy chunk");
+        assert!(matches!(err.kind(), ErrorKind::Unsupported(_)));
+    }
+}
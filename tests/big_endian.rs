@@ -0,0 +1,40 @@
+//! Decoding chunks compiled on big-endian machines.
+//!
+//! The fixtures hold the same function, compiled for each byte order:
+//!
+//! ```lua
+//! local x = 7
+//! print("hello", x)
+//! ```
+mod common;
+
+use common::decompile;
+use lua_decompiler::lua40::{Decoder, Endian};
+
+const HELLO_BE: &[u8] = include_bytes!("fixtures/hello_be.lua4");
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+#[test]
+fn test_big_endian_header() {
+    let chunk = Decoder::new(HELLO_BE)
+        .decode_chunk()
+        .expect("failed to decode");
+    assert_eq!(chunk.header().endianess, Endian::Big);
+
+    let main = chunk.main();
+    assert_eq!(main.source(), "@test.lua");
+    assert_eq!(main.max_stack(), 10);
+    assert_eq!(main.instrs().len(), 6);
+    assert_eq!(main.locals()[0].varname, "x");
+    assert_eq!(main.locals()[0].endpc, 6);
+}
+
+#[test]
+fn test_big_endian_decompile() {
+    assert_eq!(decompile(HELLO_BE), "local x = 7\nprint(\"hello\", x)\n");
+}
+
+#[test]
+fn test_byte_order_agrees() {
+    assert_eq!(decompile(HELLO_BE), decompile(HELLO_LE));
+}
@@ -0,0 +1,81 @@
+//! Call graphs of chunks, from their syntax trees.
+use lua_decompiler::lua40::{self, call_graph, Callee, Decoder, ProtoPath};
+
+const CALLGRAPH: &[u8] = include_bytes!("fixtures/callgraph.lua4");
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+
+fn graph(code: &[u8]) -> lua40::CallGraph {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let syntax = lua40::Parser::new(&proto).parse().expect("failed to parse");
+    call_graph(&syntax)
+}
+
+fn path(text: &str) -> ProtoPath {
+    text.parse().expect("invalid path")
+}
+
+#[test]
+fn test_call_graph() {
+    let graph = graph(CALLGRAPH);
+    let names: Vec<_> = graph
+        .functions
+        .iter()
+        .map(|node| (node.path.to_string(), node.name.as_deref()))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("main".to_string(), None),
+            ("main.0".to_string(), Some("say")),
+            ("main.1".to_string(), Some("add"))
+        ]
+    );
+
+    // Calls through locals, upvalues and globals are resolved to the functions assigned to them.
+    let calls: Vec<_> = graph
+        .calls
+        .iter()
+        .map(|edge| {
+            (
+                edge.caller.to_string(),
+                edge.callee.clone(),
+                edge.target.as_ref().map(ToString::to_string),
+            )
+        })
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            (
+                "main.0".to_string(),
+                Callee::Global("print".to_string()),
+                None
+            ),
+            (
+                "main.1".to_string(),
+                Callee::Upvalue("say".to_string()),
+                Some("main.0".to_string())
+            ),
+            (
+                "main".to_string(),
+                Callee::Local("say".to_string()),
+                Some("main.0".to_string())
+            ),
+            (
+                "main".to_string(),
+                Callee::Global("add".to_string()),
+                Some("main.1".to_string())
+            ),
+        ]
+    );
+    assert_eq!(graph.calls_from(&path("main")).count(), 2);
+}
+
+#[test]
+fn test_call_graph_dot() {
+    // Both functions call the global `print`, which isn't a function of the chunk.
+    assert_eq!(
+        graph(UPVALUE).dot().to_string(),
+        "digraph calls {\n    \"main\" [label=\"main\"];\n    \"main.0\" [label=\"f\\nmain.0\"];\n    \"main.1\" [label=\"g\\nmain.1\"];\n    \"print\" [shape=box];\n    \"main.0\" -> \"print\";\n    \"main.1\" -> \"print\";\n}\n"
+    );
+}
@@ -0,0 +1,62 @@
+//! Checking decompiled source against the Lua 4.0 grammar.
+use std::fs;
+use std::path::Path;
+
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::lua40::check_syntax;
+
+#[test]
+fn test_golden_sources() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for entry in fs::read_dir(dir).expect("failed to read fixtures") {
+        let path = entry.expect("failed to read fixture").path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("lua") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).expect("failed to read source");
+        if let Err(err) = check_syntax(&source) {
+            panic!("{}: {err}", path.display());
+        }
+    }
+}
+
+#[test]
+fn test_valid() {
+    let source = r#"
+local a, b = 1, -2.5e3
+t = {1, 2; x = "x", ["y"] = [[long [[nested]] string]]}
+function t.f(self, ...)
+    if not a and b ~= nil then
+        return %print(self)
+    elseif a then
+        while a < 10 do a = a + 1 end
+    else
+        repeat break until a
+    end
+end
+function t:g() for i = 1, 10, 2 do end for k, v in t do end end
+t:g() t.f "x" t.f {}
+do local c = (a .. b) ^ 2 end
+return
+"#;
+    check_syntax(source).expect("valid source");
+}
+
+#[test]
+fn test_invalid() {
+    let cases = [
+        ("if x then\n    f()\n", "line 2: f()"),
+        ("x = a != b\n", "line 1: x = a != b"),
+        ("return 1\nf()\n", "line 2: f()"),
+        ("f() = 1\n", "line 1: f() = 1"),
+        ("%x = 1\n", "line 1: %x = 1"),
+        ("x\n", "line 1: x"),
+        ("x = \"a\ny\"\n", "line 1: x = \"a"),
+        ("t = {1; 2}\n", "line 1: t = {1; 2}"),
+    ];
+    for (source, context) in cases {
+        let err = check_syntax(source).expect_err(source);
+        assert!(matches!(err.kind(), ErrorKind::Internal(_)), "{source}");
+        assert_eq!(err.context(), Some(context), "{source}");
+    }
+}
@@ -0,0 +1,96 @@
+//! Decoded chunks, and the accessors of their functions.
+use lua_decompiler::lua40::{Decoder, Endian, NumberType, Opcode};
+use lua_decompiler::LuaString;
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const LINES: &[u8] = include_bytes!("fixtures/lines.lua4");
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+const UNARY: &[u8] = include_bytes!("fixtures/unary.lua4");
+
+#[test]
+fn test_chunk() {
+    let chunk = Decoder::new(HELLO)
+        .decode_chunk()
+        .expect("failed to decode");
+    assert_eq!(chunk.header().endianess, Endian::Little);
+    assert_eq!(chunk.header().number_type, NumberType::F64);
+
+    let main = chunk.main();
+    assert_eq!(main.source(), "@test.lua");
+    assert_eq!(main.line_defined(), 0);
+    assert_eq!(main.num_params(), 0);
+    assert!(!main.is_vararg());
+    assert_eq!(main.max_stack(), 10);
+    let strings: Vec<_> = main.strings().iter().map(LuaString::to_str).collect();
+    assert_eq!(strings, [Some("print"), Some("hello")]);
+    assert!(main.numbers().is_empty());
+    assert!(main.protos().is_empty());
+    assert_eq!(main.code().len(), main.instrs().len());
+    assert_eq!(main.instrs()[0].opcode, Opcode::PushInt);
+
+    let instrs = main.instrs().len();
+    assert_eq!(chunk.into_main().instrs().len(), instrs);
+}
+
+#[test]
+fn test_nested_protos() {
+    let chunk = Decoder::new(PARAMS)
+        .decode_chunk()
+        .expect("failed to decode");
+    let protos = chunk.main().protos();
+    assert_eq!(protos.len(), 2);
+
+    let add = &protos[0];
+    assert_eq!(add.line_defined(), 1);
+    assert_eq!(add.num_params(), 2);
+    assert!(!add.is_vararg());
+    assert_eq!(protos[1].num_params(), 1);
+    assert!(protos[1].is_vararg());
+}
+
+#[test]
+fn test_locals() {
+    let chunk = Decoder::new(PARAMS)
+        .decode_chunk()
+        .expect("failed to decode");
+    let add = &chunk.main().protos()[0];
+    let names: Vec<_> = add
+        .locals()
+        .iter()
+        .map(|local| local.varname.as_str())
+        .collect();
+    assert_eq!(names, ["x", "y"]);
+    assert_eq!(add.local_name(1, 0), Some("y"));
+    assert_eq!(add.local_name(2, 0), None);
+
+    // Stripped of debug information.
+    assert!(chunk.main().protos()[1].locals().is_empty());
+    assert_eq!(chunk.main().protos()[1].local_name(0, 0), None);
+}
+
+#[test]
+fn test_lines() {
+    let chunk = Decoder::new(LINES)
+        .decode_chunk()
+        .expect("failed to decode");
+    let main = chunk.main();
+    assert!(!main.lines().is_empty());
+    let lines: Vec<_> = (0..main.instrs().len())
+        .map(|pc| main.line_at(pc))
+        .collect();
+    assert_eq!(lines, [1, 1, 2, 2, 4, 4, 10, 10, 10].map(Some));
+
+    let chunk = Decoder::new(HELLO)
+        .decode_chunk()
+        .expect("failed to decode");
+    assert!(chunk.main().lines().is_empty());
+    assert_eq!(chunk.main().line_at(0), None);
+}
+
+#[test]
+fn test_numbers() {
+    let chunk = Decoder::new(UNARY)
+        .decode_chunk()
+        .expect("failed to decode");
+    assert!(chunk.main().numbers().contains(&2.5));
+}
@@ -0,0 +1,761 @@
+//! Running the `luad` command line tool.
+#![cfg(feature = "cli")]
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use lua_decompiler::VERSION;
+
+const HELLO: &str = "tests/fixtures/hello_le.lua4";
+const HELLO_SOURCE: &str = "local x = 7\nprint(\"hello\", x)\n";
+const HELLO_LUA32: &str = "tests/fixtures/lua32/hello.lua32";
+
+/// Run `luad` from the crate's directory, so fixtures can be given by relative paths.
+fn luad(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_luad"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run luad")
+}
+
+/// Run `luad` with the input written to its stdin.
+fn luad_with_input(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_luad"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run luad");
+    child
+        .stdin
+        .take()
+        .expect("no stdin")
+        .write_all(input)
+        .expect("failed to write stdin");
+    child.wait_with_output().expect("failed to run luad")
+}
+
+fn stdout(output: &Output) -> &str {
+    assert!(
+        output.status.success(),
+        "luad failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    std::str::from_utf8(&output.stdout).expect("output isn't UTF-8")
+}
+
+/// Path in the temporary directory, unique to the test.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("luad-cli-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn test_header_comment() {
+    let output = luad(&["decompile", "--header", "--simplify", HELLO]);
+    let source = stdout(&output);
+    let mut lines = source.lines();
+    assert_eq!(
+        lines.next(),
+        Some(format!("-- decompiled by luad {VERSION}").as_str())
+    );
+    let options = lines.next().expect("no options line");
+    assert!(options.starts_with("-- options: group_locals=false,"));
+    assert!(options.contains(",simplify=true,"));
+    assert!(source.ends_with(HELLO_SOURCE));
+}
+
+#[test]
+fn test_manifest() {
+    let path = temp_path("manifest.json");
+    let manifest = path.to_str().expect("path isn't UTF-8");
+    let output = luad(&["decompile", "--manifest", manifest, "--indent", "2", HELLO]);
+    assert_eq!(stdout(&output), HELLO_SOURCE);
+
+    let json = std::fs::read_to_string(&path).expect("no manifest");
+    std::fs::remove_file(&path).expect("failed to remove");
+    assert!(json.contains(&format!("\"version\": \"{VERSION}\"")));
+    assert!(json.contains(&format!("\"input\": \"{HELLO}\"")));
+    assert!(json.contains("\"indent\": 2,"));
+    // Quotes in the fingerprint are escaped.
+    assert!(json.contains(",tolerance=\\\"strict\\\","));
+}
+
+#[test]
+fn test_fingerprint_follows_options() {
+    let fingerprint = |args: &[&str]| {
+        let output = luad(&[&["decompile", "--header"], args, &[HELLO]].concat());
+        stdout(&output).lines().nth(1).map(str::to_string)
+    };
+    assert_eq!(fingerprint(&[]), fingerprint(&[]));
+    assert_ne!(fingerprint(&[]), fingerprint(&["--tabs"]));
+    // Options that don't change the source aren't recorded.
+    assert_eq!(fingerprint(&[]), fingerprint(&["--color", "never"]));
+}
+
+#[test]
+fn test_lua32() {
+    let output = luad(&["disasm", HELLO_LUA32]);
+    assert!(stdout(&output).contains("\tCALLFUNC\t1 0\n"));
+
+    // Lua 3.2 chunks can only be disassembled.
+    let output = luad(&["decompile", HELLO_LUA32]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("unsupported: decompiling Lua 3.2 chunks, use `luad disasm`"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_batch_counts_failures() {
+    let dir = temp_path("batch");
+    std::fs::create_dir_all(&dir).expect("failed to create directory");
+    std::fs::copy(HELLO, dir.join("hello.out")).expect("failed to copy");
+    std::fs::copy("tests/fixtures/lua50/hello.lua50", dir.join("legacy.out"))
+        .expect("failed to copy");
+
+    let output = luad(&["decompile", dir.to_str().expect("path isn't UTF-8")]);
+    let source = std::fs::read_to_string(dir.join("hello.lua"));
+    let legacy = dir.join("legacy.lua").exists();
+    std::fs::remove_dir_all(&dir).expect("failed to remove");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("decompiling Lua 5.0 chunks"), "{stderr}");
+    assert!(stderr.contains("decompiled 1 of 2 files"), "{stderr}");
+    assert_eq!(source.expect("hello wasn't decompiled"), HELLO_SOURCE);
+    assert!(!legacy);
+}
+
+#[test]
+fn test_lua51_rejects_lua40_options() {
+    let output = luad(&[
+        "decompile",
+        "--annotate",
+        "tests/fixtures/lua51/loops.lua51",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--annotate with Lua 5.1 chunks"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_verbose() {
+    // Progress goes to stderr, leaving the source alone.
+    let output = luad(&["-v", "decompile", HELLO]);
+    assert_eq!(stdout(&output), HELLO_SOURCE);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("[info] version: 40,"), "{stderr}");
+
+    let output = luad(&["decompile", HELLO]);
+    assert_eq!(stdout(&output), HELLO_SOURCE);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_annotate() {
+    let output = luad(&["decompile", "--annotate", HELLO]);
+    assert_eq!(
+        stdout(&output),
+        "local x = 7  -- [1]\nprint(\"hello\", x)  -- [2-5]\n"
+    );
+}
+
+#[test]
+fn test_lenient() {
+    let chunk = "tests/fixtures/failed.lua4";
+    let output = luad(&["decompile", chunk]);
+    assert!(!output.status.success());
+
+    let output = luad(&["decompile", "--lenient", chunk]);
+    let source = stdout(&output);
+    assert!(source.contains("-- DECOMPILE FAILED: "), "{source}");
+    assert!(source.ends_with("\ny = 2\n"), "{source}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("[warn] parser error: "), "{stderr}");
+}
+
+#[test]
+fn test_output_file() {
+    let path = temp_path("output.lua");
+    let output = luad(&[
+        "decompile",
+        "-o",
+        path.to_str().expect("path isn't UTF-8"),
+        HELLO,
+    ]);
+    assert_eq!(stdout(&output), "");
+    let source = std::fs::read_to_string(&path).expect("no output file");
+    std::fs::remove_file(&path).expect("failed to remove");
+    assert_eq!(source, HELLO_SOURCE);
+}
+
+#[test]
+fn test_batch() {
+    let dir = temp_path("batch-output");
+    let out_dir = dir.join("out");
+    std::fs::create_dir_all(&dir).expect("failed to create directory");
+    std::fs::copy(HELLO, dir.join("hello.lub")).expect("failed to copy");
+    std::fs::copy("tests/fixtures/upvalue.lua4", dir.join("upvalue.out")).expect("failed to copy");
+    // Only chunk extensions are decompiled.
+    std::fs::write(dir.join("notes.txt"), "not a chunk").expect("failed to write");
+
+    let output = luad(&[
+        "decompile",
+        dir.to_str().expect("path isn't UTF-8"),
+        "-o",
+        out_dir.to_str().expect("path isn't UTF-8"),
+    ]);
+    let hello = std::fs::read_to_string(out_dir.join("hello.lua"));
+    let upvalue = std::fs::read_to_string(out_dir.join("upvalue.lua"));
+    let notes = out_dir.join("notes.lua").exists();
+    std::fs::remove_dir_all(&dir).expect("failed to remove");
+
+    assert_eq!(stdout(&output), "");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("decompiled 2 of 2 files"), "{stderr}");
+    assert_eq!(hello.expect("hello wasn't decompiled"), HELLO_SOURCE);
+    assert!(upvalue
+        .expect("upvalue wasn't decompiled")
+        .starts_with("local a = 1\n"));
+    assert!(!notes);
+}
+
+#[test]
+fn test_batch_error_format() {
+    let dir = temp_path("batch-json");
+    std::fs::create_dir_all(&dir).expect("failed to create directory");
+    std::fs::write(dir.join("broken.out"), b"\x1bLua\x40").expect("failed to write");
+
+    let output = luad(&[
+        "decompile",
+        "--error-format",
+        "json",
+        dir.to_str().expect("path isn't UTF-8"),
+    ]);
+    std::fs::remove_dir_all(&dir).expect("failed to remove");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut lines = stderr.lines();
+    let error = lines.next().expect("no error reported");
+    assert!(
+        error.starts_with('{') && error.contains("broken.out"),
+        "{stderr}"
+    );
+    assert_eq!(lines.next(), Some("decompiled 0 of 1 files"));
+}
+
+#[test]
+fn test_info() {
+    let output = luad(&["info", HELLO]);
+    let info = stdout(&output);
+    assert!(info.starts_with("version       Lua 4.0\n"), "{info}");
+    assert!(info.contains("\ninstructions  6\n"), "{info}");
+
+    let output = luad(&["info", "--json", HELLO]);
+    let json = stdout(&output);
+    assert!(json.starts_with("{\"version\": \"Lua 4.0\", "), "{json}");
+    assert!(json.contains("\"instructions\": 6, "), "{json}");
+}
+
+#[test]
+fn test_scan() {
+    let path = temp_path("archive.bin");
+    let hello = std::fs::read(HELLO).expect("failed to read");
+    let upvalue = std::fs::read("tests/fixtures/upvalue.lua4").expect("failed to read");
+    std::fs::write(&path, [&b"header"[..], &hello, &upvalue].concat()).expect("failed to write");
+    let archive = path.to_str().expect("path isn't UTF-8");
+
+    let text = luad(&["scan", archive]);
+    let json = luad(&["scan", "--json", archive]);
+    std::fs::remove_file(&path).expect("failed to remove");
+
+    let end = 6 + hello.len();
+    assert_eq!(
+        stdout(&text),
+        format!(
+            "0x6..{end:#x}\tLua 4.0\t{} bytes\n{end:#x}..{:#x}\tLua 4.0\t{} bytes\n",
+            hello.len(),
+            end + upvalue.len(),
+            upvalue.len()
+        )
+    );
+    assert!(stdout(&json).starts_with(&format!(
+        "[{{\"start\": 6, \"end\": {end}, \"version\": \"Lua 4.0\"}}, "
+    )));
+}
+
+#[test]
+fn test_disasm_function() {
+    let output = luad(&[
+        "disasm",
+        "--function",
+        "main.1",
+        "tests/fixtures/upvalue.lua4",
+    ]);
+    let listing = stdout(&output);
+    assert!(
+        listing.starts_with("function <@test.lua:3> (4 instructions)\n"),
+        "{listing}"
+    );
+    assert_eq!(listing.matches("function <").count(), 1);
+}
+
+#[test]
+fn test_exit_codes() {
+    // Usage errors.
+    assert_eq!(luad(&[]).status.code(), Some(2));
+    assert_eq!(luad(&["unknown"]).status.code(), Some(2));
+
+    let output = luad(&["info", "tests/fixtures/missing.lua4"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_inspect() {
+    let commands = "ls\nk main.1\ncd main.0\ndis\nbogus\nd main.9\nq\nls\n";
+    let output = luad_with_input(
+        &["inspect", "tests/fixtures/upvalue.lua4"],
+        commands.as_bytes(),
+    );
+    assert_eq!(
+        stdout(&output),
+        "main\t@test.lua:0\t8 instructions\n\
+         main.0\t@test.lua:2\t4 instructions\n\
+         main.1\t@test.lua:3\t4 instructions\n\
+         string 0\t\"print\"\n\
+         \t1\t[-]\tGETGLOBAL\t0\t; \"print\"\n\
+         \t2\t[-]\tPUSHUPVALUE\t0\n\
+         \t3\t[-]\tCALL\t0 0\n\
+         \t4\t[-]\tEND\n"
+    );
+    // Errors don't end the session, but quitting does.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "error: unknown command \"bogus\", try help\nerror: parser error: no function main.9\n"
+    );
+}
+
+#[test]
+fn test_inspect_decompile() {
+    let output = luad_with_input(&["inspect", "tests/fixtures/upvalue.lua4"], b"d main.1\n");
+    assert_eq!(
+        stdout(&output),
+        "local function_1 = function()\n    print(%print)\nend\n"
+    );
+}
+
+#[test]
+fn test_stdin() {
+    let chunk = std::fs::read(HELLO).expect("failed to read");
+    let output = luad_with_input(&["decompile", "-"], &chunk);
+    assert_eq!(stdout(&output), HELLO_SOURCE);
+    assert!(output.stderr.is_empty());
+
+    // Diagnostics go to stderr, leaving only the source on stdout.
+    let chunk = std::fs::read("tests/fixtures/failed.lua4").expect("failed to read");
+    let output = luad_with_input(&["decompile", "--lenient", "-"], &chunk);
+    let source = stdout(&output);
+    assert!(source.starts_with("x = 1\n"), "{source}");
+    assert!(!source.contains("[warn]"), "{source}");
+    assert!(!output.stderr.is_empty());
+
+    let output = luad_with_input(&["decompile", "-"], b"");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: -: decoder error: "), "{stderr}");
+}
+
+#[test]
+fn test_rename() {
+    let map = temp_path("rename.toml");
+    let globals = temp_path("globals.txt");
+    std::fs::write(
+        &map,
+        "[globals]\nprint = \"write\"\n\n[locals.main]\n0 = \"count\"\n",
+    )
+    .expect("failed to write");
+    std::fs::write(&globals, "-- from the manual\nprint = echo\n").expect("failed to write");
+    let map = map.to_str().expect("path isn't UTF-8");
+    let globals = globals.to_str().expect("path isn't UTF-8");
+
+    // Both files name into the same map, and the global names win.
+    let output = luad(&[
+        "decompile",
+        "--rename-map",
+        map,
+        "--rename-globals",
+        globals,
+        "tests/fixtures/upvalue.lua4",
+    ]);
+    assert_eq!(
+        stdout(&output),
+        "local count = 1\nf = function()\n    echo(%count)\nend\ng = function()\n    echo(%echo)\nend\n"
+    );
+
+    std::fs::write(globals, "print echo\n").expect("failed to write");
+    let output = luad(&["decompile", "--rename-globals", globals, HELLO]);
+    std::fs::remove_file(map).expect("failed to remove");
+    std::fs::remove_file(globals).expect("failed to remove");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rename map error: line 1"), "{stderr}");
+}
+
+#[test]
+fn test_compact_operators() {
+    let output = luad(&[
+        "decompile",
+        "--compact-operators",
+        "tests/fixtures/addi.lua4",
+    ]);
+    assert!(stdout(&output).ends_with("\nx = a+200\ny = (a+1)*2\n"));
+
+    // The grammar check passes on what's written.
+    let output = luad(&[
+        "decompile",
+        "--compact-operators",
+        "tests/fixtures/minus.lua4",
+    ]);
+    assert_eq!(
+        stdout(&output),
+        "x = a- -b\ny = a- -5\nz = a- -1.5\ns = 1 .. a\n"
+    );
+}
+
+#[test]
+fn test_truncated() {
+    let chunk = std::fs::read("tests/fixtures/callgraph.lua4").expect("failed to read");
+    let output = luad_with_input(&["decompile", "-"], &chunk[..150]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: -: failed to fill whole buffer (constants of function main.0, at byte 147)\n"
+    );
+}
+
+#[test]
+fn test_diff() {
+    let params = "tests/fixtures/params.lua4";
+    let output = luad(&["diff", params, params]);
+    assert_eq!(stdout(&output), "no differences\n");
+
+    // Chunks that differ exit with 3, like failed validation.
+    let output = luad(&["diff", params, "tests/fixtures/patched.lua4"]);
+    assert_eq!(output.status.code(), Some(3));
+    let changes = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        changes.starts_with("changed function main\n  instructions 5..6 inserted\n"),
+        "{changes}"
+    );
+    assert!(changes.contains("\nadded function main.0\n"), "{changes}");
+
+    let output = luad(&["diff", params, "missing.lua4"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = luad(&["diff", "tests/fixtures/lua51/ifelse.lua51", params]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("unsupported: comparing Lua 5.1 chunks"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_strings() {
+    let output = luad(&["strings", "tests/fixtures/callgraph.lua4"]);
+    assert_eq!(
+        stdout(&output),
+        "main\tstring 0\t\"add\"\nmain.0\tstring 0\t\"print\"\n"
+    );
+
+    let output = luad(&["strings", "--numbers", "tests/fixtures/minus.lua4"]);
+    let listing = stdout(&output);
+    assert!(listing.starts_with("main\tstring 0\t\"a\"\n"), "{listing}");
+    assert!(listing.ends_with("\nmain\tnumber 0\t1.5\n"), "{listing}");
+
+    let output = luad(&["strings", "tests/fixtures/lua51/ifelse.lua51"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_callgraph() {
+    let output = luad(&["callgraph", "tests/fixtures/callgraph.lua4"]);
+    let dot = stdout(&output);
+    assert!(dot.starts_with("digraph calls {\n"), "{dot}");
+    assert!(
+        dot.contains("    \"main.1\" -> \"main.0\" [label=\"%say\"];\n"),
+        "{dot}"
+    );
+    assert!(dot.ends_with("}\n"), "{dot}");
+
+    let output = luad(&["callgraph", "--json", "tests/fixtures/callgraph.lua4"]);
+    let json = stdout(&output);
+    assert!(
+        json.contains("{\"caller\": \"main\", \"kind\": \"global\", \"name\": \"add\", \"target\": \"main.1\", \"count\": 1}"),
+        "{json}"
+    );
+
+    let output = luad(&["callgraph", "missing.lua4"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_xrefs() {
+    let output = luad(&["xrefs", "tests/fixtures/callgraph.lua4"]);
+    assert_eq!(
+        stdout(&output),
+        "add\twrite\tmain:4\nadd\tread\tmain:6\nprint\tread\tmain.0:1\n"
+    );
+
+    let output = luad(&[
+        "xrefs",
+        "--global",
+        "print",
+        "tests/fixtures/callgraph.lua4",
+    ]);
+    assert_eq!(stdout(&output), "print\tread\tmain.0:1\n");
+    let output = luad(&[
+        "xrefs",
+        "--global",
+        "missing",
+        "tests/fixtures/callgraph.lua4",
+    ]);
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn test_export() {
+    let output = luad(&[
+        "export",
+        "--what",
+        "locals",
+        "tests/fixtures/callgraph.lua4",
+    ]);
+    assert_eq!(
+        stdout(&output),
+        "function,index,name,first_instruction,last_instruction\nmain,0,say,2,11\nmain.0,0,message,1,4\nmain.1,0,a,1,8\nmain.1,1,b,1,8\n"
+    );
+
+    let path = temp_path("constants.csv");
+    let output = luad(&[
+        "export",
+        "--what",
+        "constants",
+        "-o",
+        path.to_str().expect("path isn't UTF-8"),
+        "tests/fixtures/callgraph.lua4",
+    ]);
+    assert_eq!(stdout(&output), "");
+    let table = std::fs::read_to_string(&path).expect("no output file");
+    std::fs::remove_file(&path).expect("failed to remove");
+    assert_eq!(
+        table,
+        "function,kind,index,value,line\nmain,string,0,add,\nmain.0,string,0,print,\n"
+    );
+
+    // The table to export is required.
+    let output = luad(&["export", "tests/fixtures/callgraph.lua4"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_coverage() {
+    let dir = temp_path("coverage");
+    std::fs::create_dir_all(&dir).expect("failed to create directory");
+    std::fs::copy(HELLO, dir.join("hello.lub")).expect("failed to copy");
+    let dir_arg = dir.to_str().expect("path isn't UTF-8");
+
+    let output = luad(&["coverage", dir_arg]);
+    let report = stdout(&output);
+    assert!(
+        report.starts_with("opcode          instructions  chunks  supported\n"),
+        "{report}"
+    );
+    assert!(
+        report.contains("\nCALL                       1       1  yes\n"),
+        "{report}"
+    );
+    assert!(
+        report.ends_with("\n1 of 1 chunks only use supported opcodes\n"),
+        "{report}"
+    );
+
+    let output = luad(&["coverage", "--json", dir_arg]);
+    assert!(stdout(&output).starts_with("{\"chunks\": 1, \"supported_chunks\": 1, "));
+
+    // Chunks that fail to decode are counted, and fail the command.
+    std::fs::copy("tests/fixtures/unknown.lua4", dir.join("unknown.out")).expect("failed to copy");
+    let output = luad(&["coverage", dir_arg]);
+    std::fs::remove_dir_all(&dir).expect("failed to remove");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.ends_with("failed to decode 1 of 2 files\n"),
+        "{stderr}"
+    );
+
+    let output = luad(&["coverage", HELLO]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_color() {
+    let output = luad(&["decompile", "--color", "always", HELLO]);
+    assert_eq!(
+        stdout(&output),
+        "\x1b[35mlocal\x1b[0m x = \x1b[36m7\x1b[0m\nprint(\x1b[32m\"hello\"\x1b[0m, x)\n"
+    );
+
+    // Only a terminal is colored by default, and files never are.
+    let output = luad(&["decompile", HELLO]);
+    assert_eq!(stdout(&output), HELLO_SOURCE);
+    let path = temp_path("color.lua");
+    let path_arg = path.to_str().expect("path isn't UTF-8");
+    let output = luad(&["decompile", "--color", "always", "-o", path_arg, HELLO]);
+    assert_eq!(stdout(&output), "");
+    let source = std::fs::read_to_string(&path).expect("no output file");
+    std::fs::remove_file(&path).expect("failed to remove");
+    assert_eq!(source, HELLO_SOURCE);
+
+    let output = luad(&["decompile", "--color", "sometimes", HELLO]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_html() {
+    let output = luad(&["decompile", "--html", HELLO]);
+    let page = stdout(&output);
+    assert!(page.starts_with("<!DOCTYPE html>\n"), "{page}");
+    assert!(page.ends_with("</html>\n"), "{page}");
+    // Statements link to the instructions they were decoded from, and back.
+    assert!(
+        page.contains("<a href=\"#i-main-2\" data-end=\"5\" title=\"main instructions 2-5\">2</a></td><td><pre>print(&quot;hello&quot;, x)</pre>"),
+        "{page}"
+    );
+    assert!(
+        page.contains("<tr id=\"i-main-5\"><td class=\"ln\"><a href=\"#L2\">2</a></td><td><pre>5\t[-]\tCALL\t1 0</pre>"),
+        "{page}"
+    );
+
+    let output = luad(&["decompile", "--html", "tests/fixtures/lua51/ifelse.lua51"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("unsupported: HTML output of Lua 5.1 chunks"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_ast() {
+    let output = luad(&["decompile", "--ast", HELLO]);
+    assert_eq!(
+        stdout(&output),
+        "Block\n  Local x  [1..1]\n    Int 7\n  Call  [2..5]\n    Name print\n    Str \"hello\"\n    Name x\n"
+    );
+}
+
+#[test]
+fn test_warnings() {
+    let output = luad(&["decompile", "--warnings", "tests/fixtures/params.lua4"]);
+    assert!(stdout(&output).starts_with("add = function(x, y)\n"));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "tests/fixtures/params.lua4: note: function 1: made up the name `a` for a parameter\n\
+         tests/fixtures/params.lua4: 0 warnings, 1 note\n"
+    );
+
+    // Without the option, only the source is written.
+    let output = luad(&["decompile", "tests/fixtures/params.lua4"]);
+    stdout(&output);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_info_hex() {
+    let output = luad(&["info", "--hex", HELLO]);
+    let fields = stdout(&output);
+    assert!(
+        fields.starts_with("00000000  1b                        bytemark          ESC\n"),
+        "{fields}"
+    );
+    assert!(
+        fields.ends_with(
+            "0000000d  12 e6 5b a1 b0 b9 b2 41   test number       314159265.35897934 as F64\n"
+        ),
+        "{fields}"
+    );
+
+    // The first malformed field is marked.
+    let chunk = std::fs::read(HELLO).expect("failed to read");
+    let path = temp_path("header.lua4");
+    std::fs::write(&path, &chunk[..12]).expect("failed to write");
+    let output = luad(&["info", "--hex", path.to_str().expect("path isn't UTF-8")]);
+    std::fs::remove_file(&path).expect("failed to remove");
+    assert_eq!(output.status.code(), Some(1));
+    let fields = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        fields.ends_with("0000000c                            number size       !! failed to fill whole buffer\n"),
+        "{fields}"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.ends_with(": malformed number size\n"), "{stderr}");
+
+    let output = luad(&["info", "--hex", "--json", HELLO]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_decompile_function() {
+    let callgraph = "tests/fixtures/callgraph.lua4";
+    let output = luad(&["decompile", "--function", "main.1", callgraph]);
+    assert_eq!(
+        stdout(&output),
+        "local function_1 = function(a, b)\n    %say(a)\n    return a + b\nend\n"
+    );
+
+    let output = luad(&["decompile", "--function", "main.7", callgraph]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.ends_with("parser error: no function main.7\n"),
+        "{stderr}"
+    );
+
+    let output = luad(&[
+        "decompile",
+        "--function",
+        "main.0",
+        "tests/fixtures/lua51/ifelse.lua51",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+#[test]
+fn test_mmap() {
+    let output = luad(&["decompile", "--mmap", HELLO]);
+    assert_eq!(stdout(&output), HELLO_SOURCE);
+    let output = luad(&["scan", "--mmap", "tests/fixtures/callgraph.lua4"]);
+    assert_eq!(stdout(&output), "0x0..0x15c\tLua 4.0\t348 bytes\n");
+
+    // Stdin can't be mapped, so it's read.
+    let chunk = std::fs::read(HELLO).expect("failed to read");
+    let output = luad_with_input(&["decompile", "--mmap", "-"], &chunk);
+    assert_eq!(stdout(&output), HELLO_SOURCE);
+
+    let output = luad(&["decompile", "--mmap", "missing.lua4"]);
+    assert_eq!(output.status.code(), Some(1));
+}
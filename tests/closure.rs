@@ -0,0 +1,65 @@
+//! Nested functions and the upvalues they capture.
+//!
+//! The upvalue fixture is compiled from:
+//!
+//! ```lua
+//! local a = 1
+//! f = function() print(%a) end
+//! g = function() print(%print) end
+//! ```
+//!
+//! The vararg fixture is compiled from:
+//!
+//! ```lua
+//! f = function(...) print(arg) end
+//! g = function(...) return call(print, arg) end
+//! ```
+//!
+//! The params fixture is compiled from the following, with the debug
+//! information of the second function stripped:
+//!
+//! ```lua
+//! add = function(x, y) return x + y end
+//! h = function(a, ...) print(a, arg) end
+//! ```
+mod common;
+
+use common::decompile;
+
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+const VARARG: &[u8] = include_bytes!("fixtures/vararg.lua4");
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+
+#[test]
+fn test_upvalue() {
+    assert_eq!(
+        decompile(UPVALUE),
+        "local a = 1\n\
+         f = function()\n    print(%a)\n\
+         end\n\
+         g = function()\n    print(%print)\n\
+         end\n"
+    );
+}
+
+#[test]
+fn test_vararg() {
+    assert_eq!(
+        decompile(VARARG),
+        "f = function(...)\n    print(arg)\n\
+         end\n\
+         g = function(...)\n    return call(print, arg)\n\
+         end\n"
+    );
+}
+
+#[test]
+fn test_params() {
+    assert_eq!(
+        decompile(PARAMS),
+        "add = function(x, y)\n    return x + y\n\
+         end\n\
+         h = function(a, ...)\n    print(a, arg)\n\
+         end\n"
+    );
+}
@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests.
+//!
+//! Each test file is compiled as its own crate, and
+//! most use only some of the helpers.
+#![allow(dead_code)]
+use lua_decompiler::lua40::{self, Decoder, Parser, Proto, Syntax};
+
+/// Decode a Lua 4.0 chunk.
+pub fn decode(code: &[u8]) -> Proto {
+    Decoder::new(code).decode().expect("failed to decode")
+}
+
+/// Decode a Lua 4.0 chunk without its debug information, like `luac -s`.
+pub fn stripped(code: &[u8]) -> Proto {
+    let mut proto = decode(code);
+    proto.strip();
+    proto
+}
+
+/// Decode and parse a Lua 4.0 chunk with the default options.
+pub fn parse(code: &[u8]) -> Syntax {
+    Parser::new(&decode(code)).parse().expect("failed to parse")
+}
+
+/// Write a syntax tree in the default style.
+pub fn write(syntax: &Syntax) -> String {
+    let mut buf = String::new();
+    lua40::Scribe::default()
+        .fmt_syntax(&mut buf, syntax)
+        .expect("scribe failed");
+    buf
+}
+
+/// Decompile a Lua 4.0 chunk with the default options.
+pub fn decompile(code: &[u8]) -> String {
+    lua40::decompile(code).expect("failed to decompile")
+}
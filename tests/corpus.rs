@@ -0,0 +1,203 @@
+//! Decompiling a corpus of real compiled scripts, for a coverage metric.
+//!
+//! Opt in by pointing `LUAD_CORPUS` at a directory of Lua 4.0 chunks, like
+//! the scripts of a game, then run `cargo test --test corpus`. Every file
+//! under it that starts with the chunk signature is decoded, parsed, written
+//! and checked against the grammar. The share of chunks that make it through,
+//! the stage the others failed at, and the opcodes they failed at most are
+//! reported.
+//!
+//! Set `LUAD_CORPUS_MIN_PASS` to a percentage to fail the run when fewer
+//! chunks decompile, so a corpus can guard against regressions.
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use lua_decompiler::errors::Error;
+use lua_decompiler::lua40::{self, check_syntax, Decoder, Parser};
+
+const CORPUS_VAR: &str = "LUAD_CORPUS";
+const MIN_PASS_VAR: &str = "LUAD_CORPUS_MIN_PASS";
+
+/// Number of failing opcodes to report.
+const TOP_OPCODES: usize = 10;
+
+/// Stage of decompiling a chunk that it failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Stage {
+    Decode,
+    Parse,
+    Write,
+    Check,
+    /// The decompiler panicked, which is always a bug.
+    Panic,
+}
+
+struct Failure {
+    stage: Stage,
+    opcode: Option<&'static str>,
+    message: String,
+}
+
+#[derive(Default)]
+struct Report {
+    passed: usize,
+    failures: Vec<(PathBuf, Failure)>,
+}
+
+fn main() -> ExitCode {
+    let Some(dir) = std::env::var_os(CORPUS_VAR) else {
+        println!("corpus: skipped, set {CORPUS_VAR} to a directory of Lua 4.0 chunks");
+        return ExitCode::SUCCESS;
+    };
+
+    let mut paths = vec![];
+    if let Err(err) = walk(Path::new(&dir), &mut paths) {
+        eprintln!(
+            "corpus: failed to read {}: {err}",
+            Path::new(&dir).display()
+        );
+        return ExitCode::FAILURE;
+    }
+    paths.sort();
+
+    let mut report = Report::default();
+    for path in &paths {
+        match decompile_file(path) {
+            Ok(()) => report.passed += 1,
+            Err(failure) => report.failures.push((path.clone(), failure)),
+        }
+    }
+    report.print();
+
+    let min_pass = std::env::var(MIN_PASS_VAR)
+        .ok()
+        .and_then(|value| value.parse::<f64>().ok());
+    match min_pass {
+        Some(min_pass) if report.pass_rate() < min_pass => {
+            eprintln!(
+                "corpus: {:.1}% decompiled, below the minimum of {min_pass}%",
+                report.pass_rate()
+            );
+            ExitCode::FAILURE
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+/// Collect the files under the directory that start with the chunk signature.
+fn walk(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, paths)?;
+        } else if is_chunk(&path) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_chunk(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut signature = [0; 4];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| &signature == b"\x1bLua")
+}
+
+fn decompile_file(path: &Path) -> Result<(), Failure> {
+    let code = fs::read(path).map_err(|err| Failure {
+        stage: Stage::Decode,
+        opcode: None,
+        message: err.to_string(),
+    })?;
+    panic::catch_unwind(AssertUnwindSafe(|| decompile(&code))).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(Failure {
+            stage: Stage::Panic,
+            opcode: None,
+            message,
+        })
+    })
+}
+
+fn decompile(code: &[u8]) -> Result<(), Failure> {
+    let fail = |stage| {
+        move |err: Error| Failure {
+            stage,
+            opcode: err.location().and_then(|location| location.opcode),
+            message: err.to_string(),
+        }
+    };
+    let proto = Decoder::new(code).decode().map_err(fail(Stage::Decode))?;
+    let syntax = Parser::new(&proto).parse().map_err(fail(Stage::Parse))?;
+    let mut source = String::new();
+    lua40::Scribe::default()
+        .fmt_syntax(&mut source, &syntax)
+        .map_err(fail(Stage::Write))?;
+    check_syntax(&source).map_err(fail(Stage::Check))
+}
+
+impl Report {
+    fn total(&self) -> usize {
+        self.passed + self.failures.len()
+    }
+
+    fn pass_rate(&self) -> f64 {
+        match self.total() {
+            0 => 100.0,
+            total => self.passed as f64 * 100.0 / total as f64,
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "corpus: decompiled {} of {} chunks ({:.1}%)",
+            self.passed,
+            self.total(),
+            self.pass_rate()
+        );
+        if self.failures.is_empty() {
+            return;
+        }
+
+        let mut stages: HashMap<Stage, usize> = HashMap::new();
+        let mut opcodes: HashMap<&str, usize> = HashMap::new();
+        for (_, failure) in &self.failures {
+            *stages.entry(failure.stage).or_default() += 1;
+            if let Some(opcode) = failure.opcode {
+                *opcodes.entry(opcode).or_default() += 1;
+            }
+        }
+
+        let mut stages: Vec<_> = stages.into_iter().collect();
+        stages.sort();
+        println!("\nfailed at:");
+        for (stage, count) in stages {
+            println!("  {:<8} {count}", format!("{stage:?}").to_lowercase());
+        }
+
+        // Most failures first, then by name so the report is stable.
+        let mut opcodes: Vec<_> = opcodes.into_iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        if !opcodes.is_empty() {
+            println!("\ntop failing opcodes:");
+            for (opcode, count) in opcodes.iter().take(TOP_OPCODES) {
+                println!("  {opcode:<12} {count}");
+            }
+        }
+
+        println!("\nfailures:");
+        for (path, failure) in &self.failures {
+            println!("  {}: {}", path.display(), failure.message);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Decompiling through the convenience functions.
+use lua_decompiler::diagnostics::Severity;
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::options::{DecompileOptions, Tolerance};
+use lua_decompiler::{decompile, decompile_bytes, decompile_with, lua40};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const UNKNOWN: &[u8] = include_bytes!("fixtures/unknown.lua4");
+
+#[test]
+fn test_decompile() {
+    let expected = "local x = 7\nprint(\"hello\", x)\n";
+    assert_eq!(
+        lua40::decompile(HELLO).expect("failed to decompile"),
+        expected
+    );
+    assert_eq!(decompile(HELLO).expect("failed to decompile"), expected);
+    assert_eq!(
+        decompile_bytes(HELLO).expect("failed to decompile"),
+        expected
+    );
+}
+
+#[test]
+fn test_decompile_timed() {
+    let (source, timings) = lua40::decompile_timed(HELLO).expect("failed to decompile");
+    assert_eq!(source, "local x = 7\nprint(\"hello\", x)\n");
+    assert_eq!(timings.instructions, 6);
+    assert_eq!(
+        timings.total(),
+        timings.decode + timings.parse + timings.write
+    );
+}
+
+#[test]
+fn test_decompile_unsupported_version() {
+    let err = decompile(b"\x1bLua\x50").expect_err("Lua 5.0 can't be decompiled");
+    assert!(matches!(err.kind(), ErrorKind::Unsupported(_)));
+}
+
+#[test]
+fn test_decompiled_outlives_parser() {
+    let decompiled: Vec<_> = [HELLO, HELLO]
+        .iter()
+        .map(|code| {
+            let proto = lua40::Decoder::new(code)
+                .decode()
+                .expect("failed to decode");
+            lua40::Decompiled::parse(proto).expect("failed to parse")
+        })
+        .collect();
+
+    for decompiled in &decompiled {
+        assert_eq!(decompiled.proto().code().len(), 6);
+        let mut buf = String::new();
+        lua40::Scribe::default()
+            .fmt_syntax(&mut buf, decompiled.syntax())
+            .expect("scribe failed");
+        assert_eq!(buf, "local x = 7\nprint(\"hello\", x)\n");
+    }
+}
+
+#[test]
+fn test_decompile_tolerance() {
+    let err = decompile_with(UNKNOWN, &DecompileOptions::new()).expect_err("unknown opcode");
+    assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+
+    let lenient = DecompileOptions::new().with_tolerance(Tolerance::Lenient);
+    let output = decompile_with(UNKNOWN, &lenient).expect("failed to decompile");
+    assert!(output.source.contains("UNKNOWN"), "{}", output.source);
+    assert!(output.source.ends_with("f()\n"), "{}", output.source);
+    assert_eq!(output.diagnostics.count(Severity::Warning), 2);
+
+    let best_effort = DecompileOptions::new().with_tolerance(Tolerance::BestEffort);
+    let output = decompile_with(UNKNOWN, &best_effort).expect("failed to decompile");
+    assert_eq!(
+        output.source,
+        "x = 1\n-- skipped unknown instruction 3\nf()\n"
+    );
+    assert_eq!(output.diagnostics.count(Severity::Warning), 2);
+}
@@ -0,0 +1,68 @@
+//! Problems reported alongside the decompiled output.
+use lua_decompiler::diagnostics::{Diagnostic, Severity};
+use lua_decompiler::lua40;
+use lua_decompiler::options::{DecompileOptions, Tolerance};
+
+const DOBLOCK: &[u8] = include_bytes!("fixtures/doblock.lua4");
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+const UNKNOWN: &[u8] = include_bytes!("fixtures/unknown.lua4");
+
+#[test]
+fn test_diagnostics_of_nested_functions() {
+    let output =
+        lua40::decompile_with(PARAMS, &DecompileOptions::new()).expect("failed to decompile");
+    let diagnostics: Vec<_> = output.diagnostics.iter().collect();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Note);
+    assert_eq!(diagnostics[0].path, [1]);
+    assert_eq!(diagnostics[0].offset, None);
+}
+
+#[test]
+fn test_debug_names_are_kept() {
+    let output =
+        lua40::decompile_with(DOBLOCK, &DecompileOptions::new()).expect("failed to decompile");
+    assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+    assert!(output
+        .source
+        .starts_with("local a = 1\ndo\n    local b = 2\n"));
+
+    // Only the names of stripped chunks are made up.
+    let mut proto = lua40::Decoder::new(DOBLOCK)
+        .decode()
+        .expect("failed to decode");
+    proto.strip();
+    let mut parser = lua40::Parser::new(&proto);
+    parser.parse().expect("failed to parse");
+    let notes: Vec<_> = parser.diagnostics().iter().collect();
+    assert_eq!(notes.len(), 3);
+    assert_eq!(
+        notes[0].message,
+        "made up the name `a` for a local variable"
+    );
+}
+
+#[test]
+fn test_diagnostics_of_unknown_opcode() {
+    let options = DecompileOptions::new().with_tolerance(Tolerance::BestEffort);
+    let output = lua40::decompile_with(UNKNOWN, &options).expect("failed to decompile");
+    let diagnostics: Vec<_> = output.diagnostics.iter().collect();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(
+        *diagnostics[1],
+        Diagnostic {
+            severity: Severity::Warning,
+            path: vec![],
+            offset: Some(2),
+            message: "skipped unknown instruction".to_string(),
+        }
+    );
+    assert_eq!(
+        diagnostics[1].to_string(),
+        "warning: main function, instruction 3: skipped unknown instruction"
+    );
+    assert_eq!(
+        output.diagnostics.summary().to_string(),
+        "2 warnings, 0 notes"
+    );
+}
@@ -0,0 +1,61 @@
+//! Comparing the functions of two chunks.
+mod common;
+
+use common::decode;
+use lua_decompiler::lua40::{self, FunctionChange};
+
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+/// `params.lua4` with a function inserted before the others,
+/// and a global added to the last one.
+const PATCHED: &[u8] = include_bytes!("fixtures/patched.lua4");
+
+#[test]
+fn test_diff_same() {
+    let mut stripped = decode(PARAMS);
+    stripped.strip();
+
+    let diff = lua40::diff(&decode(PARAMS), &stripped);
+    assert!(diff.is_empty(), "debug information isn't compared");
+    assert_eq!(diff.to_string(), "no differences\n");
+}
+
+#[test]
+fn test_diff_inserted_function() {
+    let diff = lua40::diff(&decode(PARAMS), &decode(PATCHED));
+    assert_eq!(
+        diff.changes,
+        vec![
+            FunctionChange::Changed {
+                old_path: vec![],
+                new_path: vec![],
+                differences: vec![
+                    "instructions 5..6 inserted".to_string(),
+                    "string constants added: \"g\"".to_string(),
+                ],
+            },
+            FunctionChange::Added { path: vec![0] },
+            FunctionChange::Changed {
+                old_path: vec![1],
+                new_path: vec![2],
+                differences: vec![
+                    "instruction 3 replaced with instruction 3".to_string(),
+                    "string constants added: \"x\"".to_string(),
+                ],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_diff_removed_function() {
+    let diff = lua40::diff(&decode(PATCHED), &decode(PARAMS));
+    assert!(diff
+        .changes
+        .contains(&FunctionChange::Removed { path: vec![0] }));
+    assert_eq!(
+        diff.to_string(),
+        "changed function main\n  instructions 5..6 removed\n  string constants removed: \"g\"\n\
+         removed function main.0\n\
+         changed function main.1 (was main.2)\n  instruction 3 replaced with instruction 3\n  string constants removed: \"x\"\n"
+    );
+}
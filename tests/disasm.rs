@@ -0,0 +1,58 @@
+//! Instruction listings of decoded chunks.
+use lua_decompiler::{decode_any, Disassembler};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const LINES: &[u8] = include_bytes!("fixtures/lines.lua4");
+const UNARY: &[u8] = include_bytes!("fixtures/unary.lua4");
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+const CLOSURE_LUA51: &[u8] = include_bytes!("fixtures/lua51/closure.lua51");
+
+fn disasm(code: &[u8]) -> String {
+    let proto = decode_any(code).expect("failed to decode");
+    Disassembler::new(&proto).to_string()
+}
+
+#[test]
+fn test_listing() {
+    assert_eq!(
+        disasm(HELLO),
+        "function <@test.lua:0> (6 instructions)\n\
+         0 params, 10 stack, 1 locals, 2 strings, 0 numbers, 0 functions\n\
+         \t1\t[-]\tPUSHINT\t7\n\
+         \t2\t[-]\tGETGLOBAL\t0\t; \"print\"\n\
+         \t3\t[-]\tPUSHSTRING\t1\t; \"hello\"\n\
+         \t4\t[-]\tGETLOCAL\t0\t; x\n\
+         \t5\t[-]\tCALL\t1 0\n\
+         \t6\t[-]\tEND\n\n"
+    );
+}
+
+#[test]
+fn test_line_numbers() {
+    let listing = disasm(LINES);
+    assert!(listing.contains("\t1\t[1]\tPUSHINT\t1\n"));
+    assert!(listing.contains("\t7\t[10]\tPUSHINT\t4\n"));
+}
+
+#[test]
+fn test_constants() {
+    assert!(disasm(UNARY).contains("\tPUSHNUM\t1\t; 2.5\n"));
+}
+
+#[test]
+fn test_nested_functions() {
+    let listing = disasm(UPVALUE);
+    assert_eq!(listing.matches("function <@test.lua:").count(), 3);
+    assert!(listing.contains("0 locals, 3 strings, 0 numbers, 2 functions\n"));
+    assert!(listing.contains("\nfunction <@test.lua:3> (4 instructions)\n"));
+    assert!(listing.contains("\tPUSHUPVALUE\t0\n"));
+}
+
+#[test]
+fn test_lua51_listing() {
+    let listing = disasm(CLOSURE_LUA51);
+    assert!(listing.starts_with("function <@closure.lua:0,0> (9 instructions)\n"));
+    assert!(listing.contains("\t6\t[6]\tGETGLOBAL\t2 1\t; \"print\"\n"));
+    assert!(listing.contains("\nfunction <@closure.lua:2,5> (6 instructions)\n"));
+    assert!(listing.contains("\tGETUPVAL \t0 0 0\t; n\n"));
+}
@@ -0,0 +1,31 @@
+//! Encoding decoded chunks back into binary chunks.
+use lua_decompiler::lua40::{Decoder, Encoder, Endian, Header};
+
+const HELLO_BE: &[u8] = include_bytes!("fixtures/hello_be.lua4");
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+fn round_trip(code: &[u8]) -> Vec<u8> {
+    let chunk = Decoder::new(code).decode_chunk().expect("failed to decode");
+    chunk.encode().expect("failed to encode")
+}
+
+#[test]
+fn test_round_trip() {
+    assert_eq!(round_trip(HELLO_LE), HELLO_LE);
+    assert_eq!(round_trip(HELLO_BE), HELLO_BE);
+}
+
+#[test]
+fn test_encode_other_byte_order() {
+    let chunk = Decoder::new(HELLO_LE)
+        .decode_chunk()
+        .expect("failed to decode");
+    let header = Header {
+        endianess: Endian::Big,
+        ..chunk.header().clone()
+    };
+    let code = Encoder::new(header)
+        .encode(chunk.main())
+        .expect("failed to encode");
+    assert_eq!(code, HELLO_BE);
+}
@@ -0,0 +1,247 @@
+//! Malformed chunks are reported as errors, rather than panicking.
+use lua_decompiler::errors::{Error, ErrorKind, Location};
+use lua_decompiler::lua40::{Decoder, Limits, Parser};
+use lua_decompiler::{lua50, lua51};
+
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const MULTRET: &[u8] = include_bytes!("fixtures/multret.lua4");
+const TEMP: &[u8] = include_bytes!("fixtures/temp.lua4");
+const HELLO_LUA50: &[u8] = include_bytes!("fixtures/lua50/hello.lua50");
+const CLOSURE_LUA51: &[u8] = include_bytes!("fixtures/lua51/closure.lua51");
+
+/// Offset of the first instruction word in `hello_le.lua4`.
+const CODE_OFFSET: usize = HELLO_LE.len() - 6 * 4;
+
+/// Offset of the number of parameters in `hello_le.lua4`, after the header,
+/// the source name and the line defined. The stack size follows the vararg flag.
+const PARAMS_OFFSET: usize = 21 + 4 + "@test.lua\0".len() + 4;
+const MAX_STACK_OFFSET: usize = PARAMS_OFFSET + 4 + 1;
+
+/// Offset of the `PUSHINT` and `GETLOCAL` words in `temp.lua4`,
+/// which reads back the pushed integer for `f(1, 1)`.
+const TEMP_PUSH_OFFSET: usize = TEMP.len() - 4 * 4;
+const TEMP_READ_OFFSET: usize = TEMP.len() - 3 * 4;
+
+#[test]
+fn test_truncated_chunk() {
+    for len in 0..HELLO_LE.len() {
+        assert!(Decoder::new(&HELLO_LE[..len]).decode().is_err());
+    }
+}
+
+#[test]
+fn test_unknown_opcode_context() {
+    let mut code = HELLO_LE.to_vec();
+    // Opcodes are the low 6 bits, and the last one is 48.
+    code[CODE_OFFSET + 4] |= 0x3f;
+
+    let err = Decoder::new(&code)
+        .decode()
+        .expect_err("decoded unknown opcode");
+    assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+    assert_eq!(err.context(), Some("function @test.lua:0, instruction 2"));
+}
+
+#[test]
+fn test_limits() {
+    let limits = Limits {
+        max_code: 5,
+        ..Limits::default()
+    };
+    assert!(Decoder::new(HELLO_LE).with_limits(limits).decode().is_err());
+
+    let limits = Limits {
+        max_depth: 0,
+        ..Limits::default()
+    };
+    assert!(Decoder::new(HELLO_LE).with_limits(limits).decode().is_err());
+
+    // The decoders of every version share their limits.
+    let decode_lua51 = lua51::Decoder::new(CLOSURE_LUA51)
+        .with_limits(limits)
+        .decode();
+    assert!(decode_lua51.is_err());
+    assert_eq!(lua50::Limits::default(), Limits::default());
+}
+
+#[test]
+fn test_limits_lua50() {
+    let decode = |limits| {
+        lua50::Decoder::new(HELLO_LUA50)
+            .with_limits(limits)
+            .decode()
+    };
+    assert!(decode(lua50::Limits::default()).is_ok());
+
+    // The main function has 5 instructions, 2 constants and a nested function.
+    for limits in [
+        lua50::Limits {
+            max_code: 4,
+            ..lua50::Limits::default()
+        },
+        lua50::Limits {
+            max_constants: 1,
+            ..lua50::Limits::default()
+        },
+        lua50::Limits {
+            max_depth: 1,
+            ..lua50::Limits::default()
+        },
+    ] {
+        let err = decode(limits).expect_err("decoded beyond the limits");
+        assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+    }
+}
+
+#[test]
+fn test_limits_lua51() {
+    let decode = |limits| {
+        lua51::Decoder::new(CLOSURE_LUA51)
+            .with_limits(limits)
+            .decode()
+    };
+    assert!(decode(lua51::Limits::default()).is_ok());
+
+    // The main function has 9 instructions, 2 constants and a nested function.
+    for limits in [
+        lua51::Limits {
+            max_code: 8,
+            ..lua51::Limits::default()
+        },
+        lua51::Limits {
+            max_constants: 1,
+            ..lua51::Limits::default()
+        },
+        lua51::Limits {
+            max_depth: 1,
+            ..lua51::Limits::default()
+        },
+    ] {
+        let err = decode(limits).expect_err("decoded beyond the limits");
+        assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+    }
+}
+
+#[test]
+fn test_too_many_params() {
+    let with_params = |num_params: u32, max_stack: u32| {
+        let mut code = HELLO_LE.to_vec();
+        code[PARAMS_OFFSET..PARAMS_OFFSET + 4].copy_from_slice(&num_params.to_le_bytes());
+        code[MAX_STACK_OFFSET..MAX_STACK_OFFSET + 4].copy_from_slice(&max_stack.to_le_bytes());
+        Decoder::new(&code).decode()
+    };
+    assert!(with_params(10, 10).is_ok());
+
+    // More than the stack holds, and more than Lua allows.
+    for (num_params, max_stack) in [(11, 10), (101, 200), (u32::MAX, u32::MAX)] {
+        let err = with_params(num_params, max_stack).expect_err("decoded too many parameters");
+        assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+    }
+}
+
+#[test]
+fn test_oversized_count() {
+    // Claim 2^32 - 1 instructions.
+    let mut code = HELLO_LE.to_vec();
+    code[CODE_OFFSET - 4..CODE_OFFSET].copy_from_slice(&[0xff; 4]);
+    assert!(Decoder::new(&code).decode().is_err());
+}
+
+#[test]
+fn test_open_results_as_single_value() {
+    let mut code = MULTRET.to_vec();
+    // `x = f(a, g())` with all the results of `f` assigned, which no compiler emits.
+    let offset = code.len() - (29 - 10) * 4;
+    code[offset..offset + 4].copy_from_slice(&(2u32 | (255 << 6)).to_le_bytes());
+
+    let proto = Decoder::new(&code).decode().expect("failed to decode");
+    let err = Parser::new(&proto)
+        .parse()
+        .expect_err("assigned open results");
+    assert!(matches!(err.kind(), ErrorKind::Parser(_)));
+    assert_eq!(
+        err.context(),
+        Some("function @test.lua:0, instruction 12 (SETGLOBAL)")
+    );
+}
+
+#[test]
+fn test_location() {
+    let mut code = HELLO_LE.to_vec();
+    code[CODE_OFFSET + 4] |= 0x3f;
+    let err = Decoder::new(&code)
+        .decode()
+        .expect_err("decoded unknown opcode");
+    let location = Location {
+        path: vec![],
+        offset: Some(1),
+        opcode: None,
+    };
+    assert_eq!(err.location(), Some(&location));
+
+    let mut code = MULTRET.to_vec();
+    let offset = code.len() - (29 - 10) * 4;
+    code[offset..offset + 4].copy_from_slice(&(2u32 | (255 << 6)).to_le_bytes());
+    let proto = Decoder::new(&code).decode().expect("failed to decode");
+    let err = Parser::new(&proto)
+        .parse()
+        .expect_err("assigned open results");
+    let location = Location {
+        path: vec![],
+        offset: Some(11),
+        opcode: Some("SETGLOBAL"),
+    };
+    assert_eq!(err.location(), Some(&location));
+    assert_eq!(
+        err.kind().to_string(),
+        "parser error: all results of a call used as a single value"
+    );
+}
+
+/// Parse the chunk with and without its debug information, which must fail alike.
+fn parse_temp(code: &[u8]) -> Vec<Error> {
+    let mut proto = Decoder::new(code).decode().expect("failed to decode");
+    let err = Parser::new(&proto).parse().expect_err("parsed temporary");
+    proto.strip();
+    let stripped = Parser::new(&proto).parse().expect_err("parsed temporary");
+    vec![err, stripped]
+}
+
+#[test]
+fn test_repeat_temporary_with_effects() {
+    // `GETGLOBAL f` instead of `PUSHINT 1`, which may read
+    // a different value when evaluated again.
+    let mut code = TEMP.to_vec();
+    code[TEMP_PUSH_OFFSET..TEMP_PUSH_OFFSET + 4].copy_from_slice(&12u32.to_le_bytes());
+
+    for err in parse_temp(&code) {
+        assert_eq!(
+            err.kind().to_string(),
+            "parser error: temporary value at stack offset 1 is read again, \
+             but the expression that pushed it can't be repeated"
+        );
+        assert_eq!(
+            err.context(),
+            Some("function @test.lua:0, instruction 3 (GETLOCAL)")
+        );
+    }
+}
+
+#[test]
+fn test_assign_temporary() {
+    // `SETLOCAL 0` instead of `GETLOCAL 1`, overwriting
+    // the function about to be called.
+    let mut code = TEMP.to_vec();
+    code[TEMP_READ_OFFSET..TEMP_READ_OFFSET + 4].copy_from_slice(&18u32.to_le_bytes());
+
+    for err in parse_temp(&code) {
+        assert_eq!(
+            err.kind().to_string(),
+            "parser error: stack offset 0 is not a live local variable"
+        );
+        assert_eq!(
+            err.context(),
+            Some("function @test.lua:0, instruction 3 (SETLOCAL)")
+        );
+    }
+}
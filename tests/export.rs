@@ -0,0 +1,82 @@
+//! Exporting tables of constants, locals and lines.
+mod common;
+
+use common::decode;
+use lua_decompiler::lua40::{Constant, Export};
+
+const LINES: &[u8] = include_bytes!("fixtures/lines.lua4");
+
+/// Offset of the main function's count of locals in the fixture.
+const LOCALS_OFFSET: usize = 0x30;
+
+/// The fixture with a local named `name`, live over the first four instructions.
+fn with_local(name: &str) -> Vec<u8> {
+    let mut code = LINES.to_vec();
+    code[LOCALS_OFFSET..LOCALS_OFFSET + 4].copy_from_slice(&1u32.to_le_bytes());
+    let mut local = Vec::new();
+    local.extend((name.len() as u32 + 1).to_le_bytes());
+    local.extend(name.as_bytes());
+    local.push(0);
+    local.extend(0u32.to_le_bytes());
+    local.extend(4u32.to_le_bytes());
+    let end = LOCALS_OFFSET + 4;
+    code.splice(end..end, local);
+    code
+}
+
+#[test]
+fn test_constant_lines() {
+    let proto = decode(LINES);
+    let lines: Vec<_> = proto
+        .constants()
+        .map(|pooled| match pooled.constant {
+            Constant::String(string) => (string.to_string(), pooled.line),
+            constant => panic!("unexpected constant {constant:?}"),
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            ("a".to_string(), Some(1)),
+            ("b".to_string(), Some(2)),
+            ("c".to_string(), Some(4)),
+            ("d".to_string(), Some(10)),
+        ]
+    );
+}
+
+#[test]
+fn test_iter_lines() {
+    let proto = decode(LINES);
+    let lines: Vec<_> = proto.iter_lines().map(|info| info.line).collect();
+    assert_eq!(lines, [1, 1, 2, 2, 4, 4, 10, 10, 10]);
+    assert_eq!(proto.iter_lines().count(), proto.instrs().len());
+}
+
+#[test]
+fn test_iter_locals() {
+    let proto = decode(&with_local("x"));
+    let locals: Vec<_> = proto.iter_locals().collect();
+    assert_eq!(locals.len(), 1);
+    assert_eq!(locals[0].path.to_string(), "main");
+    assert_eq!(locals[0].local.varname, "x");
+    assert!(decode(LINES).iter_locals().next().is_none());
+}
+
+#[test]
+fn test_csv() {
+    let proto = decode(LINES);
+    let constants = proto.export_csv(Export::Constants).to_string();
+    assert!(constants.starts_with("function,kind,index,value,line\nmain,string,0,a,1\n"));
+
+    let lines = proto.export_csv(Export::Lines).to_string();
+    assert!(lines.starts_with("function,instruction,opcode,line\nmain,1,PUSHINT,1\n"));
+    assert_eq!(lines.lines().count(), proto.instrs().len() + 1);
+
+    // Fields with separators or quotes are quoted.
+    let proto = decode(&with_local("a,\"b\""));
+    assert_eq!(
+        proto.export_csv(Export::Locals).to_string(),
+        "function,index,name,first_instruction,last_instruction\nmain,0,\"a,\"\"b\"\"\",1,4\n"
+    );
+}
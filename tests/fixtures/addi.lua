@@ -0,0 +1,5 @@
+local a = 0
+a = a + 1
+a = a - 1
+x = a + 200
+y = (a + 1) * 2
@@ -0,0 +1,2 @@
+s = "\255\000\"q\\\n\001"
+print("café")
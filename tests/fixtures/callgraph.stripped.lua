@@ -0,0 +1,8 @@
+local b = function(a)
+    print(a)
+end
+add = function(c, d)
+    %b(c)
+    return c + d
+end
+b(add(1, 2))
@@ -0,0 +1,6 @@
+local e = 1
+a = e
+print(b)
+f = function(d)
+    return c
+end
@@ -0,0 +1,6 @@
+local d = 1
+a = d
+print(b)
+f = function(e)
+    return c
+end
@@ -0,0 +1,30 @@
+if a < b then
+    x = 1
+end
+if a > b then
+    x = 2
+end
+if a <= b then
+    x = 3
+end
+if a >= b then
+    x = 4
+end
+if a == b then
+    x = 5
+end
+if a ~= b then
+    x = 6
+end
+while a < b do
+    a = 1
+end
+repeat
+    a = 1
+until a >= b
+if not x then
+    y = 1
+end
+if x then
+    y = 2
+end
@@ -0,0 +1,3 @@
+x = not a + 1
+z = function()
+end .. "s"
@@ -0,0 +1,9 @@
+local a = 1
+do
+    local b = 2
+    print(a, b)
+end
+do
+    local c = 3
+end
+print(a)
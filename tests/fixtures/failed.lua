@@ -0,0 +1 @@
+-- parse error: parser error: temporary value at stack offset 0 is read again, but the expression that pushed it can't be repeated (function @test.lua:2, instruction 3 (GETLOCAL))
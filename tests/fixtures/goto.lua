@@ -0,0 +1,13 @@
+-- if a then goto label_5 end
+do
+    do
+        -- ::label_3::
+        -- if not b then goto label_8 end
+    end
+    do
+        -- ::label_5::
+        x = 1
+        -- goto label_3
+    end
+end
+-- ::label_8::
@@ -0,0 +1,2 @@
+local x = 8
+print("hello", x)
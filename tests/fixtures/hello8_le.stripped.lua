@@ -0,0 +1,2 @@
+local a = 8
+print("hello", a)
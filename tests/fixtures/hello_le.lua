@@ -0,0 +1,2 @@
+local x = 7
+print("hello", x)
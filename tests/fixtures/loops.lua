@@ -0,0 +1,18 @@
+while a do
+    if b then
+        break
+    end
+    x = 1
+end
+repeat
+    while c do
+        y = 2
+    end
+until d
+if a then
+    x = 1
+elseif b then
+    x = 2
+else
+    x = 3
+end
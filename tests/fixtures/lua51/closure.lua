@@ -0,0 +1,7 @@
+local n = 0
+local bump = function()
+    n = n + 1
+    return n
+end
+bump()
+print(n)
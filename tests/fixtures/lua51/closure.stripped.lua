@@ -0,0 +1,7 @@
+local l0 = 0
+local l1 = function()
+    l0 = l0 + 1
+    return l0
+end
+l1()
+print(l0)
@@ -0,0 +1,11 @@
+local x = f()
+if x > 1 then
+    print("a")
+elseif x == 0 then
+    print("b")
+else
+    print("c")
+end
+if x and y then
+    print("d")
+end
@@ -0,0 +1,11 @@
+local l0 = f()
+if l0 > 1 then
+    print("a")
+elseif l0 == 0 then
+    print("b")
+else
+    print("c")
+end
+if l0 and y then
+    print("d")
+end
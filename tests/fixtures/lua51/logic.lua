@@ -0,0 +1,3 @@
+local a = x and y or z
+local b = x == 1
+print(a or "none", b)
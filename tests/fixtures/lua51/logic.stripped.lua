@@ -0,0 +1,2 @@
+local l0, l1 = x and y or z, x == 1
+print(l0 or "none", l1)
@@ -0,0 +1,16 @@
+for i = 1, 10 do
+    print(i)
+end
+for k, v in pairs(t) do
+    if v then
+        break
+    end
+    print(k, v)
+end
+local n = 0
+while n < 10 do
+    n = n + 1
+end
+repeat
+    n = n - 1
+until n == 0
@@ -0,0 +1,16 @@
+for l3 = 1, 10 do
+    print(l3)
+end
+for l3, l4 in pairs(t) do
+    if l4 then
+        break
+    end
+    print(l3, l4)
+end
+local l0 = 0
+while l0 < 10 do
+    l0 = l0 + 1
+end
+repeat
+    l0 = l0 - 1
+until l0 == 0
@@ -0,0 +1,9 @@
+repeat
+    local line = read()
+until line == nil or line == "end"
+repeat
+    if done then
+        break
+    end
+    step()
+until x
@@ -0,0 +1,9 @@
+repeat
+    local l0 = read()
+until l0 == nil or l0 == "end"
+repeat
+    if done then
+        break
+    end
+    step()
+until x
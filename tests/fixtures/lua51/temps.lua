@@ -0,0 +1,3 @@
+local a = f()
+print(g(), a)
+print(a)
@@ -0,0 +1,3 @@
+local l0 = f()
+print(g(), l0)
+print(l0)
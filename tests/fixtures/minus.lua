@@ -0,0 +1,4 @@
+x = a - -b
+y = a - -5
+z = a - -1.5
+s = 1 .. a
@@ -0,0 +1,10 @@
+print(f(g()))
+x = f(a, g())
+local b, c = f(g())
+f(b, g(c, h()))
+ret = function(...)
+    return g(1, h())
+end
+pair = function(d)
+    return d, f(d)
+end
@@ -0,0 +1,8 @@
+outer = function()
+    local b = function(a)
+        return a * 2
+    end
+    return b(3)
+end
+call(function()
+end)
@@ -0,0 +1,2 @@
+x = 0.25
+y = -70000.5
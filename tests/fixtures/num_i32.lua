@@ -0,0 +1,2 @@
+x = 70000
+y = -3
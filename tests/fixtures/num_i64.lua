@@ -0,0 +1,2 @@
+x = 5000000000
+y = -3
@@ -0,0 +1,6 @@
+add = function(x, y)
+    return x + y
+end
+h = function(a, ...)
+    print(a, arg)
+end
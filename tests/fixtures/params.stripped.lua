@@ -0,0 +1,6 @@
+add = function(a, b)
+    return a + b
+end
+h = function(c, ...)
+    print(c, arg)
+end
@@ -0,0 +1,9 @@
+g = function()
+    return 1
+end
+add = function(x, y)
+    return x + y
+end
+h = function(a, ...)
+    print(a, x)
+end
@@ -0,0 +1,9 @@
+g = function()
+    return 1
+end
+add = function(a, b)
+    return a + b
+end
+h = function(c, ...)
+    print(c, x)
+end
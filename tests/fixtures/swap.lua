@@ -0,0 +1,4 @@
+local a = 1
+local b = 2
+x, y = y, x
+a, b = b, a
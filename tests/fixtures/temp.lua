@@ -0,0 +1 @@
+f(1, 1)
@@ -0,0 +1,9 @@
+x = -a
+y = not a
+z = -(a + b)
+w = -a ^ b
+v = (-a) ^ b
+u = -1.5
+t = (-1.5) ^ 2
+s = 2.5 * -a
+r = -(-a)
@@ -0,0 +1 @@
+-- decode error: decoder error: unknown opcode: 0x3c (function @test.lua:0, instruction 3)
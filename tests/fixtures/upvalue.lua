@@ -0,0 +1,7 @@
+local a = 1
+f = function()
+    print(%a)
+end
+g = function()
+    print(%print)
+end
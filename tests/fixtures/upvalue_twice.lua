@@ -0,0 +1,4 @@
+local a = 1
+f = function()
+    print(%a .. %a)
+end
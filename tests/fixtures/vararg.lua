@@ -0,0 +1,6 @@
+f = function(...)
+    print(arg)
+end
+g = function(...)
+    return call(print, arg)
+end
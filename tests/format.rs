@@ -0,0 +1,113 @@
+//! Checking the arguments of calls to `format` against the format string.
+use lua_decompiler::lua40::ast::{Block, Call, Expr, Ident, Lit, Node, Stmt, Syntax};
+use lua_decompiler::lua40::{check_format_calls, FormatCall, ProtoPath};
+use lua_decompiler::SymbolTable;
+
+fn name(name: &str) -> Expr {
+    Expr::Access(Ident::new(name))
+}
+
+fn string(value: &str) -> Expr {
+    Expr::Literal(Lit::Str(value.into()))
+}
+
+fn call(callee: &str, args: Vec<Expr>) -> Call {
+    Call {
+        name: name(callee),
+        args,
+    }
+}
+
+/// Check the calls made by statements calling the functions.
+fn check(calls: Vec<Call>) -> Vec<FormatCall> {
+    let syntax = Syntax {
+        root: Block {
+            nodes: calls
+                .into_iter()
+                .map(|call| Node::Stmt(Stmt::Call(Box::new(call))))
+                .collect(),
+            spans: vec![],
+        },
+        path: ProtoPath::main(),
+        symbols: SymbolTable::new(),
+    };
+    check_format_calls(&syntax)
+}
+
+#[test]
+fn test_matching_arguments() {
+    let calls = check(vec![call(
+        "format",
+        vec![string("%d items, %5.2f%% done"), name("n"), name("x")],
+    )]);
+    let [format_call] = &calls[..] else {
+        panic!("expected one call, found {calls:?}");
+    };
+    assert_eq!(format_call.callee, "format");
+    assert_eq!(format_call.specs, ["%d", "%5.2f"]);
+    assert_eq!(format_call.num_args, 2);
+    assert!(format_call.is_ok(), "{format_call}");
+}
+
+#[test]
+fn test_argument_count() {
+    let calls = check(vec![
+        call("format", vec![string("%s and %s"), name("a")]),
+        call("format", vec![string("%s"), name("a"), name("b")]),
+        // The results of the last call may make up the rest.
+        call(
+            "format",
+            vec![string("%s and %s"), Expr::Call(Box::new(call("f", vec![])))],
+        ),
+    ]);
+    assert_eq!(calls.len(), 3);
+    assert_eq!(calls[0].issues, ["expected 2 arguments, found 1"]);
+    assert_eq!(calls[1].issues, ["expected 1 arguments, found 2"]);
+    assert!(calls[2].is_ok(), "{}", calls[2]);
+}
+
+#[test]
+fn test_string_for_number() {
+    let calls = check(vec![call(
+        "format",
+        vec![
+            string("%d %d %s"),
+            string("12"),
+            string("twelve"),
+            string("ok"),
+        ],
+    )]);
+    assert_eq!(
+        calls[0].issues,
+        ["argument 2 is a string for numeric conversion %d"]
+    );
+}
+
+#[test]
+fn test_invalid_format() {
+    let calls = check(vec![
+        call("format", vec![string("%y"), name("a")]),
+        call("format", vec![string("100%")]),
+    ]);
+    assert_eq!(calls[0].issues[0], "invalid conversion '%y'");
+    assert_eq!(calls[1].issues[0], "incomplete conversion '%'");
+}
+
+#[test]
+fn test_nested_calls() {
+    // print(format("%d", n))
+    let inner = call("format", vec![string("%d"), name("n")]);
+    let calls = check(vec![call("print", vec![Expr::Call(Box::new(inner))])]);
+    assert_eq!(calls.len(), 1);
+    assert!(calls[0].is_ok());
+}
+
+#[test]
+fn test_other_calls() {
+    // Only constant format strings passed to format functions are checked.
+    let calls = check(vec![
+        call("print", vec![string("%d")]),
+        call("format", vec![name("pattern"), name("n")]),
+    ]);
+    assert!(calls.is_empty());
+}
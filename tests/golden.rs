@@ -0,0 +1,167 @@
+//! Golden-file tests of the whole decompiler.
+//!
+//! Every `.lua4` chunk in `tests/fixtures`, and `.lua51` chunk in
+//! `tests/fixtures/lua51`, is decompiled and compared against
+//! the `.lua` file of the same name beside it. Run with `LUAD_BLESS=1` to write
+//! the current output to the expected files instead, then review the changes.
+//!
+//! Each chunk is also decompiled with its debug information stripped, like
+//! `luac -s`. Where that changes the output, like the names of parameters, the
+//! expected output is in a `.stripped.lua` file, otherwise it must be the same.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lua_decompiler::{lua40, lua51};
+
+const BLESS_VAR: &str = "LUAD_BLESS";
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn decompile_lua40(code: &[u8], strip: bool) -> String {
+    let mut proto = match lua40::Decoder::new(code).decode() {
+        Ok(proto) => proto,
+        Err(err) => return format!("-- decode error: {err}\n"),
+    };
+    if strip {
+        proto.strip();
+    }
+    let syntax = match lua40::Parser::new(&proto).parse() {
+        Ok(syntax) => syntax,
+        Err(err) => return format!("-- parse error: {err}\n"),
+    };
+    let mut buf = String::new();
+    if let Err(err) = lua40::Scribe::default().fmt_syntax(&mut buf, &syntax) {
+        return format!("-- scribe error: {err}\n");
+    }
+    buf
+}
+
+fn decompile_lua51(code: &[u8], strip: bool) -> String {
+    let mut proto = match lua51::Decoder::new(code).decode() {
+        Ok(proto) => proto,
+        Err(err) => return format!("-- decode error: {err}\n"),
+    };
+    if strip {
+        proto.strip();
+    }
+    let syntax = match lua51::Parser::new(&proto).parse() {
+        Ok(syntax) => syntax,
+        Err(err) => return format!("-- parse error: {err}\n"),
+    };
+    let mut buf = String::new();
+    if let Err(err) = lua51::Scribe::default().fmt_syntax(&mut buf, &syntax) {
+        return format!("-- scribe error: {err}\n");
+    }
+    buf
+}
+
+/// Line diff of the expected and actual output, from their longest common subsequence.
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<_> = expected.lines().collect();
+    let b: Vec<_> = actual.lines().collect();
+
+    // Length of the common subsequence of the suffixes a[i..] and b[j..].
+    let mut common = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut buf = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            buf.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || common[i + 1][j] >= common[i][j + 1]) {
+            buf.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            buf.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+    buf
+}
+
+/// Compare the output against the expected file, describing the differences.
+fn compare(expected_path: &Path, actual: &str) -> Option<String> {
+    match fs::read_to_string(expected_path) {
+        Ok(expected) if expected == actual => None,
+        Ok(expected) => Some(format!(
+            "{}:\n{}",
+            expected_path.display(),
+            diff(&expected, actual)
+        )),
+        Err(_) => Some(format!(
+            "{}: missing, run with {BLESS_VAR}=1 to create it",
+            expected_path.display()
+        )),
+    }
+}
+
+/// Decompile the chunks with the extension in the directory, with and
+/// without debug information, and compare them to the expected files.
+fn check_fixtures(dir: &Path, extension: &str, decompile: fn(&[u8], bool) -> String) {
+    let bless = std::env::var_os(BLESS_VAR).is_some();
+
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .expect("failed to read fixtures")
+        .map(|entry| entry.expect("failed to read fixtures").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no fixtures found");
+
+    let mut failures = vec![];
+    for path in &paths {
+        let code = fs::read(path).expect("failed to read fixture");
+        let actual = decompile(&code, false);
+        let expected_path = path.with_extension("lua");
+
+        let stripped = decompile(&code, true);
+        let stripped_path = path.with_extension("stripped.lua");
+        let stripped_path = (stripped != actual).then_some(stripped_path.as_path());
+
+        if bless {
+            fs::write(&expected_path, &actual).expect("failed to write expected output");
+            match stripped_path {
+                Some(stripped_path) => {
+                    fs::write(stripped_path, &stripped).expect("failed to write expected output")
+                }
+                None => {
+                    let _ = fs::remove_file(path.with_extension("stripped.lua"));
+                }
+            }
+            continue;
+        }
+
+        failures.extend(compare(&expected_path, &actual));
+        // Without a file of its own, the stripped output is compared to the expected output.
+        failures.extend(compare(stripped_path.unwrap_or(&expected_path), &stripped));
+    }
+
+    assert!(
+        failures.is_empty(),
+        "decompiled output differs from the expected files:\n\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_golden() {
+    check_fixtures(&fixtures_dir(), "lua4", decompile_lua40);
+}
+
+#[test]
+fn test_golden_lua51() {
+    check_fixtures(&fixtures_dir().join("lua51"), "lua51", decompile_lua51);
+}
@@ -0,0 +1,69 @@
+//! Grouping local declarations at the top of their block.
+mod common;
+
+use common::parse;
+use lua_decompiler::lua40::ast::{Block, Call, Expr, Ident, Lit, LocalVar, Node, Stmt, Syntax};
+use lua_decompiler::lua40::{self, ProtoPath};
+use lua_decompiler::SymbolTable;
+
+const DOBLOCK: &[u8] = include_bytes!("fixtures/doblock.lua4");
+const MULTRET: &[u8] = include_bytes!("fixtures/multret.lua4");
+
+fn write_grouped(syntax: &Syntax) -> String {
+    let mut buf = String::new();
+    lua40::Scribe::default()
+        .group_locals(true)
+        .fmt_syntax(&mut buf, syntax)
+        .expect("scribe failed");
+    buf
+}
+
+fn decompile_grouped(code: &[u8]) -> String {
+    write_grouped(&parse(code))
+}
+
+#[test]
+fn test_group_nested_blocks() {
+    // Each block declares its own locals.
+    assert_eq!(
+        decompile_grouped(DOBLOCK),
+        "local a\na = 1\ndo\n    local b\n    b = 2\n    print(a, b)\nend\ndo\n    local c\n    c = 3\nend\nprint(a)\n"
+    );
+}
+
+#[test]
+fn test_group_call_results() {
+    let source = decompile_grouped(MULTRET);
+    assert!(
+        source.starts_with("local b, c\nprint(f(g()))\n"),
+        "{source}"
+    );
+    assert!(source.contains("\nb, c = f(g())\n"), "{source}");
+    // Parameters are locals of their function already.
+    assert!(source.contains("pair = function(d)\n    return d, f(d)\nend\n"));
+}
+
+#[test]
+fn test_keep_shadowed_global() {
+    // print(a)
+    // local a = 1
+    let print = Stmt::Call(Box::new(Call {
+        name: Expr::Access(Ident::new("print")),
+        args: vec![Expr::Access(Ident::new("a"))],
+    }));
+    let local_var = Stmt::LocalVar(LocalVar {
+        names: vec![Ident::new("a")],
+        rhs: vec![Expr::Literal(Lit::Int(1))],
+    });
+    let syntax = Syntax {
+        root: Block {
+            nodes: vec![Node::Stmt(print), Node::Stmt(local_var)],
+            spans: vec![],
+        },
+        path: ProtoPath::main(),
+        symbols: SymbolTable::new(),
+    };
+
+    // Declaring `a` first would make `print` read the local instead of the global.
+    assert_eq!(write_grouped(&syntax), "print(a)\nlocal a = 1\n");
+}
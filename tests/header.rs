@@ -0,0 +1,92 @@
+//! Chunk headers, and annotated hex dumps of them.
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::lua40::{annotate_header, read_header, Endian, NumberType};
+
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const HELLO_BE: &[u8] = include_bytes!("fixtures/hello_be.lua4");
+
+/// Length of a Lua 4.0 header with 8 byte numbers, up to the end of the test number.
+const HEADER_LEN: usize = 21;
+
+#[test]
+fn test_read_header() {
+    let header = read_header(HELLO_LE).expect("failed to read header");
+    assert_eq!(header.version, 0x40);
+    assert_eq!(header.endianess, Endian::Little);
+    assert_eq!(header.size_int, 4);
+    assert_eq!(header.size_t, 4);
+    assert_eq!(header.size_instr, 4);
+    assert_eq!(header.size_instr_arg, 32);
+    assert_eq!(header.size_op, 6);
+    assert_eq!(header.size_b, 9);
+    assert_eq!(header.number_type, NumberType::F64);
+
+    let header = read_header(HELLO_BE).expect("failed to read header");
+    assert_eq!(header.endianess, Endian::Big);
+}
+
+#[test]
+fn test_read_header_only() {
+    // The functions after the header aren't read.
+    assert!(read_header(&HELLO_LE[..HEADER_LEN]).is_ok());
+    assert!(read_header(&HELLO_LE[..HEADER_LEN - 1]).is_err());
+
+    let mut code = HELLO_LE.to_vec();
+    code[1] = b'X';
+    let err = read_header(&code).expect_err("read header with a bad signature");
+    assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+}
+
+#[test]
+fn test_annotate() {
+    let dump = annotate_header(HELLO_LE);
+    assert!(dump.malformed().is_none());
+
+    let names: Vec<_> = dump.fields().iter().map(|field| field.name).collect();
+    assert_eq!(
+        names,
+        [
+            "bytemark",
+            "signature",
+            "version",
+            "endianness",
+            "int size",
+            "size_t size",
+            "instruction size",
+            "instruction bits",
+            "opcode bits",
+            "B bits",
+            "number size",
+            "test number",
+        ]
+    );
+    let test_number = &dump.fields()[11];
+    assert_eq!(test_number.range, 13..21);
+
+    let listing = dump.to_string();
+    assert!(listing.contains("00000004  40                        version           4.0\n"));
+}
+
+#[test]
+fn test_malformed() {
+    // An opcode of 30 bits leaves no room for the arguments.
+    let mut code = HELLO_LE.to_vec();
+    code[10] = 30;
+    let dump = annotate_header(&code);
+    let field = dump.malformed().expect("malformed field");
+    assert_eq!(field.name, "B bits");
+    assert_eq!(field.range, 11..12);
+    assert!(dump
+        .to_string()
+        .contains("B bits            !! decoder error"));
+    assert_eq!(dump.fields().last(), Some(field));
+}
+
+#[test]
+fn test_truncated() {
+    let dump = annotate_header(&HELLO_LE[..17]);
+    let field = dump.malformed().expect("malformed field");
+    assert_eq!(field.name, "test number");
+    // The bytes that are left are shown.
+    assert_eq!(field.range, 13..17);
+}
@@ -0,0 +1,77 @@
+//! Coloring decompiled source for terminals.
+use std::fmt::Write;
+
+use lua_decompiler::lua40::{self, Decoder, Highlight, Parser};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+fn highlight(source: &str) -> String {
+    let mut highlight = Highlight::new(String::new());
+    highlight.write_str(source).expect("failed to write");
+    highlight.finish().expect("failed to highlight")
+}
+
+/// Source with the escape codes taken out.
+fn strip(colored: &str) -> String {
+    let mut plain = String::new();
+    let mut rest = colored;
+    while let Some(start) = rest.find('\x1b') {
+        plain.push_str(&rest[..start]);
+        let end = rest[start..].find('m').expect("unfinished escape code");
+        rest = &rest[start + end + 1..];
+    }
+    plain.push_str(rest);
+    plain
+}
+
+#[test]
+fn test_highlight_tokens() {
+    let colored = highlight("local a = 1.5 -- one\nprint(\"a\", a)\n");
+    assert_eq!(
+        colored,
+        "\x1b[35mlocal\x1b[0m a = \x1b[36m1.5\x1b[0m \x1b[90m-- one\x1b[0m\n\
+         print(\x1b[32m\"a\"\x1b[0m, a)\n"
+    );
+}
+
+#[test]
+fn test_highlight_split_writes() {
+    // The scribe can write a token in pieces.
+    let mut highlight = Highlight::new(String::new());
+    for piece in ["whi", "le x d", "o\nend\n"] {
+        highlight.write_str(piece).expect("failed to write");
+    }
+    let colored = highlight.finish().expect("failed to highlight");
+    assert_eq!(
+        colored,
+        "\x1b[35mwhile\x1b[0m x \x1b[35mdo\x1b[0m\n\x1b[35mend\x1b[0m\n"
+    );
+}
+
+#[test]
+fn test_highlight_long_string() {
+    let colored = highlight("s = [[a\nb]]\n");
+    assert_eq!(colored, "s = \x1b[32m[[a\x1b[0m\n\x1b[32mb]]\x1b[0m\n");
+}
+
+#[test]
+fn test_highlight_invalid_source() {
+    // Lua 5.1 has a length operator, which Lua 4.0 doesn't lex.
+    let source = "local n = #t\nprint(n)\n";
+    let colored = highlight(source);
+    assert!(colored.starts_with("\x1b[35mlocal\x1b[0m n = #t"));
+    assert_eq!(strip(&colored), source);
+}
+
+#[test]
+fn test_highlight_decompiled() {
+    let proto = Decoder::new(HELLO).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let mut highlight = Highlight::new(String::new());
+    lua40::Scribe::default()
+        .fmt_syntax(&mut highlight, &syntax)
+        .expect("scribe failed");
+    let colored = highlight.finish().expect("failed to highlight");
+    assert_eq!(strip(&colored), "local x = 7\nprint(\"hello\", x)\n");
+    assert!(colored.contains("\x1b[32m\"hello\"\x1b[0m"));
+}
@@ -0,0 +1,74 @@
+//! HTML pages linking decompiled source to the disassembly.
+use lua_decompiler::lua40::{Decoder, Parser, ProtoPath, Scribe};
+
+const CALLGRAPH: &[u8] = include_bytes!("fixtures/callgraph.lua4");
+
+fn html(code: &[u8]) -> String {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    Scribe::default()
+        .fmt_html(&mut buf, &syntax, &proto)
+        .expect("failed to write html");
+    buf
+}
+
+#[test]
+fn test_html_links_statements() {
+    let page = html(CALLGRAPH);
+    assert!(page.starts_with("<!DOCTYPE html>\n"));
+    assert!(page.ends_with("</html>\n"));
+
+    // The statement in the nested function links into its own disassembly.
+    assert!(page.contains(
+        "<tr id=\"L2\"><td class=\"ln\"><a href=\"#i-main.0-1\" data-end=\"3\" \
+         title=\"main.0 instructions 1-3\">2</a></td><td><pre>    print(message)</pre></td></tr>"
+    ));
+    assert!(page.contains("<tr id=\"i-main.0-1\">"));
+
+    // Instructions link back to the innermost statement, and `end` to none.
+    assert!(page.contains("<tr id=\"i-main-3\"><td class=\"ln\"><a href=\"#L4\">4</a>"));
+    assert!(page.contains("<tr id=\"i-main.1-8\"><td class=\"ln\"></td>"));
+
+    // Strings in the disassembly are escaped.
+    assert!(page.contains("; &quot;add&quot;"));
+}
+
+#[test]
+fn test_html_standalone_function() {
+    let proto = Decoder::new(CALLGRAPH).decode().expect("failed to decode");
+    let path: ProtoPath = "main.1".parse().expect("bad path");
+    let syntax = Parser::for_function(&proto, &path)
+        .expect("no function")
+        .parse_standalone()
+        .expect("failed to parse");
+    let mut buf = String::new();
+    Scribe::default()
+        .fmt_html(&mut buf, &syntax, &proto)
+        .expect("failed to write html");
+
+    // The definition refers to the closure in the main function,
+    // and the body to the function itself.
+    assert!(buf.contains("href=\"#i-main-3\""));
+    assert!(buf.contains("href=\"#i-main.1-4\""));
+    assert!(buf.contains("function main.1 &lt;"));
+}
+
+#[test]
+fn test_html_leaves_source_unmarked() {
+    let proto = Decoder::new(CALLGRAPH).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let mut scribe = Scribe::default();
+    let mut page = String::new();
+    scribe
+        .fmt_html(&mut page, &syntax, &proto)
+        .expect("failed to write html");
+
+    // The same scribe writes plain source afterwards.
+    let mut source = String::new();
+    scribe
+        .fmt_syntax(&mut source, &syntax)
+        .expect("scribe failed");
+    assert!(!source.contains(['\u{1}', '\u{2}']));
+    assert!(!page.contains(['\u{1}', '\u{2}']));
+}
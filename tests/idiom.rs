@@ -0,0 +1,127 @@
+//! Recognizing idioms by patterns over instructions.
+mod common;
+
+use common::write;
+use lua_decompiler::diagnostics::Severity;
+use lua_decompiler::lua40::{
+    Constant, Custom, Decoder, Idiom, Instruction, Opcode, Parser, Pattern, Proto, Recognized,
+};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const IFELSE: &[u8] = include_bytes!("fixtures/ifelse.lua4");
+
+/// Call to a global with string arguments, written as a call to a macro.
+struct Macro {
+    pattern: Pattern,
+}
+
+/// String literal, written as a string looked up in a translation table.
+struct Translated {
+    pattern: Pattern,
+}
+
+impl Idiom for Macro {
+    fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    fn recognize(&self, _proto: &Proto, instructions: &[Instruction]) -> Option<Recognized> {
+        let strings: Vec<_> = instructions
+            .iter()
+            .filter_map(|instruction| match instruction.constant {
+                Some(Constant::String(string)) => Some(string.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+        let (name, args) = strings.split_first()?;
+        if name != "print" {
+            return None;
+        }
+        Some(Recognized::Stmt(Custom {
+            kind: "macro".to_string(),
+            source: format!("LOG({:?})", args.join(", ")),
+        }))
+    }
+}
+
+impl Idiom for Translated {
+    fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    fn recognize(&self, _proto: &Proto, instructions: &[Instruction]) -> Option<Recognized> {
+        match instructions[0].constant {
+            Some(Constant::String(string)) => Some(Recognized::Expr(Custom {
+                kind: "translated".to_string(),
+                source: format!("_({:?})", string.to_string_lossy()),
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// Decompiled source and the number of warnings.
+fn decompile(code: &[u8], idiom: &dyn Idiom) -> (String, usize) {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let mut parser = Parser::new(&proto).with_idiom(idiom);
+    let syntax = parser.parse().expect("failed to parse");
+    (
+        write(&syntax),
+        parser.diagnostics().count(Severity::Warning),
+    )
+}
+
+#[test]
+fn test_pattern_match() {
+    let proto = Decoder::new(HELLO).decode().expect("failed to decode");
+    let instrs = proto.instrs();
+
+    let pattern = Pattern::parse("GETGLOBAL, PUSH*+, GETLOCAL?, CALL").expect("invalid pattern");
+    assert_eq!(pattern.match_at(instrs, 1), Some(4));
+    assert_eq!(pattern.match_at(instrs, 0), None);
+
+    // Repeated elements give back what the rest of the pattern needs.
+    let pattern = Pattern::parse("_+ call end").expect("invalid pattern");
+    assert_eq!(pattern.match_at(instrs, 0), Some(instrs.len()));
+    assert_eq!(instrs[0].opcode, Opcode::PushInt);
+}
+
+#[test]
+fn test_pattern_unknown_opcode() {
+    assert!(Pattern::parse("GETGLOBAL FOO").is_err());
+    assert!(Pattern::parse("JUMP*").is_err());
+    assert!(Pattern::parse(" , ").is_err());
+}
+
+#[test]
+fn test_idiom_stmt() {
+    let idiom = Macro {
+        pattern: Pattern::parse("GETGLOBAL PUSHSTRING+ CALL").expect("invalid pattern"),
+    };
+    let (source, warnings) = decompile(IFELSE, &idiom);
+    assert_eq!(
+        source,
+        "if x <= 1 then\n    LOG(\"a\")\nelse\n    LOG(\"b\")\nend\n"
+    );
+    assert_eq!(warnings, 0);
+}
+
+#[test]
+fn test_idiom_expr() {
+    let idiom = Translated {
+        pattern: Pattern::parse("PUSHSTRING").expect("invalid pattern"),
+    };
+    let (source, _) = decompile(HELLO, &idiom);
+    assert_eq!(source, "local x = 7\nprint(_(\"hello\"), x)\n");
+}
+
+#[test]
+fn test_idiom_unbalanced_stack() {
+    // A statement can't leave the string it pushed on the stack.
+    let idiom = Macro {
+        pattern: Pattern::parse("GETGLOBAL PUSHSTRING").expect("invalid pattern"),
+    };
+    let (source, warnings) = decompile(HELLO, &idiom);
+    assert_eq!(source, "local x = 7\nprint(\"hello\", x)\n");
+    assert_eq!(warnings, 1);
+}
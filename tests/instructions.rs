@@ -0,0 +1,255 @@
+//! Decoded instructions for external tools.
+mod common;
+
+use common::decode;
+use lua_decompiler::lua40::{Args, Constant, Decoder, Header, Op, OpMode, Opcode, Proto};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const IFELSE: &[u8] = include_bytes!("fixtures/ifelse.lua4");
+const UNARY: &[u8] = include_bytes!("fixtures/unary.lua4");
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+
+#[test]
+fn test_instructions() {
+    let proto = decode(HELLO);
+    let instructions: Vec<_> = proto.instructions().collect();
+    assert_eq!(instructions.len(), proto.code().len());
+
+    let push = &instructions[0];
+    assert_eq!(push.offset, 0);
+    assert_eq!(push.word, proto.code()[0]);
+    assert_eq!(push.opcode, Opcode::PushInt);
+    assert_eq!(push.args, Args::S(7));
+    assert!(push.constant.is_none());
+
+    let get = &instructions[1];
+    assert_eq!(get.opcode, Opcode::GetGlobal);
+    assert_eq!(get.args, Args::U(0));
+    assert!(matches!(get.constant, Some(Constant::String(s)) if s.as_bytes() == b"print"));
+
+    let call = &instructions[4];
+    assert_eq!(call.args, Args::AB(1, 0));
+    assert!(call.target.is_none());
+}
+
+#[test]
+fn test_instruction_targets() {
+    let proto = decode(IFELSE);
+    let targets: Vec<_> = proto
+        .instructions()
+        .filter_map(|instr| Some((instr.offset, instr.target?)))
+        .collect();
+    assert_eq!(targets, [(2, 7), (6, 10)]);
+}
+
+#[test]
+fn test_instruction_constants() {
+    let proto = decode(UNARY);
+    let numbers: Vec<_> = proto
+        .instructions()
+        .filter_map(|instr| match instr.constant {
+            Some(Constant::Number(number)) => Some((instr.opcode, number)),
+            _ => None,
+        })
+        .collect();
+    assert!(numbers.contains(&(Opcode::PushNegNum, 1.5)));
+
+    let proto = decode(PARAMS);
+    let params: Vec<_> = proto
+        .instructions()
+        .filter_map(|instr| match instr.constant {
+            Some(Constant::Function(nested)) => Some(nested.num_params()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(params, [2, 1]);
+}
+
+/// The hello fixture as compiled with 64 bit instructions,
+/// with `U` made 58 bits wide and `A` 49 bits wide.
+fn widen(code: &[u8]) -> Vec<u8> {
+    let proto = decode(code);
+    let mut wide = code[..code.len() - proto.code().len() * 4].to_vec();
+    wide[8] = 8;
+    wide[9] = 64;
+    let header = Header {
+        size_instr: 8,
+        size_instr_arg: 64,
+        ..Decoder::new(code)
+            .decode_chunk()
+            .expect("failed to decode")
+            .header()
+            .clone()
+    };
+    for instr in proto.instructions() {
+        let word = header
+            .encode(instr.opcode, instr.args)
+            .expect("failed to encode");
+        wide.extend_from_slice(&word.to_le_bytes());
+    }
+    wide
+}
+
+#[test]
+fn test_wide_instructions() {
+    let wide = widen(HELLO);
+    let chunk = Decoder::new(&wide)
+        .decode_chunk()
+        .expect("failed to decode");
+    assert_eq!(chunk.header().size_instr, 8);
+
+    let narrow = decode(HELLO);
+    let args = |proto: &Proto| -> Vec<_> {
+        proto
+            .instructions()
+            .map(|instr| (instr.opcode, instr.args))
+            .collect()
+    };
+    assert_eq!(args(chunk.main()), args(&narrow));
+    assert!(chunk.main().code()[0] > u32::MAX as u64);
+    assert_eq!(chunk.encode().expect("failed to encode"), wide);
+}
+
+#[test]
+fn test_wide_instruction_out_of_range() {
+    let mut wide = widen(HELLO);
+    // `U` of the first instruction after the header, `GETGLOBAL 0`.
+    let offset = wide.len() - 5 * 8;
+    wide[offset + 5] = 0x01;
+    let err = Decoder::new(&wide)
+        .decode()
+        .expect_err("decoded wide argument");
+    assert!(err.to_string().contains("out of range"), "{err}");
+
+    let mut bad = HELLO.to_vec();
+    bad[8] = 2;
+    assert!(Decoder::new(&bad).decode().is_err());
+}
+
+#[test]
+fn test_decode_instr() {
+    let chunk = Decoder::new(HELLO)
+        .decode_chunk()
+        .expect("failed to decode");
+    let header = chunk.header();
+    let proto = chunk.main();
+
+    for (word, instr) in proto.code().iter().zip(proto.instrs()) {
+        let decoded = header.decode_instr(*word).expect("failed to decode word");
+        assert_eq!(decoded.opcode(), instr.opcode());
+        assert_eq!(decoded.args(), instr.args());
+    }
+
+    let push = header
+        .decode_instr(proto.code()[0])
+        .expect("failed to decode word");
+    assert_eq!(push.opcode(), Opcode::PushInt);
+    assert_eq!(push.arg_s(), 7);
+    assert_eq!(push.op(), Op::PushInt { value: 7 });
+
+    let call = header
+        .decode_instr(proto.code()[4])
+        .expect("failed to decode word");
+    assert_eq!((call.arg_a(), call.arg_b()), (1, 0));
+    assert_eq!(
+        call.op(),
+        Op::Call {
+            stack_offset: 1,
+            results: 0
+        }
+    );
+    assert_eq!(proto.ops()[1], Op::GetGlobal { string_id: 0 });
+    assert_eq!(proto.ops().len(), proto.instrs().len());
+
+    // Opcode 63 is outside of the instruction set.
+    assert!(header.decode_instr(63).is_err());
+    let bad = Header {
+        size_op: 70,
+        ..header.clone()
+    };
+    assert!(bad.decode_instr(0).is_err());
+}
+
+/// Every opcode of the instruction set.
+fn opcodes() -> impl Iterator<Item = Opcode> {
+    (0..64).filter_map(|value| Opcode::try_from(value).ok())
+}
+
+/// Arguments at the edges of what the opcode's mode can hold.
+fn edge_args(opcode: Opcode) -> Vec<Args> {
+    match opcode.mode() {
+        OpMode::None => vec![Args::None],
+        OpMode::U => [0, 1, u32::MAX].map(Args::U).to_vec(),
+        OpMode::S => [0, 1, -1, i32::MAX, i32::MIN].map(Args::S).to_vec(),
+        OpMode::AB => [(0, 0), (1, 0), (0, 1), (u32::MAX, u32::MAX)]
+            .map(|(a, b)| Args::AB(a, b))
+            .to_vec(),
+    }
+}
+
+/// Whether the arguments fit in the fields of the layout.
+fn fits(header: &Header, args: Args) -> bool {
+    let size_u = (header.size_instr_arg - header.size_op) as u32;
+    let size_a = size_u - header.size_b as u32;
+    let max = |bits: u32| u64::MAX.checked_shr(64 - bits).unwrap_or(0);
+    match args {
+        Args::None => true,
+        Args::U(u) => u as u64 <= max(size_u),
+        Args::S(s) => {
+            let max_s = (max(size_u) >> 1) as i128;
+            (-max_s..=max(size_u) as i128 - max_s).contains(&(s as i128))
+        }
+        Args::AB(a, b) => a as u64 <= max(size_a) && b as u64 <= max(header.size_b as u32),
+    }
+}
+
+#[test]
+fn test_encode_every_layout() {
+    let mut layouts = 0;
+    for size_instr in [4, 8] {
+        for size_instr_arg in 1..=size_instr * 8 {
+            for size_op in 0..size_instr_arg {
+                for size_b in 0..size_instr_arg - size_op {
+                    let header = Header {
+                        size_instr,
+                        size_instr_arg,
+                        size_op,
+                        size_b,
+                        ..Header::default()
+                    };
+                    layouts += 1;
+                    for opcode in opcodes() {
+                        let opcode_fits = (opcode as u64) >> size_op == 0;
+                        for args in edge_args(opcode) {
+                            let encoded = header.encode(opcode, args);
+                            if !opcode_fits || !fits(&header, args) {
+                                assert!(encoded.is_err(), "{header:?} {opcode:?} {args:?}");
+                                continue;
+                            }
+                            let word = encoded.expect("failed to encode");
+                            assert_eq!(word.checked_shr(size_instr_arg as u32).unwrap_or(0), 0);
+                            assert_eq!(header.decode(word).ok(), Some((opcode, args)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    assert!(layouts > 0);
+}
+
+#[test]
+fn test_encode_mismatched_args() {
+    let chunk = Decoder::new(HELLO)
+        .decode_chunk()
+        .expect("failed to decode");
+    let header = chunk.header();
+    assert!(header.encode(Opcode::PushInt, Args::U(1)).is_err());
+    assert!(header.encode(Opcode::Call, Args::None).is_err());
+    assert!(header.encode(Opcode::End, Args::S(0)).is_err());
+    assert!(header.encode(Opcode::Unknown, Args::None).is_err());
+    assert_eq!(
+        header.encode(Opcode::PushInt, Args::S(7)).ok(),
+        Some(chunk.main().code()[0])
+    );
+}
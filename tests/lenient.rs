@@ -0,0 +1,76 @@
+//! Commenting out the instructions that fail to decompile, and carrying on.
+use lua_decompiler::diagnostics::Severity;
+use lua_decompiler::options::{DecompileOptions, Tolerance};
+use lua_decompiler::{decompile_with, lua40, lua51};
+
+/// `f`'s body reads back a call result with `GETLOCAL`, which can't be decompiled.
+const FAILED: &[u8] = include_bytes!("fixtures/failed.lua4");
+const CLOSURE_LUA51: &[u8] = include_bytes!("fixtures/lua51/closure.lua51");
+
+/// Offset of the `ADD` instruction in the nested function of `closure.lua51`.
+const LUA51_ADD_OFFSET: usize = 133;
+
+fn lenient() -> DecompileOptions {
+    DecompileOptions::new().with_tolerance(Tolerance::Lenient)
+}
+
+#[test]
+fn test_strict() {
+    let err = lua40::decompile(FAILED).expect_err("decompiled a temporary read");
+    assert!(err.to_string().contains("can't be repeated"), "{err}");
+}
+
+#[test]
+fn test_failed_function() {
+    let output = decompile_with(FAILED, &lenient()).expect("failed to decompile");
+    // The rest of the chunk is decompiled around the function that failed.
+    assert!(
+        output.source.starts_with("x = 1\nf = function()\n"),
+        "{}",
+        output.source
+    );
+    assert!(
+        output.source.ends_with("\nend\ny = 2\n"),
+        "{}",
+        output.source
+    );
+    assert!(output
+        .source
+        .contains("    -- DECOMPILE FAILED: parser error: temporary value at stack offset 0"));
+    assert!(output.source.contains("    --\t2\t[-]\tCALL\t0 1\n"));
+    assert!(output.diagnostics.count(Severity::Warning) >= 1);
+}
+
+#[test]
+fn test_parser_diagnostics() {
+    let proto = lua40::Decoder::new(FAILED)
+        .decode()
+        .expect("failed to decode");
+    let mut parser = lua40::Parser::new(&proto).with_tolerance(Tolerance::Lenient);
+    parser.parse().expect("failed to parse");
+    let diagnostic = parser.diagnostics().iter().next().expect("no diagnostics");
+    assert_eq!(diagnostic.severity, Severity::Warning);
+    assert!(diagnostic.message.contains("can't be repeated"));
+}
+
+#[test]
+fn test_lua51() {
+    // LOADBOOL that skips the next instruction, as a statement.
+    let mut code = CLOSURE_LUA51.to_vec();
+    let word = 2u32 | (1 << 14) | (1 << 23);
+    code[LUA51_ADD_OFFSET..LUA51_ADD_OFFSET + 4].copy_from_slice(&word.to_le_bytes());
+    assert!(lua51::decompile(&code).is_err());
+
+    let output = decompile_with(&code, &lenient()).expect("failed to decompile");
+    assert!(output
+        .source
+        .starts_with("local n = 0\nlocal bump = function()\n"));
+    assert!(output
+        .source
+        .contains("    --[[ DECOMPILE FAILED: parser error: unsupported instruction: LOADBOOL\n"));
+    assert!(
+        output.source.ends_with("end\nbump()\nprint(n)\n"),
+        "{}",
+        output.source
+    );
+}
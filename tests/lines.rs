@@ -0,0 +1,78 @@
+//! Keeping the layout of the original source, from the line information.
+//!
+//! The fixture is compiled from:
+//!
+//! ```lua
+//! a = 1
+//! b = 2
+//!
+//! c = 3
+//!
+//!
+//!
+//!
+//!
+//! d = 4
+//! ```
+mod common;
+
+use common::{decode, parse};
+use lua_decompiler::lua40;
+use lua_decompiler::lua40::ast::Comment;
+
+const LINES: &[u8] = include_bytes!("fixtures/lines.lua4");
+
+fn decompile(mut scribe: lua40::Scribe) -> String {
+    let syntax = parse(LINES);
+    let mut buf = String::new();
+    scribe.fmt_syntax(&mut buf, &syntax).expect("scribe failed");
+    buf
+}
+
+#[test]
+fn test_line_at() {
+    let proto = decode(LINES);
+    let lines: Vec<_> = (0..proto.code().len())
+        .map(|pc| proto.line_at(pc))
+        .collect();
+    assert_eq!(lines, [1, 1, 2, 2, 4, 4, 10, 10, 10].map(Some),);
+}
+
+#[test]
+fn test_preserve_lines() {
+    assert_eq!(
+        decompile(lua40::Scribe::default().preserve_lines(true)),
+        "a = 1\nb = 2\n\nc = 3\n\nd = 4\n"
+    );
+}
+
+#[test]
+fn test_line_markers() {
+    assert_eq!(
+        decompile(lua40::Scribe::default().line_markers(true)),
+        "a = 1\nb = 2\n-- line 4\nc = 3\n-- line 10\nd = 4\n"
+    );
+}
+
+#[test]
+fn test_canonical() {
+    let scribe = || {
+        lua40::Scribe::default()
+            .annotate(true)
+            .preserve_lines(true)
+            .line_markers(true)
+            .canonical(true)
+    };
+    let canonical = decompile(scribe());
+    assert_eq!(canonical, "a = 1\nb = 2\nc = 3\nd = 4\n");
+    assert_eq!(decompile(scribe()), canonical);
+
+    let mut syntax = parse(LINES);
+    syntax.root.insert_comment(0, Comment::new("first  "));
+    syntax.root.insert_comment(2, Comment::trailing("second\t"));
+    let mut buf = String::new();
+    scribe()
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(buf, "-- first\na = 1  -- second\nb = 2\nc = 3\nd = 4\n");
+}
@@ -0,0 +1,117 @@
+//! Writing string and number literals.
+use lua_decompiler::lstring::LuaString;
+use lua_decompiler::lua40::ast::{Assign, Block, Expr, Ident, Lit, Node, Stmt, Syntax};
+use lua_decompiler::lua40::{check_syntax, ProtoPath, Scribe};
+use lua_decompiler::style::{NumberFormat, ScribeConfig};
+use lua_decompiler::SymbolTable;
+
+/// Source of `s = lit`.
+fn write_lit(lit: Lit, config: ScribeConfig) -> String {
+    let syntax = Syntax {
+        root: Block {
+            nodes: vec![Node::Stmt(Stmt::Assign(Box::new(Assign {
+                targets: vec![Ident::new("s")],
+                rhs: vec![Expr::Literal(lit)],
+            })))],
+            spans: vec![],
+        },
+        path: ProtoPath::main(),
+        symbols: SymbolTable::new(),
+    };
+    let mut buf = String::new();
+    Scribe::new(config)
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    check_syntax(&buf).expect("invalid syntax");
+    buf
+}
+
+/// Source of `s = value`, with long strings.
+fn write(value: &[u8]) -> String {
+    let config = ScribeConfig {
+        long_strings: true,
+        ..ScribeConfig::default()
+    };
+    write_lit(Lit::Str(LuaString::new(value)), config)
+}
+
+/// The number as written, without the `s = `.
+fn number(value: f64, number_format: NumberFormat) -> String {
+    let config = ScribeConfig {
+        number_format,
+        ..ScribeConfig::default()
+    };
+    let source = write_lit(Lit::Num(value), config);
+    source["s = ".len()..].trim_end().to_string()
+}
+
+#[test]
+fn test_long_strings() {
+    assert_eq!(write(b"one\ntwo"), "s = [[one\ntwo]]\n");
+    // The line break after the opening brackets is skipped when read back.
+    assert_eq!(write(b"\nindented\n"), "s = [[\n\nindented\n]]\n");
+    // Nested brackets are fine as long as they're balanced.
+    assert_eq!(write(b"t[[1]]\n[[x]]"), "s = [[t[[1]]\n[[x]]]]\n");
+    // Strings on a single line stay quoted.
+    assert_eq!(write(b"one line"), "s = \"one line\"\n");
+}
+
+#[test]
+fn test_long_strings_quoted() {
+    // Closes the long string early.
+    assert_eq!(write(b"a]]\nb"), "s = \"a]]\\nb\"\n");
+    // The closing brackets would read as `]]]`.
+    assert_eq!(write(b"a\nb]"), "s = \"a\\nb]\"\n");
+    // Leaves the closing brackets nested.
+    assert_eq!(write(b"a\n[[b"), "s = \"a\\n[[b\"\n");
+    // Escapes can't be written in long strings.
+    assert_eq!(write(b"a\r\nb"), "s = \"a\\r\\nb\"\n");
+    assert_eq!(write(b"a\n\xff"), "s = \"a\\n\\255\"\n");
+}
+
+#[test]
+fn test_shortest_numbers() {
+    let values = [
+        0.1,
+        -2.5,
+        1.0 / 3.0,
+        1e15,
+        123456789012345680.0,
+        1e300,
+        -1e-300,
+        5e-324,
+        f64::MAX,
+        f64::MIN_POSITIVE,
+        9007199254740993.0,
+    ];
+    for value in values {
+        let text = number(value, NumberFormat::Shortest);
+        let read: f64 = text.parse().expect("not a number");
+        assert_eq!(read.to_bits(), value.to_bits(), "{text}");
+    }
+
+    assert_eq!(number(42.0, NumberFormat::Shortest), "42");
+    assert_eq!(number(-1e15, NumberFormat::Shortest), "-1000000000000000");
+    assert_eq!(number(0.1, NumberFormat::Shortest), "0.1");
+    assert_eq!(number(1e300, NumberFormat::Shortest), "1e300");
+    assert_eq!(number(1.5e-7, NumberFormat::Shortest), "1.5e-7");
+    assert_eq!(number(f64::INFINITY, NumberFormat::Shortest), "(1/0)");
+    assert_eq!(number(f64::NEG_INFINITY, NumberFormat::Shortest), "(-1/0)");
+    assert_eq!(number(f64::NAN, NumberFormat::Shortest), "(0/0)");
+}
+
+#[test]
+fn test_luac_numbers() {
+    assert_eq!(number(42.0, NumberFormat::Luac), "42");
+    assert_eq!(number(0.1, NumberFormat::Luac), "0.1");
+    assert_eq!(number(1.0 / 3.0, NumberFormat::Luac), "0.33333333333333");
+    assert_eq!(number(-2.5, NumberFormat::Luac), "-2.5");
+    assert_eq!(number(1e15, NumberFormat::Luac), "1e+15");
+    assert_eq!(
+        number(12345678901234.0, NumberFormat::Luac),
+        "12345678901234"
+    );
+    assert_eq!(number(0.0001, NumberFormat::Luac), "0.0001");
+    assert_eq!(number(0.00001, NumberFormat::Luac), "1e-05");
+    assert_eq!(number(1e300, NumberFormat::Luac), "1e+300");
+}
@@ -0,0 +1,49 @@
+//! String constants that are arbitrary bytes rather than UTF-8.
+use lua_decompiler::lua40::{self, Decoder};
+use lua_decompiler::LuaString;
+
+const BYTES: &[u8] = include_bytes!("fixtures/bytes.lua4");
+
+/// Bytes of the string constant assigned to `s`.
+const BINARY: &[u8] = b"\xff\x00\"q\\\n\x01";
+
+#[test]
+fn test_lua_string() {
+    let string = LuaString::from("café");
+    assert_eq!(string.to_str(), Some("café"));
+    assert_eq!(string.len(), 5);
+    assert_eq!(string, LuaString::new(*b"caf\xc3\xa9"));
+
+    let binary = LuaString::new(BINARY);
+    assert_eq!(binary.as_bytes(), BINARY);
+    assert_eq!(binary.to_str(), None);
+    assert_eq!(binary.to_string_lossy(), "\u{fffd}\0\"q\\\n\u{1}");
+    assert!(LuaString::from("").is_empty());
+}
+
+#[test]
+fn test_escape() {
+    let binary = LuaString::new(BINARY);
+    assert_eq!(format!("{binary:?}"), r#""\255\000\"q\\\n\001""#);
+
+    // Escapes are always three digits, so a digit after one isn't read into it.
+    let digits = LuaString::new(*b"\x017");
+    assert_eq!(format!("{digits:?}"), r#""\0017""#);
+}
+
+#[test]
+fn test_decode_bytes() {
+    let proto = Decoder::new(BYTES).decode().expect("failed to decode");
+    assert_eq!(proto.strings()[1].as_bytes(), BINARY);
+    assert_eq!(proto.strings()[3].to_str(), Some("café"));
+}
+
+#[test]
+fn test_decompile_bytes() {
+    let source = lua40::decompile(BYTES).expect("failed to decompile");
+    assert_eq!(
+        source,
+        "s = \"\\255\\000\\\"q\\\\\\n\\001\"\nprint(\"café\")\n"
+    );
+    lua40::check_syntax(&source).expect("invalid syntax");
+}
@@ -0,0 +1,109 @@
+//! Decoding Lua 3.2 chunks.
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::lua32::{read_header, Constant, Decoder, Opcode};
+use lua_decompiler::lua40::Endian;
+use lua_decompiler::{decode_any, detect_version, AnyProto, LuaVersion};
+
+const HELLO: &[u8] = include_bytes!("fixtures/lua32/hello.lua32");
+
+/// Offset of the code size in `hello.lua32`, after the header,
+/// the line defined and the source name.
+const CODE_SIZE_OFFSET: usize = 14 + 2 + 4 + "@hello.lua\0".len();
+
+/// Offset of the version byte, after the bytemark and signature.
+const VERSION_OFFSET: usize = 4;
+
+#[test]
+fn test_read_header() {
+    let header = read_header(HELLO).expect("failed to read header");
+    assert_eq!(header.version, 0x32);
+    assert_eq!(header.size_number, 8);
+    assert_eq!(header.number_endian, Endian::Little);
+}
+
+#[test]
+fn test_versions() {
+    // Lua 3.1 chunks share the format.
+    let mut code = HELLO.to_vec();
+    code[VERSION_OFFSET] = 0x31;
+    assert_eq!(
+        read_header(&code).expect("failed to read header").version,
+        0x31
+    );
+    assert!(Decoder::new(&code).decode().is_ok());
+
+    code[VERSION_OFFSET] = 0x33;
+    let err = Decoder::new(&code)
+        .decode()
+        .expect_err("decoded an unknown version");
+    assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+}
+
+#[test]
+fn test_dispatch() {
+    assert_eq!(
+        detect_version(HELLO).expect("unknown version"),
+        LuaVersion::Lua32
+    );
+    let proto = decode_any(HELLO).expect("failed to decode");
+    assert!(matches!(proto, AnyProto::Lua32(_)));
+}
+
+#[test]
+fn test_dump() {
+    let proto = Decoder::new(HELLO).decode().expect("failed to decode");
+    let listing = proto.dump().to_string();
+    assert!(listing.starts_with("function <@hello.lua:0> (8 bytes, 4 instructions)\n"));
+    assert!(listing.contains("\t    2\tPUSHCONSTANT\t1\t; \"hello\"\n"));
+}
+
+#[test]
+fn test_decode() {
+    let mut decoder = Decoder::new(HELLO);
+    let proto = decoder.decode().expect("failed to decode");
+    assert_eq!(decoder.position(), HELLO.len() as u64);
+
+    assert_eq!(proto.source(), "@hello.lua");
+    let opcodes: Vec<_> = proto.instrs().iter().map(|instr| instr.opcode).collect();
+    assert_eq!(
+        opcodes,
+        [
+            Opcode::GetGlobal,
+            Opcode::PushConstant,
+            Opcode::CallFunc,
+            Opcode::EndCode
+        ]
+    );
+    assert!(matches!(&proto.constants()[0], Constant::String(name) if name.to_string() == "print"));
+    assert!(proto.protos().is_empty());
+}
+
+#[test]
+fn test_truncated_chunk() {
+    for len in 0..HELLO.len() {
+        assert!(Decoder::new(&HELLO[..len]).decode().is_err());
+    }
+}
+
+#[test]
+fn test_oversized_code() {
+    // Claim 2^32 - 1 bytes of code, which must fail
+    // without allocating a buffer of that size.
+    let mut code = HELLO.to_vec();
+    code[CODE_SIZE_OFFSET..CODE_SIZE_OFFSET + 4].copy_from_slice(&[0xff; 4]);
+    let err = Decoder::new(&code)
+        .decode()
+        .expect_err("decoded code past the end of the chunk");
+    assert!(err.to_string().contains("exceeds chunk size"), "{err}");
+}
+
+#[test]
+fn test_missing_endcode() {
+    // Replace the final ENDCODE with a RETCODE.
+    let mut code = HELLO.to_vec();
+    code[CODE_SIZE_OFFSET + 4 + 7] = Opcode::RetCode as u8;
+    let err = Decoder::new(&code)
+        .decode()
+        .expect_err("decoded code without ENDCODE");
+    assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+}
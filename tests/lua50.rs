@@ -0,0 +1,89 @@
+//! Decoding Lua 5.0 chunks.
+//!
+//! The fixture is compiled from:
+//!
+//! ```lua
+//! local function f() end
+//! print("hello")
+//! ```
+use lua_decompiler::lua40::{Endian, NumberType};
+use lua_decompiler::lua50::{read_header, Constant, Decoder, Opcode};
+
+const HELLO: &[u8] = include_bytes!("fixtures/lua50/hello.lua50");
+
+#[test]
+fn test_read_header() {
+    let header = read_header(HELLO).expect("failed to read header");
+    assert_eq!(header.version, 0x50);
+    assert_eq!(header.endianess, Endian::Little);
+    assert_eq!(header.size_instr, 4);
+    assert_eq!(
+        (header.size_op, header.size_a, header.size_b, header.size_c),
+        (6, 8, 9, 9)
+    );
+    assert_eq!(header.number_type, NumberType::F64);
+}
+
+#[test]
+fn test_decode() {
+    let mut decoder = Decoder::new(HELLO);
+    let proto = decoder.decode().expect("failed to decode");
+    assert_eq!(decoder.position(), HELLO.len() as u64);
+
+    assert_eq!(proto.source(), "@hello.lua");
+    let opcodes: Vec<_> = proto.instrs().iter().map(|instr| instr.opcode).collect();
+    assert_eq!(
+        opcodes,
+        [
+            Opcode::Closure,
+            Opcode::GetGlobal,
+            Opcode::LoadK,
+            Opcode::Call,
+            Opcode::Return
+        ]
+    );
+    let call = proto.instrs()[3];
+    assert_eq!((call.a, call.b, call.c), (1, 2, 1));
+    let load = proto.instrs()[2];
+    assert_eq!((load.a, load.bx), (2, 1));
+
+    let strings: Vec<_> = proto
+        .constants()
+        .iter()
+        .map(|constant| match constant {
+            Constant::String(string) => string.to_string(),
+            constant => panic!("expected a string, found {constant:?}"),
+        })
+        .collect();
+    assert_eq!(strings, ["print", "hello"]);
+
+    // The nested function only returns.
+    let [nested] = proto.protos() else {
+        panic!("expected one nested function");
+    };
+    assert_eq!(nested.instrs().len(), 1);
+    assert_eq!(nested.instrs()[0].opcode, Opcode::Return);
+}
+
+#[test]
+fn test_dump() {
+    let proto = Decoder::new(HELLO).decode().expect("failed to decode");
+    let listing = proto.dump().to_string();
+    assert!(listing.starts_with("function <@hello.lua:0> (5 instructions)\n"));
+    assert!(listing.contains("\tGETGLOBAL\t1 0\t; \"print\"\n"));
+    assert!(listing.contains("function <@hello.lua:1> (1 instructions)\n"));
+}
+
+#[test]
+fn test_truncated_chunk() {
+    for len in 0..HELLO.len() {
+        assert!(Decoder::new(&HELLO[..len]).decode().is_err());
+    }
+}
+
+#[test]
+fn test_wrong_version() {
+    let mut code = HELLO.to_vec();
+    code[4] = 0x51;
+    assert!(Decoder::new(&code).decode().is_err());
+}
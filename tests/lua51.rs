@@ -0,0 +1,136 @@
+//! Decoding and decompiling Lua 5.1 chunks.
+use lua_decompiler::lua51::ast::Stmt;
+use lua_decompiler::lua51::{self, read_header, Constant, Decoder, Endian, Opcode, Parser};
+
+const CLOSURE: &[u8] = include_bytes!("fixtures/lua51/closure.lua51");
+const LOOPS: &[u8] = include_bytes!("fixtures/lua51/loops.lua51");
+const REPEAT: &[u8] = include_bytes!("fixtures/lua51/repeat.lua51");
+
+#[test]
+fn test_read_header() {
+    let header = read_header(CLOSURE).expect("failed to read header");
+    assert_eq!(header.version, 0x51);
+    assert_eq!(header.format, 0);
+    assert_eq!(header.endianess, Endian::Little);
+    assert_eq!(
+        (header.size_int, header.size_t, header.size_instr),
+        (4, 4, 4)
+    );
+    assert_eq!(header.size_number, 8);
+    assert!(!header.integral);
+}
+
+#[test]
+fn test_decode() {
+    let mut decoder = Decoder::new(CLOSURE);
+    let proto = decoder.decode().expect("failed to decode");
+    assert_eq!(decoder.position(), CLOSURE.len() as u64);
+
+    assert_eq!(proto.source(), "@closure.lua");
+    assert_eq!(proto.instrs().len(), 9);
+    let closure = proto.instrs()[1];
+    assert_eq!(
+        (closure.opcode, closure.a, closure.bx),
+        (Opcode::Closure, 1, 0)
+    );
+    assert!(matches!(proto.constants()[0], Constant::Number(n) if n == 0.0));
+    assert!(matches!(&proto.constants()[1], Constant::String(name) if name.to_string() == "print"));
+
+    // `n + 1` adds the constant 1, which is encoded with the RK bit set.
+    let [bump] = proto.protos() else {
+        panic!("expected one nested function");
+    };
+    let add = bump.instrs()[1];
+    assert_eq!(add.opcode, Opcode::Add);
+    assert_eq!(add.c, 256);
+}
+
+#[test]
+fn test_dump() {
+    let proto = Decoder::new(CLOSURE).decode().expect("failed to decode");
+    let listing = proto.dump().to_string();
+    assert!(listing.starts_with("function <@closure.lua:0,0> (9 instructions)\n"));
+    assert!(listing.contains("\tGETGLOBAL\t2 1\t; \"print\"\n"));
+    assert!(listing.contains("\tGETUPVAL \t0 0 0\t; n\n"));
+}
+
+#[test]
+fn test_truncated_chunk() {
+    for len in 0..CLOSURE.len() {
+        assert!(Decoder::new(&CLOSURE[..len]).decode().is_err());
+    }
+}
+
+#[test]
+fn test_decompile() {
+    assert_eq!(
+        lua51::decompile(CLOSURE).expect("failed to decompile"),
+        include_str!("fixtures/lua51/closure.lua")
+    );
+}
+
+#[test]
+fn test_strip() {
+    let mut proto = Decoder::new(CLOSURE).decode().expect("failed to decode");
+    proto.strip();
+    // Locals and upvalues are named by their register.
+    let listing = proto.dump().to_string();
+    assert!(listing.contains("0 upvalues, 0 locals"));
+
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    lua51::Scribe::default()
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(buf, include_str!("fixtures/lua51/closure.stripped.lua"));
+}
+
+#[test]
+fn test_loops() {
+    let proto = Decoder::new(LOOPS).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let kinds: Vec<_> = syntax
+        .root
+        .stmts
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::NumericFor(_) => "for",
+            Stmt::GenericFor(_) => "for in",
+            Stmt::LocalVar(_) => "local",
+            Stmt::While(_) => "while",
+            Stmt::Repeat(_) => "repeat",
+            _ => "other",
+        })
+        .collect();
+    assert_eq!(kinds, ["for", "for in", "local", "while", "repeat"]);
+
+    let Stmt::NumericFor(numeric_for) = &syntax.root.stmts[0] else {
+        unreachable!();
+    };
+    assert_eq!(numeric_for.var, "i");
+    assert!(numeric_for.step.is_none());
+
+    let Stmt::GenericFor(generic_for) = &syntax.root.stmts[1] else {
+        unreachable!();
+    };
+    assert_eq!(generic_for.names, ["k", "v"]);
+    assert!(matches!(generic_for.body.stmts[0], Stmt::If(_)));
+}
+
+#[test]
+fn test_repeat() {
+    let proto = Decoder::new(REPEAT).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let [Stmt::Repeat(first), Stmt::Repeat(second)] = syntax.root.stmts.as_slice() else {
+        panic!("unexpected statements: {:?}", syntax.root.stmts);
+    };
+    // The local declared in the body is in scope of the condition.
+    assert!(matches!(first.body.stmts[..], [Stmt::LocalVar(_)]));
+    assert!(matches!(second.body.stmts[0], Stmt::If(_)));
+
+    assert_eq!(
+        lua51::decompile(REPEAT).expect("failed to decompile"),
+        "repeat\n    local line = read()\nuntil line == nil or line == \"end\"\n\
+         repeat\n    if done then\n        break\n    end\n    step()\nuntil x\n"
+    );
+}
@@ -0,0 +1,61 @@
+//! Round trips through a Lua 4.0 compiler.
+//!
+//! Each source in `tests/sources` is compiled with `luac`, decompiled, and the
+//! decompiled source compiled again. The two chunks must have the same
+//! instructions and constants, so the output is equivalent to the source
+//! without keeping binary fixtures for every case.
+//!
+//! Enabled with `--features luac`. The compiler is found on the path,
+//! or at the `LUAC` environment variable.
+#![cfg(feature = "luac")]
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::write;
+use lua_decompiler::lua40::{self, Decoder, Validator};
+
+fn validator() -> Validator {
+    Validator::new(std::env::var_os("LUAC").unwrap_or_else(|| "luac".into()))
+}
+
+fn decompile(proto: &lua40::Proto) -> String {
+    let syntax = lua40::Parser::new(proto).parse().expect("failed to parse");
+    write(&syntax)
+}
+
+#[test]
+fn test_luac_round_trip() {
+    let validator = validator();
+    let sources = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sources");
+
+    let mut paths: Vec<_> = fs::read_dir(sources)
+        .expect("failed to read sources")
+        .map(|entry| entry.expect("failed to read sources").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lua"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no sources found");
+
+    let mut failures = vec![];
+    for path in &paths {
+        let source = fs::read_to_string(path).expect("failed to read source");
+        let code = validator.compile(&source).expect("failed to compile");
+        let proto = Decoder::new(&code).decode().expect("failed to decode");
+
+        let decompiled = decompile(&proto);
+        let report = validator
+            .validate(&proto, &decompiled)
+            .expect("failed to recompile");
+        if !report.is_ok() {
+            failures.push(format!("{}:\n{decompiled}\n{report}", path.display()));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "decompiled source doesn't compile to the same bytecode:\n\n{}",
+        failures.join("\n")
+    );
+}
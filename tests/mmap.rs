@@ -0,0 +1,26 @@
+//! Scanning memory mapped files.
+#![cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+use std::fs;
+use std::path::Path;
+
+use lua_decompiler::mmap::Mmap;
+use lua_decompiler::scan_chunks;
+
+#[test]
+fn test_mmap() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/callgraph.lua4");
+    // SAFETY: Fixtures aren't written to while the tests run.
+    let data = unsafe { Mmap::open(&path) }.expect("failed to map");
+    assert_eq!(&data[..], fs::read(&path).expect("failed to read"));
+    assert_eq!(scan_chunks(&data).len(), 1);
+}
+
+#[test]
+fn test_empty() {
+    let path = std::env::temp_dir().join(format!("luad-mmap-{}", std::process::id()));
+    fs::write(&path, b"").expect("failed to write");
+    // SAFETY: The file is unique to this process, and isn't written to while mapped.
+    let data = unsafe { Mmap::open(&path) }.expect("failed to map");
+    assert!(data.is_empty());
+    fs::remove_file(&path).expect("failed to remove");
+}
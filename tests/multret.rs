@@ -0,0 +1,90 @@
+//! Multiple assignment, and calls returning multiple results.
+//!
+//! The `swap.lua4` fixture is stripped, and compiled from:
+//!
+//! ```lua
+//! local a, b = 1, 2
+//! x, y = y, x
+//! a, b = b, a
+//! ```
+mod common;
+
+use common::parse;
+use lua_decompiler::lua40::ast::{Expr, Ident, Node, Stmt};
+use lua_decompiler::lua40::Syntax;
+
+const MULTRET: &[u8] = include_bytes!("fixtures/multret.lua4");
+const SWAP: &[u8] = include_bytes!("fixtures/swap.lua4");
+
+fn stmts(syntax: &Syntax) -> Vec<&Stmt> {
+    syntax
+        .root
+        .nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Stmt(stmt) => Some(stmt),
+            _ => None,
+        })
+        .collect()
+}
+
+fn names(exprs: &[Expr]) -> Vec<&str> {
+    exprs
+        .iter()
+        .map(|expr| match expr {
+            Expr::Access(ident) => ident.as_str(),
+            expr => panic!("expected a name, found {expr:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn test_local_call_results() {
+    let syntax = parse(MULTRET);
+    let local_var = stmts(&syntax)
+        .into_iter()
+        .find_map(|stmt| match stmt {
+            Stmt::LocalVar(local_var) => Some(local_var),
+            _ => None,
+        })
+        .expect("no local declaration");
+
+    // Both locals are declared from the one call.
+    assert_eq!(local_var.names, [Ident::new("b"), Ident::new("c")]);
+    let [Expr::Call(call)] = &local_var.rhs[..] else {
+        panic!("expected a single call, found {:?}", local_var.rhs);
+    };
+    // Its arguments are all the results of `g()`.
+    assert!(matches!(&call.args[..], [Expr::Call(_)]));
+}
+
+#[test]
+fn test_single_call_result() {
+    let syntax = parse(MULTRET);
+    let Stmt::Assign(assign) = stmts(&syntax)[1] else {
+        panic!("expected an assignment");
+    };
+    assert_eq!(assign.targets, [Ident::new("x")]);
+    let [Expr::Call(call)] = &assign.rhs[..] else {
+        panic!("expected a single call, found {:?}", assign.rhs);
+    };
+    assert_eq!(call.args.len(), 2);
+}
+
+#[test]
+fn test_swap() {
+    let syntax = parse(SWAP);
+    let assigns: Vec<_> = stmts(&syntax)
+        .into_iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Assign(assign) => Some(assign),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(assigns.len(), 2);
+
+    assert_eq!(assigns[0].targets, [Ident::new("x"), Ident::new("y")]);
+    assert_eq!(names(&assigns[0].rhs), ["y", "x"]);
+    assert_eq!(assigns[1].targets, [Ident::new("a"), Ident::new("b")]);
+    assert_eq!(names(&assigns[1].rhs), ["b", "a"]);
+}
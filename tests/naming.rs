@@ -0,0 +1,106 @@
+//! Strategies for making up the names of locals in stripped chunks.
+mod common;
+
+use common::{stripped, write};
+use lua_decompiler::lua40::{
+    AlphabeticNames, Decoder, IndexedNames, LocalHint, NamingStrategy, Parser, Proto, SlotNames,
+    Type, TypedNames,
+};
+
+const CALLGRAPH: &[u8] = include_bytes!("fixtures/callgraph.lua4");
+const COLLIDE: &[u8] = include_bytes!("fixtures/collide.lua4");
+const MULTRET: &[u8] = include_bytes!("fixtures/multret.lua4");
+
+fn decompile(proto: &Proto, strategy: impl NamingStrategy + 'static) -> String {
+    let syntax = Parser::new(proto)
+        .with_naming(strategy)
+        .parse()
+        .expect("failed to parse");
+    write(&syntax)
+}
+
+#[test]
+fn test_indexed() {
+    let source = decompile(&stripped(CALLGRAPH), IndexedNames::default());
+    assert!(
+        source.starts_with("local local_1 = function(param_1)\n"),
+        "{source}"
+    );
+    assert!(
+        source.contains("add = function(param_2, param_3)\n"),
+        "{source}"
+    );
+    assert!(source.contains("    %local_1(param_2)\n"), "{source}");
+}
+
+#[test]
+fn test_typed() {
+    let source = decompile(&stripped(CALLGRAPH), TypedNames::default());
+    assert!(
+        source.starts_with("local fn1 = function(param1)\n"),
+        "{source}"
+    );
+    assert!(
+        source.contains("add = function(param2, param3)\n"),
+        "{source}"
+    );
+
+    let source = decompile(&stripped(MULTRET), TypedNames::default());
+    assert!(source.contains("local var1, var2 = f(g())\n"), "{source}");
+}
+
+#[test]
+fn test_slot() {
+    let source = decompile(&stripped(MULTRET), SlotNames);
+    assert!(source.contains("local slot0, slot1 = f(g())\n"), "{source}");
+    assert!(source.contains("f(slot0, g(slot1, h()))\n"), "{source}");
+}
+
+/// Names every local after a global of the chunk.
+struct Taken;
+
+impl NamingStrategy for Taken {
+    fn name(&mut self, hint: &LocalHint) -> String {
+        assert_eq!(hint.ty, Type::Unknown);
+        "print".to_string()
+    }
+}
+
+#[test]
+fn test_taken_names() {
+    let source = decompile(&stripped(MULTRET), Taken);
+    assert!(
+        source.contains("local print_2, print_3 = f(g())\n"),
+        "{source}"
+    );
+}
+
+#[test]
+fn test_avoid_used_names() {
+    // The globals `a`, `b` and `c` are used, and `c` only by the nested
+    // function, which names its parameter `d` in the debug information.
+    let proto = Decoder::new(COLLIDE).decode().expect("failed to decode");
+    let source = decompile(&proto, AlphabeticNames::default());
+    assert!(source.starts_with("local e = 1\na = e\n"), "{source}");
+
+    let source = decompile(&stripped(COLLIDE), AlphabeticNames::default());
+    assert!(source.starts_with("local d = 1\na = d\n"), "{source}");
+}
+
+/// Names every local after a keyword.
+struct Keyword;
+
+impl NamingStrategy for Keyword {
+    fn name(&mut self, _hint: &LocalHint) -> String {
+        "end".to_string()
+    }
+}
+
+#[test]
+fn test_avoid_keywords() {
+    let source = decompile(&stripped(COLLIDE), Keyword);
+    assert!(
+        source.starts_with("local local_2 = 1\na = local_2\n"),
+        "{source}"
+    );
+}
@@ -0,0 +1,53 @@
+//! Decompiling nested functions where their closures are created.
+use lua_decompiler::lua40::ast::{Expr, Function, Node, Stmt};
+use lua_decompiler::lua40::{self, Decoder, Parser, ProtoPath};
+
+/// ```text
+/// outer = function()
+///     local b = function(a) return a * 2 end
+///     return b(3)
+/// end
+/// call(function() end)
+/// ```
+const NESTED: &[u8] = include_bytes!("fixtures/nested.lua4");
+
+fn function(expr: &Expr) -> &Function {
+    match expr {
+        Expr::Function(function) => function,
+        expr => panic!("not a function: {expr:?}"),
+    }
+}
+
+#[test]
+fn test_closure_sites() {
+    let proto = Decoder::new(NESTED).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let [Node::Stmt(Stmt::Assign(assign)), Node::Stmt(Stmt::Call(call))] =
+        syntax.root.nodes.as_slice()
+    else {
+        panic!("unexpected statements: {:?}", syntax.root.nodes);
+    };
+
+    let outer = function(&assign.rhs[0]);
+    assert_eq!(outer.path, ProtoPath::from(vec![0]));
+    assert!(outer.params.is_empty());
+    let Some(Node::Stmt(Stmt::LocalVar(local))) = outer.body.nodes.first() else {
+        panic!("unexpected statements: {:?}", outer.body.nodes);
+    };
+    let inner = function(&local.rhs[0]);
+    assert_eq!(inner.path, ProtoPath::from(vec![0, 0]));
+    assert_eq!(inner.params.len(), 1);
+
+    // Passed straight to a call.
+    let argument = function(&call.args[0]);
+    assert_eq!(argument.path, ProtoPath::from(vec![1]));
+    assert!(argument.body.nodes.is_empty());
+}
+
+#[test]
+fn test_decompile() {
+    assert_eq!(
+        lua40::decompile(NESTED).expect("failed to decompile"),
+        "outer = function()\n    local b = function(a)\n        return a * 2\n    end\n    return b(3)\nend\ncall(function()\nend)\n"
+    );
+}
@@ -0,0 +1,51 @@
+//! Number constants in the format declared by the chunk header.
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::lua40::{self, Decoder, NumberType};
+
+const NUM_F32: &[u8] = include_bytes!("fixtures/num_f32.lua4");
+const NUM_I32: &[u8] = include_bytes!("fixtures/num_i32.lua4");
+const NUM_I64: &[u8] = include_bytes!("fixtures/num_i64.lua4");
+
+/// Offset of the test number, after the signature, version, endianness and sizes.
+const TEST_NUMBER_OFFSET: usize = 13;
+
+#[test]
+fn test_number_types() {
+    let cases = [
+        (NUM_F32, NumberType::F32, [0.25, 70000.5]),
+        (NUM_I32, NumberType::I32, [70000.0, 3.0]),
+        (NUM_I64, NumberType::I64, [5000000000.0, 3.0]),
+    ];
+    for (code, number_type, numbers) in cases {
+        let chunk = Decoder::new(code).decode_chunk().expect("failed to decode");
+        assert_eq!(chunk.header().number_type, number_type);
+        assert_eq!(chunk.main().numbers(), numbers);
+    }
+}
+
+#[test]
+fn test_decompile() {
+    assert_eq!(
+        lua40::decompile(NUM_F32).expect("failed to decompile"),
+        "x = 0.25\ny = -70000.5\n"
+    );
+    assert_eq!(
+        lua40::decompile(NUM_I64).expect("failed to decompile"),
+        "x = 5000000000\ny = -3\n"
+    );
+}
+
+#[test]
+fn test_unknown_format() {
+    // Neither the float nor the truncated integer test number.
+    let mut code = NUM_F32.to_vec();
+    code[TEST_NUMBER_OFFSET..TEST_NUMBER_OFFSET + 4].copy_from_slice(&1u32.to_le_bytes());
+    let err = Decoder::new(&code)
+        .decode()
+        .expect_err("decoded an unknown number format");
+    assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+    assert!(
+        err.to_string().contains("unknown 4 byte number format"),
+        "{err}"
+    );
+}
@@ -0,0 +1,84 @@
+//! Naming the parameters of stripped functions after the arguments they're passed as.
+mod common;
+
+use common::{stripped, write};
+use lua_decompiler::lua40::{Decoder, ParamNames, Parser, Proto};
+
+const CALLGRAPH: &[u8] = include_bytes!("fixtures/callgraph.lua4");
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+
+fn decompile(proto: &Proto, param_names: &ParamNames) -> String {
+    let syntax = Parser::new(proto)
+        .with_param_names(param_names)
+        .parse()
+        .expect("failed to parse");
+    write(&syntax)
+}
+
+/// The callgraph fixture with its call to `print` patched to call
+/// `floor`, which has a name of the same length.
+fn floor_callgraph() -> Vec<u8> {
+    let mut code = CALLGRAPH.to_vec();
+    let start = code
+        .windows(5)
+        .position(|window| window == b"print")
+        .expect("no print in fixture");
+    code[start..start + 5].copy_from_slice(b"floor");
+    code
+}
+
+#[test]
+fn test_library() {
+    let source = decompile(&stripped(&floor_callgraph()), &ParamNames::library());
+    assert!(source.contains("= function(x)\n    floor(x)\n"), "{source}");
+    // Parameters only used in arithmetic, or passed to locals, keep made up names.
+    assert!(source.contains("add = function(b, c)\n"), "{source}");
+}
+
+#[test]
+fn test_custom_table() {
+    let table = ParamNames::parse("-- arguments of print\n\nprint 1 = text\nprint 2 = more\n")
+        .expect("failed to parse table");
+    let source = decompile(&stripped(CALLGRAPH), &table);
+    assert!(
+        source.contains("= function(text)\n    print(text)\n"),
+        "{source}"
+    );
+
+    // Arguments are named by position, and `arg` isn't a parameter.
+    let source = decompile(&stripped(PARAMS), &table);
+    assert!(source.contains("h = function(text, ...)\n"), "{source}");
+}
+
+#[test]
+fn test_debug_names_win() {
+    let proto = Decoder::new(CALLGRAPH).decode().expect("failed to decode");
+    let table = ParamNames::new().with_argument("print", 1, "text");
+    let source = decompile(&proto, &table);
+    assert!(source.contains("function(message)\n"), "{source}");
+}
+
+#[test]
+fn test_taken_names_are_numbered() {
+    let table = ParamNames::new()
+        .with_argument("print", 1, "add")
+        .with_table(ParamNames::new().with_argument("print", 1, "print"));
+    let source = decompile(&stripped(CALLGRAPH), &table);
+    assert!(source.contains("= function(print_2)\n"), "{source}");
+}
+
+#[test]
+fn test_parse_errors() {
+    for table in [
+        "strlen = s",
+        "strlen 0 = s",
+        "strlen 1 = and",
+        "strlen one = s",
+    ] {
+        let err = ParamNames::parse(table).expect_err(table);
+        assert!(err.to_string().contains("line 1"), "{err}");
+    }
+    let table = ParamNames::parse("strlen 1 = str").expect("failed to parse table");
+    assert_eq!(table.argument("strlen", 1), Some("str"));
+    assert_eq!(table.argument("strlen", 2), None);
+}
@@ -0,0 +1,141 @@
+//! Transforming syntax trees between parsing and writing the source.
+mod common;
+
+use common::{parse, write};
+use lua_decompiler::lua40::ast::{
+    Assign, BinExpr, BinOp, Block, Comment, Expr, Ident, IfBlock, Node, Stmt, Syntax,
+};
+use lua_decompiler::lua40::{
+    self, FlattenConcat, PassManager, ProtoPath, RenameGlobals, SimplifyConditions,
+};
+use lua_decompiler::SymbolTable;
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+
+#[test]
+fn test_rename_globals() {
+    let rename = RenameGlobals::parse("-- from the manual\nprint = echo\n\na = b\n")
+        .expect("invalid mapping");
+    let mut syntax = parse(UPVALUE);
+    PassManager::new()
+        .with_pass(rename)
+        .run(&mut syntax)
+        .expect("pass failed");
+
+    // The local `a` isn't renamed, but the global captured by `%print` is.
+    assert_eq!(
+        write(&syntax),
+        "local a = 1\nf = function()\n    echo(%a)\nend\ng = function()\n    echo(%echo)\nend\n"
+    );
+}
+
+#[test]
+fn test_rename_globals_to_local() {
+    let rename = RenameGlobals::parse("print = x").expect("invalid mapping");
+    let mut syntax = parse(HELLO);
+    let result = PassManager::new().with_pass(rename).run(&mut syntax);
+    assert!(result.is_err(), "`x` is a local where `print` is called");
+}
+
+#[test]
+fn test_rename_globals_invalid_mapping() {
+    assert!(RenameGlobals::parse("print echo").is_err());
+    assert!(RenameGlobals::parse("print = end").is_err());
+}
+
+#[test]
+fn test_simplify_conditions() {
+    // if not (not x) then end
+    let mut syntax = Syntax {
+        root: Block {
+            nodes: vec![Node::Stmt(Stmt::If(Box::new(IfBlock {
+                head: Expr::logical_not(Expr::logical_not(Expr::Access(Ident::new("x")))),
+                then: Block::default(),
+                else_: None,
+            })))],
+            spans: vec![],
+        },
+        path: ProtoPath::main(),
+        symbols: SymbolTable::new(),
+    };
+    assert_eq!(write(&syntax), "if not (not x) then\nend\n");
+
+    PassManager::new()
+        .with_pass(SimplifyConditions)
+        .run(&mut syntax)
+        .expect("pass failed");
+    assert_eq!(write(&syntax), "if x then\nend\n");
+}
+
+fn concat(lhs: Expr, rhs: Expr) -> Expr {
+    Expr::Binary(Box::new(BinExpr {
+        op: BinOp::Concat,
+        lhs,
+        rhs,
+    }))
+}
+
+fn global(name: &str) -> Expr {
+    Expr::Access(Ident::new(name))
+}
+
+#[test]
+fn test_flatten_concat() {
+    // s = ((a .. b) .. (c .. d)) .. (e + f)
+    let value = concat(
+        concat(
+            concat(global("a"), global("b")),
+            concat(global("c"), global("d")),
+        ),
+        Expr::Binary(Box::new(BinExpr {
+            op: BinOp::Add,
+            lhs: global("e"),
+            rhs: global("f"),
+        })),
+    );
+    let mut syntax = Syntax {
+        root: Block {
+            nodes: vec![Node::Stmt(Stmt::Assign(Box::new(Assign {
+                targets: vec![Ident::new("s")],
+                rhs: vec![value],
+            })))],
+            spans: vec![],
+        },
+        path: ProtoPath::main(),
+        symbols: SymbolTable::new(),
+    };
+    assert_eq!(write(&syntax), "s = ((a .. b) .. c .. d) .. e + f\n");
+
+    PassManager::new()
+        .with_pass(FlattenConcat)
+        .run(&mut syntax)
+        .expect("pass failed");
+    assert_eq!(write(&syntax), "s = a .. b .. c .. d .. e + f\n");
+}
+
+#[test]
+fn test_comments() {
+    let mut syntax = parse(HELLO);
+    let block = &mut syntax.root;
+    block.insert_comment(0, Comment::new("greeting\n\nfrom the manual"));
+    block.insert_comment(2, Comment::trailing("seven"));
+    block.insert_comment(3, Comment::trailing("lucky"));
+    block.insert_comment(5, Comment::new("the end"));
+    assert_eq!(block.nodes.len(), block.spans.len());
+    assert_eq!(
+        write(&syntax),
+        "-- greeting\n--\n-- from the manual\nlocal x = 7  -- seven  -- lucky\nprint(\"hello\", x)\n-- the end\n"
+    );
+
+    let mut buf = String::new();
+    lua40::Scribe::default()
+        .group_locals(true)
+        .annotate(true)
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(
+        buf,
+        "local x\n-- greeting  -- [1]\n--\n-- from the manual\nx = 7  -- seven  -- lucky  -- [1]\nprint(\"hello\", x)  -- [2-5]\n-- the end  -- [2-5]\n"
+    );
+}
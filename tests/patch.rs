@@ -0,0 +1,32 @@
+//! Patching constants in a decoded chunk and encoding it again.
+mod common;
+
+use common::decompile;
+use lua_decompiler::lstring::LuaString;
+use lua_decompiler::lua40::Decoder;
+
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+#[test]
+fn test_patch_string() {
+    let mut chunk = Decoder::new(HELLO_LE)
+        .decode_chunk()
+        .expect("failed to decode");
+    let old = chunk
+        .patch_string(&[], 1, "goodbye")
+        .expect("failed to patch");
+    assert_eq!(old, LuaString::from("hello"));
+
+    let code = chunk.encode().expect("failed to encode");
+    assert_eq!(decompile(&code), "local x = 7\nprint(\"goodbye\", x)\n");
+}
+
+#[test]
+fn test_patch_out_of_range() {
+    let mut chunk = Decoder::new(HELLO_LE)
+        .decode_chunk()
+        .expect("failed to decode");
+    assert!(chunk.patch_string(&[], 2, "goodbye").is_err());
+    assert!(chunk.patch_string(&[0], 0, "goodbye").is_err());
+    assert!(chunk.patch_number(&[], 0, 1.0).is_err());
+}
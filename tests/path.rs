@@ -0,0 +1,128 @@
+//! Addressing nested functions by path.
+mod common;
+
+use common::write;
+use lua_decompiler::lua40::{Constant, Decoder, Parser, ProtoPath, RenameMap};
+
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+const UNARY: &[u8] = include_bytes!("fixtures/unary.lua4");
+
+fn decompile_function(code: &[u8], path: &str, renames: &RenameMap) -> String {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let path = path.parse().expect("invalid path");
+    let syntax = Parser::for_function(&proto, &path)
+        .expect("no function")
+        .with_renames(renames)
+        .parse_standalone()
+        .expect("failed to parse");
+    write(&syntax)
+}
+
+#[test]
+fn test_proto_path_parse() {
+    let path: ProtoPath = "main/2/0".parse().expect("invalid path");
+    assert_eq!(*path, [2, 0]);
+    assert_eq!(path.to_string(), "main.2.0");
+    assert_eq!("main.2.0".parse::<ProtoPath>().ok(), Some(path.clone()));
+    assert_eq!("2/0".parse::<ProtoPath>().ok(), Some(path.clone()));
+    assert_eq!(path.parent(), Some(ProtoPath::from(vec![2])));
+    assert_eq!(
+        path.parent().and_then(|p| p.parent()),
+        Some(ProtoPath::main())
+    );
+    assert!("main".parse::<ProtoPath>().is_ok_and(|p| p.is_main()));
+
+    for bad in ["main/", "main2", "main/x", "main//1", "/1", "main.1."] {
+        assert!(bad.parse::<ProtoPath>().is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn test_iter_protos() {
+    let chunk = Decoder::new(PARAMS)
+        .decode_chunk()
+        .expect("failed to decode");
+    let paths: Vec<_> = chunk
+        .main()
+        .iter_protos()
+        .map(|(path, proto)| (path.to_string(), proto.num_params()))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            ("main".to_string(), 0),
+            ("main.0".to_string(), 2),
+            ("main.1".to_string(), 1)
+        ]
+    );
+
+    for (path, proto) in chunk.main().iter_protos() {
+        let found = chunk.get_proto(&path).expect("function not found");
+        assert!(std::ptr::eq(found, proto));
+    }
+    assert!(chunk.get_proto(&ProtoPath::from(vec![2])).is_none());
+}
+
+#[test]
+fn test_constants() {
+    let chunk = Decoder::new(UPVALUE)
+        .decode_chunk()
+        .expect("failed to decode");
+    let strings: Vec<_> = chunk
+        .constants()
+        .map(|pooled| match pooled.constant {
+            Constant::String(string) => format!("{} {} {string}", pooled.path, pooled.index),
+            _ => panic!("unexpected constant {pooled:?}"),
+        })
+        .collect();
+    assert_eq!(
+        strings,
+        [
+            "main 0 f",
+            "main 1 print",
+            "main 2 g",
+            "main.0 0 print",
+            "main.1 0 print"
+        ]
+    );
+
+    let chunk = Decoder::new(UNARY)
+        .decode_chunk()
+        .expect("failed to decode");
+    let numbers: Vec<_> = chunk
+        .constants()
+        .filter_map(|pooled| match pooled.constant {
+            Constant::Number(number) => Some((pooled.index, number)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(numbers, [(0, 1.5), (1, 2.5)]);
+}
+
+#[test]
+fn test_decompile_function() {
+    let renames = RenameMap::new();
+    assert_eq!(
+        decompile_function(PARAMS, "main/1", &renames),
+        "local function_1 = function(a, ...)\n    print(a, arg)\nend\n"
+    );
+    assert_eq!(
+        decompile_function(UPVALUE, "main/1", &renames),
+        "local function_1 = function()\n    print(%print)\nend\n"
+    );
+
+    // Locals of the stripped main function aren't named without decompiling it.
+    assert_eq!(
+        decompile_function(UPVALUE, "main/0", &renames),
+        "local function_0 = function()\n    print(%upvalue_0)\nend\n"
+    );
+    let renames = RenameMap::new().with_local(&[], 0, "count");
+    assert_eq!(
+        decompile_function(UPVALUE, "main/0", &renames),
+        "local function_0 = function()\n    print(%count)\nend\n"
+    );
+
+    let proto = Decoder::new(PARAMS).decode().expect("failed to decode");
+    assert!(Parser::for_function(&proto, &ProtoPath::from(vec![0, 0])).is_err());
+}
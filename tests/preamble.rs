@@ -0,0 +1,55 @@
+//! Skipping bytes before the chunk header, like a `#!` line.
+use std::io::Cursor;
+
+use lua_decompiler::find_chunk_start;
+use lua_decompiler::lua40::Decoder;
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+fn with_preamble(preamble: &[u8]) -> Vec<u8> {
+    let mut code = preamble.to_vec();
+    code.extend_from_slice(HELLO);
+    code
+}
+
+#[test]
+fn test_skip_preamble() {
+    let expected = Decoder::new(HELLO).decode().expect("failed to decode");
+    let code = with_preamble(b"#!/usr/bin/env lua\n");
+
+    assert!(Decoder::new(&code).decode().is_err());
+    // Read from a stream, where the skipped bytes can't be put back.
+    let proto = Decoder::from_reader(Cursor::new(&code))
+        .with_preamble(64)
+        .decode()
+        .expect("failed to decode");
+    assert_eq!(proto.code(), expected.code());
+    assert_eq!(proto.strings(), expected.strings());
+
+    assert_eq!(find_chunk_start(&code, 64), Some(19));
+    assert_eq!(find_chunk_start(HELLO, 64), Some(0));
+}
+
+#[test]
+fn test_false_start() {
+    // Bytes that start like the signature, but don't finish it.
+    let code = with_preamble(b"\x1bLu\x1b\x1bL");
+    let proto = Decoder::new(&code).with_preamble(8).decode();
+    assert!(proto.is_ok(), "{proto:?}");
+    assert_eq!(find_chunk_start(&code, 8), Some(6));
+}
+
+#[test]
+fn test_preamble_too_long() {
+    let code = with_preamble(&[b'#'; 20]);
+    let err = Decoder::new(&code)
+        .with_preamble(19)
+        .decode()
+        .expect_err("skipped more than allowed");
+    assert!(err
+        .to_string()
+        .contains("no chunk signature after skipping up to 19 bytes"));
+    assert!(Decoder::new(&code).with_preamble(20).decode().is_ok());
+    assert_eq!(find_chunk_start(&code, 19), None);
+    assert_eq!(find_chunk_start(&code, 20), Some(20));
+}
@@ -0,0 +1,114 @@
+//! Parenthesizing expressions only where needed to keep the evaluation order.
+//!
+//! The fixture is compiled from:
+//!
+//! ```lua
+//! x = (a + b) * c
+//! y = a - (b - c)
+//! z = a ^ b ^ c
+//! w = (a ^ b) ^ c
+//! s = a .. b .. c
+//! t = x - 1
+//! u = a + b * c
+//! ```
+//!
+//! The unary fixture is compiled from:
+//!
+//! ```lua
+//! x = -a
+//! y = not a
+//! z = -(a + b)
+//! w = -a ^ b
+//! v = (-a) ^ b
+//! u = -1.5
+//! t = (-1.5) ^ 2
+//! s = 2.5 * -a
+//! r = -(-a)
+//! ```
+mod common;
+
+use common::decompile;
+use lua_decompiler::lua40::ast::{
+    BinExpr, BinOp, Block, Call, Expr, Ident, IfBlock, Lit, Node, Stmt, Syntax,
+};
+use lua_decompiler::lua40::{self, ProtoPath};
+use lua_decompiler::style::ScribeConfig;
+use lua_decompiler::SymbolTable;
+
+const PRECEDENCE: &[u8] = include_bytes!("fixtures/precedence.lua4");
+const UNARY: &[u8] = include_bytes!("fixtures/unary.lua4");
+
+#[test]
+fn test_precedence() {
+    assert_eq!(
+        decompile(PRECEDENCE),
+        "x = (a + b) * c\n\
+         y = a - (b - c)\n\
+         z = a ^ b ^ c\n\
+         w = (a ^ b) ^ c\n\
+         s = a .. b .. c\n\
+         t = x - 1\n\
+         u = a + b * c\n"
+    );
+}
+
+#[test]
+fn test_unary() {
+    assert_eq!(
+        decompile(UNARY),
+        "x = -a\n\
+         y = not a\n\
+         z = -(a + b)\n\
+         w = -a ^ b\n\
+         v = (-a) ^ b\n\
+         u = -1.5\n\
+         t = (-1.5) ^ 2\n\
+         s = 2.5 * -a\n\
+         r = -(-a)\n"
+    );
+}
+
+#[test]
+fn test_conditions() {
+    let name = |name| Expr::Access(Ident::new(name));
+    let binary = |op, lhs, rhs| Expr::Binary(Box::new(BinExpr { op, lhs, rhs }));
+    // Any expression can be tested, like a call with arithmetic in its arguments.
+    let call = Expr::Call(Box::new(Call {
+        name: name("f"),
+        args: vec![binary(BinOp::Add, name("d"), Expr::Literal(Lit::Int(1)))],
+    }));
+    let head = binary(
+        BinOp::And,
+        binary(BinOp::Or, name("a"), Expr::logical_not(name("b"))),
+        binary(BinOp::Lt, name("c"), call),
+    );
+    let syntax = Syntax {
+        root: Block {
+            nodes: vec![Node::Stmt(Stmt::If(Box::new(IfBlock {
+                head,
+                then: Block::default(),
+                else_: None,
+            })))],
+            spans: vec![],
+        },
+        path: ProtoPath::main(),
+        symbols: SymbolTable::new(),
+    };
+
+    let mut buf = String::new();
+    lua40::Scribe::default()
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(buf, "if (a or not b) and c < f(d + 1) then\nend\n");
+
+    // Keywords are spaced even with compact operators.
+    let config = ScribeConfig {
+        operator_spaces: false,
+        ..ScribeConfig::default()
+    };
+    let mut buf = String::new();
+    lua40::Scribe::new(config)
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(buf, "if (a or not b) and c<f(d+1) then\nend\n");
+}
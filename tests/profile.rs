@@ -0,0 +1,60 @@
+//! Decoding chunks from engines with modified headers.
+use lua_decompiler::lua40::{self, Decoder, HeaderProfile};
+
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+/// The hello fixture as a modified engine would write it.
+fn modified() -> Vec<u8> {
+    let mut code = HELLO_LE.to_vec();
+    code[1..4].copy_from_slice(b"Gam");
+    code[4] = 0x41;
+    // Size of `B` left out, as the engine always uses 9 bits.
+    code[11] = 0;
+    code[13..21].copy_from_slice(&42.0f64.to_le_bytes());
+    code
+}
+
+fn profile() -> HeaderProfile {
+    HeaderProfile {
+        signature: b"Gam".to_vec(),
+        versions: vec![0x40, 0x41],
+        size_b: Some(9),
+        test_number: 42.0,
+        ..HeaderProfile::default()
+    }
+}
+
+#[test]
+fn test_header_profile() {
+    let proto = Decoder::new(&modified())
+        .with_profile(profile())
+        .decode()
+        .expect("failed to decode");
+    let syntax = lua40::Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    lua40::Scribe::default()
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(buf, "local x = 7\nprint(\"hello\", x)\n");
+}
+
+#[test]
+fn test_header_profile_mismatch() {
+    // The reference profile rejects the modified chunk.
+    assert!(Decoder::new(&modified()).decode().is_err());
+
+    // And the modified profile rejects reference chunks.
+    let err = Decoder::new(HELLO_LE)
+        .with_profile(profile())
+        .decode()
+        .expect_err("signature should not match");
+    assert!(err.to_string().contains("bad signature"), "{err}");
+
+    let mut code = modified();
+    code[4] = 0x50;
+    let err = Decoder::new(&code)
+        .with_profile(profile())
+        .decode()
+        .expect_err("version should not be accepted");
+    assert!(err.to_string().contains("unexpected version: 50"), "{err}");
+}
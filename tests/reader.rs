@@ -0,0 +1,36 @@
+//! Decoding chunks from readers instead of byte slices.
+use std::fs::File;
+use std::io::{BufReader, Cursor, Seek, SeekFrom};
+
+use lua_decompiler::lua40::Decoder;
+
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+#[test]
+fn test_decode_file() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/hello_le.lua4");
+    let file = BufReader::new(File::open(path).expect("failed to open fixture"));
+    let proto = Decoder::from_reader(file)
+        .decode()
+        .expect("failed to decode");
+    assert_eq!(
+        proto.code(),
+        Decoder::new(HELLO_LE).decode().unwrap().code()
+    );
+}
+
+#[test]
+fn test_decode_embedded() {
+    let mut archive = b"archive header".to_vec();
+    let offset = archive.len() as u64;
+    archive.extend_from_slice(HELLO_LE);
+    archive.extend_from_slice(b"trailing data");
+
+    let mut reader = Cursor::new(archive.as_slice());
+    reader.seek(SeekFrom::Start(offset)).unwrap();
+    let proto = Decoder::from_reader(&mut reader)
+        .decode()
+        .expect("failed to decode");
+    assert_eq!(proto.instrs().len(), 6);
+    assert_eq!(reader.position(), offset + HELLO_LE.len() as u64);
+}
@@ -0,0 +1,151 @@
+//! Naming globals and locals with a rename map.
+mod common;
+
+use common::write;
+use lua_decompiler::errors::{Error, ErrorKind};
+use lua_decompiler::lua40::{Decoder, Parser, RenameMap};
+
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+
+fn decompile(code: &[u8], renames: &RenameMap) -> String {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto)
+        .with_renames(renames)
+        .parse()
+        .expect("failed to parse");
+    write(&syntax)
+}
+
+#[test]
+fn test_rename_map() {
+    let renames = RenameMap::new()
+        .with_global("print", "echo")
+        .with_local(&[], 0, "count");
+    assert_eq!(
+        decompile(UPVALUE, &renames),
+        "local count = 1\nf = function()\n    echo(%count)\nend\ng = function()\n    echo(%echo)\nend\n"
+    );
+}
+
+#[test]
+fn test_rename_map_json() {
+    let renames = RenameMap::from_json(
+        r#"{
+            "globals": { "h": "hook" },
+            "locals": { "main.1": { "0": "value" }, "0": { "1": "b" } }
+        }"#,
+    )
+    .expect("invalid rename map");
+    assert_eq!(
+        decompile(PARAMS, &renames),
+        "add = function(x, b)\n    return x + b\nend\nhook = function(value, ...)\n    print(value, arg)\nend\n"
+    );
+}
+
+#[test]
+fn test_rename_map_toml() {
+    let renames = RenameMap::from_toml(
+        "# Annotated by hand\n[globals]\nadd = \"sum\"\n\n[locals.\"main.1\"]\n0 = \"value\"\n",
+    )
+    .expect("invalid rename map");
+    assert_eq!(renames.global("add"), Some("sum"));
+    assert_eq!(renames.local(&[1], 0), Some("value"));
+    assert_eq!(renames.local(&[1], 1), None);
+}
+
+#[test]
+fn test_rename_map_json_escapes() {
+    // Global names can be any string, even ones beyond the BMP.
+    let renames = RenameMap::from_json(
+        r#"{"globals": {"a\b\f\u00e9\ud83d\ude00\/": "b"}, "extra": [1, -2.5e3, true, null]}"#,
+    );
+    let renames = renames.expect_err("unknown key");
+    assert_eq!(
+        renames.to_string(),
+        "rename map error: unknown section `extra`"
+    );
+
+    let renames = RenameMap::from_json(r#"{"globals": {"a\b\f\u00e9\ud83d\ude00\/": "b"}}"#)
+        .expect("invalid rename map");
+    assert_eq!(renames.global("a\u{8}\u{c}\u{e9}\u{1f600}/"), Some("b"));
+}
+
+#[test]
+fn test_rename_map_toml_comments() {
+    let renames = RenameMap::from_toml(
+        "[globals] # from the manual\nadd = \"sum\"  # adds\n\"a#b\" = \"c\"\n",
+    )
+    .expect("invalid rename map");
+    assert_eq!(renames.global("add"), Some("sum"));
+    assert_eq!(renames.global("a#b"), Some("c"));
+}
+
+#[test]
+fn test_rename_map_pairs() {
+    let renames = RenameMap::from_pairs("-- from the manual\nprint = echo\n\na = b\n")
+        .expect("invalid mapping");
+    assert_eq!(renames.global("print"), Some("echo"));
+    assert_eq!(renames.global("a"), Some("b"));
+
+    // Pairs replace the names of the same globals in other maps.
+    let renames = RenameMap::new()
+        .with_global("print", "write")
+        .with_local(&[], 0, "count")
+        .merge(renames);
+    assert_eq!(renames.global("print"), Some("echo"));
+    assert_eq!(renames.local(&[], 0), Some("count"));
+}
+
+fn rename_map_error(result: Result<RenameMap, Error>) -> String {
+    let err = result.expect_err("invalid rename map");
+    assert!(matches!(err.kind(), ErrorKind::RenameMap(_)), "{err}");
+    err.to_string()
+}
+
+#[test]
+fn test_rename_map_invalid_values() {
+    assert_eq!(
+        rename_map_error(RenameMap::from_json(r#"{"globals": {"a": 1}}"#)),
+        "rename map error: expected a string for `a`, found a number"
+    );
+    assert_eq!(
+        rename_map_error(RenameMap::from_json(r#"{"globals": ["a"]}"#)),
+        "rename map error: expected an object for globals, found an array"
+    );
+    assert_eq!(
+        rename_map_error(RenameMap::from_json(r#"{"globals": {"a": null}}"#)),
+        "rename map error: expected a string for `a`, found null"
+    );
+    assert_eq!(
+        rename_map_error(RenameMap::from_json(r#"{"globals": {"\ud83d": "a"}}"#)),
+        "rename map error: unpaired surrogate in unicode escape at byte 20"
+    );
+    assert!(RenameMap::from_json(r#"{"globals": {"\ude00": "a"}}"#).is_err());
+    assert!(RenameMap::from_json(r#"{"globals": {"a": 01x}}"#).is_err());
+
+    // TOML values other than basic strings are rejected.
+    for value in ["'b'", "1", "[\"b\"]", "\"\"\"b\"\"\"", "\"\\u0062\""] {
+        let text = format!("[globals]\na = {value}\n");
+        assert_eq!(
+            rename_map_error(RenameMap::from_toml(&text)),
+            "rename map error: line 2: expected a basic string value without escapes",
+            "{value}"
+        );
+    }
+    assert_eq!(
+        rename_map_error(RenameMap::from_pairs("print echo")),
+        "rename map error: line 1: expected `old = new` names, found `print echo`"
+    );
+    assert!(RenameMap::from_pairs("print = end").is_err());
+}
+
+#[test]
+fn test_rename_map_invalid() {
+    assert!(RenameMap::from_json(r#"{"globals": {"a": "not a name"}}"#).is_err());
+    assert!(RenameMap::from_json(r#"{"locals": {"main.x": {"0": "a"}}}"#).is_err());
+    assert!(RenameMap::from_json(r#"{"names": {}}"#).is_err());
+    assert!(RenameMap::from_json(r#"{"globals": {"a": "b"}"#).is_err());
+    assert!(RenameMap::from_toml("a = \"b\"").is_err());
+    assert!(RenameMap::from_toml("[globals]\na = b").is_err());
+}
@@ -0,0 +1,39 @@
+//! Finding chunks embedded in larger files.
+use lua_decompiler::{decode_any, find_chunks, scan_chunks, LuaVersion};
+
+const HELLO_BE: &[u8] = include_bytes!("fixtures/hello_be.lua4");
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+#[test]
+fn test_find_chunks() {
+    let mut data = b"header".to_vec();
+    // Signature without a valid header.
+    data.extend_from_slice(b"\x1bLua\x40junk");
+    let first = data.len();
+    data.extend_from_slice(HELLO_LE);
+    data.extend_from_slice(b"padding");
+    let second = data.len();
+    data.extend_from_slice(HELLO_BE);
+    // Truncated chunk.
+    data.extend_from_slice(&HELLO_LE[..20]);
+
+    let ranges = find_chunks(&data);
+    assert_eq!(
+        ranges,
+        vec![
+            first..first + HELLO_LE.len(),
+            second..second + HELLO_BE.len()
+        ]
+    );
+    for range in ranges {
+        assert!(decode_any(&data[range]).is_ok());
+    }
+}
+
+#[test]
+fn test_scan_versions() {
+    let chunks = scan_chunks(HELLO_LE);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].version, LuaVersion::Lua40);
+    assert_eq!(chunks[0].range, 0..HELLO_LE.len());
+}
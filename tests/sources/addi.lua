@@ -0,0 +1,5 @@
+local i = 0
+i = i + 1
+i = i - 1
+x = i + 200
+y = (i + 1) * 2
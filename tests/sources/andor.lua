@@ -0,0 +1,9 @@
+x = a or b
+x = a and b
+x = a or b or c
+x = a and b or c
+x = a or (b and c)
+x = (a or b) and c
+print(a or "default")
+x = a < b and c or b
+x = f(a or b) or c
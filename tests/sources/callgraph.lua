@@ -0,0 +1,8 @@
+local say = function(message)
+    print(message)
+end
+add = function(a, b)
+    %say(a)
+    return a + b
+end
+say(add(1, 2))
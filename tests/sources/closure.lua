@@ -0,0 +1,5 @@
+local a = 1
+f = function() print(%a) end
+g = function() print(%print) end
+add = function(x, y) return x + y end
+h = function(a, ...) print(a, arg) end
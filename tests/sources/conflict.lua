@@ -0,0 +1,2 @@
+x = (not a) + 1
+z = function() end .. "s"
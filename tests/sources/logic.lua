@@ -0,0 +1,26 @@
+if a < b and c then
+    x = 1
+end
+if a or b then
+    x = 2
+end
+if a and b then
+    x = 3
+else
+    x = 4
+end
+while a and b ~= c do
+    x = 5
+end
+repeat
+    x = 6
+until a or b
+if a and b or c then
+    x = 7
+end
+if a and (b or c) then
+    x = 8
+end
+if a and f(b) then
+    x = 9
+end
@@ -0,0 +1,10 @@
+print(f(g()))
+x = f(a, g())
+local a, b = f(g())
+f(a, g(b, h()))
+ret = function(...)
+    return g(1, h())
+end
+pair = function(a)
+    return a, f(a)
+end
@@ -0,0 +1,7 @@
+x = (a + b) * c
+y = a - (b - c)
+z = a ^ b ^ c
+w = (a ^ b) ^ c
+s = a .. b .. c
+t = x - 1
+u = a + b * c
@@ -0,0 +1,7 @@
+x = a < b
+f(a ~= b)
+x = a < b and b < c
+x = a <= b or b >= c
+if c then
+    x = a == b
+end
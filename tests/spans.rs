@@ -0,0 +1,66 @@
+//! Spans of the instructions each syntax node was decoded from.
+use lua_decompiler::lua40::ast::{Expr, Node, Span, Stmt};
+use lua_decompiler::lua40::{self, Decoder, ProtoPath};
+
+const LINES: &[u8] = include_bytes!("fixtures/lines.lua4");
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+
+#[test]
+fn test_block_spans() {
+    let proto = Decoder::new(LINES).decode().expect("failed to decode");
+    let syntax = lua40::Parser::new(&proto).parse().expect("failed to parse");
+    assert!(syntax.path.is_main());
+
+    let spans: Vec<Span> = syntax
+        .root
+        .iter_spanned()
+        .map(|(_, span)| *span.expect("node without span"))
+        .collect();
+    assert_eq!(spans.len(), 4);
+    assert_eq!((spans[2].start, spans[2].end), (4, 5));
+    assert_eq!(spans[2].lines(), Some((4, 4)));
+    assert!(spans[2].contains(5));
+    assert!(!spans[2].contains(6));
+    assert_eq!(syntax.root.span(3), Some(&spans[3]));
+    assert_eq!(syntax.root.span(4), None);
+
+    let mut stripped = proto;
+    stripped.strip();
+    let syntax = lua40::Parser::new(&stripped)
+        .parse()
+        .expect("failed to parse");
+    assert_eq!(syntax.root.span(0).and_then(Span::lines), None);
+}
+
+#[test]
+fn test_function_paths() {
+    let proto = Decoder::new(PARAMS).decode().expect("failed to decode");
+    let syntax = lua40::Parser::new(&proto).parse().expect("failed to parse");
+
+    let paths: Vec<String> = syntax
+        .root
+        .nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Stmt(Stmt::Assign(assign)) => match assign.rhs.first() {
+                Some(Expr::Function(function)) => Some(function.path.to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    assert_eq!(paths, ["main.0", "main.1"]);
+
+    // Standalone functions are placed at their closure, in the enclosing function.
+    let path: ProtoPath = "main.1".parse().unwrap();
+    let syntax = lua40::Parser::for_function(&proto, &path)
+        .expect("no such function")
+        .parse_standalone()
+        .expect("failed to parse");
+    assert!(syntax.path.is_main());
+    let syntax = lua40::Parser::for_function(&proto, &path)
+        .expect("no such function")
+        .parse()
+        .expect("failed to parse");
+    assert_eq!(syntax.path, path);
+}
@@ -0,0 +1,52 @@
+//! Summary statistics of chunks.
+use lua_decompiler::lua40::{Coverage, Decoder, Opcode};
+
+const PARAMS: &[u8] = include_bytes!("fixtures/params.lua4");
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const TABLE: &[u8] = include_bytes!("fixtures/table.lua4");
+
+#[test]
+fn test_stats() {
+    let proto = Decoder::new(PARAMS).decode().expect("failed to decode");
+    let stats = proto.stats();
+    assert_eq!(stats.functions, 3);
+    assert_eq!(stats.instructions, 15);
+    assert_eq!(stats.strings, 3);
+    assert_eq!(stats.numbers, 0);
+    assert_eq!(stats.max_stack, 10);
+    assert_eq!(stats.depth, 1);
+    assert_eq!(stats.lines, None);
+    assert_eq!(stats.opcodes[&Opcode::GetLocal], 4);
+    assert_eq!(stats.opcodes[&Opcode::Closure], 2);
+    assert_eq!(stats.opcodes.values().sum::<usize>(), stats.instructions);
+}
+
+#[test]
+fn test_coverage() {
+    let mut coverage = Coverage::new();
+    for code in [PARAMS, HELLO, TABLE] {
+        let proto = Decoder::new(code).decode().expect("failed to decode");
+        coverage.add(&proto);
+    }
+    assert_eq!(coverage.chunks, 3);
+    assert_eq!(coverage.supported_chunks, 3);
+    assert_eq!(coverage.unsupported().count(), 0);
+
+    let end = coverage.opcodes[&Opcode::End];
+    assert_eq!(end.chunks, 3);
+    let push_int = coverage.opcodes[&Opcode::PushInt];
+    assert_eq!(push_int.chunks, 2);
+    assert_eq!(push_int.instructions, 201);
+
+    let table = coverage.to_string();
+    assert!(table.contains("SETLIST"));
+    assert!(table.ends_with("3 of 3 chunks only use supported opcodes\n"));
+}
+
+#[test]
+fn test_opcode_supported() {
+    assert!(Opcode::PushInt.is_supported());
+    assert!(Opcode::JumpOnTrue.is_supported());
+    assert!(!Opcode::GetDotted.is_supported());
+    assert!(!Opcode::ForPrep.is_supported());
+}
@@ -0,0 +1,63 @@
+//! Recovering loops and conditionals from the control flow graph.
+use lua_decompiler::diagnostics::Severity;
+use lua_decompiler::lua40::ast::{Node, Stmt};
+use lua_decompiler::lua40::{self, check_syntax, Decoder, Parser};
+
+/// A `while` loop with a `break`, a `while` nested in a `repeat`,
+/// and an `if` with `elseif` and `else` branches.
+const LOOPS: &[u8] = include_bytes!("fixtures/loops.lua4");
+/// Jumps into the middle of a loop, which no statement compiles to.
+const GOTO: &[u8] = include_bytes!("fixtures/goto.lua4");
+
+#[test]
+fn test_loops() {
+    let source = lua40::decompile(LOOPS).expect("failed to decompile");
+    assert_eq!(
+        source,
+        "while a do\n    if b then\n        break\n    end\n    x = 1\nend\n\
+         repeat\n    while c do\n        y = 2\n    end\nuntil d\n\
+         if a then\n    x = 1\nelseif b then\n    x = 2\nelse\n    x = 3\nend\n"
+    );
+}
+
+#[test]
+fn test_loop_nodes() {
+    let proto = Decoder::new(LOOPS).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let [Node::Stmt(Stmt::While(outer)), Node::Stmt(Stmt::Repeat(repeat)), Node::Stmt(Stmt::If(_))] =
+        syntax.root.nodes.as_slice()
+    else {
+        panic!("unexpected statements: {:?}", syntax.root.nodes);
+    };
+    let Some(Node::Stmt(Stmt::If(check))) = outer.body.nodes.first() else {
+        panic!("unexpected statements: {:?}", outer.body.nodes);
+    };
+    assert!(matches!(check.then.nodes[..], [Node::Stmt(Stmt::Break)]));
+    assert!(matches!(
+        repeat.body.nodes[..],
+        [Node::Stmt(Stmt::While(_))]
+    ));
+}
+
+#[test]
+fn test_gotos() {
+    let proto = Decoder::new(GOTO).decode().expect("failed to decode");
+    let mut parser = Parser::new(&proto);
+    let syntax = parser.parse().expect("failed to parse");
+    assert_eq!(parser.diagnostics().count(Severity::Warning), 3);
+
+    let mut source = String::new();
+    lua40::Scribe::default()
+        .fmt_syntax(&mut source, &syntax)
+        .expect("scribe failed");
+    assert!(
+        source.starts_with("-- if a then goto label_5 end\n"),
+        "{source}"
+    );
+    assert!(
+        source.contains("    -- ::label_5::\n        x = 1\n"),
+        "{source}"
+    );
+    assert!(source.ends_with("-- ::label_8::\n"), "{source}");
+    check_syntax(&source).expect("invalid syntax");
+}
@@ -0,0 +1,107 @@
+//! Code style options of the scribes.
+use lua_decompiler::style::{Indent, LineEnding, QuoteStyle, ScribeConfig};
+use lua_decompiler::{lua40, lua51};
+
+const IFELSE: &[u8] = include_bytes!("fixtures/ifelse.lua4");
+const IFELSE_LUA51: &[u8] = include_bytes!("fixtures/lua51/ifelse.lua51");
+const ADDI: &[u8] = include_bytes!("fixtures/addi.lua4");
+const MINUS: &[u8] = include_bytes!("fixtures/minus.lua4");
+
+fn write(code: &[u8], config: ScribeConfig) -> String {
+    let proto = lua40::Decoder::new(code)
+        .decode()
+        .expect("failed to decode");
+    let syntax = lua40::Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    lua40::Scribe::new(config)
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    buf
+}
+
+fn write_lua51(code: &[u8], config: ScribeConfig) -> String {
+    let proto = lua51::Decoder::new(code)
+        .decode()
+        .expect("failed to decode");
+    let syntax = lua51::Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    lua51::Scribe::new(config)
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    buf
+}
+
+#[test]
+fn test_default() {
+    let config = ScribeConfig::default();
+    assert_eq!(config.indent, Indent::Spaces(4));
+    assert_eq!(config.line_ending, LineEnding::Lf);
+    assert_eq!(config.quote, QuoteStyle::Double);
+    assert_eq!(
+        write(IFELSE, config),
+        "if x <= 1 then\n    print(\"a\")\nelse\n    print(\"b\")\nend\n"
+    );
+}
+
+#[test]
+fn test_indent() {
+    let config = ScribeConfig {
+        indent: Indent::Tabs,
+        ..ScribeConfig::default()
+    };
+    assert!(write(IFELSE, config).contains("\n\tprint(\"a\")\n"));
+
+    let config = ScribeConfig {
+        indent: Indent::Spaces(2),
+        ..ScribeConfig::default()
+    };
+    assert!(write(IFELSE, config).contains("\n  print(\"a\")\n"));
+}
+
+#[test]
+fn test_line_ending() {
+    let config = ScribeConfig {
+        line_ending: LineEnding::CrLf,
+        ..ScribeConfig::default()
+    };
+    let source = write(IFELSE, config);
+    assert_eq!(source.matches("\r\n").count(), 5);
+    assert_eq!(source.matches('\n').count(), 5);
+}
+
+#[test]
+fn test_quote() {
+    let config = ScribeConfig {
+        quote: QuoteStyle::Single,
+        ..ScribeConfig::default()
+    };
+    assert!(write(IFELSE, config).contains("print('a')"));
+}
+
+#[test]
+fn test_operator_spaces() {
+    let config = ScribeConfig {
+        operator_spaces: false,
+        ..ScribeConfig::default()
+    };
+    let source = write(ADDI, config.clone());
+    assert!(source.ends_with("\nx = a+200\ny = (a+1)*2\n"), "{source}");
+
+    // `--` would start a comment, and `1..` read as a malformed number.
+    assert_eq!(
+        write(MINUS, config),
+        "x = a- -b\ny = a- -5\nz = a- -1.5\ns = 1 .. a\n"
+    );
+}
+
+#[test]
+fn test_lua51() {
+    let config = ScribeConfig {
+        indent: Indent::Tabs,
+        line_ending: LineEnding::CrLf,
+        quote: QuoteStyle::Single,
+        ..ScribeConfig::default()
+    };
+    let source = write_lua51(IFELSE_LUA51, config);
+    assert!(source.starts_with("local x = f()\r\nif x > 1 then\r\n\tprint('a')\r\n"));
+}
@@ -0,0 +1,60 @@
+//! Interned names shared by syntax trees.
+use lua_decompiler::lua40::ast::{Ident, Node, Stmt};
+use lua_decompiler::lua40::{Decoder, Parser};
+use lua_decompiler::SymbolTable;
+
+const UPVALUE: &[u8] = include_bytes!("fixtures/upvalue.lua4");
+
+#[test]
+fn test_intern() {
+    let mut symbols = SymbolTable::new();
+    let symbol = symbols.intern("interned_name");
+    assert_eq!(symbols.intern("interned_name"), symbol);
+    assert_ne!(symbols.intern("other_name"), symbol);
+    assert_eq!(symbols.resolve(symbol), Some("interned_name"));
+    assert_eq!(symbols.get("interned_name"), Some(symbol));
+    assert_eq!(symbols.get("missing_name"), None);
+    assert_eq!(symbols.len(), 2);
+
+    // Tables are independent of each other.
+    let other = SymbolTable::new();
+    assert_eq!(other.resolve(symbol), None);
+}
+
+#[test]
+fn test_idents_share_symbols() {
+    let proto = Decoder::new(UPVALUE).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let targets: Vec<_> = syntax
+        .root
+        .nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Stmt(Stmt::Assign(assign)) => Some(assign.targets[0].clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(targets, [Ident::new("f"), Ident::new("g")]);
+    let symbol = targets[0]
+        .symbol(&syntax.symbols)
+        .expect("name not interned");
+    assert_eq!(syntax.symbols.resolve(symbol), Some("f"));
+    assert_eq!(format!("{:?}", targets[1]), "Ident(\"g\")");
+
+    // Made up identifiers aren't in the parser's table.
+    assert_eq!(Ident::new("unused").symbol(&syntax.symbols), None);
+}
+
+#[test]
+fn test_shared_table() {
+    let proto = Decoder::new(UPVALUE).decode().expect("failed to decode");
+    let first = Parser::new(&proto).parse().expect("failed to parse");
+    let len = first.symbols.len();
+
+    // Parsing again with the same table adds no names.
+    let second = Parser::new(&proto)
+        .with_symbols(first.symbols)
+        .parse()
+        .expect("failed to parse");
+    assert_eq!(second.symbols.len(), len);
+}
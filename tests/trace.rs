@@ -0,0 +1,95 @@
+//! Diagnostic events sent to a trace sink while decoding and parsing.
+use std::cell::Cell;
+use std::fmt;
+
+use lua_decompiler::decode_any_with_trace;
+use lua_decompiler::lua40::{Decoder, Parser, PassManager, SimplifyConditions};
+use lua_decompiler::trace::{CollectTrace, Level, Trace};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const SWAP: &[u8] = include_bytes!("fixtures/swap.lua4");
+
+/// Counts the events it's sent, wanting none.
+#[derive(Default)]
+struct Disabled {
+    events: Cell<usize>,
+}
+
+impl Trace for Disabled {
+    fn enabled(&self, _level: Level) -> bool {
+        false
+    }
+
+    fn event(&self, _level: Level, _args: fmt::Arguments) {
+        self.events.set(self.events.get() + 1);
+    }
+}
+
+#[test]
+fn test_levels() {
+    assert!(Level::Error < Level::Warn);
+    assert!(Level::Debug < Level::Trace);
+    assert_eq!(Level::Warn.to_string(), "warn");
+}
+
+#[test]
+fn test_decoder_events() {
+    let trace = CollectTrace::new(Level::Info);
+    Decoder::new(HELLO)
+        .with_trace(&trace)
+        .decode()
+        .expect("failed to decode");
+    let messages = trace.into_messages();
+    assert_eq!(messages.len(), 1, "{messages:?}");
+
+    // The decoded function is only dumped at the most verbose level.
+    let trace = CollectTrace::new(Level::Trace);
+    decode_any_with_trace(HELLO, &trace).expect("failed to decode");
+    let messages = trace.into_messages();
+    assert!(messages.len() > 2, "{messages:?}");
+    assert!(messages
+        .iter()
+        .any(|message| message.starts_with("Proto {")));
+}
+
+#[test]
+fn test_parser_events() {
+    let proto = Decoder::new(SWAP).decode().expect("failed to decode");
+    let trace = CollectTrace::new(Level::Debug);
+    Parser::new(&proto)
+        .with_trace(&trace)
+        .parse()
+        .expect("failed to parse");
+    let messages = trace.into_messages();
+    assert_eq!(messages[0], "parse");
+    assert!(messages.contains(&"function @test.lua:0 has no debug information".to_string()));
+    // Instructions are only traced at the most verbose level.
+    assert!(!messages.iter().any(|message| message.contains("op:")));
+}
+
+#[test]
+fn test_pass_events() {
+    let proto = Decoder::new(HELLO).decode().expect("failed to decode");
+    let mut syntax = Parser::new(&proto).parse().expect("failed to parse");
+    let trace = CollectTrace::new(Level::Debug);
+    PassManager::new()
+        .with_trace(&trace)
+        .with_pass(SimplifyConditions)
+        .run(&mut syntax)
+        .expect("pass failed");
+    assert_eq!(trace.into_messages(), ["pass: simplify-conditions"]);
+}
+
+#[test]
+fn test_disabled_events() {
+    let trace = Disabled::default();
+    let proto = Decoder::new(HELLO)
+        .with_trace(&trace)
+        .decode()
+        .expect("failed to decode");
+    Parser::new(&proto)
+        .with_trace(&trace)
+        .parse()
+        .expect("failed to parse");
+    assert_eq!(trace.events.get(), 0);
+}
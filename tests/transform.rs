@@ -0,0 +1,93 @@
+//! Rewriting constants as they're decoded, like decrypting protected chunks.
+use std::cell::RefCell;
+
+use lua_decompiler::errors::{Error, Result};
+use lua_decompiler::lstring::LuaString;
+use lua_decompiler::lua40::{self, ConstantTransformer, Decoder};
+
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+
+/// Strings stored with each byte XORed with a key.
+struct Xor {
+    key: u8,
+    seen: RefCell<Vec<(Vec<usize>, usize)>>,
+}
+
+/// Transformer for a different game, which can't read the strings.
+struct Refuse;
+
+impl ConstantTransformer for Xor {
+    fn transform_string(
+        &self,
+        path: &[usize],
+        index: usize,
+        string: LuaString,
+    ) -> Result<LuaString> {
+        self.seen.borrow_mut().push((path.to_vec(), index));
+        let bytes: Vec<u8> = string.as_bytes().iter().map(|b| b ^ self.key).collect();
+        Ok(LuaString::new(bytes))
+    }
+}
+
+impl ConstantTransformer for Refuse {
+    fn transform_string(
+        &self,
+        _path: &[usize],
+        _index: usize,
+        _string: LuaString,
+    ) -> Result<LuaString> {
+        Error::new_decoder("unexpected string").into()
+    }
+}
+
+/// The hello fixture with its strings XORed with the key.
+fn encrypt(key: u8) -> Vec<u8> {
+    let mut code = HELLO_LE.to_vec();
+    for word in [&b"print"[..], b"hello"] {
+        let start = code
+            .windows(word.len())
+            .position(|window| window == word)
+            .expect("string not in fixture");
+        code[start..start + word.len()]
+            .iter_mut()
+            .for_each(|b| *b ^= key);
+    }
+    code
+}
+
+#[test]
+fn test_transform_strings() {
+    let code = encrypt(0x20);
+    let xor = Xor {
+        key: 0x20,
+        seen: RefCell::new(vec![]),
+    };
+    let proto = Decoder::new(&code)
+        .with_transformer(&xor)
+        .decode()
+        .expect("failed to decode");
+    let syntax = lua40::Parser::new(&proto).parse().expect("failed to parse");
+    let mut buf = String::new();
+    lua40::Scribe::default()
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(buf, "local x = 7\nprint(\"hello\", x)\n");
+    assert_eq!(*xor.seen.borrow(), vec![(vec![], 0), (vec![], 1)]);
+
+    // Without the transformer the strings stay encrypted.
+    let proto = Decoder::new(&code).decode().expect("failed to decode");
+    assert_eq!(proto.strings()[0].as_bytes(), b"PRINT");
+}
+
+#[test]
+fn test_transform_error() {
+    let err = Decoder::new(HELLO_LE)
+        .with_transformer(&Refuse)
+        .decode()
+        .expect_err("transformer should fail");
+    assert!(
+        err.to_string()
+            .contains("string constant 0 of function main"),
+        "{err}"
+    );
+}
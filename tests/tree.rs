@@ -0,0 +1,46 @@
+//! Tree view of the syntax tree.
+use lua_decompiler::lua40::{Decoder, Parser};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const IFELSE: &[u8] = include_bytes!("fixtures/ifelse.lua4");
+
+fn dump_tree(code: &[u8]) -> String {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let syntax = Parser::new(&proto).parse().expect("failed to parse");
+    syntax.dump_tree().to_string()
+}
+
+#[test]
+fn test_dump_tree() {
+    let expected = "\
+Block
+  Local x  [1..1]
+    Int 7
+  Call  [2..5]
+    Name print
+    Str \"hello\"
+    Name x
+";
+    assert_eq!(dump_tree(HELLO), expected);
+}
+
+#[test]
+fn test_dump_tree_nested_blocks() {
+    let expected = "\
+Block
+  If  [1..10]
+    Test
+      Binary <=
+        Name x
+        Int 1
+    Then
+      Call  [4..6]
+        Name print
+        Str \"a\"
+    Else
+      Call  [8..10]
+        Name print
+        Str \"b\"
+";
+    assert_eq!(dump_tree(IFELSE), expected);
+}
@@ -0,0 +1,75 @@
+//! Decoding chunks that end partway through a function.
+use lua_decompiler::lua40::{Decoder, Parser, Scribe};
+use lua_decompiler::options::Tolerance;
+
+const CALLGRAPH: &[u8] = include_bytes!("fixtures/callgraph.lua4");
+
+/// Size of the header of the fixture, which can't be truncated.
+const HEADER_SIZE: usize = 21;
+
+#[test]
+fn test_strict() {
+    let err = Decoder::new(&CALLGRAPH[..340])
+        .decode()
+        .expect_err("decoded truncated chunk");
+    assert!(err.is_truncated());
+    assert_eq!(
+        err.to_string(),
+        "failed to fill whole buffer (code of function main, at byte 300)"
+    );
+
+    // Errors are located in the nested function that's cut short.
+    let err = Decoder::new(&CALLGRAPH[..150])
+        .decode()
+        .expect_err("decoded truncated chunk");
+    assert_eq!(
+        err.context(),
+        Some("constants of function main.0, at byte 147")
+    );
+    assert_eq!(
+        err.location().map(|location| location.path.as_slice()),
+        Some(&[0][..])
+    );
+
+    let err = Decoder::new(&CALLGRAPH[..10])
+        .decode()
+        .expect_err("decoded truncated chunk");
+    assert_eq!(err.context(), Some("chunk header opcode bits, at byte 10"));
+}
+
+#[test]
+fn test_partial_functions() {
+    let mut decoder = Decoder::new(&CALLGRAPH[..340]).with_tolerance(Tolerance::Lenient);
+    let proto = decoder.decode().expect("failed to decode");
+    assert!(proto.is_truncated());
+    assert_eq!(proto.instrs().len(), 9);
+    assert_eq!(proto.protos().len(), 2);
+    assert!(proto.protos().iter().all(|nested| !nested.is_truncated()));
+
+    let diagnostics: Vec<_> = decoder.diagnostics().iter().collect();
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("after 9 instructions"));
+
+    let mut parser = Parser::new(&proto).with_tolerance(Tolerance::Lenient);
+    let syntax = parser.parse().expect("failed to parse");
+    let mut buf = String::new();
+    Scribe::default()
+        .fmt_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert!(buf.contains("    return a + b\n"), "{buf}");
+    assert!(buf.ends_with("-- the chunk ends here\n"), "{buf}");
+}
+
+#[test]
+fn test_every_length() {
+    for len in HEADER_SIZE..CALLGRAPH.len() {
+        let proto = Decoder::new(&CALLGRAPH[..len])
+            .with_tolerance(Tolerance::Lenient)
+            .decode()
+            .unwrap_or_else(|err| panic!("{len} bytes: {err}"));
+        assert!(proto.is_truncated(), "{len} bytes");
+        let _ = Parser::new(&proto)
+            .with_tolerance(Tolerance::Lenient)
+            .parse();
+    }
+}
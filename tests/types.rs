@@ -0,0 +1,56 @@
+//! Warnings for values used as types they can't be at runtime.
+//!
+//! The conflict fixture is compiled from:
+//!
+//! ```lua
+//! x = (not a) + 1
+//! z = function() end .. "s"
+//! ```
+use std::cell::RefCell;
+use std::fmt;
+
+use lua_decompiler::lua40::{self, Decoder};
+use lua_decompiler::trace::{Level, Trace};
+
+const CONFLICT: &[u8] = include_bytes!("fixtures/conflict.lua4");
+const PRECEDENCE: &[u8] = include_bytes!("fixtures/precedence.lua4");
+
+/// Collects the warnings.
+#[derive(Default)]
+struct Warnings(RefCell<Vec<String>>);
+
+impl Trace for Warnings {
+    fn enabled(&self, level: Level) -> bool {
+        level <= Level::Warn
+    }
+
+    fn event(&self, _level: Level, args: fmt::Arguments) {
+        self.0.borrow_mut().push(args.to_string());
+    }
+}
+
+fn warnings(code: &[u8]) -> Vec<String> {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let warnings = Warnings::default();
+    lua40::Parser::new(&proto)
+        .with_trace(&warnings)
+        .parse()
+        .expect("failed to parse");
+    warnings.0.into_inner()
+}
+
+#[test]
+fn test_type_conflicts() {
+    assert_eq!(
+        warnings(CONFLICT),
+        [
+            "boolean operand of + at instruction 3",
+            "function operand of .. at instruction 7",
+        ]
+    );
+}
+
+#[test]
+fn test_no_type_conflicts() {
+    assert!(warnings(PRECEDENCE).is_empty());
+}
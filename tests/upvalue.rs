@@ -0,0 +1,42 @@
+//! Pushing the values of upvalues, captured by nested functions with `%name`.
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::lua40::{self, Decoder, Op, Opcode};
+
+/// `local a = 1; f = function() print(%a .. %a) end`
+const UPVALUE_TWICE: &[u8] = include_bytes!("fixtures/upvalue_twice.lua4");
+
+/// Offset of the second `PUSHUPVALUE` in `upvalue_twice.lua4`.
+const SECOND_PUSH_OFFSET: usize = 143;
+
+#[test]
+fn test_decode() {
+    assert_eq!(Opcode::try_from(10).ok(), Some(Opcode::PushUpvalue));
+    assert_eq!(Opcode::PushUpvalue.name(), "PUSHUPVALUE");
+
+    let proto = Decoder::new(UPVALUE_TWICE)
+        .decode()
+        .expect("failed to decode");
+    let nested = &proto.protos()[0];
+    assert!(matches!(nested.ops()[1], Op::PushUpvalue { upvalue_id: 0 }));
+    assert!(matches!(nested.ops()[2], Op::PushUpvalue { upvalue_id: 0 }));
+}
+
+#[test]
+fn test_push_twice() {
+    // Each push of the upvalue is written where it's used.
+    assert_eq!(
+        lua40::decompile(UPVALUE_TWICE).expect("failed to decompile"),
+        "local a = 1\nf = function()\n    print(%a .. %a)\nend\n"
+    );
+}
+
+#[test]
+fn test_out_of_bounds() {
+    let mut code = UPVALUE_TWICE.to_vec();
+    assert_eq!(code[SECOND_PUSH_OFFSET], Opcode::PushUpvalue as u8);
+    // PUSHUPVALUE 1, when the function only has one upvalue.
+    code[SECOND_PUSH_OFFSET] |= 1 << 6;
+    let err = lua40::decompile(&code).expect_err("pushed a missing upvalue");
+    assert!(matches!(err.kind(), ErrorKind::Parser(_)));
+    assert!(err.to_string().contains("upvalue 1 out of bounds"), "{err}");
+}
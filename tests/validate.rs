@@ -0,0 +1,61 @@
+//! Comparing recompiled bytecode against the original chunk.
+//!
+//! `hello8_le.lua4` is `hello_le.lua4` with the local initialised to 8 instead of 7.
+mod common;
+
+use common::decode;
+use lua_decompiler::lua40;
+
+const HELLO_BE: &[u8] = include_bytes!("fixtures/hello_be.lua4");
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const HELLO8_LE: &[u8] = include_bytes!("fixtures/hello8_le.lua4");
+
+#[test]
+fn test_compare_byte_orders() {
+    assert!(lua40::compare(&decode(HELLO_LE), &decode(HELLO_BE)).is_empty());
+}
+
+#[test]
+fn test_compare_mismatch() {
+    let mismatches = lua40::compare(&decode(HELLO_LE), &decode(HELLO8_LE));
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(
+        mismatches[0].to_string(),
+        "function main: instruction 1 is PUSHINT 7, recompiled as PUSHINT 8"
+    );
+}
+
+/// Stand-in for `luac` that ignores the source and writes a fixture.
+#[cfg(unix)]
+fn fake_luac(name: &str, fixture: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/").to_string() + fixture;
+    let path = std::env::temp_dir().join(format!("luad-test-{}-{name}", std::process::id()));
+    std::fs::write(&path, format!("#!/bin/sh\ncp '{fixture}' \"$2\"\n")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn test_validate() {
+    let original = decode(HELLO_LE);
+    let source = "local a = 7\nprint(\"hello\", a)\n";
+
+    let luac = fake_luac("same", "hello_be.lua4");
+    let report = lua40::Validator::new(&luac).validate(&original, source);
+    std::fs::remove_file(luac).unwrap();
+    assert!(report.expect("failed to validate").is_ok());
+
+    let luac = fake_luac("changed", "hello8_le.lua4");
+    let report = lua40::Validator::new(&luac).validate(&original, source);
+    std::fs::remove_file(luac).unwrap();
+    assert!(!report.expect("failed to validate").is_ok());
+}
+
+#[test]
+fn test_validate_missing_compiler() {
+    let validator = lua40::Validator::new("/nonexistent/luac");
+    assert!(validator.validate(&decode(HELLO_LE), "").is_err());
+}
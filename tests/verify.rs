@@ -0,0 +1,98 @@
+//! Stack verification of decoded functions.
+use std::fs;
+use std::path::Path;
+
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::lua40::{Decoder, Parser};
+use lua_decompiler::options::Tolerance;
+
+const HELLO_LE: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const IFELSE: &[u8] = include_bytes!("fixtures/ifelse.lua4");
+
+/// Chunk with the main function's instruction at `index` replaced,
+/// when the function has `len` instructions at the end of the chunk.
+fn patch(chunk: &[u8], len: usize, index: usize, word: u32) -> Vec<u8> {
+    let mut code = chunk.to_vec();
+    let offset = code.len() - (len - index) * 4;
+    code[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+    code
+}
+
+fn imbalance(code: &[u8]) -> String {
+    let proto = Decoder::new(code).decode().expect("failed to decode");
+    let err = proto.verify_stack().expect_err("verified imbalance");
+    assert!(matches!(err.kind(), ErrorKind::Decoder(_)));
+    assert_eq!(err.context(), Some("function @test.lua:0"));
+    err.to_string()
+}
+
+#[test]
+fn test_fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for entry in fs::read_dir(dir).expect("failed to read fixtures") {
+        let path = entry.expect("failed to read fixture").path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("lua4") {
+            continue;
+        }
+        let code = fs::read(&path).expect("failed to read chunk");
+        let Ok(proto) = Decoder::new(&code).decode() else {
+            continue;
+        };
+        for (nested, proto) in proto.iter_protos() {
+            if let Err(err) = proto.verify_stack() {
+                panic!("{} {nested}: {err}", path.display());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_underflow() {
+    // `CALL 1 0` replaced with `POP 5`, with 4 values on the stack.
+    let code = patch(HELLO_LE, 6, 4, 5 | (5 << 6));
+    let message = imbalance(&code);
+    assert!(
+        message.contains("stack imbalance at instruction 5: pops 5 values with 4 on the stack"),
+        "{message}"
+    );
+}
+
+#[test]
+fn test_overflow() {
+    // `PUSHINT 7` replaced with `PUSHNIL 20`, on a stack of 10.
+    let code = patch(HELLO_LE, 6, 0, 4 | (20 << 6));
+    let message = imbalance(&code);
+    assert!(
+        message.contains("stack imbalance at instruction 1: leaves 20 values on a stack of 10"),
+        "{message}"
+    );
+}
+
+#[test]
+fn test_mismatched_paths() {
+    // The call in the `then` branch keeps a result, which the `else` branch doesn't.
+    let code = patch(IFELSE, 11, 5, 2 | (1 << 6));
+    let message = imbalance(&code);
+    assert!(
+        message.contains(
+            "stack imbalance at instruction 11: reached with 0 values on one path and 1 on another"
+        ),
+        "{message}"
+    );
+}
+
+#[test]
+fn test_parse() {
+    let code = patch(HELLO_LE, 6, 4, 5 | (5 << 6));
+    let proto = Decoder::new(&code).decode().expect("failed to decode");
+
+    let err = Parser::new(&proto).parse().expect_err("parsed imbalance");
+    assert!(err.to_string().contains("stack imbalance at instruction 5"));
+
+    let mut parser = Parser::new(&proto).with_tolerance(Tolerance::Lenient);
+    let _ = parser.parse();
+    assert!(parser
+        .diagnostics()
+        .iter()
+        .any(|diagnostic| diagnostic.message.contains("stack imbalance")));
+}
@@ -0,0 +1,90 @@
+//! Writing source to byte streams and files.
+mod common;
+
+use std::io;
+
+use common::parse;
+use lua_decompiler::errors::ErrorKind;
+use lua_decompiler::{lua40, lua51};
+
+const HELLO: &[u8] = include_bytes!("fixtures/hello_le.lua4");
+const HELLO_SOURCE: &str = "local x = 7\nprint(\"hello\", x)\n";
+const CLOSURE_LUA51: &[u8] = include_bytes!("fixtures/lua51/closure.lua51");
+
+/// Accepts a few bytes, then fails.
+struct Full {
+    capacity: usize,
+}
+
+impl io::Write for Full {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.capacity == 0 {
+            return Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+        }
+        let len = buf.len().min(self.capacity);
+        self.capacity -= len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_syntax() {
+    let syntax = parse(HELLO);
+    let mut buf = vec![];
+    lua40::Scribe::default()
+        .write_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(String::from_utf8(buf).expect("not UTF-8"), HELLO_SOURCE);
+}
+
+#[test]
+fn test_write_error() {
+    let syntax = parse(HELLO);
+    let err = lua40::Scribe::default()
+        .write_syntax(Full { capacity: 8 }, &syntax)
+        .expect_err("wrote to a full stream");
+    match err.kind() {
+        ErrorKind::Io(err) => assert_eq!(err.kind(), io::ErrorKind::StorageFull),
+        kind => panic!("unexpected error: {kind:?}"),
+    }
+}
+
+#[test]
+fn test_write_file() {
+    let path = std::env::temp_dir().join(format!("luad-writer-{}.lua", std::process::id()));
+    let syntax = parse(HELLO);
+    lua40::Scribe::default()
+        .write_file(&path, &syntax)
+        .expect("scribe failed");
+    let source = std::fs::read_to_string(&path).expect("no file written");
+    std::fs::remove_file(&path).expect("failed to remove");
+    assert_eq!(source, HELLO_SOURCE);
+
+    let missing = std::env::temp_dir().join("luad-missing-dir/out.lua");
+    let err = lua40::Scribe::default()
+        .write_file(missing, &syntax)
+        .expect_err("wrote to a missing directory");
+    assert!(matches!(err.kind(), ErrorKind::Io(_)));
+}
+
+#[test]
+fn test_lua51_write_syntax() {
+    let proto = lua51::Decoder::new(CLOSURE_LUA51)
+        .decode()
+        .expect("failed to decode");
+    let syntax = lua51::Parser::new(&proto).parse().expect("failed to parse");
+    let mut expected = String::new();
+    lua51::Scribe::default()
+        .fmt_syntax(&mut expected, &syntax)
+        .expect("scribe failed");
+
+    let mut buf = vec![];
+    lua51::Scribe::default()
+        .write_syntax(&mut buf, &syntax)
+        .expect("scribe failed");
+    assert_eq!(String::from_utf8(buf).expect("not UTF-8"), expected);
+}
@@ -0,0 +1,51 @@
+//! Cross references of globals, from the bytecode.
+use lua_decompiler::lua40::{Access, Decoder, ProtoPath, Xref};
+
+const CALLGRAPH: &[u8] = include_bytes!("fixtures/callgraph.lua4");
+const LINES: &[u8] = include_bytes!("fixtures/lines.lua4");
+
+#[test]
+fn test_global_xrefs() {
+    let proto = Decoder::new(CALLGRAPH).decode().expect("failed to decode");
+    let xrefs = proto.global_xrefs();
+    assert_eq!(
+        xrefs.get("add"),
+        [
+            Xref {
+                path: ProtoPath::main(),
+                offset: 3,
+                access: Access::Write,
+                line: None,
+            },
+            Xref {
+                path: ProtoPath::main(),
+                offset: 5,
+                access: Access::Read,
+                line: None,
+            },
+        ]
+    );
+    assert_eq!(xrefs.get("print")[0].path, ProtoPath::from(vec![0]));
+    assert!(xrefs.get("missing").is_empty());
+    assert_eq!(
+        xrefs.inputs().map(ToString::to_string).collect::<Vec<_>>(),
+        ["print"]
+    );
+    assert_eq!(
+        xrefs.to_string(),
+        "add\twrite\tmain:4\nadd\tread\tmain:6\nprint\tread\tmain.0:1\n"
+    );
+}
+
+#[test]
+fn test_global_xrefs_lines() {
+    let proto = Decoder::new(LINES).decode().expect("failed to decode");
+    let lines: Vec<_> = proto
+        .global_xrefs()
+        .globals
+        .values()
+        .flatten()
+        .map(|xref| xref.line)
+        .collect();
+    assert_eq!(lines, [Some(1), Some(2), Some(4), Some(10)]);
+}